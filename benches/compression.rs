@@ -0,0 +1,72 @@
+//! CPU cost of frame compression against the bandwidth it saves.
+//!
+//! Run with `cargo bench --bench compression`. Besides the timings, the
+//! compressed size of every payload is printed, which is the other half of the
+//! trade-off.
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use rand::RngCore;
+use rand::SeedableRng;
+use schultz::network::compression::Compression;
+
+fn payloads() -> Vec<(&'static str, Vec<u8>)> {
+    let chainspec = std::fs::read("examples/chainspec.toml").expect("example chainspec");
+
+    // Serialized global state tends to be runs of structured records with a
+    // few random hashes sprinkled in.
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5c4_u64);
+    let mut records = Vec::with_capacity(1024 * 1024);
+    while records.len() < 1024 * 1024 {
+        let mut hash = [0u8; 32];
+        rng.fill_bytes(&mut hash);
+        records.extend_from_slice(&hash);
+        records.extend_from_slice(&[0u8; 64]);
+        records.extend_from_slice(b"account-hash-balance-named-keys");
+    }
+
+    let mut random = vec![0u8; 256 * 1024];
+    rng.fill_bytes(&mut random);
+
+    vec![
+        ("chainspec", chainspec),
+        ("global-state", records),
+        ("random", random),
+    ]
+}
+
+fn compression(c: &mut Criterion) {
+    for (name, payload) in payloads() {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(payload.len() as u64));
+
+        for algorithm in Compression::ALL {
+            let compressed = algorithm.compress(&payload).unwrap();
+            eprintln!(
+                "{name}/{algorithm}: {} -> {} bytes ({:.1}%)",
+                payload.len(),
+                compressed.len(),
+                100.0 * compressed.len() as f64 / payload.len() as f64
+            );
+
+            group.bench_with_input(
+                BenchmarkId::new("compress", algorithm),
+                &payload,
+                |b, payload| b.iter(|| algorithm.compress(payload).unwrap()),
+            );
+            group.bench_with_input(
+                BenchmarkId::new("decompress", algorithm),
+                &compressed,
+                |b, compressed| b.iter(|| algorithm.decompress(compressed, payload.len()).unwrap()),
+            );
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, compression);
+criterion_main!(benches);
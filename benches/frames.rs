@@ -0,0 +1,178 @@
+//! Reading large gossip payloads off the wire, from the bytes the TLS reader
+//! filled to the message handed to the observers and handlers.
+//!
+//! Run with `cargo bench --bench frames`. Besides the timings, the number of
+//! allocations and bytes allocated to read one payload are printed, counted
+//! by the global allocator: with frames passed on as `Bytes`, reading an
+//! uncompressed payload of a single fragment allocates nothing the size of
+//! the payload, and a compressed one only what it is inflated into.
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use rand::RngCore;
+use rand::SeedableRng;
+use schultz::network::compression::Compression;
+use schultz::network::compression::FrameCodec;
+use schultz::network::mux::Channel;
+use schultz::network::mux::Demuxed;
+use schultz::network::mux::Multiplexer;
+use schultz::network::observe::Observed;
+use tokio_util::codec::Decoder;
+use tokio_util::codec::Encoder;
+
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Counts what is allocated, to tell copies of the payload apart.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) { System.dealloc(ptr, layout) }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A block gossiped as an item: the payload tag, the block gossiper, the
+/// item kind, the hash of the block and the rest of it, which compresses
+/// about as well as a real one.
+fn gossip(len: usize) -> Bytes {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5c4_u64);
+    let mut frame = vec![3, 2, 3, 32];
+    let mut hash = [0u8; 32];
+    rng.fill_bytes(&mut hash);
+    frame.extend_from_slice(&hash);
+    while frame.len() < len {
+        rng.fill_bytes(&mut hash);
+        frame.extend_from_slice(&hash);
+        frame.extend_from_slice(b"deploy-hashes-approvals-and-era-end");
+    }
+    frame.truncate(len);
+    Bytes::from(frame)
+}
+
+/// The codecs of both ends of a connection, and a name for them.
+fn codecs() -> Vec<(String, FrameCodec)> {
+    let mut codecs = vec![("plain".to_string(), FrameCodec::new(MAX_FRAME_LEN))];
+    for algorithm in Compression::ALL {
+        let mut codec = FrameCodec::new(MAX_FRAME_LEN);
+        codec.enable_compression(algorithm);
+        codecs.push((algorithm.to_string(), codec));
+    }
+    let mut checked = FrameCodec::new(MAX_FRAME_LEN);
+    checked.send_checksums();
+    checked.expect_checksums(0);
+    // Past the marker, as a connection is once the handshake is done.
+    let mut marker = BytesMut::new();
+    checked.encode(Bytes::new(), &mut marker).unwrap();
+    checked.decode(&mut marker).unwrap();
+    codecs.push(("checksummed".to_string(), checked));
+    let mut multiplexed = FrameCodec::new(MAX_FRAME_LEN);
+    multiplexed.enable_multiplexing();
+    codecs.push(("multiplexed".to_string(), multiplexed));
+    codecs
+}
+
+/// What the peer writes for `payload`, cut into fragments if `codec`
+/// multiplexes.
+fn wire(codec: &mut FrameCodec, payload: &Bytes) -> BytesMut {
+    let mut wire = BytesMut::new();
+    if codec.demultiplexer().is_none() {
+        codec.encode(payload.clone(), &mut wire).unwrap();
+        return wire;
+    }
+    let mut mux = Multiplexer::default();
+    mux.push(Channel::Gossip, payload.clone(), ());
+    // Credit would come back as the peer reads.
+    mux.add_credit(Channel::Gossip, u32::MAX);
+    while let Some(frame) = mux.next_frame() {
+        codec.encode(frame, &mut wire).unwrap();
+    }
+    wire
+}
+
+/// Reads the message in `wire` the way a connection's reader does, and
+/// observes it.
+fn read(codec: &mut FrameCodec, wire: &mut BytesMut) -> Observed {
+    let peer = ([127, 0, 0, 1], 35000).into();
+    while let Some(frame) = codec.decode(wire).unwrap() {
+        let message = match codec.demultiplexer() {
+            Some(demultiplexer) => {
+                let demuxed = demultiplexer.receive(frame).unwrap();
+                demultiplexer.take_credit();
+                match demuxed {
+                    Demuxed::Message(_, message) => message,
+                    _ => continue,
+                }
+            }
+            None => frame,
+        };
+        return Observed::parse(peer, &message).unwrap();
+    }
+    panic!("no message in the wire");
+}
+
+fn frames(c: &mut Criterion) {
+    for len in [64 * 1024, 4 * 1024 * 1024] {
+        let payload = gossip(len);
+        let mut group = c.benchmark_group(format!("gossip-{}k", len / 1024));
+        group.throughput(Throughput::Bytes(len as u64));
+
+        for (name, mut codec) in codecs() {
+            let wire = wire(&mut codec, &payload);
+
+            let mut copy = wire.clone();
+            let before = (
+                ALLOCATIONS.load(Ordering::Relaxed),
+                ALLOCATED.load(Ordering::Relaxed),
+            );
+            let observed = read(&mut codec, &mut copy);
+            eprintln!(
+                "{}k/{name}: {} allocations, {} bytes for a {} byte body",
+                len / 1024,
+                ALLOCATIONS.load(Ordering::Relaxed) - before.0,
+                ALLOCATED.load(Ordering::Relaxed) - before.1,
+                observed.body.len(),
+            );
+
+            group.bench_with_input(BenchmarkId::new("read", &name), &wire, |b, wire| {
+                b.iter_batched(
+                    || wire.clone(),
+                    |mut wire| read(&mut codec, &mut wire),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
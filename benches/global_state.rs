@@ -0,0 +1,55 @@
+//! Decoding the entries of a large global state update.
+//!
+//! Run with `cargo bench --bench global_state`. The same 100k-entry update is
+//! converted on a single thread and on all of them, which shows what decoding
+//! in parallel gains.
+
+use casper_types::Key;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use schultz::primitives::GlobalStateUpdate;
+use schultz::primitives::GlobalStateUpdateConfig;
+
+const ENTRIES: usize = 100_000;
+
+fn config() -> GlobalStateUpdateConfig {
+    let mut builder = GlobalStateUpdate::builder();
+    for index in 0..ENTRIES {
+        let mut hash = [0u8; 32];
+        hash[..8].copy_from_slice(&(index as u64).to_le_bytes());
+        builder = builder.entry(Key::Hash(hash), vec![index as u8; 256]);
+    }
+    let toml = builder.build().to_toml_string().expect("serializable update");
+    toml::from_str(&toml).expect("valid update")
+}
+
+fn decoding(c: &mut Criterion) {
+    let config = config();
+    let mut group = c.benchmark_group("global-state-update");
+    group.throughput(Throughput::Elements(ENTRIES as u64));
+    group.sample_size(20);
+
+    let mut threads = vec![1];
+    if rayon::current_num_threads() > 1 {
+        threads.push(rayon::current_num_threads());
+    }
+    for threads in threads {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_function(BenchmarkId::new("decode", threads), |b| {
+            b.iter_batched(
+                || config.clone(),
+                |config| pool.install(|| GlobalStateUpdate::try_from(config).unwrap()),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decoding);
+criterion_main!(benches);
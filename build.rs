@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/schultz.proto");
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile_protos(&["proto/schultz.proto"], &["proto"])?;
+    Ok(())
+}
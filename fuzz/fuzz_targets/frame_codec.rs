@@ -0,0 +1,27 @@
+//! Feeds raw bytes from a peer into the frame decoder, with and without
+//! compression negotiated.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use schultz::network::compression::Compression;
+use schultz::network::compression::FrameCodec;
+use schultz::network::manager::MAX_FRAME_LEN;
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|input: (Option<Compression>, &[u8])| {
+    let (compression, data) = input;
+    let mut codec = FrameCodec::new(MAX_FRAME_LEN);
+    if let Some(compression) = compression {
+        codec.enable_compression(compression);
+    }
+
+    let mut src = BytesMut::from(data);
+    while let Ok(Some(frame)) = codec.decode(&mut src) {
+        assert!(
+            frame.len() <= MAX_FRAME_LEN,
+            "frame inflated past the limit"
+        );
+    }
+});
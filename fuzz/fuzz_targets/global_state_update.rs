@@ -0,0 +1,22 @@
+//! Parses bytesrepr-encoded global state updates and checks that whatever
+//! parses survives a round trip.
+
+#![no_main]
+
+use casper_types::bytesrepr::FromBytes;
+use casper_types::bytesrepr::ToBytes;
+use libfuzzer_sys::fuzz_target;
+use schultz::primitives::GlobalStateUpdate;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((update, _remainder)) = GlobalStateUpdate::from_bytes(data) {
+        // Maps may arrive unsorted or with duplicate keys, so the encoding is
+        // not necessarily the input; decoding it again must be lossless though.
+        let encoded = update.to_bytes().expect("parsed updates always encode");
+        assert_eq!(encoded.len(), update.serialized_length());
+        let (decoded, remainder) =
+            GlobalStateUpdate::from_bytes(&encoded).expect("our own encoding parses");
+        assert!(remainder.is_empty());
+        assert_eq!(decoded, update);
+    }
+});
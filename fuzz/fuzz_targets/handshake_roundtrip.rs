@@ -0,0 +1,21 @@
+//! Every handshake we can send decodes back to itself.
+
+#![no_main]
+
+use std::pin::Pin;
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use schultz::network::gossip::NodePayload;
+use schultz::network::handshake::Handshake;
+use schultz::network::message::Message;
+use schultz::network::message::MessagePackFormat;
+use tokio_serde::Deserializer;
+
+fuzz_target!(|handshake: Handshake| {
+    let encoded = handshake.clone().encode::<NodePayload>().expect("handshakes always encode");
+    let decoded: Message<NodePayload> = Pin::new(&mut MessagePackFormat)
+        .deserialize(&BytesMut::from(&encoded[..]))
+        .expect("our own handshake decodes");
+    assert_eq!(Handshake::from_message(&decoded), Some(handshake));
+});
@@ -0,0 +1,32 @@
+//! Decodes a frame the way the connection pool does: as a msgpack handshake
+//! first, then as a bincode message.
+
+#![no_main]
+
+use std::pin::Pin;
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use schultz::network::gossip::NodePayload;
+use schultz::network::handshake::Handshake;
+use schultz::network::message::BincodeFormat;
+use schultz::network::message::Message;
+use schultz::network::message::MessagePackFormat;
+use tokio_serde::Deserializer;
+
+fuzz_target!(|data: &[u8]| {
+    let frame = BytesMut::from(data);
+
+    let handshake: Result<Message<NodePayload>, _> =
+        Pin::new(&mut MessagePackFormat).deserialize(&frame);
+    if let Ok(message) = handshake {
+        let _ = Handshake::from_message(&message);
+        let _ = message.to_string();
+    }
+
+    let message: Result<Message<NodePayload>, _> =
+        Pin::new(&mut BincodeFormat::default()).deserialize(&frame);
+    if let Ok(message) = message {
+        let _ = message.to_string();
+    }
+});
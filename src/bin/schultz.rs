@@ -1,21 +1,228 @@
-use clap::Parser;
+use std::process::ExitCode;
+
+use casper_types::EraId;
+use casper_types::Timestamp;
+use schultz::commands::bench;
+use schultz::commands::binary;
 use schultz::commands::bootstrap;
+use schultz::commands::chainspec;
+use schultz::commands::config;
+use schultz::commands::crawl;
+use schultz::commands::data_dir;
+use schultz::commands::db;
+use schultz::commands::doctor;
+use schultz::commands::events;
+use schultz::commands::export_netstate;
+use schultz::commands::fetch;
+use schultz::commands::finality;
+use schultz::commands::identity;
+use schultz::commands::log_filter;
+use schultz::commands::peers;
+use schultz::commands::reload;
+use schultz::commands::rpc;
+use schultz::commands::serve;
+use schultz::commands::stats;
+use schultz::commands::tap;
+use schultz::commands::transfer;
+use schultz::commands::wire_log;
+use schultz::exit::Failure;
+use schultz::network::deploy::Transfer;
+use schultz::network::fetch::Request;
+use schultz::telemetry;
+use schultz::BenchCommands;
+use schultz::BinaryCommands;
+use schultz::ChainspecCommands;
 use schultz::Cli;
 use schultz::Commands;
+use schultz::ConfigCommands;
 use schultz::Context;
+use schultz::DataDirCommands;
+use schultz::DbCommands;
+use schultz::FetchCommands;
+use schultz::FinalityCommands;
+use schultz::IdentityCommands;
+use schultz::WireLogCommands;
 
 extern crate core;
 
+/// Exits with the code of the [`Failure`] the command ran into, see
+/// [`schultz::exit`].
 #[tokio::main]
-async fn main() -> miette::Result<()> {
-    tracing_subscriber::fmt::init();
-    let cli = Cli::parse();
-    let _ = Context::for_cli(&cli)?;
-    match cli.command {
-        Commands::Bootstrap {
-            addr,
-            bootnode,
-            chainspec,
-        } => bootstrap::setup(addr, bootnode, chainspec).await,
+async fn main() -> ExitCode {
+    let cli = Cli::parse_with_deprecated_env();
+    let ctx = match Context::for_cli(&cli) {
+        Ok(ctx) => ctx,
+        Err(report) => return Failure::Config.exit(&report),
+    };
+    match run(cli, &ctx).await {
+        Ok(code) => code,
+        Err(report) => Failure::of(&report).exit(&report),
     }
 }
+
+async fn run(cli: Cli, ctx: &Context) -> miette::Result<ExitCode> {
+    let _telemetry = telemetry::init(ctx.config.telemetry.otlp_endpoint.as_deref())?;
+    Cli::warn_deprecated_env();
+    match cli.command {
+        Commands::Bench { command } => match command {
+            BenchCommands::Handshake {
+                target,
+                concurrency,
+                duration,
+                chainspec,
+            } => bench::handshake(ctx, &target, concurrency, duration, chainspec.as_deref()).await,
+        },
+        Commands::Binary { command } => match command {
+            BinaryCommands::GetBlock { block, args } => binary::get_block(ctx, block, &args).await,
+            BinaryCommands::GetTransaction { hash, deploy, args } => {
+                binary::get_transaction(ctx, &hash, deploy, &args).await
+            }
+        },
+        // Exits with a code telling which phase of the bootstrap failed.
+        Commands::Bootstrap { .. } => return bootstrap::setup(ctx).await,
+        Commands::Chainspec { command } => match command {
+            ChainspecCommands::Diff { dir_a, dir_b } => chainspec::diff(ctx, &dir_a, &dir_b),
+            ChainspecCommands::ShowGlobalState { dir, max_value_len } => {
+                chainspec::show_global_state(ctx, &dir, max_value_len)
+            }
+            ChainspecCommands::LintGlobalState { dir } => chainspec::lint_global_state(ctx, &dir),
+            ChainspecCommands::DigestGlobalState { dir } => {
+                chainspec::digest_global_state(ctx, &dir)
+            }
+            ChainspecCommands::Validators { dir, min_weight } => {
+                chainspec::validators(ctx, &dir, min_weight)
+            }
+            ChainspecCommands::ToJson { dir, compact } => chainspec::to_json(&dir, compact),
+            ChainspecCommands::Accounts { dir } => chainspec::accounts(ctx, &dir),
+            ChainspecCommands::ListUpgrades { root, era } => {
+                chainspec::list_upgrades(ctx, &root, era.map(EraId::new))
+            }
+        },
+        Commands::Config { command } => match command {
+            ConfigCommands::Print { .. } => config::print(ctx),
+        },
+        Commands::Crawl {
+            workers,
+            listen,
+            checkpoint,
+            resume,
+            topology,
+            topology_format,
+            edge_metadata,
+            schedule,
+            diff,
+            ..
+        } => {
+            let options = crawl::Options {
+                workers,
+                listen,
+                checkpoint,
+                resume,
+                topology,
+                topology_format,
+                edge_metadata,
+                schedule,
+                diff,
+            };
+            crawl::crawl(ctx, &options).await
+        }
+        Commands::DataDir { command } => match command {
+            DataDirCommands::Info => data_dir::info(ctx),
+        },
+        Commands::Db { command } => match command {
+            DbCommands::Query {
+                kind,
+                since,
+                block,
+                limit,
+                args,
+            } => db::query(
+                ctx,
+                kind,
+                since,
+                block.as_deref(),
+                limit,
+                args.db.as_deref(),
+            ),
+            DbCommands::Info { args } => db::info(ctx, args.db.as_deref()),
+            DbCommands::Prune { args } => db::prune(ctx, args.db.as_deref()),
+        },
+        Commands::Doctor { ntp_server, .. } => doctor::doctor(ctx, &ntp_server).await,
+        Commands::Events {
+            node,
+            filter,
+            output,
+        } => events::events(ctx, &node, filter.as_deref(), output.as_deref()).await,
+        Commands::ExportNetstate { out, .. } => {
+            export_netstate::export_netstate(ctx, out.as_deref()).await
+        }
+        Commands::Fetch { command } => {
+            let hash = fetch::parse_hash(&command.args().hash)?;
+            let request = match &command {
+                FetchCommands::Block { .. } => Request::Block(hash),
+                FetchCommands::BlockHeader { .. } => Request::BlockHeader(hash),
+                FetchCommands::Deploy { .. } => Request::Deploy(hash),
+            };
+            fetch::fetch(ctx, request, command.args().timeout).await
+        }
+        Commands::Finality { command } => match command {
+            FinalityCommands::Watch { timeout, .. } => finality::watch(ctx, timeout).await,
+        },
+        Commands::Identity { command } => match command {
+            IdentityCommands::Fingerprint { cert } => {
+                identity::fingerprint(ctx, cert.cert.as_deref(), cert.connect).await
+            }
+            IdentityCommands::Check { cert } => {
+                identity::check(ctx, cert.cert.as_deref(), cert.connect).await
+            }
+        },
+        Commands::LogFilter { directives, .. } => {
+            log_filter::log_filter(ctx, directives.as_deref()).await
+        }
+        Commands::Peers { .. } => peers::peers(ctx).await,
+        Commands::Reload { .. } => reload::reload(ctx).await,
+        Commands::Rpc {
+            method,
+            node,
+            block,
+        } => rpc::rpc(ctx, method, node, block).await,
+        Commands::Serve { .. } => serve::serve(ctx).await,
+        Commands::Stats { dir } => stats::stats(ctx, dir.as_deref()),
+        Commands::Tap { output, .. } => tap::tap(ctx, output.as_deref()).await,
+        Commands::Transfer {
+            amount,
+            target,
+            secret_key,
+            payment,
+            id,
+            ttl,
+            wait,
+            ..
+        } => {
+            let transfer = Transfer {
+                amount,
+                target,
+                id: id.unwrap_or_else(|| Timestamp::now().millis()),
+                payment,
+                ttl,
+            };
+            transfer::transfer(ctx, &transfer, &secret_key, wait).await
+        }
+        Commands::WireLog { command } => match command {
+            WireLogCommands::Inspect { file } => wire_log::inspect(ctx, &file),
+            WireLogCommands::Replay { file, peer } => wire_log::replay(ctx, &file, peer),
+            WireLogCommands::Record {
+                target,
+                out,
+                chainspec,
+            } => wire_log::record(ctx, &target, &out, chainspec.as_deref()).await,
+            WireLogCommands::Conform {
+                file,
+                peer,
+                chainspec,
+            } => wire_log::conform(ctx, &file, peer, chainspec.as_deref()).await,
+        },
+    }?;
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,433 @@
+//! Client for the binary port casper-node 2.0 serves, usually on port 7779.
+//!
+//! The binary port replaces much of the JSON-RPC API on 2.x networks. Every
+//! message is a little-endian `u32` length followed by that many bytes. A
+//! request is a header naming our protocol version, the kind of request and
+//! an id, followed by the request itself; the node answers with the id and
+//! bytes of the request, a header with an error code, and the payload, all
+//! in casper's `bytesrepr` encoding. Payloads are handed back as the node
+//! wrote them, with just enough of blocks and transactions read to tell
+//! which one they are.
+
+use std::io;
+use std::time::Duration;
+
+use casper_hashing::Digest;
+use casper_types::bytesrepr::Bytes;
+use casper_types::bytesrepr::FromBytes;
+use casper_types::bytesrepr::ToBytes;
+use casper_types::ProtocolVersion;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::rpc::BlockIdentifier;
+
+/// Where nodes serve the binary port unless configured otherwise.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7779";
+
+/// Largest response read, nodes cap theirs at 4 MiB by default.
+const MAX_RESPONSE_LEN: usize = 64 * 1024 * 1024;
+
+/// Error code of a response without error.
+const NO_ERROR: u16 = 0;
+
+/// Error code of a response about an item the node does not have.
+const NOT_FOUND: u16 = 2;
+
+#[derive(Debug, Error)]
+pub enum BinaryPortError {
+    #[error("could not reach {0}")]
+    Connect(String, #[source] io::Error),
+    #[error("{0} did not answer in time")]
+    Timeout(String),
+    #[error("could not talk to {0}")]
+    Io(String, #[source] io::Error),
+    #[error("response of {0} bytes is larger than the {MAX_RESPONSE_LEN} allowed")]
+    TooLarge(usize),
+    #[error("malformed response: {0}")]
+    Malformed(String),
+    #[error("the node answered request {got} to request {sent}")]
+    WrongRequest { sent: u16, got: u16 },
+    #[error("the node answered with error {0}")]
+    Node(u16),
+}
+
+/// Parses the protocol version to tell the node, e.g. `2.0.0`.
+pub fn parse_protocol_version(value: &str) -> Result<ProtocolVersion, String> {
+    value.parse().map_err(|e| format!("invalid protocol version {value:?}: {e:?}"))
+}
+
+/// The kind of request, the `type_tag` of the request header, of those
+/// schultz sends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RequestTag {
+    Get = 0,
+    TrySpeculativeExec = 2,
+}
+
+/// Tag of `Get` requests for information, rather than records or state.
+const GET_INFORMATION: u8 = 1;
+
+/// The information `Get` requests ask for, of what schultz asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InformationTag {
+    SignedBlock = 1,
+    Transaction = 2,
+}
+
+/// Hash of a transaction, which 2.0 tells apart by the kind of transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "hash")]
+pub enum TransactionHash {
+    Deploy(Digest),
+    V1(Digest),
+}
+
+impl TransactionHash {
+    pub fn hash(&self) -> Digest {
+        match self {
+            TransactionHash::Deploy(hash) | TransactionHash::V1(hash) => *hash,
+        }
+    }
+}
+
+/// The start of a block the node returned, which is all of it schultz reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct BlockSummary {
+    /// 1 for blocks made before the upgrade to 2.0, 2 for those after.
+    pub version: u8,
+    pub hash: Digest,
+    pub parent_hash: Digest,
+    pub state_root_hash: Digest,
+}
+
+/// A response as the node wrote it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Response {
+    error: u16,
+    /// Kind of item the payload holds, if any.
+    returned_type: Option<u8>,
+    payload: Vec<u8>,
+}
+
+/// Asks a node through its binary port, one connection per request.
+#[derive(Clone, Debug)]
+pub struct BinaryPortClient {
+    addr: String,
+    protocol_version: ProtocolVersion,
+    timeout: Duration,
+    next_id: u16,
+}
+
+impl BinaryPortClient {
+    /// A client speaking `protocol_version` to the node at `addr`, which the
+    /// node checks its own against.
+    pub fn new(addr: impl Into<String>, protocol_version: ProtocolVersion) -> Self {
+        Self {
+            addr: addr.into(),
+            protocol_version,
+            timeout: Duration::from_secs(10),
+            next_id: 0,
+        }
+    }
+
+    /// Time a request may take from connecting until the whole response was
+    /// read.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn addr(&self) -> &str { &self.addr }
+
+    /// The block with its signatures, or `None` if the node does not have
+    /// it. Asking about no block in particular means the highest one.
+    pub async fn get_block(
+        &mut self,
+        block: Option<BlockIdentifier>,
+    ) -> Result<Option<Vec<u8>>, BinaryPortError> {
+        let request = information(InformationTag::SignedBlock, &block_key(block));
+        self.get(&request).await
+    }
+
+    /// The transaction with what is known about its execution, or `None` if
+    /// the node does not have it.
+    pub async fn get_transaction(
+        &mut self,
+        hash: TransactionHash,
+        with_finalized_approvals: bool,
+    ) -> Result<Option<Vec<u8>>, BinaryPortError> {
+        let mut key = transaction_key(hash);
+        key.push(u8::from(with_finalized_approvals));
+        let request = information(InformationTag::Transaction, &key);
+        self.get(&request).await
+    }
+
+    /// Has the node execute the bytesrepr-encoded `transaction` on top of
+    /// its highest block without keeping the result, and returns the
+    /// result.
+    pub async fn try_speculative_exec(
+        &mut self,
+        transaction: &[u8],
+    ) -> Result<Vec<u8>, BinaryPortError> {
+        let response = self.send(RequestTag::TrySpeculativeExec, transaction).await?;
+        match response.error {
+            NO_ERROR => Ok(response.payload),
+            code => Err(BinaryPortError::Node(code)),
+        }
+    }
+
+    /// Sends a `Get` request, whose answer is an item or nothing at all.
+    async fn get(&mut self, request: &[u8]) -> Result<Option<Vec<u8>>, BinaryPortError> {
+        let response = self.send(RequestTag::Get, request).await?;
+        match response.error {
+            NO_ERROR if response.returned_type.is_none() => Ok(None),
+            NO_ERROR => Ok(Some(response.payload)),
+            NOT_FOUND => Ok(None),
+            code => Err(BinaryPortError::Node(code)),
+        }
+    }
+
+    /// Sends the request with `tag` and body `request` and reads the
+    /// response to it.
+    async fn send(&mut self, tag: RequestTag, request: &[u8]) -> Result<Response, BinaryPortError> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let mut message = header(self.protocol_version, tag, id);
+        message.extend_from_slice(request);
+
+        let response = tokio::time::timeout(self.timeout, self.exchange(&message))
+            .await
+            .map_err(|_| BinaryPortError::Timeout(self.addr.clone()))??;
+        let (got, response) = decode_response(&response)?;
+        if got != id {
+            return Err(BinaryPortError::WrongRequest { sent: id, got });
+        }
+        Ok(response)
+    }
+
+    /// Writes `message` framed and reads the framed answer.
+    async fn exchange(&self, message: &[u8]) -> Result<Vec<u8>, BinaryPortError> {
+        let io_error = |e| BinaryPortError::Io(self.addr.clone(), e);
+        let mut stream = TcpStream::connect(&self.addr)
+            .await
+            .map_err(|e| BinaryPortError::Connect(self.addr.clone(), e))?;
+        let len = u32::try_from(message.len())
+            .map_err(|_| io_error(io::Error::new(io::ErrorKind::InvalidInput, "too long")))?;
+        stream.write_all(&len.to_le_bytes()).await.map_err(io_error)?;
+        stream.write_all(message).await.map_err(io_error)?;
+
+        let len = stream.read_u32_le().await.map_err(io_error)? as usize;
+        if len > MAX_RESPONSE_LEN {
+            return Err(BinaryPortError::TooLarge(len));
+        }
+        let mut response = vec![0; len];
+        stream.read_exact(&mut response).await.map_err(io_error)?;
+        Ok(response)
+    }
+}
+
+/// Reads which block the payload of a `get_block` response is.
+pub fn block_summary(payload: &[u8]) -> Result<BlockSummary, BinaryPortError> {
+    let (version, rest) = u8::from_bytes(payload).map_err(malformed)?;
+    if version > 1 {
+        return Err(BinaryPortError::Malformed(format!(
+            "unknown block version {version}"
+        )));
+    }
+    let (hash, rest) = digest(rest)?;
+    let (parent_hash, rest) = digest(rest)?;
+    let (state_root_hash, _) = digest(rest)?;
+    Ok(BlockSummary {
+        version: version + 1,
+        hash,
+        parent_hash,
+        state_root_hash,
+    })
+}
+
+/// Reads which transaction the payload of a `get_transaction` response is.
+pub fn transaction_summary(payload: &[u8]) -> Result<TransactionHash, BinaryPortError> {
+    let (kind, rest) = u8::from_bytes(payload).map_err(malformed)?;
+    let (hash, _) = digest(rest)?;
+    match kind {
+        0 => Ok(TransactionHash::Deploy(hash)),
+        1 => Ok(TransactionHash::V1(hash)),
+        kind => Err(BinaryPortError::Malformed(format!(
+            "unknown transaction kind {kind}"
+        ))),
+    }
+}
+
+fn malformed(error: casper_types::bytesrepr::Error) -> BinaryPortError {
+    BinaryPortError::Malformed(error.to_string())
+}
+
+/// A hash, written as its bytes without a length.
+fn digest(bytes: &[u8]) -> Result<(Digest, &[u8]), BinaryPortError> {
+    let (hash, rest) = <[u8; Digest::LENGTH]>::from_bytes(bytes).map_err(malformed)?;
+    Ok((Digest::from(hash), rest))
+}
+
+/// The header every request starts with.
+fn header(protocol_version: ProtocolVersion, tag: RequestTag, id: u16) -> Vec<u8> {
+    let mut header = protocol_version.into_bytes().expect("versions always encode");
+    header.push(tag as u8);
+    header.extend_from_slice(&id.to_le_bytes());
+    header
+}
+
+/// A `Get` request for information of kind `tag` about `key`.
+fn information(tag: InformationTag, key: &[u8]) -> Vec<u8> {
+    let mut request = vec![GET_INFORMATION];
+    request.extend_from_slice(&(tag as u16).to_le_bytes());
+    request.extend_from_slice(&Bytes::from(key.to_vec()).into_bytes().expect("keys always encode"));
+    request
+}
+
+/// An optional block identifier: a hash or a height, each after its tag.
+fn block_key(block: Option<BlockIdentifier>) -> Vec<u8> {
+    match block {
+        None => vec![0],
+        Some(BlockIdentifier::Hash(hash)) => {
+            let mut key = vec![1, 0];
+            key.extend_from_slice(hash.as_ref());
+            key
+        }
+        Some(BlockIdentifier::Height(height)) => {
+            let mut key = vec![1, 1];
+            key.extend_from_slice(&height.to_le_bytes());
+            key
+        }
+    }
+}
+
+fn transaction_key(hash: TransactionHash) -> Vec<u8> {
+    let kind = match hash {
+        TransactionHash::Deploy(_) => 0,
+        TransactionHash::V1(_) => 1,
+    };
+    let mut key = vec![kind];
+    key.extend_from_slice(hash.hash().as_ref());
+    key
+}
+
+/// Splits a response into the id of the request it answers and the rest.
+fn decode_response(bytes: &[u8]) -> Result<(u16, Response), BinaryPortError> {
+    let (id, rest) = u16::from_bytes(bytes).map_err(malformed)?;
+    let (_request, rest) = Bytes::from_bytes(rest).map_err(malformed)?;
+    // The protocol version the node speaks, which it already checked ours
+    // against.
+    let (_protocol_version, rest) = ProtocolVersion::from_bytes(rest).map_err(malformed)?;
+    let (error, rest) = u16::from_bytes(rest).map_err(malformed)?;
+    let (returned_type, rest) = Option::<u8>::from_bytes(rest).map_err(malformed)?;
+    let (payload, rest) = Bytes::from_bytes(rest).map_err(malformed)?;
+    if !rest.is_empty() {
+        return Err(BinaryPortError::Malformed(format!(
+            "{} bytes after the payload",
+            rest.len()
+        )));
+    }
+    let response = Response {
+        error,
+        returned_type,
+        payload: payload.into(),
+    };
+    Ok((id, response))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn version() -> ProtocolVersion { ProtocolVersion::from_parts(2, 0, 0) }
+
+    /// A block at height 7 with every hash made of its first byte.
+    fn block() -> Vec<u8> {
+        let mut block = vec![1];
+        for byte in [1, 2, 3, 4] {
+            block.extend_from_slice(&[byte; Digest::LENGTH]);
+        }
+        block.extend_from_slice(b"the rest of the block");
+        block
+    }
+
+    /// Answers like a node that only has the block at height 7, echoing the
+    /// id of every request, and fails speculative execution.
+    async fn node(listener: TcpListener) {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read_u32_le().await.unwrap() as usize;
+            let mut request = vec![0; len];
+            stream.read_exact(&mut request).await.unwrap();
+
+            let (_, rest) = ProtocolVersion::from_bytes(&request).unwrap();
+            let (tag, rest) = u8::from_bytes(rest).unwrap();
+            let (id, body) = u16::from_bytes(rest).unwrap();
+            let height_7 = [1, 1, 7, 0, 0, 0, 0, 0, 0, 0];
+            let (error, returned_type, payload) = match (tag, body) {
+                (0, [GET_INFORMATION, 1, 0, ..]) if body.ends_with(&height_7) => {
+                    (NO_ERROR, Some(0_u8), block())
+                }
+                (0, _) => (NOT_FOUND, None, vec![]),
+                _ => (5, None, vec![]),
+            };
+
+            let mut response = id.to_bytes().unwrap();
+            response.extend(Bytes::from(request).to_bytes().unwrap());
+            response.extend(version().to_bytes().unwrap());
+            response.extend(error.to_bytes().unwrap());
+            response.extend(returned_type.to_bytes().unwrap());
+            response.extend(Bytes::from(payload).to_bytes().unwrap());
+            stream.write_all(&(response.len() as u32).to_le_bytes()).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn asks_a_node() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(node(listener));
+        let mut client = BinaryPortClient::new(addr.to_string(), version());
+
+        let block = client.get_block(Some(BlockIdentifier::Height(7))).await.unwrap().unwrap();
+        let summary = block_summary(&block).unwrap();
+        assert_eq!(summary.version, 2);
+        assert_eq!(summary.hash, Digest::from([1; Digest::LENGTH]));
+        assert_eq!(summary.state_root_hash, Digest::from([3; Digest::LENGTH]));
+
+        assert_eq!(
+            client.get_block(Some(BlockIdentifier::Height(8))).await.unwrap(),
+            None
+        );
+        let hash = TransactionHash::V1(Digest::hash(b"transaction"));
+        assert_eq!(client.get_transaction(hash, true).await.unwrap(), None);
+        assert!(matches!(
+            client.try_speculative_exec(b"transaction").await,
+            Err(BinaryPortError::Node(5))
+        ));
+    }
+
+    #[test]
+    fn tells_transactions_apart() {
+        let hash = Digest::hash(b"deploy");
+        let mut payload = transaction_key(TransactionHash::Deploy(hash));
+        payload.extend_from_slice(b"the rest of the deploy");
+        assert_eq!(
+            transaction_summary(&payload).unwrap(),
+            TransactionHash::Deploy(hash)
+        );
+
+        payload[0] = 2;
+        assert!(matches!(
+            transaction_summary(&payload),
+            Err(BinaryPortError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use casper_types::TimeDiff;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use prometheus::Registry;
+use serde::Serialize;
+use serde_json::json;
+
+use super::bootstrap;
+use crate::network::error::ManagerError;
+use crate::network::gossip::NodePayload;
+use crate::network::manager::Manager;
+use crate::network::progress::Phase;
+use crate::network::progress::Step;
+use crate::network::resolve::Bootnode;
+use crate::network::tls::Identity;
+use crate::node::CHANNEL_SIZE;
+use crate::primitives::Chainspec;
+use crate::Context;
+use crate::OutputFormat;
+
+/// The phases a benchmarked connection goes through, in order.
+const PHASES: [Phase; 3] = [Phase::Connect, Phase::Tls, Phase::Handshake];
+
+/// Pause after a failed attempt, so an unreachable target is not dialed in a
+/// tight loop.
+const FAILURE_PAUSE: Duration = Duration::from_millis(100);
+
+/// Percentiles reported for every phase.
+const PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99)];
+
+/// The steps of a single connection attempt, with when each was taken.
+#[derive(Default)]
+struct Attempt {
+    steps: Mutex<Vec<(Step, Instant)>>,
+}
+
+impl Attempt {
+    fn record(&self, step: Step) {
+        self.steps.lock().expect("steps lock poisoned").push((step, Instant::now()));
+    }
+
+    /// How long each completed phase took, and the phase the attempt failed
+    /// in if it did not complete them all.
+    fn durations(self) -> (Vec<(Phase, Duration)>, Phase) {
+        let steps = self.steps.into_inner().expect("steps lock poisoned");
+        let mut durations = Vec::new();
+        let mut current = Phase::Connect;
+        for (step, at) in &steps {
+            match step {
+                Step::Started(phase) => current = *phase,
+                Step::Completed(phase) => {
+                    let started = steps.iter().find_map(|(step, started)| {
+                        (*step == Step::Started(*phase)).then_some(*started)
+                    });
+                    if let Some(started) = started {
+                        durations.push((*phase, *at - started));
+                    }
+                }
+            }
+        }
+        (durations, current)
+    }
+}
+
+/// Everything measured by the workers.
+#[derive(Default)]
+struct Results {
+    attempts: usize,
+    latencies: BTreeMap<Phase, Vec<Duration>>,
+    totals: Vec<Duration>,
+    failures: BTreeMap<(Phase, String), usize>,
+}
+
+impl Results {
+    /// Adds up `attempt`, which took `total` and failed with `outcome`'s
+    /// error if it did not succeed.
+    fn add(&mut self, attempt: Attempt, total: Duration, outcome: Result<(), String>) {
+        let (durations, failed_in) = attempt.durations();
+        self.attempts += 1;
+        for (phase, duration) in durations {
+            self.latencies.entry(phase).or_default().push(duration);
+        }
+        match outcome {
+            Ok(()) => self.totals.push(total),
+            Err(error) => *self.failures.entry((failed_in, error)).or_default() += 1,
+        }
+    }
+
+    fn succeeded(&self) -> usize { self.totals.len() }
+
+    fn failed(&self) -> usize { self.attempts - self.succeeded() }
+}
+
+/// The latency below which `fraction` of the sorted `latencies` fall.
+fn percentile(latencies: &[Duration], fraction: f64) -> Duration {
+    let rank = (fraction * latencies.len() as f64).ceil() as usize;
+    latencies[rank.clamp(1, latencies.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 { duration.as_micros() as f64 / 1000.0 }
+
+#[derive(Serialize)]
+struct Summary {
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+}
+
+impl Summary {
+    /// Summarizes `latencies`, sorting them. `None` if there are none.
+    fn of(latencies: &mut [Duration]) -> Option<Self> {
+        latencies.sort_unstable();
+        let max = *latencies.last()?;
+        let [p50, p90, p99] = PERCENTILES.map(|(_, fraction)| percentile(latencies, fraction));
+        Some(Summary {
+            p50_ms: millis(p50),
+            p90_ms: millis(p90),
+            p99_ms: millis(p99),
+            max_ms: millis(max),
+        })
+    }
+}
+
+/// Repeatedly connects to `target` and handshakes with it from `concurrency`
+/// connections at a time for `duration`, then prints the latency of every
+/// phase and why attempts failed.
+///
+/// Attempts take the addresses `target` resolves to in turn. Every worker
+/// runs a manager of its own, listening on a free port of the configured
+/// address, so the target sees that many distinct peers.
+pub async fn handshake(
+    ctx: &Context,
+    target: &Bootnode,
+    concurrency: usize,
+    duration: TimeDiff,
+    chainspec: Option<&Path>,
+) -> miette::Result<()> {
+    if concurrency == 0 {
+        miette::bail!("concurrency must be greater than zero");
+    }
+    let addrs: Arc<[SocketAddr]> = target
+        .resolve()
+        .await
+        .wrap_err_with(|| format!("Could not resolve {target}"))?
+        .into();
+    let next_addr = Arc::new(AtomicUsize::new(0));
+
+    let chainspec_path =
+        chainspec.map_or_else(|| bootstrap::chainspec_path(ctx), Path::to_path_buf);
+    let chainspec = Chainspec::from_path(&chainspec_path)
+        .wrap_err_with(|| format!("Failed to load chainspec from {}", chainspec_path.display()))?;
+    let identity = Identity::with_generated_certs().into_diagnostic()?;
+    let ip = ctx.config.node.addr.map_or(IpAddr::V4(Ipv4Addr::LOCALHOST), |addr| addr.ip());
+
+    let results = Arc::new(Mutex::new(Results::default()));
+    let started = Instant::now();
+    let deadline = started + Duration::from(duration);
+    let mut workers = Vec::with_capacity(concurrency);
+    for _ in 0..concurrency {
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+        let manager = Manager::with_identity::<NodePayload>(
+            identity.clone(),
+            SocketAddr::new(ip, 0),
+            event_tx,
+            chainspec.clone(),
+            ctx.config.network.clone(),
+            &Registry::new(),
+        )
+        .await
+        .wrap_err("Could not start a benchmark worker")?;
+
+        let results = results.clone();
+        let addrs = addrs.clone();
+        let next_addr = next_addr.clone();
+        workers.push(tokio::spawn(async move {
+            // Kept open so messages the target sends us are not refused.
+            let _event_rx = event_rx;
+            while Instant::now() < deadline {
+                let addr = addrs[next_addr.fetch_add(1, Ordering::Relaxed) % addrs.len()];
+                if !run_attempt(&manager, addr, &results).await {
+                    tokio::time::sleep(FAILURE_PAUSE).await;
+                }
+            }
+        }));
+    }
+    for worker in workers {
+        worker.await.into_diagnostic()?;
+    }
+    let elapsed = started.elapsed();
+
+    let mut results = Arc::into_inner(results)
+        .expect("every worker finished")
+        .into_inner()
+        .expect("results lock poisoned");
+    print(ctx, &addrs, elapsed, &mut results)
+}
+
+/// Connects to `target`, handshakes with it and disconnects again. Returns
+/// whether the attempt succeeded.
+async fn run_attempt(manager: &Manager, target: SocketAddr, results: &Mutex<Results>) -> bool {
+    let attempt = Attempt::default();
+    let started = Instant::now();
+    let outcome = async {
+        manager.connect_any(&[target], &|step| attempt.record(step)).await?;
+        attempt.record(Step::Started(Phase::Handshake));
+        manager.handshake::<NodePayload>(target).await?;
+        attempt.record(Step::Completed(Phase::Handshake));
+        Ok::<_, ManagerError>(())
+    }
+    .await;
+    let total = started.elapsed();
+    manager.disconnect(target).await;
+
+    let succeeded = outcome.is_ok();
+    let outcome = outcome.map_err(|error| error.to_string());
+    results.lock().expect("results lock poisoned").add(attempt, total, outcome);
+    succeeded
+}
+
+fn print(
+    ctx: &Context,
+    targets: &[SocketAddr],
+    elapsed: Duration,
+    results: &mut Results,
+) -> miette::Result<()> {
+    let rate = results.succeeded() as f64 / elapsed.as_secs_f64();
+    let mut summaries: Vec<(String, Summary)> = PHASES
+        .iter()
+        .filter_map(|phase| {
+            let latencies = results.latencies.get_mut(phase)?;
+            Some((phase.to_string(), Summary::of(latencies)?))
+        })
+        .collect();
+    if let Some(total) = Summary::of(&mut results.totals) {
+        summaries.push(("total".to_string(), total));
+    }
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let failures: Vec<_> = results
+                .failures
+                .iter()
+                .map(|((phase, error), count)| json!({ "phase": phase, "error": error, "count": count }))
+                .collect();
+            let output = json!({
+                "targets": targets,
+                "elapsed_secs": elapsed.as_secs_f64(),
+                "attempts": results.attempts,
+                "succeeded": results.succeeded(),
+                "failed": results.failed(),
+                "handshakes_per_sec": rate,
+                "latencies": summaries.into_iter().collect::<BTreeMap<_, _>>(),
+                "failures": failures,
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            let targets: Vec<_> = targets.iter().map(SocketAddr::to_string).collect();
+            println!(
+                "{} attempts against {} in {:.1}s: {} succeeded, {} failed, {rate:.1} handshakes/s",
+                results.attempts,
+                targets.join(", "),
+                elapsed.as_secs_f64(),
+                results.succeeded(),
+                results.failed(),
+            );
+            if !summaries.is_empty() {
+                println!();
+                let header: Vec<_> =
+                    PERCENTILES.iter().map(|(name, _)| format!("{name:>9}")).collect();
+                println!("{:<10}{}{:>9}", "phase", header.join(""), "max");
+                for (phase, summary) in &summaries {
+                    println!(
+                        "{phase:<10}{:>7.1}ms{:>7.1}ms{:>7.1}ms{:>7.1}ms",
+                        summary.p50_ms, summary.p90_ms, summary.p99_ms, summary.max_ms
+                    );
+                }
+            }
+            if !results.failures.is_empty() {
+                println!();
+                for ((phase, error), count) in &results.failures {
+                    println!("{count:>6} failed in {phase}: {error}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(steps: &[(Step, u64)]) -> Attempt {
+        let start = Instant::now();
+        let steps = steps
+            .iter()
+            .map(|(step, ms)| (*step, start + Duration::from_millis(*ms)))
+            .collect();
+        Attempt {
+            steps: Mutex::new(steps),
+        }
+    }
+
+    #[test]
+    fn attempts_add_up_by_phase_and_failure() {
+        let mut results = Results::default();
+        for ms in [10, 20, 30] {
+            let steps = [
+                (Step::Started(Phase::Connect), 0),
+                (Step::Completed(Phase::Connect), ms),
+                (Step::Started(Phase::Tls), ms),
+                (Step::Completed(Phase::Tls), 2 * ms),
+            ];
+            results.add(attempt(&steps), Duration::from_millis(2 * ms), Ok(()));
+        }
+        let failed = [
+            (Step::Started(Phase::Connect), 0),
+            (Step::Completed(Phase::Connect), 5),
+            (Step::Started(Phase::Tls), 5),
+        ];
+        for _ in 0..2 {
+            let error = Err("handshake failure".to_string());
+            results.add(attempt(&failed), Duration::from_millis(5), error);
+        }
+
+        assert_eq!(results.attempts, 5);
+        assert_eq!(results.succeeded(), 3);
+        assert_eq!(results.failed(), 2);
+        assert_eq!(results.latencies[&Phase::Connect].len(), 5);
+        assert_eq!(results.latencies[&Phase::Tls].len(), 3);
+        let failures = BTreeMap::from([((Phase::Tls, "handshake failure".to_string()), 2)]);
+        assert_eq!(results.failures, failures);
+
+        let tls = Summary::of(results.latencies.get_mut(&Phase::Tls).unwrap()).unwrap();
+        assert_eq!((tls.p50_ms, tls.max_ms), (20.0, 30.0));
+        assert!(Summary::of(&mut []).is_none());
+    }
+
+    #[test]
+    fn percentiles_round_up_to_a_latency_measured() {
+        let latencies: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&latencies, 0.5), Duration::from_millis(5));
+        assert_eq!(percentile(&latencies, 0.9), Duration::from_millis(9));
+        assert_eq!(percentile(&latencies, 0.99), Duration::from_millis(10));
+        assert_eq!(percentile(&latencies, 0.0), Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,103 @@
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+
+use super::fetch::parse_hash;
+use crate::binary_port;
+use crate::binary_port::BinaryPortClient;
+use crate::binary_port::TransactionHash;
+use crate::rpc::BlockIdentifier;
+use crate::BinaryArgs;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Gets `block`, or the highest block, from the binary port at `args.node`
+/// and prints which block it is and how large.
+pub async fn get_block(
+    ctx: &Context,
+    block: Option<BlockIdentifier>,
+    args: &BinaryArgs,
+) -> miette::Result<()> {
+    let mut client = BinaryPortClient::new(args.node.clone(), args.protocol_version);
+    let failed = || format!("Getting the block from {} failed", args.node);
+    let payload = client
+        .get_block(block)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(failed)?
+        .ok_or_else(|| miette::miette!("The node does not have the block"))?;
+    let summary = binary_port::block_summary(&payload).into_diagnostic().wrap_err_with(failed)?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "block": summary,
+                "bytes": payload.len(),
+                "payload": base16::encode_lower(&payload),
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!("hash             {:x}", summary.hash);
+            println!("parent           {:x}", summary.parent_hash);
+            println!("state root hash  {:x}", summary.state_root_hash);
+            println!("version          {}", summary.version);
+            println!("bytes            {}", payload.len());
+        }
+    }
+    Ok(())
+}
+
+/// Gets the transaction with `hash`, of a deploy if `deploy`, from the binary
+/// port at `args.node` and prints which transaction it is and how large.
+pub async fn get_transaction(
+    ctx: &Context,
+    hash: &str,
+    deploy: bool,
+    args: &BinaryArgs,
+) -> miette::Result<()> {
+    let hash = parse_hash(hash)?;
+    let hash = if deploy {
+        TransactionHash::Deploy(hash)
+    } else {
+        TransactionHash::V1(hash)
+    };
+    let mut client = BinaryPortClient::new(args.node.clone(), args.protocol_version);
+    let failed = || format!("Getting the transaction from {} failed", args.node);
+    let payload = client
+        .get_transaction(hash, true)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(failed)?
+        .ok_or_else(|| miette::miette!("The node does not have the transaction"))?;
+    let summary = binary_port::transaction_summary(&payload)
+        .into_diagnostic()
+        .wrap_err_with(failed)?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "transaction": summary,
+                "bytes": payload.len(),
+                "payload": base16::encode_lower(&payload),
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            let kind = match summary {
+                TransactionHash::Deploy(_) => "deploy",
+                TransactionHash::V1(_) => "version 1",
+            };
+            println!("hash   {:x}", summary.hash());
+            println!("kind   {kind}");
+            println!("bytes  {}", payload.len());
+        }
+    }
+    Ok(())
+}
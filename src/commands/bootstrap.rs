@@ -1,37 +1,491 @@
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
+use casper_types::EraId;
+use casper_types::TimeDiff;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::NodeConfig;
 use crate::dirs;
+use crate::error::Error;
+use crate::exit::Failure;
+use crate::network::error::ManagerError;
+use crate::network::finality::FinalityCollector;
+use crate::network::progress::BootstrapError;
+use crate::network::progress::NoProgress;
+use crate::network::progress::Phase;
+use crate::network::progress::Progress;
+use crate::network::resolve::Bootnode;
+#[cfg(any(unix, windows))]
+use crate::node::control;
+use crate::node::export;
+use crate::node::export::Exporter;
+use crate::node::export::Pushgateway;
+use crate::node::export::Statsd;
+#[cfg(feature = "grpc")]
+use crate::node::grpc;
+use crate::node::memory;
+use crate::node::memory::MemoryMetrics;
+use crate::node::memory::MEMORY_CHECK_INTERVAL;
+use crate::node::observations;
+use crate::node::observations::ObservationStore;
+use crate::node::signals;
+use crate::node::signals::Reload;
+use crate::node::snapshots;
+use crate::node::snapshots::SNAPSHOTS_KEPT;
+use crate::node::status;
+#[cfg(unix)]
+use crate::node::systemd;
+use crate::node::ws;
 use crate::node::Node;
+use crate::primitives::Chainspec;
+use crate::primitives::Upgrades;
+use crate::Context;
+use crate::OutputFormat;
+
+/// How long a peer gets to hand out a block header or finality signature it
+/// gossiped, when following the header chain.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prints a status line for every step of the bootstrap.
+struct StepPrinter {
+    output_format: OutputFormat,
+}
+
+impl StepPrinter {
+    fn print(&self, bootnode: &Bootnode, phase: Phase, event: &str, detail: Option<String>) {
+        match self.output_format {
+            OutputFormat::Json => {
+                let line = json!({
+                    "bootnode": bootnode,
+                    "phase": phase,
+                    "event": event,
+                    "detail": detail,
+                });
+                println!("{line}");
+            }
+            OutputFormat::Table => {
+                let step = format!("[{}/{}] {phase} {bootnode}", phase.step(), Phase::ALL.len());
+                match detail {
+                    Some(detail) => println!("{step} {event}: {detail}"),
+                    None => println!("{step} {event}"),
+                }
+            }
+        }
+    }
+
+    fn failed(&self, error: &BootstrapError) {
+        self.print(
+            &error.bootnode,
+            error.phase,
+            "failed",
+            Some(error.source.to_string()),
+        );
+    }
+}
+
+impl Progress for StepPrinter {
+    fn started(&self, bootnode: &Bootnode, phase: Phase) {
+        self.print(bootnode, phase, "...", None);
+    }
+
+    fn completed(&self, bootnode: &Bootnode, phase: Phase) {
+        self.print(bootnode, phase, "ok", None);
+    }
+
+    fn resolved(&self, bootnode: &Bootnode, addrs: &[SocketAddr]) {
+        let addrs: Vec<_> = addrs.iter().map(SocketAddr::to_string).collect();
+        self.print(bootnode, Phase::Resolve, "resolved", Some(addrs.join(", ")));
+    }
+
+    fn connected(&self, bootnode: &Bootnode, addr: SocketAddr) {
+        self.print(bootnode, Phase::Tls, "connected", Some(addr.to_string()));
+    }
+
+    fn retrying(
+        &self,
+        bootnode: &Bootnode,
+        phase: Phase,
+        error: &ManagerError,
+        attempt: u32,
+        delay: Duration,
+    ) {
+        let delay = TimeDiff::from_millis(delay.as_millis().try_into().unwrap_or(u64::MAX));
+        let detail = format!("{error}, retrying in {delay} after attempt {attempt}");
+        self.print(bootnode, phase, "failed", Some(detail));
+    }
+}
 
-pub async fn setup(
-    addr: String,
-    bootnode_addr: Option<String>,
-    chainspec: Option<String>,
-) -> miette::Result<()> {
-    let schultz_addr = SocketAddr::from_str(&addr).expect("Invalid Schultz address");
+/// Starts a node and bootstraps it from the configured bootnode.
+///
+/// Fails with the exit code of the [`Failure`] of the phase the bootstrap
+/// failed in, if it does.
+pub async fn setup(ctx: &Context) -> miette::Result<ExitCode> {
+    let node = start_node(ctx).await?;
 
-    let mut bootnodes = vec![];
-    if let Some(peer_addr) = &bootnode_addr {
-        bootnodes.push(SocketAddr::from_str(peer_addr).expect("Invalid bootnode address"));
+    let bootnodes: Vec<_> = ctx.config.node.bootnode.iter().cloned().collect();
+    let printer = StepPrinter {
+        output_format: ctx.output_format.clone(),
+    };
+    match node.bootstrap(&bootnodes, &printer).await {
+        Ok(()) => {}
+        Err(Error::Bootstrap(error)) => {
+            printer.failed(&error);
+            return Ok(Failure::from(error.phase).into());
+        }
+        Err(error) => return Err(error).wrap_err("Node failed"),
     }
 
-    let chainspec_path = chainspec.unwrap_or_else(|| {
+    run(ctx, node).await?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Starts a node on the configured address, not connected to any peer yet.
+pub(crate) async fn start_node(ctx: &Context) -> miette::Result<Node> {
+    let node_config = &ctx.config.node;
+    let schultz_addr = node_config.addr.ok_or_else(|| {
+        miette::miette!("No address to bind to, pass --addr or set node.addr in the config file")
+    })?;
+
+    let node = Node::new(
+        schultz_addr,
+        vec![],
+        chainspec_path(ctx),
+        ctx.config.network.clone(),
+    )
+    .await
+    .wrap_err("Node failed")?;
+    anchor(ctx, &node);
+    Ok(node)
+}
+
+/// Starts a node on the configured address, or a free local port, and joins
+/// the network through the configured bootnode without printing the steps.
+/// For commands that use the network rather than serve it.
+pub(crate) async fn join(ctx: &Context) -> miette::Result<Node> {
+    let Some(bootnode) = ctx.config.node.bootnode.clone() else {
+        miette::bail!(
+            "No bootnode to join the network through, pass --bootnode or set node.bootnode"
+        );
+    };
+    let addr = ctx
+        .config
+        .node
+        .addr
+        .unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0));
+    let node = Node::new(
+        addr,
+        vec![],
+        chainspec_path(ctx),
+        ctx.config.network.clone(),
+    )
+    .await
+    .wrap_err("Node failed")?;
+    anchor(ctx, &node);
+    node.bootstrap(&[bootnode], &NoProgress)
+        .await
+        .wrap_err("Could not join the network through the bootnode")?;
+    Ok(node)
+}
+
+/// Anchors the header chain of `node` to the configured trusted block, if
+/// any, so neither headers nor the validators switch blocks announce are
+/// taken from whichever peer speaks first.
+fn anchor(ctx: &Context, node: &Node) {
+    if let Some(hash) = ctx.config.node.trusted_hash {
+        node.headers().anchor(hash);
+        info!("Following the header chain from trusted block {hash}");
+    }
+}
+
+/// The configured chainspec, or the one of a local casper-node. Of a
+/// directory holding one per protocol version, that active in the configured
+/// era is taken.
+pub(crate) fn chainspec_path(ctx: &Context) -> PathBuf {
+    resolve_chainspec(&ctx.config.node, ctx.config.node.era)
+}
+
+/// The chainspec of `config`, of the version active in `era_id` if it names
+/// a directory holding one per protocol version.
+fn resolve_chainspec(config: &NodeConfig, era_id: Option<EraId>) -> PathBuf {
+    let path = config.chainspec.clone().unwrap_or_else(|| {
         dirs::ensure_root_dir(None)
             .expect("No home directory")
             .join(".casper-node/chainspec/chainspec.toml")
-            .to_string_lossy()
-            .to_string()
     });
+    Upgrades::resolve(&path, era_id)
+}
 
-    let node = Node::new(schultz_addr, bootnodes, PathBuf::from(chainspec_path));
-    match node.await {
-        Ok(instance) => {
-            instance.keepalive().await;
+/// Serves the status endpoint, the WebSocket stream, the gRPC service and
+/// the control socket, writes or pushes metrics, follows the header chain
+/// and records observations if configured, watches the memory used, and
+/// runs `node` until a signal asks it to stop, reloading the configuration
+/// on every SIGHUP or reload request and keeping systemd posted if it
+/// started us.
+pub(crate) async fn run(ctx: &Context, node: Node) -> miette::Result<()> {
+    let (reloads, reload_requests) = mpsc::channel(1);
+    tokio::spawn(reload_on_request(
+        ctx.clone(),
+        node.clone(),
+        reload_requests,
+    ));
+
+    if let Some(status_addr) = ctx.config.node.status_addr {
+        let listener = TcpListener::bind(status_addr)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not serve status on {status_addr}"))?;
+        let status_node = node.clone();
+        tokio::spawn(async move {
+            if let Err(e) = status::serve(listener, status_node).await {
+                error!("Status server failed: {e}");
+            }
+        });
+    }
+    serve_ws(ctx, &node).await?;
+    serve_grpc(ctx, &node)?;
+    #[cfg(any(unix, windows))]
+    if let Some(path) = &ctx.config.node.control_socket {
+        let listener = control::bind(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not listen on {}", path.display()))?;
+        let control_node = node.clone();
+        let reloads = reloads.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(listener, control_node, reloads).await {
+                error!("Control socket failed: {e}");
+            }
+        });
+    }
+    if let Some(every) = ctx.config.node.metrics_interval {
+        let (registry, dir) = (node.registry.clone(), ctx.metrics_dir());
+        let every = Duration::from(every);
+        tokio::spawn(snapshots::write_periodically(
+            registry,
+            dir,
+            every,
+            SNAPSHOTS_KEPT,
+        ));
+    }
+    push_metrics(ctx, &node).await?;
+    let memory_metrics = MemoryMetrics::new(&node.registry)
+        .into_diagnostic()
+        .wrap_err("Could not register the memory metrics")?;
+    let max_memory = ctx.config.node.max_memory;
+    tokio::spawn(memory::watch(
+        node.clone(),
+        memory_metrics,
+        MEMORY_CHECK_INTERVAL,
+        max_memory,
+    ));
+    if ctx.config.node.follow_headers {
+        follow_headers(&node).await;
+    }
+    store_observations(ctx, &node)?;
+    #[cfg(unix)]
+    tokio::spawn(signals::reload_on_hangup(reloads));
+    #[cfg(unix)]
+    match systemd::Notifier::from_env() {
+        Ok(Some(notifier)) => {
+            let watchdog = systemd::watchdog_interval();
+            tokio::spawn(systemd::supervise(node.clone(), notifier, watchdog));
         }
-        Err(e) => eprintln!("Node failed: {}", e),
+        Ok(None) => {}
+        Err(e) => warn!("Cannot notify systemd: {e}"),
     }
 
+    tokio::select! {
+        () = node.keepalive() => {}
+        signal = signals::shutdown() => info!("Received {signal}, shutting down"),
+    }
+    #[cfg(unix)]
+    if let Ok(Some(notifier)) = systemd::Notifier::from_env() {
+        if let Err(e) = notifier.notify("STOPPING=1") {
+            warn!("Could not notify systemd: {e}");
+        }
+    }
+    #[cfg(unix)]
+    if let Some(path) = &ctx.config.node.control_socket {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!(
+                "Could not remove the control socket {}: {e}",
+                path.display()
+            );
+        }
+    }
     Ok(())
 }
+
+/// Streams the network events and gossip `node` sees to WebSocket clients in
+/// the background, if `node.ws_addr` is set.
+pub(crate) async fn serve_ws(ctx: &Context, node: &Node) -> miette::Result<()> {
+    let Some(ws_addr) = ctx.config.node.ws_addr else {
+        return Ok(());
+    };
+    let listener = TcpListener::bind(ws_addr)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not serve WebSocket clients on {ws_addr}"))?;
+    let node = node.clone();
+    tokio::spawn(async move {
+        if let Err(e) = ws::serve(listener, node).await {
+            error!("WebSocket server failed: {e}");
+        }
+    });
+    Ok(())
+}
+
+/// Pushes the metrics of `node` to the pushgateway and statsd daemon
+/// configured in the background.
+pub(crate) async fn push_metrics(ctx: &Context, node: &Node) -> miette::Result<()> {
+    let telemetry = &ctx.config.telemetry;
+    let mut exporters = vec![];
+    if let Some(url) = &telemetry.pushgateway {
+        let instance = node.manager.read().await.schultz_addr();
+        exporters.push(Exporter::Pushgateway(Pushgateway::new(url, instance)?));
+    }
+    if let Some(addr) = &telemetry.statsd_addr {
+        let statsd = Statsd::connect(addr, telemetry.statsd_format).await?;
+        exporters.push(Exporter::Statsd(statsd));
+    }
+    if exporters.is_empty() {
+        return Ok(());
+    }
+    let every = telemetry.push_interval.map_or(export::PUSH_INTERVAL, Duration::from);
+    tokio::spawn(export::push_periodically(
+        node.registry.clone(),
+        exporters,
+        every,
+    ));
+    Ok(())
+}
+
+/// Answers gRPC requests about `node` in the background, if `node.grpc_addr`
+/// is set.
+#[cfg(feature = "grpc")]
+fn serve_grpc(ctx: &Context, node: &Node) -> miette::Result<()> {
+    let Some(grpc_addr) = ctx.config.node.grpc_addr else {
+        return Ok(());
+    };
+    let token = ctx
+        .config
+        .node
+        .grpc_token_file
+        .as_deref()
+        .map(|path| {
+            grpc::read_token(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not read the gRPC token from {}", path.display()))
+        })
+        .transpose()?;
+    // Anyone who can reach the service could otherwise tell the node
+    // which peers to connect to.
+    if token.is_none() && !grpc_addr.ip().is_loopback() {
+        miette::bail!(
+            "node.grpc_addr {grpc_addr} can be reached from other hosts, set node.grpc_token_file \
+             or answer gRPC requests on a loopback address"
+        );
+    }
+    let incoming = grpc::bind(grpc_addr)
+        .map_err(|e| miette::miette!("Could not answer gRPC requests on {grpc_addr}: {e}"))?;
+    let node = node.clone();
+    tokio::spawn(async move {
+        if let Err(e) = grpc::serve(incoming, node, token).await {
+            error!("gRPC server failed: {e}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(ctx: &Context, _: &Node) -> miette::Result<()> {
+    if ctx.config.node.grpc_addr.is_some() {
+        miette::bail!("node.grpc_addr is set, but schultz was built without the grpc feature");
+    }
+    Ok(())
+}
+
+/// Records what `node` observes in the data directory in the background, and
+/// prunes it as configured, if `node.store_observations` is set.
+pub(crate) fn store_observations(ctx: &Context, node: &Node) -> miette::Result<()> {
+    if !ctx.config.node.store_observations {
+        return Ok(());
+    }
+    let path = ctx.observations_db();
+    let store = ObservationStore::open(&path)?;
+    info!("Recording observations to {}", path.display());
+    let every = observations::PRUNE_INTERVAL;
+    tokio::spawn(store.clone().prune_periodically(ctx.retention(), every));
+    let node = node.clone();
+    tokio::spawn(async move { node.record_observations(store).await });
+    Ok(())
+}
+
+/// Follows the chain of the blocks peers gossip in the background, marking
+/// final those a quorum of the validators of their era signed.
+pub(crate) async fn follow_headers(node: &Node) {
+    let threshold = node.manager.read().await.chainspec.core_config.finality_threshold_fraction;
+    let collector = FinalityCollector::new(node.eras(), threshold).on_quorum({
+        let node = node.clone();
+        move |finality| {
+            if let Err(e) = node.finalize(finality.block_hash, finality.era_id) {
+                warn!("Not marking block {} final: {e}", finality.block_hash);
+            }
+        }
+    });
+    let node = node.clone();
+    tokio::spawn(async move { node.follow_headers(Arc::new(collector), HEADER_TIMEOUT).await });
+}
+
+/// Reloads the configuration for every request, one at a time, answering
+/// each with the outcome.
+async fn reload_on_request(ctx: Context, node: Node, mut requests: mpsc::Receiver<Reload>) {
+    while let Some(request) = requests.recv().await {
+        let reloaded = reload(&ctx, &node).await.map_err(|e| {
+            error!("Keeping the running configuration: {e:?}");
+            e.chain().map(ToString::to_string).collect::<Vec<_>>().join(": ")
+        });
+        let _ = request.send(reloaded);
+    }
+}
+
+/// Reads the configuration file and chainspec again and applies what can
+/// change while `node` runs, warning about every other change.
+///
+/// Nothing is applied unless both are valid. Returns the settings that need a
+/// restart to apply.
+async fn reload(ctx: &Context, node: &Node) -> miette::Result<Vec<String>> {
+    let config = ctx.reload_config()?;
+    // The switch blocks followed tell when the network reaches the
+    // activation point of a later version.
+    let era_id = node.eras().current().map(|(era_id, _)| era_id);
+    let chainspec_path = resolve_chainspec(&config.node, era_id.max(config.node.era));
+    let chainspec = Chainspec::from_path(&chainspec_path)
+        .wrap_err_with(|| format!("Failed to load chainspec from {}", chainspec_path.display()))?;
+
+    let mut restart: Vec<String> =
+        ctx.config.restart_required(&config).into_iter().map(String::from).collect();
+    if node.manager.read().await.chainspec.hash() != chainspec.hash() {
+        restart.push(chainspec_path.display().to_string());
+    }
+    let network = node.reload(&config.network).await;
+    restart.extend(network.into_iter().map(|setting| format!("network.{setting}")));
+
+    for setting in &restart {
+        warn!("{setting} changed, restart to apply it");
+    }
+    info!("Reloaded the configuration");
+    Ok(restart)
+}
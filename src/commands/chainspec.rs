@@ -0,0 +1,344 @@
+use std::path::Path;
+
+use casper_types::AsymmetricType;
+use casper_types::EraId;
+use miette::miette;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+
+use crate::primitives::AccountsSummary;
+use crate::primitives::Chainspec;
+use crate::primitives::ChainspecDiff;
+use crate::primitives::DecWeight;
+use crate::primitives::DecodedEntry;
+use crate::primitives::DecodedValue;
+use crate::primitives::GlobalStateLint;
+use crate::primitives::GlobalStateReader;
+use crate::primitives::GlobalStateUpdate;
+use crate::primitives::Severity;
+use crate::primitives::StateDigest;
+use crate::primitives::Upgrades;
+use crate::primitives::ValidatorSet;
+use crate::Context;
+use crate::OutputFormat;
+
+fn load(dir: &Path) -> miette::Result<Chainspec> {
+    Chainspec::from_path(dir)
+        .wrap_err_with(|| format!("Failed to load chainspec from {}", dir.display()))
+}
+
+pub fn diff(ctx: &Context, dir_a: &Path, dir_b: &Path) -> miette::Result<()> {
+    let chainspec_a = load(dir_a)?;
+    let chainspec_b = load(dir_b)?;
+
+    let diff = ChainspecDiff::between(&chainspec_a, &chainspec_b).into_diagnostic()?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&diff).into_diagnostic()?);
+        }
+        OutputFormat::Table => {
+            if diff.is_empty() {
+                println!("Chainspecs are identical");
+            }
+            for change in &diff.changes {
+                println!("{change}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the entries of the global state update in `dir` in the order they
+/// are written, reading the file one entry at a time.
+pub fn show_global_state(
+    ctx: &Context,
+    dir: &Path,
+    max_value_len: Option<usize>,
+) -> miette::Result<()> {
+    let mut reader = GlobalStateReader::from_dir(dir)
+        .wrap_err_with(|| format!("Failed to load global state update from {}", dir.display()))?
+        .ok_or_else(|| miette!("No global_state.toml found in {}", dir.display()))?;
+    if let Some(max_value_len) = max_value_len {
+        reader = reader.with_max_value_len(max_value_len);
+    }
+    let entries = reader.decoded().map(|entry| {
+        entry
+            .map(|(key, bytes)| DecodedEntry {
+                key: key.to_formatted_string(),
+                value: DecodedValue::from_bytes(bytes.as_slice()),
+            })
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read global state update from {}", dir.display()))
+    });
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let entries = entries.collect::<miette::Result<Vec<_>>>()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&entries).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            let (mut count, mut failed) = (0, 0);
+            for entry in entries {
+                let entry = entry?;
+                match &entry.value {
+                    DecodedValue::Decoded { type_name, value } => {
+                        println!("{}\t{}\t{}", entry.key, type_name, value);
+                    }
+                    DecodedValue::Undecodable { error, .. } => {
+                        println!("{}\tUNDECODABLE\t{}", entry.key, error);
+                        failed += 1;
+                    }
+                }
+                count += 1;
+            }
+            println!("{count} entries, {failed} failed to decode");
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints what is wrong with the global state update in `dir`, failing if
+/// any finding is an error.
+pub fn lint_global_state(ctx: &Context, dir: &Path) -> miette::Result<()> {
+    let lint = GlobalStateLint::from_dir(dir)
+        .wrap_err_with(|| format!("Failed to load global state update from {}", dir.display()))?
+        .ok_or_else(|| miette!("No global_state.toml found in {}", dir.display()))?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&lint).into_diagnostic()?);
+        }
+        OutputFormat::Table => {
+            for finding in &lint.findings {
+                println!("{}\t{}", finding.severity(), finding);
+            }
+            if lint.is_clean() {
+                println!("No mistakes found");
+            }
+        }
+    }
+
+    let errors = lint.count(Severity::Error);
+    if errors > 0 {
+        miette::bail!(
+            "Global state update has {errors} errors and {} warnings",
+            lint.count(Severity::Warning)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the hash of the trie leaf every entry of the global state update in
+/// `dir` becomes, to compare with what a node wrote after the upgrade.
+pub fn digest_global_state(ctx: &Context, dir: &Path) -> miette::Result<()> {
+    let update = GlobalStateUpdate::from_dir(dir)
+        .wrap_err_with(|| format!("Failed to load global state update from {}", dir.display()))?
+        .ok_or_else(|| miette!("No global_state.toml found in {}", dir.display()))?;
+    let digest =
+        StateDigest::of(&update).map_err(|e| miette!("Failed to encode a trie leaf: {e}"))?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&digest).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            for entry in &digest.entries {
+                println!(
+                    "{}\t{:x}",
+                    entry.key.0.to_formatted_string(),
+                    entry.leaf_hash
+                );
+            }
+            println!(
+                "{} entries, digest {:x}",
+                digest.entries.len(),
+                digest.digest
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints the validator set the global state update in `dir` installs, with
+/// each validator's share of the total weight.
+pub fn validators(ctx: &Context, dir: &Path, min_weight: Option<DecWeight>) -> miette::Result<()> {
+    let reader = GlobalStateReader::from_dir(dir)
+        .wrap_err_with(|| format!("Failed to load global state update from {}", dir.display()))?
+        .ok_or_else(|| miette!("No global_state.toml found in {}", dir.display()))?;
+    let set = ValidatorSet::read(reader, min_weight.map(|weight| weight.0))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read global state update from {}", dir.display()))?
+        .ok_or_else(|| {
+            miette!(
+                "The global_state.toml in {} leaves the validator set as it is",
+                dir.display()
+            )
+        })?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&set).into_diagnostic()?);
+        }
+        OutputFormat::Table => {
+            for validator in &set.validators {
+                println!("{validator}");
+            }
+            println!(
+                "{} validators, total weight {}",
+                set.validators.len(),
+                set.total_weight.0
+            );
+            if let Some(minimum) = set.minimum_weight {
+                println!(
+                    "{} below the minimum weight {}",
+                    set.below_minimum(),
+                    minimum.0
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the chainspec in `dir` as canonical JSON, whatever the output
+/// format, since JSON is the point.
+pub fn to_json(dir: &Path, compact: bool) -> miette::Result<()> {
+    let json = load(dir)?.to_json().into_diagnostic()?;
+    let printed = if compact {
+        serde_json::to_string(&json)
+    } else {
+        serde_json::to_string_pretty(&json)
+    };
+    println!("{}", printed.into_diagnostic()?);
+    Ok(())
+}
+
+/// Prints what the `accounts.toml` in `dir` sets up at genesis and what is
+/// wrong with it, failing if any finding is an error.
+pub fn accounts(ctx: &Context, dir: &Path) -> miette::Result<()> {
+    let summary = AccountsSummary::from_dir(dir)
+        .wrap_err_with(|| format!("Failed to load accounts from {}", dir.display()))?
+        .ok_or_else(|| miette!("No accounts.toml found in {}", dir.display()))?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&summary).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!(
+                "{} accounts, {} validators, {} delegators, {} administrators",
+                summary.accounts,
+                summary.validators.len(),
+                summary.delegators,
+                summary.administrators
+            );
+            println!(
+                "total balance {}, bonded {}, delegated {}",
+                summary.total_balance.0, summary.total_bonded.0, summary.total_delegated.0
+            );
+            for validator in &summary.validators {
+                println!(
+                    "{:<68}  {:>30}  {:>30}  {:>5} delegators  rate {}",
+                    validator.public_key.0.to_hex(),
+                    validator.bonded_amount.0,
+                    validator.delegated_amount.0,
+                    validator.delegators,
+                    validator.delegation_rate
+                );
+            }
+            for finding in &summary.findings {
+                println!("{}\t{}", finding.severity(), finding);
+            }
+        }
+    }
+
+    let errors = summary.count(Severity::Error);
+    if errors > 0 {
+        miette::bail!(
+            "Accounts have {errors} errors and {} warnings",
+            summary.count(Severity::Warning)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the protocol versions in the casper-node style directory `root`,
+/// lowest first, with the era each activates in, and the one active in
+/// `era` if given.
+pub fn list_upgrades(ctx: &Context, root: &Path, era: Option<EraId>) -> miette::Result<()> {
+    let upgrades = Upgrades::load(root)?;
+    let active = era.and_then(|era| upgrades.active_at(era)).map(|upgrade| upgrade.version);
+    if let (Some(era), None) = (era, active) {
+        miette::bail!(
+            "No protocol version in {} active in era {era}",
+            root.display()
+        );
+    }
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let upgrades: Vec<_> = upgrades
+                .iter()
+                .map(|upgrade| {
+                    let protocol = &upgrade.chainspec.protocol_config;
+                    json!({
+                        "version": upgrade.version,
+                        "dir": upgrade.dir,
+                        "activation_point": protocol.activation_point,
+                        "hard_reset": protocol.hard_reset,
+                        "global_state_update": protocol.global_state_update.is_some(),
+                        "network": upgrade.chainspec.network_config.name,
+                    })
+                })
+                .collect();
+            let output = json!({ "upgrades": upgrades, "era": era, "active": active });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!(
+                "  {:<10}{:<24}{:<12}{:<14}dir",
+                "version", "activation", "hard reset", "state update"
+            );
+            for upgrade in upgrades.iter() {
+                let protocol = &upgrade.chainspec.protocol_config;
+                let activation = match protocol.activation_point.genesis_timestamp() {
+                    Some(timestamp) => format!("genesis {timestamp}"),
+                    None => format!("era {}", upgrade.activation_era()),
+                };
+                let marker = if active == Some(upgrade.version) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!(
+                    "{marker} {:<10}{:<24}{:<12}{:<14}{}",
+                    upgrade.version.to_string(),
+                    activation,
+                    protocol.hard_reset,
+                    protocol.global_state_update.is_some(),
+                    upgrade.dir.display()
+                );
+            }
+            if let (Some(era), Some(version)) = (era, active) {
+                println!("Era {era} runs protocol version {version}");
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use miette::IntoDiagnostic;
+
+use crate::Context;
+use crate::OutputFormat;
+
+pub fn print(ctx: &Context) -> miette::Result<()> {
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&ctx.config).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            print!("{}", toml::to_string_pretty(&ctx.config).into_diagnostic()?);
+        }
+    }
+
+    Ok(())
+}
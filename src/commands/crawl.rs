@@ -0,0 +1,324 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_types::TimeDiff;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+use tracing::warn;
+
+use super::bootstrap;
+use crate::geo;
+use crate::geo::Distribution;
+use crate::geo::GeoInfo;
+use crate::geo::GeoLookup;
+use crate::geo::Located;
+use crate::network::churn;
+use crate::network::churn::Churn;
+use crate::network::churn::CrawlSnapshot;
+use crate::network::churn::CRAWLS_KEPT;
+use crate::network::crawl::CrawlState;
+use crate::network::crawl::CrawledNode;
+use crate::network::crawl::Crawler;
+use crate::network::resolve::Bootnode;
+use crate::network::tls::Identity;
+use crate::network::topology::Topology;
+use crate::network::topology::TopologyFormat;
+use crate::node::signals;
+use crate::primitives::Chainspec;
+use crate::Context;
+use crate::OutputFormat;
+
+/// How to crawl, and what to write besides the nodes printed.
+pub struct Options {
+    /// Peers dialed at a time.
+    pub workers: usize,
+    /// How long each peer is listened to for the addresses it gossips.
+    pub listen: TimeDiff,
+    /// File progress is checkpointed to, the crawl file of the data
+    /// directory if not given.
+    pub checkpoint: Option<PathBuf>,
+    /// Whether to continue the crawl checkpointed rather than start over.
+    pub resume: bool,
+    /// File to write the graph of the network to.
+    pub topology: Option<PathBuf>,
+    pub topology_format: TopologyFormat,
+    /// Whether the edges of the graph carry the protocol version and latency
+    /// of the node they lead to.
+    pub edge_metadata: bool,
+    /// How often to crawl again, once if not given.
+    pub schedule: Option<TimeDiff>,
+    /// Whether to print what changed since the previous crawl.
+    pub diff: bool,
+}
+
+/// Crawls the network from the configured bootnode as `options` say, then
+/// prints every node reached, and where it is if GeoIP databases are
+/// configured, and writes the graph of the network if asked to.
+///
+/// Progress is checkpointed, and with `options.resume` the crawl continues
+/// from the checkpoint rather than starting over. A crawl stopped by a
+/// signal is checkpointed before exiting.
+///
+/// Finished crawls are kept in the crawls directory of the data directory,
+/// and with `options.diff` what changed since the previous one is printed.
+/// With `options.schedule` the network is crawled again from the bootnode
+/// that often, until a signal stops it.
+pub async fn crawl(ctx: &Context, options: &Options) -> miette::Result<()> {
+    if options.workers == 0 {
+        miette::bail!("workers must be greater than zero");
+    }
+    let geo = geo::from_config(&ctx.config.node)?;
+    let checkpoint = options.checkpoint.clone().unwrap_or_else(|| ctx.crawl_checkpoint());
+    if options.schedule.is_some() {
+        // Later runs start from the bootnode, even after resuming.
+        bootnode(ctx)?;
+    }
+    let mut state = if options.resume {
+        let state = CrawlState::load(&checkpoint)?;
+        info!(
+            "Resuming the crawl with {} nodes reached and {} addresses left",
+            state.nodes().count(),
+            state.remaining()
+        );
+        state
+    } else {
+        from_bootnode(ctx).await?
+    };
+
+    let chainspec_path = bootstrap::chainspec_path(ctx);
+    let chainspec = Chainspec::from_path(&chainspec_path)
+        .wrap_err_with(|| format!("Failed to load chainspec from {}", chainspec_path.display()))?;
+    // Peers only need to see some certificate, so a throwaway one will do.
+    let identity = Identity::with_generated_certs().into_diagnostic()?;
+    let crawler = Crawler::new(
+        identity,
+        chainspec,
+        ctx.config.network.clone(),
+        options.workers,
+        options.listen.into(),
+    );
+    let mut runs = options.schedule.map(|every| {
+        let mut runs = interval(every.into());
+        runs.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        runs
+    });
+    if let Some(runs) = &mut runs {
+        // The first tick is the crawl starting now.
+        runs.tick().await;
+    }
+
+    loop {
+        let stop = async {
+            let signal = signals::shutdown().await;
+            info!("Received {signal}, checkpointing the crawl");
+        };
+        let finished = crawler.run(&mut state, Some(&checkpoint), stop).await?;
+        if let Some(path) = &options.topology {
+            write_topology(&state, path, options.topology_format, options.edge_metadata)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not write the topology to {}", path.display()))?;
+        }
+        let churn = match finished {
+            true => keep(ctx, &state, options.diff)?,
+            false => None,
+        };
+        print(
+            ctx,
+            &state,
+            finished,
+            &checkpoint,
+            geo.as_deref(),
+            churn.as_ref(),
+        )?;
+
+        let Some(runs) = &mut runs else {
+            return Ok(());
+        };
+        if !finished {
+            return Ok(());
+        }
+        state = loop {
+            tokio::select! {
+                _ = runs.tick() => {}
+                signal = signals::shutdown() => {
+                    info!("Received {signal}, not crawling again");
+                    return Ok(());
+                }
+            }
+            // The bootnode may well resolve again by the next run.
+            match from_bootnode(ctx).await {
+                Ok(state) => break state,
+                Err(e) => warn!("Skipping this run: {e:?}"),
+            }
+        };
+    }
+}
+
+/// The bootnode crawls start from.
+fn bootnode(ctx: &Context) -> miette::Result<&Bootnode> {
+    match &ctx.config.node.bootnode {
+        Some(bootnode) => Ok(bootnode),
+        None => miette::bail!(
+            "No bootnode to start crawling from, pass --bootnode or set node.bootnode"
+        ),
+    }
+}
+
+/// A crawl starting from the addresses of the configured bootnode.
+async fn from_bootnode(ctx: &Context) -> miette::Result<CrawlState> {
+    let bootnode = bootnode(ctx)?;
+    let seeds = bootnode
+        .resolve()
+        .await
+        .wrap_err_with(|| format!("Could not resolve {bootnode}"))?;
+    Ok(CrawlState::new(seeds))
+}
+
+/// Keeps the finished crawl of `state` in the crawls directory, and tells
+/// what changed since the previous one kept if `diff`.
+fn keep(ctx: &Context, state: &CrawlState, diff: bool) -> miette::Result<Option<Churn>> {
+    let dir = ctx.crawls_dir();
+    let previous = match diff {
+        true => CrawlSnapshot::latest(&dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not read the previous crawl in {}", dir.display()))?,
+        false => None,
+    };
+    if diff && previous.is_none() {
+        info!("No previous crawl in {} to compare with", dir.display());
+    }
+    let snapshot = CrawlSnapshot::of(state);
+    snapshot
+        .write(&dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not keep the crawl in {}", dir.display()))?;
+    if let Err(e) = churn::prune(&dir, CRAWLS_KEPT) {
+        warn!("Could not remove old crawls: {e}");
+    }
+    Ok(previous.map(|previous| Churn::between(&previous, &snapshot)))
+}
+
+fn write_topology(
+    state: &CrawlState,
+    path: &Path,
+    format: TopologyFormat,
+    edge_metadata: bool,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    Topology::of(state.nodes()).write(format, edge_metadata, &mut out)?;
+    out.flush()
+}
+
+/// Where `node` is, by the first address it was reached on.
+fn locate(geo: &dyn GeoLookup, node: &CrawledNode) -> GeoInfo {
+    geo.lookup(node.addrs.first().unwrap_or(&node.public_addr).ip())
+}
+
+fn print(
+    ctx: &Context,
+    state: &CrawlState,
+    finished: bool,
+    checkpoint: &Path,
+    geo: Option<&dyn GeoLookup>,
+    churn: Option<&Churn>,
+) -> miette::Result<()> {
+    let located: Vec<_> = state
+        .nodes()
+        .map(|node| Located {
+            item: node,
+            geo: geo.map(|geo| locate(geo, node)),
+        })
+        .collect();
+    let distribution = geo.map(|_| Distribution::of(located.iter().flat_map(|node| &node.geo)));
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "finished": finished,
+                "checkpoint": checkpoint,
+                "nodes": located,
+                "unreachable": state.unreachable(),
+                "remaining": state.remaining(),
+                "distribution": distribution,
+                "churn": churn,
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!(
+                "{:<16}{:<24}{:<24}{:<10}{:>6}  location",
+                "node", "reached on", "public address", "version", "peers"
+            );
+            for Located { item: node, geo } in &located {
+                let addrs: Vec<_> = node.addrs.iter().map(ToString::to_string).collect();
+                let location = geo.as_ref().map_or_else(
+                    || "-".to_string(),
+                    |geo| format!("{} {}", geo.country_or_unknown(), geo.asn_or_unknown()),
+                );
+                println!(
+                    "{:<16}{:<24}{:<24}{:<10}{:>6}  {location}",
+                    node.node_id.to_string(),
+                    addrs.join(","),
+                    node.public_addr.to_string(),
+                    node.protocol_version.to_string(),
+                    node.peers.len()
+                );
+            }
+            println!(
+                "{} nodes reached, {} addresses unreachable",
+                state.nodes().count(),
+                state.unreachable().len()
+            );
+            if !finished {
+                println!(
+                    "Stopped with {} addresses left, continue with --resume from {}",
+                    state.remaining(),
+                    checkpoint.display()
+                );
+            }
+            if let Some(distribution) = distribution {
+                distribution.print_table();
+            }
+            if let Some(churn) = churn {
+                print_churn(churn);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_churn(churn: &Churn) {
+    println!();
+    println!(
+        "Since {}: {} appeared, {} vanished, {} changed version, {} slower",
+        churn.since,
+        churn.appeared.len(),
+        churn.vanished.len(),
+        churn.version_changes.len(),
+        churn.latency_regressions.len()
+    );
+    for peer in &churn.appeared {
+        println!("+ {} {}", peer.node_id, peer.public_addr);
+    }
+    for peer in &churn.vanished {
+        println!("- {} {}", peer.node_id, peer.public_addr);
+    }
+    for change in &churn.version_changes {
+        println!("~ {} {} -> {}", change.node_id, change.from, change.to);
+    }
+    for regression in &churn.latency_regressions {
+        println!(
+            "! {} {} ms -> {} ms",
+            regression.node_id, regression.from_ms, regression.to_ms
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use miette::IntoDiagnostic;
+
+use crate::Context;
+use crate::OutputFormat;
+
+/// Prints where the data directory is, the version of its layout and what
+/// every part of it holds for the selected network.
+pub fn info(ctx: &Context) -> miette::Result<()> {
+    let info = ctx.data_dir.info(ctx.network_name())?;
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&info).into_diagnostic()?)
+        }
+        OutputFormat::Table => {
+            println!("{} (layout {})", info.path.display(), info.layout_version);
+            for entry in &info.entries {
+                let holds = if entry.exists {
+                    format!("{} files, {} bytes", entry.files, entry.bytes)
+                } else {
+                    "missing".to_string()
+                };
+                println!("{:<14}{:<24}{}", entry.name, holds, entry.path.display());
+            }
+        }
+    }
+    Ok(())
+}
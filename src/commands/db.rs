@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_types::AsymmetricType;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use miette::IntoDiagnostic;
+use serde_json::json;
+
+use super::fetch;
+use crate::node::observations::Kind;
+use crate::node::observations::ObservationStore;
+use crate::node::observations::Query;
+use crate::node::observations::Record;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Prints the observations of `kind` recorded within `since`, about `block`
+/// if given, most recent first and at most `limit` of them.
+pub fn query(
+    ctx: &Context,
+    kind: Kind,
+    since: Option<TimeDiff>,
+    block: Option<&str>,
+    limit: Option<usize>,
+    db: Option<&Path>,
+) -> miette::Result<()> {
+    if block.is_some() && kind == Kind::Deploys {
+        miette::bail!("deploys are not recorded with a block, leave out --block");
+    }
+    let query = Query {
+        since: since.map(|since| Timestamp::now().saturating_sub(since)),
+        block_hash: block.map(fetch::parse_hash).transpose()?,
+        limit,
+    };
+    let records = open(ctx, db)?.query(kind, &query)?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&records).into_diagnostic()?
+            )
+        }
+        OutputFormat::Table => {
+            if records.is_empty() {
+                println!("No {kind}s recorded, set node.store_observations and let the node run");
+            }
+            for record in &records {
+                print_record(record);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints how many observations of each kind are recorded.
+pub fn info(ctx: &Context, db: Option<&Path>) -> miette::Result<()> {
+    let store = open(ctx, db)?;
+    let counts = Kind::ALL
+        .into_iter()
+        .map(|kind| Ok((kind.name(), store.len(kind)?)))
+        .collect::<miette::Result<BTreeMap<_, _>>>()?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let info = json!({ "path": path(ctx, db), "counts": counts });
+            println!("{}", serde_json::to_string_pretty(&info).into_diagnostic()?)
+        }
+        OutputFormat::Table => {
+            println!("{}", path(ctx, db).display());
+            for (kind, count) in counts {
+                println!("{kind:<22}{count}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prunes the observations the configured retention does not keep.
+pub fn prune(ctx: &Context, db: Option<&Path>) -> miette::Result<()> {
+    let retention = ctx.retention();
+    if retention.max_age.is_none() && retention.max_entries.is_none() {
+        miette::bail!(
+            "No retention configured, set node.store_retention or node.store_max_entries"
+        );
+    }
+    let pruned = open(ctx, db)?.prune(retention)?;
+    match ctx.output_format {
+        OutputFormat::Json => println!("{}", json!({ "pruned": pruned })),
+        OutputFormat::Table => println!("Pruned {pruned} observations"),
+    }
+    Ok(())
+}
+
+fn path(ctx: &Context, db: Option<&Path>) -> PathBuf {
+    db.map(Path::to_path_buf).unwrap_or_else(|| ctx.observations_db())
+}
+
+/// Opens the database, which has to exist already.
+fn open(ctx: &Context, db: Option<&Path>) -> miette::Result<ObservationStore> {
+    let path = path(ctx, db);
+    if !path.exists() {
+        miette::bail!(
+            "No observations at {}, set node.store_observations and let the node run",
+            path.display()
+        );
+    }
+    Ok(ObservationStore::open(&path)?)
+}
+
+fn print_record(record: &Record) {
+    match record {
+        Record::Header(header) => println!(
+            "{}  {}  height {}  era {}{}",
+            header.observed_at,
+            header.block_hash,
+            header.height,
+            header.era_id,
+            if header.switch_block { "  switch" } else { "" }
+        ),
+        Record::Deploy(deploy) => println!(
+            "{}  {}  from {}",
+            deploy.observed_at, deploy.deploy_hash, deploy.peer
+        ),
+        Record::FinalitySignature(signature) => println!(
+            "{}  {}  era {}  by {}",
+            signature.observed_at,
+            signature.block_hash,
+            signature.era_id,
+            signature.public_key.to_hex()
+        ),
+    }
+}
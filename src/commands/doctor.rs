@@ -0,0 +1,330 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use futures::future::join_all;
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use tokio::net::lookup_host;
+use tokio::net::TcpStream;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use super::bootstrap::chainspec_path;
+use crate::network::protocol::VERSIONS;
+use crate::network::resolve::Bootnode;
+use crate::network::tls::Active;
+use crate::network::tls::Backend;
+use crate::network::tls::Identity;
+use crate::primitives::Chainspec;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Clock offset from NTP beyond which peers are likely to see our
+/// certificates and timestamps as off.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(10);
+
+/// Time the NTP server has to answer.
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seconds between the NTP epoch, 1900, and the Unix one.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Pass,
+    Fail,
+    /// The check could not be made, which says nothing about the node.
+    Skip,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "ok"),
+            Status::Fail => write!(f, "FAILED"),
+            Status::Skip => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Checks what a node needs from its environment, the TLS library, the
+/// chainspec, the bootnode and the system clock, and prints the outcome of
+/// each check. Fails if any of them does.
+pub async fn doctor(ctx: &Context, ntp_server: &str) -> miette::Result<()> {
+    let mut checks = vec![tls(ctx), chainspec(ctx)];
+    match &ctx.config.node.bootnode {
+        Some(bootnode) => checks.push(bootnode_reachable(ctx, bootnode).await),
+        None => checks.push(Check::new(
+            "bootnode",
+            Status::Skip,
+            "no bootnode configured, pass --bootnode or set node.bootnode",
+        )),
+    }
+    checks.push(clock(ntp_server).await);
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&checks).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            for check in &checks {
+                println!("{}\t{}\t{}", check.name, check.status, check.detail);
+            }
+        }
+    }
+
+    let failed = checks.iter().filter(|check| matches!(check.status, Status::Fail)).count();
+    if failed > 0 {
+        miette::bail!("{failed} of {} checks failed", checks.len());
+    }
+    Ok(())
+}
+
+/// The TLS library can make an identity and knows the configured cipher
+/// suites and groups.
+fn tls(ctx: &Context) -> Check {
+    let version = Active::version();
+    if let Err(error) = Identity::with_generated_certs() {
+        return Check::new("tls", Status::Fail, format!("{version}: {error}"));
+    }
+    match ctx.config.network.tls_options().check() {
+        Ok(()) => Check::new("tls", Status::Pass, version),
+        Err(error) => Check::new("tls", Status::Fail, format!("{version}: {error}")),
+    }
+}
+
+/// The chainspec loads and is on a protocol version schultz speaks.
+fn chainspec(ctx: &Context) -> Check {
+    let path = chainspec_path(ctx);
+    match Chainspec::from_path(&path) {
+        Ok(chainspec) if !VERSIONS.supports(chainspec.protocol_version()) => Check::new(
+            "chainspec",
+            Status::Fail,
+            format!(
+                "{} is on protocol {}, which schultz does not speak",
+                chainspec.network_config.name,
+                chainspec.protocol_version()
+            ),
+        ),
+        Ok(chainspec) => Check::new(
+            "chainspec",
+            Status::Pass,
+            format!(
+                "{} on protocol {}",
+                chainspec.network_config.name,
+                chainspec.protocol_version()
+            ),
+        ),
+        Err(error) => Check::new(
+            "chainspec",
+            Status::Fail,
+            format!("{}: {error}", path.display()),
+        ),
+    }
+}
+
+/// The bootnode resolves, and at least one of its addresses takes TCP
+/// connections within the connect timeout.
+async fn bootnode_reachable(ctx: &Context, bootnode: &Bootnode) -> Check {
+    let name = format!("bootnode {bootnode}");
+    let addrs = match bootnode.resolve().await {
+        Ok(addrs) => addrs,
+        Err(error) => return Check::new(name, Status::Fail, error.to_string()),
+    };
+
+    let connect_timeout = ctx.config.network.connect_timeout.into();
+    let probes = addrs.iter().map(|addr| async move {
+        match timeout(connect_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err("timed out".to_string()),
+        }
+    });
+    let results = join_all(probes).await;
+
+    let status = if results.iter().any(Result::is_ok) {
+        Status::Pass
+    } else {
+        Status::Fail
+    };
+    let detail: Vec<_> = addrs
+        .iter()
+        .zip(results)
+        .map(|(addr, result)| match result {
+            Ok(()) => format!("{addr} open"),
+            Err(error) => format!("{addr} {error}"),
+        })
+        .collect();
+    Check::new(name, status, detail.join(", "))
+}
+
+/// The system clock is within [`MAX_CLOCK_SKEW`] of the one of `ntp_server`.
+async fn clock(ntp_server: &str) -> Check {
+    let skew = match timeout(NTP_TIMEOUT, clock_skew(ntp_server)).await {
+        Ok(Ok(skew)) => skew,
+        Ok(Err(error)) => {
+            return Check::new(
+                "clock",
+                Status::Skip,
+                format!("could not ask {ntp_server}: {error}"),
+            )
+        }
+        Err(_) => {
+            return Check::new(
+                "clock",
+                Status::Skip,
+                format!("{ntp_server} did not answer in time"),
+            )
+        }
+    };
+    skew_check(ntp_server, skew)
+}
+
+/// Passes a clock `skew` seconds off the one of `ntp_server`, either way, if
+/// it is within [`MAX_CLOCK_SKEW`].
+fn skew_check(ntp_server: &str, skew: f64) -> Check {
+    let detail = format!("{skew:+.3}s off {ntp_server}");
+    if skew.abs() <= MAX_CLOCK_SKEW.as_secs_f64() {
+        Check::new("clock", Status::Pass, detail)
+    } else {
+        Check::new("clock", Status::Fail, detail)
+    }
+}
+
+/// Asks `ntp_server` for the time over SNTP (RFC 4330), and returns how many
+/// seconds our clock is behind it, negative if it is ahead.
+async fn clock_skew(ntp_server: &str) -> io::Result<f64> {
+    let server = lookup_host(ntp_server)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "name resolves to no address"))?;
+    let local = match server.ip() {
+        IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        IpAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+
+    // Version 4, client mode, everything else left to the server.
+    let mut request = [0u8; 48];
+    request[0] = 0b00_100_011;
+    let sent = unix_now();
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let len = socket.recv(&mut response).await?;
+    let received = unix_now();
+    if len < response.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "short NTP response",
+        ));
+    }
+    offset(&response, sent, received)
+}
+
+/// How many seconds the clock that sent a request at Unix time `sent` and
+/// got `response` at `received` is behind the server's.
+fn offset(response: &[u8; 48], sent: f64, received: f64) -> io::Result<f64> {
+    // The server's transmit timestamp, seconds and a 32 bit fraction since
+    // the NTP epoch. Half the round trip is assumed to have passed since.
+    let seconds = u32::from_be_bytes(response[40..44].try_into().expect("4 bytes"));
+    let fraction = u32::from_be_bytes(response[44..48].try_into().expect("4 bytes"));
+    if seconds == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "NTP server sent no time",
+        ));
+    }
+    let server_time = (u64::from(seconds) as f64 - NTP_UNIX_OFFSET as f64)
+        + f64::from(fraction) / 4_294_967_296.0;
+    Ok(server_time - (sent + received) / 2.0)
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A response whose transmit timestamp is `unix` seconds and `fraction`
+    /// 2^32ths of one.
+    fn response(unix: u64, fraction: u32) -> [u8; 48] {
+        let mut response = [0u8; 48];
+        let seconds = u32::try_from(unix + NTP_UNIX_OFFSET).unwrap();
+        response[40..44].copy_from_slice(&seconds.to_be_bytes());
+        response[44..48].copy_from_slice(&fraction.to_be_bytes());
+        response
+    }
+
+    #[test]
+    fn offsets_count_from_the_middle_of_the_round_trip() {
+        let response = response(1_700_000_000, 1 << 31);
+        let skew = offset(&response, 1_699_999_999.0, 1_700_000_000.0).unwrap();
+        assert_eq!(skew, 1.0);
+        let skew = offset(&response, 1_700_000_002.0, 1_700_000_004.0).unwrap();
+        assert_eq!(skew, -2.5);
+    }
+
+    #[test]
+    fn fractions_are_of_two_to_the_32nd() {
+        // At the Unix epoch, so that the fraction is not rounded away.
+        let skew = offset(&response(0, u32::MAX), 0.0, 0.0).unwrap();
+        assert!(skew < 1.0, "{skew}");
+        let skew = offset(&response(0, 1 << 30), 0.0, 0.0).unwrap();
+        assert_eq!(skew, 0.25);
+    }
+
+    #[test]
+    fn responses_without_a_time_are_rejected() {
+        let error = offset(&[0; 48], 0.0, 0.0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn skews_past_the_limit_either_way_fail() {
+        let limit = MAX_CLOCK_SKEW.as_secs_f64();
+        for skew in [0.0, limit, -limit] {
+            let check = skew_check("pool.ntp.org:123", skew);
+            assert!(matches!(check.status, Status::Pass), "{skew}");
+        }
+        for skew in [limit + 0.001, -limit - 0.001] {
+            let check = skew_check("pool.ntp.org:123", skew);
+            assert!(matches!(check.status, Status::Fail), "{skew}");
+        }
+        let check = skew_check("pool.ntp.org:123", -12.3456);
+        assert_eq!(check.detail, "-12.346s off pool.ntp.org:123");
+    }
+}
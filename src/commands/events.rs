@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+
+use crate::http::Endpoint;
+use crate::sse::EventKind;
+use crate::sse::EventStream;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Follows the event stream of the node at `node` and prints every event of
+/// the kinds in `filter`, or every event without one. With `output`, the
+/// events are appended to that file as JSON lines instead.
+///
+/// Returns once the node closes the stream.
+pub async fn events(
+    ctx: &Context,
+    node: &Endpoint,
+    filter: Option<&[EventKind]>,
+    output: Option<&Path>,
+) -> miette::Result<()> {
+    let mut file = output
+        .map(|path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not open {}", path.display()))
+        })
+        .transpose()?;
+
+    let mut stream = EventStream::connect(node).await.into_diagnostic()?;
+    while let Some(event) = stream
+        .next()
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Reading events from {node} failed"))?
+    {
+        if let Some(filter) = filter {
+            if !event.event.kind().is_some_and(|kind| filter.contains(&kind)) {
+                continue;
+            }
+        }
+        match (&mut file, &ctx.output_format) {
+            (Some(file), _) => {
+                let json = serde_json::to_string(&event).into_diagnostic()?;
+                writeln!(file, "{json}").into_diagnostic()?;
+            }
+            (None, OutputFormat::Json) => {
+                println!("{}", serde_json::to_string(&event).into_diagnostic()?);
+            }
+            (None, OutputFormat::Table) => match event.id {
+                Some(id) => println!("{id:>8} {}", event.event),
+                None => println!("{:>8} {}", "-", event.event),
+            },
+        }
+    }
+    Ok(())
+}
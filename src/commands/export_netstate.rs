@@ -0,0 +1,49 @@
+#[cfg(any(unix, windows))]
+use std::fs;
+use std::path::Path;
+
+#[cfg(any(unix, windows))]
+use miette::IntoDiagnostic;
+#[cfg(any(unix, windows))]
+use miette::WrapErr;
+
+#[cfg(any(unix, windows))]
+use super::peers::ask;
+#[cfg(any(unix, windows))]
+use crate::node::control::Request;
+#[cfg(any(unix, windows))]
+use crate::node::control::Response;
+use crate::Context;
+
+/// Asks the node answering on the configured control socket for a snapshot
+/// of its network view, and writes it to `out` as JSON, or prints it.
+#[cfg(any(unix, windows))]
+pub async fn export_netstate(ctx: &Context, out: Option<&Path>) -> miette::Result<()> {
+    let state = match ask(ctx, &Request::NetworkState).await? {
+        Response::NetworkState(state) => state,
+        Response::Error(e) => miette::bail!("The node could not answer: {e}"),
+        _ => miette::bail!("The node answered something else than its network state"),
+    };
+    let json = serde_json::to_string_pretty(&state).into_diagnostic()?;
+
+    match out {
+        Some(out) => {
+            fs::write(out, json + "\n")
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not write {}", out.display()))?;
+            println!(
+                "Wrote {} peers and {} events to {}",
+                state.peers.len(),
+                state.events.len(),
+                out.display()
+            );
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub async fn export_netstate(_ctx: &Context, _out: Option<&Path>) -> miette::Result<()> {
+    Err(super::unsupported_platform("Querying"))
+}
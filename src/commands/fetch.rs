@@ -0,0 +1,64 @@
+use casper_hashing::Digest;
+use casper_types::TimeDiff;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+
+use super::bootstrap;
+use crate::network::fetch::Request;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Parses the hex-encoded hash of an item to fetch.
+pub fn parse_hash(hash: &str) -> miette::Result<Digest> {
+    Digest::from_hex(hash)
+        .map_err(|e| miette::miette!("{e:?}"))
+        .wrap_err_with(|| format!("Invalid hash {hash:?}"))
+}
+
+/// Joins the network through the configured bootnode and fetches the item of
+/// `request` from the peers it connects to, asking each in turn until one
+/// hands it out within `timeout`.
+///
+/// Schultz does not decode items, so the item is printed as the hex of the
+/// bytes the peer sent.
+pub async fn fetch(ctx: &Context, request: Request, timeout: TimeDiff) -> miette::Result<()> {
+    let node = bootstrap::join(ctx).await?;
+
+    // Answers are handed to the request by the event loop.
+    let event_loop = tokio::spawn({
+        let node = node.clone();
+        async move { node.keepalive().await }
+    });
+    let fetched = node.fetch(request, timeout.into()).await;
+    event_loop.abort();
+    let fetched = fetched.into_diagnostic()?;
+
+    let item = base16::encode_lower(&fetched.item);
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "tag": request.tag(),
+                "hash": request.hash(),
+                "peer": fetched.peer,
+                "bytes": fetched.item.len(),
+                "item": item,
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!(
+                "Fetched {} {} from {} ({} bytes)",
+                request.tag(),
+                request.hash(),
+                fetched.peer,
+                fetched.item.len()
+            );
+            println!("{item}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use casper_types::AsymmetricType;
+use casper_types::TimeDiff;
+use miette::IntoDiagnostic;
+use serde_json::json;
+use tracing::warn;
+
+use super::bootstrap;
+use crate::network::error::FinalityError;
+use crate::network::finality::BlockFinality;
+use crate::network::finality::FinalityCollector;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Joins the network through the configured bootnode and verifies the
+/// finality signatures peers gossip, fetching each from the peer gossiping it
+/// within `timeout`. Prints every signature counted and every block a quorum
+/// of the validators of its era signed. The switch blocks among those blocks
+/// and their parents tell the validators of every era after the chainspec's.
+///
+/// With `node.store_observations` set, the headers followed and signatures
+/// counted are recorded for `schultz db query`.
+pub async fn watch(ctx: &Context, timeout: TimeDiff) -> miette::Result<()> {
+    let node = bootstrap::join(ctx).await?;
+    let eras = node.eras();
+    let Some((era_id, weights)) = eras.current() else {
+        return Err(FinalityError::NoValidators(bootstrap::chainspec_path(ctx))).into_diagnostic();
+    };
+    let threshold = node.manager.read().await.chainspec.core_config.finality_threshold_fraction;
+    let collector = FinalityCollector::new(eras, threshold)
+        .on_signature({
+            let output_format = ctx.output_format.clone();
+            move |finality| print(&output_format, "signature", finality)
+        })
+        .on_quorum({
+            let (node, output_format) = (node.clone(), ctx.output_format.clone());
+            move |finality| {
+                print(&output_format, "quorum", finality);
+                if let Err(e) = node.finalize(finality.block_hash, finality.era_id) {
+                    warn!("Not marking block {} final: {e}", finality.block_hash);
+                }
+            }
+        });
+
+    if let OutputFormat::Table = ctx.output_format {
+        println!(
+            "Watching the finality signatures of {} validators of era {era_id}",
+            weights.len()
+        );
+    }
+    bootstrap::store_observations(ctx, &node)?;
+    tokio::spawn({
+        let node = node.clone();
+        async move { node.keepalive().await }
+    });
+    node.follow_headers(Arc::new(collector), timeout.into()).await;
+    Ok(())
+}
+
+fn print(output_format: &OutputFormat, event: &str, finality: &BlockFinality) {
+    match output_format {
+        OutputFormat::Json => {
+            let line = json!({ "event": event, "finality": finality });
+            println!("{line}");
+        }
+        OutputFormat::Table => println!(
+            "{event:<9}  {}  era {}  {} signers  weight {} of {}  by {}",
+            finality.block_hash,
+            finality.era_id,
+            finality.signers,
+            finality.signed_weight,
+            finality.total_weight,
+            finality.signer.to_hex()
+        ),
+    }
+}
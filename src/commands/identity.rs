@@ -0,0 +1,123 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde::Serialize;
+
+use crate::network::tls::cert_fingerprint;
+use crate::network::tls::cert_from_pem;
+use crate::network::tls::validate_peer_cert_detailed;
+use crate::network::tls::Certificate;
+use crate::network::tls::Identity;
+use crate::network::transport::TlsTransport;
+use crate::utils::Fingerprint;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Where a fingerprinted certificate came from.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Source<'a> {
+    File(&'a Path),
+    Peer(SocketAddr),
+}
+
+#[derive(Serialize)]
+struct Fingerprinted<'a> {
+    source: Source<'a>,
+    fingerprint: Fingerprint,
+}
+
+/// Prints the fingerprint of the certificate in the PEM file `cert`, or of the
+/// one presented by the peer at `connect`.
+pub async fn fingerprint(
+    ctx: &Context,
+    cert: Option<&Path>,
+    connect: Option<SocketAddr>,
+) -> miette::Result<()> {
+    let (source, cert) = load(ctx, cert, connect).await?;
+    let fingerprint = Fingerprinted {
+        source,
+        fingerprint: cert_fingerprint(&cert).into_diagnostic()?,
+    };
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&fingerprint).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => println!("{}", fingerprint.fingerprint),
+    }
+
+    Ok(())
+}
+
+/// Runs every check peers make on the certificate in `cert`, or the one
+/// presented by the peer at `connect`, and prints the outcome of each.
+pub async fn check(
+    ctx: &Context,
+    cert: Option<&Path>,
+    connect: Option<SocketAddr>,
+) -> miette::Result<()> {
+    let (_, cert) = load(ctx, cert, connect).await?;
+    let report = validate_peer_cert_detailed(&cert, &ctx.config.network.tls_options());
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            for (check, result) in report.results() {
+                match result {
+                    Ok(()) => println!("{check}\tok"),
+                    Err(error) => println!("{check}\tFAILED\t{error}"),
+                }
+            }
+        }
+    }
+
+    let failed = report.failures().count();
+    if failed > 0 {
+        miette::bail!(
+            "Certificate failed {failed} of {} checks",
+            report.results().len()
+        );
+    }
+    Ok(())
+}
+
+async fn load<'a>(
+    ctx: &Context,
+    cert: Option<&'a Path>,
+    connect: Option<SocketAddr>,
+) -> miette::Result<(Source<'a>, Certificate)> {
+    match (cert, connect) {
+        (Some(path), _) => Ok((Source::File(path), read_cert(path)?)),
+        (None, Some(addr)) => Ok((Source::Peer(addr), fetch_cert(ctx, addr).await?)),
+        (None, None) => miette::bail!("either a certificate file or --connect is required"),
+    }
+}
+
+fn read_cert(path: &Path) -> miette::Result<Certificate> {
+    let pem = std::fs::read(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    cert_from_pem(&pem)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("{} does not hold a PEM certificate", path.display()))
+}
+
+async fn fetch_cert(ctx: &Context, addr: SocketAddr) -> miette::Result<Certificate> {
+    // Peers only need to see some certificate, so a throwaway one will do.
+    let identity = Identity::with_generated_certs().into_diagnostic()?;
+    TlsTransport::new(identity, ctx.config.network.tls_options())
+        .peer_certificate(addr)
+        .await
+        .wrap_err_with(|| format!("Failed to fetch the certificate of {addr}"))
+}
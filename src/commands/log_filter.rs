@@ -0,0 +1,37 @@
+#[cfg(any(unix, windows))]
+use serde_json::json;
+
+#[cfg(any(unix, windows))]
+use super::peers::ask;
+#[cfg(any(unix, windows))]
+use crate::node::control::Request;
+#[cfg(any(unix, windows))]
+use crate::node::control::Response;
+use crate::Context;
+#[cfg(any(unix, windows))]
+use crate::OutputFormat;
+
+/// Asks the node answering on the configured control socket to filter its
+/// logs by `directives`, if given, and prints the filter it logs by.
+#[cfg(any(unix, windows))]
+pub async fn log_filter(ctx: &Context, directives: Option<&str>) -> miette::Result<()> {
+    let request = Request::LogFilter {
+        directives: directives.map(String::from),
+    };
+    let filter = match ask(ctx, &request).await? {
+        Response::LogFilter(filter) => filter,
+        Response::Error(e) => miette::bail!("The node kept its log filter: {e}"),
+        _ => miette::bail!("The node answered something else than its log filter"),
+    };
+
+    match ctx.output_format {
+        OutputFormat::Json => println!("{}", json!({ "log_filter": filter })),
+        OutputFormat::Table => println!("{filter}"),
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub async fn log_filter(_ctx: &Context, _directives: Option<&str>) -> miette::Result<()> {
+    Err(super::unsupported_platform("Changing the log filter of"))
+}
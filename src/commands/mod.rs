@@ -1 +1,31 @@
+pub mod bench;
+pub mod binary;
 pub mod bootstrap;
+pub mod chainspec;
+pub mod config;
+pub mod crawl;
+pub mod data_dir;
+pub mod db;
+pub mod doctor;
+pub mod events;
+pub mod export_netstate;
+pub mod fetch;
+pub mod finality;
+pub mod identity;
+pub mod log_filter;
+pub mod peers;
+pub mod reload;
+pub mod rpc;
+pub mod serve;
+pub mod stats;
+pub mod tap;
+pub mod transfer;
+pub mod wire_log;
+
+/// The error of commands asking a running node, which `action` describes.
+/// The control socket is a Unix socket or a named pipe, so there is nothing
+/// to ask elsewhere.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn unsupported_platform(action: &str) -> miette::Report {
+    miette::miette!("{action} a running node needs a control socket")
+}
@@ -0,0 +1,114 @@
+#[cfg(any(unix, windows))]
+use miette::IntoDiagnostic;
+#[cfg(any(unix, windows))]
+use miette::WrapErr;
+
+#[cfg(any(unix, windows))]
+use crate::geo;
+#[cfg(any(unix, windows))]
+use crate::geo::Distribution;
+#[cfg(any(unix, windows))]
+use crate::geo::Located;
+#[cfg(any(unix, windows))]
+use crate::network::manager::PeerInfo;
+#[cfg(any(unix, windows))]
+use crate::node::control;
+#[cfg(any(unix, windows))]
+use crate::node::control::Request;
+#[cfg(any(unix, windows))]
+use crate::node::control::Response;
+use crate::Context;
+#[cfg(any(unix, windows))]
+use crate::OutputFormat;
+
+/// Asks the node answering on the configured control socket for its peers
+/// and prints them, with where they are if GeoIP databases are configured.
+#[cfg(any(unix, windows))]
+pub async fn peers(ctx: &Context) -> miette::Result<()> {
+    let geo = geo::from_config(&ctx.config.node)?;
+    let peers = match ask(ctx, &Request::Peers).await? {
+        Response::Peers(peers) => peers,
+        Response::Error(e) => miette::bail!("The node could not answer: {e}"),
+        _ => miette::bail!("The node answered something else than its peers"),
+    };
+
+    let located: Vec<_> = peers
+        .iter()
+        .map(|peer| Located {
+            item: peer,
+            geo: geo.as_ref().map(|geo| geo.lookup(peer.addr.ip())),
+        })
+        .collect();
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&located).into_diagnostic()?
+            )
+        }
+        OutputFormat::Table => {
+            print_table(&located);
+            if geo.is_some() && !located.is_empty() {
+                Distribution::of(located.iter().flat_map(|peer| &peer.geo)).print_table();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub async fn peers(_ctx: &Context) -> miette::Result<()> {
+    Err(super::unsupported_platform("Querying"))
+}
+
+/// Sends `request` to the node answering on the configured control socket.
+#[cfg(any(unix, windows))]
+pub(crate) async fn ask(ctx: &Context, request: &Request) -> miette::Result<Response> {
+    let path = ctx.config.node.control_socket.as_deref().ok_or_else(|| {
+        miette::miette!(
+            "No control socket to ask, pass --control-socket or set node.control_socket in the \
+             config file"
+        )
+    })?;
+    control::request(path, request)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not ask the node on {}", path.display()))
+}
+
+#[cfg(any(unix, windows))]
+fn print_table(peers: &[Located<PeerInfo>]) {
+    if peers.is_empty() {
+        println!("No peers");
+        return;
+    }
+    println!(
+        "{:<24}{:<10}{:<10}{:<26}{:<26}{:>12}{:>12}  {:<10}{:<36}node",
+        "peer",
+        "direction",
+        "version",
+        "connected since",
+        "last seen",
+        "bytes in",
+        "bytes out",
+        "country",
+        "autonomous system"
+    );
+    for Located { item: peer, geo } in peers {
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<24}{:<10}{:<10}{:<26}{:<26}{:>12}{:>12}  {:<10}{:<36}{}",
+            peer.addr.to_string(),
+            peer.direction.to_string(),
+            or_dash(peer.protocol_version.map(|version| version.to_string())),
+            peer.connected_since.to_string(),
+            or_dash(peer.last_seen.map(|last_seen| last_seen.to_string())),
+            peer.bytes_read,
+            peer.bytes_written,
+            or_dash(geo.as_ref().map(|geo| geo.country_or_unknown().to_string())),
+            or_dash(geo.as_ref().map(|geo| geo.asn_or_unknown())),
+            or_dash(peer.node_id.map(|node_id| format!("{node_id:#}"))),
+        );
+    }
+}
@@ -0,0 +1,40 @@
+#[cfg(any(unix, windows))]
+use serde_json::json;
+
+#[cfg(any(unix, windows))]
+use super::peers::ask;
+#[cfg(any(unix, windows))]
+use crate::node::control::Request;
+#[cfg(any(unix, windows))]
+use crate::node::control::Response;
+use crate::Context;
+#[cfg(any(unix, windows))]
+use crate::OutputFormat;
+
+/// Asks the node answering on the configured control socket to reload its
+/// configuration, as SIGHUP does on Unix, and prints the settings that need
+/// a restart to apply.
+#[cfg(any(unix, windows))]
+pub async fn reload(ctx: &Context) -> miette::Result<()> {
+    let restart = match ask(ctx, &Request::Reload).await? {
+        Response::Reloaded(restart) => restart,
+        Response::Error(e) => miette::bail!("The node kept its configuration: {e}"),
+        _ => miette::bail!("The node answered something else than the reload"),
+    };
+
+    match ctx.output_format {
+        OutputFormat::Json => println!("{}", json!({ "restart": restart })),
+        OutputFormat::Table => {
+            println!("Reloaded the configuration");
+            for setting in &restart {
+                println!("{setting} changed, restart to apply it");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub async fn reload(_ctx: &Context) -> miette::Result<()> {
+    Err(super::unsupported_platform("Reloading"))
+}
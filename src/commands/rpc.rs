@@ -0,0 +1,60 @@
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::Value;
+
+use crate::http::Endpoint;
+use crate::rpc::block_params;
+use crate::rpc::BlockIdentifier;
+use crate::rpc::Method;
+use crate::rpc::RpcClient;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Calls `method` on the node serving the API at `node` and prints the result
+/// as the node returned it, except for peers and state root hashes, which are
+/// printed as a table unless JSON output was asked for.
+pub async fn rpc(
+    ctx: &Context,
+    method: Method,
+    node: Endpoint,
+    block: Option<BlockIdentifier>,
+) -> miette::Result<()> {
+    let client = RpcClient::new(node);
+    let failed = || format!("Calling {} on {} failed", method.name(), client.endpoint());
+    if block.is_some() && !matches!(method, Method::GetBlock | Method::GetStateRootHash) {
+        miette::bail!("{} does not take a block", method.name());
+    }
+
+    match (method, &ctx.output_format) {
+        (Method::GetPeers, OutputFormat::Table) => {
+            let peers = client.get_peers().await.into_diagnostic().wrap_err_with(failed)?;
+            for peer in &peers {
+                println!("{:<24}{}", peer.address, peer.node_id);
+            }
+            if peers.is_empty() {
+                println!("No peers");
+            }
+        }
+        (Method::GetStateRootHash, OutputFormat::Table) => {
+            let hash = client
+                .get_state_root_hash(block)
+                .await
+                .into_diagnostic()
+                .wrap_err_with(failed)?
+                .ok_or_else(|| miette::miette!("The node does not have the block"))?;
+            println!("{hash:x}");
+        }
+        _ => {
+            let result: Value = client
+                .call(method.name(), block_params(block))
+                .await
+                .into_diagnostic()
+                .wrap_err_with(failed)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&result).into_diagnostic()?
+            );
+        }
+    }
+    Ok(())
+}
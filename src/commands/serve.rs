@@ -0,0 +1,60 @@
+use futures::future::try_join_all;
+use miette::WrapErr;
+use serde_json::json;
+
+use super::bootstrap;
+use crate::network::progress::NoProgress;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Runs a node other nodes bootstrap from: it accepts every peer, answers its
+/// handshake and tells it about every peer it knows.
+///
+/// A configured bootnode is joined first, so several relays can form one
+/// network; without one the node waits for peers to come to it. With several
+/// networks selected, a node runs for each, isolated from the others.
+pub async fn serve(ctx: &Context) -> miette::Result<()> {
+    let contexts = ctx.per_network();
+    try_join_all(contexts.iter().map(|ctx| async move {
+        let served = serve_network(ctx).await;
+        match ctx.network_name() {
+            Some(network) => served.wrap_err_with(|| format!("Serving {network} failed")),
+            None => served,
+        }
+    }))
+    .await?;
+    Ok(())
+}
+
+async fn serve_network(ctx: &Context) -> miette::Result<()> {
+    let network = ctx.network_name();
+    let node = bootstrap::start_node(ctx).await?;
+
+    let bootnodes: Vec<_> = ctx.config.node.bootnode.iter().cloned().collect();
+    node.bootstrap(&bootnodes, &NoProgress)
+        .await
+        .wrap_err("Could not join the network through the bootnode")?;
+
+    let (addr, fingerprint) = {
+        let manager = node.manager.read().await;
+        (
+            manager.schultz_addr(),
+            manager.identity().fingerprint().to_string(),
+        )
+    };
+    match (&ctx.output_format, network) {
+        (OutputFormat::Json, None) => {
+            println!("{}", json!({ "addr": addr, "fingerprint": fingerprint }))
+        }
+        (OutputFormat::Json, Some(network)) => println!(
+            "{}",
+            json!({ "network": network, "addr": addr, "fingerprint": fingerprint })
+        ),
+        (OutputFormat::Table, None) => println!("Serving on {addr} as {fingerprint}"),
+        (OutputFormat::Table, Some(network)) => {
+            println!("Serving {network} on {addr} as {fingerprint}")
+        }
+    }
+
+    bootstrap::run(ctx, node).await
+}
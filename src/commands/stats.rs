@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+
+use crate::node::snapshots::Interval;
+use crate::node::snapshots::Snapshot;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Prints what happened between consecutive metrics snapshots in `dir`, the
+/// metrics directory of the selected network by default.
+pub fn stats(ctx: &Context, dir: Option<&Path>) -> miette::Result<()> {
+    let dir = dir.map(Path::to_path_buf).unwrap_or_else(|| ctx.metrics_dir());
+    let snapshots = Snapshot::read_all(&dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not read the snapshots in {}", dir.display()))?;
+    let intervals = Interval::all(&snapshots);
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&intervals).into_diagnostic()?
+            )
+        }
+        OutputFormat::Table => print_table(&intervals),
+    }
+    Ok(())
+}
+
+fn print_table(intervals: &[Interval]) {
+    if intervals.is_empty() {
+        println!("Fewer than two snapshots, set node.metrics_interval and let the node run");
+        return;
+    }
+    println!(
+        "{:<26}{:>10}{:>8}{:>8}{:>12}{:>8}{:>10}{:>14}{:>14}",
+        "until",
+        "over",
+        "opened",
+        "closed",
+        "handshakes",
+        "failed",
+        "failure",
+        "bytes in",
+        "bytes out"
+    );
+    for interval in intervals {
+        let failure_rate = interval
+            .handshake_failure_rate()
+            .map(|rate| format!("{:.1}%", rate * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<26}{:>10}{:>8}{:>8}{:>12}{:>8}{:>10}{:>14}{:>14}",
+            interval.to.to_string(),
+            (interval.to.saturating_diff(interval.from)).to_string(),
+            interval.connections_opened,
+            interval.connections_closed,
+            interval.handshakes,
+            interval.handshakes_failed,
+            failure_rate,
+            interval.bytes_read,
+            interval.bytes_written,
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use casper_types::EraId;
+use casper_types::Timestamp;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use super::bootstrap;
+use crate::network::observe::Observed;
+use crate::Context;
+use crate::OutputFormat;
+
+/// A gossip message as written to the output.
+#[derive(Serialize)]
+struct Line<'a> {
+    time: Timestamp,
+    /// The latest era whose validators are known when the message arrives.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    era_id: Option<EraId>,
+    #[serde(flatten)]
+    observed: &'a Observed,
+}
+
+/// Joins the network through the configured bootnode and stays connected,
+/// printing every gossip message peers send: blocks, deploys, finality
+/// signatures and addresses. With `output`, the messages are appended to that
+/// file as JSON lines instead.
+///
+/// Schultz gossips its own address and keeps dialing the peers it learns
+/// about, so it sees as much of the network as a node would, but it never
+/// answers for an item, so it does not take part in spreading them.
+///
+/// With `node.store_observations` set, the deploys gossiped are recorded for
+/// `schultz db query`, and the header chain is followed so that the headers
+/// and finality signatures are too. Following the chain also tells the era
+/// of every message, which the switch blocks settled move forward. With
+/// `node.ws_addr` set, what is printed is streamed to WebSocket clients as
+/// well.
+pub async fn tap(ctx: &Context, output: Option<&Path>) -> miette::Result<()> {
+    let mut file = output
+        .map(|path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not open {}", path.display()))
+        })
+        .transpose()?;
+
+    let node = bootstrap::join(ctx).await?;
+    let eras = node.eras();
+    let mut observed = node.manager.read().await.observe();
+    if ctx.config.node.store_observations || ctx.config.node.follow_headers {
+        bootstrap::follow_headers(&node).await;
+    }
+    bootstrap::store_observations(ctx, &node)?;
+    bootstrap::serve_ws(ctx, &node).await?;
+    tokio::spawn({
+        let node = node.clone();
+        async move { node.keepalive().await }
+    });
+
+    loop {
+        let observed = match observed.recv().await {
+            Ok(observed) => observed,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Missed {missed} messages, the output could not keep up");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        let line = Line {
+            time: Timestamp::now(),
+            era_id: eras.current().map(|(era_id, _)| era_id),
+            observed: &observed,
+        };
+        match (&mut file, &ctx.output_format) {
+            (Some(file), _) => {
+                let json = serde_json::to_string(&line).into_diagnostic()?;
+                writeln!(file, "{json}").into_diagnostic()?;
+            }
+            (None, OutputFormat::Json) => {
+                println!("{}", serde_json::to_string(&line).into_diagnostic()?);
+            }
+            (None, OutputFormat::Table) => match line.era_id {
+                Some(era_id) => println!("{} era {era_id} {observed}", line.time),
+                None => println!("{} {observed}", line.time),
+            },
+        }
+    }
+}
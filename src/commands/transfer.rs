@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use casper_types::AsymmetricType;
+use casper_types::SecretKey;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde_json::json;
+
+use super::bootstrap;
+use crate::network::deploy::Deploy;
+use crate::network::deploy::Transfer;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Joins the network through the configured bootnode, signs `transfer` with
+/// the secret key at `secret_key` and gossips it to the peers connected to.
+///
+/// Peers that lack the deploy ask for it, and are handed it for `wait`.
+/// Whether the transfer is executed is up to the network; follow the deploy
+/// by its hash, e.g. with `schultz fetch deploy`.
+pub async fn transfer(
+    ctx: &Context,
+    transfer: &Transfer,
+    secret_key: &Path,
+    wait: TimeDiff,
+) -> miette::Result<()> {
+    let secret_key = SecretKey::from_file(secret_key)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not load the secret key {}", secret_key.display()))?;
+    let node = bootstrap::join(ctx).await?;
+    let chain_name = node.manager.read().await.chainspec.network_config.name.clone();
+    let deploy =
+        Deploy::transfer(transfer, &chain_name, &secret_key, Timestamp::now()).into_diagnostic()?;
+    let account = deploy.account().to_hex();
+
+    // Requests for the deploy are answered by the event loop.
+    let event_loop = tokio::spawn({
+        let node = node.clone();
+        async move { node.keepalive().await }
+    });
+    let gossiped = node.gossip_deploy(deploy, wait.into()).await;
+    event_loop.abort();
+    let gossiped = gossiped.into_diagnostic()?;
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "hash": gossiped.hash,
+                "chain_name": chain_name,
+                "account": account,
+                "target": transfer.target.to_hex(),
+                "amount": transfer.amount.to_string(),
+                "id": transfer.id,
+                "gossiped_to": gossiped.gossiped_to,
+                "handed_to": gossiped.handed_to,
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            println!("hash         {}", gossiped.hash);
+            println!("account      {account}");
+            println!("target       {}", transfer.target.to_hex());
+            println!("amount       {}", transfer.amount);
+            println!("id           {}", transfer.id);
+            println!("gossiped to  {} peers", gossiped.gossiped_to.len());
+            println!("handed to    {} peers", gossiped.handed_to.len());
+        }
+    }
+    Ok(())
+}
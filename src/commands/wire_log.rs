@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use prometheus::Registry;
+use serde::Serialize;
+use serde_json::json;
+
+use super::bootstrap;
+use crate::network::connection::Direction;
+use crate::network::gossip::NodePayload;
+use crate::network::handshake::Handshake;
+use crate::network::manager::Manager;
+use crate::network::replay;
+use crate::network::replay::Script;
+use crate::network::resolve::Bootnode;
+use crate::network::tls::Identity;
+use crate::network::wire_log::Capture;
+use crate::network::wire_log::Record;
+use crate::node::CHANNEL_SIZE;
+use crate::primitives::Chainspec;
+use crate::Context;
+use crate::OutputFormat;
+
+/// Reads every record of the capture at `path`.
+fn records(path: &Path) -> miette::Result<impl Iterator<Item = miette::Result<Record>> + '_> {
+    let capture = Capture::open(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not read wire log {}", path.display()))?;
+    Ok(capture.map(move |record| {
+        record
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not read wire log {}", path.display()))
+    }))
+}
+
+/// Frames and bytes exchanged.
+#[derive(Default, Serialize)]
+struct Totals {
+    frames: usize,
+    bytes: usize,
+}
+
+impl Totals {
+    fn add(&mut self, record: &Record) {
+        self.frames += 1;
+        self.bytes += record.bytes.len();
+    }
+}
+
+/// Prints what the capture at `path` holds: when it was taken, and the frames
+/// exchanged with every peer and of every kind.
+pub fn inspect(ctx: &Context, path: &Path) -> miette::Result<()> {
+    let mut total = Totals::default();
+    let mut span = None;
+    let mut peers: BTreeMap<(SocketAddr, Direction), Totals> = BTreeMap::new();
+    let mut tags: BTreeMap<String, Totals> = BTreeMap::new();
+    for record in records(path)? {
+        let record = record?;
+        total.add(&record);
+        peers.entry((record.peer, record.direction)).or_default().add(&record);
+        tags.entry(record.tag.clone()).or_default().add(&record);
+        span = match span {
+            None => Some((record.timestamp, record.timestamp)),
+            Some((first, _)) => Some((first, record.timestamp)),
+        };
+    }
+
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let peers: Vec<_> = peers
+                .iter()
+                .map(|((peer, direction), totals)| {
+                    json!({
+                        "peer": peer,
+                        "direction": direction,
+                        "frames": totals.frames,
+                        "bytes": totals.bytes,
+                    })
+                })
+                .collect();
+            let output = json!({
+                "frames": total.frames,
+                "bytes": total.bytes,
+                "first": span.map(|(first, _)| first),
+                "last": span.map(|(_, last)| last),
+                "peers": peers,
+                "tags": tags,
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => {
+            let Some((first, last)) = span else {
+                println!("No frames recorded");
+                return Ok(());
+            };
+            println!(
+                "{} frames, {} bytes, from {first} to {last}",
+                total.frames, total.bytes
+            );
+            println!();
+            println!("{:<24}{:<4}{:>9}{:>12}", "peer", "", "frames", "bytes");
+            for ((peer, direction), totals) in &peers {
+                println!(
+                    "{:<24}{:<4}{:>9}{:>12}",
+                    peer.to_string(),
+                    arrow(*direction),
+                    totals.frames,
+                    totals.bytes
+                );
+            }
+            println!();
+            println!("{:<40}{:>9}{:>12}", "tag", "frames", "bytes");
+            for (tag, totals) in &tags {
+                println!("{tag:<40}{:>9}{:>12}", totals.frames, totals.bytes);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints every frame of the capture at `path` in the order it passed,
+/// decoded as far as schultz understands it, optionally only those exchanged
+/// with `peer`.
+pub fn replay(ctx: &Context, path: &Path, peer: Option<SocketAddr>) -> miette::Result<()> {
+    for record in records(path)? {
+        let record = record?;
+        if peer.is_some_and(|peer| peer != record.peer) {
+            continue;
+        }
+        let decoded = record.decode();
+        match ctx.output_format {
+            OutputFormat::Json => {
+                let line = json!({
+                    "timestamp": record.timestamp,
+                    "direction": record.direction,
+                    "peer": record.peer,
+                    "tag": record.tag,
+                    "message": decoded.to_string(),
+                    "bytes": base16::encode_lower(&record.bytes),
+                });
+                println!("{line}");
+            }
+            OutputFormat::Table => println!(
+                "{} {} {} {} ({} bytes): {decoded}",
+                record.timestamp,
+                arrow(record.direction),
+                record.peer,
+                record.tag,
+                record.bytes.len()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Which way a frame passed, as tables show it.
+fn arrow(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Inbound => "<-",
+        Direction::Outbound => "->",
+    }
+}
+
+/// Loads the chainspec at `path`, or the one the node is configured with.
+fn chainspec(ctx: &Context, path: Option<&Path>) -> miette::Result<Chainspec> {
+    let path = path.map_or_else(|| bootstrap::chainspec_path(ctx), Path::to_path_buf);
+    Chainspec::from_path(&path)
+        .wrap_err_with(|| format!("Failed to load chainspec from {}", path.display()))
+}
+
+/// Prints the handshake `peer` sent, as the outcome of `action`.
+fn print_handshake(
+    ctx: &Context,
+    action: &str,
+    peer: SocketAddr,
+    handshake: &Handshake,
+) -> miette::Result<()> {
+    match ctx.output_format {
+        OutputFormat::Json => {
+            let output = json!({
+                "peer": peer,
+                "network_name": handshake.network_name,
+                "public_addr": handshake.public_addr,
+                "protocol_version": handshake.protocol_version.to_string(),
+                "chainspec_hash": handshake.chainspec_hash.map(|hash| hash.to_string()),
+            });
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).into_diagnostic()?
+            );
+        }
+        OutputFormat::Table => println!(
+            "{action} with {peer}: {} on {} running {}",
+            handshake.public_addr, handshake.network_name, handshake.protocol_version
+        ),
+    }
+    Ok(())
+}
+
+/// Connects to `target`, handshakes with it and disconnects again, adding
+/// the frames exchanged to the capture at `out`.
+///
+/// Captures of real casper-nodes are what [`conform`] replays, so they are
+/// worth keeping whenever the node's wire format may have changed.
+pub async fn record(
+    ctx: &Context,
+    target: &Bootnode,
+    out: &Path,
+    chainspec: Option<&Path>,
+) -> miette::Result<()> {
+    let addrs = target.resolve().await.wrap_err_with(|| format!("Could not resolve {target}"))?;
+    let chainspec = self::chainspec(ctx, chainspec)?;
+    let identity = Identity::with_generated_certs().into_diagnostic()?;
+    let ip = ctx.config.node.addr.map_or(IpAddr::V4(Ipv4Addr::LOCALHOST), |addr| addr.ip());
+    let mut config = ctx.config.network.clone();
+    config.wire_log = Some(out.to_path_buf());
+
+    let (event_tx, _event_rx) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+    let manager = Manager::with_identity::<NodePayload>(
+        identity,
+        SocketAddr::new(ip, 0),
+        event_tx,
+        chainspec,
+        config,
+        &Registry::new(),
+    )
+    .await
+    .wrap_err("Could not start the network")?;
+    let peer = manager
+        .connect_any(&addrs, &|_| {})
+        .await
+        .wrap_err_with(|| format!("Could not connect to {target}"))?;
+    let handshake = manager.handshake::<NodePayload>(peer).await;
+    manager.disconnect(peer).await;
+    let handshake = handshake.wrap_err_with(|| format!("Could not handshake with {peer}"))?;
+    print_handshake(ctx, "Recorded handshake", peer, &handshake)
+}
+
+/// Replays the handshake `peer`, or the first peer, sent in the capture at
+/// `path` against ours, failing if ours no longer accepts it or no longer
+/// answers it the way it was recorded.
+pub async fn conform(
+    ctx: &Context,
+    path: &Path,
+    peer: Option<SocketAddr>,
+    chainspec: Option<&Path>,
+) -> miette::Result<()> {
+    let capture = Capture::open(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not read wire log {}", path.display()))?;
+    let script = Script::from_records(capture, peer)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not replay wire log {}", path.display()))?;
+    let chainspec = self::chainspec(ctx, chainspec)?;
+    // The replayed frames are not worth a capture of their own.
+    let mut config = ctx.config.network.clone();
+    config.wire_log = None;
+    let handshake = replay::check_handshake(&script, chainspec, config)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("The handshake of {} no longer completes", script.peer()))?;
+    print_handshake(ctx, "Replayed handshake", script.peer(), &handshake)
+}
@@ -0,0 +1,411 @@
+//! The `schultz.toml` configuration file.
+//!
+//! Every setting can be given in the file, through an environment variable or
+//! on the command line. Command-line flags win over environment variables,
+//! which win over the file, which wins over the built-in defaults.
+//!
+//! A file may also describe several networks, e.g. mainnet and testnet, each
+//! a `[networks.<name>]` table overriding whatever differs from the rest of
+//! the file:
+//!
+//! ```toml
+//! [networks.testnet.node]
+//! chainspec = "testnet"
+//!
+//! [networks.testnet.network]
+//! identity_dir = "identity-testnet"
+//! ```
+//!
+//! `--network testnet` then runs against that network.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_hashing::Digest;
+use casper_types::EraId;
+use casper_types::TimeDiff;
+use miette::Diagnostic;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::network;
+use crate::network::memory::ByteSize;
+use crate::network::resolve::Bootnode;
+use crate::node::export::StatsdFormat;
+
+/// Name of the configuration file looked up in the root directory.
+pub const CONFIG_FILE_NAME: &str = "schultz.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Invalid configuration in {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("No network {0:?} in the config file, it has {1:?}")]
+    UnknownNetwork(String, Vec<String>),
+    #[error("Invalid configuration for network {0:?}")]
+    Network(String, #[source] toml::de::Error),
+}
+
+impl Diagnostic for ConfigError {}
+
+/// Settings of the node started by `bootstrap` or `serve`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Address to listen on.
+    pub addr: Option<SocketAddr>,
+    /// Casper node to bootstrap from, given by name or IP address.
+    pub bootnode: Option<Bootnode>,
+    /// Directory holding the chainspec, or one directory per protocol
+    /// version as casper-node lays them out. Of those the one active in
+    /// `era` is used, or the latest if the era is unknown.
+    pub chainspec: Option<PathBuf>,
+    /// Era the network is in, telling which protocol version of the
+    /// chainspec directory to run until a switch block tells a later one.
+    pub era: Option<EraId>,
+    /// Address to serve `/health` and `/status` on.
+    pub status_addr: Option<SocketAddr>,
+    /// Address to stream network events and gossip to WebSocket clients on.
+    pub ws_addr: Option<SocketAddr>,
+    /// Unix socket, or named pipe on Windows, to answer queries like
+    /// `schultz peers` on.
+    pub control_socket: Option<PathBuf>,
+    /// Address to answer the same queries on over gRPC, with the `grpc`
+    /// feature. Only a loopback address, unless `grpc_token_file` is set.
+    pub grpc_addr: Option<SocketAddr>,
+    /// File holding the token gRPC clients have to send to connect or
+    /// disconnect peers. Without one, they cannot.
+    pub grpc_token_file: Option<PathBuf>,
+    /// How often to write a metrics snapshot to the data directory for
+    /// `schultz stats`, never if unset.
+    pub metrics_interval: Option<TimeDiff>,
+    /// Whether to follow and check the chain of the blocks peers gossip,
+    /// reporting its tip on `/status`.
+    pub follow_headers: bool,
+    /// Block the header chain followed is anchored to, rather than to the
+    /// first header a peer sends, mirroring casper-node's `trusted_hash`.
+    pub trusted_hash: Option<Digest>,
+    /// Whether to record the headers, deploys and finality signatures
+    /// observed in the data directory, for `schultz db query`.
+    pub store_observations: bool,
+    /// How long recorded observations are kept, forever if unset.
+    pub store_retention: Option<TimeDiff>,
+    /// Recorded observations kept of each kind, the oldest are pruned first.
+    pub store_max_entries: Option<u64>,
+    /// Estimated memory the message queues may hold before the node sheds
+    /// peers, unlimited if unset.
+    pub max_memory: Option<ByteSize>,
+    /// MaxMind Country or City database to tell the country of peers by,
+    /// with the `geoip` feature.
+    pub geoip_db: Option<PathBuf>,
+    /// MaxMind ASN database to tell the autonomous system of peers by, with
+    /// the `geoip` feature.
+    pub geoip_asn_db: Option<PathBuf>,
+}
+
+/// Where logs, traces and metrics go.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// OTLP/gRPC endpoint to export traces to.
+    pub otlp_endpoint: Option<String>,
+    /// Prometheus pushgateway to push metrics to.
+    pub pushgateway: Option<String>,
+    /// Host and port of a statsd daemon to send metrics to.
+    pub statsd_addr: Option<String>,
+    /// Whether statsd gets labels in the name or, dogstatsd, as tags.
+    pub statsd_format: StatsdFormat,
+    /// How often metrics are pushed, every 15 seconds if unset.
+    pub push_interval: Option<TimeDiff>,
+}
+
+/// Settings of one of several networks, overriding those of the rest of the
+/// file. Tables are merged, everything else replaced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NetworkOverrides(toml::value::Table);
+
+// Settings are compared as they are written, a float that is not equal to
+// itself is not a valid setting.
+impl Eq for NetworkOverrides {}
+
+/// The complete schultz configuration.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub node: NodeConfig,
+    pub telemetry: TelemetryConfig,
+    pub network: network::Config,
+    /// Named networks, selected with `--network`.
+    pub networks: BTreeMap<String, NetworkOverrides>,
+}
+
+impl Config {
+    /// The configuration of network `name`: this one with the overrides of
+    /// `[networks.<name>]` applied. The result describes no networks itself.
+    pub fn for_network(&self, name: &str) -> Result<Config, ConfigError> {
+        let overrides = self.networks.get(name).ok_or_else(|| {
+            ConfigError::UnknownNetwork(name.to_string(), self.networks.keys().cloned().collect())
+        })?;
+        let base = Config {
+            networks: BTreeMap::new(),
+            ..self.clone()
+        };
+        let mut merged = toml::Value::try_from(base).expect("configurations serialize");
+        merge(&mut merged, toml::Value::Table(overrides.0.clone()));
+        merged.try_into().map_err(|e| ConfigError::Network(name.to_string(), e))
+    }
+
+    /// Names of the settings outside the network section that differ in
+    /// `new`. None of them can change while the node runs.
+    pub fn restart_required(&self, new: &Config) -> Vec<&'static str> {
+        let NodeConfig {
+            addr,
+            bootnode,
+            chainspec,
+            era,
+            status_addr,
+            ws_addr,
+            control_socket,
+            grpc_addr,
+            grpc_token_file,
+            metrics_interval,
+            follow_headers,
+            trusted_hash,
+            store_observations,
+            store_retention,
+            store_max_entries,
+            max_memory,
+            // Only read by the commands listing peers, never by a node.
+            geoip_db: _,
+            geoip_asn_db: _,
+        } = &new.node;
+        let TelemetryConfig {
+            otlp_endpoint,
+            pushgateway,
+            statsd_addr,
+            statsd_format,
+            push_interval,
+        } = &new.telemetry;
+
+        let restart = [
+            ("node.addr", self.node.addr == *addr),
+            ("node.bootnode", self.node.bootnode == *bootnode),
+            ("node.chainspec", self.node.chainspec == *chainspec),
+            ("node.era", self.node.era == *era),
+            ("node.status_addr", self.node.status_addr == *status_addr),
+            ("node.ws_addr", self.node.ws_addr == *ws_addr),
+            (
+                "node.control_socket",
+                self.node.control_socket == *control_socket,
+            ),
+            ("node.grpc_addr", self.node.grpc_addr == *grpc_addr),
+            (
+                "node.grpc_token_file",
+                self.node.grpc_token_file == *grpc_token_file,
+            ),
+            (
+                "node.metrics_interval",
+                self.node.metrics_interval == *metrics_interval,
+            ),
+            (
+                "node.follow_headers",
+                self.node.follow_headers == *follow_headers,
+            ),
+            ("node.trusted_hash", self.node.trusted_hash == *trusted_hash),
+            (
+                "node.store_observations",
+                self.node.store_observations == *store_observations,
+            ),
+            (
+                "node.store_retention",
+                self.node.store_retention == *store_retention,
+            ),
+            (
+                "node.store_max_entries",
+                self.node.store_max_entries == *store_max_entries,
+            ),
+            ("node.max_memory", self.node.max_memory == *max_memory),
+            (
+                "telemetry.otlp_endpoint",
+                self.telemetry.otlp_endpoint == *otlp_endpoint,
+            ),
+            (
+                "telemetry.pushgateway",
+                self.telemetry.pushgateway == *pushgateway,
+            ),
+            (
+                "telemetry.statsd_addr",
+                self.telemetry.statsd_addr == *statsd_addr,
+            ),
+            (
+                "telemetry.statsd_format",
+                self.telemetry.statsd_format == *statsd_format,
+            ),
+            (
+                "telemetry.push_interval",
+                self.telemetry.push_interval == *push_interval,
+            ),
+        ];
+        restart
+            .into_iter()
+            .filter(|(_, unchanged)| !unchanged)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Reads a configuration file. Settings missing from it keep their
+    /// defaults.
+    pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Reads `path` if it exists, falling back to the defaults otherwise.
+    pub fn from_optional_path(path: &Path) -> Result<Self, ConfigError> {
+        if path.exists() {
+            Self::from_path(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+/// Merges `overrides` into `value`, table by table.
+fn merge(value: &mut toml::Value, overrides: toml::Value) {
+    match (value, overrides) {
+        (toml::Value::Table(table), toml::Value::Table(overrides)) => {
+            for (key, overriding) in overrides {
+                match table.get_mut(&key) {
+                    Some(value) => merge(value, overriding),
+                    None => {
+                        table.insert(key, overriding);
+                    }
+                }
+            }
+        }
+        (value, overriding) => *value = overriding,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::TimeDiff;
+
+    use super::*;
+    use crate::network::compression::Compression;
+
+    #[test]
+    fn example_config_overrides_defaults() {
+        let config = Config::from_path(Path::new("examples/schultz.toml")).unwrap();
+
+        assert_eq!(config.node.addr, Some("127.0.0.1:5001".parse().unwrap()));
+        assert_eq!(
+            config.node.bootnode,
+            Some("127.0.0.1:34553".parse().unwrap())
+        );
+        assert_eq!(config.node.chainspec, Some(PathBuf::from("examples")));
+        assert_eq!(config.network.ping_interval, TimeDiff::from_seconds(10));
+        assert_eq!(config.network.compression, vec![Compression::Lz4]);
+        // Commented out in the file.
+        assert_eq!(config.node.status_addr, None);
+        assert_eq!(config.network.max_bandwidth, None);
+    }
+
+    #[test]
+    fn printed_config_reads_back() {
+        let config = Config::from_path(Path::new("examples/schultz.toml")).unwrap();
+        let printed = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&printed).unwrap(), config);
+    }
+
+    #[test]
+    fn reload_applies_limits_and_reports_the_rest() {
+        let config = Config::from_path(Path::new("examples/schultz.toml")).unwrap();
+        let mut new = config.clone();
+        new.node.status_addr = Some("127.0.0.1:9000".parse().unwrap());
+        new.network.max_connections = 3;
+        new.network.connect_timeout = TimeDiff::from_seconds(1);
+        new.network.ping_interval = TimeDiff::from_seconds(1);
+        new.network.compression.clear();
+
+        assert_eq!(config.restart_required(&new), vec!["node.status_addr"]);
+        let mut network = config.network.clone();
+        assert_eq!(
+            network.reload(&new.network),
+            vec!["ping_interval", "compression"]
+        );
+        assert_eq!(network.max_connections, 3);
+        assert_eq!(network.connect_timeout, TimeDiff::from_seconds(1));
+        assert_eq!(network.ping_interval, config.network.ping_interval);
+        assert!(network.reload(&config.network).is_empty());
+        assert_eq!(network, config.network);
+    }
+
+    #[test]
+    fn networks_override_the_rest_of_the_file() {
+        let config: Config = toml::from_str(
+            r#"
+            [node]
+            addr = "127.0.0.1:5001"
+            chainspec = "mainnet"
+
+            [network]
+            max_connections = 8
+
+            [networks.testnet.node]
+            chainspec = "testnet"
+
+            [networks.testnet.network]
+            identity_dir = "identity-testnet"
+
+            [networks.testnet.network.cert_subject]
+            common_name = "testnet-relay"
+
+            [networks.broken.network]
+            max_connections = "many"
+            "#,
+        )
+        .unwrap();
+
+        let testnet = config.for_network("testnet").unwrap();
+        assert!(testnet.networks.is_empty());
+        assert_eq!(testnet.node.addr, config.node.addr);
+        assert_eq!(testnet.node.chainspec, Some(PathBuf::from("testnet")));
+        assert_eq!(testnet.network.max_connections, 8);
+        assert_eq!(
+            testnet.network.identity_dir,
+            Some(PathBuf::from("identity-testnet"))
+        );
+        assert_eq!(testnet.network.cert_subject.common_name, "testnet-relay");
+        assert_eq!(
+            testnet.network.cert_subject.org,
+            config.network.cert_subject.org
+        );
+
+        assert!(matches!(
+            config.for_network("devnet"),
+            Err(ConfigError::UnknownNetwork(name, known)) if name == "devnet" && known.len() == 2
+        ));
+        assert!(matches!(
+            config.for_network("broken"),
+            Err(ConfigError::Network(name, _)) if name == "broken"
+        ));
+
+        let printed = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&printed).unwrap(), config);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(toml::from_str::<Config>("[network]\nping_intervall = \"5s\"").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! The consensus keypair a validator is known by.
+//!
+//! Casper nodes carry two identities: the TLS identity every connection is
+//! secured with, see [`Identity`](crate::network::tls::Identity), and, for
+//! validators, the Ed25519 or secp256k1 keypair they sign consensus messages
+//! with. The latter is what the consensus certificate of a handshake proves
+//! to hold, by signing the TLS session the handshake is sent on.
+
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use casper_types::crypto;
+use casper_types::crypto::ErrorExt;
+use casper_types::PublicKey;
+use casper_types::SecretKey;
+use casper_types::Timestamp;
+use rand::RngCore;
+use thiserror::Error;
+
+use crate::network::message::ConsensusCertificate;
+use crate::network::message::HandshakeStamp;
+use crate::network::message::StampedHandshake;
+use crate::network::tls::SessionId;
+use crate::primitives::Nonce;
+
+#[derive(Debug, Error)]
+pub enum ConsensusKeyError {
+    #[error("Could not load the consensus key {}", .0.display())]
+    Load(PathBuf, #[source] ErrorExt),
+}
+
+/// A validator's consensus keypair.
+#[derive(Clone)]
+pub struct ConsensusKeys {
+    secret_key: Arc<SecretKey>,
+    public_key: PublicKey,
+}
+
+impl ConsensusKeys {
+    /// Loads the secret key from a `secret_key.pem` in the format Casper
+    /// nodes and casper-client write it, Ed25519 or secp256k1.
+    pub fn load(path: &Path) -> Result<Self, ConsensusKeyError> {
+        let secret_key = SecretKey::from_file(path)
+            .map_err(|e| ConsensusKeyError::Load(path.to_path_buf(), e))?;
+        Ok(Self::new(secret_key))
+    }
+
+    pub fn new(secret_key: SecretKey) -> Self {
+        let public_key = PublicKey::from(&secret_key);
+        Self {
+            secret_key: Arc::new(secret_key),
+            public_key,
+        }
+    }
+
+    pub fn public_key(&self) -> &PublicKey { &self.public_key }
+
+    /// Signs the TLS session `session_id`, proving to the peer on the other
+    /// end of it that we hold the secret key.
+    pub fn certify(&self, session_id: &SessionId) -> ConsensusCertificate {
+        let signature = crypto::sign(session_id.as_bytes(), &self.secret_key, &self.public_key);
+        ConsensusCertificate::new(self.public_key.clone(), signature)
+    }
+
+    /// Stamps `handshake`, made now, with a random nonce, so peers can tell
+    /// it is neither old, replayed nor taken from another session.
+    pub fn stamp(&self, handshake: &StampedHandshake) -> HandshakeStamp {
+        let nonce = Nonce::new(rand::thread_rng().next_u64());
+        let timestamp = Timestamp::now();
+        let bytes = HandshakeStamp::signed_bytes(nonce, timestamp, handshake);
+        let signature = crypto::sign(bytes, &self.secret_key, &self.public_key);
+        HandshakeStamp::new(nonce, timestamp, signature)
+    }
+}
+
+impl Debug for ConsensusKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConsensusKeys")
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chainspec_dir;
+
+    #[test]
+    fn casper_secret_keys_load_and_certify_sessions() {
+        let keys = ConsensusKeys::load(&chainspec_dir().join("secret_key.pem")).unwrap();
+        let public_key = PublicKey::from_file(chainspec_dir().join("public_key.pem")).unwrap();
+        assert_eq!(keys.public_key(), &public_key);
+
+        let session = SessionId::from_bytes([7; 32]);
+        let certificate = keys.certify(&session);
+        assert_eq!(certificate.public_key(), &public_key);
+        certificate.validate(&session).unwrap();
+        assert!(certificate.validate(&SessionId::from_bytes([8; 32])).is_err());
+    }
+
+    #[test]
+    fn secp256k1_keys_load_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret_key.pem");
+        let secret_key = SecretKey::generate_secp256k1().unwrap();
+        secret_key.to_file(&path).unwrap();
+
+        let keys = ConsensusKeys::load(&path).unwrap();
+        assert_eq!(keys.public_key(), &PublicKey::from(&secret_key));
+        std::fs::remove_file(&path).unwrap();
+
+        let error = ConsensusKeys::load(&path).unwrap_err();
+        assert!(matches!(error, ConsensusKeyError::Load(..)));
+    }
+}
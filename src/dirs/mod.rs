@@ -22,6 +22,7 @@ pub fn ensure_root_dir(explicit: Option<&Path>) -> miette::Result<PathBuf> {
     Ok(defined)
 }
 
+#[derive(Clone)]
 pub struct Dirs {
     pub root_dir: PathBuf,
 }
@@ -1,15 +1,37 @@
+use std::fmt::Display;
+
+use miette::Diagnostic;
 use thiserror::Error;
 
 use crate::network::error::ManagerError;
+use crate::network::progress::BootstrapError;
+use crate::node::peers::PeerStoreError;
+use crate::primitives::ChainspecError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Error from the network module {0:?}")]
+    #[error("Error from the network module: {0}")]
     NetworkManager(ManagerError),
+    #[error(transparent)]
+    Bootstrap(#[from] BootstrapError),
+    #[error(transparent)]
+    PeerStore(#[from] PeerStoreError),
+    #[error("Could not load the chainspec: {0}")]
+    Chainspec(#[from] ChainspecError),
 }
 
 impl From<ManagerError> for Error {
     fn from(err: ManagerError) -> Self { Error::NetworkManager(err) }
 }
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match self {
+            Error::NetworkManager(error) => Some(Box::new(error.code())),
+            Error::Bootstrap(error) => Some(Box::new(error.source.code())),
+            Error::PeerStore(_) | Error::Chainspec(_) => None,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Exit codes of the `schultz` binary.
+//!
+//! Scripts and CI tell what kind of failure a command ran into by its exit
+//! code rather than by parsing stderr, so the codes below stay the same
+//! across releases:
+//!
+//! | Code | Failure                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | none                                                     |
+//! | 1    | anything not listed below                                |
+//! | 2    | invalid command line, as reported by clap                |
+//! | 10   | invalid configuration                                    |
+//! | 20   | the peer could not be resolved or reached                |
+//! | 21   | the peer's TLS certificate failed validation             |
+//! | 22   | the peer's handshake was rejected or never came          |
+//! | 23   | the peer did not relay our address after the handshake   |
+//! | 30   | invalid chainspec or global state update                 |
+
+use std::process::ExitCode;
+
+use miette::Report;
+
+use crate::config::ConfigError;
+use crate::error::Error;
+use crate::network::error::HandshakeError;
+use crate::network::error::ManagerError;
+use crate::network::error::TLSError;
+use crate::network::progress::BootstrapError;
+use crate::network::progress::Phase;
+use crate::primitives::ChainspecAccountsLoadError;
+use crate::primitives::ChainspecError;
+use crate::primitives::GlobalStateUpdateLoadError;
+
+/// The kind of failure a command exits with, see the [module](self) for
+/// the codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Other = 1,
+    Config = 10,
+    Unreachable = 20,
+    Tls = 21,
+    Handshake = 22,
+    Sync = 23,
+    Chainspec = 30,
+}
+
+impl Failure {
+    pub fn code(self) -> u8 { self as u8 }
+
+    /// The failure `report` is about, from the outermost error in its chain
+    /// whose kind is known.
+    pub fn of(report: &Report) -> Self {
+        report
+            .chain()
+            .find_map(|error| {
+                if let Some(error) = error.downcast_ref::<Error>() {
+                    Self::of_node(error)
+                } else if let Some(error) = error.downcast_ref::<ManagerError>() {
+                    Self::of_network(error)
+                } else if let Some(error) = error.downcast_ref::<TLSError>() {
+                    Self::of_tls(error)
+                } else if let Some(error) = error.downcast_ref::<BootstrapError>() {
+                    Some(error.phase.into())
+                } else if error.is::<HandshakeError>() {
+                    Some(Failure::Handshake)
+                } else if error.is::<ConfigError>() {
+                    Some(Failure::Config)
+                } else if error.is::<ChainspecError>()
+                    || error.is::<ChainspecAccountsLoadError>()
+                    || error.is::<GlobalStateUpdateLoadError>()
+                {
+                    Some(Failure::Chainspec)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Failure::Other)
+    }
+
+    fn of_node(error: &Error) -> Option<Self> {
+        match error {
+            Error::NetworkManager(error) => Self::of_network(error),
+            Error::Bootstrap(error) => Some(error.phase.into()),
+            Error::Chainspec(_) => Some(Failure::Chainspec),
+            Error::PeerStore(_) => None,
+        }
+    }
+
+    fn of_network(error: &ManagerError) -> Option<Self> {
+        match error {
+            ManagerError::Resolve(..)
+            | ManagerError::NoAddresses
+            | ManagerError::ConnectTimeout(_) => Some(Failure::Unreachable),
+            ManagerError::Tls(error) => Self::of_tls(error),
+            ManagerError::HandshakeRejected(..) | ManagerError::HandshakeTimeout(_) => {
+                Some(Failure::Handshake)
+            }
+            _ => None,
+        }
+    }
+
+    fn of_tls(error: &TLSError) -> Option<Self> {
+        match error {
+            TLSError::TcpConnection(_) => Some(Failure::Unreachable),
+            TLSError::TlsHandshake(_) => Some(Failure::Tls),
+            error if error.is_peer_fault() => Some(Failure::Tls),
+            _ => None,
+        }
+    }
+
+    /// Prints `report` as returning it from `main` would, and gives the exit
+    /// code of `self`.
+    pub fn exit(self, report: &Report) -> ExitCode {
+        eprintln!("Error: {report:?}");
+        self.into()
+    }
+}
+
+impl From<Phase> for Failure {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Resolve | Phase::Connect => Failure::Unreachable,
+            Phase::Tls => Failure::Tls,
+            Phase::Handshake => Failure::Handshake,
+            Phase::Sync => Failure::Sync,
+        }
+    }
+}
+
+impl From<Failure> for ExitCode {
+    fn from(failure: Failure) -> Self { ExitCode::from(failure.code()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::SocketAddr;
+
+    use miette::WrapErr;
+
+    use super::*;
+    use crate::network::resolve::Bootnode;
+
+    fn addr() -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], 34553)) }
+
+    #[test]
+    fn phases_map_to_exit_codes() {
+        let codes: Vec<_> = Phase::ALL.iter().map(|&phase| Failure::from(phase).code()).collect();
+        assert_eq!(codes, [20, 20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn classifies_wrapped_errors() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let unreachable: Result<(), _> = Err(Error::from(ManagerError::Tls(
+            TLSError::TcpConnection(refused),
+        )));
+        let report = unreachable.wrap_err("Node failed").unwrap_err();
+        assert_eq!(Failure::of(&report), Failure::Unreachable);
+
+        let expired = ManagerError::Tls(TLSError::Expired {
+            by: casper_types::TimeDiff::from_seconds(1),
+        });
+        assert_eq!(Failure::of(&Report::new(expired)), Failure::Tls);
+
+        let rejected = ManagerError::HandshakeRejected(addr(), HandshakeError::ReplayedStamp);
+        let report = Report::new(rejected).wrap_err("Could not join").wrap_err("Fetch failed");
+        assert_eq!(Failure::of(&report), Failure::Handshake);
+
+        let bootstrap = Error::Bootstrap(BootstrapError {
+            bootnode: Bootnode::from(addr()),
+            phase: Phase::Sync,
+            source: ManagerError::ConnectionClosed(addr()),
+        });
+        assert_eq!(Failure::of(&Report::new(bootstrap)).code(), 23);
+
+        let chainspec = crate::primitives::Chainspec::from_path("does-not-exist").unwrap_err();
+        assert_eq!(Failure::of(&Report::new(chainspec)), Failure::Chainspec);
+    }
+
+    #[test]
+    fn anything_else_is_a_plain_failure() {
+        assert_eq!(
+            Failure::of(&miette::miette!("something broke")),
+            Failure::Other
+        );
+        let closed = ManagerError::ConnectionClosed(addr());
+        assert_eq!(Failure::of(&Report::new(closed)).code(), 1);
+    }
+}
@@ -0,0 +1,232 @@
+//! Where peers are, by the address they are reached on: the country and the
+//! autonomous system, so `schultz crawl` and `schultz peers` can tell how the
+//! network is spread.
+//!
+//! Lookups go through the [`GeoLookup`] trait, so other sources of the data
+//! can stand in. With the `geoip` feature, [`MaxMind`] reads it from the
+//! GeoLite2 databases of MaxMind: `node.geoip_db`, a Country or City
+//! database, and `node.geoip_asn_db`, an ASN one. Either may be left out,
+//! and addresses a database does not know are reported as unknown.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+#[cfg(feature = "geoip")]
+use std::path::Path;
+#[cfg(feature = "geoip")]
+use std::path::PathBuf;
+
+use miette::Diagnostic;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config::NodeConfig;
+
+/// Stands in for what a lookup did not find, in tables and distributions.
+const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Error)]
+pub enum GeoError {
+    #[cfg(feature = "geoip")]
+    #[error("Could not open the GeoIP database {}", .0.display())]
+    Open(PathBuf, #[source] maxminddb::MaxMindDBError),
+    #[error("{0} is set, but schultz was built without the geoip feature")]
+    NotBuilt(&'static str),
+}
+
+impl Diagnostic for GeoError {}
+
+/// What is known of where an address is.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GeoInfo {
+    /// ISO 3166-1 code of the country, e.g. `DE`.
+    pub country: Option<String>,
+    /// Number of the autonomous system announcing the address.
+    pub asn: Option<u32>,
+    /// Organization running the autonomous system.
+    pub as_org: Option<String>,
+}
+
+impl GeoInfo {
+    /// The country, or `unknown`.
+    pub fn country_or_unknown(&self) -> &str { self.country.as_deref().unwrap_or(UNKNOWN) }
+
+    /// The autonomous system as `AS<number>`, followed by its organization
+    /// if known, or `unknown`.
+    pub fn asn_or_unknown(&self) -> String {
+        match (self.asn, &self.as_org) {
+            (Some(asn), Some(org)) => format!("AS{asn} {org}"),
+            (Some(asn), None) => format!("AS{asn}"),
+            (None, _) => UNKNOWN.to_string(),
+        }
+    }
+}
+
+/// Tells where addresses are.
+pub trait GeoLookup: Send + Sync {
+    /// What is known of where `ip` is, nothing if it is not known at all.
+    fn lookup(&self, ip: IpAddr) -> GeoInfo;
+}
+
+/// Looks addresses up in MaxMind databases, GeoLite2 or GeoIP2.
+#[cfg(feature = "geoip")]
+pub struct MaxMind {
+    country: Option<maxminddb::Reader<Vec<u8>>>,
+    asn: Option<maxminddb::Reader<Vec<u8>>>,
+}
+
+#[cfg(feature = "geoip")]
+impl MaxMind {
+    /// Reads the Country or City database at `country` and the ASN database
+    /// at `asn`, those given.
+    pub fn open(country: Option<&Path>, asn: Option<&Path>) -> Result<Self, GeoError> {
+        let open = |path: &Path| {
+            maxminddb::Reader::open_readfile(path)
+                .map_err(|e| GeoError::Open(path.to_path_buf(), e))
+        };
+        Ok(MaxMind {
+            country: country.map(open).transpose()?,
+            asn: asn.map(open).transpose()?,
+        })
+    }
+}
+
+#[cfg(feature = "geoip")]
+impl GeoLookup for MaxMind {
+    fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        use maxminddb::geoip2;
+
+        let mut info = GeoInfo::default();
+        // An address missing from a database only leaves it unknown.
+        if let Some(Ok(found)) = self.country.as_ref().map(|db| db.lookup::<geoip2::Country>(ip)) {
+            info.country = found.country.and_then(|country| country.iso_code).map(String::from);
+        }
+        if let Some(Ok(found)) = self.asn.as_ref().map(|db| db.lookup::<geoip2::Asn>(ip)) {
+            info.asn = found.autonomous_system_number;
+            info.as_org = found.autonomous_system_organization.map(String::from);
+        }
+        info
+    }
+}
+
+/// The lookup the databases configured in `node` make up, `None` if none
+/// is.
+#[cfg(feature = "geoip")]
+pub fn from_config(node: &NodeConfig) -> Result<Option<Box<dyn GeoLookup>>, GeoError> {
+    if node.geoip_db.is_none() && node.geoip_asn_db.is_none() {
+        return Ok(None);
+    }
+    let maxmind = MaxMind::open(node.geoip_db.as_deref(), node.geoip_asn_db.as_deref())?;
+    Ok(Some(Box::new(maxmind)))
+}
+
+#[cfg(not(feature = "geoip"))]
+pub fn from_config(node: &NodeConfig) -> Result<Option<Box<dyn GeoLookup>>, GeoError> {
+    if node.geoip_db.is_some() {
+        return Err(GeoError::NotBuilt("node.geoip_db"));
+    }
+    if node.geoip_asn_db.is_some() {
+        return Err(GeoError::NotBuilt("node.geoip_asn_db"));
+    }
+    Ok(None)
+}
+
+/// `item` with where it is, as printed in JSON.
+#[derive(Serialize)]
+pub struct Located<'a, T> {
+    #[serde(flatten)]
+    pub item: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoInfo>,
+}
+
+/// How many peers are in every country and autonomous system.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Distribution {
+    pub countries: BTreeMap<String, usize>,
+    pub asns: BTreeMap<String, usize>,
+}
+
+impl Distribution {
+    /// Counts the peers of `infos` by country and autonomous system.
+    pub fn of<'a>(infos: impl IntoIterator<Item = &'a GeoInfo>) -> Self {
+        let mut distribution = Distribution::default();
+        for info in infos {
+            *distribution.countries.entry(info.country_or_unknown().to_string()).or_default() += 1;
+            *distribution.asns.entry(info.asn_or_unknown()).or_default() += 1;
+        }
+        distribution
+    }
+
+    /// Prints the countries and autonomous systems, those with the most
+    /// peers first.
+    pub fn print_table(&self) {
+        for (title, counts) in [
+            ("country", &self.countries),
+            ("autonomous system", &self.asns),
+        ] {
+            let mut counts: Vec<_> = counts.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!();
+            println!("{:<48}{:>6}", title, "peers");
+            for (name, count) in counts {
+                println!("{name:<48}{count:>6}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Knows where a few addresses are.
+    struct Fixed(HashMap<IpAddr, GeoInfo>);
+
+    impl GeoLookup for Fixed {
+        fn lookup(&self, ip: IpAddr) -> GeoInfo { self.0.get(&ip).cloned().unwrap_or_default() }
+    }
+
+    #[test]
+    fn peers_are_counted_by_country_and_asn() {
+        let hetzner = GeoInfo {
+            country: Some("DE".to_string()),
+            asn: Some(24940),
+            as_org: Some("Hetzner Online GmbH".to_string()),
+        };
+        let lookup = Fixed(HashMap::from([
+            ([10, 0, 0, 1].into(), hetzner.clone()),
+            ([10, 0, 0, 2].into(), hetzner),
+        ]));
+        let infos: Vec<_> = [[10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3]]
+            .into_iter()
+            .map(|ip| lookup.lookup(ip.into()))
+            .collect();
+
+        let distribution = Distribution::of(&infos);
+        assert_eq!(
+            distribution.countries,
+            BTreeMap::from([("DE".to_string(), 2), ("unknown".to_string(), 1)])
+        );
+        assert_eq!(
+            distribution.asns,
+            BTreeMap::from([
+                ("AS24940 Hetzner Online GmbH".to_string(), 2),
+                ("unknown".to_string(), 1)
+            ])
+        );
+
+        let located = Located {
+            item: &serde_json::json!({ "addr": "10.0.0.3:35000" }),
+            geo: Some(infos[2].clone()),
+        };
+        assert_eq!(
+            serde_json::to_value(&located).unwrap(),
+            serde_json::json!({
+                "addr": "10.0.0.3:35000",
+                "geo": { "country": null, "asn": null, "as_org": null },
+            })
+        );
+    }
+}
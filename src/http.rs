@@ -0,0 +1,328 @@
+//! Just enough HTTP/1.1 to talk to the APIs casper-node serves, its JSON-RPC
+//! API and its event stream.
+//!
+//! Nodes serve both over plain HTTP, which is spoken here directly rather
+//! than through an HTTP client, one request per connection. Bodies are read
+//! as they arrive, so an endless event stream can be followed as well as a
+//! response read whole.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+
+use thiserror::Error;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+
+/// Longest status or header line read.
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Largest chunk of a chunked body read.
+const MAX_CHUNK_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseEndpointError {
+    #[error("only http:// endpoints are supported, got {0:?}")]
+    UnsupportedScheme(String),
+    #[error("missing host in {0:?}")]
+    MissingHost(String),
+    #[error("invalid port in {0:?}")]
+    InvalidPort(String),
+}
+
+/// Where a node serves an API, e.g. `http://node.example:7777/rpc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    /// Parses `value`, taking `default_port` and `default_path` where it
+    /// leaves them out, so `node.example` is enough.
+    pub fn parse(
+        value: &str,
+        default_port: u16,
+        default_path: &str,
+    ) -> Result<Self, ParseEndpointError> {
+        let rest = match value.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some(_) => return Err(ParseEndpointError::UnsupportedScheme(value.to_string())),
+            None => value,
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, default_path),
+        };
+        // IPv6 addresses come in brackets, so their colons are not taken for
+        // the port separator.
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port =
+                    port.parse().map_err(|_| ParseEndpointError::InvalidPort(value.to_string()))?;
+                (host, port)
+            }
+            _ => (authority, default_port),
+        };
+        let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+        if host.is_empty() {
+            return Err(ParseEndpointError::MissingHost(value.to_string()));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    pub fn path(&self) -> &str { &self.path }
+
+    /// The endpoint at `path` below this one.
+    pub fn join(&self, path: &str) -> Self {
+        Self {
+            path: format!(
+                "{}/{}",
+                self.path.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            ),
+            ..self.clone()
+        }
+    }
+
+    /// The host and port, as sent in the `Host` header.
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+/// How the end of a body is told.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Framing {
+    /// In chunks, each preceded by its length, the last one empty.
+    Chunked,
+    /// After this many more bytes.
+    Length(usize),
+    /// Once the server closes the connection.
+    Close,
+}
+
+/// A response whose body is yet to be read.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    reader: BufReader<TcpStream>,
+    framing: Framing,
+    done: bool,
+}
+
+/// Connects to `endpoint` and sends a request for its path, returning once
+/// the head of the response was read.
+pub async fn request(
+    endpoint: &Endpoint,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<Response> {
+    let mut stream = TcpStream::connect((endpoint.host.as_str(), endpoint.port)).await?;
+    let mut head = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        endpoint.path,
+        endpoint.authority()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !body.is_empty() {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut reader = BufReader::new(stream);
+    let status_line = read_line(&mut reader).await?;
+    let status = status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid(format!("not an HTTP status line: {status_line:?}")))?;
+    let mut framing = Framing::Close;
+    loop {
+        let line = read_line(&mut reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+            framing = Framing::Chunked;
+        } else if name.eq_ignore_ascii_case("content-length") && framing == Framing::Close {
+            let len = value.parse().map_err(|_| invalid(format!("bad length {value:?}")))?;
+            framing = Framing::Length(len);
+        }
+    }
+
+    Ok(Response {
+        status,
+        reader,
+        framing,
+        done: false,
+    })
+}
+
+impl Response {
+    /// The next piece of the body as it arrives, `None` once it is over.
+    pub async fn next_piece(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let piece = match &mut self.framing {
+            Framing::Chunked => {
+                let line = read_line(&mut self.reader).await?;
+                // Chunk extensions follow a semicolon.
+                let size = line.split(';').next().unwrap_or_default().trim();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| invalid(format!("bad chunk size {line:?}")))?;
+                if size > MAX_CHUNK_LEN {
+                    return Err(invalid(format!("chunk of {size} bytes")));
+                }
+                if size == 0 {
+                    // Trailers end with an empty line like the head does.
+                    while !read_line(&mut self.reader).await?.is_empty() {}
+                    vec![]
+                } else {
+                    let mut chunk = vec![0; size];
+                    self.reader.read_exact(&mut chunk).await?;
+                    if !read_line(&mut self.reader).await?.is_empty() {
+                        return Err(invalid("chunk longer than announced".to_string()));
+                    }
+                    chunk
+                }
+            }
+            Framing::Length(0) => vec![],
+            Framing::Length(remaining) => {
+                let buffer = self.reader.fill_buf().await?;
+                if buffer.is_empty() {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let piece = buffer[..buffer.len().min(*remaining)].to_vec();
+                self.reader.consume(piece.len());
+                *remaining -= piece.len();
+                piece
+            }
+            Framing::Close => {
+                let piece = self.reader.fill_buf().await?.to_vec();
+                self.reader.consume(piece.len());
+                piece
+            }
+        };
+        if piece.is_empty() {
+            self.done = true;
+            return Ok(None);
+        }
+        Ok(Some(piece))
+    }
+
+    /// Reads the rest of the body, failing if it is longer than `limit`.
+    pub async fn read_to_end(mut self, limit: usize) -> io::Result<Vec<u8>> {
+        let mut body = vec![];
+        while let Some(piece) = self.next_piece().await? {
+            if body.len() + piece.len() > limit {
+                return Err(invalid(format!("body longer than {limit} bytes")));
+            }
+            body.extend_from_slice(&piece);
+        }
+        Ok(body)
+    }
+}
+
+/// Reads a line ending in CRLF, without the line ending.
+async fn read_line(reader: &mut BufReader<TcpStream>) -> io::Result<String> {
+    let mut line = vec![];
+    let read = (&mut *reader).take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("line too long".to_string()));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| invalid("line not UTF-8".to_string()))
+}
+
+fn invalid(message: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, message) }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serves `response` to the first request and returns where.
+    async fn serve_once(response: &'static [u8]) -> Endpoint {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(response).await.unwrap();
+        });
+        Endpoint::parse(&addr.to_string(), 80, "/").unwrap()
+    }
+
+    #[tokio::test]
+    async fn bodies_are_read_whatever_their_framing() {
+        let endpoint = serve_once(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\n{\"a\r\n4;x=y\r\n\":1}\r\n0\r\n\r\n",
+        )
+        .await;
+        let response = request(&endpoint, "GET", &[], &[]).await.unwrap();
+        assert_eq!(response.read_to_end(1024).await.unwrap(), b"{\"a\":1}");
+
+        let endpoint =
+            serve_once(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnopeEXTRA").await;
+        let response = request(&endpoint, "GET", &[], &[]).await.unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.read_to_end(1024).await.unwrap(), b"nope");
+
+        let endpoint = serve_once(b"HTTP/1.1 200 OK\r\n\r\nuntil closed").await;
+        let response = request(&endpoint, "GET", &[], &[]).await.unwrap();
+        assert!(response.read_to_end(4).await.is_err());
+    }
+
+    #[test]
+    fn endpoints_take_defaults_for_what_they_leave_out() {
+        let endpoint = Endpoint::parse("node.example", 7777, "/rpc").unwrap();
+        assert_eq!(endpoint.to_string(), "http://node.example:7777/rpc");
+        let endpoint = Endpoint::parse("http://[::1]:8888/api", 7777, "/rpc").unwrap();
+        assert_eq!(endpoint.to_string(), "http://[::1]:8888/api");
+        assert!(matches!(
+            Endpoint::parse("https://node.example", 7777, "/rpc"),
+            Err(ParseEndpointError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            Endpoint::parse("http://:7777", 7777, "/rpc"),
+            Err(ParseEndpointError::MissingHost(_))
+        ));
+    }
+}
@@ -1,16 +1,45 @@
+pub mod binary_port;
 pub mod commands;
+pub mod config;
+pub mod crypto;
 pub mod dirs;
 pub mod error;
+pub mod exit;
+pub mod geo;
+pub mod http;
 pub mod network;
 pub mod node;
 pub mod primitives;
+pub mod rpc;
+pub mod sse;
+pub mod storage;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod utils;
 
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
+use casper_hashing::Digest;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::TimeDiff;
+use casper_types::U512;
 use clap::Parser;
 use clap::Subcommand;
 use clap::ValueEnum;
+use config::Config;
+use miette::IntoDiagnostic;
+use network::bandwidth::Bandwidth;
+use network::compression::Compression;
+use network::deploy;
+use network::memory::ByteSize;
+use network::resolve::Bootnode;
+use network::topology::TopologyFormat;
+use node::export::StatsdFormat;
+use primitives::DecWeight;
 
 #[derive(ValueEnum, Clone)]
 pub enum OutputFormat {
@@ -24,78 +53,1906 @@ pub struct Args {
     command: Commands,
 }
 
-#[derive(Subcommand)]
+/// Settings of the node started by `bootstrap` or `serve`, see
+/// [`config::NodeConfig`].
+#[derive(clap::Args, Clone)]
+pub struct NodeArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "addr",
+        help = "Schultz SocketAddr to bind to",
+        env = "SCHULTZ_ADDR"
+    )]
+    pub addr: Option<SocketAddr>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "bootnode",
+        help = "Casper node to bootstrap from, host:port with a name or an IP",
+        env = "SCHULTZ_BOOTNODE"
+    )]
+    pub bootnode: Option<Bootnode>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "chainspec",
+        help = "Chainspec directory, or one per protocol version, that active in --era is run",
+        env = "SCHULTZ_CHAINSPEC"
+    )]
+    pub chainspec: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "era",
+        help = "era the network is in, telling which version of the chainspec directory to run",
+        env = "SCHULTZ_ERA"
+    )]
+    pub era: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "status-addr",
+        help = "SocketAddr to serve /health and /status on",
+        env = "SCHULTZ_STATUS_ADDR"
+    )]
+    pub status_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        value_name = "ws-addr",
+        help = "SocketAddr to stream network events and gossip as JSON over WebSocket on",
+        env = "SCHULTZ_WS_ADDR"
+    )]
+    pub ws_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Unix socket, or named pipe on Windows, to answer queries like `schultz peers` on",
+        env = "SCHULTZ_CONTROL_SOCKET"
+    )]
+    pub control_socket: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "grpc-addr",
+        help = "SocketAddr to answer the control queries on over gRPC, needs the grpc feature",
+        env = "SCHULTZ_GRPC_ADDR"
+    )]
+    pub grpc_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        value_name = "path",
+        help = "file holding the token gRPC clients send to connect or disconnect peers",
+        env = "SCHULTZ_GRPC_TOKEN_FILE"
+    )]
+    pub grpc_token_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "duration",
+        help = "How often to write a metrics snapshot for `schultz stats`, e.g. 1min",
+        env = "SCHULTZ_METRICS_INTERVAL"
+    )]
+    pub metrics_interval: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        help = "follow and check the chain of the blocks peers gossip, its tip shown on /status",
+        env = "SCHULTZ_FOLLOW_HEADERS"
+    )]
+    pub follow_headers: bool,
+
+    #[arg(
+        long,
+        value_name = "block-hash",
+        value_parser = network::headers::parse_block_hash,
+        help = "block the header chain followed is anchored to, rather than the first one seen",
+        env = "SCHULTZ_TRUSTED_HASH"
+    )]
+    pub trusted_hash: Option<Digest>,
+
+    #[arg(
+        long,
+        help = "record the headers, deploys and finality signatures observed, see `schultz db`",
+        env = "SCHULTZ_STORE_OBSERVATIONS"
+    )]
+    pub store_observations: bool,
+
+    #[arg(
+        long,
+        value_name = "duration",
+        help = "how long recorded observations are kept, e.g. 7days",
+        env = "SCHULTZ_STORE_RETENTION"
+    )]
+    pub store_retention: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        value_name = "count",
+        help = "recorded observations kept of each kind, the oldest are pruned first",
+        env = "SCHULTZ_STORE_MAX_ENTRIES"
+    )]
+    pub store_max_entries: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "size",
+        help = "estimated memory the message queues may hold before the node sheds peers, e.g. \
+                512MiB",
+        env = "SCHULTZ_MAX_MEMORY"
+    )]
+    pub max_memory: Option<ByteSize>,
+}
+
+#[derive(Subcommand, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
+    #[command(about = "Measure how a peer holds up under load")]
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommands,
+    },
+    #[command(about = "Ask a casper-node 2.x through its binary port and print the result")]
+    Binary {
+        #[command(subcommand)]
+        command: BinaryCommands,
+    },
     #[command(author, version, about = "Bootstrap a Schultz node for Casper network", long_about = None)]
     Bootstrap {
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Inspect chainspec directories")]
+    Chainspec {
+        #[command(subcommand)]
+        command: ChainspecCommands,
+    },
+    #[command(about = "Inspect the effective configuration")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    #[command(about = "Map the network by dialing every address peers gossip")]
+    Crawl {
+        #[arg(
+            long,
+            value_name = "count",
+            default_value = "32",
+            help = "Peers dialed at a time",
+            env = "SCHULTZ_CRAWL_WORKERS"
+        )]
+        workers: usize,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            default_value = "10s",
+            help = "how long each peer is listened to for the addresses it gossips",
+            env = "SCHULTZ_CRAWL_LISTEN"
+        )]
+        listen: TimeDiff,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "File progress is checkpointed to, crawl.json in the data directory by default",
+            env = "SCHULTZ_CRAWL_CHECKPOINT"
+        )]
+        checkpoint: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "continue the crawl checkpointed rather than start over from the bootnode",
+            env = "SCHULTZ_CRAWL_RESUME"
+        )]
+        resume: bool,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Write the graph of the nodes reached and what they gossiped to this file",
+            env = "SCHULTZ_CRAWL_TOPOLOGY"
+        )]
+        topology: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "format",
+            default_value = "graphml",
+            help = "format of the graph written with --topology",
+            env = "SCHULTZ_CRAWL_TOPOLOGY_FORMAT"
+        )]
+        topology_format: TopologyFormat,
+
+        #[arg(
+            long,
+            help = "give the edges of the graph the protocol version and latency of their target",
+            env = "SCHULTZ_CRAWL_EDGE_METADATA"
+        )]
+        edge_metadata: bool,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            help = "crawl again from the bootnode this often, e.g. 1h, until stopped",
+            env = "SCHULTZ_CRAWL_SCHEDULE"
+        )]
+        schedule: Option<TimeDiff>,
+
+        #[arg(
+            long,
+            help = "report what changed since the previous crawl kept in the data directory",
+            env = "SCHULTZ_CRAWL_DIFF"
+        )]
+        diff: bool,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Inspect the data directory")]
+    DataDir {
+        #[command(subcommand)]
+        command: DataDirCommands,
+    },
+    #[command(about = "Query the observations recorded with --store-observations")]
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    #[command(about = "Check the TLS library, chainspec, bootnode and clock a node depends on")]
+    Doctor {
+        #[arg(
+            long,
+            value_name = "host:port",
+            default_value = "pool.ntp.org:123",
+            help = "NTP server to compare the system clock with",
+            env = "SCHULTZ_NTP_SERVER"
+        )]
+        ntp_server: String,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Follow the event stream of a casper-node and print its events")]
+    Events {
+        #[arg(
+            long,
+            value_name = "url",
+            default_value = sse::DEFAULT_ENDPOINT,
+            value_parser = sse::parse_endpoint,
+            help = "event stream of the node, e.g. http://host:9999/events/main",
+            env = "SCHULTZ_EVENTS_NODE"
+        )]
+        node: http::Endpoint,
+
+        #[arg(
+            long,
+            value_name = "kinds",
+            value_delimiter = ',',
+            help = "only print events of these kinds, e.g. deploys,steps",
+            env = "SCHULTZ_EVENTS_FILTER"
+        )]
+        filter: Option<Vec<sse::EventKind>>,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Append events to this file as JSON lines instead of printing them",
+            env = "SCHULTZ_EVENTS_OUTPUT"
+        )]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Dump the peers, scores and recent events of a running node as JSON")]
+    ExportNetstate {
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Write the snapshot to this file instead of printing it",
+            env = "SCHULTZ_EXPORT_NETSTATE_OUT"
+        )]
+        out: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Control socket of the node to ask",
+            env = "SCHULTZ_CONTROL_SOCKET"
+        )]
+        control_socket: Option<PathBuf>,
+    },
+    #[command(about = "Fetch blocks and deploys from the network by their hash")]
+    Fetch {
+        #[command(subcommand)]
+        command: FetchCommands,
+    },
+    #[command(about = "Follow the finality signatures validators gossip")]
+    Finality {
+        #[command(subcommand)]
+        command: FinalityCommands,
+    },
+    #[command(about = "Inspect node identities")]
+    Identity {
+        #[command(subcommand)]
+        command: IdentityCommands,
+    },
+    #[command(about = "Print or replace the log filter of a running node")]
+    LogFilter {
+        #[arg(
+            value_name = "directives",
+            help = "Filter to log by from now on, like RUST_LOG, e.g. info,schultz::network=trace"
+        )]
+        directives: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Control socket of the node to ask",
+            env = "SCHULTZ_CONTROL_SOCKET"
+        )]
+        control_socket: Option<PathBuf>,
+    },
+    #[command(about = "List the peers of a running node")]
+    Peers {
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Control socket of the node to ask",
+            env = "SCHULTZ_CONTROL_SOCKET"
+        )]
+        control_socket: Option<PathBuf>,
+    },
+    #[command(about = "Reload the configuration of a running node, as SIGHUP does on Unix")]
+    Reload {
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Control socket of the node to ask",
+            env = "SCHULTZ_CONTROL_SOCKET"
+        )]
+        control_socket: Option<PathBuf>,
+    },
+    #[command(about = "Ask a casper-node through its JSON-RPC API and print the result")]
+    Rpc {
+        #[arg(value_name = "method", help = "Method to call")]
+        method: rpc::Method,
+
+        #[arg(
+            long,
+            value_name = "url",
+            default_value = rpc::DEFAULT_ENDPOINT,
+            value_parser = rpc::parse_endpoint,
+            help = "JSON-RPC endpoint of the node",
+            env = "SCHULTZ_RPC_NODE"
+        )]
+        node: http::Endpoint,
+
+        #[arg(
+            long,
+            value_name = "block",
+            help = "height or hash of the block to ask about, defaults to the highest",
+            env = "SCHULTZ_RPC_BLOCK"
+        )]
+        block: Option<rpc::BlockIdentifier>,
+    },
+    #[command(about = "Run a node other nodes bootstrap from, handing them its known peers")]
+    Serve {
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Print connection churn and handshake failures between metrics snapshots")]
+    Stats {
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Directory of the snapshots, the metrics directory of the network by default",
+            env = "SCHULTZ_STATS_DIR"
+        )]
+        dir: Option<PathBuf>,
+    },
+    #[command(about = "Stay connected to the network and print every message peers gossip")]
+    Tap {
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Append messages to this file as JSON lines instead of printing them",
+            env = "SCHULTZ_TAP_OUTPUT"
+        )]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Sign a native transfer and gossip it to the peers of the network")]
+    Transfer {
+        #[arg(
+            long,
+            value_name = "motes",
+            value_parser = deploy::parse_motes,
+            help = "Motes to transfer",
+            env = "SCHULTZ_TRANSFER_AMOUNT"
+        )]
+        amount: U512,
+
+        #[arg(
+            long,
+            value_name = "public-key",
+            value_parser = deploy::parse_public_key,
+            help = "Hex-encoded public key of the account to transfer to",
+            env = "SCHULTZ_TRANSFER_TARGET"
+        )]
+        target: PublicKey,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "secret_key.pem of the account paying for the transfer",
+            env = "SCHULTZ_TRANSFER_SECRET_KEY"
+        )]
+        secret_key: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "motes",
+            default_value = "100000000",
+            value_parser = deploy::parse_motes,
+            help = "Motes paid for running the transfer",
+            env = "SCHULTZ_TRANSFER_PAYMENT"
+        )]
+        payment: U512,
+
+        #[arg(
+            long,
+            value_name = "id",
+            help = "Id of the transfer, the current time in milliseconds if not given",
+            env = "SCHULTZ_TRANSFER_ID"
+        )]
+        id: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            default_value = "30min",
+            help = "how long the deploy may still be executed, e.g. 1h",
+            env = "SCHULTZ_TRANSFER_TTL"
+        )]
+        ttl: TimeDiff,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            default_value = "10s",
+            help = "how long to hand the deploy out to peers asking for it, e.g. 30s",
+            env = "SCHULTZ_TRANSFER_WAIT"
+        )]
+        wait: TimeDiff,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+    #[command(about = "Record, decode and replay captures recorded with --wire-log")]
+    WireLog {
+        #[command(subcommand)]
+        command: WireLogCommands,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum BenchCommands {
+    #[command(
+        about = "Repeatedly connect and handshake with a peer, reporting latency percentiles and \
+                 failures"
+    )]
+    Handshake {
+        #[arg(
+            value_name = "target",
+            help = "Peer to handshake with, host:port with a name or an IP"
+        )]
+        target: Bootnode,
+
+        #[arg(
+            long,
+            value_name = "count",
+            default_value_t = 8,
+            help = "connections to handshake over at the same time",
+            env = "SCHULTZ_CONCURRENCY"
+        )]
+        concurrency: usize,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            default_value = "10s",
+            help = "how long to keep handshaking, e.g. 30s",
+            env = "SCHULTZ_DURATION"
+        )]
+        duration: TimeDiff,
+
         #[arg(
             short,
+            long,
+            value_name = "chainspec",
+            help = "Path to the chainspec of the target's network",
+            env = "SCHULTZ_CHAINSPEC"
+        )]
+        chainspec: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum WireLogCommands {
+    #[command(about = "Summarize a capture by peer and kind of message")]
+    Inspect {
+        #[arg(value_name = "file", help = "Capture recorded with --wire-log")]
+        file: PathBuf,
+    },
+    #[command(about = "Print every frame of a capture in order, decoded")]
+    Replay {
+        #[arg(value_name = "file", help = "Capture recorded with --wire-log")]
+        file: PathBuf,
+
+        #[arg(
             long,
             value_name = "addr",
-            help = "Schultz SocketAddr to bind to",
-            env = "ADDR"
+            help = "only print frames exchanged with this peer",
+            env = "SCHULTZ_WIRE_LOG_PEER"
+        )]
+        peer: Option<SocketAddr>,
+    },
+    #[command(about = "Handshake with a peer once, recording the frames exchanged")]
+    Record {
+        #[arg(
+            value_name = "target",
+            help = "Peer to handshake with, host:port with a name or an IP"
         )]
-        addr: String,
+        target: Bootnode,
+
+        #[arg(value_name = "file", help = "Where to write the capture")]
+        out: PathBuf,
 
         #[arg(
             short,
             long,
-            value_name = "bootnode",
-            help = "Casper SocketAddr to bootstrap from",
-            env = "BOOTNODE"
+            value_name = "chainspec",
+            help = "Path to the chainspec of the target's network",
+            env = "SCHULTZ_CHAINSPEC"
+        )]
+        chainspec: Option<PathBuf>,
+    },
+    #[command(
+        about = "Replay the peer side of a recorded handshake against ours, failing if it no \
+                 longer completes"
+    )]
+    Conform {
+        #[arg(value_name = "file", help = "Capture recorded with --wire-log")]
+        file: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "addr",
+            help = "peer whose handshake to replay, by default the first one recorded",
+            env = "SCHULTZ_WIRE_LOG_PEER"
         )]
-        bootnode: Option<String>,
+        peer: Option<SocketAddr>,
 
         #[arg(
             short,
             long,
             value_name = "chainspec",
-            help = "Path to the chainspec file",
-            env = "CHAINSPEC_PATH"
+            help = "Path to the chainspec of the recorded peer's network",
+            env = "SCHULTZ_CHAINSPEC"
         )]
-        chainspec: Option<String>,
+        chainspec: Option<PathBuf>,
     },
-    // Config,
 }
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-pub struct Cli {
-    #[command(subcommand)]
-    pub command: Commands,
+/// What every `fetch` command takes: the hash of the item and the node to
+/// fetch it through.
+#[derive(clap::Args, Clone)]
+pub struct FetchArgs {
+    #[arg(value_name = "hash", help = "Hex-encoded hash of the item")]
+    pub hash: String,
+
+    #[arg(
+        long,
+        value_name = "duration",
+        default_value = "10s",
+        help = "how long each peer has to hand out the item, e.g. 5s",
+        env = "SCHULTZ_FETCH_TIMEOUT"
+    )]
+    pub timeout: TimeDiff,
+
+    #[command(flatten)]
+    pub node: NodeArgs,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum FetchCommands {
+    #[command(about = "Fetch a block, header and body, by its hash")]
+    Block {
+        #[command(flatten)]
+        args: FetchArgs,
+    },
+    #[command(about = "Fetch a block header by the hash of its block")]
+    BlockHeader {
+        #[command(flatten)]
+        args: FetchArgs,
+    },
+    #[command(about = "Fetch a deploy by its hash")]
+    Deploy {
+        #[command(flatten)]
+        args: FetchArgs,
+    },
+}
+
+impl FetchCommands {
+    pub fn args(&self) -> &FetchArgs {
+        match self {
+            FetchCommands::Block { args }
+            | FetchCommands::BlockHeader { args }
+            | FetchCommands::Deploy { args } => args,
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum FinalityCommands {
+    #[command(
+        about = "Verify the finality signatures peers gossip and tell when a quorum of the \
+                 validators signed a block"
+    )]
+    Watch {
+        #[arg(
+            long,
+            value_name = "duration",
+            default_value = "10s",
+            help = "how long a peer has to hand out a signature it gossiped, e.g. 5s",
+            env = "SCHULTZ_FINALITY_TIMEOUT"
+        )]
+        timeout: TimeDiff,
+
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+}
+
+/// What every `binary` command takes: the node to ask and how.
+#[derive(clap::Args, Clone)]
+pub struct BinaryArgs {
+    #[arg(
+        long,
+        value_name = "addr",
+        default_value = binary_port::DEFAULT_ADDR,
+        help = "Binary port of the node, host:port",
+        env = "SCHULTZ_BINARY_NODE"
+    )]
+    pub node: String,
+
+    #[arg(
+        long,
+        value_name = "version",
+        default_value = "2.0.0",
+        value_parser = binary_port::parse_protocol_version,
+        help = "protocol version to tell the node we speak",
+        env = "SCHULTZ_BINARY_PROTOCOL_VERSION"
+    )]
+    pub protocol_version: ProtocolVersion,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum BinaryCommands {
+    #[command(about = "Get a block with its signatures, by default the highest")]
+    GetBlock {
+        #[arg(value_name = "block", help = "Height or hex-encoded hash of the block")]
+        block: Option<rpc::BlockIdentifier>,
+
+        #[command(flatten)]
+        args: BinaryArgs,
+    },
+    #[command(about = "Get a transaction with what is known about its execution")]
+    GetTransaction {
+        #[arg(value_name = "hash", help = "Hex-encoded hash of the transaction")]
+        hash: String,
+
+        #[arg(
+            long,
+            help = "the hash is of a deploy rather than a version 1 transaction",
+            env = "SCHULTZ_BINARY_DEPLOY"
+        )]
+        deploy: bool,
+
+        #[command(flatten)]
+        args: BinaryArgs,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigCommands {
+    #[command(about = "Print the configuration merged from flags, environment, file and defaults")]
+    Print {
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+}
+
+/// What every `db` command takes: the database to open.
+#[derive(clap::Args, Clone)]
+pub struct DbArgs {
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Database of the observations, that of the network in the data directory by default",
+        env = "SCHULTZ_DB"
+    )]
+    pub db: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum DbCommands {
+    #[command(about = "Print the recorded observations of a kind, most recent first")]
+    Query {
+        #[arg(value_name = "kind", help = "Kind of observations to print")]
+        kind: node::observations::Kind,
+
+        #[arg(
+            long,
+            value_name = "duration",
+            help = "only print those observed within this long, e.g. 1h",
+            env = "SCHULTZ_DB_SINCE"
+        )]
+        since: Option<TimeDiff>,
+
+        #[arg(
+            long,
+            value_name = "hash",
+            help = "only print those about this block, hex-encoded",
+            env = "SCHULTZ_DB_BLOCK"
+        )]
+        block: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "count",
+            help = "print at most this many",
+            env = "SCHULTZ_DB_LIMIT"
+        )]
+        limit: Option<usize>,
+
+        #[command(flatten)]
+        args: DbArgs,
+    },
+    #[command(about = "Print how many observations of each kind are recorded")]
+    Info {
+        #[command(flatten)]
+        args: DbArgs,
+    },
+    #[command(about = "Prune the recorded observations the configured retention does not keep")]
+    Prune {
+        #[command(flatten)]
+        args: DbArgs,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum DataDirCommands {
+    #[command(about = "Print the layout of the data directory and what each part holds")]
+    Info,
+}
+
+/// Where to take the certificate an `identity` command works on from.
+#[derive(clap::Args, Clone)]
+pub struct CertArgs {
+    #[arg(
+        value_name = "cert",
+        required_unless_present = "connect",
+        help = "PEM file holding the certificate"
+    )]
+    pub cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "addr",
+        conflicts_with = "cert",
+        help = "fetch the certificate from the peer listening on this address",
+        env = "SCHULTZ_CONNECT"
+    )]
+    pub connect: Option<SocketAddr>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum IdentityCommands {
+    #[command(
+        about = "Print the public key fingerprint of a certificate, as used for pinning peers"
+    )]
+    Fingerprint {
+        #[command(flatten)]
+        cert: CertArgs,
+    },
+    #[command(about = "Run every check peers make on a certificate and print the outcome of each")]
+    Check {
+        #[command(flatten)]
+        cert: CertArgs,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ChainspecCommands {
+    #[command(about = "Print a field-by-field diff between two chainspec directories")]
+    Diff {
+        #[arg(value_name = "dir-a", help = "Directory holding the base chainspec")]
+        dir_a: PathBuf,
+
+        #[arg(
+            value_name = "dir-b",
+            help = "Directory holding the chainspec to compare with"
+        )]
+        dir_b: PathBuf,
+    },
+    #[command(about = "Decode and print the entries of a global_state.toml")]
+    ShowGlobalState {
+        #[arg(value_name = "dir", help = "Directory holding global_state.toml")]
+        dir: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "bytes",
+            help = "refuse entries whose value is larger than this",
+            env = "SCHULTZ_MAX_VALUE_LEN"
+        )]
+        max_value_len: Option<usize>,
+    },
+    #[command(about = "Check a global_state.toml for common upgrade mistakes")]
+    LintGlobalState {
+        #[arg(value_name = "dir", help = "Directory holding global_state.toml")]
+        dir: PathBuf,
+    },
+    #[command(about = "Print the trie leaf hashes the entries of a global_state.toml become")]
+    DigestGlobalState {
+        #[arg(value_name = "dir", help = "Directory holding global_state.toml")]
+        dir: PathBuf,
+    },
+    #[command(about = "Print the validator set a global_state.toml installs")]
+    Validators {
+        #[arg(value_name = "dir", help = "Directory holding global_state.toml")]
+        dir: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "motes",
+            help = "flag validators weighing less than this",
+            env = "SCHULTZ_MIN_WEIGHT"
+        )]
+        min_weight: Option<DecWeight>,
+    },
+    #[command(about = "Print a chainspec directory as canonical JSON")]
+    ToJson {
+        #[arg(value_name = "dir", help = "Directory holding chainspec.toml")]
+        dir: PathBuf,
+
+        #[arg(
+            long,
+            help = "print the JSON on a single line",
+            env = "SCHULTZ_COMPACT"
+        )]
+        compact: bool,
+    },
+    #[command(about = "Summarize the genesis accounts of an accounts.toml and check them")]
+    Accounts {
+        #[arg(value_name = "dir", help = "Directory holding accounts.toml")]
+        dir: PathBuf,
+    },
+    #[command(about = "List the protocol versions of a casper-node style chainspec directory")]
+    ListUpgrades {
+        #[arg(
+            value_name = "root",
+            help = "Directory holding a directory per protocol version, like 1_5_6"
+        )]
+        root: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "era",
+            help = "tell which version is active in this era",
+            env = "SCHULTZ_ERA"
+        )]
+        era: Option<u64>,
+    },
+}
+
+#[derive(Parser, Clone)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "root dir for config and data",
+        env = "SCHULTZ_ROOT_DIR"
+    )]
+    root_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "dir",
+        conflicts_with = "root_dir",
+        help = "directory to keep config, identity, peers, captures and metrics in",
+        env = "SCHULTZ_DATA_DIR"
+    )]
+    data_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "path",
+        help = "config file to read, defaults to schultz.toml in the root dir",
+        env = "SCHULTZ_CONFIG"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "name",
+        value_delimiter = ',',
+        help = "networks of the config file to run against, several only for serve",
+        env = "SCHULTZ_NETWORK"
+    )]
+    network: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "url",
+        help = "OTLP/gRPC endpoint to export traces to, e.g. http://localhost:4317",
+        env = "SCHULTZ_OTLP_ENDPOINT"
+    )]
+    otlp_endpoint: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "url",
+        help = "Prometheus pushgateway to push metrics to, e.g. http://localhost:9091",
+        env = "SCHULTZ_PUSHGATEWAY"
+    )]
+    pushgateway: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "host:port",
+        help = "statsd daemon to send metrics to, e.g. localhost:8125",
+        env = "SCHULTZ_STATSD_ADDR"
+    )]
+    statsd_addr: Option<String>,
 
     #[arg(
-        short,
         long,
         global = true,
-        help = "root dir for config and data",
-        env = "Schultz_ROOT_DIR"
+        help = "whether statsd gets labels in metric names or, dogstatsd, as tags",
+        env = "SCHULTZ_STATSD_FORMAT"
     )]
-    root_dir: Option<PathBuf>,
+    statsd_format: Option<StatsdFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "interval between metrics pushes to the pushgateway or statsd, e.g. 15s",
+        env = "SCHULTZ_PUSH_INTERVAL"
+    )]
+    push_interval: Option<TimeDiff>,
 
     #[arg(
         short,
         long,
         global = true,
         help = "output format for command response",
-        env = "Schultz_OUTPUT_FORMAT"
+        env = "SCHULTZ_OUTPUT_FORMAT"
     )]
     output_format: Option<OutputFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "interval between keepalive pings to each peer, e.g. 30s",
+        env = "SCHULTZ_PING_INTERVAL"
+    )]
+    ping_interval: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "consecutive unanswered pings before a peer is disconnected",
+        env = "SCHULTZ_MAX_MISSED_PONGS"
+    )]
+    max_missed_pongs: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "interval between announcements of our address to peers, e.g. 120s",
+        env = "SCHULTZ_GOSSIP_INTERVAL"
+    )]
+    gossip_interval: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        help = "number of outgoing connections to dial from gossiped addresses",
+        env = "SCHULTZ_TARGET_OUTGOING_CONNECTIONS"
+    )]
+    target_outgoing_connections: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "rate",
+        help = "cap on the combined bandwidth of all connections, e.g. 5MBps",
+        env = "SCHULTZ_MAX_BANDWIDTH"
+    )]
+    max_bandwidth: Option<Bandwidth>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "rate",
+        help = "cap on the bandwidth of each connection, e.g. 512KiBps",
+        env = "SCHULTZ_MAX_PEER_BANDWIDTH"
+    )]
+    max_peer_bandwidth: Option<Bandwidth>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "size",
+        help = "memory each peer may hold in unprocessed messages, e.g. 64MiB",
+        env = "SCHULTZ_MAX_PEER_MEMORY"
+    )]
+    max_peer_memory: Option<ByteSize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "algorithms",
+        value_delimiter = ',',
+        help = "frame compression algorithms offered to peers, e.g. zstd,lz4",
+        env = "SCHULTZ_COMPRESSION"
+    )]
+    compression: Option<Vec<Compression>>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "compression",
+        help = "never compress frames",
+        env = "SCHULTZ_NO_COMPRESSION"
+    )]
+    no_compression: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "never multiplex channels over a connection",
+        env = "SCHULTZ_NO_MULTIPLEXING"
+    )]
+    no_multiplexing: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "never end frames with a checksum",
+        env = "SCHULTZ_NO_CHECKSUMS"
+    )]
+    no_checksums: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        help = "frames failing their checksum dropped before closing the connection",
+        env = "SCHULTZ_MAX_CORRUPT_FRAMES"
+    )]
+    max_corrupt_frames: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "dir",
+        help = "directory to load the TLS identity from, or save a generated one to",
+        env = "SCHULTZ_IDENTITY_DIR"
+    )]
+    identity_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "file",
+        help = "Casper secret_key.pem of the validator to sign handshakes as",
+        env = "SCHULTZ_CONSENSUS_KEY"
+    )]
+    consensus_key: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "how far from now the stamp of a validator's handshake may be, e.g. 2min",
+        env = "SCHULTZ_HANDSHAKE_MAX_AGE"
+    )]
+    handshake_max_age: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "accept validators' handshakes without a stamp, as Casper nodes send them",
+        env = "SCHULTZ_ACCEPT_UNSTAMPED_CERTIFICATES"
+    )]
+    accept_unstamped_certificates: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "warn once the TLS certificate expires within this long, e.g. 30days",
+        env = "SCHULTZ_CERT_EXPIRY_WARNING"
+    )]
+    cert_expiry_warning: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "code",
+        help = "two-letter country of generated TLS certificates, empty to leave it out",
+        env = "SCHULTZ_CERT_COUNTRY"
+    )]
+    cert_country: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "name",
+        help = "organization of generated TLS certificates, empty to leave it out",
+        env = "SCHULTZ_CERT_ORG"
+    )]
+    cert_org: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "name",
+        help = "common name of generated TLS certificates, e.g. casper-node",
+        env = "SCHULTZ_CERT_COMMON_NAME"
+    )]
+    cert_common_name: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "name",
+        help = "refuse peers whose TLS certificate is for another common name",
+        env = "SCHULTZ_EXPECTED_PEER_CN"
+    )]
+    expected_peer_cn: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "time peer certificates may be outside their validity period, e.g. 5min",
+        env = "SCHULTZ_CERT_CLOCK_SKEW"
+    )]
+    cert_clock_skew: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "renew the TLS certificate once it is about to expire",
+        env = "SCHULTZ_ROTATE_CERTS"
+    )]
+    rotate_certs: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "time over which connections on a renewed certificate are closed, e.g. 5min",
+        env = "SCHULTZ_CERT_DRAIN_WINDOW"
+    )]
+    cert_drain_window: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "times a bootnode is tried before giving up on it",
+        env = "SCHULTZ_BOOTSTRAP_ATTEMPTS"
+    )]
+    bootstrap_attempts: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "delay before trying a bootnode again, doubled after every attempt, e.g. 1s",
+        env = "SCHULTZ_BOOTSTRAP_RETRY_DELAY"
+    )]
+    bootstrap_retry_delay: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "time a single connection attempt may take, e.g. 5s",
+        env = "SCHULTZ_CONNECT_TIMEOUT"
+    )]
+    connect_timeout: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "duration",
+        help = "time a bootnode's addresses are used before resolving it again, e.g. 1min",
+        env = "SCHULTZ_BOOTNODE_RESOLVE_TTL"
+    )]
+    bootnode_resolve_ttl: Option<TimeDiff>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        help = "connections dialed at the same time, further dials wait their turn",
+        env = "SCHULTZ_MAX_CONCURRENT_DIALS"
+    )]
+    max_concurrent_dials: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        help = "connections held at the same time in either direction, further peers are refused",
+        env = "SCHULTZ_MAX_CONNECTIONS"
+    )]
+    max_connections: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "count",
+        help = "inbound connections held from the same IP",
+        env = "SCHULTZ_MAX_INBOUND_PER_IP"
+    )]
+    max_inbound_per_ip: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "file",
+        help = "file to save known peers to and rejoin through after a restart",
+        env = "SCHULTZ_PEERS_FILE"
+    )]
+    peers_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "accept peers on another protocol version, for debugging",
+        env = "SCHULTZ_ALLOW_VERSION_MISMATCH"
+    )]
+    allow_version_mismatch: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "suites",
+        value_delimiter = ',',
+        help = "TLS 1.3 cipher suites offered to peers, e.g. TLS_AES_256_GCM_SHA384",
+        env = "SCHULTZ_TLS_CIPHERSUITES"
+    )]
+    tls_ciphersuites: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "groups",
+        value_delimiter = ',',
+        help = "TLS key exchange groups offered to peers, e.g. X25519,P-384",
+        env = "SCHULTZ_TLS_GROUPS"
+    )]
+    tls_groups: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "file",
+        help = "file to record every frame exchanged with peers to, see `schultz wire-log`",
+        env = "SCHULTZ_WIRE_LOG"
+    )]
+    wire_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "path",
+        help = "MaxMind Country or City database to tell where peers are, needs the geoip feature",
+        env = "SCHULTZ_GEOIP_DB"
+    )]
+    geoip_db: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "path",
+        help = "MaxMind ASN database to tell the networks peers are in, needs the geoip feature",
+        env = "SCHULTZ_GEOIP_ASN_DB"
+    )]
+    geoip_asn_db: Option<PathBuf>,
+}
+
+/// Environment variables options were read from before every one was named
+/// `SCHULTZ_*`, with the variable that replaced each.
+pub const DEPRECATED_ENV: [(&str, &str); 15] = [
+    ("ADDR", "SCHULTZ_ADDR"),
+    ("BOOTNODE", "SCHULTZ_BOOTNODE"),
+    ("CHAINSPEC_PATH", "SCHULTZ_CHAINSPEC"),
+    ("STATUS_ADDR", "SCHULTZ_STATUS_ADDR"),
+    ("Schultz_ROOT_DIR", "SCHULTZ_ROOT_DIR"),
+    ("Schultz_CONFIG", "SCHULTZ_CONFIG"),
+    ("Schultz_OTLP_ENDPOINT", "SCHULTZ_OTLP_ENDPOINT"),
+    ("Schultz_OUTPUT_FORMAT", "SCHULTZ_OUTPUT_FORMAT"),
+    ("Schultz_PING_INTERVAL", "SCHULTZ_PING_INTERVAL"),
+    ("Schultz_MAX_MISSED_PONGS", "SCHULTZ_MAX_MISSED_PONGS"),
+    ("Schultz_GOSSIP_INTERVAL", "SCHULTZ_GOSSIP_INTERVAL"),
+    (
+        "Schultz_TARGET_OUTGOING_CONNECTIONS",
+        "SCHULTZ_TARGET_OUTGOING_CONNECTIONS",
+    ),
+    ("Schultz_MAX_BANDWIDTH", "SCHULTZ_MAX_BANDWIDTH"),
+    ("Schultz_MAX_PEER_BANDWIDTH", "SCHULTZ_MAX_PEER_BANDWIDTH"),
+    ("Schultz_COMPRESSION", "SCHULTZ_COMPRESSION"),
+];
+
+impl Commands {
+    /// Whether the command joins the network with a node of its own, which
+    /// keeps its identity and peers in the data directory.
+    pub fn runs_node(&self) -> bool {
+        matches!(
+            self,
+            Commands::Bootstrap { .. }
+                | Commands::Serve { .. }
+                | Commands::Tap { .. }
+                | Commands::Fetch { .. }
+                | Commands::Finality { .. }
+                | Commands::Transfer { .. }
+        )
+    }
+}
+
+impl Cli {
+    /// Parses the command line, reading the variables in [`DEPRECATED_ENV`]
+    /// in place of those that replaced them, unless those are set too.
+    ///
+    /// Meant to be called before any other thread reads the environment.
+    pub fn parse_with_deprecated_env() -> Self {
+        for (deprecated, replacement) in DEPRECATED_ENV {
+            if let Some(value) = std::env::var_os(deprecated) {
+                if std::env::var_os(replacement).is_none() {
+                    std::env::set_var(replacement, value);
+                }
+            }
+        }
+        Self::parse()
+    }
+
+    /// Warns about every variable in [`DEPRECATED_ENV`] that is set, once
+    /// logging is set up.
+    pub fn warn_deprecated_env() {
+        for (deprecated, replacement) in DEPRECATED_ENV {
+            if std::env::var_os(deprecated).is_some() {
+                tracing::warn!("{deprecated} is deprecated, set {replacement} instead");
+            }
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Context {
     pub dirs: dirs::Dirs,
+    pub data_dir: storage::DataDir,
     pub output_format: OutputFormat,
+    /// Configuration of the selected network, if any.
+    pub config: Config,
+    /// Every network selected with `--network`, by name.
+    networks: Vec<(String, Config)>,
+    /// The command line the configuration was merged from, to merge it again
+    /// on a reload.
+    cli: Cli,
 }
 
 impl Context {
     pub fn for_cli(cli: &Cli) -> miette::Result<Self> {
-        let dirs = dirs::Dirs::try_new(cli.root_dir.as_deref())?;
+        let dirs = match &cli.data_dir {
+            Some(dir) => dirs::Dirs {
+                root_dir: dir.clone(),
+            },
+            None => dirs::Dirs::try_new(cli.root_dir.as_deref())?,
+        };
+        // Only a node keeps state in the data directory, the other commands
+        // leave it as they find it.
+        let runs_node = cli.command.runs_node();
+        let data_dir = match runs_node {
+            true => storage::DataDir::open(&dirs.root_dir)?,
+            false => storage::DataDir::at(&dirs.root_dir),
+        };
         let output_format = cli.output_format.clone().unwrap_or(OutputFormat::Table);
 
+        // An explicitly given file has to exist, the default one may not.
+        let file = match &cli.config {
+            Some(path) => Config::from_path(path),
+            None => Config::from_optional_path(&data_dir.config_file()),
+        }?;
+
+        if cli.network.len() > 1 && !matches!(cli.command, Commands::Serve { .. }) {
+            miette::bail!("only `serve` runs several networks at once");
+        }
+        let mut networks = Vec::new();
+        for name in &cli.network {
+            let mut config = Self::apply_cli(cli, file.for_network(name)?)?;
+            if runs_node {
+                data_dir.apply_defaults(&mut config.network, Some(name));
+            }
+            networks.push((name.clone(), config));
+        }
+        let config = match networks.first() {
+            Some((_, config)) => config.clone(),
+            None => {
+                let mut config = Self::apply_cli(cli, file)?;
+                if runs_node {
+                    data_dir.apply_defaults(&mut config.network, None);
+                }
+                config
+            }
+        };
+
         Ok(Context {
             dirs,
+            data_dir,
             output_format,
+            config,
+            networks,
+            cli: cli.clone(),
         })
     }
+
+    /// Overrides `config` with the flags and environment variables of `cli`,
+    /// and checks the result.
+    fn apply_cli(cli: &Cli, mut config: Config) -> miette::Result<Config> {
+        let node_args = match &cli.command {
+            Commands::Bootstrap { node }
+            | Commands::Serve { node }
+            | Commands::Tap { node, .. }
+            | Commands::Crawl { node, .. }
+            | Commands::Transfer { node, .. }
+            | Commands::Doctor { node, .. }
+            | Commands::Config {
+                command: ConfigCommands::Print { node },
+            } => Some(node),
+            Commands::Fetch { command } => Some(&command.args().node),
+            Commands::Finality {
+                command: FinalityCommands::Watch { node, .. },
+            } => Some(node),
+            _ => None,
+        };
+        if let Some(args) = node_args {
+            let node = &mut config.node;
+            node.addr = args.addr.or(node.addr);
+            node.bootnode = args.bootnode.clone().or(node.bootnode.take());
+            node.chainspec = args.chainspec.clone().or(node.chainspec.take());
+            node.era = args.era.map(EraId::new).or(node.era);
+            node.status_addr = args.status_addr.or(node.status_addr);
+            node.ws_addr = args.ws_addr.or(node.ws_addr);
+            node.control_socket = args.control_socket.clone().or(node.control_socket.take());
+            node.grpc_addr = args.grpc_addr.or(node.grpc_addr);
+            node.grpc_token_file = args.grpc_token_file.clone().or(node.grpc_token_file.take());
+            node.metrics_interval = args.metrics_interval.or(node.metrics_interval);
+            node.follow_headers |= args.follow_headers;
+            node.trusted_hash = args.trusted_hash.or(node.trusted_hash);
+            node.store_observations |= args.store_observations;
+            node.store_retention = args.store_retention.or(node.store_retention);
+            node.store_max_entries = args.store_max_entries.or(node.store_max_entries);
+            node.max_memory = args.max_memory.or(node.max_memory);
+        }
+        if let Commands::Peers { control_socket }
+        | Commands::ExportNetstate { control_socket, .. }
+        | Commands::LogFilter { control_socket, .. }
+        | Commands::Reload { control_socket } = &cli.command
+        {
+            config.node.control_socket =
+                control_socket.clone().or(config.node.control_socket.take());
+        }
+        if cli.geoip_db.is_some() {
+            config.node.geoip_db = cli.geoip_db.clone();
+        }
+        if cli.geoip_asn_db.is_some() {
+            config.node.geoip_asn_db = cli.geoip_asn_db.clone();
+        }
+
+        if cli.otlp_endpoint.is_some() {
+            config.telemetry.otlp_endpoint = cli.otlp_endpoint.clone();
+        }
+        if cli.pushgateway.is_some() {
+            config.telemetry.pushgateway = cli.pushgateway.clone();
+        }
+        if cli.statsd_addr.is_some() {
+            config.telemetry.statsd_addr = cli.statsd_addr.clone();
+        }
+        if let Some(statsd_format) = cli.statsd_format {
+            config.telemetry.statsd_format = statsd_format;
+        }
+        if cli.push_interval.is_some() {
+            config.telemetry.push_interval = cli.push_interval;
+        }
+
+        let network = &mut config.network;
+        if let Some(ping_interval) = cli.ping_interval {
+            network.ping_interval = ping_interval;
+        }
+        if let Some(max_missed_pongs) = cli.max_missed_pongs {
+            network.max_missed_pongs = max_missed_pongs;
+        }
+        if let Some(gossip_interval) = cli.gossip_interval {
+            network.gossip_interval = gossip_interval;
+        }
+        if let Some(target_outgoing_connections) = cli.target_outgoing_connections {
+            network.target_outgoing_connections = target_outgoing_connections;
+        }
+        if cli.max_bandwidth.is_some() {
+            network.max_bandwidth = cli.max_bandwidth;
+        }
+        if cli.max_peer_bandwidth.is_some() {
+            network.max_peer_bandwidth = cli.max_peer_bandwidth;
+        }
+        if let Some(max_peer_memory) = cli.max_peer_memory {
+            network.max_peer_memory = max_peer_memory;
+        }
+        if let Some(compression) = &cli.compression {
+            network.compression = compression.clone();
+        }
+        if cli.no_compression {
+            network.compression.clear();
+        }
+        if cli.no_multiplexing {
+            network.multiplexing = false;
+        }
+        if cli.no_checksums {
+            network.checksums = false;
+        }
+        if let Some(max_corrupt_frames) = cli.max_corrupt_frames {
+            network.max_corrupt_frames = max_corrupt_frames;
+        }
+        if cli.identity_dir.is_some() {
+            network.identity_dir = cli.identity_dir.clone();
+        }
+        if cli.consensus_key.is_some() {
+            network.consensus_key = cli.consensus_key.clone();
+        }
+        if let Some(handshake_max_age) = cli.handshake_max_age {
+            network.handshake_max_age = handshake_max_age;
+        }
+        if cli.accept_unstamped_certificates {
+            network.accept_unstamped_certificates = true;
+        }
+        if let Some(cert_expiry_warning) = cli.cert_expiry_warning {
+            network.cert_expiry_warning = cert_expiry_warning;
+        }
+        if let Some(cert_country) = &cli.cert_country {
+            network.cert_subject.country = cert_country.clone();
+        }
+        if let Some(cert_org) = &cli.cert_org {
+            network.cert_subject.org = cert_org.clone();
+        }
+        if let Some(cert_common_name) = &cli.cert_common_name {
+            network.cert_subject.common_name = cert_common_name.clone();
+        }
+        if cli.expected_peer_cn.is_some() {
+            network.expected_peer_cn = cli.expected_peer_cn.clone();
+        }
+        if let Some(cert_clock_skew) = cli.cert_clock_skew {
+            network.cert_clock_skew = cert_clock_skew;
+        }
+        if cli.rotate_certs {
+            network.rotate_certs = true;
+        }
+        if let Some(cert_drain_window) = cli.cert_drain_window {
+            network.cert_drain_window = cert_drain_window;
+        }
+        if let Some(bootstrap_attempts) = cli.bootstrap_attempts {
+            network.bootstrap_attempts = bootstrap_attempts;
+        }
+        if let Some(bootstrap_retry_delay) = cli.bootstrap_retry_delay {
+            network.bootstrap_retry_delay = bootstrap_retry_delay;
+        }
+        if let Some(connect_timeout) = cli.connect_timeout {
+            network.connect_timeout = connect_timeout;
+        }
+        if let Some(bootnode_resolve_ttl) = cli.bootnode_resolve_ttl {
+            network.bootnode_resolve_ttl = bootnode_resolve_ttl;
+        }
+        if let Some(max_concurrent_dials) = cli.max_concurrent_dials {
+            network.max_concurrent_dials = max_concurrent_dials;
+        }
+        if let Some(max_connections) = cli.max_connections {
+            network.max_connections = max_connections;
+        }
+        if let Some(max_inbound_per_ip) = cli.max_inbound_per_ip {
+            network.max_inbound_per_ip = max_inbound_per_ip;
+        }
+        if cli.peers_file.is_some() {
+            network.peers_file = cli.peers_file.clone();
+        }
+        if cli.allow_version_mismatch {
+            network.allow_version_mismatch = true;
+        }
+        if let Some(tls_ciphersuites) = &cli.tls_ciphersuites {
+            network.tls_ciphersuites = tls_ciphersuites.clone();
+        }
+        if let Some(tls_groups) = &cli.tls_groups {
+            network.tls_groups = tls_groups.clone();
+        }
+        if cli.wire_log.is_some() {
+            network.wire_log = cli.wire_log.clone();
+        }
+
+        if config.node.metrics_interval.is_some_and(|interval| interval.millis() == 0) {
+            miette::bail!("metrics interval must be greater than zero");
+        }
+        if config.telemetry.push_interval.is_some_and(|interval| interval.millis() == 0) {
+            miette::bail!("push interval must be greater than zero");
+        }
+        let network = &mut config.network;
+        if network.ping_interval.millis() == 0 {
+            miette::bail!("ping interval must be greater than zero");
+        }
+        if network.max_missed_pongs == 0 {
+            miette::bail!("max missed pongs must be greater than zero");
+        }
+        if network.gossip_interval.millis() == 0 {
+            miette::bail!("gossip interval must be greater than zero");
+        }
+        if network.bootstrap_attempts == 0 {
+            miette::bail!("bootstrap attempts must be greater than zero");
+        }
+        if network.connect_timeout.millis() == 0 {
+            miette::bail!("connect timeout must be greater than zero");
+        }
+        if network.handshake_max_age.millis() == 0 {
+            miette::bail!("handshake max age must be greater than zero");
+        }
+        if network.max_concurrent_dials == 0 {
+            miette::bail!("max concurrent dials must be greater than zero");
+        }
+        if network.max_connections == 0 {
+            miette::bail!("max connections must be greater than zero");
+        }
+        if network.max_inbound_per_ip == 0 {
+            miette::bail!("max inbound connections per IP must be greater than zero");
+        }
+        let subject = &network.cert_subject;
+        if !subject.country.is_empty() && subject.country.len() != 2 {
+            miette::bail!("certificate country must be a two-letter code");
+        }
+        if subject.common_name.is_empty() {
+            miette::bail!("certificate common name must not be empty");
+        }
+        network.tls_options().check().into_diagnostic()?;
+        Ok(config)
+    }
+
+    /// Name of the network selected with `--network`, the first one if
+    /// several were.
+    pub fn network_name(&self) -> Option<&str> {
+        self.networks.first().map(|(name, _)| name.as_str())
+    }
+
+    /// Directory the metrics snapshots of the selected network are written
+    /// to, kept apart per network.
+    pub fn metrics_dir(&self) -> PathBuf { self.data_dir.metrics_dir(self.network_name()) }
+
+    /// Database the observations of the selected network are recorded to,
+    /// kept apart per network.
+    pub fn observations_db(&self) -> PathBuf { self.data_dir.observations_db(self.network_name()) }
+
+    /// Checkpoint file of the crawl of the selected network, kept apart per
+    /// network.
+    pub fn crawl_checkpoint(&self) -> PathBuf {
+        self.data_dir.crawl_checkpoint(self.network_name())
+    }
+
+    /// Directory the finished crawls of the selected network are kept in.
+    pub fn crawls_dir(&self) -> PathBuf { self.data_dir.crawls_dir(self.network_name()) }
+
+    /// What to keep of the recorded observations.
+    pub fn retention(&self) -> node::observations::Retention {
+        node::observations::Retention {
+            max_age: self.config.node.store_retention,
+            max_entries: self.config.node.store_max_entries,
+        }
+    }
+
+    /// A context for every selected network, or just this one if none was
+    /// selected. Each only knows its own network, also when reloading.
+    pub fn per_network(&self) -> Vec<Context> {
+        if self.networks.is_empty() {
+            return vec![self.clone()];
+        }
+        self.networks
+            .iter()
+            .map(|(name, config)| Context {
+                config: config.clone(),
+                networks: vec![(name.clone(), config.clone())],
+                cli: Cli {
+                    network: vec![name.clone()],
+                    ..self.cli.clone()
+                },
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Reads and validates the configuration again, as [`Context::for_cli`]
+    /// did at startup. Flags and environment variables still win over the
+    /// file.
+    pub fn reload_config(&self) -> miette::Result<Config> { Ok(Self::for_cli(&self.cli)?.config) }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn every_option_has_an_env_var() {
+        fn check(command: &clap::Command) {
+            for arg in command.get_arguments() {
+                if arg.is_positional() || matches!(arg.get_id().as_str(), "help" | "version") {
+                    continue;
+                }
+                let env = arg.get_env().and_then(|env| env.to_str());
+                let expected = format!("SCHULTZ_{}", arg.get_id().as_str().to_uppercase());
+                assert!(
+                    env.is_some_and(|env| env.starts_with("SCHULTZ_")),
+                    "--{} has no SCHULTZ_* variable, e.g. {expected}",
+                    arg.get_id()
+                );
+            }
+            command.get_subcommands().for_each(check);
+        }
+
+        let command = Cli::command();
+        command.clone().debug_assert();
+        check(&command);
+    }
+
+    #[test]
+    fn deprecated_variables_are_replaced_by_ones_still_read() {
+        fn envs(command: &clap::Command, found: &mut Vec<String>) {
+            let env = |arg: &clap::Arg| arg.get_env()?.to_str().map(str::to_string);
+            found.extend(command.get_arguments().filter_map(env));
+            command.get_subcommands().for_each(|command| envs(command, found));
+        }
+
+        let mut read = Vec::new();
+        envs(&Cli::command(), &mut read);
+        let reads = |name: &str| read.iter().any(|env| env == name);
+        for (deprecated, replacement) in DEPRECATED_ENV {
+            assert!(reads(replacement), "{replacement} is not read");
+            assert!(!reads(deprecated), "{deprecated} is still read");
+        }
+    }
+
+    #[test]
+    fn serve_runs_every_selected_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("networks.toml");
+        std::fs::write(
+            &path,
+            "[node]\naddr = \
+             \"127.0.0.1:5001\"\n\n[networks.mainnet]\n\n[networks.testnet.node]\naddr = \
+             \"127.0.0.1:5002\"\n",
+        )
+        .unwrap();
+        let context = |args: &[&str]| {
+            let (root, config) = (dir.path().to_str().unwrap(), path.to_str().unwrap());
+            let common = ["schultz", "--root-dir", root, "--config", config];
+            Context::for_cli(&Cli::try_parse_from(common.iter().chain(args)).unwrap())
+        };
+
+        let ctx = context(&["--network", "mainnet,testnet", "serve"]).unwrap();
+        let networks: Vec<_> = ctx
+            .per_network()
+            .iter()
+            .map(|ctx| {
+                (
+                    ctx.network_name().unwrap().to_string(),
+                    ctx.config.node.addr,
+                )
+            })
+            .collect();
+        assert_eq!(
+            networks,
+            vec![
+                (
+                    "mainnet".to_string(),
+                    Some("127.0.0.1:5001".parse().unwrap())
+                ),
+                (
+                    "testnet".to_string(),
+                    Some("127.0.0.1:5002".parse().unwrap())
+                ),
+            ]
+        );
+        assert_eq!(
+            ctx.per_network()[1].reload_config().unwrap(),
+            ctx.per_network()[1].config
+        );
+
+        // Each keeps its peers apart in the data directory.
+        assert_eq!(
+            ctx.per_network()[1].config.network.peers_file,
+            Some(ctx.data_dir.peers_file(Some("testnet")))
+        );
+
+        let ctx = context(&["--network", "testnet", "peers"]).unwrap();
+        assert_eq!(
+            ctx.config.node.addr,
+            Some("127.0.0.1:5002".parse().unwrap())
+        );
+        assert!(context(&["--network", "mainnet,testnet", "peers"]).is_err());
+        assert!(context(&["--network", "devnet", "serve"]).is_err());
+        assert_eq!(context(&["serve"]).unwrap().per_network().len(), 1);
+    }
+
+    #[test]
+    fn only_nodes_keep_state_in_the_data_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("data");
+        let context = |args: &[&str]| {
+            let common = ["schultz", "--data-dir", root.to_str().unwrap()];
+            Context::for_cli(&Cli::try_parse_from(common.iter().chain(args)).unwrap()).unwrap()
+        };
+
+        let ctx = context(&["chainspec", "diff", "a", "b"]);
+        assert!(!root.exists());
+        assert_eq!(ctx.config.network.identity_dir, None);
+        assert_eq!(ctx.config.network.peers_file, None);
+
+        let ctx = context(&["serve"]);
+        assert_eq!(
+            ctx.data_dir.layout_version().unwrap(),
+            storage::LAYOUT_VERSION
+        );
+        assert_eq!(
+            ctx.config.network.identity_dir,
+            Some(ctx.data_dir.identity_dir(None))
+        );
+        assert_eq!(
+            ctx.config.network.peers_file,
+            Some(ctx.data_dir.peers_file(None))
+        );
+    }
 }
@@ -0,0 +1,332 @@
+//! Bandwidth accounting and throttling.
+//!
+//! Every frame read from or written to a peer is counted, and optionally
+//! charged against a global and a per-peer token bucket. Writes wait until the
+//! buckets allow them; reads are charged after the fact and a peer whose
+//! bucket is in debt is simply not read from until it has recovered, which
+//! pushes back on the sender through TCP flow control.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use thiserror::Error;
+
+/// A data rate in bytes per second, written like `5MBps` or `512KiB/s`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bandwidth(u64);
+
+impl Bandwidth {
+    pub const fn from_bytes_per_sec(bytes: u64) -> Self { Self(bytes) }
+
+    pub fn bytes_per_sec(&self) -> u64 { self.0 }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseBandwidthError {
+    #[error("missing amount in bandwidth {0:?}")]
+    MissingAmount(String),
+    #[error("unknown unit {0:?}, expected one of B, KB, MB, GB, KiB, MiB or GiB per second")]
+    UnknownUnit(String),
+    #[error("bandwidth must be greater than zero")]
+    Zero,
+    #[error("bandwidth {0:?} is too large")]
+    Overflow(String),
+}
+
+impl FromStr for Bandwidth {
+    type Err = ParseBandwidthError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        if amount.is_empty() {
+            return Err(ParseBandwidthError::MissingAmount(value.to_string()));
+        }
+
+        let unit = unit.trim();
+        let unit = unit
+            .strip_suffix("ps")
+            .or_else(|| unit.strip_suffix("/s"))
+            .ok_or_else(|| ParseBandwidthError::UnknownUnit(unit.to_string()))?;
+        let multiplier = unit_multiplier(unit)
+            .ok_or_else(|| ParseBandwidthError::UnknownUnit(unit.to_string()))?;
+
+        let bytes = amount
+            .parse::<u64>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(multiplier))
+            .ok_or_else(|| ParseBandwidthError::Overflow(value.to_string()))?;
+        if bytes == 0 {
+            return Err(ParseBandwidthError::Zero);
+        }
+
+        Ok(Bandwidth(bytes))
+    }
+}
+
+/// Number of bytes in one `unit`, e.g. 1024 in a `KiB`.
+pub(super) fn unit_multiplier(unit: &str) -> Option<u64> {
+    match unit {
+        "B" => Some(1),
+        "KB" => Some(1_000),
+        "MB" => Some(1_000_000),
+        "GB" => Some(1_000_000_000),
+        "KiB" => Some(1 << 10),
+        "MiB" => Some(1 << 20),
+        "GiB" => Some(1 << 30),
+        _ => None,
+    }
+}
+
+impl Display for Bandwidth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (unit, size) in [("GB", 1_000_000_000), ("MB", 1_000_000), ("KB", 1_000)] {
+            if self.0.is_multiple_of(size) {
+                return write!(f, "{}{unit}ps", self.0 / size);
+            }
+        }
+        write!(f, "{}Bps", self.0)
+    }
+}
+
+impl Serialize for Bandwidth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bandwidth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Token bucket holding up to one second worth of bytes.
+///
+/// Charges may push the bucket into debt, which is paid back at the
+/// configured rate before anything else is let through.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bandwidth: Bandwidth) -> Self {
+        let rate = bandwidth.bytes_per_sec() as f64;
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` out of the bucket without waiting and returns how long
+    /// it takes to pay back the resulting debt, if any.
+    pub fn charge(&self, bytes: usize) -> Duration { self.charge_at(bytes, Instant::now()) }
+
+    fn charge_at(&self, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.refill(now);
+        state.tokens -= bytes as f64;
+        self.debt_duration(state.tokens)
+    }
+
+    /// Takes `bytes` out of the bucket, waiting until the rate allows it.
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = self.charge(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Returns `true` while the bucket is in debt.
+    pub fn is_throttled(&self) -> bool { self.refill(Instant::now()).tokens < 0.0 }
+
+    fn refill(&self, now: Instant) -> std::sync::MutexGuard<'_, BucketState> {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+        let elapsed = now.saturating_duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.rate);
+        state.refilled_at = now;
+        state
+    }
+
+    fn debt_duration(&self, tokens: f64) -> Duration {
+        if tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-tokens / self.rate)
+        }
+    }
+}
+
+/// Bytes exchanged with a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Traffic {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+#[derive(Debug)]
+struct PeerBandwidth {
+    traffic: Traffic,
+    limiter: Option<RateLimiter>,
+}
+
+/// Byte counters and rate limiters of every connection.
+#[derive(Debug)]
+pub struct BandwidthTracker {
+    global: Option<RateLimiter>,
+    per_peer_limit: Option<Bandwidth>,
+    peers: Mutex<BTreeMap<SocketAddr, PeerBandwidth>>,
+}
+
+impl BandwidthTracker {
+    pub fn new(global_limit: Option<Bandwidth>, per_peer_limit: Option<Bandwidth>) -> Self {
+        Self {
+            global: global_limit.map(RateLimiter::new),
+            per_peer_limit,
+            peers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts `bytes` towards `addr` and charges them against both buckets,
+    /// returning how long the connection should stay quiet afterwards.
+    fn account(
+        &self,
+        addr: SocketAddr,
+        bytes: usize,
+        update: impl FnOnce(&mut Traffic),
+    ) -> Duration {
+        let mut peers = self.peers.lock().expect("bandwidth lock poisoned");
+        let peer = peers.entry(addr).or_insert_with(|| PeerBandwidth {
+            traffic: Traffic::default(),
+            limiter: self.per_peer_limit.map(RateLimiter::new),
+        });
+        update(&mut peer.traffic);
+
+        let peer_wait = peer.limiter.as_ref().map(|limiter| limiter.charge(bytes));
+        let global_wait = self.global.as_ref().map(|limiter| limiter.charge(bytes));
+        peer_wait.unwrap_or_default().max(global_wait.unwrap_or_default())
+    }
+
+    /// Records a frame read from `addr`.
+    pub fn record_read(&self, addr: SocketAddr, bytes: usize) {
+        self.account(addr, bytes, |traffic| traffic.bytes_read += bytes as u64);
+    }
+
+    /// Records a frame written to `addr` without waiting for the limiters.
+    pub fn record_write(&self, addr: SocketAddr, bytes: usize) {
+        self.account(addr, bytes, |traffic| traffic.bytes_written += bytes as u64);
+    }
+
+    /// Records a frame about to be written to `addr`, waiting until the
+    /// configured bandwidth allows it.
+    pub async fn throttle_write(&self, addr: SocketAddr, bytes: usize) {
+        let wait = self.account(addr, bytes, |traffic| traffic.bytes_written += bytes as u64);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Returns `true` if reading from `addr` should be paused.
+    pub fn is_read_throttled(&self, addr: &SocketAddr) -> bool {
+        if self.global.as_ref().is_some_and(RateLimiter::is_throttled) {
+            return true;
+        }
+        let peers = self.peers.lock().expect("bandwidth lock poisoned");
+        peers
+            .get(addr)
+            .and_then(|peer| peer.limiter.as_ref())
+            .is_some_and(RateLimiter::is_throttled)
+    }
+
+    /// Forgets a disconnected peer.
+    pub fn remove(&self, addr: &SocketAddr) {
+        self.peers.lock().expect("bandwidth lock poisoned").remove(addr);
+    }
+
+    /// Returns the traffic exchanged with every peer.
+    pub fn traffic(&self) -> BTreeMap<SocketAddr, Traffic> {
+        let peers = self.peers.lock().expect("bandwidth lock poisoned");
+        peers.iter().map(|(addr, peer)| (*addr, peer.traffic)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bandwidth_units() {
+        assert_eq!("5MBps".parse(), Ok(Bandwidth(5_000_000)));
+        assert_eq!("512KiB/s".parse(), Ok(Bandwidth(512 * 1024)));
+        assert_eq!(" 100Bps ".parse(), Ok(Bandwidth(100)));
+        assert_eq!(Bandwidth(5_000_000).to_string(), "5MBps");
+        assert_eq!(Bandwidth(1500).to_string(), "1500Bps");
+
+        assert!(matches!(
+            "5MB".parse::<Bandwidth>(),
+            Err(ParseBandwidthError::UnknownUnit(_))
+        ));
+        assert!(matches!(
+            "MBps".parse::<Bandwidth>(),
+            Err(ParseBandwidthError::MissingAmount(_))
+        ));
+        assert_eq!("0KBps".parse::<Bandwidth>(), Err(ParseBandwidthError::Zero));
+    }
+
+    #[test]
+    fn limiter_goes_into_debt_and_recovers() {
+        let limiter = RateLimiter::new(Bandwidth(1000));
+        let start = limiter.state.lock().unwrap().refilled_at;
+
+        assert_eq!(limiter.charge_at(1000, start), Duration::ZERO);
+        assert_eq!(limiter.charge_at(500, start), Duration::from_millis(500));
+        // Half a second later the debt is paid back.
+        assert_eq!(
+            limiter.charge_at(0, start + Duration::from_millis(500)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn tracks_traffic_per_peer() {
+        let tracker = BandwidthTracker::new(None, Some(Bandwidth(1000)));
+        let peer = SocketAddr::from(([127, 0, 0, 1], 34553));
+
+        tracker.record_read(peer, 600);
+        tracker.record_write(peer, 600);
+        assert!(tracker.is_read_throttled(&peer));
+
+        assert_eq!(
+            tracker.traffic()[&peer],
+            Traffic {
+                bytes_read: 600,
+                bytes_written: 600
+            }
+        );
+
+        tracker.remove(&peer);
+        assert!(!tracker.is_read_throttled(&peer));
+    }
+}
@@ -0,0 +1,118 @@
+//! Read and write buffers shared by the connections over time.
+//!
+//! Every connection reads into a buffer of its own and writes from another,
+//! both growing with the frames passing. A node crawling the network opens
+//! and closes hundreds of connections, which would each allocate and free
+//! them. The buffers of closed connections are kept in a [`BufferPool`] for
+//! the next ones instead, up to [`MAX_IDLE_BUFFERS`] of them, and those grown
+//! past [`MAX_POOLED_LEN`] by a large frame are freed rather than kept.
+
+use std::sync::Mutex;
+
+use bytes::BytesMut;
+use prometheus::IntCounter;
+use prometheus::IntGauge;
+
+use super::metrics::Metrics;
+
+/// Capacity buffers are allocated with.
+pub const BUFFER_LEN: usize = 64 * 1024;
+
+/// Buffers the pool keeps at most, 16 MiB of them unless grown.
+pub const MAX_IDLE_BUFFERS: usize = 256;
+
+/// Buffers larger than this are freed rather than pooled.
+pub const MAX_POOLED_LEN: usize = 1024 * 1024;
+
+/// Buffers of closed connections, waiting for new ones.
+#[derive(Debug)]
+pub struct BufferPool {
+    idle: Mutex<Vec<BytesMut>>,
+    reused: IntCounter,
+    allocated: IntCounter,
+    idle_buffers: IntGauge,
+    idle_bytes: IntGauge,
+}
+
+impl BufferPool {
+    /// An empty pool, keeping its statistics in `metrics`.
+    pub fn new(metrics: &Metrics) -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            reused: metrics.buffers_reused.clone(),
+            allocated: metrics.buffers_allocated.clone(),
+            idle_buffers: metrics.idle_buffers.clone(),
+            idle_bytes: metrics.idle_buffer_bytes.clone(),
+        }
+    }
+
+    /// An empty buffer, from the pool if it has one.
+    pub fn take(&self) -> BytesMut {
+        let mut idle = self.idle.lock().expect("buffer pool lock poisoned");
+        match idle.pop() {
+            Some(buffer) => {
+                self.reused.inc();
+                self.idle_buffers.dec();
+                self.idle_bytes.sub(buffer.capacity() as i64);
+                buffer
+            }
+            None => {
+                self.allocated.inc();
+                BytesMut::with_capacity(BUFFER_LEN)
+            }
+        }
+    }
+
+    /// Hands `buffer` back, dropping whatever it holds. It is freed if the
+    /// pool is full, or if it is too small or too large to be worth keeping.
+    pub fn put(&self, mut buffer: BytesMut) {
+        buffer.clear();
+        if !(BUFFER_LEN..=MAX_POOLED_LEN).contains(&buffer.capacity()) {
+            return;
+        }
+        let mut idle = self.idle.lock().expect("buffer pool lock poisoned");
+        if idle.len() < MAX_IDLE_BUFFERS {
+            self.idle_buffers.inc();
+            self.idle_bytes.add(buffer.capacity() as i64);
+            idle.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::Registry;
+
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_within_bounds() {
+        let metrics = Metrics::new(&Registry::new()).unwrap();
+        let pool = BufferPool::new(&metrics);
+
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(b"frame");
+        let ptr = buffer.as_ptr();
+        pool.put(buffer);
+        assert_eq!(metrics.idle_buffer_bytes.get(), BUFFER_LEN as i64);
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(metrics.buffers_reused.get(), 1);
+        assert_eq!(metrics.buffers_allocated.get(), 1);
+
+        // Buffers not worth keeping are freed.
+        pool.put(BytesMut::with_capacity(16));
+        pool.put(BytesMut::with_capacity(MAX_POOLED_LEN + 1));
+        assert_eq!(metrics.idle_buffers.get(), 0);
+
+        for _ in 0..MAX_IDLE_BUFFERS + 1 {
+            pool.put(BytesMut::with_capacity(BUFFER_LEN));
+        }
+        assert_eq!(metrics.idle_buffers.get(), MAX_IDLE_BUFFERS as i64);
+        assert_eq!(
+            metrics.idle_buffer_bytes.get(),
+            (MAX_IDLE_BUFFERS * BUFFER_LEN) as i64
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! What changed in the network between two crawls.
+//!
+//! Every crawl that finishes is kept as a [`CrawlSnapshot`] in the crawls
+//! directory, named after when it finished, and only the latest
+//! [`CRAWLS_KEPT`] are kept. The [`Churn`] between two of them tells the
+//! nodes that appeared and vanished, told apart by their [`NodeId`], those
+//! that changed their protocol version, and those whose handshake got
+//! markedly slower: by more than [`LATENCY_REGRESSION_PERCENT`] percent and
+//! [`MIN_LATENCY_REGRESSION_MS`] milliseconds, so the jitter of fast peers is
+//! not reported.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_types::ProtocolVersion;
+use casper_types::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::crawl::CrawlState;
+use super::crawl::CrawledNode;
+use super::node_id::NodeId;
+
+/// Name of the directory in the root directory crawls are kept in.
+pub const CRAWLS_DIR_NAME: &str = "crawls";
+
+/// Crawls kept, older ones are removed.
+pub const CRAWLS_KEPT: usize = 500;
+
+/// Percent a handshake has to get slower by to be reported.
+pub const LATENCY_REGRESSION_PERCENT: u64 = 50;
+
+/// Milliseconds a handshake has to get slower by to be reported.
+pub const MIN_LATENCY_REGRESSION_MS: u64 = 50;
+
+/// The nodes a finished crawl reached.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlSnapshot {
+    pub taken_at: Timestamp,
+    pub nodes: Vec<CrawledNode>,
+}
+
+impl CrawlSnapshot {
+    /// The nodes `state` reached so far, as of now.
+    pub fn of(state: &CrawlState) -> Self {
+        CrawlSnapshot {
+            taken_at: Timestamp::now(),
+            nodes: state.nodes().cloned().collect(),
+        }
+    }
+
+    /// Writes the crawl to `dir`, creating it if needed.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.json", self.taken_at.millis()));
+        // Readers never see half a crawl.
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// The latest crawl kept in `dir`, if any.
+    pub fn latest(dir: &Path) -> io::Result<Option<Self>> {
+        let files = match crawl_files(dir) {
+            Ok(files) => files,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let Some(path) = files.last() else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+}
+
+/// Removes all but the latest `keep` crawls in `dir`.
+pub fn prune(dir: &Path, keep: usize) -> io::Result<()> {
+    let files = crawl_files(dir)?;
+    for path in &files[..files.len().saturating_sub(keep)] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The crawl files in `dir`, oldest first, by the time in their name.
+fn crawl_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            if let Some(millis) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok()) {
+                files.push((millis, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path): (u64, _)| path).collect())
+}
+
+/// A node that appeared or vanished.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Peer {
+    pub node_id: NodeId,
+    pub public_addr: SocketAddr,
+}
+
+impl From<&CrawledNode> for Peer {
+    fn from(node: &CrawledNode) -> Self {
+        Peer {
+            node_id: node.node_id,
+            public_addr: node.public_addr,
+        }
+    }
+}
+
+/// A node that runs another protocol version than it did.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VersionChange {
+    pub node_id: NodeId,
+    pub from: ProtocolVersion,
+    pub to: ProtocolVersion,
+}
+
+/// A node whose handshake got slower.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LatencyRegression {
+    pub node_id: NodeId,
+    pub from_ms: u64,
+    pub to_ms: u64,
+}
+
+/// What changed between two crawls.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Churn {
+    /// When the earlier crawl finished.
+    pub since: Timestamp,
+    pub appeared: Vec<Peer>,
+    pub vanished: Vec<Peer>,
+    pub version_changes: Vec<VersionChange>,
+    pub latency_regressions: Vec<LatencyRegression>,
+}
+
+impl Churn {
+    pub fn between(before: &CrawlSnapshot, after: &CrawlSnapshot) -> Self {
+        let by_id = |snapshot: &CrawlSnapshot| -> BTreeMap<NodeId, CrawledNode> {
+            snapshot.nodes.iter().map(|node| (node.node_id, node.clone())).collect()
+        };
+        let (before_nodes, after_nodes) = (by_id(before), by_id(after));
+        let mut churn = Churn {
+            since: before.taken_at,
+            appeared: vec![],
+            vanished: before_nodes
+                .values()
+                .filter(|node| !after_nodes.contains_key(&node.node_id))
+                .map(Peer::from)
+                .collect(),
+            version_changes: vec![],
+            latency_regressions: vec![],
+        };
+        for (node_id, node) in &after_nodes {
+            let Some(earlier) = before_nodes.get(node_id) else {
+                churn.appeared.push(Peer::from(node));
+                continue;
+            };
+            if earlier.protocol_version != node.protocol_version {
+                churn.version_changes.push(VersionChange {
+                    node_id: *node_id,
+                    from: earlier.protocol_version,
+                    to: node.protocol_version,
+                });
+            }
+            if let (Some(from_ms), Some(to_ms)) = (earlier.latency_ms, node.latency_ms) {
+                let slower = to_ms.saturating_sub(from_ms);
+                if slower >= MIN_LATENCY_REGRESSION_MS
+                    && slower * 100 > from_ms * LATENCY_REGRESSION_PERCENT
+                {
+                    churn.latency_regressions.push(LatencyRegression {
+                        node_id: *node_id,
+                        from_ms,
+                        to_ms,
+                    });
+                }
+            }
+        }
+        churn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::CrawledNodeBuilder;
+
+    fn node(id: u8, version: (u32, u32, u32), latency_ms: u64) -> CrawledNode {
+        let addr = SocketAddr::from(([10, 0, 0, id], 35000));
+        CrawledNodeBuilder::new(id, addr)
+            .protocol_version(ProtocolVersion::from_parts(version.0, version.1, version.2))
+            .latency_ms(latency_ms)
+            .build()
+    }
+
+    #[test]
+    fn churn_tells_what_changed_by_node() {
+        let before = CrawlSnapshot {
+            taken_at: Timestamp::from(1),
+            nodes: vec![
+                node(1, (1, 5, 2), 100),
+                node(2, (1, 5, 2), 20),
+                node(3, (1, 5, 2), 20),
+                node(4, (1, 5, 2), 100),
+            ],
+        };
+        let after = CrawlSnapshot {
+            taken_at: Timestamp::from(2),
+            nodes: vec![
+                // Slower, but not by half.
+                node(1, (1, 5, 3), 140),
+                // Twice as slow, but by less than the minimum.
+                node(2, (1, 5, 2), 40),
+                node(4, (1, 5, 2), 200),
+                node(5, (1, 5, 2), 20),
+            ],
+        };
+
+        let churn = Churn::between(&before, &after);
+        assert_eq!(churn.since, Timestamp::from(1));
+        assert_eq!(churn.appeared, [Peer::from(&after.nodes[3])]);
+        assert_eq!(churn.vanished, [Peer::from(&before.nodes[2])]);
+        assert_eq!(
+            churn.version_changes,
+            [VersionChange {
+                node_id: before.nodes[0].node_id,
+                from: ProtocolVersion::from_parts(1, 5, 2),
+                to: ProtocolVersion::from_parts(1, 5, 3),
+            }]
+        );
+        assert_eq!(
+            churn.latency_regressions,
+            [LatencyRegression {
+                node_id: before.nodes[3].node_id,
+                from_ms: 100,
+                to_ms: 200,
+            }]
+        );
+    }
+
+    #[test]
+    fn the_latest_crawl_is_compared_with() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("crawls");
+        assert_eq!(CrawlSnapshot::latest(&dir).unwrap(), None);
+        for millis in [3, 1, 2] {
+            let snapshot = CrawlSnapshot {
+                taken_at: Timestamp::from(millis),
+                nodes: vec![node(1, (1, 5, 2), millis)],
+            };
+            snapshot.write(&dir).unwrap();
+        }
+        prune(&dir, 2).unwrap();
+        assert_eq!(crawl_files(&dir).unwrap().len(), 2);
+        let latest = CrawlSnapshot::latest(&dir).unwrap().unwrap();
+        assert_eq!(latest.taken_at, Timestamp::from(3));
+    }
+}
@@ -0,0 +1,433 @@
+//! Frame-level compression of post-handshake traffic.
+//!
+//! Peers advertise the algorithms they support in their handshake. If both
+//! sides have one in common, every later frame starts with a one byte tag
+//! telling whether, and how, the rest of it is compressed. Small frames are
+//! sent as they are, since compressing them costs more than it saves. Casper
+//! nodes never advertise compression, so frames to them stay untouched.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
+use clap::ValueEnum;
+use datasize::DataSize;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio_util::codec::Decoder;
+use tokio_util::codec::Encoder;
+use tokio_util::codec::LengthDelimitedCodec;
+use tracing::warn;
+
+use super::checksum;
+use super::mux::Demultiplexer;
+
+/// Frames smaller than this are never compressed.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Compression level used for zstd, favouring speed over ratio.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Length of the big-endian length every frame starts with.
+const LENGTH_PREFIX_LEN: usize = 4;
+
+const TAG_UNCOMPRESSED: u8 = 0;
+const TAG_ZSTD: u8 = 1;
+const TAG_LZ4: u8 = 2;
+
+/// A frame compression algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum, DataSize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// Every algorithm, best ratio first. Negotiation picks the first one both
+    /// peers support, so both ends agree without further round trips.
+    pub const ALL: [Compression; 2] = [Compression::Zstd, Compression::Lz4];
+
+    /// Picks the algorithm to use with a peer, if any.
+    pub fn negotiate(ours: &[Compression], theirs: &[Compression]) -> Option<Compression> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| ours.contains(algorithm) && theirs.contains(algorithm))
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Compression::Zstd => TAG_ZSTD,
+            Compression::Lz4 => TAG_LZ4,
+        }
+    }
+
+    /// Compresses `data`.
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+
+    /// Decompresses `data`, refusing to inflate it past `max_len` bytes.
+    pub fn decompress(&self, data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::decompress(data, max_len),
+            Compression::Lz4 => {
+                let declared_len = data
+                    .get(..4)
+                    .map(|prefix| u32::from_le_bytes(prefix.try_into().expect("4 bytes")))
+                    .ok_or_else(|| invalid_data("truncated lz4 frame"))?;
+                if declared_len as usize > max_len {
+                    return Err(invalid_data("lz4 frame inflates past the frame limit"));
+                }
+                lz4_flex::decompress_size_prepended(data).map_err(invalid_data)
+            }
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Zstd => write!(f, "zstd"),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Length-delimited framing with optional per-frame compression and
+/// [checksums](checksum).
+///
+/// Starts out passing frames through unchanged, which is what the handshake
+/// and Casper peers expect; [`FrameCodec::enable_compression`] switches to
+/// tagged frames once a peer agreed to it. Decoded frames of a multiplexed
+/// connection are fragments, which the codec keeps a [`Demultiplexer`] for.
+#[derive(Debug)]
+pub struct FrameCodec {
+    inner: LengthDelimitedCodec,
+    max_frame_len: usize,
+    compression: Option<Compression>,
+    demultiplexer: Option<Demultiplexer>,
+    /// Corrupt frames dropped before failing, if checksums are enabled.
+    max_corrupt_frames: Option<u32>,
+    /// Checksums to check once the peer's marker arrives, see
+    /// [`FrameCodec::expect_checksums`].
+    expected_checksums: Option<u32>,
+    /// Whether the marker is still to be written ahead of the first
+    /// checksummed frame, see [`FrameCodec::send_checksums`].
+    marker_owed: bool,
+    /// Corrupt frames dropped so far, and how many of them were not taken
+    /// with [`FrameCodec::take_corrupt_frames`] yet.
+    corrupt_frames: u32,
+    untaken_corrupt_frames: u32,
+}
+
+impl FrameCodec {
+    pub fn new(max_frame_len: usize) -> Self {
+        Self {
+            inner: LengthDelimitedCodec::builder().max_frame_length(max_frame_len).new_codec(),
+            max_frame_len,
+            compression: None,
+            demultiplexer: None,
+            max_corrupt_frames: None,
+            expected_checksums: None,
+            marker_owed: false,
+            corrupt_frames: 0,
+            untaken_corrupt_frames: 0,
+        }
+    }
+
+    /// Switches to tagged frames, compressing large ones with `compression`.
+    pub fn enable_compression(&mut self, compression: Compression) {
+        self.compression = Some(compression);
+    }
+
+    /// The algorithm frames are compressed with, if compression is enabled.
+    pub fn compression(&self) -> Option<Compression> { self.compression }
+
+    /// Takes every later frame for a fragment of a multiplexed message.
+    pub fn enable_multiplexing(&mut self) {
+        self.demultiplexer = Some(Demultiplexer::new(self.max_frame_len));
+    }
+
+    /// What puts messages back together, if multiplexing is enabled.
+    pub fn demultiplexer(&mut self) -> Option<&mut Demultiplexer> { self.demultiplexer.as_mut() }
+
+    /// Ends every later frame with a checksum, behind an empty frame
+    /// marking where they start.
+    ///
+    /// The marker goes out right before the first checksummed frame, so the
+    /// peer switches at that frame whatever it reads in the meantime.
+    pub fn send_checksums(&mut self) {
+        self.max_corrupt_frames = Some(0);
+        self.marker_owed = true;
+    }
+
+    /// Checks the checksum of every frame after the peer's marker, see
+    /// [`FrameCodec::send_checksums`]. Up to `max_corrupt_frames` frames
+    /// failing theirs are dropped, the next one fails decoding with a
+    /// [`CorruptFrame`](checksum::CorruptFrame).
+    ///
+    /// No message is ever empty, nor is a tagged frame, so the marker is
+    /// told apart from them.
+    pub fn expect_checksums(&mut self, max_corrupt_frames: u32) {
+        self.expected_checksums = Some(max_corrupt_frames);
+    }
+
+    /// Whether frames end with a checksum.
+    pub fn checksums(&self) -> bool { self.max_corrupt_frames.is_some() }
+
+    /// Number of corrupt frames dropped since the last call.
+    pub fn take_corrupt_frames(&mut self) -> u32 {
+        std::mem::take(&mut self.untaken_corrupt_frames)
+    }
+
+    /// Checks the checksum of `frame`, if checksums are enabled. Returns
+    /// `None` for a corrupt frame that is dropped.
+    fn verify(&mut self, frame: BytesMut) -> io::Result<Option<BytesMut>> {
+        let Some(max_corrupt_frames) = self.max_corrupt_frames else {
+            return Ok(Some(frame));
+        };
+        match checksum::verify(frame) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(corrupt) if self.corrupt_frames < max_corrupt_frames => {
+                warn!("Dropping a corrupt frame: {corrupt}");
+                self.corrupt_frames += 1;
+                self.untaken_corrupt_frames += 1;
+                Ok(None)
+            }
+            Err(corrupt) => Err(corrupt.into()),
+        }
+    }
+
+    fn untag(&self, mut frame: BytesMut) -> io::Result<Bytes> {
+        if frame.is_empty() {
+            return Err(invalid_data("empty frame"));
+        }
+        let body = frame.split_off(1);
+        let algorithm = match frame[0] {
+            TAG_UNCOMPRESSED => return Ok(body.freeze()),
+            TAG_ZSTD => Compression::Zstd,
+            TAG_LZ4 => Compression::Lz4,
+            tag => return Err(invalid_data(format!("unknown frame compression tag {tag}"))),
+        };
+        // Handed out as is, rather than copied into the read buffer.
+        algorithm.decompress(&body, self.max_frame_len).map(Bytes::from)
+    }
+
+    /// The tag and body `frame` is sent as: compressed if that makes it
+    /// smaller, as it is otherwise.
+    fn tag(compression: Compression, frame: &[u8]) -> io::Result<(u8, Cow<'_, [u8]>)> {
+        if frame.len() >= COMPRESSION_THRESHOLD {
+            let compressed = compression.compress(frame)?;
+            if compressed.len() < frame.len() {
+                return Ok((compression.tag(), Cow::Owned(compressed)));
+            }
+        }
+        Ok((TAG_UNCOMPRESSED, Cow::Borrowed(frame)))
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        while let Some(frame) = self.inner.decode(src)? {
+            if frame.is_empty() && self.expected_checksums.is_some() {
+                self.max_corrupt_frames = self.expected_checksums.take();
+                continue;
+            }
+            let Some(frame) = self.verify(frame)? else {
+                continue;
+            };
+            if self.compression.is_some() {
+                return self.untag(frame).map(Some);
+            }
+            return Ok(Some(frame.freeze()));
+        }
+        Ok(None)
+    }
+}
+
+/// Frames are written straight into the write buffer, behind the same
+/// length prefix [`LengthDelimitedCodec`] reads, so that neither the tag nor
+/// the checksum costs a copy of the frame.
+impl Encoder<Bytes> for FrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+        let (tag, body) = match self.compression {
+            Some(compression) => {
+                let (tag, body) = Self::tag(compression, &item)?;
+                (Some(tag), body)
+            }
+            None => (None, Cow::Borrowed(&item[..])),
+        };
+        let checksum_len = if self.checksums() {
+            checksum::CHECKSUM_LEN
+        } else {
+            0
+        };
+        let len = usize::from(tag.is_some()) + body.len() + checksum_len;
+        if len > self.max_frame_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame of {len} bytes is longer than {}", self.max_frame_len),
+            ));
+        }
+        if std::mem::take(&mut self.marker_owed) {
+            dst.reserve(LENGTH_PREFIX_LEN);
+            dst.put_u32(0);
+        }
+        dst.reserve(LENGTH_PREFIX_LEN + len);
+        dst.put_u32(len as u32);
+        let start = dst.len();
+        if let Some(tag) = tag {
+            dst.put_u8(tag);
+        }
+        dst.put_slice(&body);
+        if self.checksums() {
+            checksum::put(dst, start);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(codec: &mut FrameCodec, frame: &[u8]) -> (usize, Bytes) {
+        let mut wire = BytesMut::new();
+        codec.encode(Bytes::copy_from_slice(frame), &mut wire).unwrap();
+        let wire_len = wire.len();
+        (
+            wire_len,
+            codec.decode(&mut wire).unwrap().expect("a full frame"),
+        )
+    }
+
+    #[test]
+    fn negotiates_best_common_algorithm() {
+        use Compression::*;
+        assert_eq!(
+            Compression::negotiate(&[Lz4, Zstd], &[Zstd, Lz4]),
+            Some(Zstd)
+        );
+        assert_eq!(Compression::negotiate(&[Zstd, Lz4], &[Lz4]), Some(Lz4));
+        assert_eq!(Compression::negotiate(&[Zstd], &[]), None);
+    }
+
+    #[test]
+    fn compresses_large_frames_only() {
+        let large = vec![7u8; 64 * 1024];
+        let small = b"ping".to_vec();
+
+        for algorithm in Compression::ALL {
+            let mut codec = FrameCodec::new(1024 * 1024);
+            codec.enable_compression(algorithm);
+
+            let (wire_len, decoded) = roundtrip(&mut codec, &large);
+            assert_eq!(&decoded[..], &large[..]);
+            assert!(wire_len < large.len() / 10, "{algorithm} did not compress");
+
+            let (wire_len, decoded) = roundtrip(&mut codec, &small);
+            assert_eq!(&decoded[..], &small[..]);
+            // Length prefix plus the tag byte.
+            assert_eq!(wire_len, 4 + 1 + small.len());
+        }
+    }
+
+    #[test]
+    fn plain_codec_leaves_frames_untouched() {
+        let mut codec = FrameCodec::new(1024);
+        let (wire_len, decoded) = roundtrip(&mut codec, b"handshake");
+        assert_eq!(&decoded[..], b"handshake");
+        assert_eq!(wire_len, 4 + 9);
+    }
+
+    #[test]
+    fn frames_past_the_limit_are_not_written() {
+        let mut codec = FrameCodec::new(1024);
+        codec.send_checksums();
+        let mut wire = BytesMut::new();
+        let error = codec.encode(Bytes::from(vec![1; 1020]), &mut wire).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(wire.is_empty());
+    }
+
+    #[test]
+    fn checksums_cover_compressed_frames_and_drop_corrupt_ones() {
+        let large = vec![7u8; 64 * 1024];
+        let mut codec = FrameCodec::new(1024 * 1024);
+        codec.enable_compression(Compression::Lz4);
+        codec.send_checksums();
+        codec.expect_checksums(1);
+        let (wire_len, decoded) = roundtrip(&mut codec, b"ping");
+        assert_eq!(&decoded[..], b"ping");
+        // The marker, then the length prefix, tag, frame and checksum.
+        assert_eq!(wire_len, 4 + 4 + 1 + 4 + checksum::CHECKSUM_LEN);
+        let (wire_len, _) = roundtrip(&mut codec, b"ping");
+        assert_eq!(wire_len, 4 + 1 + 4 + checksum::CHECKSUM_LEN);
+
+        let mut wire = BytesMut::new();
+        for frame in [&large[..], b"ping", &large[..]] {
+            codec.encode(Bytes::copy_from_slice(frame), &mut wire).unwrap();
+        }
+        // Flip a bit of the first and the last frame, past their length.
+        wire[10] ^= 1;
+        let last = wire.len() - 1;
+        wire[last] ^= 1;
+
+        assert_eq!(&codec.decode(&mut wire).unwrap().unwrap()[..], b"ping");
+        assert_eq!(codec.take_corrupt_frames(), 1);
+        assert_eq!(codec.take_corrupt_frames(), 0);
+        let error = codec.decode(&mut wire).unwrap_err();
+        assert!(checksum::CorruptFrame::is(&error));
+    }
+
+    #[test]
+    fn checksums_are_checked_from_the_marker_on() {
+        let mut sender = FrameCodec::new(1024);
+        let mut receiver = FrameCodec::new(1024);
+        receiver.expect_checksums(0);
+
+        let mut wire = BytesMut::new();
+        sender.encode(Bytes::from_static(b"queued before"), &mut wire).unwrap();
+        sender.send_checksums();
+        sender.encode(Bytes::from_static(b"checksummed"), &mut wire).unwrap();
+
+        let decoded = receiver.decode(&mut wire).unwrap().unwrap();
+        assert_eq!(&decoded[..], b"queued before");
+        assert!(!receiver.checksums());
+        let decoded = receiver.decode(&mut wire).unwrap().unwrap();
+        assert_eq!(&decoded[..], b"checksummed");
+        assert!(receiver.checksums());
+        assert!(wire.is_empty());
+    }
+
+    #[test]
+    fn refuses_to_inflate_past_frame_limit() {
+        let bomb = Compression::Lz4.compress(&vec![0u8; 1024 * 1024]).unwrap();
+        assert!(Compression::Lz4.decompress(&bomb, 1024).is_err());
+
+        let bomb = Compression::Zstd.compress(&vec![0u8; 1024 * 1024]).unwrap();
+        assert!(Compression::Zstd.decompress(&bomb, 1024).is_err());
+    }
+}
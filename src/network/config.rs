@@ -0,0 +1,331 @@
+//! Tunables of the network manager.
+
+use std::path::PathBuf;
+
+use casper_types::TimeDiff;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::bandwidth::Bandwidth;
+use super::compression::Compression;
+use super::memory::ByteSize;
+use super::tls::CertSubject;
+use super::tls::TlsOptions;
+
+/// Default interval between two pings sent to the same peer.
+pub const DEFAULT_PING_INTERVAL: TimeDiff = TimeDiff::from_seconds(30);
+
+/// Default number of consecutive unanswered pings before a peer is dropped.
+pub const DEFAULT_MAX_MISSED_PONGS: u32 = 3;
+
+/// Default interval between two announcements of our own address.
+pub const DEFAULT_GOSSIP_INTERVAL: TimeDiff = TimeDiff::from_seconds(120);
+
+/// Default number of outgoing connections schultz tries to maintain.
+pub const DEFAULT_TARGET_OUTGOING_CONNECTIONS: usize = 8;
+
+/// Default memory each peer may hold in messages not yet processed or sent.
+pub const DEFAULT_MAX_PEER_MEMORY: ByteSize = ByteSize::from_bytes(64 << 20);
+
+/// Default remaining validity of our certificate below which we warn about
+/// it expiring.
+pub const DEFAULT_CERT_EXPIRY_WARNING: TimeDiff = TimeDiff::from_seconds(30 * 24 * 60 * 60);
+
+/// Default time over which connections set up with our previous certificate
+/// are closed once it is renewed.
+pub const DEFAULT_CERT_DRAIN_WINDOW: TimeDiff = TimeDiff::from_seconds(5 * 60);
+
+/// Default time peer certificates may be outside their validity period, to
+/// allow for clocks that are off.
+pub const DEFAULT_CERT_CLOCK_SKEW: TimeDiff = TimeDiff::from_seconds(5 * 60);
+
+/// Default number of times a bootnode is tried before giving up on it.
+pub const DEFAULT_BOOTSTRAP_ATTEMPTS: u32 = 3;
+
+/// Default delay before trying a bootnode again, doubled after every attempt.
+pub const DEFAULT_BOOTSTRAP_RETRY_DELAY: TimeDiff = TimeDiff::from_seconds(1);
+
+/// Default time a single connection attempt may take before it is given up
+/// on.
+pub const DEFAULT_CONNECT_TIMEOUT: TimeDiff = TimeDiff::from_seconds(5);
+
+/// Default time the addresses a bootnode's name resolved to are used before
+/// it is resolved again.
+pub const DEFAULT_BOOTNODE_RESOLVE_TTL: TimeDiff = TimeDiff::from_seconds(60);
+
+/// Default number of connections dialed at the same time.
+pub const DEFAULT_MAX_CONCURRENT_DIALS: usize = 16;
+
+/// Default number of connections held at the same time, in either direction.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 512;
+
+/// Default number of inbound connections held from the same IP.
+pub const DEFAULT_MAX_INBOUND_PER_IP: usize = 16;
+
+/// Default time a stamped handshake is accepted for, before or after it was
+/// made by our clock.
+pub const DEFAULT_HANDSHAKE_MAX_AGE: TimeDiff = TimeDiff::from_seconds(2 * 60);
+
+/// Network manager configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How often every fully connected peer is pinged.
+    pub ping_interval: TimeDiff,
+    /// Consecutive pings a peer may leave unanswered before it is
+    /// disconnected.
+    pub max_missed_pongs: u32,
+    /// How often our own address is gossiped to connected peers.
+    pub gossip_interval: TimeDiff,
+    /// Number of outgoing connections to dial from gossiped addresses.
+    pub target_outgoing_connections: usize,
+    /// Cap on the combined bandwidth of all connections, if any.
+    pub max_bandwidth: Option<Bandwidth>,
+    /// Cap on the bandwidth of each single connection, if any.
+    pub max_peer_bandwidth: Option<Bandwidth>,
+    /// Memory each peer may hold in messages not yet processed or sent;
+    /// peers sending more than we get through are disconnected.
+    pub max_peer_memory: ByteSize,
+    /// Frame compression algorithms offered to peers, none to disable.
+    pub compression: Vec<Compression>,
+    /// Whether to offer peers to multiplex channels over the connection, see
+    /// [`mux`](super::mux).
+    pub multiplexing: bool,
+    /// Whether to offer peers to end every frame with a checksum, see
+    /// [`checksum`](super::checksum).
+    pub checksums: bool,
+    /// Frames failing their checksum dropped before the connection is
+    /// closed, zero to close it on the first.
+    pub max_corrupt_frames: u32,
+    /// Directory our TLS identity is loaded from and saved to. Without one,
+    /// a new identity is generated on every start.
+    pub identity_dir: Option<PathBuf>,
+    /// Remaining validity of our certificate below which we warn about it
+    /// expiring.
+    pub cert_expiry_warning: TimeDiff,
+    /// Whether to renew our certificate, keeping its key, once it is due to
+    /// expire within `cert_expiry_warning`.
+    pub rotate_certs: bool,
+    /// Time over which connections set up with our previous certificate are
+    /// closed once it is renewed, so their peers reconnect and see the new
+    /// one without all of them dropping at once.
+    pub cert_drain_window: TimeDiff,
+    /// Common name peers' certificates have to be issued to. Without one,
+    /// any is accepted.
+    pub expected_peer_cn: Option<String>,
+    /// Time a peer's certificate may be before or after its validity period,
+    /// as our clock or the peer's may be off.
+    pub cert_clock_skew: TimeDiff,
+    /// Number of times a bootnode is tried before giving up on it.
+    pub bootstrap_attempts: u32,
+    /// Delay before trying a bootnode again, doubled after every attempt.
+    pub bootstrap_retry_delay: TimeDiff,
+    /// Time a single connection attempt may take before it is given up on.
+    /// Addresses a bootnode resolves to are raced, so a slow one does not
+    /// hold up the rest.
+    pub connect_timeout: TimeDiff,
+    /// Time the addresses a bootnode's name resolved to are used at most
+    /// before it is resolved again when reconnecting, sooner if the TTL of
+    /// the answer is shorter. The system resolver does not tell the TTL, so
+    /// its answers are kept this long; zero resolves the name every time.
+    pub bootnode_resolve_ttl: TimeDiff,
+    /// Connections dialed at the same time; further dials wait their turn.
+    pub max_concurrent_dials: usize,
+    /// Connections held at the same time, in either direction. Further peers
+    /// are refused.
+    pub max_connections: usize,
+    /// Inbound connections held from the same IP. Further ones from it are
+    /// refused.
+    pub max_inbound_per_ip: usize,
+    /// File the known-peers table is saved to, so a restarted node can
+    /// rejoin through them. Without one, peers are only kept in memory.
+    pub peers_file: Option<PathBuf>,
+    /// Whether to accept peers on another protocol version, for debugging.
+    /// Their network name still has to match.
+    pub allow_version_mismatch: bool,
+    /// TLS 1.3 cipher suites offered to peers, in order of preference. Empty
+    /// for OpenSSL's defaults.
+    pub tls_ciphersuites: Vec<String>,
+    /// TLS key exchange groups offered to peers, in order of preference.
+    /// Empty for OpenSSL's defaults.
+    pub tls_groups: Vec<String>,
+    /// Casper `secret_key.pem` of the validator we sign our handshakes as,
+    /// see [`ConsensusKeys`](crate::crypto::ConsensusKeys). Without one, our
+    /// handshakes carry no consensus certificate.
+    pub consensus_key: Option<PathBuf>,
+    /// How far from now the stamp of a validator's handshake may be, see
+    /// [`Handshake::check_stamp`](super::handshake::Handshake::check_stamp).
+    /// Also how long its nonce is remembered, to reject replays.
+    pub handshake_max_age: TimeDiff,
+    /// Whether to accept handshakes with a consensus certificate but no
+    /// stamp, as Casper validators send them, so long as the certificate
+    /// signs the TLS session.
+    pub accept_unstamped_certificates: bool,
+    /// File every frame exchanged with peers is recorded to, for debugging,
+    /// see [`wire_log`](super::wire_log). Without one, nothing is recorded.
+    pub wire_log: Option<PathBuf>,
+    /// Subject of the certificates we generate, the one of Casper nodes by
+    /// default. Last, as TOML has tables after plain values.
+    pub cert_subject: CertSubject,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ping_interval: DEFAULT_PING_INTERVAL,
+            max_missed_pongs: DEFAULT_MAX_MISSED_PONGS,
+            gossip_interval: DEFAULT_GOSSIP_INTERVAL,
+            target_outgoing_connections: DEFAULT_TARGET_OUTGOING_CONNECTIONS,
+            max_bandwidth: None,
+            max_peer_bandwidth: None,
+            max_peer_memory: DEFAULT_MAX_PEER_MEMORY,
+            compression: Compression::ALL.to_vec(),
+            multiplexing: true,
+            checksums: true,
+            max_corrupt_frames: 0,
+            identity_dir: None,
+            cert_expiry_warning: DEFAULT_CERT_EXPIRY_WARNING,
+            rotate_certs: false,
+            cert_drain_window: DEFAULT_CERT_DRAIN_WINDOW,
+            expected_peer_cn: None,
+            cert_clock_skew: DEFAULT_CERT_CLOCK_SKEW,
+            bootstrap_attempts: DEFAULT_BOOTSTRAP_ATTEMPTS,
+            bootstrap_retry_delay: DEFAULT_BOOTSTRAP_RETRY_DELAY,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            bootnode_resolve_ttl: DEFAULT_BOOTNODE_RESOLVE_TTL,
+            max_concurrent_dials: DEFAULT_MAX_CONCURRENT_DIALS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_inbound_per_ip: DEFAULT_MAX_INBOUND_PER_IP,
+            peers_file: None,
+            allow_version_mismatch: false,
+            tls_ciphersuites: Vec::new(),
+            tls_groups: Vec::new(),
+            consensus_key: None,
+            handshake_max_age: DEFAULT_HANDSHAKE_MAX_AGE,
+            accept_unstamped_certificates: false,
+            wire_log: None,
+            cert_subject: CertSubject::default(),
+        }
+    }
+}
+
+impl Config {
+    /// The TLS parameters to offer peers.
+    pub fn tls_options(&self) -> TlsOptions {
+        TlsOptions {
+            ciphersuites: self.tls_ciphersuites.clone(),
+            groups: self.tls_groups.clone(),
+            clock_skew: self.cert_clock_skew.into(),
+            expected_cn: self.expected_peer_cn.clone(),
+            // Taken from the environment by the transport, see `KeyLog`.
+            key_log: None,
+        }
+    }
+
+    /// Takes over the settings of `new` that can change while the node runs,
+    /// i.e. timeouts and connection limits. Returns the names of the other
+    /// settings that differ, which only take effect after a restart.
+    pub fn reload(&mut self, new: &Config) -> Vec<&'static str> {
+        let Config {
+            ping_interval,
+            max_missed_pongs,
+            gossip_interval,
+            target_outgoing_connections,
+            max_bandwidth,
+            max_peer_bandwidth,
+            max_peer_memory,
+            compression,
+            multiplexing,
+            checksums,
+            max_corrupt_frames,
+            identity_dir,
+            cert_expiry_warning,
+            rotate_certs,
+            cert_drain_window,
+            expected_peer_cn,
+            cert_clock_skew,
+            bootstrap_attempts,
+            bootstrap_retry_delay,
+            connect_timeout,
+            bootnode_resolve_ttl,
+            max_concurrent_dials,
+            max_connections,
+            max_inbound_per_ip,
+            peers_file,
+            allow_version_mismatch,
+            tls_ciphersuites,
+            tls_groups,
+            consensus_key,
+            handshake_max_age,
+            accept_unstamped_certificates,
+            wire_log,
+            cert_subject,
+        } = new.clone();
+
+        self.target_outgoing_connections = target_outgoing_connections;
+        self.cert_expiry_warning = cert_expiry_warning;
+        self.cert_drain_window = cert_drain_window;
+        self.bootstrap_attempts = bootstrap_attempts;
+        self.bootstrap_retry_delay = bootstrap_retry_delay;
+        self.connect_timeout = connect_timeout;
+        self.bootnode_resolve_ttl = bootnode_resolve_ttl;
+        self.max_concurrent_dials = max_concurrent_dials;
+        self.max_connections = max_connections;
+        self.max_inbound_per_ip = max_inbound_per_ip;
+
+        let restart = [
+            ("ping_interval", self.ping_interval == ping_interval),
+            (
+                "max_missed_pongs",
+                self.max_missed_pongs == max_missed_pongs,
+            ),
+            ("gossip_interval", self.gossip_interval == gossip_interval),
+            ("max_bandwidth", self.max_bandwidth == max_bandwidth),
+            (
+                "max_peer_bandwidth",
+                self.max_peer_bandwidth == max_peer_bandwidth,
+            ),
+            ("max_peer_memory", self.max_peer_memory == max_peer_memory),
+            ("compression", self.compression == compression),
+            ("multiplexing", self.multiplexing == multiplexing),
+            ("checksums", self.checksums == checksums),
+            (
+                "max_corrupt_frames",
+                self.max_corrupt_frames == max_corrupt_frames,
+            ),
+            ("identity_dir", self.identity_dir == identity_dir),
+            ("rotate_certs", self.rotate_certs == rotate_certs),
+            (
+                "expected_peer_cn",
+                self.expected_peer_cn == expected_peer_cn,
+            ),
+            ("cert_clock_skew", self.cert_clock_skew == cert_clock_skew),
+            ("peers_file", self.peers_file == peers_file),
+            (
+                "allow_version_mismatch",
+                self.allow_version_mismatch == allow_version_mismatch,
+            ),
+            (
+                "tls_ciphersuites",
+                self.tls_ciphersuites == tls_ciphersuites,
+            ),
+            ("tls_groups", self.tls_groups == tls_groups),
+            ("consensus_key", self.consensus_key == consensus_key),
+            (
+                "handshake_max_age",
+                self.handshake_max_age == handshake_max_age,
+            ),
+            (
+                "accept_unstamped_certificates",
+                self.accept_unstamped_certificates == accept_unstamped_certificates,
+            ),
+            ("wire_log", self.wire_log == wire_log),
+            ("cert_subject", self.cert_subject == cert_subject),
+        ];
+        restart
+            .into_iter()
+            .filter(|(_, unchanged)| !unchanged)
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
@@ -0,0 +1,828 @@
+//! A connection to a single peer.
+//!
+//! Every connection is split in two halves served by their own tasks: a
+//! reader decoding incoming frames, and a writer draining bounded queues of
+//! outgoing ones. A peer that is slow to read only fills up its own queues,
+//! while we keep processing what it and every other peer sends us.
+//!
+//! Every [`Channel`] has a queue of its own, which the writer takes from in
+//! [order of priority](Channel::BY_PRIORITY), so pongs are not stuck behind
+//! gossip to a slow peer. Each queue has its own [`QueueLimit`], which also
+//! tells what happens to frames once it is full. Once multiplexing is
+//! enabled, the writer interleaves the messages of every channel, see
+//! [`mux`](super::mux).
+//!
+//! Every connection gets a [`ConnectionId`] of its own, carried by the spans
+//! of both tasks, the events read from it and its metrics, so its lifetime can
+//! be followed from open to close even when the peer reconnects.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use casper_types::ProtocolVersion;
+use casper_types::Timestamp;
+use futures::SinkExt;
+use prometheus::IntGauge;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::ReadHalf;
+use tokio::io::WriteHalf;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio_util::codec::FramedRead;
+use tokio_util::codec::FramedWrite;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::info_span;
+use tracing::warn;
+use tracing::Instrument;
+
+use super::bandwidth::BandwidthTracker;
+use super::buffers::BufferPool;
+use super::compression::Compression;
+use super::compression::FrameCodec;
+use super::error::ManagerError;
+use super::manager::MAX_FRAME_LEN;
+use super::memory::MemoryBudget;
+use super::memory::Reservation;
+use super::metrics::Metrics;
+use super::mux::Channel;
+use super::mux::Multiplexer;
+use super::node_id::NodeId;
+use super::tls::Identity;
+use super::transport::BoxedStream;
+use super::wire_log::WireLog;
+
+/// Messages the writer of a multiplexed connection takes in from its queues
+/// at a time.
+pub const OUTBOUND_QUEUE_LEN: usize = 64;
+
+/// What becomes of a frame for a channel whose queue to the peer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhenFull {
+    /// The sender waits for room.
+    Wait,
+    /// The sender gets [`ManagerError::QueueFull`], e.g. to ask another peer.
+    Fail,
+    /// The frame is dropped, since the peer hears of it from others too.
+    Drop,
+}
+
+/// How many frames of a channel are queued for a peer, and what becomes of
+/// any more.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueLimit {
+    pub len: usize,
+    pub when_full: WhenFull,
+}
+
+impl QueueLimit {
+    /// The limit of the queue of `channel`.
+    pub const fn of(channel: Channel) -> Self {
+        match channel {
+            Channel::Control => Self {
+                len: 16,
+                when_full: WhenFull::Wait,
+            },
+            Channel::Responses => Self {
+                len: 64,
+                when_full: WhenFull::Wait,
+            },
+            Channel::Requests => Self {
+                len: 32,
+                when_full: WhenFull::Fail,
+            },
+            Channel::Gossip => Self {
+                len: 128,
+                when_full: WhenFull::Drop,
+            },
+        }
+    }
+}
+
+/// Identifies a connection for as long as the process runs, unlike the peer
+/// address, which a reconnecting peer keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct ConnectionId(u64);
+
+impl Display for ConnectionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+/// Hands out increasing connection ids, starting at 1.
+#[derive(Debug, Default)]
+pub struct ConnectionIds(AtomicU64);
+
+impl ConnectionIds {
+    pub fn next(&self) -> ConnectionId { ConnectionId(self.0.fetch_add(1, Ordering::Relaxed) + 1) }
+}
+
+/// Which side opened a connection, or which way a frame passed on one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// The peer connected to us, or sent us the frame.
+    Inbound,
+    /// We connected to the peer, or sent it the frame.
+    Outbound,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Inbound => write!(f, "inbound"),
+            Direction::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+/// What is known about a connection besides its id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub direction: Direction,
+    pub opened_at: Timestamp,
+    /// When a frame was last read from the peer.
+    pub last_seen: Option<Timestamp>,
+    /// Protocol version the peer completed its handshake with.
+    pub protocol_version: Option<ProtocolVersion>,
+}
+
+/// The [`ConnectionInfo`] of a connection, kept up to date by its reader.
+#[derive(Clone, Debug)]
+pub struct SharedInfo(Arc<Mutex<ConnectionInfo>>);
+
+impl SharedInfo {
+    fn new(direction: Direction) -> Self {
+        Self(Arc::new(Mutex::new(ConnectionInfo {
+            direction,
+            opened_at: Timestamp::now(),
+            last_seen: None,
+            protocol_version: None,
+        })))
+    }
+
+    pub fn get(&self) -> ConnectionInfo { *self.0.lock().expect("connection info lock poisoned") }
+
+    /// Records that a frame was just read from the peer.
+    pub fn seen(&self) {
+        self.0.lock().expect("connection info lock poisoned").last_seen = Some(Timestamp::now());
+    }
+
+    /// Records that the peer completed its handshake with `protocol_version`.
+    pub fn handshake_completed(&self, protocol_version: ProtocolVersion) {
+        self.0.lock().expect("connection info lock poisoned").protocol_version =
+            Some(protocol_version);
+    }
+}
+
+/// The receiving half of a connection, handing its read buffer back to the
+/// pool once dropped.
+pub struct FrameReader {
+    frames: FramedRead<ReadHalf<BoxedStream>, FrameCodec>,
+    buffers: Arc<BufferPool>,
+}
+
+impl Deref for FrameReader {
+    type Target = FramedRead<ReadHalf<BoxedStream>, FrameCodec>;
+
+    fn deref(&self) -> &Self::Target { &self.frames }
+}
+
+impl DerefMut for FrameReader {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.frames }
+}
+
+impl Drop for FrameReader {
+    fn drop(&mut self) { self.buffers.put(std::mem::take(self.frames.read_buffer_mut())) }
+}
+
+type FrameWriter = FramedWrite<WriteHalf<BoxedStream>, FrameCodec>;
+
+enum Outbound {
+    /// A frame for a channel, charged to the peer's memory budget until
+    /// written and counted in the depth of its queue until taken from it.
+    Frame(Channel, Bytes, Reservation, Queued),
+    /// Compresses every frame taken from the queues after this one, which is
+    /// queued on the control channel.
+    EnableCompression(Compression),
+    /// Multiplexes every frame taken from the queues after this one, which is
+    /// queued on the control channel.
+    EnableMultiplexing,
+    /// Ends every frame taken from the queues after this one with a
+    /// checksum, behind a marker telling the peer where they start. Queued
+    /// on the control channel.
+    EnableChecksums,
+}
+
+/// Counts a frame in the depth of its channel's queue for as long as it is
+/// kept.
+struct Queued(IntGauge);
+
+impl Queued {
+    fn new(depth: IntGauge) -> Self {
+        depth.inc();
+        Self(depth)
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) { self.0.dec(); }
+}
+
+/// Flow control of a multiplexed connection, passed to the writer apart from
+/// the frames so it never waits behind them.
+#[derive(Debug)]
+enum Credit {
+    /// The peer is ready for this many more bytes of the channel.
+    Received(Channel, u32),
+    /// We read this many bytes of the channel, which the peer is told.
+    Returned(Channel, u32),
+}
+
+/// Queues frames for the writer task of a connection.
+#[derive(Clone, Debug)]
+pub struct OutboundQueue {
+    id: ConnectionId,
+    peer_addr: SocketAddr,
+    /// A queue per channel, indexed like [`Channel::ALL`].
+    queues: [mpsc::Sender<Outbound>; 4],
+    credit: UnboundedSender<Credit>,
+    memory: Arc<MemoryBudget>,
+    metrics: Arc<Metrics>,
+}
+
+impl OutboundQueue {
+    /// The connection the frames are queued on.
+    pub fn id(&self) -> ConnectionId { self.id }
+
+    /// Queues `frame` on the control channel, waiting for room if the peer
+    /// is not keeping up.
+    ///
+    /// Fails right away if the frame would put the peer over its memory
+    /// budget.
+    pub async fn send(&self, frame: Bytes) -> Result<(), ManagerError> {
+        self.send_on(Channel::Control, frame).await
+    }
+
+    /// Queues `frame` on `channel`, doing what the channel's [`QueueLimit`]
+    /// says if its queue is full.
+    pub async fn send_on(&self, channel: Channel, frame: Bytes) -> Result<(), ManagerError> {
+        let reservation = self.memory.reserve(self.peer_addr, frame.len())?;
+        let depth = self.metrics.outbound_queue_depth.with_label_values(&[&channel.to_string()]);
+        let outbound = Outbound::Frame(channel, frame, reservation, Queued::new(depth));
+        self.push(channel, outbound).await
+    }
+
+    /// Compresses every frame taken from the queues from now on with
+    /// `compression`.
+    pub async fn enable_compression(&self, compression: Compression) -> Result<(), ManagerError> {
+        self.push(Channel::Control, Outbound::EnableCompression(compression)).await
+    }
+
+    /// Multiplexes every frame taken from the queues from now on.
+    pub async fn enable_multiplexing(&self) -> Result<(), ManagerError> {
+        self.push(Channel::Control, Outbound::EnableMultiplexing).await
+    }
+
+    /// Ends every frame taken from the queues from now on with a checksum.
+    pub async fn enable_checksums(&self) -> Result<(), ManagerError> {
+        self.push(Channel::Control, Outbound::EnableChecksums).await
+    }
+
+    /// Lets the writer send `credit` more bytes of `channel`, which the peer
+    /// returned.
+    pub fn credit_received(&self, channel: Channel, credit: u32) -> Result<(), ManagerError> {
+        self.push_credit(Credit::Received(channel, credit))
+    }
+
+    /// Returns `credit` for `channel` to the peer, as we read that much of
+    /// it.
+    pub fn return_credit(&self, channel: Channel, credit: u32) -> Result<(), ManagerError> {
+        self.push_credit(Credit::Returned(channel, credit))
+    }
+
+    fn push_credit(&self, credit: Credit) -> Result<(), ManagerError> {
+        self.credit
+            .send(credit)
+            .map_err(|_| ManagerError::ConnectionClosed(self.peer_addr))
+    }
+
+    async fn push(&self, channel: Channel, outbound: Outbound) -> Result<(), ManagerError> {
+        let queue = &self.queues[channel.index()];
+        let closed = || ManagerError::ConnectionClosed(self.peer_addr);
+        let outbound = match queue.try_send(outbound) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(_)) => return Err(closed()),
+            Err(TrySendError::Full(outbound)) => outbound,
+        };
+
+        self.metrics.outbound_queue_full.inc();
+        match QueueLimit::of(channel).when_full {
+            WhenFull::Wait => {
+                warn!(
+                    "Outbound {channel} queue to {:?} on connection {} is full, waiting for the \
+                     peer",
+                    self.peer_addr, self.id
+                );
+                self.metrics.outbound_queue_waiting.inc();
+                let sent = queue.send(outbound).await;
+                self.metrics.outbound_queue_waiting.dec();
+                sent.map_err(|_| closed())
+            }
+            WhenFull::Fail => Err(ManagerError::QueueFull(self.peer_addr, channel)),
+            WhenFull::Drop => {
+                debug!(
+                    "Outbound {channel} queue to {:?} on connection {} is full, dropping a frame",
+                    self.peer_addr, self.id
+                );
+                self.metrics
+                    .outbound_frames_dropped
+                    .with_label_values(&[&channel.to_string()])
+                    .inc();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The reader and writer tasks of a connection, stopped when dropped.
+#[derive(Debug)]
+pub struct Connection {
+    outbound: OutboundQueue,
+    info: SharedInfo,
+    /// The identity we presented setting the connection up, kept for as long
+    /// as it lasts even once we present another one.
+    identity: Option<Identity>,
+    /// The node at the other end, if the transport tells.
+    peer_id: Option<NodeId>,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+}
+
+impl Connection {
+    /// Splits `stream`, opened in `direction`, and starts serving both
+    /// halves, in spans carrying `id`.
+    ///
+    /// The writer sends queued frames, throttled by `bandwidth`, recording
+    /// them to `wire_log` if there is one. The reader is the future returned
+    /// by `read`, which gets the receiving half of the stream, a handle to
+    /// queue replies with and the connection's info to keep up to date.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open<F, R>(
+        id: ConnectionId,
+        peer_addr: SocketAddr,
+        direction: Direction,
+        stream: BoxedStream,
+        bandwidth: Arc<BandwidthTracker>,
+        memory: Arc<MemoryBudget>,
+        buffers: Arc<BufferPool>,
+        metrics: Arc<Metrics>,
+        wire_log: Option<Arc<WireLog>>,
+        read: F,
+    ) -> Self
+    where
+        F: FnOnce(FrameReader, OutboundQueue, SharedInfo) -> R,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        let (read_half, write_half) = tokio::io::split(stream);
+        let (queue_tx, queue_rx): (Vec<_>, Vec<_>) = Channel::ALL
+            .into_iter()
+            .map(|channel| mpsc::channel(QueueLimit::of(channel).len))
+            .unzip();
+        let queue_tx = queue_tx.try_into().expect("a queue per channel");
+        let queue_rx = queue_rx.try_into().expect("a queue per channel");
+        let (credit_tx, credit_rx) = mpsc::unbounded_channel();
+        let outbound = OutboundQueue {
+            id,
+            peer_addr,
+            queues: queue_tx,
+            credit: credit_tx,
+            memory,
+            metrics: metrics.clone(),
+        };
+
+        let info = SharedInfo::new(direction);
+
+        let span = info_span!("connection", id = %id, peer = %peer_addr);
+        info!(parent: &span, "Opened {direction} connection {id} to {peer_addr:?}");
+
+        let mut frames_out = FramedWrite::new(write_half, FrameCodec::new(MAX_FRAME_LEN));
+        *frames_out.write_buffer_mut() = buffers.take();
+        let writer = Writer {
+            peer_addr,
+            frames: frames_out,
+            bandwidth,
+            buffers: buffers.clone(),
+            metrics,
+            wire_log,
+            mux: Multiplexer::default(),
+            multiplexing: false,
+        };
+        let writer =
+            tokio::spawn(Self::write(writer, queue_rx, credit_rx).instrument(span.clone()));
+        let mut frames_in = FramedRead::new(read_half, FrameCodec::new(MAX_FRAME_LEN));
+        *frames_in.read_buffer_mut() = buffers.take();
+        let frames_in = FrameReader {
+            frames: frames_in,
+            buffers,
+        };
+        let reader = tokio::spawn(read(frames_in, outbound.clone(), info.clone()).instrument(span));
+
+        Self {
+            outbound,
+            info,
+            identity: None,
+            peer_id: None,
+            reader,
+            writer,
+        }
+    }
+
+    /// Records that we presented `identity` setting the connection up.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Records which node is at the other end.
+    pub fn with_peer_id(mut self, peer_id: Option<NodeId>) -> Self {
+        self.peer_id = peer_id;
+        self
+    }
+
+    pub fn id(&self) -> ConnectionId { self.outbound.id }
+
+    /// The node at the other end, if it was recorded.
+    pub fn peer_id(&self) -> Option<NodeId> { self.peer_id }
+
+    /// The identity we presented setting the connection up, if it was
+    /// recorded.
+    pub fn identity(&self) -> Option<&Identity> { self.identity.as_ref() }
+
+    /// A handle to queue frames to the peer with.
+    pub fn outbound(&self) -> OutboundQueue { self.outbound.clone() }
+
+    pub fn info(&self) -> ConnectionInfo { self.info.get() }
+
+    async fn write(
+        mut writer: Writer,
+        mut queues: [mpsc::Receiver<Outbound>; 4],
+        mut credit: UnboundedReceiver<Credit>,
+    ) {
+        loop {
+            while let Ok(update) = credit.try_recv() {
+                writer.apply_credit(update);
+            }
+            if writer.multiplexing {
+                // Take in what is queued so every channel gets its turn, but
+                // no more than that, so senders still wait for a peer that
+                // is not keeping up.
+                while writer.accepts_more() {
+                    let Some(outbound) = Self::next_queued(&mut queues) else {
+                        break;
+                    };
+                    if !writer.take(outbound).await {
+                        return;
+                    }
+                }
+                if let Some(frame) = writer.mux.next_frame() {
+                    if !writer.write_frame(frame).await {
+                        return;
+                    }
+                    continue;
+                }
+            } else if let Some(outbound) = Self::next_queued(&mut queues) {
+                if !writer.take(outbound).await {
+                    return;
+                }
+                continue;
+            }
+
+            let accepting = writer.accepts_more();
+            let [control, requests, responses, gossip] = &mut queues;
+            let outbound = tokio::select! {
+                biased;
+                update = credit.recv() => match update {
+                    Some(update) => {
+                        writer.apply_credit(update);
+                        continue;
+                    }
+                    None => break,
+                },
+                outbound = control.recv(), if accepting => outbound,
+                outbound = responses.recv(), if accepting => outbound,
+                outbound = requests.recv(), if accepting => outbound,
+                outbound = gossip.recv(), if accepting => outbound,
+            };
+            match outbound {
+                Some(outbound) => {
+                    if !writer.take(outbound).await {
+                        return;
+                    }
+                }
+                None => break,
+            }
+        }
+        // Nothing is left to send, so close our side of the stream, which
+        // over TLS tells the peer with a close_notify.
+        if let Err(e) = writer.frames.close().await {
+            warn!("Error closing the stream to {:?}: {e:?}", writer.peer_addr);
+        }
+    }
+
+    /// Takes what comes first from the queues, without waiting.
+    fn next_queued(queues: &mut [mpsc::Receiver<Outbound>; 4]) -> Option<Outbound> {
+        Channel::BY_PRIORITY
+            .into_iter()
+            .find_map(|channel| queues[channel.index()].try_recv().ok())
+    }
+}
+
+/// The state of a writer task.
+struct Writer {
+    peer_addr: SocketAddr,
+    frames: FrameWriter,
+    bandwidth: Arc<BandwidthTracker>,
+    /// Where the write buffer goes once the writer is done.
+    buffers: Arc<BufferPool>,
+    metrics: Arc<Metrics>,
+    wire_log: Option<Arc<WireLog>>,
+    /// Keeps track of credit from the start, since it may arrive before
+    /// multiplexing is enabled.
+    mux: Multiplexer<Reservation>,
+    multiplexing: bool,
+}
+
+impl Writer {
+    /// Whether to take more from the queues, which the multiplexer only does
+    /// until it holds [`OUTBOUND_QUEUE_LEN`] messages.
+    fn accepts_more(&self) -> bool { !self.multiplexing || self.mux.queued() < OUTBOUND_QUEUE_LEN }
+
+    /// Takes in `outbound`, writing frames right away unless multiplexing.
+    /// Returns whether the connection is still usable.
+    async fn take(&mut self, outbound: Outbound) -> bool {
+        match outbound {
+            Outbound::Frame(channel, frame, reservation, _queued) => {
+                if let Some(wire_log) = &self.wire_log {
+                    wire_log.record(Direction::Outbound, self.peer_addr, &frame);
+                }
+                if !self.multiplexing {
+                    return self.write_frame(frame).await;
+                }
+                self.mux.push(channel, frame, reservation);
+            }
+            Outbound::EnableCompression(compression) => {
+                info!(
+                    "Compressing frames to {:?} with {compression}",
+                    self.peer_addr
+                );
+                self.frames.encoder_mut().enable_compression(compression);
+            }
+            Outbound::EnableMultiplexing => {
+                info!("Multiplexing frames to {:?}", self.peer_addr);
+                self.multiplexing = true;
+            }
+            Outbound::EnableChecksums => {
+                info!("Checksumming frames to {:?}", self.peer_addr);
+                self.frames.encoder_mut().send_checksums();
+            }
+        }
+        true
+    }
+
+    fn apply_credit(&mut self, update: Credit) {
+        match update {
+            Credit::Received(channel, credit) => self.mux.add_credit(channel, credit),
+            Credit::Returned(channel, credit) => self.mux.return_credit(channel, credit),
+        }
+    }
+
+    /// Writes `frame`, returning whether the connection is still usable.
+    async fn write_frame(&mut self, frame: Bytes) -> bool {
+        self.bandwidth.throttle_write(self.peer_addr, frame.len()).await;
+        self.metrics.bytes_written.inc_by(frame.len() as u64);
+        if let Err(e) = self.frames.send(frame).await {
+            error!("Error writing to {:?}: {e:?}", self.peer_addr);
+            return false;
+        }
+        true
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) { self.buffers.put(std::mem::take(self.frames.write_buffer_mut())) }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        info!(
+            "Closed connection {} to {:?}",
+            self.outbound.id, self.outbound.peer_addr
+        );
+        self.reader.abort();
+        self.writer.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use prometheus::Registry;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::network::config::DEFAULT_MAX_PEER_MEMORY;
+    use crate::network::mux::Demultiplexer;
+    use crate::network::mux::Demuxed;
+
+    #[tokio::test]
+    async fn slow_reader_does_not_block_incoming_frames() {
+        let (ours, theirs) = tokio::io::duplex(1024);
+        let metrics = Arc::new(Metrics::new(&Registry::new()).unwrap());
+        let bandwidth = Arc::new(BandwidthTracker::new(None, None));
+        let memory = Arc::new(MemoryBudget::new(DEFAULT_MAX_PEER_MEMORY));
+
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+        let connection = Connection::open(
+            ConnectionIds::default().next(),
+            SocketAddr::from(([127, 0, 0, 1], 5000)),
+            Direction::Outbound,
+            Box::new(ours),
+            bandwidth,
+            memory,
+            Arc::new(BufferPool::new(&metrics)),
+            metrics.clone(),
+            None,
+            |mut frames, _outbound, info| async move {
+                while let Some(Ok(frame)) = frames.next().await {
+                    info.seen();
+                    let _ = received_tx.send(frame);
+                }
+            },
+        );
+
+        // The peer never reads, so our writes stall once the pipe and the
+        // queue are full.
+        let outbound = connection.outbound();
+        let stalled = tokio::spawn(async move {
+            for _ in 0..OUTBOUND_QUEUE_LEN * 4 {
+                outbound.send(Bytes::from(vec![0u8; 512])).await.unwrap();
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while metrics.outbound_queue_waiting.get() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the outbound queue filled up");
+        assert!(metrics.outbound_queue_full.get() > 0);
+
+        // Meanwhile, frames from the peer still come through.
+        let (mut peer_read, peer_write) = tokio::io::split(theirs);
+        let mut peer = FramedWrite::new(peer_write, FrameCodec::new(MAX_FRAME_LEN));
+        peer.send(Bytes::from_static(b"hello")).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+            .await
+            .expect("the frame was read while our writes were stalled")
+            .unwrap();
+        assert_eq!(&frame[..], b"hello");
+        assert!(connection.info().last_seen.is_some());
+
+        // Draining our side lets the writer finish.
+        let drain = tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while peer_read.read(&mut buf).await.unwrap_or(0) > 0 {}
+        });
+        tokio::time::timeout(Duration::from_secs(5), stalled).await.unwrap().unwrap();
+        assert_eq!(metrics.outbound_queue_waiting.get(), 0);
+        drain.abort();
+    }
+
+    #[tokio::test]
+    async fn multiplexed_messages_do_not_wait_for_larger_ones() {
+        let (ours, theirs) = tokio::io::duplex(1024);
+        let metrics = Arc::new(Metrics::new(&Registry::new()).unwrap());
+        let connection = Connection::open(
+            ConnectionIds::default().next(),
+            SocketAddr::from(([127, 0, 0, 1], 5000)),
+            Direction::Outbound,
+            Box::new(ours),
+            Arc::new(BandwidthTracker::new(None, None)),
+            Arc::new(MemoryBudget::new(DEFAULT_MAX_PEER_MEMORY)),
+            Arc::new(BufferPool::new(&metrics)),
+            metrics,
+            None,
+            |mut frames, outbound, _info| async move {
+                frames.decoder_mut().enable_multiplexing();
+                while let Some(Ok(frame)) = frames.next().await {
+                    let demultiplexer = frames.decoder_mut().demultiplexer().unwrap();
+                    if let Ok(Demuxed::Credit(channel, credit)) = demultiplexer.receive(frame) {
+                        outbound.credit_received(channel, credit).unwrap();
+                    }
+                }
+            },
+        );
+
+        let outbound = connection.outbound();
+        outbound.enable_multiplexing().await.unwrap();
+        let gossip = Bytes::from(vec![7u8; 1 << 20]);
+        outbound.send_on(Channel::Gossip, gossip.clone()).await.unwrap();
+        outbound.send(Bytes::from_static(b"ping")).await.unwrap();
+
+        // The peer hands credit back as it reads, or the gossip would stall
+        // after the first window.
+        let (peer_read, peer_write) = tokio::io::split(theirs);
+        let mut peer_in = FramedRead::new(peer_read, FrameCodec::new(MAX_FRAME_LEN));
+        let mut peer_out = FramedWrite::new(peer_write, FrameCodec::new(MAX_FRAME_LEN));
+        let mut demultiplexer = Demultiplexer::new(MAX_FRAME_LEN);
+        let mut credit = Multiplexer::<()>::default();
+        let mut received = vec![];
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while received.len() < 2 {
+                let frame = peer_in.next().await.unwrap().unwrap();
+                if let Demuxed::Message(channel, message) = demultiplexer.receive(frame).unwrap() {
+                    received.push((channel, message));
+                }
+                for (channel, returned) in demultiplexer.take_credit() {
+                    credit.return_credit(channel, returned);
+                    peer_out.send(credit.next_frame().unwrap()).await.unwrap();
+                }
+            }
+        })
+        .await
+        .expect("both messages arrived");
+        assert_eq!(
+            received,
+            vec![
+                (Channel::Control, Bytes::from_static(b"ping")),
+                (Channel::Gossip, gossip),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn control_frames_jump_the_queues_of_a_slow_peer() {
+        let (ours, theirs) = tokio::io::duplex(64);
+        let metrics = Arc::new(Metrics::new(&Registry::new()).unwrap());
+        let peer_addr = SocketAddr::from(([127, 0, 0, 1], 5000));
+        let connection = Connection::open(
+            ConnectionIds::default().next(),
+            peer_addr,
+            Direction::Outbound,
+            Box::new(ours),
+            Arc::new(BandwidthTracker::new(None, None)),
+            Arc::new(MemoryBudget::new(DEFAULT_MAX_PEER_MEMORY)),
+            Arc::new(BufferPool::new(&metrics)),
+            metrics.clone(),
+            None,
+            |_frames, _outbound, _info| futures::future::pending(),
+        );
+
+        // The writer does not get to run before the test yields, so every
+        // frame is queued before any is written.
+        let outbound = connection.outbound();
+        let gossip_limit = QueueLimit::of(Channel::Gossip).len;
+        for _ in 0..gossip_limit + 10 {
+            outbound.send_on(Channel::Gossip, Bytes::from(vec![1; 100])).await.unwrap();
+        }
+        let depth =
+            |channel: &str| metrics.outbound_queue_depth.with_label_values(&[channel]).get();
+        assert_eq!(depth("gossip"), gossip_limit as i64);
+        assert_eq!(
+            metrics.outbound_frames_dropped.with_label_values(&["gossip"]).get(),
+            10
+        );
+
+        for _ in 0..QueueLimit::of(Channel::Requests).len {
+            outbound.send_on(Channel::Requests, Bytes::from_static(b"get")).await.unwrap();
+        }
+        assert!(matches!(
+            outbound.send_on(Channel::Requests, Bytes::from_static(b"get")).await,
+            Err(ManagerError::QueueFull(addr, Channel::Requests)) if addr == peer_addr
+        ));
+        outbound.send(Bytes::from_static(b"pong")).await.unwrap();
+
+        let mut peer = FramedRead::new(theirs, FrameCodec::new(MAX_FRAME_LEN));
+        assert_eq!(&peer.next().await.unwrap().unwrap()[..], b"pong");
+        assert_eq!(&peer.next().await.unwrap().unwrap()[..], b"get");
+        assert!(depth("control") == 0 && depth("requests") < 32);
+    }
+}
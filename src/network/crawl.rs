@@ -0,0 +1,383 @@
+//! Crawling the network: dialing every address peers gossip, to map the
+//! nodes it is made of.
+//!
+//! A crawl starts from a few addresses. Each is dialed with a fresh
+//! [`PeerSession`](super::session::PeerSession), listened to for the
+//! addresses it gossips for a while, and those not seen before are queued in
+//! turn. A [`Crawler`] dials up to its number of workers at a time. Nodes are
+//! told apart by their [`NodeId`], so one reachable on several addresses is
+//! counted once, with all of them.
+//!
+//! The [`CrawlState`] is written to a checkpoint file every
+//! [`CHECKPOINT_INTERVAL`] and when the crawl is stopped. Loading it resumes
+//! the crawl where it stopped, dialing again the addresses that were being
+//! dialed.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use casper_types::ProtocolVersion;
+use miette::Diagnostic;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::task::JoinSet;
+use tokio::time::interval_at;
+use tokio::time::timeout;
+use tokio::time::timeout_at;
+use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use super::config::Config;
+use super::error::ManagerError;
+use super::gossip::GossipMessage;
+use super::gossip::NodePayload;
+use super::message::Message;
+use super::node_id::NodeId;
+use super::session;
+use super::tls::Identity;
+use crate::primitives::Chainspec;
+
+/// How often the crawl state is written to the checkpoint file.
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest a peer gets to accept the connection and complete the handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Error)]
+pub enum CrawlError {
+    #[error("Could not read the crawl checkpoint {}", .0.display())]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Invalid crawl checkpoint {}", .0.display())]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Could not write the crawl checkpoint {}", .0.display())]
+    Write(PathBuf, #[source] io::Error),
+}
+
+impl Diagnostic for CrawlError {}
+
+/// A node reached during the crawl.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawledNode {
+    pub node_id: NodeId,
+    /// Addresses the node was reached on.
+    pub addrs: BTreeSet<SocketAddr>,
+    /// Address the node announces in its handshake.
+    pub public_addr: SocketAddr,
+    pub network_name: String,
+    pub protocol_version: ProtocolVersion,
+    /// Milliseconds the crawler took to connect and complete the handshake,
+    /// the least of them if it was reached more than once.
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    /// Addresses the node gossiped while it was listened to.
+    pub peers: BTreeSet<SocketAddr>,
+}
+
+/// Progress of a crawl, as kept in its checkpoint file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlState {
+    /// Addresses waiting to be dialed, in the order they were learned.
+    queue: VecDeque<SocketAddr>,
+    /// Addresses being dialed, dialed again when the crawl is resumed.
+    in_flight: BTreeSet<SocketAddr>,
+    /// Every address queued so far, so none is dialed twice.
+    seen: BTreeSet<SocketAddr>,
+    /// The nodes reached, by their id.
+    nodes: BTreeMap<NodeId, CrawledNode>,
+    /// Addresses that could not be crawled, and why.
+    unreachable: BTreeMap<SocketAddr, String>,
+}
+
+impl CrawlState {
+    /// A crawl starting from `seeds`.
+    pub fn new(seeds: impl IntoIterator<Item = SocketAddr>) -> Self {
+        let mut state = CrawlState::default();
+        for addr in seeds {
+            state.enqueue(addr);
+        }
+        state
+    }
+
+    /// The crawl checkpointed to `path`, with the addresses that were being
+    /// dialed queued first.
+    pub fn load(path: &Path) -> Result<Self, CrawlError> {
+        let contents = fs::read(path).map_err(|e| CrawlError::Read(path.to_path_buf(), e))?;
+        let mut state: CrawlState = serde_json::from_slice(&contents)
+            .map_err(|e| CrawlError::Parse(path.to_path_buf(), e))?;
+        for addr in std::mem::take(&mut state.in_flight).into_iter().rev() {
+            state.queue.push_front(addr);
+        }
+        Ok(state)
+    }
+
+    /// Writes the crawl to `path`, replacing the previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<(), CrawlError> {
+        let error = |e| CrawlError::Write(path.to_path_buf(), e);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        // An interrupted write leaves the previous checkpoint in place.
+        let partial = path.with_extension("partial");
+        let contents = serde_json::to_vec(self).map_err(|e| error(e.into()))?;
+        fs::write(&partial, contents).map_err(error)?;
+        fs::rename(&partial, path).map_err(error)
+    }
+
+    /// Queues `addr` unless it was queued before or cannot be dialed.
+    fn enqueue(&mut self, addr: SocketAddr) {
+        if addr.ip().is_unspecified() || addr.port() == 0 {
+            return;
+        }
+        if self.seen.insert(addr) {
+            self.queue.push_back(addr);
+        }
+    }
+
+    /// The next address to dial, counted as being dialed until it is
+    /// [recorded](Self::record).
+    fn next(&mut self) -> Option<SocketAddr> {
+        let addr = self.queue.pop_front()?;
+        self.in_flight.insert(addr);
+        Some(addr)
+    }
+
+    /// Records what dialing `addr` came to, queueing the addresses the node
+    /// announced and gossiped.
+    fn record(&mut self, addr: SocketAddr, outcome: Result<CrawledNode, String>) {
+        self.in_flight.remove(&addr);
+        let node = match outcome {
+            Ok(node) => node,
+            Err(error) => {
+                self.unreachable.insert(addr, error);
+                return;
+            }
+        };
+        self.enqueue(node.public_addr);
+        for peer in &node.peers {
+            self.enqueue(*peer);
+        }
+        match self.nodes.get_mut(&node.node_id) {
+            Some(known) => {
+                known.addrs.extend(node.addrs);
+                known.peers.extend(node.peers);
+                known.latency_ms = known.latency_ms.into_iter().chain(node.latency_ms).min();
+            }
+            None => {
+                self.nodes.insert(node.node_id, node);
+            }
+        }
+    }
+
+    /// The nodes reached so far, ordered by id.
+    pub fn nodes(&self) -> impl Iterator<Item = &CrawledNode> { self.nodes.values() }
+
+    /// Addresses that could not be crawled, and why.
+    pub fn unreachable(&self) -> &BTreeMap<SocketAddr, String> { &self.unreachable }
+
+    /// Number of addresses still to be dialed.
+    pub fn remaining(&self) -> usize { self.queue.len() + self.in_flight.len() }
+}
+
+/// Dials the addresses of a [`CrawlState`] a number of workers at a time.
+pub struct Crawler {
+    identity: Identity,
+    chainspec: Arc<Chainspec>,
+    config: Arc<Config>,
+    workers: usize,
+    listen: Duration,
+}
+
+impl Crawler {
+    /// A crawler dialing as `identity`, `workers` peers at a time, and
+    /// listening to each for `listen`.
+    pub fn new(
+        identity: Identity,
+        chainspec: Chainspec,
+        config: Config,
+        workers: usize,
+        listen: Duration,
+    ) -> Self {
+        Crawler {
+            identity,
+            chainspec: Arc::new(chainspec),
+            config: Arc::new(config),
+            workers: workers.max(1),
+            listen,
+        }
+    }
+
+    /// Crawls until no address is left to dial or `stop` completes, writing
+    /// `state` to `checkpoint` on the way and when done. Returns whether the
+    /// crawl finished.
+    pub async fn run(
+        &self,
+        state: &mut CrawlState,
+        checkpoint: Option<&Path>,
+        stop: impl Future<Output = ()>,
+    ) -> Result<bool, CrawlError> {
+        let save = |state: &CrawlState| checkpoint.map_or(Ok(()), |path| state.save(path));
+        let mut visits = JoinSet::new();
+        let mut checkpoints =
+            interval_at(Instant::now() + CHECKPOINT_INTERVAL, CHECKPOINT_INTERVAL);
+        checkpoints.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::pin!(stop);
+        loop {
+            while visits.len() < self.workers {
+                let Some(addr) = state.next() else {
+                    break;
+                };
+                let identity = self.identity.clone();
+                let chainspec = self.chainspec.clone();
+                let config = self.config.clone();
+                let listen = self.listen;
+                visits.spawn(async move {
+                    (addr, visit(addr, identity, chainspec, config, listen).await)
+                });
+            }
+            if visits.is_empty() {
+                break;
+            }
+            tokio::select! {
+                Some(joined) = visits.join_next() => match joined {
+                    Ok((addr, outcome)) => {
+                        match &outcome {
+                            Ok(node) => info!(
+                                "Crawled {addr}: {}, {} peers gossiped",
+                                node.node_id,
+                                node.peers.len()
+                            ),
+                            Err(error) => debug!("Could not crawl {addr}: {error}"),
+                        }
+                        state.record(addr, outcome);
+                    }
+                    // Its address stays in flight, to be dialed again on a
+                    // resume.
+                    Err(e) => warn!("A crawl worker failed: {e}"),
+                },
+                _ = checkpoints.tick() => save(state)?,
+                _ = &mut stop => {
+                    // Dropping the visits aborts them, their addresses stay
+                    // in flight.
+                    drop(visits);
+                    save(state)?;
+                    return Ok(false);
+                }
+            }
+        }
+        save(state)?;
+        Ok(true)
+    }
+}
+
+/// Connects to `addr`, then listens for the addresses it gossips for
+/// `listen`. Fails with why the peer could not be reached.
+async fn visit(
+    addr: SocketAddr,
+    identity: Identity,
+    chainspec: Arc<Chainspec>,
+    config: Arc<Config>,
+    listen: Duration,
+) -> Result<CrawledNode, String> {
+    let started = Instant::now();
+    let connecting =
+        session::connect_with_config::<NodePayload>(addr, &identity, &chainspec, &config);
+    let mut session = timeout(CONNECT_TIMEOUT, connecting)
+        .await
+        .map_err(|_| format!("no handshake within {CONNECT_TIMEOUT:?}"))?
+        .map_err(|e| e.to_string())?;
+    let handshake = session.handshake();
+    let mut node = CrawledNode {
+        node_id: NodeId::from(session.fingerprint()),
+        addrs: BTreeSet::from([addr]),
+        public_addr: handshake.public_addr,
+        network_name: handshake.network_name.clone(),
+        protocol_version: handshake.protocol_version,
+        latency_ms: Some(started.elapsed().as_millis().try_into().unwrap_or(u64::MAX)),
+        peers: BTreeSet::new(),
+    };
+
+    // What the peer gossiped until it went quiet or away is kept.
+    let deadline = Instant::now() + listen;
+    while let Ok(received) = timeout_at(deadline, session.recv()).await {
+        match received {
+            Ok(Some(Message::Payload(NodePayload::AddressGossiper(GossipMessage::Gossip(
+                gossiped,
+            ))))) => {
+                node.peers.insert(gossiped.address());
+            }
+            // Payloads schultz does not decode are skipped.
+            Ok(Some(_)) | Err(ManagerError::CouldNotDecodeMessage(..)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+    if let Err(e) = session.close().await {
+        debug!("Could not close the connection to {addr}: {e}");
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::CrawledNodeBuilder;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([10, 0, 0, 1], port)) }
+
+    fn node(id: u8, reached_on: u16, peers: &[u16]) -> CrawledNode {
+        CrawledNodeBuilder::new(id, addr(reached_on))
+            .latency_ms(reached_on.into())
+            .peers(peers.iter().copied().map(addr))
+            .build()
+    }
+
+    #[test]
+    fn nodes_are_told_apart_by_their_id() {
+        let mut state = CrawlState::new([addr(1), addr(2), addr(1)]);
+        assert_eq!(state.next(), Some(addr(1)));
+        state.record(addr(1), Ok(node(1, 1, &[2, 3])));
+        assert_eq!(state.next(), Some(addr(2)));
+        // The same node, reached on another address.
+        state.record(addr(2), Ok(node(1, 2, &[3, 4])));
+        assert_eq!(state.next(), Some(addr(3)));
+        state.record(addr(3), Err("connection refused".to_string()));
+
+        let nodes: Vec<_> = state.nodes().collect();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].addrs, BTreeSet::from([addr(1), addr(2)]));
+        assert_eq!(nodes[0].latency_ms, Some(1));
+        assert_eq!(nodes[0].peers.len(), 3);
+        assert_eq!(state.unreachable().len(), 1);
+        assert_eq!(state.next(), Some(addr(4)));
+        assert_eq!(state.next(), None);
+    }
+
+    #[test]
+    fn resumed_crawls_dial_what_was_in_flight_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crawl.json");
+        let mut state = CrawlState::new([addr(1), addr(2), addr(3)]);
+        state.next();
+        state.next();
+        state.record(addr(1), Ok(node(1, 1, &[4])));
+        state.save(&path).unwrap();
+
+        let mut resumed = CrawlState::load(&path).unwrap();
+        assert_eq!(resumed.remaining(), 3);
+        assert_eq!(resumed.nodes().count(), 1);
+        let order: Vec<_> = std::iter::from_fn(|| resumed.next()).collect();
+        assert_eq!(order, [addr(2), addr(3), addr(4)]);
+    }
+}
@@ -0,0 +1,513 @@
+//! Native transfer deploys, built and signed by schultz and gossiped to peers.
+//!
+//! casper-types 4 has no `Deploy`, so this mirrors casper-node's: the header
+//! and the executable items are hashed in their bytesrepr encoding, and the
+//! deploy travels in bincode like every other item. Only the two executable
+//! items a native transfer needs are modelled.
+//!
+//! A deploy is not sent unasked. Its id is gossiped, and a peer that lacks it
+//! answers the gossip or fetches the deploy, both of which the node holding it
+//! serves from [`HeldDeploys`].
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use casper_hashing::Digest;
+use casper_types::bytesrepr;
+use casper_types::bytesrepr::ToBytes;
+use casper_types::crypto;
+use casper_types::AsymmetricType;
+use casper_types::PublicKey;
+use casper_types::RuntimeArgs;
+use casper_types::SecretKey;
+use casper_types::Signature;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use casper_types::U512;
+use serde::Serialize;
+
+use super::error::DeployError;
+use super::fetch::Tag;
+use super::gossip::NodePayloadDiscriminants;
+use super::message::BincodeFormat;
+use super::observe::PAYLOAD_TAG;
+
+/// Gas price of the deploys built.
+const GAS_PRICE: u64 = 1;
+
+/// Parses an amount of motes, in decimal.
+pub fn parse_motes(value: &str) -> Result<U512, String> {
+    U512::from_dec_str(value).map_err(|e| format!("invalid amount of motes {value:?}: {e}"))
+}
+
+/// Parses a hex-encoded public key, prefixed with its algorithm tag as
+/// casper-client prints them.
+pub fn parse_public_key(value: &str) -> Result<PublicKey, String> {
+    PublicKey::from_hex(value).map_err(|e| format!("invalid public key {value:?}: {e}"))
+}
+
+/// What a native transfer moves, and who pays for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    /// Motes moved.
+    pub amount: U512,
+    /// Account receiving them.
+    pub target: PublicKey,
+    /// Tells the transfer apart from others of the same amount and target.
+    pub id: u64,
+    /// Motes paid for running the transfer.
+    pub payment: U512,
+    /// How long after it was made the deploy may still be executed.
+    pub ttl: TimeDiff,
+}
+
+/// Mirrors casper-node's `DeployHeader`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeployHeader {
+    account: PublicKey,
+    timestamp: Timestamp,
+    ttl: TimeDiff,
+    gas_price: u64,
+    body_hash: Digest,
+    dependencies: Vec<Digest>,
+    chain_name: String,
+}
+
+impl ToBytes for DeployHeader {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut bytes = self.account.to_bytes()?;
+        bytes.extend(self.timestamp.to_bytes()?);
+        bytes.extend(self.ttl.to_bytes()?);
+        bytes.extend(self.gas_price.to_bytes()?);
+        bytes.extend(self.body_hash.to_bytes()?);
+        bytes.extend(self.dependencies.to_bytes()?);
+        bytes.extend(self.chain_name.to_bytes()?);
+        Ok(bytes)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.account.serialized_length()
+            + self.timestamp.serialized_length()
+            + self.ttl.serialized_length()
+            + self.gas_price.serialized_length()
+            + self.body_hash.serialized_length()
+            + self.dependencies.serialized_length()
+            + self.chain_name.serialized_length()
+    }
+}
+
+/// Mirrors casper-node's `ExecutableDeployItem`.
+///
+/// The stored contract variants stand in for items a transfer does not use
+/// and exist so that `Transfer` keeps its wire tag.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ExecutableDeployItem {
+    ModuleBytes {
+        module_bytes: bytesrepr::Bytes,
+        args: RuntimeArgs,
+    },
+    StoredContractByHash,
+    StoredContractByName,
+    StoredVersionedContractByHash,
+    StoredVersionedContractByName,
+    Transfer {
+        args: RuntimeArgs,
+    },
+}
+
+impl ToBytes for ExecutableDeployItem {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        match self {
+            ExecutableDeployItem::ModuleBytes { module_bytes, args } => {
+                let mut bytes = vec![0];
+                bytes.extend(module_bytes.to_bytes()?);
+                bytes.extend(args.to_bytes()?);
+                Ok(bytes)
+            }
+            ExecutableDeployItem::Transfer { args } => {
+                let mut bytes = vec![5];
+                bytes.extend(args.to_bytes()?);
+                Ok(bytes)
+            }
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+
+    fn serialized_length(&self) -> usize {
+        1 + match self {
+            ExecutableDeployItem::ModuleBytes { module_bytes, args } => {
+                module_bytes.serialized_length() + args.serialized_length()
+            }
+            ExecutableDeployItem::Transfer { args } => args.serialized_length(),
+            _ => 0,
+        }
+    }
+}
+
+/// A signature of the deploy hash, mirroring casper-node's `Approval`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Approval {
+    signer: PublicKey,
+    signature: Signature,
+}
+
+impl ToBytes for Approval {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut bytes = self.signer.to_bytes()?;
+        bytes.extend(self.signature.to_bytes()?);
+        Ok(bytes)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.signer.serialized_length() + self.signature.serialized_length()
+    }
+}
+
+/// A signed deploy, mirroring casper-node's `Deploy`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Deploy {
+    hash: Digest,
+    header: DeployHeader,
+    payment: ExecutableDeployItem,
+    session: ExecutableDeployItem,
+    approvals: Vec<Approval>,
+}
+
+/// Identifies a deploy and the approvals it was gossiped with, mirroring
+/// casper-node's `DeployId`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DeployId {
+    deploy_hash: Digest,
+    approvals_hash: Digest,
+}
+
+impl Deploy {
+    /// Builds `transfer` on the network `chain_name` as made at `timestamp`
+    /// and signs it with `secret_key`, whose account pays for it.
+    pub fn transfer(
+        transfer: &Transfer,
+        chain_name: &str,
+        secret_key: &SecretKey,
+        timestamp: Timestamp,
+    ) -> Result<Self, DeployError> {
+        let mut payment_args = RuntimeArgs::new();
+        payment_args.insert("amount", transfer.payment).map_err(encode)?;
+        let payment = ExecutableDeployItem::ModuleBytes {
+            module_bytes: bytesrepr::Bytes::new(),
+            args: payment_args,
+        };
+
+        let mut session_args = RuntimeArgs::new();
+        session_args.insert("amount", transfer.amount).map_err(encode)?;
+        session_args.insert("target", transfer.target.clone()).map_err(encode)?;
+        session_args.insert("id", Some(transfer.id)).map_err(encode)?;
+        let session = ExecutableDeployItem::Transfer { args: session_args };
+
+        let mut body = payment.to_bytes().map_err(encode)?;
+        body.extend(session.to_bytes().map_err(encode)?);
+        let account = PublicKey::from(secret_key);
+        let header = DeployHeader {
+            account: account.clone(),
+            timestamp,
+            ttl: transfer.ttl,
+            gas_price: GAS_PRICE,
+            body_hash: Digest::hash(body),
+            dependencies: vec![],
+            chain_name: chain_name.to_string(),
+        };
+        let hash = Digest::hash(header.to_bytes().map_err(encode)?);
+        let signature = crypto::sign(hash, secret_key, &account);
+        Ok(Self {
+            hash,
+            header,
+            payment,
+            session,
+            approvals: vec![Approval {
+                signer: account,
+                signature,
+            }],
+        })
+    }
+
+    pub fn hash(&self) -> Digest { self.hash }
+
+    pub fn account(&self) -> &PublicKey { &self.header.account }
+
+    /// The id the deploy is gossiped and fetched by.
+    pub fn id(&self) -> Result<DeployId, DeployError> {
+        Ok(DeployId {
+            deploy_hash: self.hash,
+            approvals_hash: Digest::hash(self.approvals.to_bytes().map_err(encode)?),
+        })
+    }
+
+    /// The deploy as peers fetch it.
+    pub fn serialized(&self) -> io::Result<Vec<u8>> {
+        BincodeFormat::default().serialize_arbitrary(self)
+    }
+}
+
+fn encode(error: impl ToString) -> DeployError { DeployError::Encode(error.to_string()) }
+
+/// A message of the deploy gossiper, mirroring casper-node's
+/// `gossiper::Message<Deploy>`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum DeployMessage {
+    /// Announces a deploy by its id.
+    Gossip(DeployId),
+    /// Answer to a `Gossip`, telling whether the deploy was already held.
+    GossipResponse {
+        item_id: DeployId,
+        is_already_held: bool,
+    },
+    /// Requests a deploy by its id.
+    GetItem(DeployId),
+    /// The deploy itself.
+    Item(Box<Deploy>),
+}
+
+impl DeployMessage {
+    /// Encodes the message as a whole `Message::Payload` frame.
+    ///
+    /// Schultz does not decode deploy gossip, so the message is not a
+    /// [`NodePayload`](super::gossip::NodePayload) and is framed by hand.
+    pub fn frame(&self) -> io::Result<Bytes> {
+        let message = BincodeFormat::default().serialize_arbitrary(self)?;
+        let (&variant, rest) = message
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty message"))?;
+        let gossiper = NodePayloadDiscriminants::DeployGossiper as u8;
+        let mut frame = Vec::with_capacity(message.len() + 3);
+        frame.extend([PAYLOAD_TAG, gossiper, variant]);
+        frame.extend_from_slice(rest);
+        Ok(Bytes::from(frame))
+    }
+
+    /// Whether the observed body of a `GossipResponse` tells that the peer
+    /// already held the deploy, rather than asking for it. Bincode writes
+    /// the flag last, right after the id.
+    pub fn already_held(body: &[u8]) -> bool { body.last() == Some(&1) }
+}
+
+/// A deploy gossiped to peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gossiped {
+    pub hash: Digest,
+    /// Peers the deploy was gossiped to.
+    pub gossiped_to: Vec<SocketAddr>,
+    /// Peers that lacked the deploy, asked for it and were handed it.
+    pub handed_to: BTreeSet<SocketAddr>,
+}
+
+/// A deploy we gossiped and the peers it was handed out to.
+#[derive(Debug)]
+struct Held {
+    deploy: Deploy,
+    handed_to: BTreeSet<SocketAddr>,
+}
+
+/// Deploys we gossiped, handed out to the peers that ask for them.
+#[derive(Debug, Default)]
+pub struct HeldDeploys {
+    held: Mutex<BTreeMap<Digest, Held>>,
+}
+
+impl HeldDeploys {
+    pub fn hold(&self, deploy: Deploy) {
+        let held = Held {
+            deploy: deploy.clone(),
+            handed_to: BTreeSet::new(),
+        };
+        self.held
+            .lock()
+            .expect("held deploys lock poisoned")
+            .insert(deploy.hash(), held);
+    }
+
+    /// Hands the deploy with `hash` out to `peer`, if we hold it.
+    pub fn hand_out(&self, hash: Digest, peer: SocketAddr) -> Option<Deploy> {
+        let mut held = self.held.lock().expect("held deploys lock poisoned");
+        let held = held.get_mut(&hash)?;
+        held.handed_to.insert(peer);
+        Some(held.deploy.clone())
+    }
+
+    /// Hands out the serialized deploy a `GetRequest` of `peer` for a `tag`
+    /// item with `serialized_id` asks for, if we hold it.
+    ///
+    /// Deploys are asked for by their id, or by their hash alone as a legacy
+    /// deploy.
+    pub fn get(&self, peer: SocketAddr, tag: Tag, serialized_id: &[u8]) -> Option<Vec<u8>> {
+        let bincode = BincodeFormat::default();
+        let mut held = self.held.lock().expect("held deploys lock poisoned");
+        let held = held.values_mut().find(|held| {
+            let id = match tag {
+                Tag::Deploy => held.deploy.id().ok().map(|id| bincode.serialize_arbitrary(&id)),
+                Tag::LegacyDeploy => Some(bincode.serialize_arbitrary(&held.deploy.hash())),
+                _ => None,
+            };
+            matches!(id, Some(Ok(id)) if id == serialized_id)
+        })?;
+        held.handed_to.insert(peer);
+        held.deploy.serialized().ok()
+    }
+
+    /// The peers the deploy with `hash` was handed out to.
+    pub fn handed_to(&self, hash: Digest) -> BTreeSet<SocketAddr> {
+        let held = self.held.lock().expect("held deploys lock poisoned");
+        held.get(&hash).map(|held| held.handed_to.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::bytesrepr::FromBytes;
+    use casper_types::CLValue;
+
+    use super::*;
+    use crate::network::observe::GossipKind;
+    use crate::network::observe::Gossiper;
+    use crate::network::observe::Observed;
+
+    fn transfer() -> (Deploy, SecretKey) {
+        let secret_key = SecretKey::ed25519_from_bytes([7; 32]).unwrap();
+        let target = PublicKey::from(&SecretKey::ed25519_from_bytes([8; 32]).unwrap());
+        let transfer = Transfer {
+            amount: U512::from(2_500_000_000u64),
+            target,
+            id: 42,
+            payment: U512::from(100_000_000u64),
+            ttl: TimeDiff::from_seconds(1800),
+        };
+        let timestamp = Timestamp::from(1_000);
+        let deploy = Deploy::transfer(&transfer, "casper-test", &secret_key, timestamp).unwrap();
+        (deploy, secret_key)
+    }
+
+    #[test]
+    fn transfers_are_hashed_and_signed() {
+        let (deploy, secret_key) = transfer();
+        assert_eq!(
+            deploy.hash(),
+            Digest::hash(deploy.header.to_bytes().unwrap())
+        );
+        let mut body = deploy.payment.to_bytes().unwrap();
+        body.extend(deploy.session.to_bytes().unwrap());
+        assert_eq!(deploy.header.body_hash, Digest::hash(body));
+        assert_eq!(
+            deploy.header.to_bytes().unwrap().len(),
+            deploy.header.serialized_length()
+        );
+
+        let approval = &deploy.approvals[0];
+        assert_eq!(&approval.signer, deploy.account());
+        assert_eq!(approval.signer, PublicKey::from(&secret_key));
+        crypto::verify(deploy.hash(), &approval.signature, &approval.signer).unwrap();
+
+        let ExecutableDeployItem::Transfer { args } = &deploy.session else {
+            panic!("not a transfer: {:?}", deploy.session);
+        };
+        let id = args.get("id").map(CLValue::inner_bytes).unwrap();
+        assert_eq!(Option::<u64>::from_bytes(id).unwrap().0, Some(42));
+        assert_eq!(deploy.session.to_bytes().unwrap()[0], 5);
+    }
+
+    #[test]
+    fn gossip_frames_are_observed_as_deploy_gossip() {
+        let (deploy, _) = transfer();
+        let id = deploy.id().unwrap();
+        let peer = "127.0.0.1:35000".parse().unwrap();
+        let frame = DeployMessage::Gossip(id).frame().unwrap();
+        let observed = Observed::parse(peer, &frame).unwrap();
+        assert_eq!(observed.gossiper, Gossiper::Deploy);
+        assert_eq!(observed.kind, GossipKind::Gossip);
+        assert_eq!(observed.item, deploy.hash().to_string());
+
+        let frame = DeployMessage::Item(Box::new(deploy.clone())).frame().unwrap();
+        let observed = Observed::parse(peer, &frame).unwrap();
+        assert_eq!(observed.kind, GossipKind::Item);
+        assert_eq!(observed.item, deploy.hash().to_string());
+    }
+
+    /// The gossip response casper-node writes for a deploy id, by hand from
+    /// its bincode layout: the message, gossiper and variant tags, both
+    /// hashes behind their length and the flag.
+    #[test]
+    fn gossip_responses_are_framed_as_casper_nodes_do() {
+        let id = DeployId {
+            deploy_hash: Digest::from([0xaa; Digest::LENGTH]),
+            approvals_hash: Digest::from([0xbb; Digest::LENGTH]),
+        };
+        let response = DeployMessage::GossipResponse {
+            item_id: id,
+            is_already_held: true,
+        };
+        let mut expected = vec![3, 3, 1, 32];
+        expected.extend([0xaa; 32]);
+        expected.push(32);
+        expected.extend([0xbb; 32]);
+        expected.push(1);
+        let frame = response.frame().unwrap();
+        assert_eq!(&frame[..], &expected[..]);
+
+        let peer = "127.0.0.1:35000".parse().unwrap();
+        let observed = Observed::parse(peer, &frame).unwrap();
+        assert_eq!(observed.kind, GossipKind::GossipResponse);
+        assert!(DeployMessage::already_held(&observed.body));
+        let asking = DeployMessage::GossipResponse {
+            item_id: id,
+            is_already_held: false,
+        };
+        let frame = asking.frame().unwrap();
+        let observed = Observed::parse(peer, &frame).unwrap();
+        assert!(!DeployMessage::already_held(&observed.body));
+    }
+
+    /// The header casper-node hashes, by hand from its bytesrepr layout:
+    /// the tagged key, little-endian integers and length-prefixed lists.
+    #[test]
+    fn headers_are_encoded_as_casper_nodes_do() {
+        let (deploy, secret_key) = transfer();
+        let PublicKey::Ed25519(account) = PublicKey::from(&secret_key) else {
+            panic!("not an ed25519 key");
+        };
+        let mut expected = vec![1];
+        expected.extend(account.as_bytes());
+        expected.extend(1_000u64.to_le_bytes());
+        expected.extend(1_800_000u64.to_le_bytes());
+        expected.extend(1u64.to_le_bytes());
+        expected.extend(deploy.header.body_hash.value());
+        expected.extend(0u32.to_le_bytes());
+        expected.extend(11u32.to_le_bytes());
+        expected.extend(b"casper-test");
+        assert_eq!(deploy.header.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn held_deploys_are_handed_out_by_id_or_hash() {
+        let (deploy, _) = transfer();
+        let bincode = BincodeFormat::default();
+        let id = bincode.serialize_arbitrary(&deploy.id().unwrap()).unwrap();
+        let hash = bincode.serialize_arbitrary(&deploy.hash()).unwrap();
+
+        let held = HeldDeploys::default();
+        let first: SocketAddr = "127.0.0.1:35000".parse().unwrap();
+        let second: SocketAddr = "127.0.0.1:35001".parse().unwrap();
+        assert_eq!(held.get(first, Tag::Deploy, &id), None);
+        held.hold(deploy.clone());
+        let serialized = deploy.serialized().unwrap();
+        assert_eq!(held.get(first, Tag::Deploy, &id), Some(serialized.clone()));
+        assert_eq!(held.get(first, Tag::LegacyDeploy, &hash), Some(serialized));
+        assert_eq!(held.get(second, Tag::LegacyDeploy, &id), None);
+        assert_eq!(held.get(second, Tag::Block, &hash), None);
+        assert_eq!(held.handed_to(deploy.hash()), BTreeSet::from([first]));
+
+        assert_eq!(held.hand_out(deploy.hash(), second), Some(deploy.clone()));
+        assert_eq!(held.handed_to(deploy.hash()).len(), 2);
+        assert_eq!(held.hand_out(Digest::hash(b"other"), second), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Routing of the messages peers send us to the handlers registered for them.
+//!
+//! Every [`Route`] has at most one handler, an async function getting the
+//! node's context, the sender and the message. New kinds of messages are
+//! supported by registering another handler, without touching the loop that
+//! reads them.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tracing::info;
+
+use super::message::Message;
+use super::message::Routable;
+use super::message::Route;
+
+type BoxedHandler<C, P> =
+    Box<dyn Fn(C, SocketAddr, Message<P>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Handlers for the messages read from peers, by route.
+pub struct Dispatcher<C, P: Routable> {
+    handlers: BTreeMap<Route<P::Kind>, BoxedHandler<C, P>>,
+}
+
+impl<C, P: Routable> Default for Dispatcher<C, P> {
+    fn default() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C, P> Dispatcher<C, P>
+where
+    C: Send + 'static,
+    P: Routable + Send + 'static,
+{
+    pub fn new() -> Self { Self::default() }
+
+    /// Handles messages on `route` with `handler`, replacing any handler
+    /// registered for it before.
+    pub fn on<F, Fut>(mut self, route: Route<P::Kind>, handler: F) -> Self
+    where
+        F: Fn(C, SocketAddr, Message<P>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler = move |context, from, message| handler(context, from, message).boxed();
+        self.handlers.insert(route, Box::new(handler));
+        self
+    }
+
+    /// Whether a handler is registered for `route`.
+    pub fn handles(&self, route: Route<P::Kind>) -> bool { self.handlers.contains_key(&route) }
+
+    /// Passes `message` from `from` to its handler and waits for it to finish.
+    /// Returns `false` if no handler is registered for it.
+    pub async fn dispatch(&self, context: C, from: SocketAddr, message: Message<P>) -> bool {
+        let route = message.route();
+        match self.handlers.get(&route) {
+            Some(handler) => {
+                handler(context, from, message).await;
+                true
+            }
+            None => {
+                info!("Received a message from {from:?} without a handler for {route:?}");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::primitives::Nonce;
+
+    #[derive(Clone, Debug)]
+    struct Text(&'static str);
+
+    impl Routable for Text {
+        type Kind = usize;
+
+        fn kind(&self) -> usize { self.0.len() }
+    }
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    #[tokio::test]
+    async fn routes_messages_to_their_handlers() {
+        let dispatcher = Dispatcher::<Log, Text>::new()
+            .on(Route::Ping, |log: Log, from, _| async move {
+                log.lock().unwrap().push(format!("ping from {}", from.port()));
+            })
+            .on(Route::Payload(5), |log: Log, _, message| async move {
+                if let Message::Payload(Text(text)) = message {
+                    log.lock().unwrap().push(text.to_string());
+                }
+            });
+        let from = SocketAddr::from(([127, 0, 0, 1], 7));
+        let log = Log::default();
+
+        let ping = Message::Ping {
+            nonce: Nonce::new(1),
+        };
+        assert!(dispatcher.dispatch(log.clone(), from, ping).await);
+        assert!(dispatcher.dispatch(log.clone(), from, Message::Payload(Text("hello"))).await);
+        assert!(!dispatcher.dispatch(log.clone(), from, Message::Payload(Text("hi"))).await);
+        assert!(!dispatcher.handles(Route::Pong));
+
+        assert_eq!(*log.lock().unwrap(), ["ping from 7", "hello"]);
+    }
+}
@@ -0,0 +1,160 @@
+//! The validators of every era, followed across switch blocks.
+//!
+//! The chainspec only knows the validators of the era it activates in: those
+//! its global state update installs, or else the genesis validators. Every
+//! era after that is announced by the switch block ending the one before,
+//! which carries the weights of the next era's validators. Switch blocks seen
+//! while following the header chain, or fetched, are fed to the
+//! [`EraTracker`] once they are settled, a quorum of their era having signed
+//! them or a settled block descending from them, see
+//! [`HeaderChain`](super::headers::HeaderChain). Finality signatures and
+//! consensus certificates are then checked against the validators of their
+//! era rather than the chainspec's.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use casper_types::EraId;
+use casper_types::PublicKey;
+use serde::Serialize;
+
+use super::error::FinalityError;
+use super::finality::Weights;
+use super::headers::EraAnnouncement;
+use crate::primitives::Chainspec;
+
+/// Eras whose validators are kept, the oldest are forgotten first.
+pub const MAX_ERAS: usize = 16;
+
+/// The validators of an era, as reported by `/status`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EraValidators {
+    pub era_id: EraId,
+    pub validators: usize,
+}
+
+/// The weights of the validators of the eras known so far.
+#[derive(Debug, Default)]
+pub struct EraTracker {
+    eras: RwLock<BTreeMap<EraId, Arc<Weights>>>,
+}
+
+impl EraTracker {
+    /// Starts from the validators of `chainspec`, loaded from `dir`, in the
+    /// era it activates in.
+    pub fn start_from(&self, dir: &Path, chainspec: &Chainspec) -> Result<EraId, FinalityError> {
+        let era_id = chainspec.protocol_config.activation_point.era_id();
+        self.start(era_id, Weights::load(dir, chainspec)?);
+        Ok(era_id)
+    }
+
+    /// Takes `weights` as those of `era_id`, unless a switch block told them
+    /// already.
+    pub fn start(&self, era_id: EraId, weights: Weights) {
+        let mut eras = self.eras.write().expect("era tracker lock poisoned");
+        eras.entry(era_id).or_insert_with(|| Arc::new(weights));
+        Self::prune(&mut eras);
+    }
+
+    /// Takes the validators a settled switch block announced for the next
+    /// era.
+    pub fn announce(&self, announcement: &EraAnnouncement) {
+        let weights = Weights::new(announcement.weights.clone());
+        let mut eras = self.eras.write().expect("era tracker lock poisoned");
+        eras.insert(announcement.era_id, Arc::new(weights));
+        Self::prune(&mut eras);
+    }
+
+    /// The validators of `era_id`. An era whose switch block was not seen is
+    /// taken to have the validators of the latest era before it.
+    pub fn weights(&self, era_id: EraId) -> Option<Arc<Weights>> {
+        let eras = self.eras.read().expect("era tracker lock poisoned");
+        eras.range(..=era_id).next_back().map(|(_, weights)| weights.clone())
+    }
+
+    /// The latest era known and its validators.
+    pub fn current(&self) -> Option<(EraId, Arc<Weights>)> {
+        let eras = self.eras.read().expect("era tracker lock poisoned");
+        eras.last_key_value().map(|(era_id, weights)| (*era_id, weights.clone()))
+    }
+
+    /// Whether `public_key` is a validator of the latest era known.
+    pub fn is_validator(&self, public_key: &PublicKey) -> bool {
+        self.current().is_some_and(|(_, weights)| weights.weight(public_key).is_some())
+    }
+
+    /// The latest era known and how many validators it has.
+    pub fn status(&self) -> Option<EraValidators> {
+        self.current().map(|(era_id, weights)| EraValidators {
+            era_id,
+            validators: weights.len(),
+        })
+    }
+
+    fn prune(eras: &mut BTreeMap<EraId, Arc<Weights>>) {
+        while eras.len() > MAX_ERAS {
+            eras.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_hashing::Digest;
+    use casper_types::U512;
+
+    use super::*;
+    use crate::testing::public_key;
+
+    fn weights(seeds: &[u8]) -> BTreeMap<PublicKey, U512> {
+        seeds.iter().map(|seed| (public_key(*seed), U512::from(10))).collect()
+    }
+
+    fn announcement(era_id: u64, weights: BTreeMap<PublicKey, U512>) -> EraAnnouncement {
+        EraAnnouncement {
+            block_hash: Digest::hash(era_id.to_le_bytes()),
+            era_id: EraId::new(era_id),
+            weights,
+        }
+    }
+
+    #[test]
+    fn switch_blocks_announce_the_validators_of_the_next_era() {
+        let eras = EraTracker::default();
+        assert!(eras.weights(EraId::new(5)).is_none());
+        eras.start(EraId::new(5), Weights::new(weights(&[1, 2])));
+        assert!(eras.is_validator(&public_key(1)));
+
+        eras.announce(&announcement(6, weights(&[2, 3])));
+        assert!(!eras.is_validator(&public_key(1)));
+        assert!(eras.is_validator(&public_key(3)));
+        assert_eq!(eras.weights(EraId::new(5)).unwrap().len(), 2);
+        assert!(eras.weights(EraId::new(5)).unwrap().weight(&public_key(1)).is_some());
+        // Later eras keep the validators of the latest one known.
+        assert!(eras.weights(EraId::new(9)).unwrap().weight(&public_key(3)).is_some());
+        assert!(eras.weights(EraId::new(4)).is_none());
+
+        // The chainspec does not override what a switch block told.
+        eras.start(EraId::new(6), Weights::new(weights(&[1])));
+        assert!(!eras.is_validator(&public_key(1)));
+        assert_eq!(
+            eras.status(),
+            Some(EraValidators {
+                era_id: EraId::new(6),
+                validators: 2
+            })
+        );
+    }
+
+    #[test]
+    fn only_the_latest_eras_are_kept() {
+        let eras = EraTracker::default();
+        for era in 0..(MAX_ERAS as u64 + 4) {
+            eras.announce(&announcement(era, weights(&[1])));
+        }
+        assert!(eras.weights(EraId::new(3)).is_none());
+        assert!(eras.weights(EraId::new(4)).is_some());
+    }
+}
@@ -1,16 +1,44 @@
+use std::fmt::Display;
 use std::io;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
-use openssl::error::ErrorStack;
+use casper_hashing::Digest;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::TimeDiff;
+use miette::Diagnostic;
 use serde::Serialize;
 use thiserror::Error;
 
+use super::fetch::Tag;
+use super::limits::LimitReached;
+use super::memory::OverBudget;
+use super::mux::Channel;
+use crate::crypto::ConsensusKeyError;
+use crate::primitives::GlobalStateUpdateLoadError;
+
+// Every network error answers the same three questions: a stable `code` to
+// match on in logs and scripts, whether trying again may help, and whether
+// the peer is to blame. Errors wrapping others defer to the wrapped one.
+// They are diagnostics showing their code, so commands can pass them on
+// as they are and the binary still tells them apart by type.
+
 #[derive(Debug, Error, Serialize)]
 pub enum ManagerError {
     #[error("Failed to bind to address")]
     PeerNotFound,
     #[error("Error sending message to peer")]
     SendFailed(String),
+    #[error("Connection to {0} closed")]
+    ConnectionClosed(SocketAddr),
+    #[error("Queue of {1} frames to {0} is full")]
+    QueueFull(SocketAddr, Channel),
+    #[error(transparent)]
+    OverBudget(#[from] OverBudget),
+    #[error(transparent)]
+    LimitReached(#[from] LimitReached),
     #[error("failed to get listener addr")]
     ListenerCreation(
         #[serde(skip_serializing)]
@@ -20,9 +48,319 @@ pub enum ManagerError {
     ),
     #[error("Error serializing protocol handshake")]
     CouldNotEncodeOurHandshake(String),
+    #[error("Error serializing message: {0}")]
+    CouldNotEncodeMessage(String),
+    #[error("Error receiving message from peer: {0}")]
+    ReceiveFailed(String),
+    #[error("Could not decode a message from {0}: {1}")]
+    CouldNotDecodeMessage(SocketAddr, String),
     #[error("Error from the Network module {0:?}")]
     #[serde(skip_serializing)]
     Tls(TLSError),
+    #[error("Handshake with {0} rejected: {1}")]
+    #[serde(skip_serializing)]
+    HandshakeRejected(SocketAddr, HandshakeError),
+    #[error("Timed out waiting for a handshake from {0}")]
+    HandshakeTimeout(SocketAddr),
+    #[error("Could not resolve {0}: {1}")]
+    Resolve(
+        String,
+        #[serde(skip_serializing)]
+        #[source]
+        io::Error,
+    ),
+    #[error("No addresses to connect to")]
+    NoAddresses,
+    #[error("Timed out connecting to {0}")]
+    ConnectTimeout(SocketAddr),
+    #[error("Failed to register network metrics: {0}")]
+    #[serde(skip_serializing)]
+    Metrics(#[from] prometheus::Error),
+    #[error(transparent)]
+    #[serde(skip_serializing)]
+    ConsensusKey(#[from] ConsensusKeyError),
+    #[error("Could not open the wire log {}", .0.display())]
+    WireLog(
+        PathBuf,
+        #[serde(skip_serializing)]
+        #[source]
+        io::Error,
+    ),
+}
+
+impl ManagerError {
+    /// Stable identifier of the kind of error, e.g. `network.connect_timeout`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ManagerError::PeerNotFound => "network.peer_not_found",
+            ManagerError::SendFailed(_) => "network.send_failed",
+            ManagerError::ConnectionClosed(_) => "network.connection_closed",
+            ManagerError::QueueFull(..) => "network.queue_full",
+            ManagerError::OverBudget(_) => "network.over_budget",
+            ManagerError::LimitReached(_) => "network.limit_reached",
+            ManagerError::ListenerCreation(..) => "network.listener_creation",
+            ManagerError::CouldNotEncodeOurHandshake(_) => "network.encode_handshake",
+            ManagerError::CouldNotEncodeMessage(_) => "network.encode_message",
+            ManagerError::ReceiveFailed(_) => "network.receive_failed",
+            ManagerError::CouldNotDecodeMessage(..) => "network.decode_message",
+            ManagerError::Tls(error) => error.code(),
+            ManagerError::HandshakeRejected(_, error) => error.code(),
+            ManagerError::HandshakeTimeout(_) => "network.handshake_timeout",
+            ManagerError::Resolve(..) => "network.resolve",
+            ManagerError::NoAddresses => "network.no_addresses",
+            ManagerError::ConnectTimeout(_) => "network.connect_timeout",
+            ManagerError::Metrics(_) => "network.metrics",
+            ManagerError::ConsensusKey(_) => "network.consensus_key",
+            ManagerError::WireLog(..) => "network.wire_log",
+        }
+    }
+
+    /// Whether the same operation may succeed if tried again later, e.g.
+    /// after a DNS hiccup or a timeout.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ManagerError::SendFailed(_)
+            | ManagerError::ReceiveFailed(_)
+            | ManagerError::ConnectionClosed(_)
+            | ManagerError::QueueFull(..)
+            | ManagerError::OverBudget(_)
+            | ManagerError::LimitReached(_)
+            | ManagerError::HandshakeTimeout(_)
+            | ManagerError::Resolve(..)
+            | ManagerError::NoAddresses
+            | ManagerError::ConnectTimeout(_) => true,
+            ManagerError::Tls(error) => error.is_retryable(),
+            ManagerError::HandshakeRejected(_, error) => error.is_retryable(),
+            ManagerError::PeerNotFound
+            | ManagerError::ListenerCreation(..)
+            | ManagerError::CouldNotEncodeOurHandshake(_)
+            | ManagerError::CouldNotEncodeMessage(_)
+            | ManagerError::CouldNotDecodeMessage(..)
+            | ManagerError::Metrics(_)
+            | ManagerError::ConsensusKey(_)
+            | ManagerError::WireLog(..) => false,
+        }
+    }
+
+    /// Whether the peer broke the protocol, rather than the network or we
+    /// failing. Such peers are not worth talking to again.
+    pub fn is_peer_fault(&self) -> bool {
+        match self {
+            ManagerError::CouldNotDecodeMessage(..) => true,
+            ManagerError::Tls(error) => error.is_peer_fault(),
+            ManagerError::HandshakeRejected(_, error) => error.is_peer_fault(),
+            _ => false,
+        }
+    }
+
+    /// Why connecting to or handshaking with a peer failed, for telling
+    /// failures apart in the `net_connection_failures` metric.
+    pub fn failure_cause(&self) -> FailureCause {
+        match self {
+            ManagerError::Resolve(..) | ManagerError::NoAddresses => FailureCause::Dns,
+            ManagerError::ConnectTimeout(_) => FailureCause::TcpTimeout,
+            ManagerError::Tls(TLSError::TcpConnection(error)) => match error.kind() {
+                io::ErrorKind::ConnectionRefused => FailureCause::TcpRefused,
+                io::ErrorKind::TimedOut => FailureCause::TcpTimeout,
+                _ => FailureCause::Tcp,
+            },
+            ManagerError::Tls(error) if error.is_peer_fault() => {
+                FailureCause::TlsValidation(detail(error.code()))
+            }
+            ManagerError::Tls(_) => FailureCause::TlsProtocol,
+            ManagerError::HandshakeRejected(_, error) => {
+                FailureCause::HandshakeRejected(detail(error.code()))
+            }
+            ManagerError::HandshakeTimeout(_) => FailureCause::HandshakeTimeout,
+            error => FailureCause::Other(detail(error.code())),
+        }
+    }
+}
+
+/// A failed attempt to connect to or handshake with a peer, put in a bucket
+/// by why it failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureCause {
+    /// The peer's name did not resolve to any address.
+    Dns,
+    TcpRefused,
+    TcpTimeout,
+    /// Any other TCP error, e.g. an unreachable network.
+    Tcp,
+    /// The TLS handshake failed before a certificate was to check.
+    TlsProtocol,
+    /// The peer's certificate failed the check named, e.g. `expired`.
+    TlsValidation(&'static str),
+    /// The protocol handshake was rejected for the reason named, e.g.
+    /// `wrong_network`.
+    HandshakeRejected(&'static str),
+    HandshakeTimeout,
+    /// Anything else, named by its error code, e.g. `limit_reached`.
+    Other(&'static str),
+}
+
+impl FailureCause {
+    /// The `cause` and `detail` labels the failure is counted under.
+    pub fn labels(&self) -> [&'static str; 2] {
+        match self {
+            FailureCause::Dns => ["dns", ""],
+            FailureCause::TcpRefused => ["tcp-refused", ""],
+            FailureCause::TcpTimeout => ["tcp-timeout", ""],
+            FailureCause::Tcp => ["tcp", ""],
+            FailureCause::TlsProtocol => ["tls-protocol", ""],
+            FailureCause::TlsValidation(check) => ["tls-validation", check],
+            FailureCause::HandshakeRejected(reason) => ["handshake-rejected", reason],
+            FailureCause::HandshakeTimeout => ["handshake-timeout", ""],
+            FailureCause::Other(code) => ["other", code],
+        }
+    }
+}
+
+/// An error code without the area it belongs to, `expired` for
+/// `tls.expired`.
+fn detail(code: &'static str) -> &'static str {
+    code.split_once('.').map_or(code, |(_, detail)| detail)
+}
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("Could not serialize the id of {0}: {1}")]
+    CouldNotEncodeId(Digest, #[source] io::Error),
+    #[error("No connected peer to fetch {0} {1} from")]
+    NoPeers(Tag, Digest),
+    #[error("None of {tried} peers provided {tag} {hash}")]
+    NotFound {
+        tag: Tag,
+        hash: Digest,
+        tried: usize,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum DeployError {
+    #[error("Could not encode the deploy: {0}")]
+    Encode(String),
+    #[error("Could not serialize deploy {0}: {1}")]
+    CouldNotSerialize(Digest, #[source] io::Error),
+    #[error("No connected peer to gossip deploy {0} to")]
+    NoPeers(Digest),
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    #[error("Header hashes to {actual}, not to the gossiped {expected}")]
+    HashMismatch { expected: Digest, actual: Digest },
+    #[error("Could not encode the header of block {0}: {1}")]
+    Encode(Digest, String),
+    #[error("Block {hash} is at height {height}, its parent at {parent_height}")]
+    HeightMismatch {
+        hash: Digest,
+        height: u64,
+        parent_height: u64,
+    },
+    #[error("Block {hash} is in era {era_id}, expected era {expected}")]
+    EraMismatch {
+        hash: Digest,
+        era_id: EraId,
+        expected: EraId,
+    },
+    #[error("Block {hash} is in era {era_id} but a quorum signed it in era {signed}")]
+    SignedInOtherEra {
+        hash: Digest,
+        era_id: EraId,
+        signed: EraId,
+    },
+    #[error("Block {hash} is at height {height}, trusted block {trusted} at {trusted_height}")]
+    NotAfterTrusted {
+        hash: Digest,
+        height: u64,
+        trusted: Digest,
+        trusted_height: u64,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum FinalityError {
+    #[error("Signature of {0} for block {1} does not verify")]
+    InvalidSignature(Box<PublicKey>, Digest),
+    #[error("{0} is not a known validator")]
+    NotAValidator(Box<PublicKey>),
+    #[error("The validators of era {0} are not known")]
+    UnknownEra(EraId),
+    #[error("No validators in the chainspec at {}", .0.display())]
+    NoValidators(PathBuf),
+    #[error("Could not read the validators of the global state update")]
+    GlobalStateUpdate(#[from] GlobalStateUpdateLoadError),
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    #[error("peer is on network {theirs:?}, expected {ours:?}")]
+    WrongNetwork { ours: String, theirs: String },
+    #[error("peer speaks protocol version {theirs}, expected {ours}")]
+    IncompatibleVersion {
+        ours: ProtocolVersion,
+        theirs: ProtocolVersion,
+    },
+    #[error("peer speaks protocol version {0}, which schultz does not support")]
+    UnsupportedVersion(ProtocolVersion),
+    #[error("peer did not send a chainspec hash")]
+    MissingChainspecHash,
+    #[error("peer runs chainspec {theirs}, expected {ours}")]
+    ChainspecMismatch { ours: Digest, theirs: Digest },
+    #[error("connection closed before the handshake completed")]
+    ConnectionClosed,
+    #[error("peer stamped its handshake without a consensus certificate to check it with")]
+    StampWithoutCertificate,
+    #[error("peer sent a consensus certificate without a stamp")]
+    CertificateWithoutStamp,
+    #[error("peer's consensus certificate is not for this session")]
+    InvalidCertificate,
+    #[error("peer's handshake stamp is not signed by its consensus key for this session")]
+    InvalidStamp,
+    #[error("peer's handshake was made {age} away from now, more than the {max} allowed")]
+    StaleStamp { age: TimeDiff, max: TimeDiff },
+    #[error("peer replayed a handshake already seen")]
+    ReplayedStamp,
+}
+
+impl Diagnostic for ManagerError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ManagerError::code(self)))
+    }
+}
+
+impl HandshakeError {
+    /// See [`ManagerError::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            HandshakeError::WrongNetwork { .. } => "handshake.wrong_network",
+            HandshakeError::IncompatibleVersion { .. } => "handshake.incompatible_version",
+            HandshakeError::UnsupportedVersion(_) => "handshake.unsupported_version",
+            HandshakeError::MissingChainspecHash => "handshake.missing_chainspec_hash",
+            HandshakeError::ChainspecMismatch { .. } => "handshake.chainspec_mismatch",
+            HandshakeError::ConnectionClosed => "handshake.connection_closed",
+            HandshakeError::StampWithoutCertificate => "handshake.stamp_without_certificate",
+            HandshakeError::CertificateWithoutStamp => "handshake.certificate_without_stamp",
+            HandshakeError::InvalidCertificate => "handshake.invalid_certificate",
+            HandshakeError::InvalidStamp => "handshake.invalid_stamp",
+            HandshakeError::StaleStamp { .. } => "handshake.stale_stamp",
+            HandshakeError::ReplayedStamp => "handshake.replayed_stamp",
+        }
+    }
+
+    /// See [`ManagerError::is_retryable`].
+    pub fn is_retryable(&self) -> bool { matches!(self, HandshakeError::ConnectionClosed) }
+
+    /// See [`ManagerError::is_peer_fault`]. A peer on another network or
+    /// chainspec will not move to ours.
+    pub fn is_peer_fault(&self) -> bool { !self.is_retryable() }
+}
+
+impl Diagnostic for HandshakeError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(HandshakeError::code(self)))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -31,14 +369,34 @@ pub enum TLSError {
     TcpConnection(io::Error),
     #[error("Could not set Nodelay for TCP")]
     TcpNoDelay,
-    #[error("Error generating TLS certs {0:?}")]
-    CouldNotGenerateTlsCertificate(ErrorStack),
+    #[error("Error generating TLS certs {0}")]
+    CouldNotGenerateTlsCertificate(String),
+    #[error("Error encoding identity {0}")]
+    CouldNotEncodeIdentity(String),
+    #[error("Error decoding identity {0}")]
+    CouldNotDecodeIdentity(String),
+    #[error("Secret key does not match the TLS certificate")]
+    SecretKeyMismatch,
+    #[error("TLS certificate was not signed by the network CA")]
+    NotSignedByNetworkCa,
+    #[error("TLS certificate chain does not lead to the network CA: {0}")]
+    UntrustedChain(String),
+    #[error("TLS certificate comes with intermediates, but there is no network CA they lead to")]
+    IntermediatesWithoutNetworkCa,
+    #[error("TLS certificate was issued by the network CA and cannot be renewed by us")]
+    IssuedByNetworkCa,
+    #[error("Error accessing identity file {}: {1}", .0.display())]
+    IdentityFile(PathBuf, io::Error),
     #[error("Error extracting keys from TLS handshake")]
     CouldNotExtractEcKey,
     #[error("Error initializing TLS Handshake {0:?}")]
     TlsInitialization(String),
     #[error("Error during TLS Handshake")]
     TlsHandshake(String),
+    #[error("Unknown TLS 1.3 cipher suite {0:?}")]
+    UnknownCipherSuite(String),
+    #[error("Unknown TLS key exchange group {0:?}")]
+    UnknownGroup(String),
     #[error("Could not find Peer's TLS certificate")]
     NoPeerCertificate,
     #[error("Signature Algorithm mimatch during TLS handshake")]
@@ -47,16 +405,22 @@ pub enum TLSError {
     WrongCurve,
     #[error("Could not verify certificate subject/issuer")]
     CorruptSubjectOrIssuer,
+    #[error("Could not read TLS certificate extensions")]
+    CannotReadExtensions,
+    #[error("TLS certificate has unexpected extensions")]
+    UnexpectedExtensions,
+    #[error("TLS certificate is for {actual:?}, expected {expected:?}")]
+    UnexpectedCommonName { expected: String, actual: String },
     #[error("TLS certificate was not self-signed")]
     NotSelfSigned,
     #[error("Serial number mismatch during TLS handshake")]
     WrongSerialNumber,
     #[error("Timing mismatch during TLS handshake")]
     TimeIssue,
-    #[error("TLS certificate yet to be valid")]
-    NotYetValid,
-    #[error("Expired TLS certificate")]
-    Expired,
+    #[error("TLS certificate is not valid for another {by}")]
+    NotYetValid { by: TimeDiff },
+    #[error("TLS certificate expired {by} ago")]
+    Expired { by: TimeDiff },
     #[error("Error reading PublicKey from TLS connections")]
     CannotReadPublicKey,
     #[error("Error verifying PublicKey during TLS handshake")]
@@ -67,8 +431,182 @@ pub enum TLSError {
     InvalidSignature,
     #[error("Error verifying Serial number during TLS handshake")]
     InvalidSerialNumber,
+    #[error("Built without a TLS backend, enable the openssl feature")]
+    NoBackend,
+}
+
+impl TLSError {
+    /// See [`ManagerError::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            TLSError::TcpConnection(_) => "tls.tcp_connection",
+            TLSError::TcpNoDelay => "tls.tcp_no_delay",
+            TLSError::CouldNotGenerateTlsCertificate(_) => "tls.generate_certificate",
+            TLSError::CouldNotEncodeIdentity(_) => "tls.encode_identity",
+            TLSError::CouldNotDecodeIdentity(_) => "tls.decode_identity",
+            TLSError::SecretKeyMismatch => "tls.secret_key_mismatch",
+            TLSError::NotSignedByNetworkCa => "tls.not_signed_by_network_ca",
+            TLSError::UntrustedChain(_) => "tls.untrusted_chain",
+            TLSError::IntermediatesWithoutNetworkCa => "tls.intermediates_without_network_ca",
+            TLSError::IssuedByNetworkCa => "tls.issued_by_network_ca",
+            TLSError::IdentityFile(..) => "tls.identity_file",
+            TLSError::CouldNotExtractEcKey => "tls.extract_ec_key",
+            TLSError::TlsInitialization(_) => "tls.initialization",
+            TLSError::TlsHandshake(_) => "tls.handshake",
+            TLSError::UnknownCipherSuite(_) => "tls.unknown_cipher_suite",
+            TLSError::UnknownGroup(_) => "tls.unknown_group",
+            TLSError::NoPeerCertificate => "tls.no_peer_certificate",
+            TLSError::WrongSignatureAlgorithm => "tls.wrong_signature_algorithm",
+            TLSError::WrongCurve => "tls.wrong_curve",
+            TLSError::CorruptSubjectOrIssuer => "tls.corrupt_subject_or_issuer",
+            TLSError::CannotReadExtensions => "tls.cannot_read_extensions",
+            TLSError::UnexpectedExtensions => "tls.unexpected_extensions",
+            TLSError::UnexpectedCommonName { .. } => "tls.unexpected_common_name",
+            TLSError::NotSelfSigned => "tls.not_self_signed",
+            TLSError::WrongSerialNumber => "tls.wrong_serial_number",
+            TLSError::TimeIssue => "tls.time_issue",
+            TLSError::NotYetValid { .. } => "tls.not_yet_valid",
+            TLSError::Expired { .. } => "tls.expired",
+            TLSError::CannotReadPublicKey => "tls.cannot_read_public_key",
+            TLSError::KeyFailsCheck => "tls.key_fails_check",
+            TLSError::FailedToValidateSignature => "tls.failed_to_validate_signature",
+            TLSError::InvalidSignature => "tls.invalid_signature",
+            TLSError::InvalidSerialNumber => "tls.invalid_serial_number",
+            TLSError::NoBackend => "tls.no_backend",
+        }
+    }
+
+    /// See [`ManagerError::is_retryable`]. A failed TLS handshake is, as it
+    /// is as likely a dropped connection as a misbehaving peer.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            TLSError::TcpConnection(_) | TLSError::TcpNoDelay | TLSError::TlsHandshake(_)
+        )
+    }
+
+    /// See [`ManagerError::is_peer_fault`]. True for every certificate the
+    /// peer presents that fails validation.
+    pub fn is_peer_fault(&self) -> bool {
+        match self {
+            TLSError::UntrustedChain(_)
+            | TLSError::CouldNotExtractEcKey
+            | TLSError::NoPeerCertificate
+            | TLSError::WrongSignatureAlgorithm
+            | TLSError::WrongCurve
+            | TLSError::CorruptSubjectOrIssuer
+            | TLSError::CannotReadExtensions
+            | TLSError::UnexpectedExtensions
+            | TLSError::UnexpectedCommonName { .. }
+            | TLSError::NotSelfSigned
+            | TLSError::WrongSerialNumber
+            | TLSError::NotYetValid { .. }
+            | TLSError::Expired { .. }
+            | TLSError::CannotReadPublicKey
+            | TLSError::KeyFailsCheck
+            | TLSError::FailedToValidateSignature
+            | TLSError::InvalidSignature
+            | TLSError::InvalidSerialNumber => true,
+            TLSError::TcpConnection(_)
+            | TLSError::TcpNoDelay
+            | TLSError::CouldNotGenerateTlsCertificate(_)
+            | TLSError::CouldNotEncodeIdentity(_)
+            | TLSError::CouldNotDecodeIdentity(_)
+            | TLSError::SecretKeyMismatch
+            | TLSError::NotSignedByNetworkCa
+            | TLSError::IntermediatesWithoutNetworkCa
+            | TLSError::IssuedByNetworkCa
+            | TLSError::IdentityFile(..)
+            | TLSError::TlsInitialization(_)
+            | TLSError::TlsHandshake(_)
+            | TLSError::UnknownCipherSuite(_)
+            | TLSError::UnknownGroup(_)
+            | TLSError::TimeIssue
+            | TLSError::NoBackend => false,
+        }
+    }
+}
+
+impl Diagnostic for TLSError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> { Some(Box::new(TLSError::code(self))) }
 }
 
 impl From<TLSError> for ManagerError {
     fn from(value: TLSError) -> Self { ManagerError::Tls(value) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], 34553)) }
+
+    #[test]
+    fn transient_errors_are_retried_and_violations_blamed_on_the_peer() {
+        let dns = ManagerError::Resolve(
+            "bootnode.example".to_string(),
+            io::Error::other("temporary failure in name resolution"),
+        );
+        assert!(dns.is_retryable());
+        assert!(!dns.is_peer_fault());
+        assert!(ManagerError::ConnectTimeout(addr()).is_retryable());
+
+        let wrong_network = ManagerError::HandshakeRejected(
+            addr(),
+            HandshakeError::WrongNetwork {
+                ours: "casper".to_string(),
+                theirs: "casper-test".to_string(),
+            },
+        );
+        assert!(!wrong_network.is_retryable());
+        assert!(wrong_network.is_peer_fault());
+        assert_eq!(wrong_network.code(), "handshake.wrong_network");
+
+        let expired = ManagerError::from(TLSError::Expired {
+            by: TimeDiff::from_seconds(60),
+        });
+        assert!(!expired.is_retryable());
+        assert!(expired.is_peer_fault());
+        assert_eq!(expired.code(), "tls.expired");
+
+        // Our own mistakes are neither worth retrying nor the peer's.
+        let ours = ManagerError::from(TLSError::SecretKeyMismatch);
+        assert!(!ours.is_retryable());
+        assert!(!ours.is_peer_fault());
+    }
+
+    #[test]
+    fn failures_are_bucketed_by_cause() {
+        let refused = ManagerError::from(TLSError::TcpConnection(
+            io::ErrorKind::ConnectionRefused.into(),
+        ));
+        assert_eq!(refused.failure_cause().labels(), ["tcp-refused", ""]);
+        assert_eq!(
+            ManagerError::ConnectTimeout(addr()).failure_cause(),
+            FailureCause::TcpTimeout
+        );
+        assert_eq!(ManagerError::NoAddresses.failure_cause(), FailureCause::Dns);
+
+        let expired = ManagerError::from(TLSError::Expired {
+            by: TimeDiff::from_seconds(60),
+        });
+        assert_eq!(
+            expired.failure_cause().labels(),
+            ["tls-validation", "expired"]
+        );
+        assert_eq!(
+            ManagerError::from(TLSError::TlsHandshake("eof".to_string())).failure_cause(),
+            FailureCause::TlsProtocol
+        );
+
+        let rejected = ManagerError::HandshakeRejected(addr(), HandshakeError::InvalidStamp);
+        assert_eq!(
+            rejected.failure_cause().labels(),
+            ["handshake-rejected", "invalid_stamp"]
+        );
+        assert_eq!(
+            ManagerError::PeerNotFound.failure_cause().labels(),
+            ["other", "peer_not_found"]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Fault injection for exercising resilience logic in tests.
+//!
+//! A [`FaultyTransport`] wraps another transport and passes every stream it
+//! establishes or accepts through the faults currently set on its [`Faults`]
+//! handle: delayed writes, a delayed first write (which on a fresh connection
+//! is our handshake), streams cut off after a number of bytes, and
+//! connections reset at random. The handle can be changed at any time and
+//! affects streams already open, so a test can let peers connect cleanly and
+//! then break things. Random disconnects come from a seeded generator, so a
+//! test sees the same ones on every run as long as it writes in the same
+//! order.
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::TryFutureExt;
+use prometheus::IntCounterVec;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::time::Sleep;
+
+use super::error::ManagerError;
+use super::error::TLSError;
+use super::node_id::NodeId;
+use super::progress::Step;
+use super::tls::Identity;
+use super::tls::SessionId;
+use super::transport::Accepted;
+use super::transport::BoxedStream;
+use super::transport::Listener;
+use super::transport::Transport;
+
+/// The faults to inject, none by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultPlan {
+    /// Delay of every write before it reaches the peer. Writes on a stream
+    /// are held one after the other, so this also caps how many go out.
+    pub latency: Duration,
+    /// Extra delay of the first write on every stream.
+    pub handshake_delay: Duration,
+    /// Bytes every stream carries before it is closed mid-write, so the peer
+    /// reads a truncated frame followed by the end of the stream.
+    pub truncate_after: Option<usize>,
+    /// Chance of every write resetting the connection instead, from 0 to 1.
+    pub disconnect_probability: f64,
+    /// Whether connection attempts are refused outright.
+    pub refuse_connections: bool,
+}
+
+#[derive(Debug)]
+struct FaultState {
+    plan: FaultPlan,
+    rng: StdRng,
+}
+
+/// The faults a [`FaultyTransport`] injects, shared by every clone.
+#[derive(Clone, Debug)]
+pub struct Faults {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl Faults {
+    /// No faults, with random disconnects drawn from `seed` once enabled.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FaultState {
+                plan: FaultPlan::default(),
+                rng: StdRng::seed_from_u64(seed),
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FaultState> {
+        self.state.lock().expect("faults lock poisoned")
+    }
+
+    /// Injects the faults of `plan` from now on.
+    pub fn set(&self, plan: FaultPlan) { self.lock().plan = plan; }
+
+    /// Changes the faults injected from now on.
+    pub fn update(&self, change: impl FnOnce(&mut FaultPlan)) { change(&mut self.lock().plan); }
+
+    /// Stops injecting faults.
+    pub fn clear(&self) { self.set(FaultPlan::default()); }
+
+    pub fn plan(&self) -> FaultPlan { self.lock().plan.clone() }
+
+    /// Whether the next write resets its connection.
+    fn disconnects(&self) -> bool {
+        let mut state = self.lock();
+        let probability = state.plan.disconnect_probability.clamp(0.0, 1.0);
+        probability > 0.0 && state.rng.gen_bool(probability)
+    }
+}
+
+/// A transport injecting [`Faults`] into the streams of another one.
+pub struct FaultyTransport {
+    inner: Arc<dyn Transport>,
+    faults: Faults,
+}
+
+impl FaultyTransport {
+    pub fn new(inner: Arc<dyn Transport>, faults: Faults) -> Self { Self { inner, faults } }
+
+    fn refused(&self) -> Option<ManagerError> {
+        self.faults
+            .plan()
+            .refuse_connections
+            .then(|| TLSError::TcpConnection(io::ErrorKind::ConnectionRefused.into()).into())
+    }
+
+    fn wrap(&self, stream: BoxedStream) -> BoxedStream {
+        Box::new(FaultyStream::new(stream, self.faults.clone()))
+    }
+}
+
+impl Transport for FaultyTransport {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, Result<Box<dyn Listener>, ManagerError>> {
+        async move {
+            let inner = self.inner.bind(addr).await?;
+            Ok(Box::new(FaultyListener {
+                inner,
+                faults: self.faults.clone(),
+            }) as Box<dyn Listener>)
+        }
+        .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<BoxedStream, ManagerError>> {
+        async move {
+            if let Some(refused) = self.refused() {
+                return Err(refused);
+            }
+            Ok(self.wrap(self.inner.connect(addr).await?))
+        }
+        .boxed()
+    }
+
+    fn connect_with_progress<'a>(
+        &'a self,
+        addr: SocketAddr,
+        report: &'a (dyn Fn(Step) + Sync),
+    ) -> BoxFuture<'a, Result<BoxedStream, ManagerError>> {
+        async move {
+            if let Some(refused) = self.refused() {
+                return Err(refused);
+            }
+            let stream = self.inner.connect_with_progress(addr, report).await?;
+            Ok(self.wrap(stream))
+        }
+        .boxed()
+    }
+
+    fn set_identity(&self, identity: Identity) { self.inner.set_identity(identity) }
+
+    fn peer_id(&self, addr: SocketAddr) -> Option<NodeId> { self.inner.peer_id(addr) }
+
+    fn session_id(&self, addr: SocketAddr) -> Option<SessionId> { self.inner.session_id(addr) }
+
+    fn forget_peer(&self, addr: SocketAddr, session_id: SessionId) {
+        self.inner.forget_peer(addr, session_id)
+    }
+
+    fn count_handshakes(&self, counter: IntCounterVec) { self.inner.count_handshakes(counter) }
+}
+
+struct FaultyListener {
+    inner: Box<dyn Listener>,
+    faults: Faults,
+}
+
+impl Listener for FaultyListener {
+    fn local_addr(&self) -> SocketAddr { self.inner.local_addr() }
+
+    fn accept(&mut self) -> BoxFuture<'_, Result<Accepted, ManagerError>> {
+        async move {
+            let accepted = self.inner.accept().await?;
+            let faults = self.faults.clone();
+            let setup = accepted
+                .setup
+                .map_ok(|stream| Box::new(FaultyStream::new(stream, faults)) as BoxedStream);
+            Ok(Accepted {
+                peer_addr: accepted.peer_addr,
+                setup: setup.boxed(),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// A stream passing its writes through the current faults.
+struct FaultyStream {
+    inner: BoxedStream,
+    faults: Faults,
+    /// Bytes written so far.
+    written: usize,
+    /// The delay the pending write is held for.
+    delay: Option<Pin<Box<Sleep>>>,
+    /// Whether the pending write was held for as long as it should be.
+    delayed: bool,
+    /// The error the stream was cut off with; reads end and writes fail
+    /// from then on.
+    cut: Option<io::ErrorKind>,
+    /// Whether the peer was told about the cut.
+    closed: bool,
+}
+
+impl FaultyStream {
+    fn new(inner: BoxedStream, faults: Faults) -> Self {
+        Self {
+            inner,
+            faults,
+            written: 0,
+            delay: None,
+            delayed: false,
+            cut: None,
+            closed: false,
+        }
+    }
+
+    /// Closes the stream so the peer notices, and fails with `kind`.
+    fn poll_cut(&mut self, cx: &mut Context<'_>, kind: io::ErrorKind) -> Poll<io::Result<usize>> {
+        let kind = *self.cut.get_or_insert(kind);
+        if !self.closed {
+            // The peer may be gone already, we are cutting it off anyway.
+            let _ = ready!(Pin::new(&mut self.inner).poll_shutdown(cx));
+            self.closed = true;
+        }
+        Poll::Ready(Err(kind.into()))
+    }
+}
+
+impl AsyncRead for FaultyStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.cut.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for FaultyStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Some(kind) = this.cut {
+            return this.poll_cut(cx, kind);
+        }
+        let plan = this.faults.plan();
+
+        if !this.delayed {
+            let delay = this.delay.get_or_insert_with(|| {
+                let mut wait = plan.latency;
+                if this.written == 0 {
+                    wait += plan.handshake_delay;
+                }
+                Box::pin(tokio::time::sleep(wait))
+            });
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+            this.delayed = true;
+        }
+
+        if this.faults.disconnects() {
+            return this.poll_cut(cx, io::ErrorKind::ConnectionReset);
+        }
+        let allowed = match plan.truncate_after {
+            Some(limit) if this.written >= limit => {
+                return this.poll_cut(cx, io::ErrorKind::BrokenPipe);
+            }
+            Some(limit) => buf.len().min(limit - this.written),
+            None => buf.len(),
+        };
+
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..allowed]))?;
+        this.written += written;
+        this.delayed = false;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::network::testing::MemoryNetwork;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    /// A faulty connection over a fresh in-memory network, ours first.
+    async fn connected(faults: &Faults) -> (BoxedStream, BoxedStream) {
+        let transport = FaultyTransport::new(Arc::new(MemoryNetwork::new()), faults.clone());
+        let mut listener = transport.bind(addr(5000)).await.unwrap();
+        let ours = transport.connect(addr(5000)).await.unwrap();
+        let theirs = listener.accept().await.unwrap().setup.await.unwrap();
+        (ours, theirs)
+    }
+
+    #[tokio::test]
+    async fn writes_are_delayed() {
+        let faults = Faults::new(0);
+        faults.set(FaultPlan {
+            latency: Duration::from_millis(50),
+            handshake_delay: Duration::from_millis(100),
+            ..FaultPlan::default()
+        });
+        let (mut ours, mut theirs) = connected(&faults).await;
+
+        let mut buf = [0; 1];
+        for expected in [Duration::from_millis(150), Duration::from_millis(50)] {
+            let started = Instant::now();
+            ours.write_all(b"x").await.unwrap();
+            theirs.read_exact(&mut buf).await.unwrap();
+            assert!(started.elapsed() >= expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_are_cut_off_after_the_limit() {
+        let faults = Faults::new(0);
+        faults.update(|plan| plan.truncate_after = Some(3));
+        let (mut ours, mut theirs) = connected(&faults).await;
+
+        let error = ours.write_all(b"hello").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        let mut received = Vec::new();
+        theirs.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hel");
+        assert_eq!(ours.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn disconnects_and_refusals_follow_the_plan() {
+        let faults = Faults::new(7);
+        let (mut ours, _theirs) = connected(&faults).await;
+        ours.write_all(b"fine").await.unwrap();
+
+        faults.update(|plan| plan.disconnect_probability = 1.0);
+        let error = ours.write_all(b"gone").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+
+        faults.set(FaultPlan {
+            refuse_connections: true,
+            ..FaultPlan::default()
+        });
+        let transport = FaultyTransport::new(Arc::new(MemoryNetwork::new()), faults.clone());
+        let _listener = transport.bind(addr(5000)).await.unwrap();
+        assert!(transport.connect(addr(5000)).await.is_err());
+        faults.clear();
+        assert!(transport.connect(addr(5000)).await.is_ok());
+    }
+}
@@ -0,0 +1,266 @@
+//! Fetching blocks and deploys from peers by their hash.
+//!
+//! Casper nodes answer a `GetRequest`, naming the kind of item and its
+//! serialized id, with a `GetResponse` carrying a serialized `FetchResponse`:
+//! the item itself, or word that it was not found or is not handed out.
+//! Schultz does not decode the items, so a fetched one is kept as the bytes
+//! the peer sent.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use casper_hashing::Digest;
+use datasize::DataSize;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use super::message::BincodeFormat;
+
+/// Kind of item requested, mirroring casper-node's `fetcher::Tag`.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, DataSize,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Tag {
+    Deploy,
+    LegacyDeploy,
+    Block,
+    BlockHeader,
+    TrieOrChunk,
+    FinalitySignature,
+    SyncLeap,
+    ApprovalsHashes,
+    BlockExecutionResultsOrChunk,
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Deploy | Tag::LegacyDeploy => write!(f, "deploy"),
+            Tag::Block => write!(f, "block"),
+            Tag::BlockHeader => write!(f, "block header"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// An item to fetch, by its hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    Block(Digest),
+    BlockHeader(Digest),
+    Deploy(Digest),
+}
+
+impl Request {
+    /// The kind of item requested. Deploys are requested by their hash alone,
+    /// which casper-node calls a legacy deploy.
+    pub fn tag(&self) -> Tag {
+        match self {
+            Request::Block(_) => Tag::Block,
+            Request::BlockHeader(_) => Tag::BlockHeader,
+            Request::Deploy(_) => Tag::LegacyDeploy,
+        }
+    }
+
+    pub fn hash(&self) -> Digest {
+        match self {
+            Request::Block(hash) | Request::BlockHeader(hash) | Request::Deploy(hash) => *hash,
+        }
+    }
+
+    /// The id of the item as sent in a `GetRequest`.
+    pub fn serialized_id(&self) -> io::Result<Vec<u8>> {
+        BincodeFormat::default().serialize_arbitrary(&self.hash())
+    }
+}
+
+/// What a peer answered, mirroring casper-node's `FetchResponse`, with the
+/// item or id left serialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FetchResponse {
+    /// The serialized item.
+    Fetched(Vec<u8>),
+    /// The peer does not have the item with this serialized id.
+    NotFound(Vec<u8>),
+    /// The peer has the item with this serialized id but does not hand it
+    /// out.
+    NotProvided(Vec<u8>),
+}
+
+impl FetchResponse {
+    /// Decodes the `serialized_item` of a `GetResponse`.
+    ///
+    /// The variant is a single byte, as bincode writes small integers, and
+    /// everything after it is the item or id.
+    pub fn decode(serialized: &[u8]) -> Option<Self> {
+        let (variant, rest) = serialized.split_first()?;
+        match variant {
+            0 => Some(FetchResponse::Fetched(rest.to_vec())),
+            1 => Some(FetchResponse::NotFound(rest.to_vec())),
+            2 => Some(FetchResponse::NotProvided(rest.to_vec())),
+            _ => None,
+        }
+    }
+
+    /// Encodes the response as the `serialized_item` of a `GetResponse`.
+    pub fn encode(&self) -> Vec<u8> {
+        let (variant, rest) = match self {
+            FetchResponse::Fetched(item) => (0, item),
+            FetchResponse::NotFound(id) => (1, id),
+            FetchResponse::NotProvided(id) => (2, id),
+        };
+        let mut serialized = Vec::with_capacity(rest.len() + 1);
+        serialized.push(variant);
+        serialized.extend_from_slice(rest);
+        serialized
+    }
+}
+
+/// An item a peer handed out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fetched {
+    pub peer: SocketAddr,
+    /// The item as the peer serialized it.
+    pub item: Vec<u8>,
+}
+
+/// A request sent, waiting for its answer.
+type Waiting = (Vec<u8>, oneshot::Sender<FetchResponse>);
+
+/// Requests sent to peers and not answered yet.
+///
+/// A fetched item does not name its id, so answers are matched to requests
+/// by peer and kind, oldest first. Answers that the item is missing are
+/// matched by id as well.
+#[derive(Debug, Default)]
+pub struct PendingFetches {
+    waiting: Mutex<BTreeMap<(SocketAddr, Tag), Vec<Waiting>>>,
+}
+
+impl PendingFetches {
+    /// Registers a request for the item with `serialized_id` sent to `peer`.
+    /// Its answer is sent on the returned channel.
+    pub fn expect(
+        &self,
+        peer: SocketAddr,
+        tag: Tag,
+        serialized_id: Vec<u8>,
+    ) -> oneshot::Receiver<FetchResponse> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut waiting = self.waiting.lock().expect("pending fetches lock poisoned");
+        waiting.entry((peer, tag)).or_default().push((serialized_id, reply_tx));
+        reply_rx
+    }
+
+    /// Forgets a request given up on.
+    pub fn cancel(&self, peer: SocketAddr, tag: Tag, serialized_id: &[u8]) {
+        self.take(peer, tag, |id| id == serialized_id);
+    }
+
+    /// Passes the answer of `peer` on to the request it belongs to. Returns
+    /// `false` if there is none.
+    pub fn resolve(&self, peer: SocketAddr, tag: Tag, response: FetchResponse) -> bool {
+        let reply_tx = match &response {
+            FetchResponse::Fetched(_) => self.take(peer, tag, |_| true),
+            FetchResponse::NotFound(id) | FetchResponse::NotProvided(id) => {
+                self.take(peer, tag, |waiting| waiting == id.as_slice())
+            }
+        };
+        match reply_tx {
+            // The request may have timed out just now, nothing to do then.
+            Some(reply_tx) => {
+                let _ = reply_tx.send(response);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the oldest request to `peer` for a `tag` item whose id matches.
+    fn take(
+        &self,
+        peer: SocketAddr,
+        tag: Tag,
+        matches: impl Fn(&[u8]) -> bool,
+    ) -> Option<oneshot::Sender<FetchResponse>> {
+        let mut waiting = self.waiting.lock().expect("pending fetches lock poisoned");
+        let requests = waiting.get_mut(&(peer, tag))?;
+        let position = requests.iter().position(|(id, _)| matches(id))?;
+        let (_, reply_tx) = requests.remove(position);
+        if requests.is_empty() {
+            waiting.remove(&(peer, tag));
+        }
+        Some(reply_tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// casper-node's `FetchResponse`, to check the wire format against.
+    #[derive(Serialize)]
+    enum CasperFetchResponse<T, Id> {
+        Fetched(T),
+        NotFound(Id),
+        NotProvided(Id),
+    }
+
+    fn peer(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn responses_match_casper_encoding() {
+        let bincode = BincodeFormat::default();
+        let request = Request::Deploy(Digest::hash(b"deploy"));
+        let id = request.serialized_id().unwrap();
+        assert_eq!(id.len(), 33, "length prefix and the hash bytes");
+
+        let item = (42u64, "item".to_string());
+        let casper = [
+            CasperFetchResponse::Fetched(item.clone()),
+            CasperFetchResponse::NotFound(item.clone()),
+            CasperFetchResponse::NotProvided(item.clone()),
+        ];
+        for (casper, variant) in casper.iter().zip(0u8..) {
+            let serialized = bincode.serialize_arbitrary(casper).unwrap();
+            let decoded = FetchResponse::decode(&serialized).unwrap();
+            assert_eq!(decoded.encode(), serialized);
+            assert_eq!(serialized[0], variant);
+        }
+
+        let not_provided = FetchResponse::NotProvided(id.clone());
+        let casper = CasperFetchResponse::<(), Digest>::NotProvided(request.hash());
+        assert_eq!(
+            not_provided.encode(),
+            bincode.serialize_arbitrary(&casper).unwrap()
+        );
+        assert_eq!(FetchResponse::decode(&[3]), None);
+        assert_eq!(FetchResponse::decode(&[]), None);
+    }
+
+    #[test]
+    fn answers_go_to_the_matching_request() {
+        let pending = PendingFetches::default();
+        let mut first = pending.expect(peer(1), Tag::Block, vec![1]);
+        let mut second = pending.expect(peer(1), Tag::Block, vec![2]);
+        let mut header = pending.expect(peer(1), Tag::BlockHeader, vec![1]);
+
+        assert!(pending.resolve(peer(1), Tag::Block, FetchResponse::NotFound(vec![2])));
+        assert_eq!(second.try_recv().unwrap(), FetchResponse::NotFound(vec![2]));
+        assert!(!pending.resolve(peer(2), Tag::Block, FetchResponse::Fetched(vec![9])));
+        assert!(pending.resolve(peer(1), Tag::Block, FetchResponse::Fetched(vec![9])));
+        assert_eq!(first.try_recv().unwrap(), FetchResponse::Fetched(vec![9]));
+        assert!(!pending.resolve(peer(1), Tag::Block, FetchResponse::Fetched(vec![9])));
+
+        pending.cancel(peer(1), Tag::BlockHeader, &[1]);
+        assert!(header.try_recv().is_err());
+        assert!(pending.waiting.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,532 @@
+//! Finality signatures validators gossip, verified and weighed per block.
+//!
+//! Validators sign every block they consider final and gossip the id of the
+//! signature: the block, its era and the signer. A node lacking the signature
+//! fetches it from the peer that gossiped it. When watching finality schultz
+//! does the same, verifies every signature against the block it names, and
+//! adds the signer's weight to the block's until a quorum of the validators
+//! signed it.
+//!
+//! casper-node 1.x signs the block hash and era. Version 2 signatures, of
+//! nodes on protocol 2.0, sign the block height and the hash of the chain
+//! name as well.
+//!
+//! Signatures are weighed against the validators of their era, as the
+//! [`EraTracker`] knows them: those of the chainspec in the era it activates
+//! in, and those every switch block seen since announced for the next era.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use casper_hashing::Digest;
+use casper_types::crypto;
+use casper_types::EraId;
+use casper_types::PublicKey;
+use casper_types::Signature;
+use casper_types::U512;
+use num::rational::Ratio;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::era_tracker::EraTracker;
+use super::error::FinalityError;
+use super::message::BincodeFormat;
+use crate::primitives::Chainspec;
+use crate::primitives::GlobalStateReader;
+use crate::primitives::ValidatorSet;
+
+/// Blocks whose signatures are kept, the oldest are forgotten first.
+pub const MAX_BLOCKS: usize = 1024;
+
+/// Identifies a finality signature, mirroring casper-node's
+/// `FinalitySignatureId`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct FinalitySignatureId {
+    pub block_hash: Digest,
+    pub era_id: EraId,
+    pub public_key: PublicKey,
+}
+
+impl FinalitySignatureId {
+    /// Decodes the id a gossip message carries, see
+    /// [`Observed::body`](super::observe::Observed::body).
+    pub fn decode(body: &[u8]) -> Option<Self> {
+        BincodeFormat::default().deserialize_arbitrary(body).ok()
+    }
+
+    /// The id as sent in a `GetRequest`.
+    pub fn serialized(&self) -> io::Result<Vec<u8>> {
+        BincodeFormat::default().serialize_arbitrary(self)
+    }
+}
+
+/// casper-node 1.x's `FinalitySignature`, and version 1 of 2.x's.
+#[derive(Serialize, Deserialize)]
+struct V1 {
+    block_hash: Digest,
+    era_id: EraId,
+    signature: Signature,
+    public_key: PublicKey,
+}
+
+/// Version 2 of casper-node 2.x's `FinalitySignature`.
+#[derive(Serialize, Deserialize)]
+struct V2 {
+    block_hash: Digest,
+    block_height: u64,
+    era_id: EraId,
+    chain_name_hash: Digest,
+    signature: Signature,
+    public_key: PublicKey,
+}
+
+/// casper-node 2.x's `FinalitySignature`.
+#[derive(Serialize, Deserialize)]
+enum Versioned {
+    V1(V1),
+    V2(V2),
+}
+
+/// A validator's signature of a block, of either version.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FinalitySignature {
+    pub block_hash: Digest,
+    /// Height of the block, signed by version 2 signatures only.
+    pub block_height: Option<u64>,
+    pub era_id: EraId,
+    /// Hash of the chain name, signed by version 2 signatures only.
+    pub chain_name_hash: Option<Digest>,
+    pub signature: Signature,
+    pub public_key: PublicKey,
+}
+
+impl From<V1> for FinalitySignature {
+    fn from(v1: V1) -> Self {
+        Self {
+            block_hash: v1.block_hash,
+            block_height: None,
+            era_id: v1.era_id,
+            chain_name_hash: None,
+            signature: v1.signature,
+            public_key: v1.public_key,
+        }
+    }
+}
+
+impl From<V2> for FinalitySignature {
+    fn from(v2: V2) -> Self {
+        Self {
+            block_hash: v2.block_hash,
+            block_height: Some(v2.block_height),
+            era_id: v2.era_id,
+            chain_name_hash: Some(v2.chain_name_hash),
+            signature: v2.signature,
+            public_key: v2.public_key,
+        }
+    }
+}
+
+impl FinalitySignature {
+    /// Decodes a fetched or gossiped signature.
+    ///
+    /// Signatures of casper-node 1.x start with the length of the block hash,
+    /// those of 2.x with their version, so the two cannot be mistaken.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let bincode = BincodeFormat::default();
+        if usize::from(*bytes.first()?) == Digest::LENGTH {
+            return bincode.deserialize_arbitrary::<V1>(bytes).ok().map(Self::from);
+        }
+        match bincode.deserialize_arbitrary(bytes).ok()? {
+            Versioned::V1(v1) => Some(v1.into()),
+            Versioned::V2(v2) => Some(v2.into()),
+        }
+    }
+
+    pub fn id(&self) -> FinalitySignatureId {
+        FinalitySignatureId {
+            block_hash: self.block_hash,
+            era_id: self.era_id,
+            public_key: self.public_key.clone(),
+        }
+    }
+
+    /// What the validator signed.
+    pub fn bytes_to_sign(&self) -> Vec<u8> {
+        let mut bytes = self.block_hash.value().to_vec();
+        if let Some(block_height) = self.block_height {
+            bytes.extend(block_height.to_le_bytes());
+        }
+        bytes.extend(self.era_id.value().to_le_bytes());
+        if let Some(chain_name_hash) = self.chain_name_hash {
+            bytes.extend(chain_name_hash.value());
+        }
+        bytes
+    }
+
+    pub fn verify(&self) -> Result<(), FinalityError> {
+        crypto::verify(self.bytes_to_sign(), &self.signature, &self.public_key).map_err(|_| {
+            FinalityError::InvalidSignature(Box::new(self.public_key.clone()), self.block_hash)
+        })
+    }
+}
+
+/// The weight of every validator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Weights {
+    weights: BTreeMap<PublicKey, U512>,
+    total: U512,
+}
+
+impl Weights {
+    pub fn new(weights: BTreeMap<PublicKey, U512>) -> Self {
+        let total = weights
+            .values()
+            .fold(U512::zero(), |total, weight| total.saturating_add(*weight));
+        Self { weights, total }
+    }
+
+    /// The validators of `chainspec`, loaded from `dir`: those its global
+    /// state update installs, or else the genesis validators.
+    pub fn load(dir: &Path, chainspec: &Chainspec) -> Result<Self, FinalityError> {
+        let installed = match GlobalStateReader::from_dir(dir)? {
+            Some(reader) => ValidatorSet::read(reader, None)?,
+            None => None,
+        };
+        let weights = match installed {
+            Some(set) => set
+                .validators
+                .into_iter()
+                .map(|validator| (validator.public_key.0, validator.weight.0))
+                .collect(),
+            None => chainspec.network_config.accounts_config.validator_weights(),
+        };
+        if weights.is_empty() {
+            return Err(FinalityError::NoValidators(dir.to_path_buf()));
+        }
+        Ok(Self::new(weights))
+    }
+
+    pub fn weight(&self, public_key: &PublicKey) -> Option<U512> {
+        self.weights.get(public_key).copied()
+    }
+
+    pub fn total(&self) -> U512 { self.total }
+
+    pub fn len(&self) -> usize { self.weights.len() }
+
+    pub fn is_empty(&self) -> bool { self.weights.is_empty() }
+}
+
+/// Where a block stands after one more of its signatures was counted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BlockFinality {
+    pub block_hash: Digest,
+    pub era_id: EraId,
+    /// The validator whose signature was counted.
+    pub signer: PublicKey,
+    /// Number of validators that signed the block.
+    pub signers: usize,
+    pub signed_weight: U512,
+    pub total_weight: U512,
+}
+
+/// What counting a signature came to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Collected {
+    /// The signature was counted.
+    Signed(BlockFinality),
+    /// The signature was counted and brought its block to a quorum.
+    Quorum(BlockFinality),
+    /// The signature was counted before.
+    Duplicate,
+}
+
+/// The signers of a block and their weight so far.
+#[derive(Debug, Default)]
+struct Signers {
+    signers: BTreeSet<PublicKey>,
+    weight: U512,
+    quorum: bool,
+}
+
+#[derive(Debug, Default)]
+struct Blocks {
+    signers: BTreeMap<Digest, Signers>,
+    /// Blocks in the order they were first signed.
+    order: VecDeque<Digest>,
+}
+
+type Callback = Box<dyn Fn(&BlockFinality) + Send + Sync>;
+
+/// Collects the finality signatures of blocks and tells when a quorum of
+/// the validators signed one.
+///
+/// A quorum is more than `(1 + f) / 2` of the total weight of the block's
+/// era, with `f` the finality threshold fraction of the chainspec, as
+/// casper-node requires for a block to be strictly final.
+pub struct FinalityCollector {
+    eras: Arc<EraTracker>,
+    quorum: Ratio<u64>,
+    blocks: Mutex<Blocks>,
+    on_signature: Vec<Callback>,
+    on_quorum: Vec<Callback>,
+}
+
+impl FinalityCollector {
+    pub fn new(eras: Arc<EraTracker>, finality_threshold_fraction: Ratio<u64>) -> Self {
+        Self {
+            eras,
+            quorum: (Ratio::from_integer(1) + finality_threshold_fraction) / 2,
+            blocks: Mutex::new(Blocks::default()),
+            on_signature: vec![],
+            on_quorum: vec![],
+        }
+    }
+
+    /// Calls `callback` for every signature counted.
+    pub fn on_signature(
+        mut self,
+        callback: impl Fn(&BlockFinality) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_signature.push(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` once for every block a quorum signed, with the
+    /// signature that completed the quorum.
+    pub fn on_quorum(mut self, callback: impl Fn(&BlockFinality) + Send + Sync + 'static) -> Self {
+        self.on_quorum.push(Box::new(callback));
+        self
+    }
+
+    pub fn eras(&self) -> &EraTracker { &self.eras }
+
+    /// Whether the signature with `id` was counted already.
+    pub fn knows(&self, id: &FinalitySignatureId) -> bool {
+        let blocks = self.blocks.lock().expect("finality lock poisoned");
+        blocks
+            .signers
+            .get(&id.block_hash)
+            .is_some_and(|signers| signers.signers.contains(&id.public_key))
+    }
+
+    /// Verifies `signature` and counts its signer's weight in the era of the
+    /// signature towards its block.
+    pub fn add(&self, signature: &FinalitySignature) -> Result<Collected, FinalityError> {
+        let weights = self
+            .eras
+            .weights(signature.era_id)
+            .ok_or(FinalityError::UnknownEra(signature.era_id))?;
+        let weight = weights
+            .weight(&signature.public_key)
+            .ok_or_else(|| FinalityError::NotAValidator(Box::new(signature.public_key.clone())))?;
+        signature.verify()?;
+
+        let (finality, reached) = {
+            let mut blocks = self.blocks.lock().expect("finality lock poisoned");
+            let Blocks { signers, order } = &mut *blocks;
+            let block = signers.entry(signature.block_hash).or_insert_with(|| {
+                order.push_back(signature.block_hash);
+                Signers::default()
+            });
+            if !block.signers.insert(signature.public_key.clone()) {
+                return Ok(Collected::Duplicate);
+            }
+            block.weight = block.weight.saturating_add(weight);
+            let reached = !block.quorum && self.is_quorum(block.weight, weights.total());
+            block.quorum |= reached;
+            let finality = BlockFinality {
+                block_hash: signature.block_hash,
+                era_id: signature.era_id,
+                signer: signature.public_key.clone(),
+                signers: block.signers.len(),
+                signed_weight: block.weight,
+                total_weight: weights.total(),
+            };
+            while order.len() > MAX_BLOCKS {
+                if let Some(oldest) = order.pop_front() {
+                    signers.remove(&oldest);
+                }
+            }
+            (finality, reached)
+        };
+
+        for callback in &self.on_signature {
+            callback(&finality);
+        }
+        if !reached {
+            return Ok(Collected::Signed(finality));
+        }
+        for callback in &self.on_quorum {
+            callback(&finality);
+        }
+        Ok(Collected::Quorum(finality))
+    }
+
+    /// Whether `weight` is more than the quorum of the `total` weight.
+    fn is_quorum(&self, weight: U512, total: U512) -> bool {
+        let numer = U512::from(*self.quorum.numer());
+        let denom = U512::from(*self.quorum.denom());
+        weight.saturating_mul(denom) > total.saturating_mul(numer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::testing::public_key;
+    use crate::testing::secret_key;
+
+    /// A 1.x signature of `block` by the validator of `seed`.
+    fn sign(seed: u8, block: Digest) -> FinalitySignature {
+        let mut signature = FinalitySignature {
+            block_hash: block,
+            block_height: None,
+            era_id: EraId::new(7),
+            chain_name_hash: None,
+            signature: Signature::System,
+            public_key: public_key(seed),
+        };
+        signature.signature = crypto::sign(
+            signature.bytes_to_sign(),
+            &secret_key(seed),
+            &signature.public_key,
+        );
+        signature
+    }
+
+    /// Validators 1 to 4 of era 7, of weights 10, 20, 30 and 40.
+    fn eras() -> Arc<EraTracker> {
+        let eras = EraTracker::default();
+        let weights = (1..=4).map(|seed| (public_key(seed), U512::from(seed as u64 * 10)));
+        eras.start(EraId::new(7), Weights::new(weights.collect()));
+        Arc::new(eras)
+    }
+
+    #[test]
+    fn signatures_of_both_versions_decode_and_verify() {
+        let block = Digest::hash(b"block");
+        let v1 = sign(1, block);
+        let bincode = BincodeFormat::default();
+        let unversioned = V1 {
+            block_hash: v1.block_hash,
+            era_id: v1.era_id,
+            signature: v1.signature,
+            public_key: v1.public_key.clone(),
+        };
+        let bytes = bincode.serialize_arbitrary(&unversioned).unwrap();
+        assert_eq!(FinalitySignature::decode(&bytes), Some(v1.clone()));
+        let bytes = bincode.serialize_arbitrary(&Versioned::V1(unversioned)).unwrap();
+        assert_eq!(FinalitySignature::decode(&bytes), Some(v1.clone()));
+        v1.verify().unwrap();
+
+        let mut v2 = FinalitySignature {
+            block_height: Some(12),
+            chain_name_hash: Some(Digest::hash(b"casper-test")),
+            ..v1.clone()
+        };
+        v2.signature = crypto::sign(v2.bytes_to_sign(), &secret_key(1), &v2.public_key);
+        let versioned = Versioned::V2(V2 {
+            block_hash: v2.block_hash,
+            block_height: 12,
+            era_id: v2.era_id,
+            chain_name_hash: Digest::hash(b"casper-test"),
+            signature: v2.signature,
+            public_key: v2.public_key.clone(),
+        });
+        let bytes = bincode.serialize_arbitrary(&versioned).unwrap();
+        assert_eq!(FinalitySignature::decode(&bytes), Some(v2.clone()));
+        v2.verify().unwrap();
+
+        // A version 1 signature does not sign the height.
+        let forged = FinalitySignature {
+            block_height: Some(12),
+            ..v1.clone()
+        };
+        assert!(forged.verify().is_err());
+
+        let id = bincode.serialize_arbitrary(&v1.id()).unwrap();
+        assert_eq!(FinalitySignatureId::decode(&id), Some(v1.id()));
+        assert_eq!(FinalitySignature::decode(&[]), None);
+    }
+
+    #[test]
+    fn quorum_is_reached_once_and_told_to_callbacks() {
+        let quorums = Arc::new(AtomicUsize::new(0));
+        let signatures = Arc::new(AtomicUsize::new(0));
+        // With a threshold of 1/3, a quorum is more than 2/3 of the weight.
+        let collector = FinalityCollector::new(eras(), Ratio::new(1, 3))
+            .on_quorum({
+                let quorums = quorums.clone();
+                move |_| {
+                    quorums.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .on_signature({
+                let signatures = signatures.clone();
+                move |_| {
+                    signatures.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        let block = Digest::hash(b"block");
+
+        assert!(matches!(
+            collector.add(&sign(4, block)),
+            Ok(Collected::Signed(_))
+        ));
+        assert!(collector.knows(&sign(4, block).id()));
+        assert_eq!(
+            collector.add(&sign(4, block)).unwrap(),
+            Collected::Duplicate
+        );
+        // 40 + 20 is not more than 2/3 of 100.
+        assert!(matches!(
+            collector.add(&sign(2, block)),
+            Ok(Collected::Signed(_))
+        ));
+        let Collected::Quorum(finality) = collector.add(&sign(1, block)).unwrap() else {
+            panic!("no quorum at 70 of 100");
+        };
+        assert_eq!(finality.signers, 3);
+        assert_eq!(finality.signed_weight, U512::from(70));
+        assert!(matches!(
+            collector.add(&sign(3, block)),
+            Ok(Collected::Signed(_))
+        ));
+        assert_eq!(quorums.load(Ordering::Relaxed), 1);
+        assert_eq!(signatures.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn signatures_of_strangers_or_forged_ones_are_rejected() {
+        let collector = FinalityCollector::new(eras(), Ratio::new(1, 3));
+        let block = Digest::hash(b"block");
+        assert!(matches!(
+            collector.add(&sign(5, block)),
+            Err(FinalityError::NotAValidator(_))
+        ));
+        let mut early = sign(1, block);
+        early.era_id = EraId::new(6);
+        assert!(matches!(
+            collector.add(&early),
+            Err(FinalityError::UnknownEra(_))
+        ));
+
+        let mut forged = sign(1, block);
+        forged.block_hash = Digest::hash(b"other block");
+        assert!(matches!(
+            collector.add(&forged),
+            Err(FinalityError::InvalidSignature(..))
+        ));
+        assert!(!collector.knows(&forged.id()));
+    }
+}
@@ -0,0 +1,324 @@
+//! Peer discovery through gossiped addresses.
+//!
+//! Casper nodes periodically gossip their public listening address to the
+//! peers they are connected to. Schultz takes part in the same exchange: it
+//! announces its own address after every handshake and on a timer, and feeds
+//! the addresses it hears about into an [`AddressBook`] from which new
+//! outgoing connections are dialed.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+
+use datasize::DataSize;
+use serde::Deserialize;
+use serde::Serialize;
+use strum::EnumDiscriminants;
+
+use super::fetch::Tag;
+use super::message::Routable;
+use super::mux::Channel;
+use super::node_id::NodeId;
+use super::node_id::PeerKey;
+use crate::primitives::Payload;
+
+/// Number of connected peers a newly learned address is relayed to.
+pub const GOSSIP_FANOUT: usize = 3;
+
+/// A public listening address gossiped across the network.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, DataSize,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct GossipedAddress {
+    /// The public listening address of the node.
+    address: SocketAddr,
+    /// Distinguishes subsequent announcements of the same address, so
+    /// gossipers do not treat them as already held.
+    index: u32,
+}
+
+impl GossipedAddress {
+    pub fn new(address: SocketAddr, index: u32) -> Self { Self { address, index } }
+
+    pub fn address(&self) -> SocketAddr { self.address }
+}
+
+impl Display for GossipedAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "gossiped-address {} ({})", self.address, self.index)
+    }
+}
+
+/// A message of the address gossiper, mirroring casper-node's
+/// `gossiper::Message<GossipedAddress>`.
+///
+/// Addresses are their own identifiers, so `Gossip` already carries the full
+/// item and `GetItem`/`Item` are only kept for wire compatibility.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DataSize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum GossipMessage {
+    /// Announces an address.
+    Gossip(GossipedAddress),
+    /// Answer to a `Gossip`, telling whether the address was already known.
+    GossipResponse {
+        item_id: GossipedAddress,
+        is_already_held: bool,
+    },
+    /// Requests an item by its identifier.
+    GetItem(GossipedAddress),
+    /// Answer to a `GetItem`.
+    Item(Box<GossipedAddress>),
+}
+
+/// Payload of `Message::Payload` as exchanged with Casper nodes.
+///
+/// Only the address gossiper and item fetching are understood. The other
+/// variants stand in for Casper payloads schultz does not decode and exist so
+/// that the understood ones keep their wire tags; any such message fails to
+/// decode and is ignored like before.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DataSize, EnumDiscriminants)]
+#[strum_discriminants(derive(PartialOrd, Ord, Hash))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum NodePayload {
+    Consensus,
+    ConsensusRequest,
+    BlockGossiper,
+    DeployGossiper,
+    FinalitySignatureGossiper,
+    AddressGossiper(GossipMessage),
+    /// Requests an item by its serialized id, see [`fetch`](super::fetch).
+    GetRequest {
+        tag: Tag,
+        serialized_id: Vec<u8>,
+    },
+    /// Answers a `GetRequest` with a serialized
+    /// [`FetchResponse`](super::fetch::FetchResponse).
+    GetResponse {
+        tag: Tag,
+        serialized_item: Vec<u8>,
+    },
+}
+
+impl Payload for NodePayload {
+    fn channel(&self) -> Channel {
+        match self {
+            NodePayload::ConsensusRequest | NodePayload::GetRequest { .. } => Channel::Requests,
+            NodePayload::GetResponse { .. } => Channel::Responses,
+            _ => Channel::Gossip,
+        }
+    }
+}
+
+impl Routable for NodePayload {
+    type Kind = NodePayloadDiscriminants;
+
+    fn kind(&self) -> NodePayloadDiscriminants { self.into() }
+}
+
+impl Display for NodePayload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NodePayload::AddressGossiper(GossipMessage::Gossip(item)) => {
+                write!(f, "gossip {item}")
+            }
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// Addresses schultz knows about and the ones it is connected to.
+#[derive(Clone, Debug, DataSize)]
+pub struct AddressBook {
+    /// Our own public address, never dialed.
+    own: SocketAddr,
+    /// Every address learned through handshakes or gossip.
+    known: BTreeSet<SocketAddr>,
+    /// Addresses we dialed and are connected to.
+    outgoing: BTreeSet<SocketAddr>,
+    /// Public addresses of peers that connected to us.
+    incoming: BTreeSet<SocketAddr>,
+    /// Addresses being dialed right now.
+    dialing: BTreeSet<SocketAddr>,
+    /// Peers that broke the protocol: the nodes, turned away at any address,
+    /// and the addresses they were at, never learned again.
+    blocked: BTreeSet<PeerKey>,
+}
+
+impl AddressBook {
+    pub fn new(own: SocketAddr) -> Self {
+        Self {
+            own,
+            known: BTreeSet::new(),
+            outgoing: BTreeSet::new(),
+            incoming: BTreeSet::new(),
+            dialing: BTreeSet::new(),
+            blocked: BTreeSet::new(),
+        }
+    }
+
+    /// Records a learned address. Returns `true` if it was not known before.
+    pub fn learn(&mut self, addr: SocketAddr) -> bool {
+        addr != self.own && !self.blocked.contains(&PeerKey::Addr(addr)) && self.known.insert(addr)
+    }
+
+    /// Records a peer that connected to us under its public address.
+    ///
+    /// The address is not marked as known, so the peer's own announcement
+    /// still counts as news and gets relayed to the rest of our peers.
+    pub fn incoming_connected(&mut self, addr: SocketAddr) {
+        if addr != self.own {
+            self.incoming.insert(addr);
+        }
+    }
+
+    /// Records an outgoing connection that completed its handshake.
+    pub fn outgoing_connected(&mut self, addr: SocketAddr) {
+        self.learn(addr);
+        self.dialing.remove(&addr);
+        self.outgoing.insert(addr);
+    }
+
+    /// Forgets an address, e.g. after failing to dial it.
+    pub fn forget(&mut self, addr: &SocketAddr) {
+        self.known.remove(addr);
+        self.dialing.remove(addr);
+        self.outgoing.remove(addr);
+        self.incoming.remove(addr);
+    }
+
+    /// Forgets an address and refuses to learn it again, e.g. after the
+    /// peer there broke the protocol. The `node` found there, if known, is
+    /// blocked wherever it turns up next.
+    pub fn block(&mut self, addr: SocketAddr, node: Option<NodeId>) {
+        self.forget(&addr);
+        self.blocked.insert(PeerKey::Addr(addr));
+        self.blocked.extend(node.map(PeerKey::Node));
+    }
+
+    /// Whether `node` broke the protocol at some address before.
+    pub fn is_blocked(&self, node: &NodeId) -> bool { self.blocked.contains(&PeerKey::Node(*node)) }
+
+    /// Drops outgoing connections that are no longer established.
+    pub fn retain_outgoing(&mut self, connected: &[SocketAddr]) {
+        self.outgoing.retain(|addr| connected.contains(addr));
+    }
+
+    /// Number of outgoing connections, established or being dialed.
+    pub fn outgoing_count(&self) -> usize { self.outgoing.len() + self.dialing.len() }
+
+    /// Picks addresses to dial so that the number of outgoing connections
+    /// reaches `target`, and marks them as being dialed.
+    ///
+    /// Addresses are picked best first as ordered by `rank`, see
+    /// [`Reputation::rank`](super::reputation::Reputation::rank).
+    pub fn next_to_dial(
+        &mut self,
+        target: usize,
+        rank: impl FnOnce(Vec<SocketAddr>) -> Vec<SocketAddr>,
+    ) -> Vec<SocketAddr> {
+        let wanted = target.saturating_sub(self.outgoing_count());
+        let candidates: Vec<SocketAddr> = self
+            .known
+            .iter()
+            .filter(|addr| {
+                !self.outgoing.contains(addr)
+                    && !self.incoming.contains(addr)
+                    && !self.dialing.contains(addr)
+            })
+            .copied()
+            .collect();
+        let picked: Vec<SocketAddr> = rank(candidates).into_iter().take(wanted).collect();
+        self.dialing.extend(picked.iter().copied());
+        picked
+    }
+
+    /// Every address learned so far.
+    pub fn known(&self) -> impl Iterator<Item = &SocketAddr> { self.known.iter() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::message::BincodeFormat;
+    use crate::network::message::Message;
+    use crate::network::reputation::Behavior;
+    use crate::network::reputation::Reputation;
+    use crate::utils::Fingerprint;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn dials_up_to_target_and_skips_connected_peers() {
+        let mut book = AddressBook::new(addr(1));
+        assert!(!book.learn(addr(1)), "own address is never learned");
+
+        book.outgoing_connected(addr(2));
+        book.incoming_connected(addr(3));
+        assert!(book.learn(addr(3)), "incoming peers still get relayed");
+        for port in 4..10 {
+            assert!(book.learn(addr(port)));
+        }
+        assert!(!book.learn(addr(4)));
+
+        assert_eq!(book.next_to_dial(3, |addrs| addrs), vec![addr(4), addr(5)]);
+        assert_eq!(book.next_to_dial(3, |addrs| addrs), vec![]);
+
+        book.forget(&addr(4));
+        book.outgoing_connected(addr(5));
+        assert_eq!(book.next_to_dial(3, |addrs| addrs), vec![addr(6)]);
+
+        book.retain_outgoing(&[addr(5)]);
+        assert_eq!(book.outgoing_count(), 2);
+
+        let node = NodeId::from(Fingerprint::from_bytes([7; Fingerprint::SIZE]));
+        book.block(addr(7), Some(node));
+        assert!(
+            !book.learn(addr(7)),
+            "blocked addresses are never learned again"
+        );
+        assert!(!book.known().any(|known| *known == addr(7)));
+        assert!(book.is_blocked(&node));
+    }
+
+    #[test]
+    fn dials_the_best_scoring_addresses_first() {
+        let mut book = AddressBook::new(addr(1));
+        for port in 2..6 {
+            book.learn(addr(port));
+        }
+        let reputation = Reputation::default();
+        reputation.record(addr(2), Behavior::ConnectFailed);
+        reputation.record(addr(5), Behavior::HandshakeCompleted);
+
+        let picked = book.next_to_dial(2, |addrs| reputation.rank(addrs));
+        assert_eq!(picked, vec![addr(5), addr(3)]);
+    }
+
+    #[test]
+    fn address_gossip_keeps_casper_wire_tag() {
+        let message = Message::Payload(NodePayload::AddressGossiper(GossipMessage::Gossip(
+            GossipedAddress::new(addr(34553), 7),
+        )));
+        let bytes = BincodeFormat::default().serialize_arbitrary(&message).unwrap();
+
+        // `Message::Payload` is variant 3, `AddressGossiper` variant 5 and
+        // `Gossip` variant 0, each a single varint byte.
+        assert_eq!(&bytes[..3], &[3, 5, 0]);
+    }
+
+    #[test]
+    fn fetch_requests_keep_casper_wire_tag() {
+        let message = Message::Payload(NodePayload::GetRequest {
+            tag: Tag::BlockHeader,
+            serialized_id: vec![1, 2],
+        });
+        let bytes = BincodeFormat::default().serialize_arbitrary(&message).unwrap();
+
+        // `GetRequest` is variant 6 and `BlockHeader` variant 3, followed by
+        // the length of the id.
+        assert_eq!(bytes, [3, 6, 3, 2, 1, 2]);
+    }
+}
@@ -0,0 +1,659 @@
+//! Protocol-level handshake exchanged once the TLS session is established.
+//!
+//! Both sides send a [`Handshake`] describing the network they belong to and
+//! the chainspec they run. A peer is only considered connected once its
+//! handshake passes [`Handshake::negotiate`] against our own chainspec.
+//!
+//! Validators also [stamp](HandshakeStamp) their handshakes with a nonce and
+//! the time, signed by their consensus key along with the TLS session and
+//! what the handshake tells of them, so a recorded handshake can neither be
+//! replayed nor altered to pass as them. Stamps are checked with
+//! [`Handshake::check_stamp`].
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use casper_hashing::Digest;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use serde::Serialize;
+use tokio_serde::Serializer;
+use tracing::warn;
+
+use super::compression::Compression;
+use super::error::HandshakeError;
+use super::error::ManagerError;
+use super::message::ConsensusCertificate;
+use super::message::HandshakeStamp;
+use super::message::Message;
+use super::message::MessagePackFormat;
+use super::message::StampedHandshake;
+use super::protocol::VERSIONS;
+use super::tls::SessionId;
+use crate::crypto::ConsensusKeys;
+use crate::primitives::Chainspec;
+use crate::primitives::Payload;
+
+/// Contents of a `Message::Handshake`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    /// Network the sender is connected to.
+    pub network_name: String,
+    /// The public address of the sender.
+    pub public_addr: SocketAddr,
+    /// Protocol version the sender is speaking.
+    pub protocol_version: ProtocolVersion,
+    /// A self-signed certificate indicating validator status.
+    pub consensus_certificate: Option<ConsensusCertificate>,
+    /// True if the sender is syncing.
+    pub is_syncing: bool,
+    /// Hash of the chainspec the sender is running.
+    pub chainspec_hash: Option<Digest>,
+    /// Frame compression algorithms the sender supports.
+    pub compression: Vec<Compression>,
+    /// Whether the sender multiplexes channels over the connection.
+    pub multiplexing: bool,
+    /// Whether the sender ends its frames with a checksum.
+    pub checksums: bool,
+    /// Proof that the handshake is not replayed, sent by validators.
+    pub stamp: Option<HandshakeStamp>,
+}
+
+/// Outcome of a handshake with a peer, kept for status reporting.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HandshakeResult {
+    pub peer: SocketAddr,
+    pub timestamp: Timestamp,
+    /// Why the handshake failed, `None` if it succeeded.
+    pub error: Option<String>,
+    /// Whether the peer certified its handshake with the consensus key of a
+    /// validator of the latest era known, `None` if it sent no certificate.
+    pub validator: Option<bool>,
+}
+
+impl HandshakeResult {
+    pub fn new<T, E: ToString>(peer: SocketAddr, outcome: Result<T, E>) -> Self {
+        Self {
+            peer,
+            timestamp: Timestamp::now(),
+            error: outcome.err().map(|error| error.to_string()),
+            validator: None,
+        }
+    }
+
+    /// Records whether the peer is a validator, see
+    /// [`HandshakeResult::validator`].
+    pub fn with_validator(mut self, validator: Option<bool>) -> Self {
+        self.validator = validator;
+        self
+    }
+}
+
+impl Handshake {
+    /// Creates the handshake we send to peers.
+    pub fn new(chainspec: &Chainspec, public_addr: SocketAddr) -> Self {
+        Self {
+            network_name: chainspec.network_config.name.clone(),
+            public_addr,
+            protocol_version: chainspec.protocol_version(),
+            consensus_certificate: None, // only validators send one
+            is_syncing: false,           // not required
+            chainspec_hash: Some(chainspec.hash()),
+            compression: vec![],
+            multiplexing: false,
+            checksums: false,
+            stamp: None,
+        }
+    }
+
+    /// Advertises the given frame compression algorithms.
+    pub fn with_compression(mut self, compression: Vec<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Advertises multiplexing if `multiplexing` is set.
+    pub fn with_multiplexing(mut self, multiplexing: bool) -> Self {
+        self.multiplexing = multiplexing;
+        self
+    }
+
+    /// Advertises checksums if `checksums` is set.
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Proves we are the validator `consensus_certificate` names.
+    pub fn with_consensus_certificate(
+        mut self,
+        consensus_certificate: Option<ConsensusCertificate>,
+    ) -> Self {
+        self.consensus_certificate = consensus_certificate;
+        self
+    }
+
+    /// Proves the handshake is made now, see [`ConsensusKeys::stamp`].
+    pub fn with_stamp(mut self, stamp: Option<HandshakeStamp>) -> Self {
+        self.stamp = stamp;
+        self
+    }
+
+    /// Stamps the handshake with `keys` for the TLS session `session_id`, if
+    /// it carries a consensus certificate.
+    pub fn stamped_with(
+        self,
+        keys: Option<&ConsensusKeys>,
+        session_id: Option<&SessionId>,
+    ) -> Self {
+        let stamp = match (&self.consensus_certificate, keys, session_id) {
+            (Some(_), Some(keys), Some(session_id)) => Some(keys.stamp(&self.stamped(session_id))),
+            _ => None,
+        };
+        self.with_stamp(stamp)
+    }
+
+    /// What a stamp of the handshake sent over the TLS session `session_id`
+    /// signs.
+    pub fn stamped<'a>(&'a self, session_id: &'a SessionId) -> StampedHandshake<'a> {
+        StampedHandshake {
+            session_id,
+            network_name: &self.network_name,
+            public_addr: self.public_addr,
+            chainspec_hash: self.chainspec_hash,
+        }
+    }
+
+    /// Extracts the handshake from a message, if it is one.
+    pub fn from_message<P>(message: &Message<P>) -> Option<Self> {
+        match message {
+            Message::Handshake {
+                network_name,
+                public_addr,
+                protocol_version,
+                consensus_certificate,
+                is_syncing,
+                chainspec_hash,
+                compression,
+                multiplexing,
+                checksums,
+                stamp,
+            } => Some(Self {
+                network_name: network_name.clone(),
+                public_addr: *public_addr,
+                protocol_version: *protocol_version,
+                consensus_certificate: consensus_certificate.clone(),
+                is_syncing: *is_syncing,
+                chainspec_hash: *chainspec_hash,
+                compression: compression.clone(),
+                multiplexing: *multiplexing,
+                checksums: *checksums,
+                stamp: stamp.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn into_message<P>(self) -> Message<P> {
+        Message::Handshake {
+            network_name: self.network_name,
+            public_addr: self.public_addr,
+            protocol_version: self.protocol_version,
+            consensus_certificate: self.consensus_certificate,
+            is_syncing: self.is_syncing,
+            chainspec_hash: self.chainspec_hash,
+            compression: self.compression,
+            multiplexing: self.multiplexing,
+            checksums: self.checksums,
+            stamp: self.stamp,
+        }
+    }
+
+    /// Encodes the handshake the way Casper expects it on the wire (msgpack).
+    pub fn encode<P: Payload>(self) -> Result<Bytes, ManagerError> {
+        Pin::new(&mut MessagePackFormat)
+            .serialize(&self.into_message::<P>())
+            .map_err(|e| ManagerError::CouldNotEncodeOurHandshake(e.to_string()))
+    }
+
+    /// Checks a peer's handshake against our chainspec.
+    ///
+    /// The network name and protocol version are checked first since they
+    /// give the most actionable error; the chainspec hash catches every other
+    /// configuration difference.
+    ///
+    /// With `allow_version_mismatch`, a peer on another protocol version is
+    /// only warned about. Its chainspec hash is not checked then either, as
+    /// the hash covers the protocol version and cannot match. Peers on a
+    /// version schultz does not speak at all are rejected either way.
+    pub fn negotiate(
+        &self,
+        chainspec: &Chainspec,
+        allow_version_mismatch: bool,
+    ) -> Result<(), HandshakeError> {
+        if self.network_name != chainspec.network_config.name {
+            return Err(HandshakeError::WrongNetwork {
+                ours: chainspec.network_config.name.clone(),
+                theirs: self.network_name.clone(),
+            });
+        }
+
+        let wire = VERSIONS
+            .wire(self.protocol_version)
+            .ok_or(HandshakeError::UnsupportedVersion(self.protocol_version))?;
+
+        if self.protocol_version != chainspec.protocol_version() {
+            let error = HandshakeError::IncompatibleVersion {
+                ours: chainspec.protocol_version(),
+                theirs: self.protocol_version,
+            };
+            if !allow_version_mismatch {
+                return Err(error);
+            }
+            warn!("Accepting the handshake although the {error}");
+            return Ok(());
+        }
+
+        // Peers on a version sending chainspec hashes have to send theirs.
+        let peer_chainspec_hash = match self.chainspec_hash {
+            Some(hash) => hash,
+            None if wire.chainspec_hash => return Err(HandshakeError::MissingChainspecHash),
+            None => return Ok(()),
+        };
+
+        if peer_chainspec_hash != chainspec.hash() {
+            return Err(HandshakeError::ChainspecMismatch {
+                ours: chainspec.hash(),
+                theirs: peer_chainspec_hash,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the stamp of a peer's handshake received over the TLS session
+    /// `session_id`. A handshake with a consensus certificate has to be
+    /// stamped, by the key of the certificate, for this session and the rest
+    /// of the handshake, less than `max_age` from now either way, and not
+    /// seen by `guard` before.
+    ///
+    /// Casper validators do not stamp their handshakes, so theirs are refused
+    /// unless `accept_unstamped`, and then only if the certificate signs
+    /// `session_id`. A handshake without a certificate passes unstamped.
+    pub fn check_stamp(
+        &self,
+        session_id: Option<&SessionId>,
+        max_age: TimeDiff,
+        accept_unstamped: bool,
+        guard: &ReplayGuard,
+    ) -> Result<(), HandshakeError> {
+        let Some(certificate) = &self.consensus_certificate else {
+            return match self.stamp {
+                Some(_) => Err(HandshakeError::StampWithoutCertificate),
+                None => Ok(()),
+            };
+        };
+        // Without TLS there is no session to certify or stamp.
+        let session_id = session_id.ok_or(HandshakeError::InvalidCertificate)?;
+        let Some(stamp) = &self.stamp else {
+            if !accept_unstamped {
+                return Err(HandshakeError::CertificateWithoutStamp);
+            }
+            return certificate
+                .validate(session_id)
+                .map_err(|_| HandshakeError::InvalidCertificate);
+        };
+        stamp
+            .validate(certificate.public_key(), &self.stamped(session_id))
+            .map_err(|_| HandshakeError::InvalidStamp)?;
+
+        // A clock running ahead is as suspicious as one running behind.
+        let now = Timestamp::now();
+        let age = now
+            .saturating_diff(stamp.timestamp())
+            .max(stamp.timestamp().saturating_diff(now));
+        if age > max_age {
+            return Err(HandshakeError::StaleStamp { age, max: max_age });
+        }
+        if !guard.first_seen(certificate.public_key(), stamp, max_age) {
+            return Err(HandshakeError::ReplayedStamp);
+        }
+        Ok(())
+    }
+}
+
+/// The handshake stamps seen lately, so none is accepted twice.
+///
+/// Stamps are forgotten once they are too old to pass
+/// [`Handshake::check_stamp`] anyway, which keeps the guard small.
+#[derive(Debug, Default)]
+pub struct ReplayGuard {
+    seen: Mutex<BTreeMap<(PublicKey, u64), Timestamp>>,
+}
+
+impl ReplayGuard {
+    /// Remembers `stamp` of the validator `public_key`, and returns whether
+    /// it was not seen before.
+    fn first_seen(
+        &self,
+        public_key: &PublicKey,
+        stamp: &HandshakeStamp,
+        max_age: TimeDiff,
+    ) -> bool {
+        let mut seen = self.seen.lock().expect("replay guard lock poisoned");
+        let oldest = Timestamp::now().saturating_sub(max_age);
+        seen.retain(|_, timestamp| *timestamp >= oldest);
+        let key = (public_key.clone(), stamp.nonce().value());
+        seen.insert(key, stamp.timestamp()).is_none()
+    }
+}
+
+/// Any handshake a peer could send, minus the consensus certificate and stamp
+/// whose signatures would never verify anyway.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Handshake {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            network_name: u.arbitrary()?,
+            // Flow info and scope id are not sent over the wire.
+            public_addr: SocketAddr::new(u.arbitrary()?, u.arbitrary()?),
+            protocol_version: ProtocolVersion::from_parts(
+                u.arbitrary()?,
+                u.arbitrary()?,
+                u.arbitrary()?,
+            ),
+            consensus_certificate: None,
+            is_syncing: u.arbitrary()?,
+            chainspec_hash: u.arbitrary::<Option<[u8; Digest::LENGTH]>>()?.map(Digest::from),
+            compression: u.arbitrary()?,
+            multiplexing: u.arbitrary()?,
+            checksums: u.arbitrary()?,
+            stamp: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use casper_types::crypto;
+    use casper_types::SecretKey;
+    use serde::Deserialize;
+    use tokio_serde::Deserializer;
+
+    use super::*;
+    use crate::network::config::Config;
+    use crate::network::gossip::NodePayload;
+    use crate::primitives::Nonce;
+
+    fn chainspec() -> Chainspec {
+        Chainspec::from_path("examples").expect("example chainspec should load")
+    }
+
+    fn peer_handshake(chainspec: &Chainspec) -> Handshake {
+        Handshake::new(chainspec, "127.0.0.1:34553".parse().unwrap())
+    }
+
+    #[test]
+    fn accepts_matching_handshake() {
+        let chainspec = chainspec();
+        assert!(peer_handshake(&chainspec).negotiate(&chainspec, false).is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_network() {
+        let chainspec = chainspec();
+        let mut handshake = peer_handshake(&chainspec);
+        handshake.network_name = "casper-test".to_string();
+
+        assert!(matches!(
+            handshake.negotiate(&chainspec, true),
+            Err(HandshakeError::WrongNetwork { theirs, .. }) if theirs == "casper-test"
+        ));
+    }
+
+    #[test]
+    fn rejects_other_protocol_version() {
+        let chainspec = chainspec();
+        let mut handshake = peer_handshake(&chainspec);
+        handshake.protocol_version = ProtocolVersion::from_parts(1, 4, 0);
+
+        assert!(matches!(
+            handshake.negotiate(&chainspec, false),
+            Err(HandshakeError::IncompatibleVersion { .. })
+        ));
+        // Allowed for debugging, despite the chainspec hash differing too.
+        handshake.chainspec_hash = Some(Digest::hash(b"another chainspec"));
+        assert!(handshake.negotiate(&chainspec, true).is_ok());
+    }
+
+    #[test]
+    fn rejects_unsupported_protocol_versions_even_when_allowed() {
+        let chainspec = chainspec();
+        let mut handshake = peer_handshake(&chainspec);
+        handshake.protocol_version = VERSIONS.until();
+
+        assert_eq!(
+            handshake.negotiate(&chainspec, true),
+            Err(HandshakeError::UnsupportedVersion(VERSIONS.until()))
+        );
+    }
+
+    #[test]
+    fn versions_before_chainspec_hashes_do_not_need_one() {
+        let mut chainspec = chainspec();
+        chainspec.protocol_config.version = ProtocolVersion::from_parts(1, 3, 0);
+        let mut handshake = peer_handshake(&chainspec);
+        handshake.chainspec_hash = None;
+
+        assert!(handshake.negotiate(&chainspec, false).is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_or_different_chainspec_hash() {
+        let chainspec = chainspec();
+        let mut handshake = peer_handshake(&chainspec);
+
+        handshake.chainspec_hash = None;
+        assert!(matches!(
+            handshake.negotiate(&chainspec, true),
+            Err(HandshakeError::MissingChainspecHash)
+        ));
+
+        handshake.chainspec_hash = Some(Digest::hash(b"another chainspec"));
+        assert!(matches!(
+            handshake.negotiate(&chainspec, true),
+            Err(HandshakeError::ChainspecMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_string_length_is_rejected_without_allocating() {
+        // A one-entry map whose key claims to be a 3.6 GB string.
+        let mut frame = BytesMut::from(&[0x81, 0xdb, 0xdb, 0xdb, 0xdb, 0xdb][..]);
+        frame.extend_from_slice(&[0xdb; 64]);
+
+        let decoded: Result<Message<Vec<u8>>, _> =
+            Pin::new(&mut MessagePackFormat).deserialize(&frame);
+        assert!(decoded.is_err());
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let chainspec = chainspec();
+        let handshake = peer_handshake(&chainspec);
+        let message: Message<Vec<u8>> = handshake.clone().into_message();
+        assert_eq!(Handshake::from_message(&message), Some(handshake));
+    }
+
+    /// A handshake the way casper-node decodes it, knowing none of the
+    /// fields schultz adds.
+    #[derive(Debug, Deserialize)]
+    enum CasperMessage {
+        Handshake {
+            network_name: String,
+            public_addr: SocketAddr,
+            protocol_version: ProtocolVersion,
+            consensus_certificate: Option<ConsensusCertificate>,
+            is_syncing: bool,
+            chainspec_hash: Option<Digest>,
+        },
+    }
+
+    #[test]
+    fn casper_nodes_decode_our_handshakes() {
+        let chainspec = chainspec();
+        let config = Config::default();
+        // A validator's, which carries every extension.
+        let ours = stamped(&chainspec, 1, 7, Timestamp::now())
+            .with_compression(config.compression.clone())
+            .with_multiplexing(config.multiplexing)
+            .with_checksums(config.checksums);
+        assert!(!ours.compression.is_empty());
+        let encoded = ours.clone().encode::<NodePayload>().unwrap();
+
+        let decoded: CasperMessage = Pin::new(&mut MessagePackFormat)
+            .deserialize(&BytesMut::from(&encoded[..]))
+            .unwrap();
+        let CasperMessage::Handshake {
+            network_name,
+            public_addr,
+            protocol_version,
+            consensus_certificate,
+            is_syncing,
+            chainspec_hash,
+        } = decoded;
+        assert_eq!(network_name, ours.network_name);
+        assert_eq!(public_addr, ours.public_addr);
+        assert_eq!(protocol_version, ours.protocol_version);
+        assert_eq!(consensus_certificate, ours.consensus_certificate);
+        assert_eq!(is_syncing, ours.is_syncing);
+        assert_eq!(chainspec_hash, ours.chainspec_hash);
+    }
+
+    fn secret_key(byte: u8) -> SecretKey { SecretKey::ed25519_from_bytes([byte; 32]).unwrap() }
+
+    /// The TLS session the handshakes of the tests are sent over.
+    fn session() -> SessionId { SessionId::from_bytes([7; 32]) }
+
+    fn certified(chainspec: &Chainspec) -> Handshake {
+        let keys = ConsensusKeys::new(secret_key(1));
+        peer_handshake(chainspec).with_consensus_certificate(Some(keys.certify(&session())))
+    }
+
+    /// A certified handshake whose stamp of `nonce` and `timestamp` is
+    /// signed by `secret_key(signer)`.
+    fn stamped(chainspec: &Chainspec, signer: u8, nonce: u64, timestamp: Timestamp) -> Handshake {
+        let secret_key = secret_key(signer);
+        let nonce = Nonce::new(nonce);
+        let handshake = certified(chainspec);
+        let bytes = HandshakeStamp::signed_bytes(nonce, timestamp, &handshake.stamped(&session()));
+        let signature = crypto::sign(bytes, &secret_key, &PublicKey::from(&secret_key));
+        handshake.with_stamp(Some(HandshakeStamp::new(nonce, timestamp, signature)))
+    }
+
+    #[test]
+    fn accepts_fresh_stamps_once() {
+        let chainspec = chainspec();
+        let guard = ReplayGuard::default();
+        let max_age = TimeDiff::from_seconds(60);
+        let check =
+            |handshake: &Handshake| handshake.check_stamp(Some(&session()), max_age, false, &guard);
+
+        assert!(check(&peer_handshake(&chainspec)).is_ok());
+
+        let keys = ConsensusKeys::new(secret_key(1));
+        let handshake = certified(&chainspec).stamped_with(Some(&keys), Some(&session()));
+        assert!(check(&handshake).is_ok());
+        assert!(matches!(
+            check(&handshake),
+            Err(HandshakeError::ReplayedStamp)
+        ));
+
+        let other = stamped(&chainspec, 1, 1, Timestamp::now());
+        assert!(check(&other).is_ok());
+        let message: Message<Vec<u8>> = other.clone().into_message();
+        assert_eq!(Handshake::from_message(&message), Some(other));
+    }
+
+    #[test]
+    fn certificates_have_to_be_stamped() {
+        let chainspec = chainspec();
+        let guard = ReplayGuard::default();
+        let max_age = TimeDiff::from_seconds(60);
+
+        let unstamped = certified(&chainspec);
+        assert!(matches!(
+            unstamped.check_stamp(Some(&session()), max_age, false, &guard),
+            Err(HandshakeError::CertificateWithoutStamp)
+        ));
+        // As Casper validators send theirs, certifying the session.
+        assert!(unstamped.check_stamp(Some(&session()), max_age, true, &guard).is_ok());
+        let elsewhere = SessionId::from_bytes([8; 32]);
+        assert!(matches!(
+            unstamped.check_stamp(Some(&elsewhere), max_age, true, &guard),
+            Err(HandshakeError::InvalidCertificate)
+        ));
+        assert!(matches!(
+            unstamped.check_stamp(None, max_age, true, &guard),
+            Err(HandshakeError::InvalidCertificate)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_stamps() {
+        let chainspec = chainspec();
+        let guard = ReplayGuard::default();
+        let max_age = TimeDiff::from_seconds(60);
+        let now = Timestamp::now();
+        let check =
+            |handshake: &Handshake| handshake.check_stamp(Some(&session()), max_age, false, &guard);
+
+        let unsigned = stamped(&chainspec, 1, 1, now).with_consensus_certificate(None);
+        assert!(matches!(
+            check(&unsigned),
+            Err(HandshakeError::StampWithoutCertificate)
+        ));
+
+        let forged = stamped(&chainspec, 2, 2, now);
+        assert!(matches!(check(&forged), Err(HandshakeError::InvalidStamp)));
+
+        // Stamped for another session, or altered on the way.
+        let stamp = stamped(&chainspec, 1, 3, now);
+        let elsewhere = SessionId::from_bytes([8; 32]);
+        assert!(matches!(
+            stamp.check_stamp(Some(&elsewhere), max_age, false, &guard),
+            Err(HandshakeError::InvalidStamp)
+        ));
+        let mut renamed = stamp.clone();
+        renamed.network_name.push('x');
+        let mut moved = stamp.clone();
+        moved.public_addr.set_port(moved.public_addr.port() + 1);
+        let mut respecced = stamp;
+        respecced.chainspec_hash = None;
+        for altered in [renamed, moved, respecced] {
+            assert!(matches!(check(&altered), Err(HandshakeError::InvalidStamp)));
+        }
+
+        let old = stamped(
+            &chainspec,
+            1,
+            4,
+            now.saturating_sub(TimeDiff::from_seconds(120)),
+        );
+        assert!(matches!(
+            check(&old),
+            Err(HandshakeError::StaleStamp { .. })
+        ));
+        let ahead = stamped(&chainspec, 1, 5, now + TimeDiff::from_seconds(120));
+        assert!(matches!(
+            check(&ahead),
+            Err(HandshakeError::StaleStamp { .. })
+        ));
+    }
+}
@@ -0,0 +1,751 @@
+//! The chain of block headers peers gossip, followed and checked as blocks
+//! are observed.
+//!
+//! Peers gossip the hash of every block they add. Following the header chain
+//! schultz fetches the header of each from the peer that gossiped it and
+//! checks it: that it hashes to what was gossiped, that it is one higher than
+//! its parent, and that it is in its parent's era, or the next one if the
+//! parent ended its era. A header at a height another one holds already is a
+//! fork. Blocks a quorum of validators signed, see
+//! [`FinalityCollector`](super::finality::FinalityCollector), are marked
+//! final, once the era they were signed in is checked against the header's.
+//!
+//! Only the links between headers are kept, not the headers, and only those
+//! of the last [`MAX_HEADERS`] heights. A header whose parent is not known,
+//! the first one followed or one after a gap, is taken on trust, unless the
+//! chain is anchored to a trusted block, as casper-node is by its
+//! `trusted_hash`. Then only the header hashing to the trusted hash is taken
+//! on trust, every other one has to descend from it, and one whose parent
+//! is not known is left for its parents to be followed first.
+//!
+//! Headers are not signed, anyone can write one naming a final block as its
+//! parent. What a header says is only relied on once it is settled: once a
+//! quorum signed its block, or it is the trusted block, or the parent of a
+//! settled one. Only settled switch blocks announce the validators of the
+//! next era, see [`HeaderChain::take_announcements`].
+//!
+//! Only casper-node 1.x headers are decoded, those of 2.x are versioned and
+//! start with their version rather than with the parent hash.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use bincode::Options;
+use casper_hashing::Digest;
+use casper_types::bytesrepr;
+use casper_types::bytesrepr::ToBytes;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::Timestamp;
+use casper_types::U512;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::error::HeaderError;
+use super::message::BincodeFormat;
+
+/// Heights whose headers are kept, the lowest are forgotten first.
+pub const MAX_HEADERS: u64 = 1024;
+
+/// Final blocks remembered before their header is fetched.
+const MAX_EARLY_FINALS: usize = 1024;
+
+/// Parses the hex-encoded hash of a block.
+pub fn parse_block_hash(value: &str) -> Result<Digest, String> {
+    Digest::from_hex(value).map_err(|e| format!("invalid block hash {value:?}: {e:?}"))
+}
+
+/// Mirrors casper-node's `EraReport`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EraReport {
+    pub equivocators: Vec<PublicKey>,
+    pub rewards: BTreeMap<PublicKey, u64>,
+    pub inactive_validators: Vec<PublicKey>,
+}
+
+impl ToBytes for EraReport {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut bytes = self.equivocators.to_bytes()?;
+        bytes.extend(self.rewards.to_bytes()?);
+        bytes.extend(self.inactive_validators.to_bytes()?);
+        Ok(bytes)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.equivocators.serialized_length()
+            + self.rewards.serialized_length()
+            + self.inactive_validators.serialized_length()
+    }
+}
+
+/// Mirrors casper-node's `EraEnd`, which only switch blocks have.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EraEnd {
+    pub era_report: EraReport,
+    pub next_era_validator_weights: BTreeMap<PublicKey, U512>,
+}
+
+impl ToBytes for EraEnd {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut bytes = self.era_report.to_bytes()?;
+        bytes.extend(self.next_era_validator_weights.to_bytes()?);
+        Ok(bytes)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.era_report.serialized_length() + self.next_era_validator_weights.serialized_length()
+    }
+}
+
+/// Mirrors casper-node 1.x's `BlockHeader`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub parent_hash: Digest,
+    pub state_root_hash: Digest,
+    pub body_hash: Digest,
+    pub random_bit: bool,
+    pub accumulated_seed: Digest,
+    pub era_end: Option<EraEnd>,
+    pub timestamp: Timestamp,
+    pub era_id: EraId,
+    pub height: u64,
+    pub protocol_version: ProtocolVersion,
+}
+
+impl ToBytes for BlockHeader {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut bytes = self.parent_hash.to_bytes()?;
+        bytes.extend(self.state_root_hash.to_bytes()?);
+        bytes.extend(self.body_hash.to_bytes()?);
+        bytes.extend(self.random_bit.to_bytes()?);
+        bytes.extend(self.accumulated_seed.to_bytes()?);
+        bytes.extend(self.era_end.to_bytes()?);
+        bytes.extend(self.timestamp.to_bytes()?);
+        bytes.extend(self.era_id.to_bytes()?);
+        bytes.extend(self.height.to_bytes()?);
+        bytes.extend(self.protocol_version.to_bytes()?);
+        Ok(bytes)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.parent_hash.serialized_length()
+            + self.state_root_hash.serialized_length()
+            + self.body_hash.serialized_length()
+            + self.random_bit.serialized_length()
+            + self.accumulated_seed.serialized_length()
+            + self.era_end.serialized_length()
+            + self.timestamp.serialized_length()
+            + self.era_id.serialized_length()
+            + self.height.serialized_length()
+            + self.protocol_version.serialized_length()
+    }
+}
+
+impl BlockHeader {
+    /// Decodes a fetched header, if it is one of casper-node 1.x.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if usize::from(*bytes.first()?) != Digest::LENGTH {
+            return None;
+        }
+        BincodeFormat::default().deserialize_arbitrary(bytes).ok()
+    }
+
+    /// Decodes the header of a fetched block, which follows the block hash.
+    pub fn decode_from_block(bytes: &[u8]) -> Option<Self> {
+        if usize::from(*bytes.first()?) != Digest::LENGTH {
+            return None;
+        }
+        let header = bytes.get(1 + Digest::LENGTH..)?;
+        if usize::from(*header.first()?) != Digest::LENGTH {
+            return None;
+        }
+        // The block body follows the header.
+        bincode::options().allow_trailing_bytes().deserialize(header).ok()
+    }
+
+    /// The hash of the block, that of the header's bytesrepr encoding.
+    pub fn hash(&self) -> Result<Digest, bytesrepr::Error> { Ok(Digest::hash(self.to_bytes()?)) }
+
+    /// Whether the block is the last of its era.
+    pub fn is_switch_block(&self) -> bool { self.era_end.is_some() }
+}
+
+/// The highest block followed that is trusted, final, or linked to one that
+/// is.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Tip {
+    pub block_hash: Digest,
+    pub height: u64,
+    pub era_id: EraId,
+    pub timestamp: Timestamp,
+    /// Whether a quorum of validators signed the block.
+    pub finalized: bool,
+}
+
+/// The validators a settled switch block announced for the era after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EraAnnouncement {
+    pub block_hash: Digest,
+    pub era_id: EraId,
+    pub weights: BTreeMap<PublicKey, U512>,
+}
+
+/// What following a header came to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Followed {
+    /// The header was checked against its parent and added.
+    Linked,
+    /// The header was added without its parent to check it against.
+    Detached,
+    /// The header was added next to another at the same height.
+    Fork { other: Digest },
+    /// The header was followed before.
+    Known,
+    /// The header of the trusted block was added, anchoring the chain.
+    Trusted,
+    /// The header was not added, the chain being anchored and its parent not
+    /// known.
+    Unanchored,
+}
+
+/// The block the chain is anchored to.
+#[derive(Clone, Copy, Debug)]
+struct Anchor {
+    hash: Digest,
+    /// Height of the block, once its header was followed.
+    height: Option<u64>,
+}
+
+/// What is kept of a header.
+#[derive(Clone, Debug)]
+struct Link {
+    parent_hash: Digest,
+    height: u64,
+    era_id: EraId,
+    timestamp: Timestamp,
+    switch_block: bool,
+    /// The validators a switch block announces, until it is settled.
+    next_era_weights: Option<BTreeMap<PublicKey, U512>>,
+    finalized: bool,
+    settled: bool,
+    /// Whether the block is trusted, final, or linked to one that is, which
+    /// it takes for the block to become the tip.
+    grounded: bool,
+}
+
+#[derive(Debug, Default)]
+struct Chain {
+    links: HashMap<Digest, Link>,
+    heights: BTreeMap<u64, BTreeSet<Digest>>,
+    tip: Option<Digest>,
+    anchor: Option<Anchor>,
+    /// Blocks a quorum signed before their header was followed, with the
+    /// era they were signed in, oldest first.
+    early_finals: VecDeque<(Digest, EraId)>,
+    /// Announcements of settled switch blocks not taken yet.
+    announcements: Vec<EraAnnouncement>,
+    forks: u64,
+    invalid: u64,
+}
+
+impl Chain {
+    fn tip(&self) -> Option<Tip> {
+        let block_hash = self.tip?;
+        let link = self.links.get(&block_hash)?;
+        Some(Tip {
+            block_hash,
+            height: link.height,
+            era_id: link.era_id,
+            timestamp: link.timestamp,
+            finalized: link.finalized,
+        })
+    }
+
+    /// Checks `header` against its parent, if that is known.
+    fn check(&self, hash: Digest, header: &BlockHeader) -> Result<bool, HeaderError> {
+        let Some(parent) = self.links.get(&header.parent_hash) else {
+            return Ok(false);
+        };
+        if parent.height.checked_add(1) != Some(header.height) {
+            return Err(HeaderError::HeightMismatch {
+                hash,
+                height: header.height,
+                parent_height: parent.height,
+            });
+        }
+        let expected = if parent.switch_block {
+            parent.era_id.successor()
+        } else {
+            parent.era_id
+        };
+        if header.era_id != expected {
+            return Err(HeaderError::EraMismatch {
+                hash,
+                era_id: header.era_id,
+                expected,
+            });
+        }
+        Ok(true)
+    }
+
+    /// Whether a settled block at `height + 1` names block `hash` as its
+    /// parent.
+    fn has_settled_child(&self, hash: Digest, height: u64) -> bool {
+        let Some(children) = height.checked_add(1).and_then(|height| self.heights.get(&height))
+        else {
+            return false;
+        };
+        children
+            .iter()
+            .filter_map(|child| self.links.get(child))
+            .any(|child| child.settled && child.parent_hash == hash)
+    }
+
+    /// Settles block `hash` and the ancestors of it that are known, which
+    /// its header vouches for by naming its parent.
+    fn settle(&mut self, mut hash: Digest) {
+        while let Some(link) = self.links.get_mut(&hash).filter(|link| !link.settled) {
+            link.settled = true;
+            if let Some(weights) = link.next_era_weights.take() {
+                self.announcements.push(EraAnnouncement {
+                    block_hash: hash,
+                    era_id: link.era_id.successor(),
+                    weights,
+                });
+            }
+            hash = link.parent_hash;
+        }
+    }
+
+    /// Grounds block `hash` and the blocks known to be linked after it,
+    /// moving the tip up to the highest of them.
+    fn ground(&mut self, hash: Digest) {
+        let Some(link) = self.links.get_mut(&hash) else {
+            return;
+        };
+        link.grounded = true;
+        let mut highest = (link.height, hash);
+        let mut parents = BTreeSet::from([hash]);
+        for (height, hashes) in self.heights.range(link.height + 1..) {
+            let children: BTreeSet<Digest> = hashes
+                .iter()
+                .filter(|child| {
+                    self.links.get(child).is_some_and(|link| parents.contains(&link.parent_hash))
+                })
+                .copied()
+                .collect();
+            let Some(first) = children.first() else {
+                break;
+            };
+            highest = (*height, *first);
+            for child in &children {
+                if let Some(link) = self.links.get_mut(child) {
+                    link.grounded = true;
+                }
+            }
+            parents = children;
+        }
+        let (height, hash) = highest;
+        if self.tip().is_none_or(|tip| height > tip.height) {
+            self.tip = Some(hash);
+        }
+    }
+
+    /// Forgets the lowest heights beyond the last [`MAX_HEADERS`].
+    fn prune(&mut self) {
+        while self.heights.len() as u64 > MAX_HEADERS {
+            let Some((_, hashes)) = self.heights.pop_first() else {
+                break;
+            };
+            for hash in hashes {
+                self.links.remove(&hash);
+            }
+        }
+    }
+}
+
+/// The chain of block headers followed so far.
+#[derive(Debug, Default)]
+pub struct HeaderChain {
+    chain: Mutex<Chain>,
+}
+
+impl HeaderChain {
+    /// Anchors the chain to the block `hash`: from now on only headers
+    /// descending from it are followed.
+    pub fn anchor(&self, hash: Digest) {
+        let mut chain = self.chain.lock().expect("header chain lock poisoned");
+        chain.anchor = Some(Anchor { hash, height: None });
+    }
+
+    /// The block the chain is anchored to, if any.
+    pub fn trusted_hash(&self) -> Option<Digest> {
+        let chain = self.chain.lock().expect("header chain lock poisoned");
+        chain.anchor.map(|anchor| anchor.hash)
+    }
+
+    /// Height of the block the chain is anchored to, once its header was
+    /// followed.
+    pub fn trusted_height(&self) -> Option<u64> {
+        let chain = self.chain.lock().expect("header chain lock poisoned");
+        chain.anchor.and_then(|anchor| anchor.height)
+    }
+
+    /// Whether the header of block `hash` was followed already.
+    pub fn knows(&self, hash: &Digest) -> bool {
+        self.chain.lock().expect("header chain lock poisoned").links.contains_key(hash)
+    }
+
+    /// Checks `header`, gossiped as that of block `hash`, and adds it to the
+    /// chain. A header that fails its checks is counted as invalid.
+    pub fn follow(&self, hash: Digest, header: &BlockHeader) -> Result<Followed, HeaderError> {
+        let mut chain = self.chain.lock().expect("header chain lock poisoned");
+        let followed = Self::link(&mut chain, hash, header);
+        if followed.is_err() {
+            chain.invalid += 1;
+        }
+        followed
+    }
+
+    fn link(
+        chain: &mut Chain,
+        hash: Digest,
+        header: &BlockHeader,
+    ) -> Result<Followed, HeaderError> {
+        let actual = header.hash().map_err(|e| HeaderError::Encode(hash, e.to_string()))?;
+        if actual != hash {
+            return Err(HeaderError::HashMismatch {
+                expected: hash,
+                actual,
+            });
+        }
+        if chain.links.contains_key(&hash) {
+            return Ok(Followed::Known);
+        }
+        let linked = chain.check(hash, header)?;
+        let parent_grounded =
+            chain.links.get(&header.parent_hash).is_some_and(|parent| parent.grounded);
+        let trusted = chain.anchor.is_some_and(|anchor| anchor.hash == hash);
+        if let Some(anchor) = &mut chain.anchor {
+            if trusted {
+                anchor.height = Some(header.height);
+            } else if let Some(height) = anchor.height.filter(|height| header.height <= *height) {
+                return Err(HeaderError::NotAfterTrusted {
+                    hash,
+                    height: header.height,
+                    trusted: anchor.hash,
+                    trusted_height: height,
+                });
+            } else if !linked {
+                return Ok(Followed::Unanchored);
+            }
+        }
+        let mut finalized = false;
+        let early = chain.early_finals.iter().position(|(final_hash, _)| *final_hash == hash);
+        if let Some(index) = early {
+            let (_, signed) = chain.early_finals.remove(index).expect("index is in bounds");
+            if signed != header.era_id {
+                return Err(HeaderError::SignedInOtherEra {
+                    hash,
+                    era_id: header.era_id,
+                    signed,
+                });
+            }
+            finalized = true;
+        }
+
+        let at_height = chain.heights.entry(header.height).or_default();
+        let other = at_height.iter().next().copied();
+        at_height.insert(hash);
+        let next_era_weights = header
+            .era_end
+            .as_ref()
+            .map(|era_end| era_end.next_era_validator_weights.clone())
+            .filter(|weights| !weights.is_empty());
+        chain.links.insert(
+            hash,
+            Link {
+                parent_hash: header.parent_hash,
+                height: header.height,
+                era_id: header.era_id,
+                timestamp: header.timestamp,
+                switch_block: header.is_switch_block(),
+                next_era_weights,
+                finalized,
+                settled: false,
+                grounded: false,
+            },
+        );
+        if trusted || finalized || chain.has_settled_child(hash, header.height) {
+            chain.settle(hash);
+        }
+        // Anyone can write a header that hashes right, so only those vouched
+        // for may move the tip.
+        if trusted || finalized || (linked && parent_grounded) {
+            chain.ground(hash);
+        }
+        chain.prune();
+
+        match other {
+            Some(other) => {
+                chain.forks += 1;
+                Ok(Followed::Fork { other })
+            }
+            None if trusted => Ok(Followed::Trusted),
+            None if linked => Ok(Followed::Linked),
+            None => Ok(Followed::Detached),
+        }
+    }
+
+    /// Marks block `hash` final, a quorum having signed it in `era_id`.
+    /// A block not followed yet is marked once its header is.
+    pub fn finalize(&self, hash: Digest, era_id: EraId) -> Result<(), HeaderError> {
+        let mut chain = self.chain.lock().expect("header chain lock poisoned");
+        let Some(link) = chain.links.get_mut(&hash) else {
+            chain.early_finals.push_back((hash, era_id));
+            if chain.early_finals.len() > MAX_EARLY_FINALS {
+                chain.early_finals.pop_front();
+            }
+            return Ok(());
+        };
+        if link.era_id != era_id {
+            let era = link.era_id;
+            chain.invalid += 1;
+            return Err(HeaderError::SignedInOtherEra {
+                hash,
+                era_id: era,
+                signed: era_id,
+            });
+        }
+        link.finalized = true;
+        chain.settle(hash);
+        chain.ground(hash);
+        Ok(())
+    }
+
+    /// The validators settled switch blocks announced since last asked, for
+    /// the [`EraTracker`](super::era_tracker::EraTracker).
+    pub fn take_announcements(&self) -> Vec<EraAnnouncement> {
+        std::mem::take(&mut self.chain.lock().expect("header chain lock poisoned").announcements)
+    }
+
+    /// The highest block followed that is trusted, final, or linked to one
+    /// that is, if any.
+    pub fn tip(&self) -> Option<Tip> {
+        self.chain.lock().expect("header chain lock poisoned").tip()
+    }
+
+    /// Number of headers found next to another at the same height.
+    pub fn forks(&self) -> u64 { self.chain.lock().expect("header chain lock poisoned").forks }
+
+    /// Number of headers, or finality quorums, that failed their checks.
+    pub fn invalid(&self) -> u64 { self.chain.lock().expect("header chain lock poisoned").invalid }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(parent_hash: Digest, height: u64, era_id: u64, switch_block: bool) -> BlockHeader {
+        let era_end = switch_block.then(|| EraEnd {
+            era_report: EraReport {
+                equivocators: vec![],
+                rewards: BTreeMap::new(),
+                inactive_validators: vec![],
+            },
+            next_era_validator_weights: BTreeMap::new(),
+        });
+        BlockHeader {
+            parent_hash,
+            state_root_hash: Digest::hash(height.to_le_bytes()),
+            body_hash: Digest::hash(b"body"),
+            random_bit: height.is_multiple_of(2),
+            accumulated_seed: Digest::hash(b"seed"),
+            era_end,
+            timestamp: Timestamp::from(height * 1000),
+            era_id: EraId::new(era_id),
+            height,
+            protocol_version: ProtocolVersion::V1_0_0,
+        }
+    }
+
+    fn follow(chain: &HeaderChain, header: &BlockHeader) -> Result<Followed, HeaderError> {
+        chain.follow(header.hash().unwrap(), header)
+    }
+
+    #[test]
+    fn headers_decode_from_what_peers_send() {
+        let header = header(Digest::hash(b"parent"), 3, 1, true);
+        let bytes = BincodeFormat::default().serialize_arbitrary(&header).unwrap();
+        assert_eq!(BlockHeader::decode(&bytes), Some(header.clone()));
+        let mut block = vec![32];
+        block.extend(header.hash().unwrap().value());
+        block.extend(&bytes);
+        block.extend(b"the body");
+        assert_eq!(BlockHeader::decode_from_block(&block), Some(header.clone()));
+        assert_eq!(
+            header.hash().unwrap(),
+            Digest::hash(header.to_bytes().unwrap())
+        );
+        assert_eq!(header.to_bytes().unwrap().len(), header.serialized_length());
+        // A 2.x header starts with its version.
+        assert_eq!(BlockHeader::decode(&[0, 32]), None);
+        assert_eq!(BlockHeader::decode(&[]), None);
+    }
+
+    #[test]
+    fn headers_are_linked_across_eras_and_forks_flagged() {
+        let chain = HeaderChain::default();
+        let first = header(Digest::hash(b"genesis"), 10, 1, true);
+        assert_eq!(follow(&chain, &first), Ok(Followed::Detached));
+        chain.finalize(first.hash().unwrap(), EraId::new(1)).unwrap();
+        let second = header(first.hash().unwrap(), 11, 2, false);
+        assert_eq!(follow(&chain, &second), Ok(Followed::Linked));
+        assert_eq!(follow(&chain, &second), Ok(Followed::Known));
+
+        let mut fork = header(first.hash().unwrap(), 11, 2, false);
+        fork.random_bit = !fork.random_bit;
+        assert_eq!(
+            follow(&chain, &fork),
+            Ok(Followed::Fork {
+                other: second.hash().unwrap()
+            })
+        );
+        assert_eq!(chain.forks(), 1);
+
+        // The first header at the highest height stays the tip.
+        let tip = chain.tip().unwrap();
+        assert_eq!(tip.block_hash, second.hash().unwrap());
+        assert_eq!(
+            (tip.height, tip.era_id, tip.finalized),
+            (11, EraId::new(2), false)
+        );
+        chain.finalize(second.hash().unwrap(), EraId::new(2)).unwrap();
+        assert!(chain.tip().unwrap().finalized);
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let chain = HeaderChain::default();
+        let first = header(Digest::hash(b"genesis"), 10, 1, false);
+        follow(&chain, &first).unwrap();
+        chain.finalize(first.hash().unwrap(), EraId::new(1)).unwrap();
+
+        let third = header(Digest::hash(b"genesis"), 11, 1, false);
+        assert!(matches!(
+            chain.follow(first.hash().unwrap(), &third),
+            Err(HeaderError::HashMismatch { .. })
+        ));
+        // Only the block after a switch block starts a new era.
+        let next_era = header(first.hash().unwrap(), 11, 2, false);
+        assert!(matches!(
+            follow(&chain, &next_era),
+            Err(HeaderError::EraMismatch { .. })
+        ));
+        let skipped = header(first.hash().unwrap(), 12, 1, false);
+        assert!(matches!(
+            follow(&chain, &skipped),
+            Err(HeaderError::HeightMismatch { .. })
+        ));
+        // A quorum signed in another era than the block's.
+        let second = header(first.hash().unwrap(), 11, 1, false);
+        chain.finalize(second.hash().unwrap(), EraId::new(3)).unwrap();
+        assert!(matches!(
+            follow(&chain, &second),
+            Err(HeaderError::SignedInOtherEra { .. })
+        ));
+        assert_eq!(chain.invalid(), 4);
+        assert_eq!(chain.tip().unwrap().height, 10);
+    }
+
+    #[test]
+    fn anchored_headers_descend_from_the_trusted_block() {
+        let trusted = header(Digest::hash(b"genesis"), 10, 1, true);
+        let next = header(trusted.hash().unwrap(), 11, 2, false);
+        let chain = HeaderChain::default();
+        chain.anchor(trusted.hash().unwrap());
+        assert_eq!(chain.trusted_hash(), trusted.hash().ok());
+
+        // Nothing is taken on trust before the trusted block.
+        assert_eq!(follow(&chain, &next), Ok(Followed::Unanchored));
+        assert_eq!(chain.tip(), None);
+        assert_eq!(chain.trusted_height(), None);
+        assert_eq!(follow(&chain, &trusted), Ok(Followed::Trusted));
+        assert_eq!(chain.trusted_height(), Some(10));
+        assert_eq!(follow(&chain, &next), Ok(Followed::Linked));
+        // Nor after a gap.
+        let after_gap = header(Digest::hash(b"missing"), 13, 2, false);
+        assert_eq!(follow(&chain, &after_gap), Ok(Followed::Unanchored));
+
+        let mut other = header(Digest::hash(b"genesis"), 10, 1, true);
+        other.random_bit = !other.random_bit;
+        assert!(matches!(
+            follow(&chain, &other),
+            Err(HeaderError::NotAfterTrusted { .. })
+        ));
+        assert_eq!(chain.invalid(), 1);
+        assert_eq!(chain.tip().unwrap().block_hash, next.hash().unwrap());
+    }
+
+    #[test]
+    fn only_settled_switch_blocks_announce_the_next_era() {
+        let weights: BTreeMap<_, _> = [(crate::testing::public_key(1), U512::from(10))].into();
+        let mut switch_block = header(Digest::hash(b"genesis"), 10, 1, true);
+        switch_block.era_end.as_mut().unwrap().next_era_validator_weights = weights.clone();
+        let hash = switch_block.hash().unwrap();
+        let next = header(hash, 11, 2, false);
+        let chain = HeaderChain::default();
+
+        // Anyone can gossip a header, or write one after a final block.
+        assert_eq!(follow(&chain, &next), Ok(Followed::Detached));
+        assert_eq!(follow(&chain, &switch_block), Ok(Followed::Detached));
+        let mut forged = header(next.hash().unwrap(), 12, 2, true);
+        forged.era_end.as_mut().unwrap().next_era_validator_weights = weights.clone();
+        assert_eq!(follow(&chain, &forged), Ok(Followed::Linked));
+        assert_eq!(chain.take_announcements(), []);
+
+        // A quorum signing a block vouches for its parents.
+        chain.finalize(next.hash().unwrap(), EraId::new(2)).unwrap();
+        assert_eq!(
+            chain.take_announcements(),
+            [EraAnnouncement {
+                block_hash: hash,
+                era_id: EraId::new(2),
+                weights,
+            }]
+        );
+        assert_eq!(chain.take_announcements(), []);
+    }
+
+    #[test]
+    fn only_vouched_for_headers_move_the_tip() {
+        let chain = HeaderChain::default();
+        let first = header(Digest::hash(b"genesis"), 10, 1, false);
+        let second = header(first.hash().unwrap(), 11, 1, false);
+        let third = header(second.hash().unwrap(), 12, 1, false);
+        follow(&chain, &first).unwrap();
+        follow(&chain, &second).unwrap();
+        assert_eq!(chain.tip(), None);
+
+        // Nor does a header far ahead of the chain.
+        let spoofed = header(Digest::hash(b"nowhere"), 1_000_000, 1, false);
+        let after_spoofed = header(spoofed.hash().unwrap(), 1_000_001, 1, false);
+        assert_eq!(follow(&chain, &spoofed), Ok(Followed::Detached));
+        assert_eq!(follow(&chain, &after_spoofed), Ok(Followed::Linked));
+        assert_eq!(chain.tip(), None);
+
+        // A final block brings up those linked after it, and those to come.
+        chain.finalize(first.hash().unwrap(), EraId::new(1)).unwrap();
+        assert_eq!(chain.tip().unwrap().block_hash, second.hash().unwrap());
+        assert_eq!(follow(&chain, &third), Ok(Followed::Linked));
+        let tip = chain.tip().unwrap();
+        assert_eq!(
+            (tip.block_hash, tip.finalized),
+            (third.hash().unwrap(), false)
+        );
+    }
+}
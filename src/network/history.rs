@@ -0,0 +1,149 @@
+//! The latest things that happened to our connections.
+//!
+//! Logs tell the whole story, but rarely make it into a bug report in one
+//! piece. A short history of connections opened and closed and of the
+//! behaviors that changed a peer's score is kept instead, to go along with a
+//! [snapshot](super::manager::Manager::network_state) of the network view.
+//! Every event is also passed on to whoever
+//! [subscribed](super::manager::Manager::network_events) to them as it
+//! happens.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use casper_types::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::connection::Direction;
+use super::reputation::Behavior;
+
+/// Events kept by default, older ones are dropped.
+pub const HISTORY_CAPACITY: usize = 256;
+
+/// Events kept for a subscriber that falls behind, older ones are dropped.
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Something that happened to a peer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkEvent {
+    pub at: Timestamp,
+    pub peer: SocketAddr,
+    pub kind: EventKind,
+}
+
+/// What happened to the peer of a [`NetworkEvent`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A connection to the peer was set up.
+    Connected { direction: Direction },
+    /// The peer closed the connection, or reading from it failed.
+    Closed,
+    /// We dropped the connection, e.g. as the peer stopped answering pings.
+    Dropped,
+    /// The peer did something that changed its score to `score`.
+    Scored { behavior: Behavior, score: i32 },
+}
+
+/// The latest [`NetworkEvent`]s, oldest first.
+#[derive(Debug)]
+pub struct History {
+    capacity: usize,
+    events: Mutex<VecDeque<NetworkEvent>>,
+    subscribers: broadcast::Sender<NetworkEvent>,
+}
+
+impl Default for History {
+    fn default() -> Self { Self::new(HISTORY_CAPACITY) }
+}
+
+impl History {
+    /// Keeps the latest `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            subscribers: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        }
+    }
+
+    /// Records that `kind` happened to `peer` just now.
+    pub fn record(&self, peer: SocketAddr, kind: EventKind) {
+        let event = NetworkEvent {
+            at: Timestamp::now(),
+            peer,
+            kind,
+        };
+        // Nobody subscribed is not an error.
+        let _ = self.subscribers.send(event.clone());
+
+        let mut events = self.events.lock().expect("history lock poisoned");
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        if self.capacity > 0 {
+            events.push_back(event);
+        }
+    }
+
+    /// Subscribes to every event recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> { self.subscribers.subscribe() }
+
+    /// Bytes the events kept take up on the heap, at most.
+    pub fn heap_size(&self) -> usize {
+        let events = self.events.lock().expect("history lock poisoned");
+        events.capacity() * std::mem::size_of::<NetworkEvent>()
+    }
+
+    /// The events kept, oldest first.
+    pub fn events(&self) -> Vec<NetworkEvent> {
+        self.events.lock().expect("history lock poisoned").iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn keeps_the_latest_events() {
+        let history = History::new(2);
+        history.record(
+            peer(1),
+            EventKind::Connected {
+                direction: Direction::Inbound,
+            },
+        );
+        history.record(peer(2), EventKind::Closed);
+        history.record(peer(3), EventKind::Dropped);
+
+        let kept: Vec<_> = history.events().into_iter().map(|event| event.peer).collect();
+        assert_eq!(kept, vec![peer(2), peer(3)]);
+
+        let nothing = History::new(0);
+        let mut subscriber = nothing.subscribe();
+        nothing.record(peer(1), EventKind::Closed);
+        assert!(nothing.events().is_empty());
+        // Subscribers see every event, whatever is kept.
+        assert_eq!(subscriber.try_recv().unwrap().peer, peer(1));
+    }
+
+    #[test]
+    fn events_are_tagged_json() {
+        let kind = EventKind::Scored {
+            behavior: Behavior::HandshakeFailed,
+            score: -20,
+        };
+        let json = serde_json::to_value(&kind).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"event": "scored", "behavior": "handshake_failed", "score": -20})
+        );
+        assert_eq!(serde_json::from_value::<EventKind>(json).unwrap(), kind);
+    }
+}
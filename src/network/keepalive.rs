@@ -0,0 +1,95 @@
+//! Liveness tracking of connected peers.
+//!
+//! Every fully connected peer is pinged once per ping interval. A peer that
+//! has not answered the previous ping by the time the next one is due gets a
+//! missed pong counted against it; once it misses too many in a row, the
+//! connection is dropped. Answered pings yield a round-trip latency sample.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::primitives::Nonce;
+
+/// Ping/pong bookkeeping for a single peer.
+#[derive(Clone, Debug, Default)]
+pub struct PeerLiveness {
+    /// The ping we are waiting on an answer for, and when it was sent.
+    outstanding: Option<(Nonce, Instant)>,
+    /// Number of pings in a row that went unanswered.
+    missed_pongs: u32,
+    /// Round-trip time of the last answered ping.
+    latency: Option<Duration>,
+}
+
+impl PeerLiveness {
+    /// Gives up on the outstanding ping, if any, and returns the number of
+    /// consecutive pings the peer has now missed.
+    pub fn expire_outstanding(&mut self) -> u32 {
+        if self.outstanding.take().is_some() {
+            self.missed_pongs += 1;
+        }
+        self.missed_pongs
+    }
+
+    /// Records a ping sent at `sent_at`.
+    pub fn ping_sent(&mut self, nonce: Nonce, sent_at: Instant) {
+        self.outstanding = Some((nonce, sent_at));
+    }
+
+    /// Records a pong received at `received_at`.
+    ///
+    /// Returns the measured latency if the pong answers the outstanding ping,
+    /// stale or unsolicited pongs are ignored.
+    pub fn pong_received(&mut self, nonce: Nonce, received_at: Instant) -> Option<Duration> {
+        match self.outstanding {
+            Some((expected, sent_at)) if expected == nonce => {
+                let latency = received_at.saturating_duration_since(sent_at);
+                self.outstanding = None;
+                self.missed_pongs = 0;
+                self.latency = Some(latency);
+                Some(latency)
+            }
+            _ => None,
+        }
+    }
+
+    /// Round-trip time of the last answered ping.
+    pub fn latency(&self) -> Option<Duration> { self.latency }
+
+    /// Number of pings in a row that went unanswered.
+    pub fn missed_pongs(&self) -> u32 { self.missed_pongs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pong_measures_latency_and_resets_missed_count() {
+        let start = Instant::now();
+        let mut liveness = PeerLiveness::default();
+
+        liveness.ping_sent(Nonce::new(1), start);
+        assert_eq!(liveness.expire_outstanding(), 1);
+
+        liveness.ping_sent(Nonce::new(2), start);
+        // An answer to the expired ping does not count.
+        assert_eq!(liveness.pong_received(Nonce::new(1), start), None);
+
+        let latency = liveness.pong_received(Nonce::new(2), start + Duration::from_millis(40));
+        assert_eq!(latency, Some(Duration::from_millis(40)));
+        assert_eq!(liveness.latency(), latency);
+        assert_eq!(liveness.missed_pongs(), 0);
+    }
+
+    #[test]
+    fn consecutive_unanswered_pings_accumulate() {
+        let mut liveness = PeerLiveness::default();
+        assert_eq!(liveness.expire_outstanding(), 0);
+
+        for expected in 1..=3 {
+            liveness.ping_sent(Nonce::new(expected.into()), Instant::now());
+            assert_eq!(liveness.expire_outstanding(), expected);
+        }
+    }
+}
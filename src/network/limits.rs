@@ -0,0 +1,442 @@
+//! Limits on the connections we dial and keep.
+//!
+//! Dials wait for one of a fixed number of slots, so however many peers
+//! bootstrapping and gossip come up with, only so many sockets are being
+//! opened at once. Every established connection, in either direction, holds a
+//! permit for as long as its reader runs; inbound ones also count against the
+//! peer's IP, so a single host cannot take up all of them. Every time a limit
+//! is hit, it is counted in the `net_connection_limit_hits` metric.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use super::config::Config;
+use super::metrics::Metrics;
+
+/// A new connection would go over one of the limits.
+#[derive(Debug, Error, PartialEq, Eq, Serialize)]
+pub enum LimitReached {
+    #[error("already holding the maximum of {0} connections")]
+    Connections(usize),
+    #[error("{ip} already holds the maximum of {limit} inbound connections")]
+    InboundPerIp { ip: IpAddr, limit: usize },
+}
+
+impl LimitReached {
+    /// Label of the limit in the `net_connection_limit_hits` metric.
+    fn label(&self) -> &'static str {
+        match self {
+            LimitReached::Connections(_) => "connections",
+            LimitReached::InboundPerIp { .. } => "inbound_per_ip",
+        }
+    }
+}
+
+/// The dial slots and connection permits still available.
+#[derive(Debug)]
+pub struct ConnectionLimits {
+    dials: Permits,
+    connections: Permits,
+    max_inbound_per_ip: AtomicUsize,
+    inbound: Mutex<BTreeMap<IpAddr, usize>>,
+    metrics: Arc<Metrics>,
+}
+
+impl ConnectionLimits {
+    pub fn new(config: &Config, metrics: Arc<Metrics>) -> Self {
+        Self {
+            dials: Permits::new(config.max_concurrent_dials),
+            connections: Permits::new(config.max_connections),
+            max_inbound_per_ip: AtomicUsize::new(config.max_inbound_per_ip),
+            inbound: Mutex::new(BTreeMap::new()),
+            metrics,
+        }
+    }
+
+    /// Waits for a dial slot, held until the returned permit is dropped.
+    pub async fn dial(&self) -> OwnedSemaphorePermit {
+        if let Ok(permit) = self.dials.semaphore.clone().try_acquire_owned() {
+            return permit;
+        }
+        debug!("Every dial slot is taken, waiting for one");
+        self.metrics.connection_limit_hits.with_label_values(&["dials"]).inc();
+        self.dials
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("dial slots are never closed")
+    }
+
+    /// Takes a permit for a connection we dial.
+    pub fn outbound(self: &Arc<Self>) -> Result<ConnectionPermit, LimitReached> {
+        self.hit(self.connection())
+    }
+
+    /// Takes a permit for a connection accepted from `ip`.
+    pub fn inbound(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionPermit, LimitReached> {
+        let inbound_ip = self.inbound_ip(ip)?;
+        let mut permit = self.hit(self.connection())?;
+        permit.inbound_ip = Some(inbound_ip);
+        Ok(permit)
+    }
+
+    /// Takes a permit for a connection accepted from `ip`, waiting up to
+    /// `wait` for one to be given back if we hold the maximum, e.g. after
+    /// evicting a peer to make room.
+    pub async fn inbound_within(
+        self: &Arc<Self>,
+        ip: IpAddr,
+        wait: Duration,
+    ) -> Result<ConnectionPermit, LimitReached> {
+        let inbound_ip = self.inbound_ip(ip)?;
+        let connection = self.connections.semaphore.clone().acquire_owned();
+        let connection = tokio::time::timeout(wait, connection)
+            .await
+            .map_err(|_| LimitReached::Connections(self.max_connections()));
+        let connection = self.hit(connection)?.expect("connection permits are never closed");
+        Ok(ConnectionPermit {
+            _connection: connection,
+            inbound_ip: Some(inbound_ip),
+        })
+    }
+
+    /// Counts a connection against `ip`.
+    fn inbound_ip(self: &Arc<Self>, ip: IpAddr) -> Result<InboundIp, LimitReached> {
+        let taken = {
+            let mut inbound = self.inbound.lock().expect("inbound connections lock poisoned");
+            let held = inbound.entry(ip).or_default();
+            let limit = self.max_inbound_per_ip.load(Ordering::Relaxed);
+            if *held >= limit {
+                Err(LimitReached::InboundPerIp { ip, limit })
+            } else {
+                *held += 1;
+                Ok(())
+            }
+        };
+        self.hit(taken)?;
+        Ok(InboundIp {
+            limits: self.clone(),
+            ip,
+        })
+    }
+
+    /// Connections currently holding a permit.
+    pub fn connections(&self) -> usize { self.connections.held() }
+
+    /// Takes over the limits of `config`.
+    ///
+    /// Lowered limits close no connection and cancel no dial, they are only
+    /// enforced once enough of them are given back.
+    pub fn resize(&self, config: &Config) {
+        self.dials.resize(config.max_concurrent_dials);
+        self.connections.resize(config.max_connections);
+        self.max_inbound_per_ip.store(config.max_inbound_per_ip, Ordering::Relaxed);
+    }
+
+    fn max_connections(&self) -> usize { self.connections.size().max }
+
+    /// Inbound connections currently held by `ip`.
+    pub fn inbound_from(&self, ip: IpAddr) -> usize {
+        let inbound = self.inbound.lock().expect("inbound connections lock poisoned");
+        inbound.get(&ip).copied().unwrap_or_default()
+    }
+
+    fn connection(&self) -> Result<ConnectionPermit, LimitReached> {
+        let permit = self
+            .connections
+            .semaphore
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| LimitReached::Connections(self.max_connections()))?;
+        Ok(ConnectionPermit {
+            _connection: permit,
+            inbound_ip: None,
+        })
+    }
+
+    /// Counts `result` in the metrics if a limit was hit.
+    fn hit<T>(&self, result: Result<T, LimitReached>) -> Result<T, LimitReached> {
+        if let Err(limit) = &result {
+            self.metrics.connection_limit_hits.with_label_values(&[limit.label()]).inc();
+        }
+        result
+    }
+
+    fn release_inbound(&self, ip: IpAddr) {
+        let mut inbound = self.inbound.lock().expect("inbound connections lock poisoned");
+        if let Some(held) = inbound.get_mut(&ip) {
+            *held = held.saturating_sub(1);
+            if *held == 0 {
+                inbound.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Permits of a semaphore whose number can be changed while they are held.
+#[derive(Debug)]
+struct Permits {
+    semaphore: Arc<Semaphore>,
+    size: Arc<Mutex<Size>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Size {
+    /// Number of permits there are meant to be.
+    max: usize,
+    /// Permits over `max` held when it was lowered, taken out as they are
+    /// given back.
+    owed: usize,
+}
+
+impl Permits {
+    fn new(max: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            size: Arc::new(Mutex::new(Size { max, owed: 0 })),
+        }
+    }
+
+    fn size(&self) -> Size { *self.size.lock().expect("permits lock poisoned") }
+
+    /// Permits currently held, including those owed.
+    fn held(&self) -> usize {
+        let size = self.size();
+        (size.max + size.owed).saturating_sub(self.semaphore.available_permits())
+    }
+
+    /// Changes the number of permits to `max`. Permits over it that are held
+    /// right now are taken out as they are given back, unless it is raised
+    /// again first.
+    fn resize(&self, max: usize) {
+        let mut size = self.size.lock().expect("permits lock poisoned");
+        if max >= size.max {
+            let raise = max - size.max;
+            let forgiven = raise.min(size.owed);
+            size.owed -= forgiven;
+            self.semaphore.add_permits(raise - forgiven);
+        } else {
+            let excess = size.max - max;
+            let owing = size.owed > 0;
+            size.owed += excess - self.semaphore.forget_permits(excess);
+            if !owing && size.owed > 0 {
+                tokio::spawn(take_back(self.semaphore.clone(), self.size.clone()));
+            }
+        }
+        size.max = max;
+    }
+}
+
+/// Takes out the permits `size` owes as they are given back to `semaphore`,
+/// until none are.
+async fn take_back(semaphore: Arc<Semaphore>, size: Arc<Mutex<Size>>) {
+    loop {
+        let Ok(permit) = semaphore.clone().acquire_owned().await else {
+            return;
+        };
+        let mut size = size.lock().expect("permits lock poisoned");
+        // What is not owed anymore goes back with the permit.
+        if size.owed == 0 {
+            return;
+        }
+        size.owed -= 1;
+        permit.forget();
+        if size.owed == 0 {
+            return;
+        }
+    }
+}
+
+/// A connection counted against the limits, given back when dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    _connection: OwnedSemaphorePermit,
+    inbound_ip: Option<InboundIp>,
+}
+
+/// An inbound connection counted against the peer's IP.
+#[derive(Debug)]
+struct InboundIp {
+    limits: Arc<ConnectionLimits>,
+    ip: IpAddr,
+}
+
+impl Drop for InboundIp {
+    fn drop(&mut self) { self.limits.release_inbound(self.ip); }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::Registry;
+
+    use super::*;
+
+    fn limits(dials: usize, connections: usize, per_ip: usize) -> Arc<ConnectionLimits> {
+        let config = Config {
+            max_concurrent_dials: dials,
+            max_connections: connections,
+            max_inbound_per_ip: per_ip,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(&Registry::new()).unwrap());
+        Arc::new(ConnectionLimits::new(&config, metrics))
+    }
+
+    fn hits(limits: &ConnectionLimits, limit: &str) -> u64 {
+        limits.metrics.connection_limit_hits.with_label_values(&[limit]).get()
+    }
+
+    fn ip(last: u8) -> IpAddr { IpAddr::from([10, 0, 0, last]) }
+
+    #[test]
+    fn connections_are_capped_and_released_on_drop() {
+        let limits = limits(1, 2, 10);
+        let first = limits.outbound().unwrap();
+        let _second = limits.inbound(ip(1)).unwrap();
+        assert_eq!(limits.outbound().unwrap_err(), LimitReached::Connections(2));
+        assert_eq!(hits(&limits, "connections"), 1);
+
+        drop(first);
+        assert_eq!(limits.connections(), 1);
+        let _third = limits.outbound().unwrap();
+    }
+
+    #[test]
+    fn inbound_connections_are_capped_per_ip() {
+        let limits = limits(1, 10, 2);
+        let first = limits.inbound(ip(1)).unwrap();
+        let _second = limits.inbound(ip(1)).unwrap();
+        assert_eq!(
+            limits.inbound(ip(1)).unwrap_err(),
+            LimitReached::InboundPerIp {
+                ip: ip(1),
+                limit: 2
+            }
+        );
+        assert_eq!(hits(&limits, "inbound_per_ip"), 1);
+        // Other hosts and our own dials are not affected.
+        let _other = limits.inbound(ip(2)).unwrap();
+        let _outbound = limits.outbound().unwrap();
+
+        drop(first);
+        assert_eq!(limits.inbound_from(ip(1)), 1);
+        let _third = limits.inbound(ip(1)).unwrap();
+    }
+
+    #[test]
+    fn refused_inbound_connections_hold_nothing() {
+        let limits = limits(1, 1, 10);
+        let _held = limits.outbound().unwrap();
+        assert!(limits.inbound(ip(1)).is_err());
+        assert_eq!(limits.inbound_from(ip(1)), 0);
+    }
+
+    #[tokio::test]
+    async fn inbound_connections_can_wait_for_a_free_permit() {
+        let limits = limits(1, 1, 10);
+        let held = limits.outbound().unwrap();
+        let wait = Duration::from_millis(50);
+        assert_eq!(
+            limits.inbound_within(ip(1), wait).await.unwrap_err(),
+            LimitReached::Connections(1)
+        );
+        assert_eq!(limits.inbound_from(ip(1)), 0);
+
+        let waiting = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.inbound_within(ip(1), Duration::from_secs(1)).await }
+        });
+        drop(held);
+        let _permit = waiting.await.unwrap().unwrap();
+        assert_eq!(limits.inbound_from(ip(1)), 1);
+    }
+
+    #[tokio::test]
+    async fn limits_can_be_resized_while_held() {
+        let limits = limits(1, 3, 1);
+        let first = limits.outbound().unwrap();
+        let _second = limits.outbound().unwrap();
+
+        let lowered = Config {
+            max_concurrent_dials: 1,
+            max_connections: 1,
+            max_inbound_per_ip: 2,
+            ..Config::default()
+        };
+        limits.resize(&lowered);
+        assert_eq!(limits.outbound().unwrap_err(), LimitReached::Connections(1));
+        // Two connections are still held, so one has to go before another is
+        // let in.
+        drop(first);
+        tokio::task::yield_now().await;
+        assert!(limits.outbound().is_err());
+
+        let raised = Config {
+            max_connections: 3,
+            ..lowered
+        };
+        limits.resize(&raised);
+        let _third = limits.inbound(ip(1)).unwrap();
+        let _fourth = limits.inbound(ip(1)).unwrap();
+        assert!(limits.inbound(ip(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn limits_raised_back_are_not_taken_out_later() {
+        let limits = limits(1, 2, 10);
+        let first = limits.outbound().unwrap();
+        let _second = limits.outbound().unwrap();
+        let sized = |max_connections| Config {
+            max_concurrent_dials: 1,
+            max_connections,
+            max_inbound_per_ip: 10,
+            ..Config::default()
+        };
+        limits.resize(&sized(1));
+        limits.resize(&sized(2));
+        assert_eq!(limits.connections(), 2);
+
+        // The permit given back is not owed anymore.
+        drop(first);
+        tokio::task::yield_now().await;
+        assert_eq!(limits.connections(), 1);
+        let _third = limits.outbound().unwrap();
+        assert_eq!(limits.outbound().unwrap_err(), LimitReached::Connections(2));
+
+        limits.resize(&sized(1));
+        assert_eq!(limits.connections(), 2);
+        limits.resize(&sized(4));
+        let _fourth = limits.outbound().unwrap();
+        let _fifth = limits.outbound().unwrap();
+        assert_eq!(limits.outbound().unwrap_err(), LimitReached::Connections(4));
+    }
+
+    #[tokio::test]
+    async fn dials_wait_for_a_free_slot() {
+        let limits = limits(1, 10, 10);
+        let first = limits.dial().await;
+
+        let waiting = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.dial().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        let _permit = tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+        assert_eq!(hits(&limits, "dials"), 1);
+    }
+}
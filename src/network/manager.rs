@@ -4,59 +4,225 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use bytes::Bytes;
-use bytes::BytesMut;
-use casper_hashing::Digest;
 use casper_types::ProtocolVersion;
-use futures::stream::SplitSink;
-use futures::SinkExt;
+use casper_types::Timestamp;
 use futures::StreamExt;
+#[cfg(feature = "openssl")]
 use openssl::pkey::PKeyRef;
+#[cfg(feature = "openssl")]
 use openssl::pkey::Private;
-use openssl::ssl::Ssl;
+#[cfg(feature = "openssl")]
 use openssl::ssl::SslAcceptor;
-use openssl::ssl::SslMethod;
+#[cfg(feature = "openssl")]
 use openssl::x509::X509Ref;
+use prometheus::Registry;
 use rand::RngCore;
-use tokio::net::TcpListener;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
 use tokio::net::TcpStream;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
+use tokio::task::JoinSet;
 use tokio::time::interval;
-use tokio_openssl::SslStream;
-use tokio_serde::Deserializer;
 use tokio_serde::Serializer;
-use tokio_util::codec::Framed;
-use tokio_util::codec::LengthDelimitedCodec;
 use tracing::error;
 use tracing::info;
+use tracing::instrument;
 use tracing::trace;
 use tracing::warn;
 
+use super::bandwidth::BandwidthTracker;
+use super::bandwidth::Traffic;
+use super::buffers::BufferPool;
+use super::checksum::CorruptFrame;
+use super::compression::Compression;
+use super::config::Config;
+use super::connection::Connection;
+use super::connection::ConnectionId;
+use super::connection::ConnectionIds;
+use super::connection::Direction;
+use super::connection::FrameReader;
+use super::connection::OutboundQueue;
+use super::connection::SharedInfo;
+use super::era_tracker::EraTracker;
+use super::error::HandshakeError;
 use super::error::ManagerError;
-use super::error::TLSError;
-use super::message::FramedTransport;
+use super::handshake::Handshake;
+use super::handshake::HandshakeResult;
+use super::handshake::ReplayGuard;
+use super::history::EventKind;
+use super::history::History;
+use super::history::NetworkEvent;
+use super::keepalive::PeerLiveness;
+use super::limits::ConnectionLimits;
+use super::limits::ConnectionPermit;
+use super::limits::LimitReached;
+use super::memory::MemoryBudget;
+use super::memory::OverBudget;
+use super::memory::Reservation;
+use super::message::ConsensusCertificate;
 use super::message::Message;
 use super::message::MessagePackFormat;
-use super::message::SchultzMessage;
+use super::metrics::Metrics;
+use super::mux::Channel;
+use super::mux::Demuxed;
+use super::node_id::NodeId;
+use super::observe::Observed;
+use super::observe::OBSERVED_CAPACITY;
+use super::progress::Step;
+use super::reputation::Behavior;
+use super::reputation::Reputation;
+use super::resolve;
+#[cfg(feature = "openssl")]
 use super::tls;
-use super::tls::set_context_options;
+#[cfg(feature = "openssl")]
+use super::tls::openssl::SslResult;
 use super::tls::Identity;
-use super::tls::SslResult;
+use super::tls::SessionId;
+use super::tls::TlsOptions;
+use super::tls::TlsStream;
+use super::transport::Accepted;
+use super::transport::BoxedStream;
+use super::transport::Listener;
+use super::transport::PeerRecord;
+use super::transport::TlsTransport;
+use super::transport::Transport;
+use super::wire_log::WireLog;
+use crate::crypto::ConsensusKeys;
 use crate::network::message::BincodeFormat;
-use crate::network::tls::validate_self_signed_cert;
 use crate::primitives::Chainspec;
 use crate::primitives::Nonce;
 use crate::primitives::Payload;
+use crate::utils::Fingerprint;
 
 /// Maximum frame length to be decoded from incoming stream
 pub const MAX_FRAME_LEN: usize = 25165824; // 25 MB as Bytes
 
-/// Connection Pool polling rate
+/// How often a connection over its bandwidth budget checks whether it may
+/// read again
 pub const POLLING_RATE: u64 = 1; // 1 ms
 
+/// How long to wait for a contacted peer to answer our handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a peer whose handshake we rejected stays connected, so our own
+/// handshake still reaches it and it can tell why
+pub const REJECTED_PEER_GRACE: Duration = Duration::from_secs(1);
+
+/// How long an accepted connection waits for the permit of the peer evicted
+/// to make room for it
+pub const EVICTION_GRACE: Duration = Duration::from_secs(1);
+
+/// How long an accepted connection may take to be set up, e.g. for the peer
+/// to complete the TLS handshake
+pub const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why we stop reading from a peer and disconnect it
+#[derive(Debug, Error)]
+pub enum Disconnect {
+    #[error(transparent)]
+    OverBudget(#[from] OverBudget),
+    #[error("rejected its handshake: {0}")]
+    Rejected(HandshakeError),
+}
+
+/// A message read from a peer on the given connection, charged to its memory
+/// budget until dropped
+pub type Event<P> = (SocketAddr, ConnectionId, Message<P>, Reservation);
+
+/// A peer we have a connection to, as reported by [`Manager::peers`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub addr: SocketAddr,
+    /// The node at the other end, `None` over transports without
+    /// certificates.
+    #[serde(alias = "fingerprint")]
+    pub node_id: Option<NodeId>,
+    /// Protocol version the peer completed its handshake with, `None` until
+    /// it did.
+    pub protocol_version: Option<ProtocolVersion>,
+    pub direction: Direction,
+    pub connected_since: Timestamp,
+    /// When a frame was last read from the peer.
+    pub last_seen: Option<Timestamp>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Everything we know about the network at one point in time, as reported
+/// by [`Manager::network_state`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkState {
+    pub taken_at: Timestamp,
+    pub addr: SocketAddr,
+    /// Fingerprint of the certificate we present.
+    pub fingerprint: Fingerprint,
+    pub peers: Vec<PeerInfo>,
+    /// Score of every peer that has one, connected or not.
+    pub scores: BTreeMap<SocketAddr, i32>,
+    /// Last measured ping round-trip time of every peer that answered one.
+    pub latencies: BTreeMap<SocketAddr, Duration>,
+    /// The latest things that happened to our connections, oldest first.
+    pub events: Vec<NetworkEvent>,
+}
+
+/// Peers we sent a handshake to, with the channel to report their answer on
+type AwaitingHandshakes =
+    Arc<Mutex<BTreeMap<SocketAddr, oneshot::Sender<Result<Handshake, HandshakeError>>>>>;
+
+/// The most recent handshake with any peer
+type LastHandshake = Arc<Mutex<Option<HandshakeResult>>>;
+
+/// Ping/pong bookkeeping of every peer we have pinged
+type LivenessMap = Arc<Mutex<BTreeMap<SocketAddr, PeerLiveness>>>;
+
+/// Open connections by peer address. A node may hold one each way, and
+/// peers over transports without certificates have no [`NodeId`], see
+/// [`Manager::peer_addr`] for those of a node
+type ConnectionPool = Arc<Mutex<BTreeMap<SocketAddr, Connection>>>;
+
+/// The identity connections opened from now on are set up presenting.
+type SharedIdentity = Arc<std::sync::RwLock<Identity>>;
+
+/// Starts serving a freshly established connection, holding its permit for
+/// as long as it is read from
+type ConnectionOpener =
+    Arc<dyn Fn(SocketAddr, Direction, BoxedStream, ConnectionPermit) -> Connection + Send + Sync>;
+
+/// State shared by the reader tasks of every connection
+struct ReaderContext<P: Payload> {
+    schultz_addr: SocketAddr,
+    chainspec: Chainspec,
+    config: Config,
+    fully_connected_peers: Arc<Mutex<Vec<SocketAddr>>>,
+    awaiting_reply_from_peers: AwaitingHandshakes,
+    last_handshake: LastHandshake,
+    bandwidth: Arc<BandwidthTracker>,
+    liveness: LivenessMap,
+    memory: Arc<MemoryBudget>,
+    buffers: Arc<BufferPool>,
+    metrics: Arc<Metrics>,
+    reputation: Arc<Reputation>,
+    history: Arc<History>,
+    observed: broadcast::Sender<Observed>,
+    wire_log: Option<Arc<WireLog>>,
+    transport: Arc<dyn Transport>,
+    identity: SharedIdentity,
+    consensus_keys: Option<ConsensusKeys>,
+    replay_guard: ReplayGuard,
+    eras: Arc<EraTracker>,
+    connection_pool: ConnectionPool,
+    connection_ids: ConnectionIds,
+    event_tx: Sender<Event<P>>,
+}
+
 /// # Manager
 ///
 /// The `Manager` struct is responsible for handling network communications,
@@ -66,21 +232,38 @@ pub const POLLING_RATE: u64 = 1; // 1 ms
 /// ## Usage
 ///
 /// To create a new `Manager`, use the `new` method, providing the required
-/// parameters such as `schultz_addr`, `event_tx`, and `chainspec`.
+/// parameters such as `schultz_addr`, `event_tx`, `chainspec`, the network
+/// `config` and the metrics `registry`.
 ///
 /// ```rust
-/// let manager = Manager::new(schultz_addr, event_tx, chainspec).await?; 
+/// let manager = Manager::new(schultz_addr, event_tx, chainspec, config, &registry).await?;
 /// ```
 pub struct Manager {
     schultz_addr: SocketAddr,
-    tcp_ep: Arc<Mutex<TcpListener>>,
+    transport: Arc<dyn Transport>,
     identity: Identity,
+    connection_identity: SharedIdentity,
+    consensus_keys: Option<ConsensusKeys>,
     pub chainspec: Chainspec,
-    connection_pool: Arc<Mutex<BTreeMap<SocketAddr, FramedTransport>>>,
-    awaiting_hs_reply_from: Arc<Mutex<Vec<SocketAddr>>>,
+    connection_pool: ConnectionPool,
+    open_connection: ConnectionOpener,
+    awaiting_hs_reply_from: AwaitingHandshakes,
     fully_connected_peers: Arc<Mutex<Vec<SocketAddr>>>,
+    last_handshake: LastHandshake,
+    config: Config,
+    liveness: LivenessMap,
+    memory: Arc<MemoryBudget>,
+    metrics: Arc<Metrics>,
+    bandwidth: Arc<BandwidthTracker>,
+    limits: Arc<ConnectionLimits>,
+    reputation: Arc<Reputation>,
+    history: Arc<History>,
+    observed: broadcast::Sender<Observed>,
+    eras: Arc<EraTracker>,
+    wire_log: Option<Arc<WireLog>>,
     endpoint_listener_handle: Option<JoinHandle<()>>,
-    conn_pool_listener_handle: Option<JoinHandle<()>>,
+    keepalive_handle: Option<JoinHandle<()>>,
+    drain_handle: Option<JoinHandle<()>>,
 }
 
 impl Manager {
@@ -94,6 +277,8 @@ impl Manager {
     /// - `schultz_addr`: The address to bind the network listener.
     /// - `event_tx`: A channel sender for transmitting events.
     /// - `chainspec`: The chainspec configuration for the network.
+    /// - `config`: The network configuration, e.g. keepalive settings.
+    /// - `registry`: The registry to register the network metrics with.
     ///
     /// # Returns
     ///
@@ -103,37 +288,150 @@ impl Manager {
     /// # Example
     ///
     /// ```rust
-    /// let manager = Manager::new(schultz_addr, event_tx, chainspec).await?; 
+    /// let manager = Manager::new(schultz_addr, event_tx, chainspec, config, &registry).await?;
     /// ```
     pub async fn new<P: Payload>(
         schultz_addr: SocketAddr,
-        event_tx: Sender<(SocketAddr, Message<P>)>,
+        event_tx: Sender<Event<P>>,
         chainspec: Chainspec,
+        config: Config,
+        registry: &Registry,
+    ) -> Result<Self, ManagerError> {
+        let identity = Identity::generate(&config.cert_subject)?;
+        Self::with_identity(
+            identity,
+            schultz_addr,
+            event_tx,
+            chainspec,
+            config,
+            registry,
+        )
+        .await
+    }
+
+    /// Creates a new `Manager` instance presenting the given `identity` to
+    /// peers, see [`Manager::new`].
+    ///
+    /// Binding to port 0 picks a free port, which is then used as our public
+    /// address.
+    pub async fn with_identity<P: Payload>(
+        identity: Identity,
+        schultz_addr: SocketAddr,
+        event_tx: Sender<Event<P>>,
+        chainspec: Chainspec,
+        config: Config,
+        registry: &Registry,
+    ) -> Result<Self, ManagerError> {
+        let transport = Arc::new(TlsTransport::new(identity.clone(), config.tls_options()));
+        Self::with_transport(
+            transport,
+            identity,
+            schultz_addr,
+            event_tx,
+            chainspec,
+            config,
+            registry,
+        )
+        .await
+    }
+
+    /// Creates a new `Manager` instance establishing its connections through
+    /// `transport`, see [`Manager::new`].
+    pub async fn with_transport<P: Payload>(
+        transport: Arc<dyn Transport>,
+        identity: Identity,
+        schultz_addr: SocketAddr,
+        event_tx: Sender<Event<P>>,
+        chainspec: Chainspec,
+        config: Config,
+        registry: &Registry,
     ) -> Result<Self, ManagerError> {
         info!("Starting network communications...");
-        let listener = TcpListener::bind(schultz_addr)
-            .await
-            .map_err(|error| ManagerError::ListenerCreation(error, schultz_addr))?;
+        let listener = transport.bind(schultz_addr).await?;
+        let schultz_addr = listener.local_addr();
+
+        let bandwidth = BandwidthTracker::new(config.max_bandwidth, config.max_peer_bandwidth);
+        let wire_log = match &config.wire_log {
+            Some(path) => Some(Arc::new(
+                WireLog::open(path).map_err(|e| ManagerError::WireLog(path.clone(), e))?,
+            )),
+            None => None,
+        };
+        let consensus_keys = match &config.consensus_key {
+            Some(path) => {
+                let keys = ConsensusKeys::load(path)?;
+                info!("Signing handshakes as validator {}", keys.public_key());
+                Some(keys)
+            }
+            None => None,
+        };
 
-        let identity = Identity::with_generated_certs().expect("Failed to generate identity");
+        let history = Arc::new(History::default());
+        let metrics = Arc::new(Metrics::new(registry)?);
+        let reader_context = Arc::new(ReaderContext {
+            schultz_addr,
+            chainspec: chainspec.clone(),
+            config: config.clone(),
+            fully_connected_peers: Arc::new(Mutex::new(Vec::new())),
+            awaiting_reply_from_peers: Arc::new(Mutex::new(BTreeMap::new())),
+            last_handshake: Arc::new(Mutex::new(None)),
+            bandwidth: Arc::new(bandwidth),
+            liveness: Arc::new(Mutex::new(BTreeMap::new())),
+            memory: Arc::new(MemoryBudget::new(config.max_peer_memory)),
+            buffers: Arc::new(BufferPool::new(&metrics)),
+            metrics,
+            reputation: Arc::new(Reputation::default().with_history(history.clone())),
+            history,
+            observed: broadcast::channel(OBSERVED_CAPACITY).0,
+            wire_log: wire_log.clone(),
+            transport: transport.clone(),
+            identity: Arc::new(std::sync::RwLock::new(identity.clone())),
+            consensus_keys: consensus_keys.clone(),
+            replay_guard: ReplayGuard::default(),
+            eras: Arc::new(EraTracker::default()),
+            connection_pool: Arc::new(Mutex::new(BTreeMap::new())),
+            connection_ids: ConnectionIds::default(),
+            event_tx,
+        });
+        transport.count_handshakes(reader_context.metrics.tls_handshakes.clone());
+        let limits = Arc::new(ConnectionLimits::new(
+            &config,
+            reader_context.metrics.clone(),
+        ));
 
         let mut schultz = Self {
             schultz_addr,
-            tcp_ep: Arc::new(Mutex::new(listener)),
+            transport,
             identity,
+            connection_identity: reader_context.identity.clone(),
+            consensus_keys,
             chainspec,
-            connection_pool: Arc::new(Mutex::new(BTreeMap::new())),
-            awaiting_hs_reply_from: Arc::new(Mutex::new(Vec::new())),
-            fully_connected_peers: Arc::new(Mutex::new(Vec::new())),
+            connection_pool: reader_context.connection_pool.clone(),
+            open_connection: Self::connection_opener(reader_context.clone()),
+            awaiting_hs_reply_from: reader_context.awaiting_reply_from_peers.clone(),
+            fully_connected_peers: reader_context.fully_connected_peers.clone(),
+            last_handshake: reader_context.last_handshake.clone(),
+            config,
+            liveness: reader_context.liveness.clone(),
+            memory: reader_context.memory.clone(),
+            metrics: reader_context.metrics.clone(),
+            bandwidth: reader_context.bandwidth.clone(),
+            limits,
+            reputation: reader_context.reputation.clone(),
+            history: reader_context.history.clone(),
+            observed: reader_context.observed.clone(),
+            eras: reader_context.eras.clone(),
+            wire_log,
             endpoint_listener_handle: None,
-            conn_pool_listener_handle: None,
+            keepalive_handle: None,
+            drain_handle: None,
         };
 
-        let endpoint_listener_handle = schultz.listen_on_endpoint().await;
-        let conn_pool_listener_handle = schultz.listen_to_connection_pool(event_tx).await;
+        let endpoint_listener_handle = schultz.listen_on_endpoint(listener).await;
+        let keepalive_handle = schultz.start_keepalive::<P>();
 
         schultz.endpoint_listener_handle = Some(endpoint_listener_handle);
-        schultz.conn_pool_listener_handle = Some(conn_pool_listener_handle);
+        schultz.keepalive_handle = Some(keepalive_handle);
 
         info!("Network communications started!");
         trace!("Waiting for incoming connections...");
@@ -143,10 +441,183 @@ impl Manager {
 
     pub fn schultz_addr(&self) -> SocketAddr { self.schultz_addr }
 
+    pub fn identity(&self) -> &Identity { &self.identity }
+
+    /// Takes over the timeouts and connection limits of `config`, see
+    /// [`Config::reload`].
+    pub fn reload(&mut self, config: &Config) {
+        self.config.reload(config);
+        self.limits.resize(&self.config);
+    }
+
+    /// Presents `identity` to peers from now on.
+    ///
+    /// Established connections keep the certificate they were set up with
+    /// until they are reconnected, see [`Manager::stale_connections`].
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.transport.set_identity(identity.clone());
+        *self.connection_identity.write().expect("identity lock poisoned") = identity.clone();
+        self.identity = identity;
+    }
+
+    /// Keeps the task moving peers over to a renewed certificate, so it
+    /// stops at shutdown. One still draining for an earlier renewal is
+    /// superseded and stopped.
+    pub fn set_drain(&mut self, handle: JoinHandle<()>) {
+        if let Some(previous) = self.drain_handle.replace(handle) {
+            previous.abort();
+        }
+    }
+
+    /// Peers whose connection was set up presenting another certificate
+    /// than the one we present now.
+    pub async fn stale_connections(&self) -> Vec<SocketAddr> {
+        self.connection_pool
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, connection)| self.is_stale_connection(connection))
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+
+    /// Whether the connection to `addr` was set up presenting another
+    /// certificate than the one we present now.
+    pub async fn is_stale(&self, addr: SocketAddr) -> bool {
+        let pool = self.connection_pool.lock().await;
+        pool.get(&addr).is_some_and(|connection| self.is_stale_connection(connection))
+    }
+
+    fn is_stale_connection(&self, connection: &Connection) -> bool {
+        connection
+            .identity()
+            .is_some_and(|identity| !identity.same_certificate(&self.identity))
+    }
+
+    /// The node at `addr` the last time we were connected to it, if the
+    /// transport has certificates.
+    pub fn peer_id(&self, addr: &SocketAddr) -> Option<NodeId> { self.transport.peer_id(*addr) }
+
+    /// Where we are connected to `node`, if we are. The most recent
+    /// connection if there are several, e.g. one in each direction.
+    pub async fn peer_addr(&self, node: &NodeId) -> Option<SocketAddr> {
+        self.connection_pool
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, connection)| connection.peer_id() == Some(*node))
+            .max_by_key(|(_, connection)| connection.id())
+            .map(|(addr, _)| *addr)
+    }
+
+    /// The validators of every era known, see [`EraTracker`].
+    pub fn eras(&self) -> Arc<EraTracker> { self.eras.clone() }
+
+    /// Returns the outcome of the most recent handshake, if any.
+    pub async fn last_handshake(&self) -> Option<HandshakeResult> {
+        self.last_handshake.lock().await.clone()
+    }
+
+    /// Returns the bytes exchanged with every peer.
+    pub fn peer_traffic(&self) -> BTreeMap<SocketAddr, Traffic> { self.bandwidth.traffic() }
+
+    /// Returns the score of every peer, see [`Reputation`].
+    pub fn reputation(&self) -> &Reputation { &self.reputation }
+
+    /// Subscribes to every gossip message read from a peer from now on, see
+    /// [`Observed`].
+    pub fn observe(&self) -> broadcast::Receiver<Observed> { self.observed.subscribe() }
+
+    /// Subscribes to every [`NetworkEvent`] from now on: connections opened
+    /// and closed and peers scored.
+    pub fn network_events(&self) -> broadcast::Receiver<NetworkEvent> { self.history.subscribe() }
+
+    /// Returns the bytes currently held on behalf of `addr`.
+    pub fn peer_memory(&self, addr: &SocketAddr) -> usize { self.memory.in_use(addr) }
+
+    /// Bytes of messages and frames to or from any peer not yet handled.
+    pub fn buffered_memory(&self) -> usize { self.memory.total() }
+
+    /// Every peer with messages or frames not yet handled, with their
+    /// bytes, the most first.
+    pub fn peers_by_memory(&self) -> Vec<(SocketAddr, usize)> { self.memory.largest() }
+
+    /// Bytes held for recording what happens on the network: the event
+    /// history and the wire log's buffer.
+    pub fn capture_memory(&self) -> usize {
+        let wire_log = self.wire_log.as_ref().map_or(0, |wire_log| wire_log.buffer_size());
+        self.history.heap_size() + wire_log
+    }
+
+    /// Returns the connection to every peer we have one open with.
+    pub async fn connection_ids(&self) -> BTreeMap<SocketAddr, ConnectionId> {
+        self.connection_pool
+            .lock()
+            .await
+            .iter()
+            .map(|(addr, connection)| (*addr, connection.id()))
+            .collect()
+    }
+
+    /// Returns what we know about every peer we have a connection to,
+    /// whether or not its handshake completed.
+    pub async fn peers(&self) -> Vec<PeerInfo> {
+        let traffic = self.peer_traffic();
+        self.connection_pool
+            .lock()
+            .await
+            .iter()
+            .map(|(addr, connection)| {
+                let info = connection.info();
+                let traffic = traffic.get(addr).copied().unwrap_or_default();
+                PeerInfo {
+                    addr: *addr,
+                    node_id: connection.peer_id(),
+                    protocol_version: info.protocol_version,
+                    direction: info.direction,
+                    connected_since: info.opened_at,
+                    last_seen: info.last_seen,
+                    bytes_read: traffic.bytes_read,
+                    bytes_written: traffic.bytes_written,
+                }
+            })
+            .collect()
+    }
+
+    /// Takes a snapshot of the connections, scores and recent events, e.g. to
+    /// attach to a bug report.
+    pub async fn network_state(&self) -> NetworkState {
+        NetworkState {
+            taken_at: Timestamp::now(),
+            addr: self.schultz_addr,
+            fingerprint: self.identity.fingerprint(),
+            peers: self.peers().await,
+            scores: self.reputation.scores(),
+            latencies: self.peer_latencies().await,
+            events: self.history.events(),
+        }
+    }
+
+    /// Returns the peers whose handshake completed.
+    pub async fn connected_peers(&self) -> Vec<SocketAddr> {
+        self.fully_connected_peers.lock().await.clone()
+    }
+
+    /// Returns the last measured ping round-trip time of every peer that
+    /// answered one of our pings.
+    pub async fn peer_latencies(&self) -> BTreeMap<SocketAddr, Duration> {
+        self.liveness
+            .lock()
+            .await
+            .iter()
+            .filter_map(|(addr, liveness)| Some((*addr, liveness.latency()?)))
+            .collect()
+    }
+
     /// Connects to a peer at the specified address.
     ///
-    /// This method establishes a TCP connection to the given address and
-    /// performs a TLS handshake.
+    /// This method establishes a connection to the given address through the
+    /// transport, e.g. TCP and TLS, and starts reading from and writing to it.
     ///
     /// # Parameters
     ///
@@ -163,43 +634,53 @@ impl Manager {
     /// manager.connect(&peer_addr).await?; 
     /// ```
     pub async fn connect(&self, addr: &SocketAddr) -> Result<(), ManagerError> {
-        info!("Connecting to {addr:?}");
-        let stream = TcpStream::connect(addr).await.map_err(TLSError::TcpConnection)?;
-
-        stream.set_nodelay(true).map_err(|_| TLSError::TcpNoDelay)?;
-
-        let mut transport =
-            tls::create_tls_connector(&self.identity.tls_certificate, &self.identity.secret_key)
-                .and_then(|connector| connector.configure())
-                .and_then(|mut config| {
-                    config.set_verify_hostname(false);
-                    config.into_ssl("this-will-not-be-checked.example.com")
-                })
-                .and_then(|ssl| SslStream::new(ssl, stream))
-                .map_err(|error| TLSError::TlsInitialization(error.to_string()))?;
-
-        SslStream::connect(Pin::new(&mut transport))
-            .await
-            .map_err(|error| TLSError::TlsHandshake(error.to_string()))?;
-
-        let peer_cert = transport.ssl().peer_certificate().ok_or(TLSError::NoPeerCertificate)?;
-
-        tls::validate_peer_cert(peer_cert).map_err(|_| TLSError::FailedToValidateSignature)?;
+        self.connect_any(&[*addr], &|_| {}).await?;
+        Ok(())
+    }
 
-        let framed_transport = tokio_util::codec::Framed::new(
-            transport,
-            LengthDelimitedCodec::builder().max_frame_length(MAX_FRAME_LEN).new_codec(),
-        );
+    /// Connects to whichever of `addrs` answers first, racing them in order,
+    /// and returns it. Each phase of setting up a connection is passed to
+    /// `report`.
+    ///
+    /// Every attempt is given up on after the configured connect timeout.
+    /// Fails right away if we already hold the maximum of connections, and
+    /// waits for a dial slot if every one is taken.
+    pub async fn connect_any(
+        &self,
+        addrs: &[SocketAddr],
+        report: &(dyn Fn(Step) + Sync),
+    ) -> Result<SocketAddr, ManagerError> {
+        let permit = self.limits.outbound()?;
+        let dial = self.limits.dial().await;
+        let raced = resolve::race(addrs, self.config.connect_timeout.into(), |addr| {
+            self.transport.connect_with_progress(addr, report)
+        })
+        .await;
+        drop(dial);
+        let (addr, stream) = match raced {
+            Ok(connected) => connected,
+            Err(e) => {
+                for addr in addrs {
+                    self.reputation.record(*addr, Behavior::ConnectFailed);
+                }
+                self.count_failure(&e);
+                return Err(e);
+            }
+        };
 
-        self.connection_pool.lock().await.insert(*addr, framed_transport);
+        let connection = (self.open_connection)(addr, Direction::Outbound, stream, permit);
+        let stale = Self::insert_connection(&self.connection_pool, addr, connection).await;
+        if let Some(stale) = stale {
+            self.disconnect(stale).await;
+        }
 
-        Ok(())
+        Ok(addr)
     }
 
-    /// Sends a handshake message to a peer.
+    /// Performs the protocol handshake with a connected peer.
     ///
-    /// This method constructs and sends a handshake message to the specified
-    /// peer address.
+    /// This method sends our handshake to the specified peer address and waits
+    /// for the peer's answer, which is negotiated against our chainspec.
     ///
     /// # Parameters
     ///
@@ -207,38 +688,77 @@ impl Manager {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if the handshake is sent successfully, or a
-    /// `ManagerError` if an error occurs.
+    /// Returns the peer's `Handshake` if it was accepted, or a `ManagerError`
+    /// describing why the handshake failed or was rejected.
     ///
     /// # Example
     ///
     /// ```rust
-    /// manager.handshake::<Payload>(peer_addr).await?; 
+    /// let peer_handshake = manager.handshake::<Payload>(peer_addr).await?; 
     /// ```
-    pub async fn handshake<P: Payload>(&self, addr: SocketAddr) -> Result<(), ManagerError> {
-        let mut encoder = MessagePackFormat;
-        let hs: Message<P> = Message::Handshake {
-            network_name: self.chainspec.network_config.name.clone(),
-            public_addr: self.schultz_addr,
-            protocol_version: self.chainspec.protocol_config.version,
-            consensus_certificate: None,
-            is_syncing: false,
-            chainspec_hash: Some(self.chainspec.hash()),
-        };
-
-        let serialized_handshake_message = Pin::new(&mut encoder)
-            .serialize(&Arc::new(hs))
-            .map_err(|error| TLSError::TlsHandshake(error.to_string()))?;
+    #[instrument(name = "protocol_handshake", skip(self), fields(peer = %addr))]
+    pub async fn handshake<P: Payload>(&self, addr: SocketAddr) -> Result<Handshake, ManagerError> {
+        let consensus_certificate = Self::consensus_certificate(
+            self.consensus_keys.as_ref(),
+            self.transport.as_ref(),
+            addr,
+        );
+        let session_id = self.transport.session_id(addr);
+        let serialized_handshake_message = Handshake::new(&self.chainspec, self.schultz_addr)
+            .with_compression(self.config.compression.clone())
+            .with_multiplexing(self.config.multiplexing)
+            .with_checksums(self.config.checksums)
+            .with_consensus_certificate(consensus_certificate)
+            .stamped_with(self.consensus_keys.as_ref(), session_id.as_ref())
+            .encode::<P>()?;
+
+        // Register before sending so a fast reply cannot slip past us.
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.awaiting_hs_reply_from.lock().await.insert(addr, reply_tx);
 
         trace!("1.Trying to send a Handshake to {addr:?}");
 
-        self.send_message(addr, serialized_handshake_message).await?;
+        if let Err(error) = self.send_message(addr, serialized_handshake_message).await {
+            self.awaiting_hs_reply_from.lock().await.remove(&addr);
+            self.count_failure(&error);
+            return Err(error);
+        }
 
         info!("Sent a handshake to {addr:?}");
 
-        self.awaiting_hs_reply_from.lock().await.push(addr);
+        let outcome = match tokio::time::timeout(HANDSHAKE_TIMEOUT, reply_rx).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_)) => Err(HandshakeError::ConnectionClosed),
+            Err(_) => {
+                self.awaiting_hs_reply_from.lock().await.remove(&addr);
+                self.reputation.record(addr, Behavior::HandshakeFailed);
+                self.metrics.handshakes.with_label_values(&["timed_out"]).inc();
+                let error = ManagerError::HandshakeTimeout(addr);
+                self.count_failure(&error);
+                *self.last_handshake.lock().await =
+                    Some(HandshakeResult::new(addr, Err::<(), _>(&error)));
+                return Err(error);
+            }
+        };
 
-        Ok(())
+        let behavior = match &outcome {
+            Ok(_) => Behavior::HandshakeCompleted,
+            Err(error) if error.is_peer_fault() => Behavior::ProtocolViolation,
+            Err(_) => Behavior::HandshakeFailed,
+        };
+        self.reputation.record(addr, behavior);
+        outcome.map_err(|error| {
+            let error = ManagerError::HandshakeRejected(addr, error);
+            self.count_failure(&error);
+            error
+        })
+    }
+
+    /// Counts a failed attempt to connect to or handshake with a peer under
+    /// its [`FailureCause`](super::error::FailureCause).
+    pub fn count_failure(&self, error: &ManagerError) {
+        let labels = error.failure_cause().labels();
+        self.metrics.connection_failures.with_label_values(&labels).inc();
     }
 
     /// Sends a message to a peer.
@@ -261,19 +781,66 @@ impl Manager {
     /// manager.send_message(peer_addr, payload).await?; 
     /// ```
     pub async fn send_message(&self, addr: SocketAddr, payload: Bytes) -> Result<(), ManagerError> {
+        Self::send_to(&self.connection_pool, addr, Channel::Control, payload).await
+    }
+
+    /// Queues `payload` on `channel` for the writer task of the connection
+    /// to `addr`.
+    ///
+    /// Waits while that peer's outbound queue is full; throttling and the
+    /// write itself happen on the writer task.
+    async fn send_to(
+        connection_pool: &Mutex<BTreeMap<SocketAddr, Connection>>,
+        addr: SocketAddr,
+        channel: Channel,
+        payload: Bytes,
+    ) -> Result<(), ManagerError> {
         info!("Sending message to {addr:?}");
-        let mut conn_pool = self.connection_pool.lock().await;
-        let peer_connection = conn_pool.get_mut(&addr).ok_or(ManagerError::PeerNotFound)?;
-        let message = SchultzMessage::new(payload)?;
-        message.write_to_stream(peer_connection).await?;
+        // Release the pool lock before waiting on a full queue, so a slow
+        // peer does not hold up everyone else.
+        let outbound = connection_pool
+            .lock()
+            .await
+            .get(&addr)
+            .map(Connection::outbound)
+            .ok_or(ManagerError::PeerNotFound)?;
+        outbound.send_on(channel, payload).await
+    }
 
-        Ok(())
+    /// Sends a payload message to a peer.
+    pub async fn send_payload<P: Payload>(
+        &self,
+        addr: SocketAddr,
+        payload: P,
+    ) -> Result<(), ManagerError> {
+        let channel = payload.channel();
+        let serialized = Self::encode_bincode(Message::Payload(payload))?;
+        Self::send_to(&self.connection_pool, addr, channel, serialized).await
+    }
+
+    /// Sends a payload message that is already encoded, e.g. one schultz frames
+    /// by hand because it does not decode its kind, on `channel`.
+    pub async fn send_encoded(
+        &self,
+        addr: SocketAddr,
+        channel: Channel,
+        serialized: Bytes,
+    ) -> Result<(), ManagerError> {
+        Self::send_to(&self.connection_pool, addr, channel, serialized).await
+    }
+
+    /// Encodes a post-handshake message the way Casper expects it (bincode).
+    fn encode_bincode<P: Payload>(message: Message<P>) -> Result<Bytes, ManagerError> {
+        Pin::new(&mut BincodeFormat::default())
+            .serialize(&Arc::new(message))
+            .map_err(|e| ManagerError::CouldNotEncodeMessage(e.to_string()))
     }
 
     /// Sends a ping message to a peer.
     ///
     /// This method constructs and sends a ping message to the specified peer
-    /// address.
+    /// address. The ping is tracked, so a matching pong updates the peer's
+    /// latency.
     ///
     /// # Parameters
     ///
@@ -290,24 +857,149 @@ impl Manager {
     /// manager.send_ping::<YourPayloadType>(peer_addr).await?; 
     /// ```
     pub async fn send_ping<P: Payload>(&self, addr: SocketAddr) -> Result<(), ManagerError> {
-        info!("Sending a ping to {addr:?}");
-        let ping: Message<P> = Message::Ping {
-            nonce: Nonce::new(rand::thread_rng().next_u64()),
-        };
+        Self::ping_peer::<P>(&self.connection_pool, &self.liveness, &self.metrics, addr).await
+    }
 
-        let mut encoder = BincodeFormat::default();
+    async fn ping_peer<P: Payload>(
+        connection_pool: &Mutex<BTreeMap<SocketAddr, Connection>>,
+        liveness: &LivenessMap,
+        metrics: &Metrics,
+        addr: SocketAddr,
+    ) -> Result<(), ManagerError> {
+        info!("Sending a ping to {addr:?}");
+        let nonce = Nonce::new(rand::thread_rng().next_u64());
+        let serialized_ping_message = Self::encode_bincode::<P>(Message::Ping { nonce })?;
 
-        let serialized_ping_message = Pin::new(&mut encoder)
-            .serialize(&Arc::new(ping))
-            .map_err(|e| ManagerError::CouldNotEncodeOurHandshake(e.to_string()))?;
+        Self::send_to(
+            connection_pool,
+            addr,
+            Channel::Control,
+            serialized_ping_message,
+        )
+        .await?;
 
-        self.send_message(addr, serialized_ping_message).await?;
+        liveness.lock().await.entry(addr).or_default().ping_sent(nonce, Instant::now());
+        metrics.pings_sent.inc();
 
         info!("Sent a ping to {addr:?}");
 
         Ok(())
     }
 
+    /// Starts pinging every fully connected peer once per ping interval.
+    ///
+    /// A peer that leaves `max_missed_pongs` pings in a row unanswered is
+    /// disconnected.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `JoinHandle<()>` that represents the spawned task.
+    pub fn start_keepalive<P: Payload>(&self) -> JoinHandle<()> {
+        let connection_pool = self.connection_pool.clone();
+        let fully_connected_peers = self.fully_connected_peers.clone();
+        let liveness = self.liveness.clone();
+        let metrics = self.metrics.clone();
+        let bandwidth = self.bandwidth.clone();
+        let history = self.history.clone();
+        let ping_interval: Duration = self.config.ping_interval.into();
+        let max_missed_pongs = self.config.max_missed_pongs;
+        info!("Starting keepalive task, pinging peers every {ping_interval:?}");
+        tokio::spawn(async move {
+            let mut interval = interval(ping_interval);
+            // The first tick completes immediately, skip it.
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                let peers = fully_connected_peers.lock().await.clone();
+                for peer_addr in peers {
+                    let missed_pongs =
+                        liveness.lock().await.entry(peer_addr).or_default().expire_outstanding();
+
+                    if missed_pongs >= max_missed_pongs {
+                        warn!("{peer_addr:?} missed {missed_pongs} pongs in a row, disconnecting");
+                        metrics.peers_timed_out.inc();
+                        Self::drop_peer(
+                            &connection_pool,
+                            &fully_connected_peers,
+                            &bandwidth,
+                            &liveness,
+                            &metrics,
+                            &history,
+                            peer_addr,
+                        )
+                        .await;
+                        continue;
+                    }
+
+                    let pinged =
+                        Self::ping_peer::<P>(&connection_pool, &liveness, &metrics, peer_addr)
+                            .await;
+                    if let Err(e) = pinged {
+                        error!("Error {e:?} sending ping to {peer_addr:?}");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Closes the connection to a peer.
+    pub async fn disconnect(&self, addr: SocketAddr) {
+        info!("Disconnecting from {addr:?}");
+        Self::drop_peer(
+            &self.connection_pool,
+            &self.fully_connected_peers,
+            &self.bandwidth,
+            &self.liveness,
+            &self.metrics,
+            &self.history,
+            addr,
+        )
+        .await;
+    }
+
+    /// Stops listening and keeping connections alive, and closes every
+    /// connection. The manager is of no use afterwards.
+    pub async fn shutdown(&self) {
+        info!("Shutting down network communications");
+        let tasks = [
+            &self.endpoint_listener_handle,
+            &self.keepalive_handle,
+            &self.drain_handle,
+        ];
+        for handle in tasks.into_iter().flatten() {
+            handle.abort();
+        }
+        let open: Vec<_> = self.connection_pool.lock().await.keys().copied().collect();
+        for addr in open {
+            self.disconnect(addr).await;
+        }
+    }
+
+    /// Closes the connection to a peer and forgets everything about it.
+    async fn drop_peer(
+        connection_pool: &Mutex<BTreeMap<SocketAddr, Connection>>,
+        fully_connected_peers: &Mutex<Vec<SocketAddr>>,
+        bandwidth: &BandwidthTracker,
+        liveness: &LivenessMap,
+        metrics: &Metrics,
+        history: &History,
+        addr: SocketAddr,
+    ) {
+        let connection = connection_pool.lock().await.remove(&addr);
+        fully_connected_peers.lock().await.retain(|peer| *peer != addr);
+        bandwidth.remove(&addr);
+        liveness.lock().await.remove(&addr);
+        if let Some(connection) = connection {
+            history.record(addr, EventKind::Dropped);
+            metrics.connections_closed.with_label_values(&["dropped"]).inc();
+            let _ = metrics
+                .peer_latency
+                .remove_label_values(&[&addr.to_string(), &connection.id().to_string()]);
+        }
+    }
+
     /// Creates a TLS acceptor for incoming connections.
     ///
     /// This function sets up an `SslAcceptor` using the provided certificate
@@ -320,6 +1012,7 @@ impl Manager {
     /// - `cert`: A reference to the certificate (`X509Ref`) used for TLS.
     /// - `private_key`: A reference to the private key (`PKeyRef<Private>`)
     ///   used for TLS.
+    /// - `options`: The cipher suites and groups offered to peers.
     ///
     /// # Returns
     ///
@@ -329,50 +1022,49 @@ impl Manager {
     /// # Example
     ///
     /// ```rust
-    /// let acceptor = Manager::create_tls_acceptor(&cert, &private_key)?; 
+    /// let acceptor = Manager::create_tls_acceptor(&cert, &private_key, &options)?; 
     /// ```
+    #[cfg(feature = "openssl")]
     pub fn create_tls_acceptor(
         cert: &X509Ref,
         private_key: &PKeyRef<Private>,
+        options: &TlsOptions,
     ) -> SslResult<SslAcceptor> {
         info!("Creating TLS acceptor for incoming connections");
-        let mut builder = SslAcceptor::mozilla_modern_v5(SslMethod::tls_server())?;
-        set_context_options(&mut builder, cert, private_key)?;
-
-        Ok(builder.build())
+        tls::openssl::create_tls_acceptor(cert, &[], private_key, options)
     }
 
     /// Sets up a TLS connection with a peer.
     ///
     /// This asynchronous function initializes a TLS stream on top of an
     /// existing TCP stream using the identity provided. It returns a
-    /// `SslStream` that can be used for secure communication.
+    /// `TlsStream` that can be used for secure communication.
     ///
     /// # Parameters
     ///
     /// - `stream`: The TCP stream to wrap with TLS.
     /// - `identity`: The identity containing the TLS certificate and secret
     ///   key.
+    /// - `options`: The cipher suites and groups offered to the peer.
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the `SslStream` if successful, or a
+    /// Returns a `Result` containing the `TlsStream` if successful, or a
     /// `ManagerError` if the setup fails.
     ///
     /// # Example
     ///
     /// ```rust
-    /// let tls_stream = Manager::setup_tls(tcp_stream, &identity).await?; 
+    /// let tls_stream = Manager::setup_tls(tcp_stream, &identity, &options).await?; 
     /// ```
+    #[instrument(skip_all)]
     pub async fn setup_tls(
         stream: TcpStream,
         identity: &Identity,
-    ) -> Result<SslStream<TcpStream>, ManagerError> {
+        options: &TlsOptions,
+    ) -> Result<TlsStream<TcpStream>, ManagerError> {
         info!("Setting up TLS with connected peer");
-        Self::create_tls_acceptor(&identity.tls_certificate, &identity.secret_key)
-            .and_then(|ssl_acceptor| Ssl::new(ssl_acceptor.context()))
-            .and_then(|ssl| SslStream::new(ssl, stream))
-            .map_err(|e| ManagerError::Tls(TLSError::TlsInitialization(e.to_string())))
+        Ok(TlsStream::server(stream, identity, options)?)
     }
 
     /// Performs a TLS handshake on the given stream.
@@ -382,7 +1074,7 @@ impl Manager {
     ///
     /// # Parameters
     ///
-    /// - `transport`: A mutable reference to the `SslStream` on which to
+    /// - `transport`: A mutable reference to the `TlsStream` on which to
     ///   perform the handshake.
     ///
     /// # Returns
@@ -395,21 +1087,22 @@ impl Manager {
     /// ```rust
     /// Manager::perform_tls_handshake(&mut tls_stream).await?; 
     /// ```
+    #[instrument(name = "tls_handshake", skip_all)]
     pub async fn perform_tls_handshake(
-        transport: &mut SslStream<TcpStream>,
+        transport: &mut TlsStream<TcpStream>,
     ) -> Result<(), ManagerError> {
         info!("Starting TLS level handshake");
-        SslStream::accept(Pin::new(transport))
-            .await
-            .map_err(|e| ManagerError::Tls(TLSError::TlsHandshake(e.to_string())))
+        Ok(transport.accept().await?)
     }
 
     /// Listens for incoming connections on the TCP endpoint.
     ///
     /// This asynchronous function continuously listens for new connections
-    /// on the configured TCP endpoint. For each connection, it sets up TLS
-    /// and performs a handshake, then adds the connection to the connection
-    /// pool.
+    /// on `listener`, which is closed once the task is aborted. Each accepted
+    /// connection within the connection limits is set up by the transport,
+    /// e.g. with TLS, in a task of its own, so a peer stalling it holds up
+    /// no one else. Once set up, it is served by its own reader and writer
+    /// tasks and added to the connection pool.
     ///
     /// # Returns
     ///
@@ -418,163 +1111,434 @@ impl Manager {
     /// # Example
     ///
     /// ```rust
-    /// let handle = manager.listen_on_endpoint().await; 
+    /// let handle = manager.listen_on_endpoint(listener).await; 
     /// ```
-    pub async fn listen_on_endpoint(&self) -> JoinHandle<()> {
+    pub async fn listen_on_endpoint(&self, mut listener: Box<dyn Listener>) -> JoinHandle<()> {
         let connection_pool = self.connection_pool.clone();
-        let identity = self.identity.clone();
-        let tcp_ep = self.tcp_ep.clone();
+        let open_connection = self.open_connection.clone();
+        let limits = self.limits.clone();
+        let fully_connected_peers = self.fully_connected_peers.clone();
+        let bandwidth = self.bandwidth.clone();
+        let liveness = self.liveness.clone();
+        let metrics = self.metrics.clone();
+        let reputation = self.reputation.clone();
+        let history = self.history.clone();
         info!("Starting to listen on TCP Endpoint for incoming connections");
         tokio::spawn(async move {
+            // Connections being set up, aborted along with the listener.
+            let mut setups = JoinSet::new();
             loop {
-                let (stream, peer_addr) = match tcp_ep.lock().await.accept().await {
-                    Ok(connection) => connection,
+                while setups.try_join_next().is_some() {}
+                let Accepted { peer_addr, setup } = match listener.accept().await {
+                    Ok(accepted) => accepted,
                     Err(e) => {
                         error!("Error accepting connection at endpoint {e:?}");
                         continue;
                     }
                 };
 
-                info!("New connection received!");
-                info!("Setting up TLS with connected peer");
-                let mut transport: SslStream<TcpStream> =
-                    match Self::setup_tls(stream, &identity).await {
-                        Ok(stream) => stream,
-                        Err(e) => {
-                            error!("Error accepting connection at endpoint {e:?}");
-                            continue;
-                        }
-                    };
-
-                info!("Performing TLS handshake with connected peer");
-                if let Err(e) = Self::perform_tls_handshake(&mut transport).await {
-                    error!("Error accepting connection to endpoint {e:?}");
-                    continue;
-                }
+                let permit = match limits.inbound(peer_addr.ip()) {
+                    Err(LimitReached::Connections(_))
+                        if Self::evict_worse_than(
+                            &connection_pool,
+                            &fully_connected_peers,
+                            &bandwidth,
+                            &liveness,
+                            &metrics,
+                            &reputation,
+                            &history,
+                            peer_addr,
+                        )
+                        .await =>
+                    {
+                        limits.inbound_within(peer_addr.ip(), EVICTION_GRACE).await
+                    }
+                    permit => permit,
+                };
+                let permit = match permit {
+                    Ok(permit) => permit,
+                    Err(e) => {
+                        warn!("Refusing connection from {peer_addr:?}: {e}");
+                        continue;
+                    }
+                };
 
-                info!("Receiving peer Ssl certificates");
-                let peer_cert =
-                    match transport.ssl().peer_certificate().ok_or(TLSError::NoPeerCertificate) {
-                        Ok(cert) => cert,
-                        Err(e) => {
-                            error!("Error accepting connection at endpoint {e:?}");
-                            continue;
+                let open_connection = open_connection.clone();
+                let connection_pool = connection_pool.clone();
+                let fully_connected_peers = fully_connected_peers.clone();
+                let bandwidth = bandwidth.clone();
+                let liveness = liveness.clone();
+                let metrics = metrics.clone();
+                let history = history.clone();
+                setups.spawn(async move {
+                    let stream = match tokio::time::timeout(SETUP_TIMEOUT, setup).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            error!("Error setting up connection from {peer_addr:?}: {e:?}");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("Connection from {peer_addr:?} not set up in {SETUP_TIMEOUT:?}");
+                            return;
                         }
                     };
 
-                info!("Verifying peer's certificates for sanity");
-                let _validated_peer_cert = match validate_self_signed_cert(peer_cert) {
-                    Ok(peer_cert) => peer_cert,
-                    Err(e) => {
-                        error!("Error accepting connection at endpoint {e:?}");
-                        continue;
+                    info!("Inserting connection into schultz connection pool");
+                    let connection = open_connection(peer_addr, Direction::Inbound, stream, permit);
+                    let stale =
+                        Self::insert_connection(&connection_pool, peer_addr, connection).await;
+                    if let Some(stale) = stale {
+                        Self::drop_peer(
+                            &connection_pool,
+                            &fully_connected_peers,
+                            &bandwidth,
+                            &liveness,
+                            &metrics,
+                            &history,
+                            stale,
+                        )
+                        .await;
                     }
-                };
+                });
+            }
+        })
+    }
 
-                info!("Framing the stream to match Casper's encoding");
-                // Frame the transport
-                let framed_transport = tokio_util::codec::Framed::new(
-                    transport,
-                    LengthDelimitedCodec::builder().max_frame_length(MAX_FRAME_LEN).new_codec(),
-                );
+    /// Adds the connection to `addr` to the pool. Returns the address of the
+    /// connection the same node has open in the same direction, which it
+    /// left behind reconnecting, so that the caller closes it.
+    async fn insert_connection(
+        connection_pool: &Mutex<BTreeMap<SocketAddr, Connection>>,
+        addr: SocketAddr,
+        connection: Connection,
+    ) -> Option<SocketAddr> {
+        let mut pool = connection_pool.lock().await;
+        let direction = connection.info().direction;
+        let stale = connection.peer_id().and_then(|node| {
+            let (stale, _) = pool.iter().find(|(other, open)| {
+                **other != addr
+                    && open.peer_id() == Some(node)
+                    && open.info().direction == direction
+            })?;
+            info!("{node} reconnected from {addr:?}, closing its connection from {stale:?}");
+            Some(*stale)
+        });
+        pool.insert(addr, connection);
+        stale
+    }
+
+    /// Drops the lowest scoring peer we are connected to, if it scores below
+    /// `newcomer`, to make room for it. Returns whether a peer was dropped.
+    #[allow(clippy::too_many_arguments)]
+    async fn evict_worse_than(
+        connection_pool: &Mutex<BTreeMap<SocketAddr, Connection>>,
+        fully_connected_peers: &Mutex<Vec<SocketAddr>>,
+        bandwidth: &BandwidthTracker,
+        liveness: &LivenessMap,
+        metrics: &Metrics,
+        reputation: &Reputation,
+        history: &History,
+        newcomer: SocketAddr,
+    ) -> bool {
+        let connected: Vec<SocketAddr> = connection_pool.lock().await.keys().copied().collect();
+        let Some(worst) = reputation.worst(&connected) else {
+            return false;
+        };
+        if reputation.score(&worst) >= reputation.score(&newcomer) {
+            return false;
+        }
+
+        info!(
+            "Evicting {worst:?}, scoring {}, to make room for {newcomer:?}",
+            reputation.score(&worst)
+        );
+        Self::drop_peer(
+            connection_pool,
+            fully_connected_peers,
+            bandwidth,
+            liveness,
+            metrics,
+            history,
+            worst,
+        )
+        .await;
+        true
+    }
 
-                info!("Inserting stream into schultz connection pool");
-                // insert into connection pool
-                let _ = connection_pool.lock().await.insert(peer_addr, framed_transport);
+    /// Builds the function starting the reader and writer tasks of every new
+    /// connection. Messages read are handled with `context`.
+    fn connection_opener<P: Payload>(context: Arc<ReaderContext<P>>) -> ConnectionOpener {
+        Arc::new(move |peer_addr, direction, stream, permit| {
+            let context = context.clone();
+            let identity = context.identity.read().expect("identity lock poisoned").clone();
+            let peer_id = context.transport.peer_id(peer_addr);
+            let record = PeerRecord::new(context.transport.clone(), peer_addr);
+            if let Some(peer_id) = peer_id {
+                context.reputation.identify(peer_addr, peer_id);
             }
+            context.history.record(peer_addr, EventKind::Connected { direction });
+            let direction_label = direction.to_string();
+            context.metrics.connections_opened.with_label_values(&[&direction_label]).inc();
+            Connection::open(
+                context.connection_ids.next(),
+                peer_addr,
+                direction,
+                stream,
+                context.bandwidth.clone(),
+                context.memory.clone(),
+                context.buffers.clone(),
+                context.metrics.clone(),
+                context.wire_log.clone(),
+                move |frames, outbound, info| {
+                    let reading = Self::read_from_peer(context, peer_addr, frames, outbound, info);
+                    async move {
+                        let _permit = permit;
+                        let _record = record;
+                        reading.await
+                    }
+                },
+            )
+            .with_identity(identity)
+            .with_peer_id(peer_id)
         })
     }
 
-    pub async fn listen_to_connection_pool<P: Payload>(
-        &self,
-        event_tx: Sender<(SocketAddr, Message<P>)>,
-    ) -> JoinHandle<()> {
-        info!("Starting connection pool listener thread");
-        let schultz_addr = self.schultz_addr();
-        let all_receivers = self.connection_pool.clone();
-        let chainspec = self.chainspec.clone();
-        let awaiting_reply_from_peers = self.awaiting_hs_reply_from.clone();
-        let fully_connected_peers = self.fully_connected_peers.clone();
-        tokio::spawn(async move {
-            // Polling interval
-            let mut interval = interval(Duration::from_millis(POLLING_RATE));
+    /// Certifies the TLS session with `addr` if we have consensus keys.
+    /// Sessions over transports without TLS cannot be certified.
+    fn consensus_certificate(
+        keys: Option<&ConsensusKeys>,
+        transport: &dyn Transport,
+        addr: SocketAddr,
+    ) -> Option<ConsensusCertificate> {
+        Some(keys?.certify(&transport.session_id(addr)?))
+    }
 
-            loop {
-                // Wait for the polling to happen
-                interval.tick().await;
+    /// Reads and handles frames from a peer until it disconnects.
+    async fn read_from_peer<P: Payload>(
+        context: Arc<ReaderContext<P>>,
+        peer_addr: SocketAddr,
+        mut frames: FrameReader,
+        outbound: OutboundQueue,
+        info: SharedInfo,
+    ) {
+        let consensus_certificate = Self::consensus_certificate(
+            context.consensus_keys.as_ref(),
+            context.transport.as_ref(),
+            peer_addr,
+        );
+        let session_id = context.transport.session_id(peer_addr);
+        loop {
+            // Leave peers over their bandwidth budget unread for now
+            while context.bandwidth.is_read_throttled(&peer_addr) {
+                tokio::time::sleep(Duration::from_millis(POLLING_RATE)).await;
+            }
 
-                for (peer_addr, stream) in all_receivers.lock().await.iter_mut() {
-                    // Split into a bi-directional stream
-                    let (mut writer, mut reader) = stream.split();
+            // Whatever was read before has been handled by now, so the peer
+            // may send more of it.
+            if let Some(demultiplexer) = frames.decoder_mut().demultiplexer() {
+                for (channel, credit) in demultiplexer.take_credit() {
+                    if let Err(e) = outbound.return_credit(channel, credit) {
+                        warn!("Error returning credit to {peer_addr:?}: {e:?}");
+                    }
+                }
+            }
 
-                    if let Ok(Some(msg)) =
-                        tokio::time::timeout(Duration::from_millis(POLLING_RATE), reader.next())
-                            .await
-                    {
-                        match msg {
-                            Ok(bytes_read) => {
-                                Self::handle_incoming_message(
-                                    &schultz_addr,
-                                    &chainspec,
-                                    peer_addr,
-                                    &fully_connected_peers,
-                                    &awaiting_reply_from_peers,
-                                    &event_tx,
-                                    bytes_read,
-                                    &mut writer,
-                                )
-                                .await
-                            }
-                            Err(e) => {
-                                error!("Error reading from client: {:?}", e);
-                            }
-                        }
+            let next = frames.next().await;
+            let corrupt_frames = frames.decoder_mut().take_corrupt_frames();
+            if corrupt_frames > 0 {
+                warn!("Dropped {corrupt_frames} corrupt frames from {peer_addr:?}");
+                context.metrics.corrupt_frames.inc_by(corrupt_frames.into());
+            }
+            let demuxed = match next {
+                Some(Ok(frame)) => {
+                    info.seen();
+                    context.bandwidth.record_read(peer_addr, frame.len());
+                    context.metrics.bytes_read.inc_by(frame.len() as u64);
+                    match frames.decoder_mut().demultiplexer() {
+                        Some(demultiplexer) => demultiplexer.receive(frame),
+                        None => Ok(Demuxed::Message(Channel::Control, frame)),
+                    }
+                }
+                Some(Err(e)) => Err(e),
+                None => {
+                    info!("{peer_addr:?} closed the connection");
+                    context.history.record(peer_addr, EventKind::Closed);
+                    context.metrics.connections_closed.with_label_values(&["closed"]).inc();
+                    return;
+                }
+            };
+            let bytes_read = match demuxed {
+                Ok(Demuxed::Message(_, bytes_read)) => bytes_read,
+                Ok(Demuxed::Fragment) => continue,
+                Ok(Demuxed::Credit(channel, credit)) => {
+                    if let Err(e) = outbound.credit_received(channel, credit) {
+                        warn!("Error passing on credit from {peer_addr:?}: {e:?}");
                     }
+                    continue;
                 }
+                Err(e) => {
+                    error!("Error reading from {peer_addr:?}, closing the connection: {e:?}");
+                    if e.kind() == io::ErrorKind::InvalidData {
+                        context.reputation.record(peer_addr, Behavior::ProtocolViolation);
+                    }
+                    let reason = if CorruptFrame::is(&e) {
+                        context.metrics.corrupt_frames.inc();
+                        "corrupt"
+                    } else {
+                        "closed"
+                    };
+                    context.history.record(peer_addr, EventKind::Closed);
+                    context.metrics.connections_closed.with_label_values(&[reason]).inc();
+                    return;
+                }
+            };
+
+            if let Some(wire_log) = &context.wire_log {
+                wire_log.record(Direction::Inbound, peer_addr, &bytes_read);
             }
-        })
+            if context.observed.receiver_count() > 0 {
+                if let Some(observed) = Observed::parse(peer_addr, &bytes_read) {
+                    // Nobody may be listening anymore, which is fine.
+                    let _ = context.observed.send(observed);
+                }
+            }
+
+            let handled = Self::handle_incoming_message(
+                &context.schultz_addr,
+                &context.chainspec,
+                &context.config,
+                &peer_addr,
+                &context.fully_connected_peers,
+                &context.awaiting_reply_from_peers,
+                &context.last_handshake,
+                &context.liveness,
+                &context.memory,
+                &context.metrics,
+                &context.reputation,
+                &context.event_tx,
+                bytes_read,
+                &mut frames,
+                &outbound,
+                &info,
+                consensus_certificate.as_ref(),
+                context.consensus_keys.as_ref(),
+                session_id.as_ref(),
+                &context.replay_guard,
+                &context.eras,
+            )
+            .await;
+
+            if let Err(e) = handled {
+                warn!(
+                    "Disconnecting {peer_addr:?} on connection {}: {e}",
+                    outbound.id()
+                );
+                let grace = match e {
+                    Disconnect::OverBudget(_) => {
+                        context.metrics.peers_over_memory_budget.inc();
+                        context.reputation.record(peer_addr, Behavior::ProtocolViolation);
+                        Duration::ZERO
+                    }
+                    Disconnect::Rejected(_) => REJECTED_PEER_GRACE,
+                };
+                // Dropping the connection stops this very task, so leave it
+                // to another one.
+                let context = context.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(grace).await;
+                    Self::drop_peer(
+                        &context.connection_pool,
+                        &context.fully_connected_peers,
+                        &context.bandwidth,
+                        &context.liveness,
+                        &context.metrics,
+                        &context.history,
+                        peer_addr,
+                    )
+                    .await
+                });
+                return;
+            }
+        }
+    }
+
+    /// Hands a message to the event loop, charging it to the peer's memory
+    /// budget until the event loop is done with it.
+    async fn forward<P: Payload>(
+        memory: &Arc<MemoryBudget>,
+        event_tx: &Sender<Event<P>>,
+        peer_addr: SocketAddr,
+        connection: ConnectionId,
+        message: Message<P>,
+    ) -> Result<(), OverBudget> {
+        let reservation = memory.reserve(peer_addr, datasize::data_size(&message))?;
+        let _ = event_tx.send((peer_addr, connection, message, reservation)).await;
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        name = "incoming_message",
+        skip_all,
+        fields(peer = %peer_addr, len = bytes_read.len())
+    )]
     pub async fn handle_incoming_message<P: Payload>(
         schultz_addr: &SocketAddr,
         chainspec: &Chainspec,
+        config: &Config,
         peer_addr: &SocketAddr,
         fully_connected_peers: &Arc<Mutex<Vec<SocketAddr>>>,
-        awaiting_reply_from_peers: &Arc<Mutex<Vec<SocketAddr>>>,
-        event_tx: &Sender<(SocketAddr, Message<P>)>,
-        bytes_read: BytesMut,
-        writer: &mut SplitSink<&mut Framed<SslStream<TcpStream>, LengthDelimitedCodec>, Bytes>,
-    ) {
-        let mut encoder = MessagePackFormat;
+        awaiting_reply_from_peers: &AwaitingHandshakes,
+        last_handshake: &LastHandshake,
+        liveness: &LivenessMap,
+        memory: &Arc<MemoryBudget>,
+        metrics: &Metrics,
+        reputation: &Reputation,
+        event_tx: &Sender<Event<P>>,
+        bytes_read: Bytes,
+        frames: &mut FrameReader,
+        outbound: &OutboundQueue,
+        info: &SharedInfo,
+        consensus_certificate: Option<&ConsensusCertificate>,
+        consensus_keys: Option<&ConsensusKeys>,
+        session_id: Option<&SessionId>,
+        replay_guard: &ReplayGuard,
+        eras: &EraTracker,
+    ) -> Result<(), Disconnect> {
         let remote_message: Result<Message<P>, io::Error> =
-            Pin::new(&mut encoder).deserialize(&bytes_read);
+            MessagePackFormat.deserialize_arbitrary(&bytes_read);
 
         if let Ok(msg) = remote_message {
-            match &msg {
-                Message::Handshake {
-                    network_name,
-                    protocol_version,
-                    chainspec_hash,
-                    ..
-                } => {
+            match Handshake::from_message(&msg) {
+                Some(handshake) => {
                     Self::handle_handshake_message(
                         &msg,
-                        network_name,
-                        protocol_version,
-                        chainspec_hash,
+                        handshake,
                         schultz_addr,
                         chainspec,
+                        config,
                         peer_addr,
                         fully_connected_peers,
                         awaiting_reply_from_peers,
+                        last_handshake,
+                        memory,
+                        metrics,
+                        reputation,
                         event_tx,
-                        writer,
+                        frames,
+                        outbound,
+                        info,
+                        consensus_certificate,
+                        consensus_keys,
+                        session_id,
+                        replay_guard,
+                        eras,
                     )
-                    .await;
+                    .await
                 }
-                _ => {
+                None => {
                     info!("Ignoring post-handshake traffic from Casper");
+                    Ok(())
                 }
             }
         } else {
@@ -588,143 +1552,249 @@ impl Manager {
             // designed to handle those situations gracefully.
             trace!("BYTES FROM CASPER {bytes_read:?}");
 
-            let mut bincode_fmt = BincodeFormat::default();
-
-            let _: Message<P> = match Pin::new(&mut bincode_fmt).deserialize(&bytes_read) {
-                Ok(message) => {
-                    let _ = event_tx.send((*peer_addr, message.clone())).await;
-                    message
-                }
+            let decoded = BincodeFormat::default().deserialize_arbitrary(&bytes_read);
+            let message: Message<P> = match decoded {
+                Ok(message) => message,
                 Err(e) => {
                     warn!("Error deserializing {e:?}");
                     warn!(
                         "Received an internal message from Casper. Ignoring the deserialization \
                          error"
                     );
-                    return;
+                    return Ok(());
                 }
             };
+
+            match message {
+                Message::Ping { nonce } => {
+                    // Answer right away so peers keep us connected.
+                    match Self::encode_bincode::<P>(Message::Pong { nonce }) {
+                        Ok(pong) => {
+                            if let Err(e) = outbound.send(pong).await {
+                                error!("Error sending pong to {peer_addr:?}: {e:?}");
+                            }
+                        }
+                        Err(e) => error!("Error serializing pong: {e:?}"),
+                    }
+                }
+                Message::Pong { nonce } => {
+                    let latency = liveness
+                        .lock()
+                        .await
+                        .get_mut(peer_addr)
+                        .and_then(|peer| peer.pong_received(nonce, Instant::now()));
+                    if let Some(latency) = latency {
+                        trace!("Round-trip time to {peer_addr:?} is {latency:?}");
+                        reputation.record(*peer_addr, Behavior::Latency(latency));
+                        metrics.pongs_received.inc();
+                        metrics
+                            .peer_latency
+                            .with_label_values(&[
+                                &peer_addr.to_string(),
+                                &outbound.id().to_string(),
+                            ])
+                            .set(latency.as_secs_f64());
+                    }
+                }
+                _ => {}
+            }
+
+            Self::forward(memory, event_tx, *peer_addr, outbound.id(), message).await?;
+            reputation.record(*peer_addr, Behavior::Exchange);
+            Ok(())
         }
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        name = "protocol_handshake",
+        skip_all,
+        fields(peer = %peer_addr, network = %handshake.network_name)
+    )]
     async fn handle_handshake_message<P: Payload>(
         msg: &Message<P>,
-        network_name: &String,
-        protocol_version: &ProtocolVersion,
-        chainspec_hash: &Option<Digest>,
+        handshake: Handshake,
         schultz_addr: &SocketAddr,
         chainspec: &Chainspec,
+        config: &Config,
         peer_addr: &SocketAddr,
         fully_connected_peers: &Arc<Mutex<Vec<SocketAddr>>>,
-        awaiting_reply_from_peers: &Arc<Mutex<Vec<SocketAddr>>>,
-        event_tx: &Sender<(SocketAddr, Message<P>)>,
-        writer: &mut SplitSink<&mut Framed<SslStream<TcpStream>, LengthDelimitedCodec>, Bytes>,
-    ) {
+        awaiting_reply_from_peers: &AwaitingHandshakes,
+        last_handshake: &LastHandshake,
+        memory: &Arc<MemoryBudget>,
+        metrics: &Metrics,
+        reputation: &Reputation,
+        event_tx: &Sender<Event<P>>,
+        frames: &mut FrameReader,
+        outbound: &OutboundQueue,
+        info: &SharedInfo,
+        consensus_certificate: Option<&ConsensusCertificate>,
+        consensus_keys: Option<&ConsensusKeys>,
+        session_id: Option<&SessionId>,
+        replay_guard: &ReplayGuard,
+        eras: &EraTracker,
+    ) -> Result<(), Disconnect> {
         if fully_connected_peers.lock().await.contains(peer_addr) {
             info!("Finished handshake to {peer_addr:?}. Ignoring redundant Handshakes");
-            return;
+            return Ok(());
         }
 
-        if awaiting_reply_from_peers.lock().await.contains(peer_addr) {
-            info!("Received handshake from the contacted peer");
-
-            if Self::is_valid_handshake_parameters(
-                network_name,
-                protocol_version,
-                chainspec_hash,
-                chainspec,
-            ) {
-                info!("Handshake complete! Successfully connected to peer {peer_addr:?}");
+        let outcome =
+            handshake.negotiate(chainspec, config.allow_version_mismatch).and_then(|()| {
+                handshake.check_stamp(
+                    session_id,
+                    config.handshake_max_age,
+                    config.accept_unstamped_certificates,
+                    replay_guard,
+                )
+            });
+        let validator = handshake
+            .consensus_certificate
+            .as_ref()
+            .map(|certificate| eras.is_validator(certificate.public_key()));
+        if let (Ok(()), Some(true)) = (&outcome, validator) {
+            info!("{peer_addr:?} is a validator of the current era");
+        }
+        *last_handshake.lock().await =
+            Some(HandshakeResult::new(*peer_addr, outcome.as_ref()).with_validator(validator));
+        let outcome_label = if outcome.is_ok() {
+            "completed"
+        } else {
+            "rejected"
+        };
+        metrics.handshakes.with_label_values(&[outcome_label]).inc();
+        let compression = Compression::negotiate(&config.compression, &handshake.compression);
+        let multiplexing = config.multiplexing && handshake.multiplexing;
+        let checksums =
+            (config.checksums && handshake.checksums).then_some(config.max_corrupt_frames);
 
-                // Remove the peer since we are through with the
-                // handshake.
-                awaiting_reply_from_peers.lock().await.retain(|addr| addr != peer_addr);
+        if let Some(reply_tx) = awaiting_reply_from_peers.lock().await.remove(peer_addr) {
+            info!("Received handshake from the contacted peer");
 
-                fully_connected_peers.lock().await.push(*peer_addr);
-                return;
-            } else {
-                error!("Error connecting to peer: Bad Handshake parameters");
-                return;
+            match &outcome {
+                Ok(()) => {
+                    info!("Handshake complete! Successfully connected to peer {peer_addr:?}");
+                    info.handshake_completed(handshake.protocol_version);
+                    fully_connected_peers.lock().await.push(*peer_addr);
+                    Self::enable_compression(frames, outbound, peer_addr, compression).await;
+                    Self::enable_multiplexing(frames, outbound, peer_addr, multiplexing).await;
+                    Self::enable_checksums(frames, outbound, peer_addr, checksums).await;
+                }
+                Err(e) => error!("Error connecting to peer {peer_addr:?}: {e}"),
             }
-        }
 
-        if !Self::is_valid_handshake_parameters(
-            network_name,
-            protocol_version,
-            chainspec_hash,
-            chainspec,
-        ) {
-            error!("Error connecting to peer: Bad Handshake parameters");
-            return;
+            // The waiting side may have timed out already, nothing to do then.
+            let _ = reply_tx.send(outcome.map(|()| handshake));
+            return Ok(());
         }
 
-        // Notify the event loop
-        let _ = event_tx.send((*peer_addr, msg.clone())).await;
-
-        // Send back a handshake message on the same stream
-        let hs: Message<P> = Message::Handshake {
-            network_name: chainspec.network_config.name.clone(),
-            public_addr: *schultz_addr,
-            protocol_version: chainspec.protocol_version(),
-            consensus_certificate: None, // not required
-            is_syncing: false,           // not required
-            chainspec_hash: Some(chainspec.hash()),
-        };
+        // Send back a handshake message on the same stream. This happens even
+        // when we reject the peer, so it can report the mismatch on its side.
+        let hs = Handshake::new(chainspec, *schultz_addr)
+            .with_compression(config.compression.clone())
+            .with_multiplexing(config.multiplexing)
+            .with_checksums(config.checksums)
+            .with_consensus_certificate(consensus_certificate.cloned())
+            .stamped_with(consensus_keys, session_id);
 
         info!("Sending Handshake to Casper");
         trace!("{hs:?}");
 
         // Serialize schultz handshake
-        match Pin::new(&mut MessagePackFormat)
-            .serialize(&Arc::new(hs))
-            .map_err(|e| ManagerError::CouldNotEncodeOurHandshake(e.to_string()))
-        {
+        match hs.encode::<P>() {
             Ok(bytes) => {
-                if let Err(e) = writer.send(bytes).await {
+                if let Err(e) = outbound.send(bytes).await {
                     error!("Error sending handshake to CASPER!: {e:?}");
                 }
-                fully_connected_peers.lock().await.push(*peer_addr);
             }
             Err(e) => {
                 error!("Error serializing handshake for Casper!: {e:?}");
+                return Ok(());
             }
         }
-    }
 
-    fn is_valid_handshake_parameters(
-        network_name: &String,
-        protocol_version: &ProtocolVersion,
-        chainspec_hash: &Option<Digest>,
-        chainspec: &Chainspec,
-    ) -> bool {
-        // Check if the handshake is from the correct network
-        if network_name != &chainspec.network_config.name {
-            error!("Network name in handshake did not match schultz network");
-            return false;
+        if let Err(e) = outcome {
+            error!(
+                code = e.code(),
+                "Rejecting handshake from {peer_addr:?}: {e}"
+            );
+            let behavior = if e.is_peer_fault() {
+                Behavior::ProtocolViolation
+            } else {
+                Behavior::HandshakeFailed
+            };
+            reputation.record(*peer_addr, behavior);
+            return Err(Disconnect::Rejected(e));
         }
 
-        // Check if the peer is the correct protocol version
-        if protocol_version != &chainspec.protocol_config.version {
-            error!("ProtocolVersion in handshake did not match schultz protocol version");
-            return false;
-        }
+        reputation.record(*peer_addr, Behavior::HandshakeCompleted);
+        info.handshake_completed(handshake.protocol_version);
+        fully_connected_peers.lock().await.push(*peer_addr);
+        // Our handshake is queued uncompressed and whole, everything after it
+        // may not be.
+        Self::enable_compression(frames, outbound, peer_addr, compression).await;
+        Self::enable_multiplexing(frames, outbound, peer_addr, multiplexing).await;
+        Self::enable_checksums(frames, outbound, peer_addr, checksums).await;
 
-        // Check if the peer has Chainspec config. This should be true because we have
-        // a protocol version at this point.
-        let peer_chainspec_hash = if let Some(hash) = chainspec_hash {
-            hash
-        } else {
-            error!("Chainspec hash missing from handshake.");
-            return false;
-        };
+        // Notify the event loop
+        Ok(Self::forward(memory, event_tx, *peer_addr, outbound.id(), msg.clone()).await?)
+    }
 
-        // Check if the peer has the same Chainspec config hash
-        if peer_chainspec_hash != &chainspec.hash() {
-            error!("Chainspec hash in handshake did not match schultz chainspec.");
-            return false;
+    /// Switches both directions of a connection to compressed frames.
+    ///
+    /// Frames already queued go out as they are.
+    async fn enable_compression(
+        frames: &mut FrameReader,
+        outbound: &OutboundQueue,
+        peer_addr: &SocketAddr,
+        compression: Option<Compression>,
+    ) {
+        if let Some(compression) = compression {
+            frames.decoder_mut().enable_compression(compression);
+            if let Err(e) = outbound.enable_compression(compression).await {
+                error!("Error enabling compression to {peer_addr:?}: {e:?}");
+            }
         }
+    }
 
-        true
+    /// Switches both directions of a connection to multiplexed frames.
+    ///
+    /// Enabled after compression, so fragments are compressed on their own
+    /// rather than messages before being cut.
+    async fn enable_multiplexing(
+        frames: &mut FrameReader,
+        outbound: &OutboundQueue,
+        peer_addr: &SocketAddr,
+        multiplexing: bool,
+    ) {
+        if multiplexing {
+            frames.decoder_mut().enable_multiplexing();
+            if let Err(e) = outbound.enable_multiplexing().await {
+                error!("Error enabling multiplexing to {peer_addr:?}: {e:?}");
+            }
+        }
+    }
+
+    /// Switches both directions of a connection to frames ending with a
+    /// checksum, dropping up to `checksums` corrupt frames read before
+    /// closing it.
+    ///
+    /// Frames on other channels may overtake the switch on either side, so
+    /// each side marks in the stream where its checksums start and the
+    /// other checks them from that marker on, see
+    /// [`FrameCodec::send_checksums`](super::compression::FrameCodec::send_checksums).
+    /// Enabled last, so the checksum covers the frame as it goes out.
+    async fn enable_checksums(
+        frames: &mut FrameReader,
+        outbound: &OutboundQueue,
+        peer_addr: &SocketAddr,
+        checksums: Option<u32>,
+    ) {
+        if let Some(max_corrupt_frames) = checksums {
+            frames.decoder_mut().expect_checksums(max_corrupt_frames);
+            if let Err(e) = outbound.enable_checksums().await {
+                error!("Error enabling checksums to {peer_addr:?}: {e:?}");
+            }
+        }
     }
 }
@@ -0,0 +1,255 @@
+//! Per-peer memory accounting.
+//!
+//! Messages read from a peer are charged to it, by their `DataSize` estimate,
+//! until the event loop is done with them; frames queued for a peer are
+//! charged until they are written. Each peer may only hold so much at a time:
+//! one sending more than we get through is disconnected, and frames that would
+//! push a slow reader over its budget are refused.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use thiserror::Error;
+
+use super::bandwidth::unit_multiplier;
+
+/// An amount of memory, written like `64MiB` or `500KB`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(u64);
+
+impl ByteSize {
+    pub const fn from_bytes(bytes: u64) -> Self { Self(bytes) }
+
+    pub fn bytes(&self) -> u64 { self.0 }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseByteSizeError {
+    #[error("missing amount in size {0:?}")]
+    MissingAmount(String),
+    #[error("unknown unit {0:?}, expected one of B, KB, MB, GB, KiB, MiB or GiB")]
+    UnknownUnit(String),
+    #[error("size must be greater than zero")]
+    Zero,
+    #[error("size {0:?} is too large")]
+    Overflow(String),
+}
+
+impl FromStr for ByteSize {
+    type Err = ParseByteSizeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        if amount.is_empty() {
+            return Err(ParseByteSizeError::MissingAmount(value.to_string()));
+        }
+
+        let unit = unit.trim();
+        let multiplier = unit_multiplier(unit)
+            .ok_or_else(|| ParseByteSizeError::UnknownUnit(unit.to_string()))?;
+
+        let bytes = amount
+            .parse::<u64>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(multiplier))
+            .ok_or_else(|| ParseByteSizeError::Overflow(value.to_string()))?;
+        if bytes == 0 {
+            return Err(ParseByteSizeError::Zero);
+        }
+
+        Ok(ByteSize(bytes))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (unit, size) in [("GiB", 1 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)] {
+            if self.0.is_multiple_of(size) {
+                return write!(f, "{}{unit}", self.0 / size);
+            }
+        }
+        write!(f, "{}B", self.0)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A peer would go over its memory budget.
+#[derive(Debug, Error, PartialEq, Eq, Serialize)]
+#[error("{peer} would hold {in_use} + {requested} bytes, over its budget of {limit}")]
+pub struct OverBudget {
+    pub peer: SocketAddr,
+    pub requested: usize,
+    pub in_use: usize,
+    pub limit: ByteSize,
+}
+
+/// Bytes currently held on behalf of each peer.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: ByteSize,
+    in_use: Mutex<BTreeMap<SocketAddr, usize>>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: ByteSize) -> Self {
+        Self {
+            limit,
+            in_use: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Charges `bytes` to `peer` until the returned reservation is dropped.
+    ///
+    /// Fails, charging nothing, if the peer would go over the budget. A single
+    /// charge larger than the whole budget is let through while the peer
+    /// holds nothing else, so no message is refused for its size alone.
+    pub fn reserve(
+        self: &Arc<Self>,
+        peer: SocketAddr,
+        bytes: usize,
+    ) -> Result<Reservation, OverBudget> {
+        let mut in_use = self.in_use.lock().expect("memory budget lock poisoned");
+        let held = in_use.get(&peer).copied().unwrap_or_default();
+        let limit = usize::try_from(self.limit.bytes()).unwrap_or(usize::MAX);
+        if held > 0 && held.saturating_add(bytes) > limit {
+            return Err(OverBudget {
+                peer,
+                requested: bytes,
+                in_use: held,
+                limit: self.limit,
+            });
+        }
+
+        *in_use.entry(peer).or_default() += bytes;
+        Ok(Reservation {
+            budget: self.clone(),
+            peer,
+            bytes,
+        })
+    }
+
+    /// Bytes currently charged to `peer`.
+    pub fn in_use(&self, peer: &SocketAddr) -> usize {
+        let in_use = self.in_use.lock().expect("memory budget lock poisoned");
+        in_use.get(peer).copied().unwrap_or_default()
+    }
+
+    /// Bytes currently charged to all peers together.
+    pub fn total(&self) -> usize {
+        let in_use = self.in_use.lock().expect("memory budget lock poisoned");
+        in_use.values().sum()
+    }
+
+    /// Every peer charged anything, with what it holds, the most first.
+    pub fn largest(&self) -> Vec<(SocketAddr, usize)> {
+        let in_use = self.in_use.lock().expect("memory budget lock poisoned");
+        let mut largest: Vec<_> = in_use.iter().map(|(peer, bytes)| (*peer, *bytes)).collect();
+        largest.sort_by(|(_, a), (_, b)| b.cmp(a));
+        largest
+    }
+
+    fn release(&self, peer: SocketAddr, bytes: usize) {
+        let mut in_use = self.in_use.lock().expect("memory budget lock poisoned");
+        if let Some(held) = in_use.get_mut(&peer) {
+            *held = held.saturating_sub(bytes);
+            if *held == 0 {
+                in_use.remove(&peer);
+            }
+        }
+    }
+}
+
+/// Memory charged to a peer, given back when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    peer: SocketAddr,
+    bytes: usize,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) { self.budget.release(self.peer, self.bytes); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!("64MiB".parse(), Ok(ByteSize(64 << 20)));
+        assert_eq!(" 500KB ".parse(), Ok(ByteSize(500_000)));
+        assert_eq!(ByteSize(64 << 20).to_string(), "64MiB");
+        assert_eq!(ByteSize(1500).to_string(), "1500B");
+
+        assert!(matches!(
+            "5MBps".parse::<ByteSize>(),
+            Err(ParseByteSizeError::UnknownUnit(_))
+        ));
+        assert_eq!("0B".parse::<ByteSize>(), Err(ParseByteSizeError::Zero));
+    }
+
+    #[test]
+    fn reservations_are_capped_per_peer_and_released_on_drop() {
+        let budget = Arc::new(MemoryBudget::new(ByteSize(1000)));
+
+        let first = budget.reserve(peer(1), 600).unwrap();
+        let error = budget.reserve(peer(1), 600).unwrap_err();
+        assert_eq!(error.in_use, 600);
+        // Other peers have budgets of their own.
+        let _other = budget.reserve(peer(2), 600).unwrap();
+
+        drop(first);
+        assert_eq!(budget.in_use(&peer(1)), 0);
+        let _second = budget.reserve(peer(1), 600).unwrap();
+        assert_eq!(budget.in_use(&peer(1)), 600);
+    }
+
+    #[test]
+    fn totals_what_peers_hold_the_most_first() {
+        let budget = Arc::new(MemoryBudget::new(ByteSize(1000)));
+        let _first = budget.reserve(peer(1), 300).unwrap();
+        let _second = budget.reserve(peer(2), 700).unwrap();
+        let third = budget.reserve(peer(3), 500).unwrap();
+        assert_eq!(budget.total(), 1500);
+        assert_eq!(
+            budget.largest(),
+            [(peer(2), 700), (peer(3), 500), (peer(1), 300)]
+        );
+        drop(third);
+        assert_eq!(budget.total(), 1000);
+        assert_eq!(budget.largest(), [(peer(2), 700), (peer(1), 300)]);
+    }
+
+    #[test]
+    fn lets_a_single_oversized_message_through() {
+        let budget = Arc::new(MemoryBudget::new(ByteSize(1000)));
+        let _large = budget.reserve(peer(1), 5000).unwrap();
+        assert!(budget.reserve(peer(1), 1).is_err());
+    }
+}
@@ -3,7 +3,6 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::io;
-use std::io::Cursor;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -22,52 +21,23 @@ use casper_types::AsymmetricType;
 use casper_types::ProtocolVersion;
 use casper_types::PublicKey;
 use casper_types::Signature;
-use futures::SinkExt;
-use futures::StreamExt;
+use casper_types::Timestamp;
+use datasize::DataSize;
+use serde::de::DeserializeOwned;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
 use serde::Serializer;
 use strum::EnumDiscriminants;
-use tokio::net::TcpStream;
-use tokio_openssl::SslStream;
 use tokio_serde::Deserializer as TokioDeserializer;
 use tokio_serde::Serializer as TokioSerializer;
-use tokio_util::codec::LengthDelimitedCodec;
 
-use super::error::ManagerError;
+use super::compression::Compression;
+use super::tls::SessionId;
 use crate::primitives::Nonce;
 use crate::utils::OptDisplay;
 
-/// Transport type alias for base encrypted connections.
-type Transport = SslStream<TcpStream>;
-pub type FramedTransport = tokio_util::codec::Framed<Transport, LengthDelimitedCodec>;
-
-/// A thin wrapper over bytes to impl Payload Trait
-pub struct SchultzMessage {
-    payload: Bytes,
-}
-
-impl SchultzMessage {
-    pub fn new(payload: Bytes) -> Result<Self, ManagerError> { Ok(SchultzMessage { payload }) }
-
-    // Helper to write SchultzMessage bytes to the provided stream.
-    pub async fn write_to_stream(&self, stream: &mut FramedTransport) -> Result<(), ManagerError> {
-        let SchultzMessage { payload, .. } = self;
-
-        let (mut writer, mut _reader) = stream.split();
-
-        // Send bytes of TcpStream
-        writer
-            .send(payload.clone())
-            .await
-            .map_err(|e| ManagerError::SendFailed(e.to_string()))?;
-
-        Ok(())
-    }
-}
-
 /// Certificate used to indicate that the peer is a validator using the
 /// specified public key.
 ///
@@ -75,12 +45,105 @@ impl SchultzMessage {
 /// to allow the `public_key` and `signature` fields to be encoded to
 /// all-lowercase hex, hence circumventing the checksummed-hex encoding used by
 /// `PublicKey` and `Signature` in versions 1.4.2 and 1.4.3.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, DataSize)]
 pub struct ConsensusCertificate {
     public_key: PublicKey,
     signature: Signature,
 }
 
+impl ConsensusCertificate {
+    pub fn new(public_key: PublicKey, signature: Signature) -> Self {
+        Self {
+            public_key,
+            signature,
+        }
+    }
+
+    /// Key of the validator the sender claims to be.
+    pub fn public_key(&self) -> &PublicKey { &self.public_key }
+
+    /// Checks that the certificate signs `session_id`, i.e. that the sender
+    /// holds the secret key and made it for this very session.
+    pub fn validate(&self, session_id: &SessionId) -> Result<(), casper_types::crypto::Error> {
+        casper_types::crypto::verify(session_id.as_bytes(), &self.signature, &self.public_key)
+    }
+}
+
+/// What a [`HandshakeStamp`] vouches for besides when it was made: the TLS
+/// session the handshake is sent over and what it tells of the sender.
+#[derive(Clone, Copy, Debug)]
+pub struct StampedHandshake<'a> {
+    pub session_id: &'a SessionId,
+    pub network_name: &'a str,
+    pub public_addr: SocketAddr,
+    pub chainspec_hash: Option<Digest>,
+}
+
+/// Proof that a handshake was made just now, for the session it is sent
+/// over, by the validator its consensus certificate names, so it can neither
+/// be replayed later nor passed on to another session.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, DataSize)]
+pub struct HandshakeStamp {
+    nonce: Nonce,
+    #[data_size(skip)]
+    timestamp: Timestamp,
+    signature: Signature,
+}
+
+impl HandshakeStamp {
+    pub fn new(nonce: Nonce, timestamp: Timestamp, signature: Signature) -> Self {
+        Self {
+            nonce,
+            timestamp,
+            signature,
+        }
+    }
+
+    /// The bytes a stamp of `handshake` with `nonce` and `timestamp` signs.
+    pub fn signed_bytes(
+        nonce: Nonce,
+        timestamp: Timestamp,
+        handshake: &StampedHandshake,
+    ) -> Vec<u8> {
+        let mut bytes = b"schultz-handshake-stamp".to_vec();
+        bytes.extend_from_slice(&nonce.value().to_le_bytes());
+        bytes.extend_from_slice(&timestamp.millis().to_le_bytes());
+        bytes.extend_from_slice(handshake.session_id.as_bytes());
+        // Prefixed with its length, so no two names and addresses sign alike.
+        let network_name = handshake.network_name.as_bytes();
+        bytes.extend_from_slice(&(network_name.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(network_name);
+        let public_addr = handshake.public_addr.to_string();
+        bytes.extend_from_slice(&(public_addr.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(public_addr.as_bytes());
+        match handshake.chainspec_hash {
+            Some(hash) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&hash.value());
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// Random for every handshake.
+    pub fn nonce(&self) -> Nonce { self.nonce }
+
+    /// When the handshake was made, by the sender's clock.
+    pub fn timestamp(&self) -> Timestamp { self.timestamp }
+
+    /// Checks that the holder of `public_key` signed the nonce and timestamp
+    /// for `handshake`.
+    pub fn validate(
+        &self,
+        public_key: &PublicKey,
+        handshake: &StampedHandshake,
+    ) -> Result<(), casper_types::crypto::Error> {
+        let bytes = Self::signed_bytes(self.nonce, self.timestamp, handshake);
+        casper_types::crypto::verify(bytes, &self.signature, public_key)
+    }
+}
+
 impl Display for ConsensusCertificate {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "key:{}", self.public_key) }
 }
@@ -89,6 +152,21 @@ impl Display for ConsensusCertificate {
 #[derive(Debug)]
 pub struct MessagePackFormat;
 
+impl MessagePackFormat {
+    /// Deserializes an arbitrary value from `bytes`, for frames that are not
+    /// in a [`BytesMut`].
+    #[inline]
+    pub fn deserialize_arbitrary<T>(&self, bytes: &[u8]) -> io::Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        // Decode from the slice rather than a reader: the reader allocates
+        // whatever length a string claims before reading it, so a few bytes
+        // could ask for gigabytes.
+        rmp_serde::from_slice(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
 impl<M> TokioSerializer<M> for MessagePackFormat
 where
     M: Serialize,
@@ -113,12 +191,11 @@ where
 
     #[inline]
     fn deserialize(self: Pin<&mut Self>, src: &BytesMut) -> Result<M, Self::Error> {
-        rmp_serde::from_read(Cursor::new(src))
-            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        self.deserialize_arbitrary(src)
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, EnumDiscriminants)]
+#[derive(Clone, Debug, Deserialize, Serialize, EnumDiscriminants, DataSize)]
 #[strum_discriminants(derive(strum::EnumIter))]
 #[allow(clippy::large_enum_variant)]
 pub enum Message<P> {
@@ -139,6 +216,31 @@ pub enum Message<P> {
         /// Hash of the chainspec the node is running.
         #[serde(default)]
         chainspec_hash: Option<Digest>,
+        /// Frame compression algorithms the node supports, a schultz
+        /// extension.
+        ///
+        /// Fields are encoded by position, so schultz extensions are only
+        /// left out from the end: this one is written even when empty, so
+        /// `multiplexing` is not taken for it. Casper nodes do receive them,
+        /// and only get along because rmp-serde 0.14 leaves the elements of
+        /// an array past the fields it expects unread, which
+        /// `casper_nodes_decode_our_handshakes` checks.
+        #[serde(default)]
+        compression: Vec<Compression>,
+        /// Whether the node multiplexes channels over the connection, a
+        /// schultz extension. Written even when false, so `checksums` is not
+        /// taken for it.
+        #[serde(default)]
+        multiplexing: bool,
+        /// Whether the node ends its frames with a checksum, a schultz
+        /// extension. Written even when false, so `stamp` is not taken for
+        /// it.
+        #[serde(default)]
+        checksums: bool,
+        /// Proof that a validator's handshake is not replayed, a schultz
+        /// extension left out for everyone else.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stamp: Option<HandshakeStamp>,
     },
     /// A ping request.
     Ping {
@@ -153,6 +255,36 @@ pub enum Message<P> {
     Payload(P),
 }
 
+/// What a message is routed to a handler by: its variant, and for payloads
+/// the kind of payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Route<K> {
+    Handshake,
+    Ping,
+    Pong,
+    Payload(K),
+}
+
+/// A payload that tells which kind it is, so handlers can be registered for
+/// each kind separately.
+pub trait Routable {
+    type Kind: Copy + Debug + Ord + Send + Sync + 'static;
+
+    fn kind(&self) -> Self::Kind;
+}
+
+impl<P: Routable> Message<P> {
+    /// Where the message is dispatched to.
+    pub fn route(&self) -> Route<P::Kind> {
+        match self {
+            Message::Handshake { .. } => Route::Handshake,
+            Message::Ping { .. } => Route::Ping,
+            Message::Pong { .. } => Route::Pong,
+            Message::Payload(payload) => Route::Payload(payload.kind()),
+        }
+    }
+}
+
 /// The default protocol version to use in absence of one in the protocol
 /// version field.
 #[inline]
@@ -246,6 +378,7 @@ impl<P: Display> Display for Message<P> {
                 consensus_certificate,
                 is_syncing,
                 chainspec_hash,
+                ..
             } => {
                 write!(
                     f,
@@ -295,6 +428,18 @@ impl BincodeFormat {
             .serialize(item)
             .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
     }
+
+    /// Deserializes an arbitrary value serialized with
+    /// [`BincodeFormat::serialize_arbitrary`].
+    #[inline]
+    pub fn deserialize_arbitrary<T>(&self, bytes: &[u8]) -> io::Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.0
+            .deserialize(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
 }
 
 impl Debug for BincodeFormat {
@@ -0,0 +1,255 @@
+//! Prometheus metrics of the network manager.
+
+use prometheus::GaugeVec;
+use prometheus::IntCounter;
+use prometheus::IntCounterVec;
+use prometheus::IntGauge;
+use prometheus::IntGaugeVec;
+use prometheus::Opts;
+use prometheus::Registry;
+
+/// Network manager metrics.
+#[derive(Debug)]
+pub struct Metrics {
+    /// Number of pings sent.
+    pub(super) pings_sent: IntCounter,
+    /// Number of pongs received in answer to one of our pings.
+    pub(super) pongs_received: IntCounter,
+    /// Number of peers dropped for leaving too many pings unanswered.
+    pub(super) peers_timed_out: IntCounter,
+    /// Number of bytes read from peers.
+    pub(super) bytes_read: IntCounter,
+    /// Number of bytes written to peers.
+    pub(super) bytes_written: IntCounter,
+    /// Last measured round-trip time per peer and connection, in seconds.
+    pub(super) peer_latency: GaugeVec,
+    /// Number of peers dropped for going over their memory budget.
+    pub(super) peers_over_memory_budget: IntCounter,
+    /// Number of frames that found their peer's outbound queue full.
+    pub(super) outbound_queue_full: IntCounter,
+    /// Number of senders currently waiting for room in an outbound queue.
+    pub(super) outbound_queue_waiting: IntGauge,
+    /// Number of frames queued for peers, by channel, including the ones
+    /// waiting for room.
+    pub(super) outbound_queue_depth: IntGaugeVec,
+    /// Number of frames dropped as their peer's queue for the channel was
+    /// full, by channel.
+    pub(super) outbound_frames_dropped: IntCounterVec,
+    /// Number of TLS handshakes completed, by direction and the version,
+    /// cipher suite and group they settled on.
+    pub(super) tls_handshakes: IntCounterVec,
+    /// Number of times a connection limit was hit, by limit: dials waiting
+    /// for a slot, and connections refused.
+    pub(super) connection_limit_hits: IntCounterVec,
+    /// Number of connections set up, by direction.
+    pub(super) connections_opened: IntCounterVec,
+    /// Number of connections gone, by whether the peer closed them or we
+    /// dropped them.
+    pub(super) connections_closed: IntCounterVec,
+    /// Number of protocol handshakes, by outcome: completed, rejected by
+    /// either side, or timed out waiting for the peer's.
+    pub(super) handshakes: IntCounterVec,
+    /// Number of frames read from peers that failed their checksum.
+    pub(super) corrupt_frames: IntCounter,
+    /// Number of failed attempts to connect to or handshake with a peer, by
+    /// cause and detail, see [`FailureCause`](super::error::FailureCause).
+    pub(super) connection_failures: IntCounterVec,
+    /// Number of connection buffers taken from the pool.
+    pub(super) buffers_reused: IntCounter,
+    /// Number of connection buffers allocated as the pool had none.
+    pub(super) buffers_allocated: IntCounter,
+    /// Number of buffers in the pool.
+    pub(super) idle_buffers: IntGauge,
+    /// Bytes held by the buffers in the pool.
+    pub(super) idle_buffer_bytes: IntGauge,
+    /// Registry the metrics are registered with, for unregistering on drop.
+    registry: Registry,
+}
+
+impl Metrics {
+    /// Creates the network metrics and registers them with `registry`.
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let pings_sent = IntCounter::new("net_pings_sent", "number of pings sent to peers")?;
+        let pongs_received = IntCounter::new(
+            "net_pongs_received",
+            "number of pongs received in answer to our pings",
+        )?;
+        let peers_timed_out = IntCounter::new(
+            "net_peers_timed_out",
+            "number of peers disconnected after missing too many pongs",
+        )?;
+        let bytes_read = IntCounter::new("net_bytes_read", "number of bytes read from peers")?;
+        let bytes_written =
+            IntCounter::new("net_bytes_written", "number of bytes written to peers")?;
+        let peer_latency = GaugeVec::new(
+            Opts::new(
+                "net_peer_latency_seconds",
+                "last measured ping round-trip time per peer",
+            ),
+            &["peer", "connection"],
+        )?;
+        let peers_over_memory_budget = IntCounter::new(
+            "net_peers_over_memory_budget",
+            "number of peers disconnected for going over their memory budget",
+        )?;
+        let outbound_queue_full = IntCounter::new(
+            "net_outbound_queue_full",
+            "number of frames that found their peer's outbound queue full",
+        )?;
+        let outbound_queue_waiting = IntGauge::new(
+            "net_outbound_queue_waiting",
+            "number of senders waiting for room in a peer's outbound queue",
+        )?;
+        let outbound_queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "net_outbound_queue_depth",
+                "number of frames queued for peers, by channel",
+            ),
+            &["channel"],
+        )?;
+        let outbound_frames_dropped = IntCounterVec::new(
+            Opts::new(
+                "net_outbound_frames_dropped",
+                "number of frames dropped as their peer's queue for the channel was full",
+            ),
+            &["channel"],
+        )?;
+        let tls_handshakes = IntCounterVec::new(
+            Opts::new(
+                "net_tls_handshakes",
+                "number of TLS handshakes completed, by negotiated parameters",
+            ),
+            &["direction", "version", "cipher", "group"],
+        )?;
+        let connection_limit_hits = IntCounterVec::new(
+            Opts::new(
+                "net_connection_limit_hits",
+                "number of dials that waited for a slot and connections refused, by limit",
+            ),
+            &["limit"],
+        )?;
+        let connections_opened = IntCounterVec::new(
+            Opts::new(
+                "net_connections_opened",
+                "number of connections set up, by direction",
+            ),
+            &["direction"],
+        )?;
+        let connections_closed = IntCounterVec::new(
+            Opts::new(
+                "net_connections_closed",
+                "number of connections closed by the peer or dropped by us",
+            ),
+            &["reason"],
+        )?;
+        let handshakes = IntCounterVec::new(
+            Opts::new(
+                "net_handshakes",
+                "number of protocol handshakes, by outcome",
+            ),
+            &["outcome"],
+        )?;
+        let corrupt_frames = IntCounter::new(
+            "net_corrupt_frames",
+            "number of frames read from peers that failed their checksum",
+        )?;
+
+        let connection_failures = IntCounterVec::new(
+            Opts::new(
+                "net_connection_failures",
+                "number of failed attempts to connect to or handshake with a peer, by cause",
+            ),
+            &["cause", "detail"],
+        )?;
+
+        let buffers_reused = IntCounter::new(
+            "net_buffer_pool_reused",
+            "number of connection buffers taken from the pool",
+        )?;
+        let buffers_allocated = IntCounter::new(
+            "net_buffer_pool_allocated",
+            "number of connection buffers allocated as the pool had none",
+        )?;
+        let idle_buffers = IntGauge::new("net_buffer_pool_idle", "number of buffers in the pool")?;
+        let idle_buffer_bytes = IntGauge::new(
+            "net_buffer_pool_idle_bytes",
+            "bytes held by the buffers in the pool",
+        )?;
+
+        registry.register(Box::new(pings_sent.clone()))?;
+        registry.register(Box::new(pongs_received.clone()))?;
+        registry.register(Box::new(peers_timed_out.clone()))?;
+        registry.register(Box::new(bytes_read.clone()))?;
+        registry.register(Box::new(bytes_written.clone()))?;
+        registry.register(Box::new(peer_latency.clone()))?;
+        registry.register(Box::new(peers_over_memory_budget.clone()))?;
+        registry.register(Box::new(outbound_queue_full.clone()))?;
+        registry.register(Box::new(outbound_queue_waiting.clone()))?;
+        registry.register(Box::new(outbound_queue_depth.clone()))?;
+        registry.register(Box::new(outbound_frames_dropped.clone()))?;
+        registry.register(Box::new(tls_handshakes.clone()))?;
+        registry.register(Box::new(connection_limit_hits.clone()))?;
+        registry.register(Box::new(connections_opened.clone()))?;
+        registry.register(Box::new(connections_closed.clone()))?;
+        registry.register(Box::new(handshakes.clone()))?;
+        registry.register(Box::new(corrupt_frames.clone()))?;
+        registry.register(Box::new(connection_failures.clone()))?;
+        registry.register(Box::new(buffers_reused.clone()))?;
+        registry.register(Box::new(buffers_allocated.clone()))?;
+        registry.register(Box::new(idle_buffers.clone()))?;
+        registry.register(Box::new(idle_buffer_bytes.clone()))?;
+
+        Ok(Self {
+            pings_sent,
+            pongs_received,
+            peers_timed_out,
+            bytes_read,
+            bytes_written,
+            peer_latency,
+            peers_over_memory_budget,
+            outbound_queue_full,
+            outbound_queue_waiting,
+            outbound_queue_depth,
+            outbound_frames_dropped,
+            tls_handshakes,
+            connection_limit_hits,
+            connections_opened,
+            connections_closed,
+            handshakes,
+            corrupt_frames,
+            connection_failures,
+            buffers_reused,
+            buffers_allocated,
+            idle_buffers,
+            idle_buffer_bytes,
+            registry: registry.clone(),
+        })
+    }
+}
+
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        let _ = self.registry.unregister(Box::new(self.pings_sent.clone()));
+        let _ = self.registry.unregister(Box::new(self.pongs_received.clone()));
+        let _ = self.registry.unregister(Box::new(self.peers_timed_out.clone()));
+        let _ = self.registry.unregister(Box::new(self.bytes_read.clone()));
+        let _ = self.registry.unregister(Box::new(self.bytes_written.clone()));
+        let _ = self.registry.unregister(Box::new(self.peer_latency.clone()));
+        let _ = self.registry.unregister(Box::new(self.peers_over_memory_budget.clone()));
+        let _ = self.registry.unregister(Box::new(self.outbound_queue_full.clone()));
+        let _ = self.registry.unregister(Box::new(self.outbound_queue_waiting.clone()));
+        let _ = self.registry.unregister(Box::new(self.outbound_queue_depth.clone()));
+        let _ = self.registry.unregister(Box::new(self.outbound_frames_dropped.clone()));
+        let _ = self.registry.unregister(Box::new(self.tls_handshakes.clone()));
+        let _ = self.registry.unregister(Box::new(self.connection_limit_hits.clone()));
+        let _ = self.registry.unregister(Box::new(self.connections_opened.clone()));
+        let _ = self.registry.unregister(Box::new(self.connections_closed.clone()));
+        let _ = self.registry.unregister(Box::new(self.handshakes.clone()));
+        let _ = self.registry.unregister(Box::new(self.corrupt_frames.clone()));
+        let _ = self.registry.unregister(Box::new(self.connection_failures.clone()));
+        let _ = self.registry.unregister(Box::new(self.buffers_reused.clone()));
+        let _ = self.registry.unregister(Box::new(self.buffers_allocated.clone()));
+        let _ = self.registry.unregister(Box::new(self.idle_buffers.clone()));
+        let _ = self.registry.unregister(Box::new(self.idle_buffer_bytes.clone()));
+    }
+}
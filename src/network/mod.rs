@@ -1,4 +1,45 @@
+pub mod bandwidth;
+pub mod buffers;
+pub mod checksum;
+pub mod churn;
+pub mod compression;
+pub mod config;
+pub mod connection;
+pub mod crawl;
+pub mod deploy;
+pub mod dispatch;
+pub mod era_tracker;
 pub mod error;
+#[cfg(any(test, feature = "testing"))]
+pub mod faults;
+pub mod fetch;
+pub mod finality;
+pub mod gossip;
+pub mod handshake;
+pub mod headers;
+pub mod history;
+pub mod keepalive;
+pub mod limits;
 pub mod manager;
+pub mod memory;
 pub mod message;
+pub mod metrics;
+pub mod mux;
+pub mod node_id;
+pub mod observe;
+pub mod progress;
+pub mod protocol;
+pub mod replay;
+pub mod reputation;
+pub mod resolve;
+pub mod session;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod tls;
+pub mod topology;
+pub mod transport;
+pub mod wire_log;
+
+pub use config::Config;
+pub use session::connect;
+pub use session::PeerSession;
@@ -0,0 +1,411 @@
+//! Multiplexing of independent flows over a single connection.
+//!
+//! Without it, every message is a frame of its own and frames go out in the
+//! order they were queued, so a large gossip item holds up the pong or the
+//! response queued behind it. Peers that both advertise multiplexing in their
+//! handshake instead cut messages into fragments of at most
+//! [`MAX_FRAGMENT_LEN`] bytes, each starting with a two byte header: the
+//! [`Channel`] it belongs to and flags telling whether it ends its message.
+//! Channels take turns sending a fragment, so a message only ever waits for
+//! the messages of its own channel.
+//!
+//! Every channel is flow-controlled on its own: a sender may have at most
+//! [`WINDOW`] bytes of a channel in flight, until the receiver returns credit
+//! for what it read in a frame flagged as such. A busy channel thus cannot
+//! fill the socket buffers the fragments of every other channel have to get
+//! through. Casper nodes never advertise multiplexing, so frames to them stay
+//! whole.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
+use serde::Serialize;
+
+/// Largest fragment a message is cut into.
+pub const MAX_FRAGMENT_LEN: usize = 16 * 1024;
+
+/// Bytes of a channel a sender may have in flight before it needs credit.
+pub const WINDOW: u32 = 256 * 1024;
+
+/// Length of the header every frame starts with.
+const HEADER_LEN: usize = 2;
+
+/// The fragment is the last one of its message.
+const FLAG_END: u8 = 0b01;
+/// The frame returns credit for the channel instead of carrying data.
+const FLAG_CREDIT: u8 = 0b10;
+
+/// A flow of messages that never waits for the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Handshakes, pings and pongs.
+    Control,
+    /// Requests for items.
+    Requests,
+    /// Answers to requests.
+    Responses,
+    /// Gossip and everything else.
+    Gossip,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [
+        Channel::Control,
+        Channel::Requests,
+        Channel::Responses,
+        Channel::Gossip,
+    ];
+
+    /// Every channel, in the order their queued frames are written to a
+    /// peer: control traffic keeps the connection alive, and answering
+    /// requests matters more than making new ones or gossiping.
+    pub const BY_PRIORITY: [Channel; 4] = [
+        Channel::Control,
+        Channel::Responses,
+        Channel::Requests,
+        Channel::Gossip,
+    ];
+
+    fn from_id(id: u8) -> Option<Self> { Self::ALL.get(id as usize).copied() }
+
+    fn id(self) -> u8 { self as u8 }
+
+    pub(super) fn index(self) -> usize { self as usize }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Control => write!(f, "control"),
+            Channel::Requests => write!(f, "requests"),
+            Channel::Responses => write!(f, "responses"),
+            Channel::Gossip => write!(f, "gossip"),
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn frame(channel: Channel, flags: u8, body: &[u8]) -> Bytes {
+    let mut frame = BytesMut::with_capacity(HEADER_LEN + body.len());
+    frame.put_u8(channel.id());
+    frame.put_u8(flags);
+    frame.put_slice(body);
+    frame.freeze()
+}
+
+#[derive(Debug)]
+struct SendChannel<T> {
+    /// Messages waiting to be sent, the first one maybe in part already,
+    /// each with what it keeps alive until it is.
+    queue: VecDeque<(Bytes, T)>,
+    /// Bytes the peer is ready to receive.
+    credit: u32,
+}
+
+/// Cuts messages queued on every channel into fragments, taking turns
+/// between the channels with credit left.
+///
+/// Each message comes with a `T` that is dropped once it is sent in full,
+/// e.g. the reservation of its memory.
+#[derive(Debug)]
+pub struct Multiplexer<T> {
+    channels: [SendChannel<T>; 4],
+    /// Credit frames for the peer, sent ahead of any fragment.
+    credit_frames: VecDeque<Bytes>,
+    /// Index of the channel whose turn it is.
+    turn: usize,
+}
+
+impl<T> Default for Multiplexer<T> {
+    fn default() -> Self {
+        Self {
+            channels: Channel::ALL.map(|_| SendChannel {
+                queue: VecDeque::new(),
+                credit: WINDOW,
+            }),
+            credit_frames: VecDeque::new(),
+            turn: 0,
+        }
+    }
+}
+
+impl<T> Multiplexer<T> {
+    /// Queues `message` behind the others of `channel`.
+    pub fn push(&mut self, channel: Channel, message: Bytes, attached: T) {
+        self.channels[channel.index()].queue.push_back((message, attached));
+    }
+
+    /// Number of messages not sent in full yet.
+    pub fn queued(&self) -> usize { self.channels.iter().map(|channel| channel.queue.len()).sum() }
+
+    /// Lets the peer send `credit` more bytes of `channel`, because we read
+    /// as many.
+    pub fn return_credit(&mut self, channel: Channel, credit: u32) {
+        self.credit_frames.push_back(frame(channel, FLAG_CREDIT, &credit.to_be_bytes()));
+    }
+
+    /// Takes note that the peer is ready for `credit` more bytes of
+    /// `channel`.
+    pub fn add_credit(&mut self, channel: Channel, credit: u32) {
+        let channel = &mut self.channels[channel.index()];
+        channel.credit = channel.credit.saturating_add(credit);
+    }
+
+    /// The next frame to send, `None` until more is queued or the peer
+    /// returns credit.
+    pub fn next_frame(&mut self) -> Option<Bytes> {
+        if let Some(frame) = self.credit_frames.pop_front() {
+            return Some(frame);
+        }
+        let count = self.channels.len();
+        let index = (0..count).map(|offset| (self.turn + offset) % count).find(|index| {
+            let channel = &self.channels[*index];
+            !channel.queue.is_empty() && channel.credit > 0
+        })?;
+        self.turn = (index + 1) % count;
+
+        let channel = &mut self.channels[index];
+        let (message, _) = channel.queue.front_mut()?;
+        let len = message.len().min(MAX_FRAGMENT_LEN).min(channel.credit as usize);
+        let fragment = message.split_to(len);
+        channel.credit -= len as u32;
+        let flags = if message.is_empty() {
+            channel.queue.pop_front();
+            FLAG_END
+        } else {
+            0
+        };
+        Some(frame(Channel::ALL[index], flags, &fragment))
+    }
+}
+
+/// What a frame of a multiplexed connection amounts to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Demuxed {
+    /// The last fragment of a message, completing it.
+    Message(Channel, Bytes),
+    /// A fragment of a message still missing some.
+    Fragment,
+    /// The peer returned credit for the channel.
+    Credit(Channel, u32),
+}
+
+#[derive(Debug)]
+struct ReceiveChannel {
+    /// Fragments of the message being received.
+    partial: BytesMut,
+    /// Bytes the peer may still send.
+    window: u32,
+    /// Bytes read that the peer did not get credit back for yet.
+    unreturned: u32,
+}
+
+/// Puts messages back together from the fragments of every channel, and
+/// keeps track of the credit the peer is owed.
+#[derive(Debug)]
+pub struct Demultiplexer {
+    channels: [ReceiveChannel; 4],
+    max_message_len: usize,
+}
+
+impl Demultiplexer {
+    /// Accepts messages of up to `max_message_len` bytes.
+    pub fn new(max_message_len: usize) -> Self {
+        Self {
+            channels: Channel::ALL.map(|_| ReceiveChannel {
+                partial: BytesMut::new(),
+                window: WINDOW,
+                unreturned: 0,
+            }),
+            max_message_len,
+        }
+    }
+
+    /// Takes in a frame read from the peer.
+    ///
+    /// Fails if the frame is malformed, or the peer sent more than it had
+    /// credit for.
+    pub fn receive(&mut self, mut frame: Bytes) -> io::Result<Demuxed> {
+        if frame.len() < HEADER_LEN {
+            return Err(invalid_data(format!("frame of {} bytes", frame.len())));
+        }
+        let id = frame.get_u8();
+        let channel =
+            Channel::from_id(id).ok_or_else(|| invalid_data(format!("unknown channel {id}")))?;
+        let flags = frame.get_u8();
+        let state = &mut self.channels[channel.index()];
+        match flags {
+            FLAG_CREDIT => {
+                let credit = <[u8; 4]>::try_from(&frame[..])
+                    .map_err(|_| invalid_data(format!("credit frame of {} bytes", frame.len())))?;
+                Ok(Demuxed::Credit(channel, u32::from_be_bytes(credit)))
+            }
+            0 | FLAG_END => {
+                let len = frame.len() as u32;
+                if len > state.window {
+                    return Err(invalid_data(format!(
+                        "{len} bytes on the {channel} channel with {} bytes of credit",
+                        state.window
+                    )));
+                }
+                if state.partial.len() + frame.len() > self.max_message_len {
+                    return Err(invalid_data(format!(
+                        "message on the {channel} channel longer than {} bytes",
+                        self.max_message_len
+                    )));
+                }
+                state.window -= len;
+                state.unreturned += len;
+                if flags == 0 {
+                    state.partial.extend_from_slice(&frame);
+                    return Ok(Demuxed::Fragment);
+                }
+                // Messages of a single fragment, most of them, are what was
+                // read. Only those of several are copied, to be made whole.
+                let message = if state.partial.is_empty() {
+                    frame
+                } else {
+                    let mut message = std::mem::take(&mut state.partial);
+                    message.extend_from_slice(&frame);
+                    message.freeze()
+                };
+                Ok(Demuxed::Message(channel, message))
+            }
+            flags => Err(invalid_data(format!("unknown frame flags {flags:#04b}"))),
+        }
+    }
+
+    /// Credit to return to the peer for what was read.
+    ///
+    /// Credit is returned once half a channel's window was read rather than
+    /// for every frame, so the peer never runs dry while the frames returning
+    /// it stay few.
+    pub fn take_credit(&mut self) -> Vec<(Channel, u32)> {
+        Channel::ALL
+            .into_iter()
+            .zip(&mut self.channels)
+            .filter(|(_, state)| state.unreturned >= WINDOW / 2)
+            .map(|(channel, state)| {
+                let credit = std::mem::take(&mut state.unreturned);
+                state.window += credit;
+                (channel, credit)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends every frame the multiplexer has, returning the messages in the
+    /// order they were completed and handing credit back along the way.
+    fn transfer(mux: &mut Multiplexer<()>, demux: &mut Demultiplexer) -> Vec<(Channel, Bytes)> {
+        let mut messages = vec![];
+        while let Some(frame) = mux.next_frame() {
+            match demux.receive(frame).unwrap() {
+                Demuxed::Message(channel, message) => messages.push((channel, message)),
+                Demuxed::Fragment => {}
+                Demuxed::Credit(channel, credit) => mux.add_credit(channel, credit),
+            }
+            for (channel, credit) in demux.take_credit() {
+                mux.add_credit(channel, credit);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn small_messages_overtake_large_ones_of_other_channels() {
+        let mut mux = Multiplexer::default();
+        let mut demux = Demultiplexer::new(1 << 20);
+        let gossip = Bytes::from(vec![7; 10 * MAX_FRAGMENT_LEN]);
+        mux.push(Channel::Gossip, gossip.clone(), ());
+        mux.push(Channel::Control, Bytes::from_static(b"pong"), ());
+        mux.push(Channel::Responses, Bytes::new(), ());
+        assert_eq!(mux.queued(), 3);
+
+        let messages = transfer(&mut mux, &mut demux);
+        assert_eq!(
+            messages,
+            vec![
+                (Channel::Control, Bytes::from_static(b"pong")),
+                (Channel::Responses, Bytes::new()),
+                (Channel::Gossip, gossip),
+            ]
+        );
+        assert_eq!(mux.queued(), 0);
+    }
+
+    #[test]
+    fn channels_stall_without_credit_and_resume_with_it() {
+        let mut mux = Multiplexer::default();
+        let mut demux = Demultiplexer::new(1 << 20);
+        let large = Bytes::from(vec![1; WINDOW as usize + 1]);
+        mux.push(Channel::Gossip, large.clone(), ());
+
+        // The window goes out, the last byte has to wait for credit.
+        let mut sent = 0;
+        while let Some(frame) = mux.next_frame() {
+            sent += frame.len() - HEADER_LEN;
+            assert_eq!(demux.receive(frame).unwrap(), Demuxed::Fragment);
+        }
+        assert_eq!(sent, WINDOW as usize);
+
+        // Other channels are not held up meanwhile.
+        mux.push(Channel::Requests, Bytes::from_static(b"get"), ());
+        let frame = mux.next_frame().unwrap();
+        assert_eq!(
+            demux.receive(frame).unwrap(),
+            Demuxed::Message(Channel::Requests, Bytes::from_static(b"get"))
+        );
+
+        // Returning credit takes a frame of its own.
+        let credit = demux.take_credit();
+        assert_eq!(credit, vec![(Channel::Gossip, WINDOW)]);
+        let mut peer = Multiplexer::<()>::default();
+        peer.return_credit(Channel::Gossip, WINDOW);
+        let frame = peer.next_frame().unwrap();
+        assert_eq!(
+            demux.receive(frame).unwrap(),
+            Demuxed::Credit(Channel::Gossip, WINDOW)
+        );
+        mux.add_credit(Channel::Gossip, WINDOW);
+
+        let frame = mux.next_frame().unwrap();
+        assert_eq!(
+            demux.receive(frame).unwrap(),
+            Demuxed::Message(Channel::Gossip, large)
+        );
+        assert_eq!(mux.next_frame(), None);
+    }
+
+    #[test]
+    fn peers_may_not_overrun_their_credit() {
+        let mut demux = Demultiplexer::new(usize::MAX);
+        let fragment = frame(Channel::Gossip, 0, &vec![0; MAX_FRAGMENT_LEN]);
+        for _ in 0..WINDOW as usize / MAX_FRAGMENT_LEN {
+            demux.receive(fragment.clone()).unwrap();
+        }
+        let error = demux.receive(fragment.clone()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut demux = Demultiplexer::new(4);
+        let error = demux.receive(frame(Channel::Gossip, 0, b"hello"));
+        assert!(error.is_err());
+        assert!(demux.receive(Bytes::from_static(&[9, 0])).is_err());
+        assert!(demux.receive(Bytes::from_static(&[0, 4])).is_err());
+        assert!(demux.receive(Bytes::from_static(&[0])).is_err());
+    }
+}
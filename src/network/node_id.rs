@@ -0,0 +1,144 @@
+//! The identifier of a peer, which stays the same across reconnects.
+//!
+//! Addresses are no good at telling peers apart: a peer dialing us comes
+//! from a new ephemeral port every time, and may move to another address
+//! altogether. What stays is the key pair behind the certificate it
+//! presents, so peers are known by the [`Fingerprint`] of its public key,
+//! as casper-node knows them.
+//!
+//! Until a peer is identified, or over transports without certificates, all
+//! there is to know it by is its address. [`PeerKey`] is either, and is what
+//! scores and the blocklist are kept by.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use datasize::DataSize;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::error::TLSError;
+use super::tls;
+use super::tls::Certificate;
+use crate::utils::Fingerprint;
+
+/// Hex digits of the identifier shown in logs, enough to tell peers apart.
+const SHORT_LEN: usize = 10;
+
+/// Identifies a peer by the fingerprint of the public key in its
+/// certificate.
+///
+/// Displayed shortened as `tls:` and the first hex digits, like casper-node
+/// does in its logs, and in full with `{:#}`. Serialized as the full
+/// fingerprint, so it reads what fingerprints were written as.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, DataSize)]
+#[serde(transparent)]
+pub struct NodeId(Fingerprint);
+
+impl NodeId {
+    /// The identifier of whoever presents `cert`. Fails if its public key
+    /// cannot be read, which a validated certificate's always can.
+    pub fn of(cert: &Certificate) -> Result<Self, TLSError> {
+        tls::cert_fingerprint(cert).map(NodeId)
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint { self.0 }
+}
+
+impl From<Fingerprint> for NodeId {
+    fn from(fingerprint: Fingerprint) -> Self { NodeId(fingerprint) }
+}
+
+// Identifiers are public, so they are ordered and hashed by their bytes
+// rather than compared in constant time only.
+impl Ord for NodeId {
+    fn cmp(&self, other: &Self) -> Ordering { self.0.as_bytes().cmp(other.0.as_bytes()) }
+}
+
+impl PartialOrd for NodeId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Hash for NodeId {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.0.as_bytes().hash(state) }
+}
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "tls:{}", self.0);
+        }
+        let hex = self.0.to_string();
+        write!(f, "tls:{}", &hex[..SHORT_LEN])
+    }
+}
+
+impl Debug for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { Display::fmt(self, f) }
+}
+
+impl FromStr for NodeId {
+    type Err = base16::DecodeError;
+
+    /// Parses the full fingerprint, with or without the `tls:` prefix.
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        id.strip_prefix("tls:").unwrap_or(id).parse().map(NodeId)
+    }
+}
+
+/// What a peer is known by: its [`NodeId`] once identified, its address
+/// until then.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, DataSize)]
+pub enum PeerKey {
+    Node(NodeId),
+    Addr(SocketAddr),
+}
+
+impl PeerKey {
+    /// The peer at `addr`, known by `node` if it was identified.
+    pub fn of(addr: SocketAddr, node: Option<NodeId>) -> Self {
+        node.map_or(PeerKey::Addr(addr), PeerKey::Node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tls::Identity;
+
+    #[test]
+    fn node_ids_are_the_fingerprint_of_the_certificate_key() {
+        let identity = Identity::from_seed(1).unwrap();
+        let id = NodeId::of(&identity.tls_certificate).unwrap();
+        assert_eq!(id.fingerprint(), identity.fingerprint());
+        assert_eq!(id, identity.node_id());
+
+        let shown = id.to_string();
+        assert_eq!(shown.len(), "tls:".len() + SHORT_LEN);
+        assert!(format!("{id:#}").starts_with(&shown));
+        assert_eq!(format!("{id:#}").parse::<NodeId>().unwrap(), id);
+        assert_eq!(id.fingerprint().to_string().parse::<NodeId>().unwrap(), id);
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(
+            json,
+            serde_json::to_string(&identity.fingerprint()).unwrap()
+        );
+        assert_eq!(serde_json::from_str::<NodeId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn node_ids_are_ordered_by_their_bytes() {
+        let low = NodeId::from(Fingerprint::from_bytes([0; Fingerprint::SIZE]));
+        let high = NodeId::from(Fingerprint::from_bytes([1; Fingerprint::SIZE]));
+        assert!(low < high);
+        assert_ne!(low, high);
+    }
+}
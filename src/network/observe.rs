@@ -0,0 +1,226 @@
+//! Passive observation of what peers gossip.
+//!
+//! Schultz only understands the address gossiper, but every gossiper shares
+//! casper-node's `gossiper::Message` envelope, and every item it carries,
+//! or the id of one, starts with a hash. That is enough to tell which block,
+//! deploy or finality signature is being gossiped without decoding the rest,
+//! so every gossip message read from a peer can be reported to whoever
+//! [observes](super::manager::Manager::observe) them.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use casper_hashing::Digest;
+use serde::Serialize;
+
+use super::gossip::GossipMessage;
+use super::gossip::NodePayload;
+use super::message::BincodeFormat;
+use super::message::Message;
+
+/// Messages kept for an observer that falls behind, older ones are dropped.
+pub const OBSERVED_CAPACITY: usize = 1024;
+
+/// Wire tag of `Message::Payload`.
+pub(super) const PAYLOAD_TAG: u8 = 3;
+
+/// The gossiper a message belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gossiper {
+    Block,
+    Deploy,
+    FinalitySignature,
+    Address,
+}
+
+impl Gossiper {
+    /// The gossiper of a payload with the wire tag `tag`.
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            2 => Some(Gossiper::Block),
+            3 => Some(Gossiper::Deploy),
+            4 => Some(Gossiper::FinalitySignature),
+            5 => Some(Gossiper::Address),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Gossiper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Gossiper::Block => write!(f, "block"),
+            Gossiper::Deploy => write!(f, "deploy"),
+            Gossiper::FinalitySignature => write!(f, "finality-signature"),
+            Gossiper::Address => write!(f, "address"),
+        }
+    }
+}
+
+/// The variant of casper-node's `gossiper::Message`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GossipKind {
+    Gossip,
+    GossipResponse,
+    GetItem,
+    Item,
+}
+
+impl GossipKind {
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(GossipKind::Gossip),
+            1 => Some(GossipKind::GossipResponse),
+            2 => Some(GossipKind::GetItem),
+            3 => Some(GossipKind::Item),
+            _ => None,
+        }
+    }
+}
+
+impl Display for GossipKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GossipKind::Gossip => write!(f, "gossip"),
+            GossipKind::GossipResponse => write!(f, "gossip-response"),
+            GossipKind::GetItem => write!(f, "get-item"),
+            GossipKind::Item => write!(f, "item"),
+        }
+    }
+}
+
+/// A gossip message read from a peer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Observed {
+    pub peer: SocketAddr,
+    pub gossiper: Gossiper,
+    pub kind: GossipKind,
+    /// Hash of the block or deploy, of the block a finality signature is
+    /// for, or the gossiped address.
+    pub item: String,
+    /// Size of the whole message.
+    pub bytes: usize,
+    /// The item or id gossiped, as the peer serialized it, for those who
+    /// decode it. Shares the frame it was read in rather than copying it.
+    #[serde(skip)]
+    pub body: Bytes,
+}
+
+impl Observed {
+    /// Tells what the bincode `frame` read from `peer` gossips, if it is a
+    /// gossip message at all.
+    pub fn parse(peer: SocketAddr, frame: &Bytes) -> Option<Self> {
+        let (&message, rest) = frame.split_first()?;
+        let (&payload, rest) = rest.split_first()?;
+        let (&kind, rest) = rest.split_first()?;
+        if message != PAYLOAD_TAG {
+            return None;
+        }
+        let gossiper = Gossiper::from_tag(payload)?;
+        let kind = GossipKind::from_tag(kind)?;
+
+        let item = match gossiper {
+            Gossiper::Address => Self::address(frame)?,
+            _ => Self::leading_hash(rest)?.to_string(),
+        };
+        Some(Self {
+            peer,
+            gossiper,
+            kind,
+            item,
+            bytes: frame.len(),
+            body: frame.slice(frame.len() - rest.len()..),
+        })
+    }
+
+    /// The address in an address gossip message, which we fully understand.
+    fn address(frame: &[u8]) -> Option<String> {
+        let message: Message<NodePayload> =
+            BincodeFormat::default().deserialize_arbitrary(frame).ok()?;
+        let Message::Payload(NodePayload::AddressGossiper(gossip)) = message else {
+            return None;
+        };
+        let address = match gossip {
+            GossipMessage::Gossip(item) | GossipMessage::GetItem(item) => item.address(),
+            GossipMessage::GossipResponse { item_id, .. } => item_id.address(),
+            GossipMessage::Item(item) => item.address(),
+        };
+        Some(address.to_string())
+    }
+
+    /// The hash every other item and id starts with, written by bincode as a
+    /// length-prefixed slice.
+    fn leading_hash(bytes: &[u8]) -> Option<Digest> {
+        let (&len, rest) = bytes.split_first()?;
+        if usize::from(len) != Digest::LENGTH {
+            return None;
+        }
+        let hash: [u8; Digest::LENGTH] = rest.get(..Digest::LENGTH)?.try_into().ok()?;
+        Some(Digest::from(hash))
+    }
+}
+
+impl Display for Observed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} ({} bytes)",
+            self.peer, self.gossiper, self.kind, self.item, self.bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::network::gossip::GossipedAddress;
+
+    fn peer() -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], 35000)) }
+
+    #[test]
+    fn tells_what_a_message_gossips() {
+        let hash = Digest::hash(b"block");
+        let mut frame = BytesMut::from(&[PAYLOAD_TAG, 2, 3, 32][..]);
+        frame.extend_from_slice(hash.as_ref());
+        frame.extend_from_slice(b"the rest of the block");
+        let frame = frame.freeze();
+
+        let observed = Observed::parse(peer(), &frame).unwrap();
+        assert_eq!(observed.gossiper, Gossiper::Block);
+        assert_eq!(observed.kind, GossipKind::Item);
+        assert_eq!(observed.item, hash.to_string());
+        assert_eq!(observed.bytes, frame.len());
+        assert_eq!(observed.body.as_ptr(), frame[3..].as_ptr());
+
+        let address = SocketAddr::from(([10, 0, 0, 1], 35000));
+        let message = Message::Payload(NodePayload::AddressGossiper(GossipMessage::Gossip(
+            GossipedAddress::new(address, 1),
+        )));
+        let bytes = BincodeFormat::default().serialize_arbitrary(&message).unwrap();
+        let observed = Observed::parse(peer(), &Bytes::from(bytes)).unwrap();
+        assert_eq!(observed.gossiper, Gossiper::Address);
+        assert_eq!(observed.item, address.to_string());
+    }
+
+    #[test]
+    fn ignores_everything_else() {
+        // A ping, a consensus message and a truncated block gossip.
+        for frame in [
+            &[1, 0][..],
+            &[PAYLOAD_TAG, 0, 0, 32],
+            &[PAYLOAD_TAG, 2, 0, 32, 1],
+        ] {
+            assert_eq!(
+                Observed::parse(peer(), &Bytes::copy_from_slice(frame)),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Progress of bootstrapping from a bootnode.
+//!
+//! Joining the network through a bootnode goes through a fixed series of
+//! [`Phase`]s. Each one is reported to a [`Progress`] as it starts and
+//! completes, so a caller can tell how far it got and where it failed.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::error::ManagerError;
+use super::resolve::Bootnode;
+
+/// A step of bootstrapping from a bootnode, in the order they are taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Finding the addresses the bootnode listens on.
+    Resolve,
+    /// Opening a TCP connection.
+    Connect,
+    /// Setting up TLS and checking the bootnode's certificate.
+    Tls,
+    /// Exchanging protocol handshakes.
+    Handshake,
+    /// Announcing ourselves so the bootnode relays our address.
+    Sync,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Resolve,
+        Phase::Connect,
+        Phase::Tls,
+        Phase::Handshake,
+        Phase::Sync,
+    ];
+
+    /// Position of the phase, counting from 1.
+    pub fn step(self) -> usize { self as usize + 1 }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Resolve => "resolve",
+            Phase::Connect => "connect",
+            Phase::Tls => "tls",
+            Phase::Handshake => "handshake",
+            Phase::Sync => "sync",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A phase of a single connection attempt starting or completing, as
+/// reported by a [`Transport`](super::transport::Transport).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Started(Phase),
+    Completed(Phase),
+}
+
+/// Receives the progress of a bootstrap. Every method does nothing by
+/// default.
+pub trait Progress: Send + Sync {
+    /// `phase` started for `bootnode`.
+    fn started(&self, _bootnode: &Bootnode, _phase: Phase) {}
+
+    /// `phase` completed for `bootnode`.
+    fn completed(&self, _bootnode: &Bootnode, _phase: Phase) {}
+
+    /// `bootnode` resolved to `addrs`, which are tried in that order.
+    fn resolved(&self, _bootnode: &Bootnode, _addrs: &[SocketAddr]) {}
+
+    /// Of the addresses `bootnode` resolved to, `addr` was connected to.
+    fn connected(&self, _bootnode: &Bootnode, _addr: SocketAddr) {}
+
+    /// `phase` failed with `error`, and the bootnode is tried again from the
+    /// start after `delay`, for the `attempt`th time.
+    fn retrying(
+        &self,
+        _bootnode: &Bootnode,
+        _phase: Phase,
+        _error: &ManagerError,
+        _attempt: u32,
+        _delay: Duration,
+    ) {
+    }
+}
+
+/// Ignores all progress.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// A bootstrap that gave up, with the phase it last failed in.
+#[derive(Debug, Error)]
+#[error("Bootstrapping from {bootnode} failed in the {phase} phase: {source}")]
+pub struct BootstrapError {
+    pub bootnode: Bootnode,
+    pub phase: Phase,
+    #[source]
+    pub source: ManagerError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_are_numbered_in_order() {
+        let steps: Vec<_> = Phase::ALL.iter().map(|phase| phase.step()).collect();
+        assert_eq!(steps, [1, 2, 3, 4, 5]);
+    }
+}
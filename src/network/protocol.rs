@@ -0,0 +1,77 @@
+//! The casper-node protocol versions schultz speaks, and what sets their
+//! wire formats apart.
+//!
+//! Every supported version opens a connection with the same MessagePack
+//! handshake, so a peer's protocol version is known once its handshake is
+//! read. [`VERSIONS`] then tells how the peer writes what follows: a row
+//! covers every version from its own up to the next row's, and versions
+//! before the first row or from [`Versions::until`] on are not spoken at all.
+//!
+//! casper-node 2.x is not spoken: its block headers are versioned, its
+//! deploys are gossiped as transactions and neither is decoded.
+
+use casper_types::ProtocolVersion;
+
+/// What a range of protocol versions puts on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wire {
+    /// Oldest version of the range.
+    pub since: ProtocolVersion,
+    /// Whether handshakes carry the hash of the sender's chainspec, which a
+    /// peer on our own version then has to send.
+    pub chainspec_hash: bool,
+}
+
+/// The supported protocol versions, mapped to their wire format.
+#[derive(Debug)]
+pub struct Versions {
+    /// Ordered by `since`.
+    wires: &'static [Wire],
+    until: ProtocolVersion,
+}
+
+/// Every protocol version schultz speaks.
+pub const VERSIONS: Versions = Versions {
+    wires: &[
+        Wire {
+            since: ProtocolVersion::V1_0_0,
+            chainspec_hash: false,
+        },
+        Wire {
+            since: ProtocolVersion::from_parts(1, 4, 0),
+            chainspec_hash: true,
+        },
+    ],
+    until: ProtocolVersion::from_parts(2, 0, 0),
+};
+
+impl Versions {
+    /// The wire format of `version`, if it is supported.
+    pub fn wire(&self, version: ProtocolVersion) -> Option<&'static Wire> {
+        if version >= self.until {
+            return None;
+        }
+        self.wires.iter().rev().find(|wire| wire.since <= version)
+    }
+
+    pub fn supports(&self, version: ProtocolVersion) -> bool { self.wire(version).is_some() }
+
+    /// The first version no longer supported.
+    pub fn until(&self) -> ProtocolVersion { self.until }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_map_to_the_row_they_fall_in() {
+        let wire = |major, minor| VERSIONS.wire(ProtocolVersion::from_parts(major, minor, 0));
+        assert!(!wire(1, 3).unwrap().chainspec_hash);
+        assert!(wire(1, 4).unwrap().chainspec_hash);
+        assert!(wire(1, 5).unwrap().chainspec_hash);
+        assert_eq!(wire(2, 0), None);
+        assert_eq!(wire(0, 9), None);
+        assert!(!VERSIONS.supports(VERSIONS.until()));
+    }
+}
@@ -0,0 +1,400 @@
+//! Replaying the peer side of a recorded handshake against ours.
+//!
+//! A [wire log](super::wire_log) taken while handshaking with a real
+//! casper-node holds the exact frames the node sent. A [`Script`] takes them
+//! from the capture, along with the kind of every frame we sent in between,
+//! and [`check_handshake`] has a [`Manager`] dial a scripted peer sending the
+//! recorded frames byte for byte, in the same order relative to ours. The
+//! handshake has to complete again, so a change breaking compatibility with
+//! what casper-node puts on the wire shows up without a node at hand.
+//!
+//! Our own frames are only checked for their kind: they carry our address,
+//! and validators a fresh stamp, so their bytes never repeat.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::SinkExt;
+use futures::StreamExt;
+use prometheus::Registry;
+use thiserror::Error;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::DuplexStream;
+use tokio_util::codec::Framed;
+
+use super::compression::FrameCodec;
+use super::config::Config;
+use super::connection::Direction;
+use super::error::ManagerError;
+use super::error::TLSError;
+use super::gossip::NodePayload;
+use super::handshake::Handshake;
+use super::manager::Manager;
+use super::manager::HANDSHAKE_TIMEOUT;
+use super::manager::MAX_FRAME_LEN;
+use super::tls::Identity;
+use super::transport::Accepted;
+use super::transport::BoxedStream;
+use super::transport::Listener;
+use super::transport::Transport;
+use super::wire_log;
+use super::wire_log::Record;
+use crate::primitives::Chainspec;
+
+/// Tag of handshake frames, see [`wire_log::Decoded::tag`].
+const HANDSHAKE_TAG: &str = "handshake";
+
+/// Room for the frames in flight between us and the scripted peer.
+const PIPE_CAPACITY: usize = 1024 * 1024;
+
+/// Room for the events of the single connection made, which nobody reads.
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("the capture holds no handshake{}", from(.0))]
+    NoHandshake(Option<SocketAddr>),
+    #[error("expected a {expected} frame from us, got a {got} frame")]
+    Unexpected { expected: String, got: String },
+    #[error("we closed the connection while a {0} frame was expected")]
+    Closed(String),
+    #[error("we stopped sending while a {0} frame was expected")]
+    Stalled(String),
+    #[error("could not exchange frames with us: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Manager(#[from] ManagerError),
+}
+
+/// Names the peer a capture lacks a handshake from, if one was asked for.
+fn from(peer: &Option<SocketAddr>) -> String {
+    peer.map_or_else(String::new, |peer| format!(" from {peer}"))
+}
+
+/// A step of the recorded exchange, as the peer takes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The peer sends this frame.
+    Send(Vec<u8>),
+    /// We send a frame of this kind.
+    Expect(String),
+}
+
+/// The peer side of a recorded handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Script {
+    peer: SocketAddr,
+    steps: Vec<Step>,
+}
+
+impl Script {
+    /// Takes the exchange with `peer` from `records`, or with the first peer
+    /// sending a handshake, up to the point both sides sent theirs.
+    pub fn from_records<I>(records: I, peer: Option<SocketAddr>) -> Result<Self, ReplayError>
+    where
+        I: IntoIterator<Item = io::Result<Record>>,
+    {
+        let mut records = records.into_iter();
+        let mut steps = Vec::new();
+        let mut chosen = peer;
+        // Peers we sent our handshake before picking one of them.
+        let mut greeted = Vec::new();
+        let (mut theirs, mut ours) = (false, false);
+        while !(theirs && ours) {
+            let Some(record) = records.next().transpose()? else {
+                return Err(ReplayError::NoHandshake(peer));
+            };
+            let is_handshake = record.tag == HANDSHAKE_TAG;
+            let peer = match chosen {
+                Some(peer) => peer,
+                None if is_handshake => match record.direction {
+                    Direction::Inbound => {
+                        if greeted.contains(&record.peer) {
+                            ours = true;
+                            steps.push(Step::Expect(HANDSHAKE_TAG.to_string()));
+                        }
+                        *chosen.insert(record.peer)
+                    }
+                    Direction::Outbound => {
+                        greeted.push(record.peer);
+                        continue;
+                    }
+                },
+                None => continue,
+            };
+            if record.peer != peer {
+                continue;
+            }
+            match record.direction {
+                Direction::Inbound => {
+                    theirs |= is_handshake;
+                    steps.push(Step::Send(record.bytes));
+                }
+                Direction::Outbound => {
+                    ours |= is_handshake;
+                    steps.push(Step::Expect(record.tag));
+                }
+            }
+        }
+        Ok(Self {
+            peer: chosen.expect("a handshake was found"),
+            steps,
+        })
+    }
+
+    /// The peer the exchange was recorded with.
+    pub fn peer(&self) -> SocketAddr { self.peer }
+
+    pub fn steps(&self) -> &[Step] { &self.steps }
+
+    /// Takes the peer's side of the exchange over `stream`, failing as soon
+    /// as we send something else than recorded.
+    pub async fn play<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+    ) -> Result<(), ReplayError> {
+        let mut frames = Framed::new(stream, FrameCodec::new(MAX_FRAME_LEN));
+        for step in &self.steps {
+            match step {
+                Step::Send(frame) => frames.send(Bytes::copy_from_slice(frame)).await?,
+                Step::Expect(expected) => {
+                    let Some(frame) = frames.next().await.transpose()? else {
+                        return Err(ReplayError::Closed(expected.clone()));
+                    };
+                    let got = wire_log::decode(self.peer, &frame).tag();
+                    if got != *expected {
+                        return Err(ReplayError::Unexpected {
+                            expected: expected.clone(),
+                            got,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Has a manager running `chainspec` with `config` dial the scripted peer
+/// and handshake with it, returning the handshake it accepted.
+pub async fn check_handshake(
+    script: &Script,
+    chainspec: Chainspec,
+    config: Config,
+) -> Result<Handshake, ReplayError> {
+    let (ours, theirs) = tokio::io::duplex(PIPE_CAPACITY);
+    let transport = Scripted {
+        stream: Mutex::new(Some(ours)),
+    };
+    let (event_tx, _event_rx) = tokio::sync::mpsc::channel(EVENT_CAPACITY);
+    let manager = Manager::with_transport::<NodePayload>(
+        Arc::new(transport),
+        Identity::with_generated_certs()?,
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        event_tx,
+        chainspec,
+        config,
+        &Registry::new(),
+    )
+    .await?;
+
+    let peer = script.peer;
+    let handshaking = async {
+        manager.connect(&peer).await?;
+        let handshake = manager.handshake::<NodePayload>(peer).await;
+        // Ends the script if it still waits for us. Once we succeeded, the
+        // script may still read what we queued, so the connection stays.
+        if handshake.is_err() {
+            manager.disconnect(peer).await;
+        }
+        handshake
+    };
+    let playing = tokio::time::timeout(HANDSHAKE_TIMEOUT, script.play(theirs));
+    tokio::pin!(handshaking, playing);
+    let mut handshake = None;
+    let played = loop {
+        tokio::select! {
+            played = &mut playing => break played,
+            result = &mut handshaking, if handshake.is_none() => handshake = Some(result),
+        }
+    };
+
+    let handshake = match (played, handshake) {
+        // The script tells best what we did differently.
+        (Ok(Err(error @ ReplayError::Unexpected { .. })), _) => return Err(error),
+        (_, Some(Err(error))) => return Err(error.into()),
+        (Ok(Err(error)), _) => return Err(error),
+        (Err(_), _) => {
+            let expected = script.steps.iter().rev().find_map(|step| match step {
+                Step::Expect(tag) => Some(tag.clone()),
+                Step::Send(_) => None,
+            });
+            return Err(ReplayError::Stalled(expected.unwrap_or_default()));
+        }
+        (Ok(Ok(())), Some(handshake)) => handshake,
+        // Our side may still be taking in the peer's last frames.
+        (Ok(Ok(())), None) => handshaking.await,
+    };
+    Ok(handshake?)
+}
+
+/// Connects whatever address is dialed to the scripted peer, once.
+struct Scripted {
+    stream: Mutex<Option<DuplexStream>>,
+}
+
+impl Transport for Scripted {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, Result<Box<dyn Listener>, ManagerError>> {
+        futures::future::ready(Ok(Box::new(Unreachable(addr)) as Box<dyn Listener>)).boxed()
+    }
+
+    fn connect(&self, _addr: SocketAddr) -> BoxFuture<'_, Result<BoxedStream, ManagerError>> {
+        let stream = self.stream.lock().expect("scripted stream lock poisoned").take();
+        let result = stream
+            .map(|stream| Box::new(stream) as BoxedStream)
+            .ok_or_else(|| TLSError::TcpConnection(io::ErrorKind::ConnectionRefused.into()).into());
+        futures::future::ready(result).boxed()
+    }
+}
+
+/// A listener nobody connects to.
+struct Unreachable(SocketAddr);
+
+impl Listener for Unreachable {
+    fn local_addr(&self) -> SocketAddr { self.0 }
+
+    fn accept(&mut self) -> BoxFuture<'_, Result<Accepted, ManagerError>> {
+        futures::future::pending().boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+
+    use casper_hashing::Digest;
+    use casper_types::ProtocolVersion;
+    use casper_types::Timestamp;
+    use serde::Serialize;
+    use tokio_serde::Serializer;
+
+    use super::*;
+    use crate::network::error::HandshakeError;
+    use crate::network::message::MessagePackFormat;
+
+    /// A handshake the way casper-node sends it, without any of the fields
+    /// schultz adds.
+    #[derive(Serialize)]
+    enum CasperMessage {
+        Handshake {
+            network_name: String,
+            public_addr: SocketAddr,
+            protocol_version: ProtocolVersion,
+            consensus_certificate: Option<()>,
+            is_syncing: bool,
+            chainspec_hash: Option<Digest>,
+        },
+    }
+
+    fn casper() -> SocketAddr { SocketAddr::from(([10, 0, 0, 1], 35000)) }
+
+    fn chainspec() -> Chainspec { Chainspec::from_path("examples").unwrap() }
+
+    fn casper_handshake(network_name: &str) -> Vec<u8> {
+        let chainspec = chainspec();
+        let message = CasperMessage::Handshake {
+            network_name: network_name.to_string(),
+            public_addr: casper(),
+            protocol_version: chainspec.protocol_version(),
+            consensus_certificate: None,
+            is_syncing: false,
+            chainspec_hash: Some(chainspec.hash()),
+        };
+        Pin::new(&mut MessagePackFormat).serialize(&message).unwrap().to_vec()
+    }
+
+    fn record(direction: Direction, peer: SocketAddr, bytes: Vec<u8>) -> io::Result<Record> {
+        Ok(Record {
+            timestamp: Timestamp::now(),
+            direction,
+            peer,
+            tag: wire_log::decode(peer, &Bytes::copy_from_slice(&bytes)).tag(),
+            bytes,
+        })
+    }
+
+    /// A capture of us dialing casper-node, with traffic of another peer in
+    /// between and a ping after the handshakes.
+    fn capture(network_name: &str) -> Vec<io::Result<Record>> {
+        let ours = Handshake::new(&chainspec(), SocketAddr::from(([127, 0, 0, 1], 34553)))
+            .encode::<NodePayload>()
+            .unwrap()
+            .to_vec();
+        let other = SocketAddr::from(([10, 0, 0, 2], 35000));
+        vec![
+            record(Direction::Outbound, casper(), ours.clone()),
+            record(Direction::Outbound, other, ours),
+            record(Direction::Inbound, casper(), casper_handshake(network_name)),
+            record(Direction::Inbound, casper(), vec![9, 9]),
+        ]
+    }
+
+    #[test]
+    fn scripts_take_the_exchange_with_one_peer_up_to_both_handshakes() {
+        let script = Script::from_records(capture("casper"), Some(casper())).unwrap();
+        assert_eq!(script.peer(), casper());
+        assert_eq!(
+            script.steps(),
+            [
+                Step::Expect(HANDSHAKE_TAG.to_string()),
+                Step::Send(casper_handshake("casper")),
+            ]
+        );
+
+        // Found without being told, starting at the peer's handshake.
+        let found = Script::from_records(capture("casper"), None).unwrap();
+        assert_eq!(found, script);
+
+        let other = Some(SocketAddr::from(([10, 0, 0, 3], 35000)));
+        assert!(matches!(
+            Script::from_records(capture("casper"), other),
+            Err(ReplayError::NoHandshake(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn recorded_casper_handshakes_are_accepted() {
+        let script = Script::from_records(capture("casper"), None).unwrap();
+        let handshake = check_handshake(&script, chainspec(), Config::default()).await.unwrap();
+        assert_eq!(handshake.public_addr, casper());
+        assert!(handshake.compression.is_empty());
+        assert!(!handshake.multiplexing);
+    }
+
+    #[tokio::test]
+    async fn handshakes_no_longer_accepted_are_reported() {
+        let script = Script::from_records(capture("another-network"), None).unwrap();
+        let error = check_handshake(&script, chainspec(), Config::default()).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ReplayError::Manager(ManagerError::HandshakeRejected(
+                _,
+                HandshakeError::WrongNetwork { .. }
+            ))
+        ));
+
+        // Us sending something else than recorded fails the script.
+        let script = Script {
+            peer: casper(),
+            steps: vec![Step::Expect("ping".to_string())],
+        };
+        let error = check_handshake(&script, chainspec(), Config::default()).await.unwrap_err();
+        assert!(matches!(error, ReplayError::Unexpected { got, .. } if got == HANDSHAKE_TAG));
+    }
+}
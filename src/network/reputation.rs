@@ -0,0 +1,276 @@
+//! Per-peer reputation.
+//!
+//! Every peer starts out with a neutral score of zero, which goes up as it
+//! completes handshakes, exchanges messages and answers pings quickly, and
+//! down as it fails to connect or handshake, breaks the protocol or answers
+//! slowly. Scores are clamped, so no amount of past good behavior makes up
+//! for a peer that started misbehaving. Peers are dialed and gossiped to best
+//! first, and the worst one makes way when we are full.
+//!
+//! Scores are kept by [`NodeId`] once the peer at an address is identified,
+//! and by address until then, so a peer reconnecting from another address
+//! picks up where it left off rather than starting over.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::history::EventKind;
+use super::history::History;
+use super::node_id::NodeId;
+use super::node_id::PeerKey;
+
+/// Lowest and highest score a peer can have.
+pub const SCORE_RANGE: (i32, i32) = (-100, 100);
+
+/// Pings answered faster than this improve the score.
+const FAST_PONG: Duration = Duration::from_millis(100);
+
+/// Pings answered slower than this hurt the score.
+const SLOW_PONG: Duration = Duration::from_secs(1);
+
+/// Something a peer did that changes its score.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Behavior {
+    /// A protocol handshake with the peer completed.
+    HandshakeCompleted,
+    /// The peer rejected our handshake, or sent one we rejected, or never
+    /// answered.
+    HandshakeFailed,
+    /// The peer could not be connected to.
+    ConnectFailed,
+    /// The peer sent something it should not have, e.g. a malformed frame.
+    ProtocolViolation,
+    /// A message from the peer was handled.
+    Exchange,
+    /// The peer answered a ping after this long.
+    Latency(Duration),
+}
+
+impl Behavior {
+    /// How much the behavior changes the peer's score.
+    pub fn weight(&self) -> i32 {
+        match self {
+            Behavior::HandshakeCompleted => 10,
+            Behavior::HandshakeFailed => -20,
+            Behavior::ConnectFailed => -20,
+            Behavior::ProtocolViolation => -50,
+            Behavior::Exchange => 1,
+            Behavior::Latency(latency) if *latency < FAST_PONG => 2,
+            Behavior::Latency(latency) if *latency > SLOW_PONG => -5,
+            Behavior::Latency(_) => 0,
+        }
+    }
+
+    /// Whether the behavior is worth a place in the [`History`]. Exchanges
+    /// and pongs happen all the time, so they are not.
+    pub fn is_notable(&self) -> bool { !matches!(self, Behavior::Exchange | Behavior::Latency(_)) }
+}
+
+/// The score of every peer that did something worth scoring.
+#[derive(Debug, Default)]
+pub struct Reputation {
+    scores: Mutex<Scores>,
+    history: Option<Arc<History>>,
+}
+
+#[derive(Debug, Default)]
+struct Scores {
+    scores: BTreeMap<PeerKey, i32>,
+    /// The node at every identified address.
+    nodes: BTreeMap<SocketAddr, NodeId>,
+}
+
+impl Scores {
+    /// What the peer at `peer` is scored as.
+    fn key(&self, peer: SocketAddr) -> PeerKey { PeerKey::of(peer, self.nodes.get(&peer).copied()) }
+
+    fn get(&self, peer: SocketAddr) -> i32 {
+        self.scores.get(&self.key(peer)).copied().unwrap_or_default()
+    }
+}
+
+impl Reputation {
+    /// Also records notable behaviors in `history`.
+    pub fn with_history(mut self, history: Arc<History>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Changes the score of `peer` according to `behavior`, and returns the
+    /// new score.
+    pub fn record(&self, peer: SocketAddr, behavior: Behavior) -> i32 {
+        let mut scores = self.scores.lock().expect("reputation lock poisoned");
+        let key = scores.key(peer);
+        let score = scores.scores.entry(key).or_default();
+        *score = score.saturating_add(behavior.weight()).clamp(SCORE_RANGE.0, SCORE_RANGE.1);
+        let score = *score;
+        drop(scores);
+        if let Some(history) = self.history.as_ref().filter(|_| behavior.is_notable()) {
+            history.record(peer, EventKind::Scored { behavior, score });
+        }
+        score
+    }
+
+    /// Records that `node` is the peer at `peer`, which is scored as the node
+    /// from now on. An unknown node takes over whatever `peer` scored so far.
+    pub fn identify(&self, peer: SocketAddr, node: NodeId) {
+        let mut scores = self.scores.lock().expect("reputation lock poisoned");
+        scores.nodes.insert(peer, node);
+        if let Some(score) = scores.scores.remove(&PeerKey::Addr(peer)) {
+            scores.scores.entry(PeerKey::Node(node)).or_insert(score);
+        }
+    }
+
+    /// Score of `peer`, zero if it did nothing worth scoring yet.
+    pub fn score(&self, peer: &SocketAddr) -> i32 {
+        self.scores.lock().expect("reputation lock poisoned").get(*peer)
+    }
+
+    /// Score of `node` at whatever address it was last at, zero if it did
+    /// nothing worth scoring yet.
+    pub fn node_score(&self, node: &NodeId) -> i32 {
+        let scores = self.scores.lock().expect("reputation lock poisoned");
+        scores.scores.get(&PeerKey::Node(*node)).copied().unwrap_or_default()
+    }
+
+    /// Score of every peer that has one, at every address it was at.
+    pub fn scores(&self) -> BTreeMap<SocketAddr, i32> {
+        let scores = self.scores.lock().expect("reputation lock poisoned");
+        let by_node = scores.nodes.iter().filter_map(|(addr, node)| {
+            let score = scores.scores.get(&PeerKey::Node(*node))?;
+            Some((*addr, *score))
+        });
+        let by_addr = scores.scores.iter().filter_map(|(key, score)| match key {
+            PeerKey::Addr(addr) => Some((*addr, *score)),
+            PeerKey::Node(_) => None,
+        });
+        by_node.chain(by_addr).collect()
+    }
+
+    /// Orders `peers` best first, keeping the given order among equal scores.
+    pub fn rank(&self, mut peers: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let scores = self.scores.lock().expect("reputation lock poisoned");
+        peers.sort_by_key(|peer| Reverse(scores.get(*peer)));
+        peers
+    }
+
+    /// The lowest scoring of `peers`, the first one among equals.
+    pub fn worst<'a>(&self, peers: impl IntoIterator<Item = &'a SocketAddr>) -> Option<SocketAddr> {
+        let scores = self.scores.lock().expect("reputation lock poisoned");
+        peers
+            .into_iter()
+            .map(|peer| (scores.get(*peer), *peer))
+            .min_by_key(|(score, _)| *score)
+            .map(|(_, peer)| peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Fingerprint;
+
+    fn peer(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn scores_follow_behavior_and_stay_in_range() {
+        let reputation = Reputation::default();
+        assert_eq!(reputation.score(&peer(1)), 0);
+
+        reputation.record(peer(1), Behavior::HandshakeCompleted);
+        reputation.record(peer(1), Behavior::Exchange);
+        reputation.record(peer(1), Behavior::Latency(Duration::from_millis(5)));
+        assert_eq!(reputation.score(&peer(1)), 13);
+        reputation.record(peer(1), Behavior::Latency(Duration::from_millis(500)));
+        assert_eq!(reputation.score(&peer(1)), 13);
+        reputation.record(peer(1), Behavior::Latency(Duration::from_secs(2)));
+        assert_eq!(reputation.score(&peer(1)), 8);
+
+        for _ in 0..5 {
+            reputation.record(peer(2), Behavior::ProtocolViolation);
+        }
+        assert_eq!(reputation.score(&peer(2)), SCORE_RANGE.0);
+        for _ in 0..20 {
+            reputation.record(peer(3), Behavior::HandshakeCompleted);
+        }
+        assert_eq!(reputation.score(&peer(3)), SCORE_RANGE.1);
+
+        assert_eq!(reputation.scores().len(), 3);
+    }
+
+    #[test]
+    fn ranks_best_first_and_finds_the_worst() {
+        let reputation = Reputation::default();
+        reputation.record(peer(2), Behavior::ConnectFailed);
+        reputation.record(peer(3), Behavior::HandshakeCompleted);
+        reputation.record(peer(4), Behavior::ProtocolViolation);
+
+        let peers = vec![peer(1), peer(2), peer(3), peer(4), peer(5)];
+        assert_eq!(
+            reputation.rank(peers.clone()),
+            vec![peer(3), peer(1), peer(5), peer(2), peer(4)]
+        );
+        assert_eq!(reputation.worst(&peers), Some(peer(4)));
+        assert_eq!(reputation.worst(&[peer(1), peer(5)]), Some(peer(1)));
+        assert_eq!(reputation.worst(&[]), None);
+    }
+
+    #[test]
+    fn scores_follow_nodes_to_new_addresses() {
+        let node = NodeId::from(Fingerprint::from_bytes([1; Fingerprint::SIZE]));
+        let reputation = Reputation::default();
+        reputation.record(peer(1), Behavior::HandshakeCompleted);
+        reputation.identify(peer(1), node);
+        reputation.record(peer(1), Behavior::HandshakeCompleted);
+        assert_eq!(reputation.node_score(&node), 20);
+
+        // The node reconnects from another port, which failed before.
+        reputation.record(peer(2), Behavior::ConnectFailed);
+        reputation.identify(peer(2), node);
+        assert_eq!(reputation.score(&peer(2)), 20);
+        reputation.record(peer(2), Behavior::ProtocolViolation);
+        assert_eq!(reputation.node_score(&node), -30);
+        assert_eq!(
+            reputation.score(&peer(1)),
+            -30,
+            "every address of the node shares its score"
+        );
+        assert_eq!(
+            reputation.scores(),
+            BTreeMap::from([(peer(1), -30), (peer(2), -30)])
+        );
+    }
+
+    #[test]
+    fn notable_behaviors_make_it_into_the_history() {
+        let history = Arc::new(History::default());
+        let reputation = Reputation::default().with_history(history.clone());
+        reputation.record(peer(1), Behavior::HandshakeCompleted);
+        reputation.record(peer(1), Behavior::Exchange);
+        reputation.record(peer(1), Behavior::Latency(Duration::from_millis(5)));
+        reputation.record(peer(2), Behavior::ConnectFailed);
+
+        let kinds: Vec<_> = history.events().into_iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::Scored {
+                    behavior: Behavior::HandshakeCompleted,
+                    score: 10
+                },
+                EventKind::Scored {
+                    behavior: Behavior::ConnectFailed,
+                    score: -20
+                },
+            ]
+        );
+    }
+}
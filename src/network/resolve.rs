@@ -0,0 +1,560 @@
+//! Bootnode addresses given by name, and connecting to one of the addresses
+//! they resolve to.
+//!
+//! Names are resolved to every A and AAAA record, which are then tried
+//! Happy Eyeballs style (RFC 8305): alternating between IPv6 and IPv4, a new
+//! attempt starts whenever the previous one fails or takes longer than
+//! [`ATTEMPT_DELAY`], and the first to connect wins.
+//!
+//! Names are looked up through a [`Resolver`], the system's by default. A
+//! bootnode is known by its name rather than the addresses it resolved to,
+//! so a [`CachingResolver`] looks it up again once its answer expired, and
+//! reconnecting follows the bootnode to a new IP.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::FutureExt;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use thiserror::Error;
+use tracing::warn;
+
+use super::error::ManagerError;
+
+/// Time to wait on a pending attempt before starting the next one.
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// The address of a bootnode, `host:port` where the host is a name or an IP.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bootnode {
+    host: String,
+    port: u16,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseBootnodeError {
+    #[error("missing port in {0:?}, expected host:port")]
+    MissingPort(String),
+    #[error("invalid port in {0:?}")]
+    InvalidPort(String),
+    #[error("missing host in {0:?}")]
+    MissingHost(String),
+}
+
+impl Bootnode {
+    pub fn host(&self) -> &str { &self.host }
+
+    pub fn port(&self) -> u16 { self.port }
+
+    /// Every address the bootnode can be reached on, in the order to try
+    /// them, looked up with the system resolver.
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>, ManagerError> {
+        self.resolve_with(&SystemResolver).await
+    }
+
+    /// Like [`Bootnode::resolve`], looking the name up with `resolver`.
+    pub async fn resolve_with(
+        &self,
+        resolver: &dyn Resolver,
+    ) -> Result<Vec<SocketAddr>, ManagerError> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, self.port)]);
+        }
+
+        let lookup = resolver
+            .lookup(&self.host, self.port)
+            .await
+            .map_err(|error| ManagerError::Resolve(self.to_string(), error))?;
+        let addrs = interleave(lookup.addrs);
+        if addrs.is_empty() {
+            let error = io::Error::new(io::ErrorKind::NotFound, "no addresses found");
+            return Err(ManagerError::Resolve(self.to_string(), error));
+        }
+        Ok(addrs)
+    }
+}
+
+impl From<SocketAddr> for Bootnode {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+        }
+    }
+}
+
+impl FromStr for Bootnode {
+    type Err = ParseBootnodeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (host, port) = value
+            .rsplit_once(':')
+            .ok_or_else(|| ParseBootnodeError::MissingPort(value.to_string()))?;
+        let port = port.parse().map_err(|_| ParseBootnodeError::InvalidPort(value.to_string()))?;
+
+        // IPv6 addresses come in brackets, so their colons are not taken for
+        // the port separator.
+        let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+        if host.is_empty() {
+            return Err(ParseBootnodeError::MissingHost(value.to_string()));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl Display for Bootnode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl Serialize for Bootnode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bootnode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Default time a failed lookup is remembered, so a name that does not
+/// resolve is not looked up again on every attempt.
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(10);
+
+/// The addresses a name resolved to, and how long they may be used for if
+/// the resolver knows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup {
+    pub addrs: Vec<SocketAddr>,
+    pub ttl: Option<Duration>,
+}
+
+/// Looks names up.
+pub trait Resolver: Send + Sync {
+    /// Every address `host` resolves to, with `port`, in no particular order.
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, io::Result<Lookup>>;
+}
+
+/// Looks names up with the resolver of the operating system, e.g.
+/// `getaddrinfo`, which does not tell the TTL of its answers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, io::Result<Lookup>> {
+        async move {
+            let addrs = tokio::net::lookup_host((host, port)).await?;
+            Ok(Lookup {
+                addrs: addrs.collect(),
+                ttl: None,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Looks names up with trust-dns, asking the name servers of the system
+/// configuration directly, so answers come with their TTL.
+#[cfg(feature = "trust-dns")]
+pub struct TrustDnsResolver(trust_dns_resolver::TokioAsyncResolver);
+
+#[cfg(feature = "trust-dns")]
+impl TrustDnsResolver {
+    /// Reads the name servers to ask from the system configuration, e.g.
+    /// `/etc/resolv.conf`.
+    pub fn from_system_conf() -> io::Result<Self> {
+        trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf()
+            .map(Self)
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(feature = "trust-dns")]
+impl Resolver for TrustDnsResolver {
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, io::Result<Lookup>> {
+        async move {
+            let lookup = self.0.lookup_ip(host).await.map_err(io::Error::other)?;
+            Ok(Lookup {
+                addrs: lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect(),
+                ttl: Some(lookup.valid_until().saturating_duration_since(Instant::now())),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// The resolver names are looked up with by default: trust-dns when built
+/// with the `trust-dns` feature and the system configuration can be read,
+/// the system resolver otherwise.
+pub fn default_resolver() -> Arc<dyn Resolver> {
+    #[cfg(feature = "trust-dns")]
+    match TrustDnsResolver::from_system_conf() {
+        Ok(resolver) => return Arc::new(resolver),
+        Err(e) => warn!("Could not read the resolver configuration, using the system's: {e}"),
+    }
+    Arc::new(SystemResolver)
+}
+
+/// What a name resolved to, kept until `expires`. Failures are kept as the
+/// kind and message of the error, which cannot be cloned.
+struct Entry {
+    expires: Instant,
+    result: Result<Lookup, (io::ErrorKind, String)>,
+}
+
+struct Cache {
+    max_ttl: Duration,
+    entries: HashMap<(String, u16), Entry>,
+}
+
+/// Keeps what another resolver answered, so names looked up again and again,
+/// like those of bootnodes being reconnected to, do not hammer it.
+///
+/// Answers are kept for their TTL, but no longer than a maximum, which is
+/// all they are kept for when the resolver does not tell their TTL. Failed
+/// lookups are kept for a while too.
+pub struct CachingResolver {
+    inner: Arc<dyn Resolver>,
+    negative_ttl: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl CachingResolver {
+    /// Keeps the answers of `inner` for up to `max_ttl`, and its failures for
+    /// `negative_ttl`.
+    pub fn new(inner: Arc<dyn Resolver>, max_ttl: Duration, negative_ttl: Duration) -> Self {
+        Self {
+            inner,
+            negative_ttl,
+            cache: Mutex::new(Cache {
+                max_ttl,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Keeps answers looked up from now on for up to `max_ttl`.
+    pub fn set_max_ttl(&self, max_ttl: Duration) { self.lock().max_ttl = max_ttl; }
+
+    /// Drops what `bootnode` resolved to, for instance after none of its
+    /// addresses could be reached, so it is looked up again next time.
+    pub fn forget(&self, bootnode: &Bootnode) {
+        self.lock().entries.remove(&(bootnode.host.clone(), bootnode.port));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Cache> { self.cache.lock().expect("resolver lock poisoned") }
+}
+
+impl Resolver for CachingResolver {
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, io::Result<Lookup>> {
+        async move {
+            let key = (host.to_string(), port);
+            if let Some(entry) = self.lock().entries.get(&key) {
+                if entry.expires > Instant::now() {
+                    return entry
+                        .result
+                        .clone()
+                        .map_err(|(kind, message)| io::Error::new(kind, message));
+                }
+            }
+
+            let result = self.inner.lookup(host, port).await;
+            let mut cache = self.lock();
+            let ttl = match &result {
+                Ok(lookup) => lookup.ttl.map_or(cache.max_ttl, |ttl| ttl.min(cache.max_ttl)),
+                Err(_) => self.negative_ttl,
+            };
+            let entry = Entry {
+                expires: Instant::now() + ttl,
+                result: match &result {
+                    Ok(lookup) => Ok(lookup.clone()),
+                    Err(e) => Err((e.kind(), e.to_string())),
+                },
+            };
+            cache.entries.insert(key, entry);
+            result
+        }
+        .boxed()
+    }
+}
+
+/// Orders addresses IPv6 first, alternating between the two families, and
+/// drops duplicates.
+pub fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+
+    let mut ordered = Vec::new();
+    loop {
+        let (next_v6, next_v4) = (v6.next(), v4.next());
+        if next_v6.is_none() && next_v4.is_none() {
+            return ordered;
+        }
+        for addr in next_v6.into_iter().chain(next_v4) {
+            if !ordered.contains(&addr) {
+                ordered.push(addr);
+            }
+        }
+    }
+}
+
+/// Races `connect` to `addrs`, returning the first address connected to.
+///
+/// Every attempt is given up on after `attempt_timeout`. If all of them fail,
+/// the error of the last one to fail is returned.
+pub async fn race<T, F, Fut>(
+    addrs: &[SocketAddr],
+    attempt_timeout: Duration,
+    connect: F,
+) -> Result<(SocketAddr, T), ManagerError>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, ManagerError>>,
+{
+    let attempt = |addr: SocketAddr| {
+        let connecting = connect(addr);
+        async move {
+            let result = tokio::time::timeout(attempt_timeout, connecting)
+                .await
+                .unwrap_or(Err(ManagerError::ConnectTimeout(addr)));
+            (addr, result)
+        }
+    };
+
+    let mut remaining = addrs.iter().copied();
+    let mut pending = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        // Every pass follows an attempt failing or the delay passing, either
+        // of which starts the next attempt.
+        if let Some(addr) = remaining.next() {
+            pending.push(attempt(addr));
+        }
+        if pending.is_empty() {
+            return Err(last_error.unwrap_or(ManagerError::NoAddresses));
+        }
+
+        let more = remaining.len() > 0;
+        tokio::select! {
+            Some((addr, result)) = pending.next() => match result {
+                Ok(connected) => return Ok((addr, connected)),
+                Err(error) => {
+                    warn!("Connecting to {addr:?} failed: {error}");
+                    last_error = Some(error);
+                }
+            },
+            _ = tokio::time::sleep(ATTEMPT_DELAY), if more => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    fn addr(addr: &str) -> SocketAddr { addr.parse().unwrap() }
+
+    #[test]
+    fn parses_names_and_ip_addresses() {
+        let bootnode: Bootnode = "node.example.com:35000".parse().unwrap();
+        assert_eq!(
+            (bootnode.host(), bootnode.port()),
+            ("node.example.com", 35000)
+        );
+        assert_eq!(bootnode.to_string(), "node.example.com:35000");
+
+        let bootnode: Bootnode = "[::1]:35000".parse().unwrap();
+        assert_eq!(bootnode.host(), "::1");
+        assert_eq!(bootnode, Bootnode::from(addr("[::1]:35000")));
+        assert_eq!(bootnode.to_string(), "[::1]:35000");
+
+        assert!(matches!(
+            "node.example.com".parse::<Bootnode>(),
+            Err(ParseBootnodeError::MissingPort(_))
+        ));
+        assert!(matches!(
+            ":35000".parse::<Bootnode>(),
+            Err(ParseBootnodeError::MissingHost(_))
+        ));
+    }
+
+    #[test]
+    fn interleaves_address_families_ipv6_first() {
+        let ordered = interleave(vec![
+            addr("10.0.0.1:1"),
+            addr("10.0.0.2:1"),
+            addr("10.0.0.1:1"),
+            addr("[::1]:1"),
+        ]);
+        assert_eq!(
+            ordered,
+            [addr("[::1]:1"), addr("10.0.0.1:1"), addr("10.0.0.2:1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_localhost() {
+        let bootnode: Bootnode = "localhost:35000".parse().unwrap();
+        let addrs = bootnode.resolve().await.unwrap();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback() && addr.port() == 35000));
+    }
+
+    /// Answers every lookup with the same result, counting them.
+    struct Fixed {
+        answer: Option<Lookup>,
+        lookups: AtomicUsize,
+    }
+
+    impl Fixed {
+        fn new(answer: Option<Lookup>) -> Arc<Self> {
+            Arc::new(Self {
+                answer,
+                lookups: AtomicUsize::new(0),
+            })
+        }
+
+        fn lookups(&self) -> usize { self.lookups.load(Ordering::Relaxed) }
+    }
+
+    impl Resolver for Fixed {
+        fn lookup<'a>(&'a self, _host: &'a str, _port: u16) -> BoxFuture<'a, io::Result<Lookup>> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            let answer = self.answer.clone().ok_or(io::ErrorKind::NotFound.into());
+            async move { answer }.boxed()
+        }
+    }
+
+    fn answer(ttl: Option<Duration>) -> Option<Lookup> {
+        Some(Lookup {
+            addrs: vec![addr("10.0.0.1:35000")],
+            ttl,
+        })
+    }
+
+    #[tokio::test]
+    async fn answers_are_kept_for_their_ttl_up_to_the_maximum() {
+        let bootnode: Bootnode = "node.example.com:35000".parse().unwrap();
+        let hour = Duration::from_secs(60 * 60);
+        for (ttl, max_ttl, kept) in [
+            (None, hour, true),
+            (None, Duration::ZERO, false),
+            (Some(hour), hour, true),
+            (Some(Duration::ZERO), hour, false),
+            (Some(hour), Duration::ZERO, false),
+        ] {
+            let fixed = Fixed::new(answer(ttl));
+            let resolver = CachingResolver::new(fixed.clone(), max_ttl, hour);
+            for _ in 0..2 {
+                let addrs = bootnode.resolve_with(&resolver).await.unwrap();
+                assert_eq!(addrs, [addr("10.0.0.1:35000")]);
+            }
+            assert_eq!(
+                fixed.lookups(),
+                if kept { 1 } else { 2 },
+                "{ttl:?} {max_ttl:?}"
+            );
+
+            resolver.set_max_ttl(hour);
+            resolver.forget(&bootnode);
+            bootnode.resolve_with(&resolver).await.unwrap();
+            assert_eq!(fixed.lookups(), if kept { 2 } else { 3 });
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_are_kept_until_forgotten() {
+        let bootnode: Bootnode = "missing.example.com:35000".parse().unwrap();
+        let fixed = Fixed::new(None);
+        let hour = Duration::from_secs(60 * 60);
+        let resolver = CachingResolver::new(fixed.clone(), hour, hour);
+        for _ in 0..2 {
+            let error = bootnode.resolve_with(&resolver).await.unwrap_err();
+            assert!(matches!(
+                error,
+                ManagerError::Resolve(_, error) if error.kind() == io::ErrorKind::NotFound
+            ));
+        }
+        assert_eq!(fixed.lookups(), 1);
+
+        resolver.forget(&bootnode);
+        bootnode.resolve_with(&resolver).await.unwrap_err();
+        assert_eq!(fixed.lookups(), 2);
+    }
+
+    #[tokio::test]
+    async fn ip_addresses_are_not_looked_up() {
+        let fixed = Fixed::new(None);
+        let bootnode = Bootnode::from(addr("[::1]:35000"));
+        assert_eq!(
+            bootnode.resolve_with(&*fixed).await.unwrap(),
+            [addr("[::1]:35000")]
+        );
+        assert_eq!(fixed.lookups(), 0);
+    }
+
+    #[tokio::test]
+    async fn slow_address_is_overtaken_by_the_next() {
+        let slow = addr("[::1]:1");
+        let fast = addr("10.0.0.1:1");
+        let (winner, ()) = race(&[slow, fast], Duration::from_secs(10), |addr| async move {
+            if addr == slow {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, fast);
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_move_on_and_report_the_last_error() {
+        let addrs = [addr("[::1]:1"), addr("10.0.0.1:1")];
+        let result: Result<(SocketAddr, ()), _> =
+            race(&addrs, Duration::from_millis(100), |addr| async move {
+                if addr.is_ipv6() {
+                    Err(ManagerError::PeerNotFound)
+                } else {
+                    std::future::pending().await
+                }
+            })
+            .await;
+        assert!(matches!(
+            result,
+            Err(ManagerError::ConnectTimeout(addr)) if addr == addrs[1]
+        ));
+    }
+}
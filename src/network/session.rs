@@ -0,0 +1,211 @@
+//! A single connection to a peer, for code that wants to talk to one peer
+//! directly rather than run a [`Manager`](super::manager::Manager).
+//!
+//! [`connect`] does what the manager does when it dials a peer: connects over
+//! TLS, checks the certificate the peer presents, and exchanges handshakes.
+//! What comes out is a [`PeerSession`] exchanging typed messages, so commands
+//! do not each reimplement that sequence.
+//!
+//! Sessions advertise neither compression, multiplexing nor checksums, so
+//! frames stay as Casper nodes send them by default, and carry no consensus
+//! certificate.
+
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use futures::SinkExt;
+use futures::StreamExt;
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+use tracing::info;
+use tracing::instrument;
+use tracing::trace;
+
+use super::compression::FrameCodec;
+use super::config::Config;
+use super::error::HandshakeError;
+use super::error::ManagerError;
+use super::handshake::Handshake;
+use super::handshake::ReplayGuard;
+use super::manager::HANDSHAKE_TIMEOUT;
+use super::manager::MAX_FRAME_LEN;
+use super::message::BincodeFormat;
+use super::message::Message;
+use super::message::MessagePackFormat;
+use super::tls;
+use super::tls::Certificate;
+use super::tls::Identity;
+use super::tls::SessionId;
+use super::tls::TlsStream;
+use super::transport::TlsTransport;
+use crate::primitives::Chainspec;
+use crate::primitives::Payload;
+use crate::utils::Fingerprint;
+
+/// Connects to the peer at `addr` as `identity`, with the default network
+/// [`Config`], and completes the handshake against `chainspec`.
+pub async fn connect<P: Payload>(
+    addr: SocketAddr,
+    identity: &Identity,
+    chainspec: &Chainspec,
+) -> Result<PeerSession<P>, ManagerError> {
+    connect_with_config(addr, identity, chainspec, &Config::default()).await
+}
+
+/// Like [`connect`], with the TLS options and version policy of `config`.
+#[instrument(name = "peer_session", skip_all, fields(peer = %addr))]
+pub async fn connect_with_config<P: Payload>(
+    addr: SocketAddr,
+    identity: &Identity,
+    chainspec: &Chainspec,
+    config: &Config,
+) -> Result<PeerSession<P>, ManagerError> {
+    let transport = TlsTransport::new(identity.clone(), config.tls_options());
+    let (stream, peer_cert) = transport.connect_validated(addr).await?;
+    let fingerprint = tls::cert_fingerprint(&peer_cert)?;
+    let session_id = SessionId::of(&stream, &identity.fingerprint(), &fingerprint);
+    // We do not listen for the peer, so the best address to give is the one
+    // it sees us on.
+    let local_addr = stream
+        .get_ref()
+        .local_addr()
+        .map_err(|error| ManagerError::SendFailed(error.to_string()))?;
+
+    let mut frames = Framed::new(stream, FrameCodec::new(MAX_FRAME_LEN));
+    let ours = Handshake::new(chainspec, local_addr).encode::<P>()?;
+    frames
+        .send(ours)
+        .await
+        .map_err(|error| ManagerError::SendFailed(error.to_string()))?;
+
+    let received = tokio::time::timeout(HANDSHAKE_TIMEOUT, recv_handshake(&mut frames)).await;
+    let handshake = received
+        .map_err(|_| ManagerError::HandshakeTimeout(addr))?
+        .and_then(|handshake| {
+            handshake.negotiate(chainspec, config.allow_version_mismatch)?;
+            handshake.check_stamp(
+                Some(&session_id),
+                config.handshake_max_age,
+                config.accept_unstamped_certificates,
+                &ReplayGuard::default(),
+            )?;
+            Ok(handshake)
+        })
+        .map_err(|error| ManagerError::HandshakeRejected(addr, error))?;
+    info!("Handshake complete with {addr:?}");
+
+    Ok(PeerSession {
+        addr,
+        frames,
+        peer_cert,
+        fingerprint,
+        session_id,
+        handshake,
+        _payload: PhantomData,
+    })
+}
+
+/// Waits for the peer's handshake, skipping whatever it sends before.
+async fn recv_handshake(
+    frames: &mut Framed<TlsStream<TcpStream>, FrameCodec>,
+) -> Result<Handshake, HandshakeError> {
+    while let Some(frame) = frames.next().await {
+        let Ok(frame) = frame else {
+            break;
+        };
+        let message: Result<Message<()>, _> = MessagePackFormat.deserialize_arbitrary(&frame);
+        match message.ok().as_ref().and_then(Handshake::from_message) {
+            Some(handshake) => return Ok(handshake),
+            None => trace!("Ignoring a frame sent before the handshake"),
+        }
+    }
+    Err(HandshakeError::ConnectionClosed)
+}
+
+/// An established connection to a peer whose certificate was checked and
+/// whose handshake was accepted.
+pub struct PeerSession<P> {
+    addr: SocketAddr,
+    frames: Framed<TlsStream<TcpStream>, FrameCodec>,
+    peer_cert: Certificate,
+    fingerprint: Fingerprint,
+    session_id: SessionId,
+    handshake: Handshake,
+    _payload: PhantomData<P>,
+}
+
+impl<P: Payload> PeerSession<P> {
+    /// The address the peer was dialed on.
+    pub fn addr(&self) -> SocketAddr { self.addr }
+
+    /// The certificate the peer presented.
+    pub fn peer_certificate(&self) -> &Certificate { &self.peer_cert }
+
+    /// Fingerprint of the peer's certificate, the one it is known by.
+    pub fn fingerprint(&self) -> Fingerprint { self.fingerprint }
+
+    /// The TLS session, which a consensus certificate sent to the peer signs.
+    pub fn session_id(&self) -> SessionId { self.session_id }
+
+    /// The handshake the peer sent.
+    pub fn handshake(&self) -> &Handshake { &self.handshake }
+
+    /// Sends `message` to the peer.
+    pub async fn send(&mut self, message: Message<P>) -> Result<(), ManagerError> {
+        let encoded = BincodeFormat::default()
+            .serialize_arbitrary(&message)
+            .map_err(|error| ManagerError::CouldNotEncodeMessage(error.to_string()))?;
+        self.send_frame(Bytes::from(encoded)).await
+    }
+
+    /// Sends `payload` to the peer.
+    pub async fn send_payload(&mut self, payload: P) -> Result<(), ManagerError> {
+        self.send(Message::Payload(payload)).await
+    }
+
+    /// Waits for the next message from the peer, `None` once it closed the
+    /// connection.
+    ///
+    /// Pings are answered right away and not handed out, so the peer keeps
+    /// us connected however rarely this is called.
+    pub async fn recv(&mut self) -> Result<Option<Message<P>>, ManagerError> {
+        loop {
+            let Some(frame) = self.frames.next().await else {
+                return Ok(None);
+            };
+            let frame = frame.map_err(|error| ManagerError::ReceiveFailed(error.to_string()))?;
+
+            // Handshakes are msgpack, every other message bincode.
+            let handshake: Result<Message<P>, _> = MessagePackFormat.deserialize_arbitrary(&frame);
+            if let Ok(message @ Message::Handshake { .. }) = handshake {
+                return Ok(Some(message));
+            }
+            let message: Message<P> = BincodeFormat::default()
+                .deserialize_arbitrary(&frame)
+                .map_err(|e| ManagerError::CouldNotDecodeMessage(self.addr, e.to_string()))?;
+            match message {
+                Message::Ping { nonce } => {
+                    trace!("Answering a ping from {:?}", self.addr);
+                    self.send(Message::Pong { nonce }).await?;
+                }
+                message => return Ok(Some(message)),
+            }
+        }
+    }
+
+    /// Closes the connection, telling the peer.
+    pub async fn close(mut self) -> Result<(), ManagerError> {
+        self.frames
+            .close()
+            .await
+            .map_err(|error| ManagerError::SendFailed(error.to_string()))
+    }
+
+    async fn send_frame(&mut self, frame: Bytes) -> Result<(), ManagerError> {
+        self.frames
+            .send(frame)
+            .await
+            .map_err(|error| ManagerError::SendFailed(error.to_string()))
+    }
+}
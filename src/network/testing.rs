@@ -0,0 +1,165 @@
+//! In-memory transport for running many nodes in a single process.
+//!
+//! Every node attached to the same [`MemoryNetwork`] can reach the others by
+//! their listening address. Connections are plain in-memory pipes: no ports
+//! are bound and no TLS is involved, while everything above the byte stream
+//! (framing, handshakes, gossip) runs exactly as over TCP.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+
+use super::error::ManagerError;
+use super::error::TLSError;
+use super::transport::Accepted;
+use super::transport::BoxedStream;
+use super::transport::Listener;
+use super::transport::Transport;
+
+/// Bytes buffered in each direction of an in-memory connection.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// First port handed out for port 0 binds and outgoing connections.
+const FIRST_EPHEMERAL_PORT: u16 = 40_000;
+
+type Incoming = mpsc::UnboundedSender<(DuplexStream, SocketAddr)>;
+
+#[derive(Debug)]
+struct NetworkState {
+    listeners: BTreeMap<SocketAddr, Incoming>,
+    next_port: u16,
+}
+
+/// A set of in-memory listeners, shared by every clone.
+#[derive(Clone, Debug)]
+pub struct MemoryNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl Default for MemoryNetwork {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetworkState {
+                listeners: BTreeMap::new(),
+                next_port: FIRST_EPHEMERAL_PORT,
+            })),
+        }
+    }
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self { Self::default() }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, NetworkState> {
+        self.state.lock().expect("memory network lock poisoned")
+    }
+
+    fn ephemeral(state: &mut NetworkState, ip: std::net::IpAddr) -> SocketAddr {
+        let port = state.next_port;
+        state.next_port = state.next_port.checked_add(1).expect("ran out of ports");
+        SocketAddr::new(ip, port)
+    }
+}
+
+impl Transport for MemoryNetwork {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, Result<Box<dyn Listener>, ManagerError>> {
+        let result = (|| {
+            let mut state = self.lock();
+            let addr = if addr.port() == 0 {
+                Self::ephemeral(&mut state, addr.ip())
+            } else {
+                addr
+            };
+            if state.listeners.contains_key(&addr) {
+                let error = io::Error::from(io::ErrorKind::AddrInUse);
+                return Err(ManagerError::ListenerCreation(error, addr));
+            }
+
+            let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+            state.listeners.insert(addr, incoming_tx);
+            Ok(Box::new(MemoryListener {
+                network: self.clone(),
+                addr,
+                incoming: incoming_rx,
+            }) as Box<dyn Listener>)
+        })();
+        futures::future::ready(result).boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<BoxedStream, ManagerError>> {
+        let result = (|| {
+            let mut state = self.lock();
+            let refused = || TLSError::TcpConnection(io::ErrorKind::ConnectionRefused.into());
+            let incoming = state.listeners.get(&addr).ok_or_else(refused)?.clone();
+
+            let from = Self::ephemeral(&mut state, addr.ip());
+            let (ours, theirs) = tokio::io::duplex(PIPE_CAPACITY);
+            incoming.send((theirs, from)).map_err(|_| refused())?;
+            Ok(Box::new(ours) as BoxedStream)
+        })();
+        futures::future::ready(result).boxed()
+    }
+}
+
+struct MemoryListener {
+    network: MemoryNetwork,
+    addr: SocketAddr,
+    incoming: mpsc::UnboundedReceiver<(DuplexStream, SocketAddr)>,
+}
+
+impl Listener for MemoryListener {
+    fn local_addr(&self) -> SocketAddr { self.addr }
+
+    fn accept(&mut self) -> BoxFuture<'_, Result<Accepted, ManagerError>> {
+        async move {
+            match self.incoming.recv().await {
+                Some((stream, from)) => Ok(Accepted::ready(Box::new(stream), from)),
+                // The sending half lives in the network until we drop it.
+                None => unreachable!("listener unregistered while accepting"),
+            }
+        }
+        .boxed()
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) { self.network.lock().listeners.remove(&self.addr); }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[tokio::test]
+    async fn connects_to_bound_listeners_only() {
+        let network = MemoryNetwork::new();
+        let mut listener = network.bind(addr(5000)).await.unwrap();
+        assert!(network.bind(addr(5000)).await.is_err());
+
+        let mut client = network.connect(addr(5000)).await.unwrap();
+        let accepted = listener.accept().await.unwrap();
+        assert_ne!(accepted.peer_addr, addr(5000));
+        let mut server = accepted.setup.await.unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        drop(listener);
+        assert!(network.connect(addr(5000)).await.is_err());
+        assert!(network.connect(addr(5001)).await.is_err());
+    }
+}
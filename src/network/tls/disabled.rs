@@ -0,0 +1,201 @@
+//! No TLS at all, for builds without the `openssl` feature.
+//!
+//! No key can be generated or decoded, so there is never a key, certificate
+//! or stream to work with, and every attempt to get one fails with
+//! [`TLSError::NoBackend`]. The node builds, but cannot talk to peers.
+
+use std::io;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+use super::Backend;
+use super::CertSubject;
+use super::Encoding;
+use super::Identity;
+use super::Negotiated;
+use super::TlsOptions;
+use super::ValidationReport;
+use super::SESSION_RANDOM_LEN;
+use crate::network::error::TLSError;
+use crate::utils::Fingerprint;
+
+/// The backend of builds without TLS.
+#[derive(Clone, Copy, Debug)]
+pub enum Disabled {}
+
+/// Stands in for keys and certificates, none of which can exist.
+#[derive(Clone, Debug)]
+pub enum Never {}
+
+/// Stands in for TLS connections, none of which can be set up.
+pub struct NoStream<S> {
+    never: Never,
+    _stream: PhantomData<S>,
+}
+
+impl Backend for Disabled {
+    type Certificate = Never;
+    type SecretKey = Never;
+    type Stream<S: AsyncRead + AsyncWrite + Unpin + Send> = NoStream<S>;
+
+    fn version() -> String { "none".to_string() }
+
+    fn generate_key() -> Result<Never, TLSError> { Err(TLSError::NoBackend) }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn key_from_seed(_seed: u64) -> Result<Never, TLSError> { Err(TLSError::NoBackend) }
+
+    fn self_signed(key: &Never, _subject: &CertSubject) -> Result<Never, TLSError> { match *key {} }
+
+    fn key_fingerprint(key: &Never) -> Fingerprint { match *key {} }
+
+    fn cert_public_key(cert: &Never) -> Result<Vec<u8>, TLSError> { match *cert {} }
+
+    fn is_for_key(cert: &Never, _key: &Never) -> Result<bool, TLSError> { match *cert {} }
+
+    fn is_signed_by(cert: &Never, _ca: &Never) -> Result<bool, TLSError> { match *cert {} }
+
+    fn verify_chain(
+        cert: &Never,
+        _chain: &[Never],
+        _ca: &Never,
+        _clock_skew: Duration,
+    ) -> Result<(), TLSError> {
+        match *cert {}
+    }
+
+    fn check_validity_period(cert: &Never, _clock_skew: Duration) -> Result<(), TLSError> {
+        match *cert {}
+    }
+
+    fn subject(cert: &Never) -> Result<CertSubject, TLSError> { match *cert {} }
+
+    fn valid_for(cert: &Never) -> Result<Duration, TLSError> { match *cert {} }
+
+    fn validate(cert: &Never, _clock_skew: Duration) -> ValidationReport { match *cert {} }
+
+    fn encode_key(key: &Never, _encoding: Encoding) -> Result<Vec<u8>, TLSError> { match *key {} }
+
+    fn decode_key(_encoded: &[u8], _encoding: Encoding) -> Result<Never, TLSError> {
+        Err(TLSError::NoBackend)
+    }
+
+    fn encode_cert(cert: &Never, _encoding: Encoding) -> Result<Vec<u8>, TLSError> {
+        match *cert {}
+    }
+
+    fn decode_cert(_encoded: &[u8], _encoding: Encoding) -> Result<Never, TLSError> {
+        Err(TLSError::NoBackend)
+    }
+
+    fn decode_cert_bundle(_pem: &[u8]) -> Result<Vec<Never>, TLSError> { Err(TLSError::NoBackend) }
+
+    fn check_options(_options: &TlsOptions) -> Result<(), TLSError> { Err(TLSError::NoBackend) }
+
+    fn client<S>(
+        _stream: S,
+        identity: &Identity,
+        _options: &TlsOptions,
+    ) -> Result<NoStream<S>, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match *identity.secret_key {}
+    }
+
+    fn server<S>(
+        _stream: S,
+        identity: &Identity,
+        _options: &TlsOptions,
+    ) -> Result<NoStream<S>, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match *identity.secret_key {}
+    }
+
+    fn connect<'a, S>(stream: &'a mut NoStream<S>) -> BoxFuture<'a, Result<(), TLSError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'a,
+    {
+        match stream.never {}
+    }
+
+    fn accept<'a, S>(stream: &'a mut NoStream<S>) -> BoxFuture<'a, Result<(), TLSError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'a,
+    {
+        match stream.never {}
+    }
+
+    fn peer_certificate<S>(stream: &NoStream<S>) -> Result<Never, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match stream.never {}
+    }
+
+    fn peer_chain<S>(stream: &NoStream<S>) -> Vec<Never>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match stream.never {}
+    }
+
+    fn negotiated<S>(stream: &NoStream<S>) -> Negotiated
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match stream.never {}
+    }
+
+    fn randoms<S>(stream: &NoStream<S>) -> [[u8; SESSION_RANDOM_LEN]; 2]
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match stream.never {}
+    }
+
+    fn get_ref<S>(stream: &NoStream<S>) -> &S
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        match stream.never {}
+    }
+}
+
+impl<S> AsyncRead for NoStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.never {}
+    }
+}
+
+impl<S> AsyncWrite for NoStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.never {}
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.never {}
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.never {}
+    }
+}
@@ -0,0 +1,1211 @@
+//! The OpenSSL backend, the default one.
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+use std::cmp::Ordering;
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use casper_types::TimeDiff;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use openssl::asn1::Asn1Integer;
+use openssl::asn1::Asn1IntegerRef;
+use openssl::asn1::Asn1Time;
+use openssl::asn1::Asn1TimeRef;
+use openssl::bn::BigNum;
+#[cfg(any(test, feature = "testing"))]
+use openssl::bn::BigNumContext;
+use openssl::bn::BigNumRef;
+use openssl::ec;
+use openssl::ec::EcKey;
+#[cfg(any(test, feature = "testing"))]
+use openssl::ec::EcPoint;
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::pkey::PKey;
+use openssl::pkey::PKeyRef;
+use openssl::pkey::Private;
+use openssl::pkey::Public;
+use openssl::ssl::Ssl;
+use openssl::ssl::SslAcceptor;
+use openssl::ssl::SslConnector;
+use openssl::ssl::SslContext;
+use openssl::ssl::SslContextBuilder;
+use openssl::ssl::SslMethod;
+use openssl::ssl::SslRef;
+use openssl::ssl::SslVerifyMode;
+use openssl::ssl::SslVersion;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::X509Builder;
+use openssl::x509::X509Name;
+use openssl::x509::X509NameBuilder;
+use openssl::x509::X509NameRef;
+use openssl::x509::X509Ref;
+use openssl::x509::X509StoreContext;
+use openssl::x509::X509;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio_openssl::SslStream;
+
+use super::Backend;
+use super::CertCheck;
+use super::CertSubject;
+use super::Encoding;
+use super::Identity;
+use super::Negotiated;
+use super::TlsOptions;
+use super::ValidationReport;
+use super::SESSION_RANDOM_LEN;
+use crate::network::error::TLSError;
+use crate::utils::Fingerprint;
+use crate::utils::Sha512;
+
+pub type SslResult<T> = Result<T, ErrorStack>;
+
+/// The chosen signature algorithm (**ECDSA  with SHA512**).
+const SIGNATURE_ALGORITHM: Nid = Nid::ECDSA_WITH_SHA512;
+
+/// The underlying elliptic curve (**P-521**).
+const SIGNATURE_CURVE: Nid = Nid::SECP521R1;
+
+/// The chosen signature algorithm (**SHA512**).
+pub const SIGNATURE_DIGEST: Nid = Nid::SHA512;
+
+/// TLS by OpenSSL, linked through the `openssl` crate.
+#[derive(Clone, Copy, Debug)]
+pub enum OpenSsl {}
+
+impl Backend for OpenSsl {
+    type Certificate = X509;
+    type SecretKey = PKey<Private>;
+    type Stream<S: AsyncRead + AsyncWrite + Unpin + Send> = OpenSslStream<S>;
+
+    fn version() -> String { openssl::version::version().to_string() }
+
+    fn generate_key() -> Result<PKey<Private>, TLSError> {
+        generate_private_key().map_err(generation_error)
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn key_from_seed(seed: u64) -> Result<PKey<Private>, TLSError> {
+        let derive = || -> SslResult<PKey<Private>> {
+            let group = ec::EcGroup::from_curve_name(SIGNATURE_CURVE)?;
+            let context = BigNumContext::new()?;
+            // 512 bits, always below the order of the 521 bit curve.
+            let scalar = BigNum::from_slice(&openssl::sha::sha512(&seed.to_be_bytes()))?;
+            let mut public_point = EcPoint::new(&group)?;
+            public_point.mul_generator(&group, &scalar, &context)?;
+            let ec_key = EcKey::from_private_components(&group, &scalar, &public_point)?;
+            PKey::from_ec_key(ec_key)
+        };
+        derive().map_err(generation_error)
+    }
+
+    fn self_signed(key: &PKey<Private>, subject: &CertSubject) -> Result<X509, TLSError> {
+        generate_cert(key, subject).map_err(generation_error)
+    }
+
+    fn subject(cert: &X509) -> Result<CertSubject, TLSError> {
+        let name = cert.subject_name();
+        let entry = |nid: Nid| -> Result<String, TLSError> {
+            match name.entries_by_nid(nid).next() {
+                Some(entry) => Ok(entry
+                    .data()
+                    .as_utf8()
+                    .map_err(|_| TLSError::CorruptSubjectOrIssuer)?
+                    .to_string()),
+                None => Ok(String::new()),
+            }
+        };
+        Ok(CertSubject {
+            country: entry(Nid::COUNTRYNAME)?,
+            org: entry(Nid::ORGANIZATIONNAME)?,
+            common_name: entry(Nid::COMMONNAME)?,
+        })
+    }
+
+    fn key_fingerprint(key: &PKey<Private>) -> Fingerprint {
+        let public_key = key.public_key_to_der().expect("a generated key always encodes to DER");
+        Fingerprint::of(public_key)
+    }
+
+    fn cert_public_key(cert: &X509) -> Result<Vec<u8>, TLSError> {
+        cert.public_key()
+            .and_then(|key| key.public_key_to_der())
+            .map_err(|_| TLSError::CannotReadPublicKey)
+    }
+
+    fn is_for_key(cert: &X509, key: &PKey<Private>) -> Result<bool, TLSError> {
+        let public_key = cert.public_key().map_err(|_| TLSError::CannotReadPublicKey)?;
+        Ok(key.public_eq(&public_key))
+    }
+
+    fn is_signed_by(cert: &X509, ca: &X509) -> Result<bool, TLSError> {
+        let ca_key = ca.public_key().map_err(|_| TLSError::CannotReadPublicKey)?;
+        cert.verify(&ca_key).map_err(|_| TLSError::FailedToValidateSignature)
+    }
+
+    fn verify_chain(
+        cert: &X509,
+        chain: &[X509],
+        ca: &X509,
+        clock_skew: Duration,
+    ) -> Result<(), TLSError> {
+        let verify = || -> SslResult<Result<(), TLSError>> {
+            let mut store = X509StoreBuilder::new()?;
+            store.add_cert(ca.clone())?;
+            // OpenSSL allows for no clock skew, so the validity periods are
+            // checked below instead.
+            store.set_flags(X509VerifyFlags::NO_CHECK_TIME)?;
+            let store = store.build();
+            let mut untrusted = Stack::new()?;
+            for cert in chain {
+                untrusted.push(cert.clone())?;
+            }
+            let mut context = X509StoreContext::new()?;
+            context.init(&store, cert, &untrusted, |context| {
+                if !context.verify_cert()? {
+                    return Ok(Err(TLSError::UntrustedChain(context.error().to_string())));
+                }
+                let mut chain = context.chain().into_iter().flatten();
+                Ok(chain.try_for_each(|cert| validate_cert_expiration_date(cert, clock_skew)))
+            })
+        };
+        verify().map_err(|error| TLSError::UntrustedChain(error.to_string()))?
+    }
+
+    fn check_validity_period(cert: &X509, clock_skew: Duration) -> Result<(), TLSError> {
+        validate_cert_expiration_date(cert, clock_skew)
+    }
+
+    fn valid_for(cert: &X509) -> Result<Duration, TLSError> {
+        let asn1_now = Asn1Time::from_unix(now()).map_err(|_| TLSError::TimeIssue)?;
+        let left = asn1_now.diff(cert.not_after()).map_err(|_| TLSError::TimeIssue)?;
+        let secs = i64::from(left.days) * 24 * 60 * 60 + i64::from(left.secs);
+        Ok(Duration::from_secs(secs.try_into().unwrap_or_default()))
+    }
+
+    fn validate(cert: &X509, clock_skew: Duration) -> ValidationReport {
+        validate_peer_cert_detailed(cert, clock_skew)
+    }
+
+    fn encode_key(key: &PKey<Private>, encoding: Encoding) -> Result<Vec<u8>, TLSError> {
+        match encoding {
+            Encoding::Pem => key.private_key_to_pem_pkcs8(),
+            Encoding::Der => key.private_key_to_pkcs8(),
+        }
+        .map_err(|error| TLSError::CouldNotEncodeIdentity(error.to_string()))
+    }
+
+    fn decode_key(encoded: &[u8], encoding: Encoding) -> Result<PKey<Private>, TLSError> {
+        match encoding {
+            Encoding::Pem => PKey::private_key_from_pem(encoded),
+            Encoding::Der => PKey::private_key_from_der(encoded),
+        }
+        .map_err(|error| TLSError::CouldNotDecodeIdentity(error.to_string()))
+    }
+
+    fn encode_cert(cert: &X509, encoding: Encoding) -> Result<Vec<u8>, TLSError> {
+        match encoding {
+            Encoding::Pem => cert.to_pem(),
+            Encoding::Der => cert.to_der(),
+        }
+        .map_err(|error| TLSError::CouldNotEncodeIdentity(error.to_string()))
+    }
+
+    fn decode_cert(encoded: &[u8], encoding: Encoding) -> Result<X509, TLSError> {
+        match encoding {
+            Encoding::Pem => X509::from_pem(encoded),
+            Encoding::Der => X509::from_der(encoded),
+        }
+        .map_err(|error| TLSError::CouldNotDecodeIdentity(error.to_string()))
+    }
+
+    fn decode_cert_bundle(pem: &[u8]) -> Result<Vec<X509>, TLSError> {
+        X509::stack_from_pem(pem)
+            .map_err(|error| TLSError::CouldNotDecodeIdentity(error.to_string()))
+    }
+
+    fn check_options(options: &TlsOptions) -> Result<(), TLSError> {
+        let mut ctx = SslContext::builder(SslMethod::tls())
+            .map_err(|error| TLSError::TlsInitialization(error.to_string()))?;
+        for suite in &options.ciphersuites {
+            ctx.set_ciphersuites(suite)
+                .map_err(|_| TLSError::UnknownCipherSuite(suite.clone()))?;
+        }
+        for group in &options.groups {
+            ctx.set_groups_list(group).map_err(|_| TLSError::UnknownGroup(group.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn client<S>(
+        stream: S,
+        identity: &Identity,
+        options: &TlsOptions,
+    ) -> Result<OpenSslStream<S>, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let ssl = create_tls_connector(
+            &identity.tls_certificate,
+            &identity.intermediates,
+            &identity.secret_key,
+            options,
+        )
+        .and_then(|connector| connector.configure())
+        .and_then(|mut config| {
+            config.set_verify_hostname(false);
+            config.into_ssl("this-will-not-be-checked.example.com")
+        })
+        .map_err(|error| TLSError::TlsInitialization(error.to_string()))?;
+        OpenSslStream::new(ssl, stream)
+    }
+
+    fn server<S>(
+        stream: S,
+        identity: &Identity,
+        options: &TlsOptions,
+    ) -> Result<OpenSslStream<S>, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let ssl = create_tls_acceptor(
+            &identity.tls_certificate,
+            &identity.intermediates,
+            &identity.secret_key,
+            options,
+        )
+        .and_then(|acceptor| Ssl::new(acceptor.context()))
+        .map_err(|error| TLSError::TlsInitialization(error.to_string()))?;
+        OpenSslStream::new(ssl, stream)
+    }
+
+    fn connect<'a, S>(stream: &'a mut OpenSslStream<S>) -> BoxFuture<'a, Result<(), TLSError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'a,
+    {
+        async move {
+            Pin::new(&mut stream.inner)
+                .connect()
+                .await
+                .map_err(|error| TLSError::TlsHandshake(error.to_string()))
+        }
+        .boxed()
+    }
+
+    fn accept<'a, S>(stream: &'a mut OpenSslStream<S>) -> BoxFuture<'a, Result<(), TLSError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'a,
+    {
+        async move {
+            Pin::new(&mut stream.inner)
+                .accept()
+                .await
+                .map_err(|error| TLSError::TlsHandshake(error.to_string()))
+        }
+        .boxed()
+    }
+
+    fn peer_certificate<S>(stream: &OpenSslStream<S>) -> Result<X509, TLSError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        stream.ssl().peer_certificate().ok_or(TLSError::NoPeerCertificate)
+    }
+
+    fn peer_chain<S>(stream: &OpenSslStream<S>) -> Vec<X509>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        stream.ssl().peer_cert_chain().map_or_else(Vec::new, |chain| {
+            chain.iter().map(ToOwned::to_owned).collect()
+        })
+    }
+
+    fn negotiated<S>(stream: &OpenSslStream<S>) -> Negotiated
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        negotiated(stream.ssl())
+    }
+
+    fn randoms<S>(stream: &OpenSslStream<S>) -> [[u8; SESSION_RANDOM_LEN]; 2]
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let mut client_random = [0; SESSION_RANDOM_LEN];
+        let mut server_random = [0; SESSION_RANDOM_LEN];
+        stream.ssl().client_random(&mut client_random);
+        stream.ssl().server_random(&mut server_random);
+        [client_random, server_random]
+    }
+
+    fn get_ref<S>(stream: &OpenSslStream<S>) -> &S
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        stream.inner.get_ref()
+    }
+}
+
+fn generation_error(error: ErrorStack) -> TLSError {
+    TLSError::CouldNotGenerateTlsCertificate(error.to_string())
+}
+
+/// Hash of the public key in `cert`, the fingerprint its owner is known by.
+pub fn cert_fingerprint(cert: &X509Ref) -> SslResult<Fingerprint> {
+    Ok(Fingerprint::of(cert.public_key()?.public_key_to_der()?))
+}
+
+/// Generates a self-signed (key, certificate) pair suitable for TLS and
+/// signing.
+///
+/// The certificate is for the default [`CertSubject`], with common name
+/// "casper-node".
+pub fn generate_node_cert() -> SslResult<(X509, PKey<Private>)> {
+    let private_key = generate_private_key()?;
+    let cert = generate_cert(&private_key, &CertSubject::default())?;
+
+    Ok((cert, private_key))
+}
+
+/// Generates a secret key suitable for TLS encryption.
+fn generate_private_key() -> SslResult<PKey<Private>> {
+    // We do not care about browser-compliance, so we're free to use elliptic curves
+    // that are more likely to hold up under pressure than the NIST ones. We
+    // want to go with ED25519 because djb knows best: PKey::generate_ed25519()
+    //
+    // However the following bug currently prevents us from doing so:
+    // https://mta.openssl.org/pipermail/openssl-users/2018-July/008362.html (The same error occurs
+    // when trying to sign the cert inside the builder)
+
+    // Our second choice is 2^521-1, which is slow but a "nice prime".
+    // http://blog.cr.yp.to/20140323-ecdsa.html
+
+    // An alternative is https://en.bitcoin.it/wiki/Secp256k1, which puts us at level of bitcoin.
+
+    // TODO: Please verify this for accuracy!
+
+    let ec_group = ec::EcGroup::from_curve_name(SIGNATURE_CURVE)?;
+    let ec_key = ec::EcKey::generate(ec_group.as_ref())?;
+
+    PKey::from_ec_key(ec_key)
+}
+
+/// An integer that fits a [`BigNum`], e.g. a certificate serial number.
+///
+/// Our certificates use serial number one, but a CA may hand out timestamps
+/// or random 128 bit numbers instead.
+trait ToBigNum {
+    fn to_bignum(&self) -> SslResult<BigNum>;
+}
+
+impl ToBigNum for u32 {
+    fn to_bignum(&self) -> SslResult<BigNum> { BigNum::from_u32(*self) }
+}
+
+impl ToBigNum for u64 {
+    fn to_bignum(&self) -> SslResult<BigNum> { BigNum::from_slice(&self.to_be_bytes()) }
+}
+
+impl ToBigNum for u128 {
+    fn to_bignum(&self) -> SslResult<BigNum> { BigNum::from_slice(&self.to_be_bytes()) }
+}
+
+impl ToBigNum for BigNumRef {
+    fn to_bignum(&self) -> SslResult<BigNum> { self.to_owned() }
+}
+
+/// Creates an ASN1 integer from any [`ToBigNum`].
+fn mknum<N: ToBigNum + ?Sized>(n: &N) -> SslResult<Asn1Integer> { n.to_bignum()?.to_asn1_integer() }
+
+/// Returns an OpenSSL compatible timestamp.
+fn now() -> i64 {
+    // Note: We could do the timing dance a little better going straight to the UNIX
+    // time functions,       but this saves us having to bring in `libc` as a
+    // dependency.
+    let now = SystemTime::now();
+    let ts: i64 = now
+        .duration_since(UNIX_EPOCH)
+        // This should work unless the clock is set to before 1970.
+        .expect("Great Scott! Your clock is horribly broken, Marty.")
+        .as_secs()
+        // This will fail past year 2038 on 32 bit systems and very far into the future, both cases
+        // we consider out of scope.
+        .try_into()
+        .expect("32-bit systems and far future are not supported");
+
+    ts
+}
+
+/// Creates an ASN1 name from string components.
+///
+/// If `c` or `o` are empty string, they are omitted from the result.
+fn mkname(c: &str, o: &str, cn: &str) -> SslResult<X509Name> {
+    let mut builder = X509NameBuilder::new()?;
+
+    if !c.is_empty() {
+        builder.append_entry_by_text("C", c)?;
+    }
+
+    if !o.is_empty() {
+        builder.append_entry_by_text("O", o)?;
+    }
+
+    builder.append_entry_by_text("CN", cn)?;
+    Ok(builder.build())
+}
+
+/// Generates a self-signed certificate based on `private_key` for `subject`.
+fn generate_cert(private_key: &PKey<Private>, subject: &CertSubject) -> SslResult<X509> {
+    let mut builder = X509Builder::new()?;
+
+    // x509 v3 commonly used, the version is 0-indexed, thus 2 == v3.
+    builder.set_version(2)?;
+
+    // The serial number is always one, since we are issuing only one cert.
+    builder.set_serial_number(mknum(&1u32)?.as_ref())?;
+
+    let issuer = mkname(&subject.country, &subject.org, &subject.common_name)?;
+
+    // Set the issuer, subject names, putting the "self" in "self-signed".
+    builder.set_issuer_name(issuer.as_ref())?;
+    builder.set_subject_name(issuer.as_ref())?;
+
+    let ts = now();
+    // We set valid-from to one minute into the past to allow some clock-skew.
+    builder.set_not_before(Asn1Time::from_unix(ts - 60)?.as_ref())?;
+
+    // Valid-until is a little under 10 years, missing at least 2 leap days.
+    builder.set_not_after(Asn1Time::from_unix(ts + 10 * 365 * 24 * 60 * 60)?.as_ref())?;
+
+    // Set the public key and sign.
+    builder.set_pubkey(private_key.as_ref())?;
+    assert_eq!(Sha512::NID, SIGNATURE_DIGEST);
+    builder.sign(private_key.as_ref(), Sha512::create_message_digest())?;
+
+    let cert = builder.build();
+
+    // Cheap sanity check.
+    assert!(
+        validate_peer_cert_detailed(&cert, Duration::ZERO).is_valid(),
+        "newly generated cert does not pass our own validity check"
+    );
+
+    Ok(cert)
+}
+
+/// Converts an `X509NameRef` to a human readable string.
+fn name_to_string(name: &X509NameRef) -> SslResult<String> {
+    let mut output = String::new();
+
+    for entry in name.entries() {
+        output.push_str(entry.object().nid().long_name()?);
+        output.push('=');
+        output.push_str(entry.data().as_utf8()?.as_ref());
+        output.push(' ');
+    }
+
+    Ok(output)
+}
+
+/// Checks if an `Asn1IntegerRef` is equal to any [`ToBigNum`].
+fn num_eq<N: ToBigNum + ?Sized>(num: &Asn1IntegerRef, other: &N) -> SslResult<bool> {
+    let l = num.to_bn()?;
+    let r = other.to_bignum()?;
+
+    // The `BigNum` API seems to be really lacking here.
+    Ok(l.is_negative() == r.is_negative() && l.ucmp(r.as_ref()) == Ordering::Equal)
+}
+
+/// Check cert's expiration times against current time, allowing it to be up
+/// to `clock_skew` outside of them.
+fn validate_cert_expiration_date(cert: &X509Ref, clock_skew: Duration) -> Result<(), TLSError> {
+    let asn1_now = Asn1Time::from_unix(now()).map_err(|_| TLSError::TimeIssue)?;
+    let secs_until = |time: &Asn1TimeRef| {
+        let diff = asn1_now.diff(time).map_err(|_| TLSError::TimeIssue)?;
+        Ok(i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs))
+    };
+    // How far outside the validity period a certificate is, in seconds,
+    // counting its very first and last second as outside.
+    let skew = i64::try_from(clock_skew.as_secs()).unwrap_or(i64::MAX);
+    let outside = |secs: i64| TimeDiff::from_seconds(secs.try_into().unwrap_or(u32::MAX));
+
+    let early = secs_until(cert.not_before())?;
+    if early >= 0 && early >= skew {
+        return Err(TLSError::NotYetValid { by: outside(early) });
+    }
+
+    let late = -secs_until(cert.not_after())?;
+    if late >= 0 && late >= skew {
+        return Err(TLSError::Expired { by: outside(late) });
+    }
+
+    Ok(())
+}
+
+/// Validate cert's public key, and it's EC key parameters.
+fn validate_cert_ec_key(cert: &X509Ref) -> Result<(PKey<Public>, EcKey<Public>), TLSError> {
+    let public_key = cert.public_key().map_err(|_| TLSError::CannotReadPublicKey)?;
+    let ec_key = public_key.ec_key().map_err(|_| TLSError::CouldNotExtractEcKey)?;
+    ec_key.check_key().map_err(|_| TLSError::KeyFailsCheck)?;
+    Ok((public_key, ec_key))
+}
+
+/// Splits the DER element at the start of `der` into its tag, its contents and
+/// the bytes following it.
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        // Long form, the low bits give the number of length bytes.
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > std::mem::size_of::<usize>() || rest.len() < count {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at(count);
+        let len = bytes.iter().fold(0, |len, byte| len << 8 | usize::from(*byte));
+        (len, rest)
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Check that the cert has no X.509v3 extensions, as ours never do.
+fn validate_cert_extensions(cert: &X509Ref) -> Result<(), TLSError> {
+    // The `[3]` tagged field of the TBSCertificate sequence holds the extensions.
+    const EXTENSIONS_TAG: u8 = 0xa3;
+
+    let der = cert.to_der().map_err(|_| TLSError::CannotReadExtensions)?;
+    let (_, certificate, _) = der_element(&der).ok_or(TLSError::CannotReadExtensions)?;
+    let (_, mut fields, _) = der_element(certificate).ok_or(TLSError::CannotReadExtensions)?;
+    while !fields.is_empty() {
+        let (tag, _, rest) = der_element(fields).ok_or(TLSError::CannotReadExtensions)?;
+        if tag == EXTENSIONS_TAG {
+            return Err(TLSError::UnexpectedExtensions);
+        }
+        fields = rest;
+    }
+    Ok(())
+}
+
+/// Runs every check peers make on `peer_cert`, see
+/// [`super::validate_peer_cert_detailed`].
+///
+/// The curve and signature checks need the public key, and are left out if
+/// it cannot be read.
+pub fn validate_peer_cert_detailed(peer_cert: &X509Ref, clock_skew: Duration) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    // The signature algorithm is not of the exact kind we are using to generate our
+    // certificates, an attacker could have used a weaker one to generate colliding
+    // keys.
+    let algorithm = peer_cert.signature_algorithm().object().nid();
+    report.record(
+        CertCheck::SignatureAlgorithm,
+        (algorithm == SIGNATURE_ALGORITHM)
+            .then_some(())
+            .ok_or(TLSError::WrongSignatureAlgorithm),
+    );
+    // Our certificates carry no extensions. Rejecting any leaves an attacker no
+    // room to add bytes of their choosing, an additional hurdle for preimage
+    // attacks to clear.
+    report.record(CertCheck::Extensions, validate_cert_extensions(peer_cert));
+
+    // All of our certificates are self-signed, so it cannot hurt to check.
+    let self_signed = || {
+        let subject = name_to_string(peer_cert.subject_name())
+            .map_err(|_| TLSError::CorruptSubjectOrIssuer)?;
+        let issuer = name_to_string(peer_cert.issuer_name())
+            .map_err(|_| TLSError::CorruptSubjectOrIssuer)?;
+        (subject == issuer).then_some(()).ok_or(TLSError::NotSelfSigned)
+    };
+    report.record(CertCheck::SelfSigned, self_signed());
+
+    // All our certificates have serial number 1.
+    let serial_number = num_eq(peer_cert.serial_number(), &1u32)
+        .map_err(|_| TLSError::InvalidSerialNumber)
+        .and_then(|is_one| is_one.then_some(()).ok_or(TLSError::WrongSerialNumber));
+    report.record(CertCheck::SerialNumber, serial_number);
+
+    // Check expiration times against current time.
+    report.record(
+        CertCheck::Validity,
+        validate_cert_expiration_date(peer_cert, clock_skew),
+    );
+
+    let (public_key, ec_key) = match validate_cert_ec_key(peer_cert) {
+        Ok(keys) => {
+            report.record(CertCheck::PublicKey, Ok(()));
+            keys
+        }
+        Err(e) => {
+            report.record(CertCheck::PublicKey, Err(e));
+            return report;
+        }
+    };
+
+    // Ensure that the key is using the correct curve parameters.
+    report.record(
+        CertCheck::Curve,
+        (ec_key.group().curve_name() == Some(SIGNATURE_CURVE))
+            .then_some(())
+            .ok_or(TLSError::WrongCurve),
+    );
+
+    // Finally we can check the actual signature.
+    let signature = peer_cert
+        .verify(&public_key)
+        .map_err(|_| TLSError::FailedToValidateSignature)
+        .and_then(|valid| valid.then_some(()).ok_or(TLSError::InvalidSignature));
+    report.record(CertCheck::Signature, signature);
+
+    report
+}
+
+/// Creates a TLS acceptor for a client.
+///
+/// A connector compatible with the acceptor created using
+/// `create_tls_acceptor`. Server certificates must always be validated using
+/// `validate_cert` after connecting.
+pub(crate) fn create_tls_connector(
+    cert: &X509Ref,
+    intermediates: &[X509],
+    private_key: &PKeyRef<Private>,
+    options: &TlsOptions,
+) -> SslResult<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    set_context_options(&mut builder, cert, intermediates, private_key, options)?;
+
+    Ok(builder.build())
+}
+
+/// Creates a TLS acceptor for peers connecting to us, the counterpart of
+/// `create_tls_connector`.
+pub(crate) fn create_tls_acceptor(
+    cert: &X509Ref,
+    intermediates: &[X509],
+    private_key: &PKeyRef<Private>,
+    options: &TlsOptions,
+) -> SslResult<SslAcceptor> {
+    let mut builder = SslAcceptor::mozilla_modern_v5(SslMethod::tls_server())?;
+    set_context_options(&mut builder, cert, intermediates, private_key, options)?;
+
+    Ok(builder.build())
+}
+
+/// Sets common options of both acceptor and connector on TLS context.
+///
+/// Used internally to set various TLS parameters. `cert` is presented along
+/// with the `intermediates` between it and the network CA, if any.
+pub fn set_context_options(
+    ctx: &mut SslContextBuilder,
+    cert: &X509Ref,
+    intermediates: &[X509],
+    private_key: &PKeyRef<Private>,
+    options: &TlsOptions,
+) -> SslResult<()> {
+    ctx.set_min_proto_version(Some(SslVersion::TLS1_3))?;
+    if !options.ciphersuites.is_empty() {
+        ctx.set_ciphersuites(&options.ciphersuites.join(":"))?;
+    }
+    if !options.groups.is_empty() {
+        ctx.set_groups_list(&options.groups.join(":"))?;
+    }
+
+    ctx.set_certificate(cert)?;
+    for intermediate in intermediates {
+        ctx.add_extra_chain_cert(intermediate.clone())?;
+    }
+    ctx.set_private_key(private_key)?;
+    ctx.check_private_key()?;
+
+    // Note that this does not seem to work as one might naively expect; the client
+    // can still send no certificate and there will be no error from OpenSSL.
+    // For this reason, we pass set `PEER` (causing the request of a cert), but
+    // pass all of them through and verify them after the handshake has
+    // completed.
+    ctx.set_verify_callback(SslVerifyMode::PEER, |_, _| true);
+
+    if let Some(key_log) = options.key_log.clone() {
+        ctx.set_keylog_callback(move |_, line| key_log.log(line));
+    }
+
+    Ok(())
+}
+
+/// The parameters the handshake over `ssl` settled on.
+pub fn negotiated(ssl: &SslRef) -> Negotiated {
+    Negotiated {
+        version: ssl.version_str(),
+        cipher: ssl
+            .current_cipher()
+            .map_or_else(|| "none".to_string(), |cipher| cipher.name().to_string()),
+        group: ssl.peer_tmp_key().ok().and_then(|key| key_group(&key)),
+    }
+}
+
+fn key_group(key: &PKeyRef<Public>) -> Option<String> {
+    match key.id() {
+        Id::X25519 => Some("X25519".to_string()),
+        Id::X448 => Some("X448".to_string()),
+        Id::EC => {
+            let nid = key.ec_key().ok()?.group().curve_name()?;
+            nid.short_name().ok().map(str::to_string)
+        }
+        _ => None,
+    }
+}
+
+/// An OpenSSL connection over `S`.
+///
+/// Reading and writing retry whenever OpenSSL wants to read or write the
+/// underlying stream first. Shutting down sends a close_notify once and then
+/// shuts down the underlying stream, without waiting for the peer's.
+pub struct OpenSslStream<S> {
+    inner: SslStream<S>,
+    /// Whether our close_notify went out.
+    shut_down: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> OpenSslStream<S> {
+    pub fn new(ssl: Ssl, stream: S) -> Result<Self, TLSError> {
+        SslStream::new(ssl, stream)
+            .map(|inner| Self {
+                inner,
+                shut_down: false,
+            })
+            .map_err(|error| TLSError::TlsInitialization(error.to_string()))
+    }
+
+    pub fn ssl(&self) -> &SslRef { self.inner.ssl() }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for OpenSslStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for OpenSslStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Calling SSL_shutdown again after the close_notify went out waits
+        // for the peer's, which it may never send.
+        if self.shut_down {
+            return Pin::new(self.inner.get_mut()).poll_shutdown(cx);
+        }
+        let result = ready!(Pin::new(&mut self.inner).poll_shutdown(cx));
+        self.shut_down = result.is_ok();
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::x509::extension::BasicConstraints;
+    use openssl::x509::extension::KeyUsage;
+
+    use super::*;
+    use crate::network::tls::validate_peer_cert;
+    use crate::network::tls::validate_peer_cert_with_ca;
+
+    /// Options that allow for no clock skew at all.
+    fn strict() -> TlsOptions {
+        TlsOptions {
+            clock_skew: Duration::ZERO,
+            ..TlsOptions::default()
+        }
+    }
+
+    /// A node certificate for `secret_key`, issued by `ca`.
+    fn issue_cert(
+        secret_key: &PKey<Private>,
+        ca: &X509,
+        ca_key: &PKey<Private>,
+    ) -> SslResult<X509> {
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(mknum(&2u32)?.as_ref())?;
+        builder.set_issuer_name(ca.subject_name())?;
+        builder.set_subject_name(mkname("US", "Casper Blockchain", "casper-node")?.as_ref())?;
+        builder.set_not_before(Asn1Time::from_unix(now() - 60)?.as_ref())?;
+        builder.set_not_after(Asn1Time::days_from_now(1)?.as_ref())?;
+        builder.set_pubkey(secret_key)?;
+        builder.sign(ca_key, Sha512::create_message_digest())?;
+        Ok(builder.build())
+    }
+
+    /// A CA certificate named `cn` for `key`, issued by `issuer`, or
+    /// self-signed without one.
+    fn ca_cert(
+        key: &PKey<Private>,
+        cn: &str,
+        issuer: Option<(&X509, &PKey<Private>)>,
+    ) -> SslResult<X509> {
+        let name = mkname("US", "Casper Blockchain", cn)?;
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(mknum(&3u32)?.as_ref())?;
+        builder.set_subject_name(&name)?;
+        match issuer {
+            Some((issuer, _)) => builder.set_issuer_name(issuer.subject_name())?,
+            None => builder.set_issuer_name(&name)?,
+        }
+        builder.set_not_before(Asn1Time::from_unix(now() - 60)?.as_ref())?;
+        builder.set_not_after(Asn1Time::days_from_now(1)?.as_ref())?;
+        builder.set_pubkey(key)?;
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(KeyUsage::new().critical().key_cert_sign().build()?)?;
+        let signer = issuer.map_or(key, |(_, issuer_key)| issuer_key);
+        builder.sign(signer, Sha512::create_message_digest())?;
+        Ok(builder.build())
+    }
+
+    /// A root CA, an intermediate CA issued by it, and their keys.
+    fn ca_chain() -> ((X509, PKey<Private>), (X509, PKey<Private>)) {
+        let root_key = generate_private_key().unwrap();
+        let root = ca_cert(&root_key, "casper-root", None).unwrap();
+        let intermediate_key = generate_private_key().unwrap();
+        let intermediate = ca_cert(
+            &intermediate_key,
+            "casper-intermediate",
+            Some((&root, &root_key)),
+        )
+        .unwrap();
+        ((root, root_key), (intermediate, intermediate_key))
+    }
+
+    #[test]
+    fn peer_certificates_are_checked_against_the_network_ca() {
+        let ((root, root_key), (intermediate, intermediate_key)) = ca_chain();
+        let key = generate_private_key().unwrap();
+
+        // Issued by the root itself, or through the intermediate the peer
+        // sends along.
+        let direct = issue_cert(&key, &root, &root_key).unwrap();
+        validate_peer_cert_with_ca(direct, &[], &root, &strict()).unwrap();
+        let leaf = issue_cert(&key, &intermediate, &intermediate_key).unwrap();
+        let chain = std::slice::from_ref(&intermediate);
+        validate_peer_cert_with_ca(leaf.clone(), chain, &root, &strict()).unwrap();
+
+        // Without the intermediate there is no way to the root.
+        assert!(matches!(
+            validate_peer_cert_with_ca(leaf.clone(), &[], &root, &strict()),
+            Err(TLSError::UntrustedChain(_))
+        ));
+
+        // Another network's CA, under the same names.
+        let ((other_root, _), _) = ca_chain();
+        assert!(matches!(
+            validate_peer_cert_with_ca(leaf, &[intermediate], &other_root, &strict()),
+            Err(TLSError::UntrustedChain(_))
+        ));
+
+        // A certificate that is not a CA's cannot issue others.
+        let not_a_ca_key = generate_private_key().unwrap();
+        let not_a_ca = issue_cert(&not_a_ca_key, &root, &root_key).unwrap();
+        let leaf = issue_cert(&key, &not_a_ca, &not_a_ca_key).unwrap();
+        assert!(matches!(
+            validate_peer_cert_with_ca(leaf, &[not_a_ca], &root, &strict()),
+            Err(TLSError::UntrustedChain(_))
+        ));
+
+        // Nor does a self-signed certificate, however valid, pass.
+        let (self_signed, _) = generate_node_cert().unwrap();
+        assert!(matches!(
+            validate_peer_cert_with_ca(self_signed, &[], &root, &strict()),
+            Err(TLSError::UntrustedChain(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn peers_on_a_network_with_a_ca_present_certificates_it_issued() {
+        use futures::TryFutureExt;
+
+        use crate::network::transport::TlsTransport;
+        use crate::network::transport::Transport;
+
+        let ((root, root_key), _) = ca_chain();
+        let issued = || {
+            let key = generate_private_key().unwrap();
+            let cert = issue_cert(&key, &root, &root_key).unwrap();
+            Identity::new(key, cert, vec![], Some(root.clone()))
+        };
+        let server = TlsTransport::new(issued(), TlsOptions::default());
+        let mut listener = server.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr();
+
+        let client = TlsTransport::new(issued(), TlsOptions::default());
+        let accepted = listener.accept().and_then(|accepted| accepted.setup);
+        let (accepted, connected) = tokio::join!(accepted, client.connect(addr));
+        accepted.unwrap();
+        connected.unwrap();
+
+        // A node presenting the usual self-signed certificate is turned away
+        // on both ends.
+        let outsider = TlsTransport::new(Identity::from_seed(1).unwrap(), TlsOptions::default());
+        let accepted = listener.accept().and_then(|accepted| accepted.setup);
+        let (accepted, _) = tokio::join!(accepted, outsider.connect(addr));
+        assert!(accepted.is_err());
+        let mut outsider_listener = outsider.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let outsider_addr = outsider_listener.local_addr();
+        let accepted = outsider_listener.accept().and_then(|accepted| accepted.setup);
+        let (_, connected) = tokio::join!(accepted, client.connect(outsider_addr));
+        assert!(connected.is_err());
+    }
+
+    #[tokio::test]
+    async fn identities_issued_through_an_intermediate_present_it() {
+        use futures::TryFutureExt;
+
+        use crate::network::tls::CERTIFICATE_FILE;
+        use crate::network::transport::TlsTransport;
+        use crate::network::transport::Transport;
+
+        let ((root, root_key), (intermediate, intermediate_key)) = ca_chain();
+        let issued = || {
+            let key = generate_private_key().unwrap();
+            let cert = issue_cert(&key, &intermediate, &intermediate_key).unwrap();
+            Identity::new(key, cert, vec![intermediate.clone()], Some(root.clone()))
+        };
+        let identity = issued();
+        let encoded = identity.to_pem().unwrap();
+        let decoded = Identity::from_pem(&encoded).unwrap();
+        assert_eq!(decoded.to_der().unwrap(), identity.to_der().unwrap());
+        let decoded = Identity::from_der(&identity.to_der().unwrap()).unwrap();
+        assert_eq!(decoded.to_der().unwrap(), identity.to_der().unwrap());
+
+        // The certificate file starts with ours, as casper-node reads it.
+        let dir = tempfile::tempdir().unwrap();
+        identity.save(dir.path()).unwrap();
+        let loaded = Identity::load_or_generate(dir.path(), &CertSubject::default()).unwrap();
+        assert_eq!(loaded.to_der().unwrap(), identity.to_der().unwrap());
+        let file = std::fs::read(dir.path().join(CERTIFICATE_FILE)).unwrap();
+        assert_eq!(
+            X509::from_pem(&file).unwrap().to_der().unwrap(),
+            identity.tls_certificate.to_der().unwrap()
+        );
+
+        // Without the intermediate there is no way to the root, and without
+        // a root the intermediate leads nowhere.
+        let mut bare = encoded.clone();
+        bare.intermediates.clear();
+        assert!(matches!(
+            Identity::from_pem(&bare),
+            Err(TLSError::NotSignedByNetworkCa)
+        ));
+        let mut rootless = encoded;
+        rootless.network_ca = None;
+        assert!(matches!(
+            Identity::from_pem(&rootless),
+            Err(TLSError::IntermediatesWithoutNetworkCa)
+        ));
+
+        // Peers that only know the root accept it, either way round.
+        let direct = {
+            let key = generate_private_key().unwrap();
+            let cert = issue_cert(&key, &root, &root_key).unwrap();
+            Identity::new(key, cert, vec![], Some(root.clone()))
+        };
+        for (server, client) in [(issued(), direct.clone()), (direct, issued())] {
+            let server = TlsTransport::new(server, TlsOptions::default());
+            let mut listener = server.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            let addr = listener.local_addr();
+            let client = TlsTransport::new(client, TlsOptions::default());
+            let accepted = listener.accept().and_then(|accepted| accepted.setup);
+            let (accepted, connected) = tokio::join!(accepted, client.connect(addr));
+            accepted.unwrap();
+            connected.unwrap();
+        }
+    }
+
+    #[test]
+    fn identity_with_network_ca_roundtrips() {
+        let ((ca, ca_key), _) = ca_chain();
+        let secret_key = generate_private_key().unwrap();
+        let cert = issue_cert(&secret_key, &ca, &ca_key).unwrap();
+        let identity = Identity::new(secret_key, cert, vec![], Some(ca));
+
+        let decoded = Identity::from_pem(&identity.to_pem().unwrap()).unwrap();
+        assert_eq!(decoded.to_der().unwrap(), identity.to_der().unwrap());
+        let decoded = Identity::from_der(&identity.to_der().unwrap()).unwrap();
+        assert_eq!(decoded.to_der().unwrap(), identity.to_der().unwrap());
+
+        // A certificate issued by some other CA is refused.
+        let mut encoded = identity.to_pem().unwrap();
+        encoded.network_ca = Some(generate_node_cert().unwrap().0.to_pem().unwrap());
+        assert!(matches!(
+            Identity::from_pem(&encoded),
+            Err(TLSError::NotSignedByNetworkCa)
+        ));
+    }
+
+    #[test]
+    fn detailed_validation_reports_every_failed_check() {
+        let identity = Identity::from_seed(1).unwrap();
+        let report = validate_peer_cert_detailed(&identity.tls_certificate, Duration::ZERO);
+        assert!(report.is_valid());
+        assert_eq!(report.results().len(), 8);
+
+        // Issued by someone else under the same name, with serial number 2.
+        let (ca, ca_key) = generate_node_cert().unwrap();
+        let cert = issue_cert(&identity.secret_key, &ca, &ca_key).unwrap();
+        let report = validate_peer_cert_detailed(&cert, Duration::ZERO);
+        let failed: Vec<_> = report.failures().map(|(check, _)| check).collect();
+        assert_eq!(failed, [CertCheck::SerialNumber, CertCheck::Signature]);
+        assert!(matches!(
+            validate_peer_cert(cert, &strict()),
+            Err(TLSError::WrongSerialNumber)
+        ));
+    }
+
+    #[test]
+    fn hostile_certificates_are_rejected() {
+        use openssl::hash::MessageDigest;
+
+        use super::testing::CertBuilder;
+
+        let (cert, _) = CertBuilder::default().build().unwrap();
+        validate_peer_cert(cert, &strict()).unwrap();
+
+        let cases = [
+            (
+                CertBuilder::default().curve(Nid::SECP384R1),
+                TLSError::WrongCurve,
+            ),
+            (
+                CertBuilder::default().digest(MessageDigest::sha256()),
+                TLSError::WrongSignatureAlgorithm,
+            ),
+            (
+                CertBuilder::default().issuer_cn("casper-ca"),
+                TLSError::NotSelfSigned,
+            ),
+            (
+                CertBuilder::default().serial_number(2),
+                TLSError::WrongSerialNumber,
+            ),
+            (
+                CertBuilder::default().expired(),
+                TLSError::Expired {
+                    by: TimeDiff::default(),
+                },
+            ),
+            (
+                CertBuilder::default().not_yet_valid(),
+                TLSError::NotYetValid {
+                    by: TimeDiff::default(),
+                },
+            ),
+            (
+                CertBuilder::default().garbage_extension(),
+                TLSError::UnexpectedExtensions,
+            ),
+        ];
+        for (builder, expected) in cases {
+            let (cert, _) = builder.build().unwrap();
+            let error = validate_peer_cert(cert.clone(), &strict()).unwrap_err();
+            assert_eq!(
+                std::mem::discriminant(&error),
+                std::mem::discriminant(&expected),
+                "expected {expected:?}, got {error:?}"
+            );
+            let report = validate_peer_cert_detailed(&cert, Duration::ZERO);
+            assert_eq!(report.failures().count(), 1, "{expected:?}");
+        }
+    }
+
+    #[test]
+    fn serial_numbers_of_any_width_are_compared_exactly() {
+        use super::testing::CertBuilder;
+
+        let timestamp = u64::try_from(now()).unwrap();
+        let random: u128 = rand::random::<u128>() | 1 << 127;
+        // One more than fits 128 bits.
+        let huge = BigNum::from_dec_str("340282366920938463463374607431768211457").unwrap();
+
+        assert!(num_eq(&mknum(&7u32).unwrap(), &7u32).unwrap());
+        assert!(num_eq(&mknum(&timestamp).unwrap(), &timestamp).unwrap());
+        assert!(num_eq(&mknum(&random).unwrap(), &random).unwrap());
+        assert!(num_eq(&mknum(&*huge).unwrap(), &*huge).unwrap());
+
+        // Equal in the low bits only.
+        let wide = u64::from(u32::MAX) + 2;
+        assert!(!num_eq(&mknum(&wide).unwrap(), &1u32).unwrap());
+        assert!(!num_eq(&mknum(&random).unwrap(), &(random as u64)).unwrap());
+        let mut negative = huge.to_owned().unwrap();
+        negative.set_negative(true);
+        assert!(!num_eq(&mknum(&*negative).unwrap(), &*huge).unwrap());
+
+        let (cert, _) = CertBuilder::default().serial_number(random).build().unwrap();
+        assert!(num_eq(cert.serial_number(), &random).unwrap());
+        assert!(matches!(
+            validate_peer_cert(cert, &strict()),
+            Err(TLSError::WrongSerialNumber)
+        ));
+    }
+
+    #[test]
+    fn validity_period_allows_for_clock_skew() {
+        use super::testing::CertBuilder;
+
+        let skewed = TlsOptions {
+            clock_skew: Duration::from_secs(5 * 60),
+            ..TlsOptions::default()
+        };
+        let secs = |by: TimeDiff| by.millis() / 1000;
+
+        // Issued by a peer whose clock is a minute ahead of ours.
+        let (early, _) = CertBuilder::default().validity(60, 24 * 60 * 60).build().unwrap();
+        validate_peer_cert(early.clone(), &skewed).unwrap();
+        match validate_peer_cert(early, &strict()) {
+            Err(TLSError::NotYetValid { by }) => assert!((58..=60).contains(&secs(by))),
+            other => panic!("expected the certificate to be early, got {other:?}"),
+        }
+
+        // Expired half a minute ago, or a day ago, well beyond the skew.
+        let (late, _) = CertBuilder::default().validity(-24 * 60 * 60, -30).build().unwrap();
+        validate_peer_cert(late, &skewed).unwrap();
+        let (expired, _) = CertBuilder::default().expired().build().unwrap();
+        match validate_peer_cert(expired, &skewed) {
+            Err(TLSError::Expired { by }) => {
+                assert!((24 * 60 * 60..24 * 60 * 60 + 5).contains(&secs(by)))
+            }
+            other => panic!("expected the certificate to be expired, got {other:?}"),
+        }
+    }
+}
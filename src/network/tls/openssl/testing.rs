@@ -0,0 +1,122 @@
+//! Deliberately malformed certificates, for testing how we treat hostile peers.
+//!
+//! [`CertBuilder`] starts out producing the same certificate as
+//! [`generate_node_cert`](super::generate_node_cert); every setter breaks it in
+//! one particular way.
+
+use openssl::asn1::Asn1Object;
+use openssl::asn1::Asn1OctetString;
+use openssl::asn1::Asn1Time;
+use openssl::ec::EcGroup;
+use openssl::ec::EcKey;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::pkey::Private;
+use openssl::x509::X509Builder;
+use openssl::x509::X509Extension;
+use openssl::x509::X509;
+
+use super::mkname;
+use super::mknum;
+use super::now;
+use super::SslResult;
+use super::SIGNATURE_CURVE;
+
+/// An OID under the arc reserved for examples, which nobody will recognize.
+const GARBAGE_EXTENSION_OID: &str = "1.3.6.1.4.1.32473.1";
+
+/// Builds a node certificate with chosen defects.
+#[derive(Clone)]
+pub struct CertBuilder {
+    curve: Nid,
+    digest: MessageDigest,
+    issuer_cn: &'static str,
+    serial_number: u128,
+    not_before: i64,
+    not_after: i64,
+    garbage_extension: bool,
+}
+
+impl Default for CertBuilder {
+    fn default() -> Self {
+        Self {
+            curve: SIGNATURE_CURVE,
+            digest: MessageDigest::sha512(),
+            issuer_cn: "casper-node",
+            serial_number: 1,
+            not_before: -60,
+            not_after: 10 * 365 * 24 * 60 * 60,
+            garbage_extension: false,
+        }
+    }
+}
+
+impl CertBuilder {
+    /// Generates the key on `curve`.
+    pub fn curve(mut self, curve: Nid) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Signs the certificate using `digest`.
+    pub fn digest(mut self, digest: MessageDigest) -> Self {
+        self.digest = digest;
+        self
+    }
+
+    /// Names an issuer other than the subject. The certificate is still signed
+    /// with its own key.
+    pub fn issuer_cn(mut self, cn: &'static str) -> Self {
+        self.issuer_cn = cn;
+        self
+    }
+
+    pub fn serial_number(mut self, serial_number: u128) -> Self {
+        self.serial_number = serial_number;
+        self
+    }
+
+    /// Sets the validity period, in seconds relative to now.
+    pub fn validity(mut self, not_before: i64, not_after: i64) -> Self {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    /// A certificate that expired a day ago.
+    pub fn expired(self) -> Self { self.validity(-2 * 24 * 60 * 60, -24 * 60 * 60) }
+
+    /// A certificate that only becomes valid in a day.
+    pub fn not_yet_valid(self) -> Self { self.validity(24 * 60 * 60, 2 * 24 * 60 * 60) }
+
+    /// Adds a non-critical extension with an unknown OID and junk contents.
+    pub fn garbage_extension(mut self) -> Self {
+        self.garbage_extension = true;
+        self
+    }
+
+    /// Generates a key and the certificate for it.
+    pub fn build(&self) -> SslResult<(X509, PKey<Private>)> {
+        let group = EcGroup::from_curve_name(self.curve)?;
+        let private_key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(mknum(&self.serial_number)?.as_ref())?;
+        builder.set_issuer_name(mkname("US", "Casper Blockchain", self.issuer_cn)?.as_ref())?;
+        builder.set_subject_name(mkname("US", "Casper Blockchain", "casper-node")?.as_ref())?;
+        let ts = now();
+        builder.set_not_before(Asn1Time::from_unix(ts + self.not_before)?.as_ref())?;
+        builder.set_not_after(Asn1Time::from_unix(ts + self.not_after)?.as_ref())?;
+        builder.set_pubkey(&private_key)?;
+        if self.garbage_extension {
+            let oid = Asn1Object::from_str(GARBAGE_EXTENSION_OID)?;
+            let contents = Asn1OctetString::new_from_bytes(b"\x04\x08hostile!")?;
+            builder.append_extension(X509Extension::new_from_der(&oid, false, &contents)?)?;
+        }
+        builder.sign(&private_key, self.digest)?;
+
+        Ok((builder.build(), private_key))
+    }
+}
@@ -0,0 +1,344 @@
+//! The graph a crawl found, for tools like Gephi or analysis scripts.
+//!
+//! Every node reached is a vertex, and so is every address gossiped that
+//! could not be, marked unreached. An edge leads from each node to those it
+//! gossiped. How two peers see each other cannot be told from outside, so
+//! the metadata edges may carry is what the crawler saw of the node they
+//! lead to: its protocol version and the latency of the handshake with it.
+//!
+//! The graph is written as GraphML, as DOT, or as compact JSON mapping every
+//! vertex to those it leads to.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+
+use casper_types::ProtocolVersion;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+use super::crawl::CrawledNode;
+use super::node_id::NodeId;
+
+/// The formats a topology is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TopologyFormat {
+    Graphml,
+    Dot,
+    /// Compact JSON, every vertex with the vertices it leads to.
+    Adjacency,
+}
+
+/// A node of the network, reached or only gossiped.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+struct Vertex {
+    /// The full node id if reached, the address otherwise.
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_id: Option<NodeId>,
+    addr: SocketAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol_version: Option<ProtocolVersion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u64>,
+    reached: bool,
+}
+
+impl Vertex {
+    /// What Gephi and Graphviz show for the vertex: the short node id, or
+    /// the address.
+    fn label(&self) -> String {
+        self.node_id
+            .map_or_else(|| self.addr.to_string(), |node_id| node_id.to_string())
+    }
+}
+
+/// The vertices and edges of a crawl.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    vertices: Vec<Vertex>,
+    /// Indexes into `vertices`, from the node that gossiped to the one it
+    /// gossiped.
+    edges: BTreeSet<(usize, usize)>,
+}
+
+impl Topology {
+    /// The graph of `nodes` and the addresses they gossiped.
+    pub fn of<'a>(nodes: impl IntoIterator<Item = &'a CrawledNode>) -> Self {
+        let nodes: Vec<_> = nodes.into_iter().collect();
+        let mut topology = Topology::default();
+        let mut by_addr = BTreeMap::new();
+        for node in &nodes {
+            let index = topology.vertices.len();
+            topology.vertices.push(Vertex {
+                id: format!("{:#}", node.node_id),
+                node_id: Some(node.node_id),
+                addr: node.public_addr,
+                protocol_version: Some(node.protocol_version),
+                latency_ms: node.latency_ms,
+                reached: true,
+            });
+            for addr in node.addrs.iter().chain([&node.public_addr]) {
+                by_addr.entry(*addr).or_insert(index);
+            }
+        }
+        for (from, node) in nodes.iter().enumerate() {
+            for peer in &node.peers {
+                let to = *by_addr.entry(*peer).or_insert_with(|| {
+                    topology.vertices.push(Vertex {
+                        id: peer.to_string(),
+                        node_id: None,
+                        addr: *peer,
+                        protocol_version: None,
+                        latency_ms: None,
+                        reached: false,
+                    });
+                    topology.vertices.len() - 1
+                });
+                if to != from {
+                    topology.edges.insert((from, to));
+                }
+            }
+        }
+        topology
+    }
+
+    /// Writes the graph to `out` in `format`, with `edge_metadata` on every
+    /// edge if asked for.
+    pub fn write(
+        &self,
+        format: TopologyFormat,
+        edge_metadata: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match format {
+            TopologyFormat::Graphml => self.write_graphml(edge_metadata, out),
+            TopologyFormat::Dot => self.write_dot(edge_metadata, out),
+            TopologyFormat::Adjacency => self.write_adjacency(edge_metadata, out),
+        }
+    }
+
+    fn write_graphml(&self, edge_metadata: bool, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        let keys = [
+            ("label", "node", "string"),
+            ("addr", "node", "string"),
+            ("protocol_version", "node", "string"),
+            ("latency_ms", "node", "long"),
+            ("reached", "node", "boolean"),
+        ];
+        let edge_keys = [
+            ("edge_protocol_version", "edge", "string"),
+            ("edge_latency_ms", "edge", "long"),
+        ];
+        let edge_keys = if edge_metadata { &edge_keys[..] } else { &[] };
+        for (id, scope, kind) in keys.iter().chain(edge_keys) {
+            let name = id.trim_start_matches("edge_");
+            writeln!(
+                out,
+                r#"  <key id="{id}" for="{scope}" attr.name="{name}" attr.type="{kind}"/>"#
+            )?;
+        }
+        writeln!(out, r#"  <graph id="casper" edgedefault="directed">"#)?;
+        for vertex in &self.vertices {
+            writeln!(out, r#"    <node id="{}">"#, xml_escape(&vertex.id))?;
+            write_data(out, "label", Some(vertex.label()))?;
+            write_data(out, "addr", Some(vertex.addr))?;
+            write_data(out, "protocol_version", vertex.protocol_version)?;
+            write_data(out, "latency_ms", vertex.latency_ms)?;
+            write_data(out, "reached", Some(vertex.reached))?;
+            writeln!(out, "    </node>")?;
+        }
+        for &(from, to) in &self.edges {
+            let (from, to) = (&self.vertices[from], &self.vertices[to]);
+            writeln!(
+                out,
+                r#"    <edge source="{}" target="{}">"#,
+                xml_escape(&from.id),
+                xml_escape(&to.id)
+            )?;
+            if edge_metadata {
+                write_data(out, "edge_protocol_version", to.protocol_version)?;
+                write_data(out, "edge_latency_ms", to.latency_ms)?;
+            }
+            writeln!(out, "    </edge>")?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+
+    fn write_dot(&self, edge_metadata: bool, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "digraph casper {{")?;
+        for vertex in &self.vertices {
+            let mut attributes = vec![
+                format!("label={}", dot_quote(&vertex.label())),
+                format!("addr={}", dot_quote(&vertex.addr.to_string())),
+            ];
+            if let Some(version) = vertex.protocol_version {
+                attributes.push(format!(
+                    "protocol_version={}",
+                    dot_quote(&version.to_string())
+                ));
+            }
+            if let Some(latency_ms) = vertex.latency_ms {
+                attributes.push(format!("latency_ms={latency_ms}"));
+            }
+            if !vertex.reached {
+                attributes.push("style=dashed".to_string());
+            }
+            writeln!(
+                out,
+                "  {} [{}];",
+                dot_quote(&vertex.id),
+                attributes.join(", ")
+            )?;
+        }
+        for &(from, to) in &self.edges {
+            let (from, to) = (&self.vertices[from], &self.vertices[to]);
+            let mut attributes = vec![];
+            if edge_metadata {
+                if let Some(version) = to.protocol_version {
+                    attributes.push(format!(
+                        "protocol_version={}",
+                        dot_quote(&version.to_string())
+                    ));
+                }
+                if let Some(latency_ms) = to.latency_ms {
+                    attributes.push(format!("latency_ms={latency_ms}"));
+                }
+            }
+            let attributes = match attributes.is_empty() {
+                true => String::new(),
+                false => format!(" [{}]", attributes.join(", ")),
+            };
+            writeln!(
+                out,
+                "  {} -> {}{attributes};",
+                dot_quote(&from.id),
+                dot_quote(&to.id)
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_adjacency(&self, edge_metadata: bool, out: &mut impl Write) -> io::Result<()> {
+        let mut vertices = Map::new();
+        let mut adjacency: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+        for vertex in &self.vertices {
+            vertices.insert(vertex.id.clone(), serde_json::to_value(vertex)?);
+            adjacency.entry(&vertex.id).or_default();
+        }
+        for &(from, to) in &self.edges {
+            let (from, to) = (&self.vertices[from], &self.vertices[to]);
+            let edge = match edge_metadata {
+                true => json!({
+                    "to": to.id,
+                    "protocol_version": to.protocol_version,
+                    "latency_ms": to.latency_ms,
+                }),
+                false => json!(to.id),
+            };
+            adjacency.entry(&from.id).or_default().push(edge);
+        }
+        serde_json::to_writer(
+            &mut *out,
+            &json!({ "vertices": vertices, "adjacency": adjacency }),
+        )?;
+        writeln!(out)
+    }
+}
+
+/// Writes a `data` element of `key`, nothing if there is no `value`.
+fn write_data(out: &mut impl Write, key: &str, value: Option<impl ToString>) -> io::Result<()> {
+    match value {
+        Some(value) => writeln!(
+            out,
+            r#"      <data key="{key}">{}</data>"#,
+            xml_escape(&value.to_string())
+        ),
+        None => Ok(()),
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `text` as a quoted DOT identifier.
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::CrawledNodeBuilder;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([10, 0, 0, 1], port)) }
+
+    fn node(id: u8, port: u16, peers: &[u16]) -> CrawledNode {
+        CrawledNodeBuilder::new(id, addr(port))
+            .protocol_version(ProtocolVersion::from_parts(1, 5, 2))
+            .latency_ms(12)
+            .peers(peers.iter().copied().map(addr))
+            .build()
+    }
+
+    fn written(topology: &Topology, format: TopologyFormat, edge_metadata: bool) -> String {
+        let mut out = vec![];
+        topology.write(format, edge_metadata, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn graphs_link_nodes_to_what_they_gossiped() {
+        let nodes = [node(1, 1, &[1, 2, 3]), node(2, 2, &[1])];
+        let topology = Topology::of(&nodes);
+        // Itself is no edge, and the unreached address is a vertex too.
+        assert_eq!(topology.vertices.len(), 3);
+        assert_eq!(topology.edges, BTreeSet::from([(0, 1), (0, 2), (1, 0)]));
+
+        let dot = written(&topology, TopologyFormat::Dot, true);
+        let unreached = dot.lines().find(|line| line.contains("10.0.0.1:3\" [")).unwrap();
+        assert!(unreached.ends_with("style=dashed];"), "{unreached}");
+        assert!(dot.contains(r#"-> "10.0.0.1:3";"#), "{dot}");
+        assert!(
+            dot.contains(r#" [protocol_version="1.5.2", latency_ms=12];"#),
+            "{dot}"
+        );
+
+        let adjacency: Value =
+            serde_json::from_str(&written(&topology, TopologyFormat::Adjacency, false)).unwrap();
+        let first = format!("{:#}", nodes[0].node_id);
+        assert_eq!(
+            adjacency["adjacency"][&first],
+            json!([format!("{:#}", nodes[1].node_id), "10.0.0.1:3"])
+        );
+        assert_eq!(adjacency["vertices"]["10.0.0.1:3"]["reached"], json!(false));
+
+        let graphml = written(&topology, TopologyFormat::Graphml, false);
+        assert_eq!(graphml.matches("<node ").count(), 3);
+        assert_eq!(graphml.matches("<edge ").count(), 3);
+        assert!(!graphml.contains("edge_latency_ms"));
+    }
+}
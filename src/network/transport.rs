@@ -0,0 +1,445 @@
+//! The byte streams connections run over.
+//!
+//! The manager only frames and exchanges messages; how a connection is
+//! established is up to a [`Transport`]. [`TlsTransport`] talks TLS over TCP
+//! the way Casper nodes do, while tests can swap in the in-memory transport
+//! from [`super::testing`].
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use prometheus::IntCounterVec;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use super::error::ManagerError;
+use super::error::TLSError;
+use super::manager::Manager;
+use super::node_id::NodeId;
+use super::progress::Phase;
+use super::progress::Step;
+use super::tls;
+use super::tls::Certificate;
+use super::tls::Identity;
+use super::tls::KeyLog;
+use super::tls::SessionId;
+use super::tls::TlsOptions;
+use super::tls::TlsStream;
+
+/// A bidirectional byte stream to a peer.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+pub type BoxedStream = Box<dyn Stream>;
+
+/// A connection a listener accepted, before it is set up.
+pub struct Accepted {
+    pub peer_addr: SocketAddr,
+    /// Sets the connection up, e.g. with a TLS handshake. It is up to the
+    /// peer how long that takes, so it runs apart from accepting others.
+    pub setup: BoxFuture<'static, Result<BoxedStream, ManagerError>>,
+}
+
+impl Accepted {
+    /// A connection with nothing left to set up.
+    pub fn ready(stream: BoxedStream, peer_addr: SocketAddr) -> Self {
+        Self {
+            peer_addr,
+            setup: futures::future::ready(Ok(stream)).boxed(),
+        }
+    }
+}
+
+/// Accepts incoming connections on a bound address.
+pub trait Listener: Send {
+    /// The address peers reach us on.
+    fn local_addr(&self) -> SocketAddr;
+
+    /// Waits for the next peer to connect.
+    fn accept(&mut self) -> BoxFuture<'_, Result<Accepted, ManagerError>>;
+}
+
+/// Establishes connections with peers.
+pub trait Transport: Send + Sync {
+    /// Starts listening on `addr`. Port 0 picks a free port.
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, Result<Box<dyn Listener>, ManagerError>>;
+
+    /// Connects to the peer listening on `addr`.
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<BoxedStream, ManagerError>>;
+
+    /// Like [`Transport::connect`], passing the connect phase and any
+    /// further ones, like TLS, to `report` as they start and complete.
+    fn connect_with_progress<'a>(
+        &'a self,
+        addr: SocketAddr,
+        report: &'a (dyn Fn(Step) + Sync),
+    ) -> BoxFuture<'a, Result<BoxedStream, ManagerError>> {
+        async move {
+            report(Step::Started(Phase::Connect));
+            let stream = self.connect(addr).await?;
+            report(Step::Completed(Phase::Connect));
+            Ok(stream)
+        }
+        .boxed()
+    }
+
+    /// Presents `identity` on connections established from now on, both
+    /// outgoing and accepted ones. Transports without certificates ignore it.
+    fn set_identity(&self, _identity: Identity) {}
+
+    /// Identifier of the peer at `addr`, from the certificate it presented
+    /// the last time we connected to it, or it connected to us from `addr`.
+    /// Transports without certificates have none.
+    fn peer_id(&self, _addr: SocketAddr) -> Option<NodeId> { None }
+
+    /// The TLS session last established with the peer at `addr`, which a
+    /// consensus certificate sent to it signs. Transports without TLS have
+    /// none.
+    fn session_id(&self, _addr: SocketAddr) -> Option<SessionId> { None }
+
+    /// Forgets the identifier of the peer at `addr` and the session with it,
+    /// once the connection over `session_id` closed. What a newer session
+    /// with the same address established is kept.
+    fn forget_peer(&self, _addr: SocketAddr, _session_id: SessionId) {}
+
+    /// Counts the TLS handshakes completed from now on with `counter`, by
+    /// direction and negotiated parameters. Transports without TLS ignore it.
+    fn count_handshakes(&self, _counter: IntCounterVec) {}
+}
+
+/// The identity a transport and its listeners present, swapped when the
+/// certificate is renewed.
+type SharedIdentity = Arc<RwLock<Identity>>;
+
+fn current(identity: &SharedIdentity) -> Identity {
+    identity.read().expect("identity lock poisoned").clone()
+}
+
+/// The counter of completed TLS handshakes, once the manager handed one over.
+type HandshakeCounter = Arc<RwLock<Option<IntCounterVec>>>;
+
+/// Logs the parameters the handshake with `addr` settled on and counts it.
+fn record_handshake<S>(
+    counter: &HandshakeCounter,
+    direction: &str,
+    addr: SocketAddr,
+    stream: &TlsStream<S>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let negotiated = stream.negotiated();
+    if negotiated.is_tls13() {
+        info!("Negotiated {negotiated} with {addr:?}");
+    } else {
+        warn!("Negotiated {negotiated} with {addr:?} although only TLSv1.3 is allowed");
+    }
+    if let Some(counter) = &*counter.read().expect("handshake counter lock poisoned") {
+        let group = negotiated.group.as_deref().unwrap_or("unknown");
+        counter
+            .with_label_values(&[direction, negotiated.version, &negotiated.cipher, group])
+            .inc();
+    }
+}
+
+/// What the transport knows of the peer at an address over one session,
+/// forgotten once dropped, when the connection closes.
+pub struct PeerRecord {
+    transport: Arc<dyn Transport>,
+    addr: SocketAddr,
+    session_id: SessionId,
+}
+
+impl PeerRecord {
+    /// The record of the session last established with `addr`, if the
+    /// transport keeps any.
+    pub fn new(transport: Arc<dyn Transport>, addr: SocketAddr) -> Option<Self> {
+        let session_id = transport.session_id(addr)?;
+        Some(Self {
+            transport,
+            addr,
+            session_id,
+        })
+    }
+}
+
+impl Drop for PeerRecord {
+    fn drop(&mut self) { self.transport.forget_peer(self.addr, self.session_id); }
+}
+
+/// Identifiers of peers, by address.
+type PeerIds = Arc<Mutex<HashMap<SocketAddr, NodeId>>>;
+
+/// TLS sessions established with peers, by address.
+type Sessions = Arc<Mutex<HashMap<SocketAddr, SessionId>>>;
+
+/// Remembers the identifier of the peer at `addr` and the session
+/// established with it.
+fn record_peer<S>(
+    peer_ids: &PeerIds,
+    sessions: &Sessions,
+    identity: &Identity,
+    addr: SocketAddr,
+    peer_id: NodeId,
+    stream: &TlsStream<S>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    info!("Peer at {addr:?} is {peer_id}");
+    let session_id = SessionId::of(stream, &identity.fingerprint(), &peer_id.fingerprint());
+    peer_ids.lock().expect("peer id lock poisoned").insert(addr, peer_id);
+    sessions.lock().expect("session lock poisoned").insert(addr, session_id);
+}
+
+/// TLS over TCP, presenting `identity` and checking the peer's self-signed
+/// certificate.
+#[derive(Clone, Debug)]
+pub struct TlsTransport {
+    identity: SharedIdentity,
+    options: TlsOptions,
+    handshakes: HandshakeCounter,
+    /// Identifiers of the peers we connected to, by the address dialed,
+    /// and of those that connected to us, by the address they came from.
+    peer_ids: PeerIds,
+    /// Sessions established with the same peers.
+    sessions: Sessions,
+}
+
+impl TlsTransport {
+    /// Logs the secrets of every session to the file named by
+    /// `SSLKEYLOGFILE` if set and `options` has no key log of its own.
+    pub fn new(identity: Identity, mut options: TlsOptions) -> Self {
+        options.key_log = options.key_log.or_else(KeyLog::from_env);
+        Self {
+            identity: Arc::new(RwLock::new(identity)),
+            options,
+            handshakes: Arc::default(),
+            peer_ids: Arc::default(),
+            sessions: Arc::default(),
+        }
+    }
+
+    #[instrument(name = "outbound_connection", skip(self, report), fields(peer = %addr))]
+    async fn connect_tls(
+        &self,
+        addr: SocketAddr,
+        report: &(dyn Fn(Step) + Sync),
+    ) -> Result<(TlsStream<TcpStream>, Certificate), ManagerError> {
+        let identity = current(&self.identity);
+        let (transport, peer_cert) = self.handshake(&identity, addr, report).await?;
+
+        let peer_cert = match identity.network_ca() {
+            Some(network_ca) => {
+                let chain = transport.peer_chain();
+                tls::validate_peer_cert_with_ca(peer_cert, &chain, network_ca, &self.options)?
+            }
+            None => {
+                let validation = tls::validate_peer_cert_detailed(&peer_cert, &self.options);
+                for (check, error) in validation.failures() {
+                    warn!("Certificate of {addr:?} failed the {check} check: {error}");
+                }
+                validation.into_result()?;
+                peer_cert
+            }
+        };
+        let peer_id = NodeId::of(&peer_cert).map_err(|_| TLSError::CannotReadPublicKey)?;
+        record_peer(
+            &self.peer_ids,
+            &self.sessions,
+            &identity,
+            addr,
+            peer_id,
+            &transport,
+        );
+        report(Step::Completed(Phase::Tls));
+
+        Ok((transport, peer_cert))
+    }
+
+    /// Connects to `addr` like [`Transport::connect`], but hands out the TLS
+    /// stream itself along with the peer's certificate, once checked.
+    pub async fn connect_validated(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<(TlsStream<TcpStream>, Certificate), ManagerError> {
+        self.connect_tls(addr, &|_| {}).await
+    }
+
+    /// Completes a TLS handshake with `addr` and returns the certificate it
+    /// presented, without checking it.
+    pub async fn peer_certificate(&self, addr: SocketAddr) -> Result<Certificate, ManagerError> {
+        let (_, peer_cert) = self.handshake(&current(&self.identity), addr, &|_| {}).await?;
+        Ok(peer_cert)
+    }
+
+    /// Reports the connect phase, and the start of the TLS phase, which is
+    /// only complete once the caller checked the certificate.
+    async fn handshake(
+        &self,
+        identity: &Identity,
+        addr: SocketAddr,
+        report: &(dyn Fn(Step) + Sync),
+    ) -> Result<(TlsStream<TcpStream>, Certificate), ManagerError> {
+        info!("Connecting to {addr:?}");
+        report(Step::Started(Phase::Connect));
+        let stream = TcpStream::connect(addr).await.map_err(TLSError::TcpConnection)?;
+
+        stream.set_nodelay(true).map_err(|_| TLSError::TcpNoDelay)?;
+        report(Step::Completed(Phase::Connect));
+
+        report(Step::Started(Phase::Tls));
+
+        let mut transport = TlsStream::client(stream, identity, &self.options)?;
+        transport.connect().await?;
+        record_handshake(&self.handshakes, "outbound", addr, &transport);
+
+        let peer_cert = transport.peer_certificate()?;
+        Ok((transport, peer_cert))
+    }
+}
+
+impl Transport for TlsTransport {
+    fn bind(&self, addr: SocketAddr) -> BoxFuture<'_, Result<Box<dyn Listener>, ManagerError>> {
+        async move {
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|error| ManagerError::ListenerCreation(error, addr))?;
+            let local_addr = listener
+                .local_addr()
+                .map_err(|error| ManagerError::ListenerCreation(error, addr))?;
+            Ok(Box::new(TlsListener {
+                listener,
+                local_addr,
+                identity: self.identity.clone(),
+                inbound: InboundTls {
+                    options: self.options.clone(),
+                    handshakes: self.handshakes.clone(),
+                    peer_ids: self.peer_ids.clone(),
+                    sessions: self.sessions.clone(),
+                },
+            }) as Box<dyn Listener>)
+        }
+        .boxed()
+    }
+
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, Result<BoxedStream, ManagerError>> {
+        self.connect_with_progress(addr, &|_| {})
+    }
+
+    fn connect_with_progress<'a>(
+        &'a self,
+        addr: SocketAddr,
+        report: &'a (dyn Fn(Step) + Sync),
+    ) -> BoxFuture<'a, Result<BoxedStream, ManagerError>> {
+        async move {
+            let (stream, _) = self.connect_tls(addr, report).await?;
+            Ok(Box::new(stream) as BoxedStream)
+        }
+        .boxed()
+    }
+
+    fn set_identity(&self, identity: Identity) {
+        *self.identity.write().expect("identity lock poisoned") = identity;
+    }
+
+    fn peer_id(&self, addr: SocketAddr) -> Option<NodeId> {
+        self.peer_ids.lock().expect("peer id lock poisoned").get(&addr).copied()
+    }
+
+    fn session_id(&self, addr: SocketAddr) -> Option<SessionId> {
+        self.sessions.lock().expect("session lock poisoned").get(&addr).copied()
+    }
+
+    fn forget_peer(&self, addr: SocketAddr, session_id: SessionId) {
+        let mut sessions = self.sessions.lock().expect("session lock poisoned");
+        if sessions.get(&addr) == Some(&session_id) {
+            sessions.remove(&addr);
+            self.peer_ids.lock().expect("peer id lock poisoned").remove(&addr);
+        }
+    }
+
+    fn count_handshakes(&self, counter: IntCounterVec) {
+        *self.handshakes.write().expect("handshake counter lock poisoned") = Some(counter);
+    }
+}
+
+struct TlsListener {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    identity: SharedIdentity,
+    inbound: InboundTls,
+}
+
+/// What setting up TLS on accepted connections takes, apart from the
+/// identity, which is read as each connection is accepted.
+#[derive(Clone)]
+struct InboundTls {
+    options: TlsOptions,
+    handshakes: HandshakeCounter,
+    peer_ids: PeerIds,
+    sessions: Sessions,
+}
+
+impl InboundTls {
+    /// Sets up TLS on an accepted TCP connection.
+    #[instrument(name = "inbound_connection", skip(self, stream, identity), fields(peer = %peer_addr))]
+    async fn accept_tls(
+        self,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        identity: Identity,
+    ) -> Result<BoxedStream, ManagerError> {
+        info!("Setting up TLS with connected peer");
+        let mut transport = Manager::setup_tls(stream, &identity, &self.options).await?;
+
+        info!("Performing TLS handshake with connected peer");
+        Manager::perform_tls_handshake(&mut transport).await?;
+        record_handshake(&self.handshakes, "inbound", peer_addr, &transport);
+
+        info!("Receiving peer Ssl certificates");
+        let peer_cert = transport.peer_certificate()?;
+
+        let peer_id = NodeId::of(&peer_cert).map_err(|_| TLSError::CannotReadPublicKey)?;
+        info!("Verifying peer's certificates for sanity");
+        identity.validate_peer_cert(peer_cert, &transport.peer_chain(), &self.options)?;
+        record_peer(
+            &self.peer_ids,
+            &self.sessions,
+            &identity,
+            peer_addr,
+            peer_id,
+            &transport,
+        );
+
+        Ok(Box::new(transport))
+    }
+}
+
+impl Listener for TlsListener {
+    fn local_addr(&self) -> SocketAddr { self.local_addr }
+
+    fn accept(&mut self) -> BoxFuture<'_, Result<Accepted, ManagerError>> {
+        async move {
+            let (stream, peer_addr) =
+                self.listener.accept().await.map_err(TLSError::TcpConnection)?;
+            info!("New connection received!");
+            let identity = current(&self.identity);
+            let setup = self.inbound.clone().accept_tls(stream, peer_addr, identity);
+            Ok(Accepted {
+                peer_addr,
+                setup: setup.boxed(),
+            })
+        }
+        .boxed()
+    }
+}
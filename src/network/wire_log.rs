@@ -0,0 +1,416 @@
+//! Capturing every frame exchanged with peers, for protocol debugging.
+//!
+//! With a wire log configured, every frame read from or written to a peer is
+//! recorded, uncompressed, with when it passed, in which direction, the peer
+//! and the kind of message it carries. A capture starts with [`MAGIC`],
+//! followed by the records, each a big-endian `u32` length and the record in
+//! bincode. [`Capture`] reads them back and [`decode`] tells what a frame
+//! says, so captures can be looked at offline.
+//!
+//! Frames are handed to a writer thread of their own, so a slow disk holds
+//! up no connection. It writes records as they come and flushes whenever it
+//! caught up, so a capture survives the process being killed. Should it fall
+//! more than [`QUEUE_LEN`] frames behind, frames are left out of the capture
+//! rather than waited for, and how many is logged.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+use bytes::Bytes;
+use casper_types::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::warn;
+
+use super::connection::Direction;
+use super::gossip::NodePayload;
+use super::gossip::NodePayloadDiscriminants;
+use super::manager::MAX_FRAME_LEN;
+use super::message::BincodeFormat;
+use super::message::Message;
+use super::message::MessagePackFormat;
+use super::message::Route;
+use super::observe::Gossiper;
+use super::observe::Observed;
+
+/// What every capture starts with.
+pub const MAGIC: &[u8; 8] = b"SCHWLOG1";
+
+/// Room for the fields of a record besides the frame.
+const RECORD_OVERHEAD: usize = 1024;
+
+/// Frames waiting for the writer thread at most.
+pub const QUEUE_LEN: usize = 1024;
+
+/// Bytes of the buffer records are written through.
+const WRITE_BUFFER: usize = 64 * 1024;
+
+/// A frame exchanged with a peer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp: Timestamp,
+    /// Inbound if the frame was read from the peer, outbound if written to
+    /// it.
+    pub direction: Direction,
+    pub peer: SocketAddr,
+    /// Kind of message the frame carries, see [`Decoded::tag`].
+    pub tag: String,
+    /// The frame, uncompressed.
+    pub bytes: Vec<u8>,
+}
+
+impl Record {
+    /// What the frame says.
+    pub fn decode(&self) -> Decoded { decode(self.peer, &Bytes::copy_from_slice(&self.bytes)) }
+}
+
+/// A [`Record`] of a frame still being passed on, which is serialized the
+/// same without copying the frame.
+#[derive(Serialize)]
+struct BorrowedRecord<'a> {
+    timestamp: Timestamp,
+    direction: Direction,
+    peer: SocketAddr,
+    tag: String,
+    bytes: &'a [u8],
+}
+
+/// What a frame says, as far as schultz understands it.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Decoded {
+    /// A message schultz decodes, the handshake in MessagePack and the rest
+    /// in bincode.
+    Message(Message<NodePayload>),
+    /// A gossip message about an item schultz does not decode.
+    Gossip(Observed),
+    /// Anything else, e.g. consensus traffic.
+    Unknown,
+}
+
+impl Decoded {
+    /// Short name of the kind of message, e.g. `ping` or
+    /// `payload/AddressGossiper`.
+    pub fn tag(&self) -> String {
+        match self {
+            Decoded::Message(message) => match message.route() {
+                Route::Handshake => "handshake".to_string(),
+                Route::Ping => "ping".to_string(),
+                Route::Pong => "pong".to_string(),
+                Route::Payload(kind) => format!("payload/{kind:?}"),
+            },
+            Decoded::Gossip(observed) => {
+                let kind = match observed.gossiper {
+                    Gossiper::Block => NodePayloadDiscriminants::BlockGossiper,
+                    Gossiper::Deploy => NodePayloadDiscriminants::DeployGossiper,
+                    Gossiper::FinalitySignature => {
+                        NodePayloadDiscriminants::FinalitySignatureGossiper
+                    }
+                    Gossiper::Address => NodePayloadDiscriminants::AddressGossiper,
+                };
+                format!("payload/{kind:?}")
+            }
+            Decoded::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+impl Display for Decoded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Decoded::Message(Message::Payload(payload)) => write!(f, "{payload}"),
+            Decoded::Message(message) => write!(f, "{message:?}"),
+            Decoded::Gossip(observed) => {
+                write!(
+                    f,
+                    "{} {} {}",
+                    observed.gossiper, observed.kind, observed.item
+                )
+            }
+            Decoded::Unknown => write!(f, "undecoded"),
+        }
+    }
+}
+
+/// Tells what the `frame` exchanged with `peer` says, trying the formats in
+/// the order the manager does.
+pub fn decode(peer: SocketAddr, frame: &Bytes) -> Decoded {
+    let handshake: io::Result<Message<NodePayload>> =
+        MessagePackFormat.deserialize_arbitrary(frame);
+    if let Ok(message @ Message::Handshake { .. }) = handshake {
+        return Decoded::Message(message);
+    }
+    let message: io::Result<Message<NodePayload>> =
+        BincodeFormat::default().deserialize_arbitrary(frame);
+    if let Ok(message) = message {
+        return Decoded::Message(message);
+    }
+    Observed::parse(peer, frame).map_or(Decoded::Unknown, Decoded::Gossip)
+}
+
+/// A frame waiting to be written.
+struct Pending {
+    timestamp: Timestamp,
+    direction: Direction,
+    peer: SocketAddr,
+    frame: Bytes,
+}
+
+/// A capture being written.
+#[derive(Debug)]
+pub struct WireLog {
+    pending: Option<SyncSender<Pending>>,
+    writer: Option<JoinHandle<()>>,
+    /// Bytes of the frames waiting.
+    queued: Arc<AtomicUsize>,
+    /// Frames left out since the writer last said so.
+    dropped: Arc<AtomicU64>,
+}
+
+impl WireLog {
+    /// Opens the capture at `path` to add records to, starting a new one if
+    /// the file is missing or empty, and starts writing to it.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::options().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        }
+        let (pending, received) = mpsc::sync_channel(QUEUE_LEN);
+        let queued = Arc::new(AtomicUsize::new(0));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = Writer {
+            file: BufWriter::with_capacity(WRITE_BUFFER, file),
+            queued: queued.clone(),
+            dropped: dropped.clone(),
+        };
+        let writer = thread::Builder::new()
+            .name("wire-log".to_string())
+            .spawn(move || writer.run(received))?;
+        Ok(Self {
+            pending: Some(pending),
+            writer: Some(writer),
+            queued,
+            dropped,
+        })
+    }
+
+    /// Records `frame` passing in `direction` with `peer`. The frame is left
+    /// out if the writer fell too far behind, and a failure to write is
+    /// logged, rather than interrupting the connection.
+    pub fn record(&self, direction: Direction, peer: SocketAddr, frame: &Bytes) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let len = frame.len();
+        self.queued.fetch_add(len, Ordering::Relaxed);
+        let sent = pending.try_send(Pending {
+            timestamp: Timestamp::now(),
+            direction,
+            peer,
+            frame: frame.clone(),
+        });
+        if sent.is_err() {
+            self.queued.fetch_sub(len, Ordering::Relaxed);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Bytes of the frames waiting and of the buffer records are written
+    /// through.
+    pub fn buffer_size(&self) -> usize { self.queued.load(Ordering::Relaxed) + WRITE_BUFFER }
+}
+
+impl Drop for WireLog {
+    /// Waits for the frames recorded so far to be written.
+    fn drop(&mut self) {
+        self.pending.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Writes the frames of a [`WireLog`] on a thread of its own.
+struct Writer {
+    file: BufWriter<File>,
+    queued: Arc<AtomicUsize>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Writer {
+    /// Writes what is `received` until the wire log is dropped.
+    fn run(mut self, received: Receiver<Pending>) {
+        while let Ok(pending) = received.recv() {
+            self.write_logged(pending);
+            // Flush only once caught up, and write what keeps coming first.
+            while let Ok(pending) = received.try_recv() {
+                self.write_logged(pending);
+            }
+            if let Err(e) = self.file.flush() {
+                warn!("Could not write to the wire log: {e}");
+            }
+            let dropped = self.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("Left {dropped} frames out of the wire log, as it fell behind");
+            }
+        }
+    }
+
+    fn write_logged(&mut self, pending: Pending) {
+        self.queued.fetch_sub(pending.frame.len(), Ordering::Relaxed);
+        if let Err(e) = self.write(&pending) {
+            warn!("Could not write to the wire log: {e}");
+        }
+    }
+
+    fn write(&mut self, pending: &Pending) -> io::Result<()> {
+        let record = BorrowedRecord {
+            timestamp: pending.timestamp,
+            direction: pending.direction,
+            peer: pending.peer,
+            tag: decode(pending.peer, &pending.frame).tag(),
+            bytes: &pending.frame,
+        };
+        let bytes = BincodeFormat::default().serialize_arbitrary(&record)?;
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too long"))?;
+        self.file.write_all(&len.to_be_bytes())?;
+        self.file.write_all(&bytes)
+    }
+}
+
+/// The records of a capture, in the order they were written.
+pub struct Capture<R> {
+    reader: R,
+}
+
+impl Capture<BufReader<File>> {
+    /// Reads the capture at `path`.
+    pub fn open(path: &Path) -> io::Result<Self> { Self::new(BufReader::new(File::open(path)?)) }
+}
+
+impl<R: Read> Capture<R> {
+    /// Reads a capture from `reader`, failing if it does not start with
+    /// [`MAGIC`].
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a schultz wire log",
+            ));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut len = [0; 4];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            // A record cut short by the process being killed ends the
+            // capture as well.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN + RECORD_OVERHEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record of {len} bytes is longer than any frame"),
+            ));
+        }
+        let mut bytes = vec![0; len];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        BincodeFormat::default().deserialize_arbitrary(&bytes).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for Capture<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> { self.read_record().transpose() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::network::gossip::GossipMessage;
+    use crate::network::gossip::GossipedAddress;
+    use crate::primitives::Nonce;
+
+    fn peer() -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], 35000)) }
+
+    #[test]
+    fn captures_read_back_what_was_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wire.log");
+        let bincode = BincodeFormat::default();
+        let ping = bincode
+            .serialize_arbitrary(&Message::<NodePayload>::Ping {
+                nonce: Nonce::new(7),
+            })
+            .map(Bytes::from)
+            .unwrap();
+        let gossip = bincode
+            .serialize_arbitrary(&Message::Payload(NodePayload::AddressGossiper(
+                GossipMessage::Gossip(GossipedAddress::new(peer(), 1)),
+            )))
+            .map(Bytes::from)
+            .unwrap();
+
+        let wire_log = WireLog::open(&path).unwrap();
+        wire_log.record(Direction::Outbound, peer(), &ping);
+        drop(wire_log);
+        // Reopening adds to the capture rather than starting over.
+        let wire_log = WireLog::open(&path).unwrap();
+        wire_log.record(Direction::Inbound, peer(), &gossip);
+        wire_log.record(Direction::Inbound, peer(), &Bytes::from_static(&[9, 9]));
+        drop(wire_log);
+
+        let records: Vec<_> = Capture::open(&path).unwrap().map(Result::unwrap).collect();
+        let tags: Vec<_> = records.iter().map(|record| record.tag.as_str()).collect();
+        assert_eq!(tags, ["ping", "payload/AddressGossiper", "unknown"]);
+        assert_eq!(records[0].direction, Direction::Outbound);
+        assert_eq!(records[1].bytes, gossip);
+        assert_eq!(
+            records[1].decode().to_string(),
+            format!("gossip {}", GossipedAddress::new(peer(), 1))
+        );
+    }
+
+    #[test]
+    fn truncated_and_foreign_captures() {
+        let mut capture = MAGIC.to_vec();
+        capture.extend_from_slice(&100u32.to_be_bytes());
+        capture.extend_from_slice(&[0; 10]);
+        assert_eq!(Capture::new(Cursor::new(capture)).unwrap().count(), 0);
+
+        let error = Capture::new(Cursor::new(b"not a capture".to_vec())).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,295 @@
+//! Unix socket, or named pipe on Windows, answering queries about a running
+//! node.
+//!
+//! Every line a client writes is a [`Request`] in JSON, answered with a line
+//! holding the [`Response`], until the client hangs up. `schultz peers` asks
+//! and `schultz export-netstate` ask through it, and anything that speaks JSON
+//! over a Unix socket or named pipe can too. On Windows, which has no SIGHUP,
+//! `schultz reload` asks through it for the configuration to be reloaded.
+//! `schultz log-filter` asks through it for the log filter to be replaced.
+//! The gRPC service, with the `grpc` feature, answers the same.
+
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+#[cfg(windows)]
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::ClientOptions;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::NamedPipeServer;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::ServerOptions;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tracing::info;
+use tracing::warn;
+
+use super::signals::Reload;
+use super::Node;
+use crate::network::manager::NetworkState;
+use crate::network::manager::PeerInfo;
+use crate::telemetry;
+
+/// A query about the node, e.g. `{"command":"peers"}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Every peer the node has a connection to.
+    Peers,
+    /// A snapshot of everything the node knows about the network.
+    NetworkState,
+    /// Reload the configuration, as SIGHUP does on Unix.
+    Reload,
+    /// Connect and handshake with the peer at `addr`.
+    Connect { addr: SocketAddr },
+    /// Close the connection to the peer at `addr`.
+    Disconnect { addr: SocketAddr },
+    /// The directives logs are filtered by, replaced by `directives` if
+    /// given.
+    LogFilter { directives: Option<String> },
+}
+
+/// The answer to a [`Request`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Peers(Vec<PeerInfo>),
+    NetworkState(Box<NetworkState>),
+    /// The configuration was reloaded, but for the settings listed, which
+    /// need a restart to apply.
+    Reloaded(Vec<String>),
+    Connected(SocketAddr),
+    Disconnected(SocketAddr),
+    /// The directives logs are filtered by now.
+    LogFilter(String),
+    /// The request could not be understood, or not be carried out.
+    Error(String),
+}
+
+/// Where control requests come in.
+#[cfg(unix)]
+pub type Listener = UnixListener;
+
+/// Where control requests come in: a named pipe, with the instance the next
+/// client connects to.
+#[cfg(windows)]
+pub struct Listener {
+    path: PathBuf,
+    next: NamedPipeServer,
+}
+
+/// Listens on `path`, replacing a socket left behind by a node that is gone.
+///
+/// Fails if a node still answers on `path`.
+#[cfg(unix)]
+pub fn bind(path: &Path) -> io::Result<Listener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another node answers on this socket",
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// Listens on the named pipe `path`, e.g. `\\.\pipe\schultz`.
+///
+/// Fails if a node still answers on `path`.
+#[cfg(windows)]
+pub fn bind(path: &Path) -> io::Result<Listener> {
+    let next = ServerOptions::new().first_pipe_instance(true).create(path)?;
+    Ok(Listener {
+        path: path.to_path_buf(),
+        next,
+    })
+}
+
+#[cfg(unix)]
+async fn accept(listener: &mut Listener) -> io::Result<UnixStream> {
+    Ok(listener.accept().await?.0)
+}
+
+/// Waits for a client on the pipe instance listened on, and listens on a new
+/// one for the next client.
+#[cfg(windows)]
+async fn accept(listener: &mut Listener) -> io::Result<NamedPipeServer> {
+    listener.next.connect().await?;
+    let next = ServerOptions::new().create(&listener.path)?;
+    Ok(std::mem::replace(&mut listener.next, next))
+}
+
+#[cfg(unix)]
+fn local_path(listener: &Listener) -> io::Result<String> {
+    Ok(format!("{:?}", listener.local_addr()?))
+}
+
+#[cfg(windows)]
+fn local_path(listener: &Listener) -> io::Result<String> { Ok(listener.path.display().to_string()) }
+
+/// Answers requests about `node` on `listener` until accepting fails, passing
+/// [`Request::Reload`] on to `reloads`.
+pub async fn serve(
+    mut listener: Listener,
+    node: Node,
+    reloads: mpsc::Sender<Reload>,
+) -> io::Result<()> {
+    info!("Answering control requests on {}", local_path(&listener)?);
+    loop {
+        let stream = accept(&mut listener).await?;
+        let node = node.clone();
+        let reloads = reloads.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer(stream, &node, &reloads).await {
+                warn!("Control connection failed: {e}");
+            }
+        });
+    }
+}
+
+async fn answer<S>(stream: S, node: &Node, reloads: &mpsc::Sender<Reload>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => handle(request, node, reloads).await,
+            Err(e) => Response::Error(format!("invalid request: {e}")),
+        };
+        let mut bytes = serde_json::to_vec(&response)?;
+        bytes.push(b'\n');
+        write.write_all(&bytes).await?;
+    }
+    Ok(())
+}
+
+async fn handle(request: Request, node: &Node, reloads: &mpsc::Sender<Reload>) -> Response {
+    match request {
+        Request::Peers => Response::Peers(node.manager.read().await.peers().await),
+        Request::NetworkState => {
+            Response::NetworkState(Box::new(node.manager.read().await.network_state().await))
+        }
+        Request::Reload => {
+            let (reload, reloaded) = oneshot::channel();
+            if reloads.send(reload).await.is_err() {
+                return Response::Error("the node does not reload".to_string());
+            }
+            match reloaded.await {
+                Ok(Ok(restart)) => Response::Reloaded(restart),
+                Ok(Err(e)) => Response::Error(e),
+                Err(_) => Response::Error("the node stopped reloading".to_string()),
+            }
+        }
+        Request::Connect { addr } => match node.connect_peer(addr).await {
+            Ok(()) => Response::Connected(addr),
+            Err(e) => Response::Error(format!("could not connect to {addr}: {e}")),
+        },
+        Request::Disconnect { addr } => match node.disconnect_peer(addr).await {
+            true => Response::Disconnected(addr),
+            false => Response::Error(format!("not connected to {addr}")),
+        },
+        Request::LogFilter { directives } => {
+            let filter = match directives {
+                Some(directives) => telemetry::set_log_filter(&directives).and_then(|()| {
+                    info!("Filtering logs by {directives:?}");
+                    telemetry::log_filter()
+                }),
+                None => telemetry::log_filter(),
+            };
+            match filter {
+                Ok(filter) => Response::LogFilter(filter),
+                Err(e) => Response::Error(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Sends `request` to the node answering on `path` and waits for the
+/// response.
+#[cfg(unix)]
+pub async fn request(path: &Path, request: &Request) -> io::Result<Response> {
+    exchange(UnixStream::connect(path).await?, request).await
+}
+
+/// Sends `request` to the node answering on the named pipe `path` and waits
+/// for the response.
+#[cfg(windows)]
+pub async fn request(path: &Path, request: &Request) -> io::Result<Response> {
+    exchange(ClientOptions::new().open(path)?, request).await
+}
+
+async fn exchange<S>(mut stream: S, request: &Request) -> io::Result<Response>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut bytes = serde_json::to_vec(request)?;
+    bytes.push(b'\n');
+    stream.write_all(&bytes).await?;
+    let line = BufReader::new(stream).lines().next_line().await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the node hung up without answering",
+        )
+    })?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_and_responses_are_json_lines() {
+        assert_eq!(
+            serde_json::to_string(&Request::Peers).unwrap(),
+            r#"{"command":"peers"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Response::Error("nope".to_string())).unwrap(),
+            r#"{"error":"nope"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"connect","addr":"127.0.0.1:1"}"#)
+                .unwrap(),
+            Request::Connect {
+                addr: SocketAddr::from(([127, 0, 0, 1], 1))
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"log_filter"}"#).unwrap(),
+            Request::LogFilter { directives: None }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command":"reboot"}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stale_sockets_are_replaced_but_live_ones_are_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let listener = bind(&path).expect("the stale socket was replaced");
+        assert_eq!(bind(&path).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+    }
+}
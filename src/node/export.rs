@@ -0,0 +1,327 @@
+//! Metrics pushed to a Prometheus pushgateway or a statsd daemon, for
+//! environments where nothing scrapes `/metrics`.
+//!
+//! Every `telemetry.push_interval` each configured [`Exporter`] is handed
+//! what the node's registry holds, the same metrics `/metrics` serves. The
+//! pushgateway gets them all in the text format, grouped under job `schultz`
+//! and the node's address as instance. Statsd gets counters as their increase
+//! since the last push and gauges as they are, histograms are left out. Plain
+//! statsd has no labels, so they become part of the name, as in
+//! `net_handshakes.outcome.completed`, where dogstatsd gets them as tags.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use miette::Diagnostic;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
+use prometheus::Encoder;
+use prometheus::Registry;
+use prometheus::TextEncoder;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+use crate::http;
+use crate::http::Endpoint;
+use crate::http::ParseEndpointError;
+
+/// How often metrics are pushed unless configured otherwise.
+pub const PUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Port pushgateways listen on by default.
+const PUSHGATEWAY_PORT: u16 = 9091;
+
+/// Job metrics are grouped under on the pushgateway.
+const JOB: &str = "schultz";
+
+/// Largest statsd packet sent, small enough not to be fragmented on common
+/// links.
+const MAX_PACKET_LEN: usize = 1432;
+
+/// Longest pushgateway answer read, to report why a push failed.
+const MAX_ANSWER_LEN: usize = 4 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Invalid pushgateway {0:?}")]
+    Pushgateway(String, #[source] ParseEndpointError),
+    #[error("Could not reach statsd at {0}")]
+    Statsd(String, #[source] io::Error),
+}
+
+impl Diagnostic for ExportError {}
+
+/// The statsd dialect spoken.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdFormat {
+    /// Labels become part of the metric name.
+    #[default]
+    Statsd,
+    /// Labels are sent as tags.
+    Dogstatsd,
+}
+
+/// Somewhere metrics are pushed to.
+#[derive(Debug)]
+pub enum Exporter {
+    Pushgateway(Pushgateway),
+    Statsd(Statsd),
+}
+
+impl Exporter {
+    async fn push(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Exporter::Pushgateway(pushgateway) => pushgateway.push(registry).await,
+            Exporter::Statsd(statsd) => statsd.push(registry).await,
+        }
+    }
+}
+
+impl Display for Exporter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Exporter::Pushgateway(pushgateway) => write!(f, "pushgateway {}", pushgateway.endpoint),
+            Exporter::Statsd(statsd) => write!(f, "statsd {}", statsd.addr),
+        }
+    }
+}
+
+/// A Prometheus pushgateway, replacing the metrics of the node on every
+/// push.
+#[derive(Debug)]
+pub struct Pushgateway {
+    endpoint: Endpoint,
+}
+
+impl Pushgateway {
+    /// The pushgateway at `url`, e.g. `http://localhost:9091`, grouping the
+    /// metrics pushed under `instance`.
+    pub fn new(url: &str, instance: SocketAddr) -> Result<Self, ExportError> {
+        let endpoint = Endpoint::parse(url, PUSHGATEWAY_PORT, "")
+            .map_err(|e| ExportError::Pushgateway(url.to_string(), e))?;
+        Ok(Pushgateway {
+            endpoint: endpoint.join(&format!("metrics/job/{JOB}/instance/{instance}")),
+        })
+    }
+
+    async fn push(&self, registry: &Registry) -> io::Result<()> {
+        let encoder = TextEncoder::new();
+        let mut body = vec![];
+        encoder
+            .encode(&registry.gather(), &mut body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let content_type = encoder.format_type();
+        let headers = [("Content-Type", content_type)];
+        let response = http::request(&self.endpoint, "PUT", &headers, &body).await?;
+        if !(200..300).contains(&response.status) {
+            let status = response.status;
+            let answer = response.read_to_end(MAX_ANSWER_LEN).await.unwrap_or_default();
+            return Err(io::Error::other(format!(
+                "answered {status}: {}",
+                String::from_utf8_lossy(&answer).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A statsd daemon, sent counters and gauges over UDP.
+#[derive(Debug)]
+pub struct Statsd {
+    addr: SocketAddr,
+    socket: UdpSocket,
+    format: StatsdFormat,
+    /// Counter values last pushed, by name and labels.
+    counters: HashMap<String, f64>,
+}
+
+impl Statsd {
+    /// Looks up `addr`, e.g. `localhost:8125`, and sends from a socket of
+    /// its address family.
+    pub async fn connect(addr: &str, format: StatsdFormat) -> Result<Self, ExportError> {
+        let error = |e| ExportError::Statsd(addr.to_string(), e);
+        let resolved = tokio::net::lookup_host(addr)
+            .await
+            .map_err(error)?
+            .next()
+            .ok_or_else(|| error(io::ErrorKind::NotFound.into()))?;
+        let local = match resolved {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local).await.map_err(error)?;
+        socket.connect(resolved).await.map_err(error)?;
+        Ok(Statsd {
+            addr: resolved,
+            socket,
+            format,
+            counters: HashMap::new(),
+        })
+    }
+
+    async fn push(&mut self, registry: &Registry) -> io::Result<()> {
+        let lines = self.lines(&registry.gather());
+        for packet in packets(&lines) {
+            self.socket.send(packet.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// A statsd line for every counter that increased and every gauge in
+    /// `families`.
+    fn lines(&mut self, families: &[MetricFamily]) -> Vec<String> {
+        let mut lines = vec![];
+        for family in families {
+            for metric in family.get_metric() {
+                let labels: Vec<_> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| (label.get_name(), label.get_value()))
+                    .collect();
+                let (value, kind) = match family.get_field_type() {
+                    MetricType::COUNTER => {
+                        let value = metric.get_counter().get_value();
+                        let key = format!("{}{labels:?}", family.get_name());
+                        let last = self.counters.insert(key, value).unwrap_or(0.0);
+                        // Counters start over with the node.
+                        let increase = if value >= last { value - last } else { value };
+                        if increase == 0.0 {
+                            continue;
+                        }
+                        (increase, "c")
+                    }
+                    MetricType::GAUGE => (metric.get_gauge().get_value(), "g"),
+                    _ => continue,
+                };
+                lines.push(match self.format {
+                    StatsdFormat::Statsd => {
+                        let mut name = family.get_name().to_string();
+                        for (label, value) in &labels {
+                            name.push_str(&format!(".{}.{}", sanitize(label), sanitize(value)));
+                        }
+                        format!("{name}:{value}|{kind}")
+                    }
+                    StatsdFormat::Dogstatsd => {
+                        let name = family.get_name();
+                        let tags: Vec<_> = labels
+                            .iter()
+                            .map(|(label, value)| format!("{label}:{}", sanitize(value)))
+                            .collect();
+                        match tags.is_empty() {
+                            true => format!("{name}:{value}|{kind}"),
+                            false => format!("{name}:{value}|{kind}|#{}", tags.join(",")),
+                        }
+                    }
+                });
+            }
+        }
+        lines
+    }
+}
+
+/// Replaces what statsd would take for a separator.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// `lines` joined into packets of at most [`MAX_PACKET_LEN`] bytes, but for
+/// lines longer than that on their own.
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets: Vec<String> = vec![];
+    for line in lines {
+        match packets.last_mut() {
+            Some(packet) if packet.len() + 1 + line.len() <= MAX_PACKET_LEN => {
+                packet.push('\n');
+                packet.push_str(line);
+            }
+            _ => packets.push(line.clone()),
+        }
+    }
+    packets
+}
+
+/// Pushes the metrics of `registry` to every one of `exporters` every
+/// `every`.
+pub async fn push_periodically(registry: Registry, mut exporters: Vec<Exporter>, every: Duration) {
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        for exporter in &mut exporters {
+            if let Err(e) = exporter.push(&registry).await {
+                warn!("Could not push metrics to {exporter}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::IntCounterVec;
+    use prometheus::IntGauge;
+    use prometheus::Opts;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn counters_are_sent_as_increases() {
+        let registry = Registry::new();
+        let handshakes =
+            IntCounterVec::new(Opts::new("net_handshakes", "handshakes"), &["outcome"]).unwrap();
+        let peers = IntGauge::new("net_peers", "peers").unwrap();
+        registry.register(Box::new(handshakes.clone())).unwrap();
+        registry.register(Box::new(peers.clone())).unwrap();
+        handshakes.with_label_values(&["timed out"]).inc_by(3);
+        peers.set(2);
+
+        let mut statsd = Statsd::connect("127.0.0.1:8125", StatsdFormat::Statsd).await.unwrap();
+        assert_eq!(
+            statsd.lines(&registry.gather()),
+            ["net_handshakes.outcome.timed_out:3|c", "net_peers:2|g"]
+        );
+        handshakes.with_label_values(&["timed out"]).inc();
+        statsd.format = StatsdFormat::Dogstatsd;
+        assert_eq!(
+            statsd.lines(&registry.gather()),
+            ["net_handshakes:1|c|#outcome:timed_out", "net_peers:2|g"]
+        );
+        assert_eq!(statsd.lines(&registry.gather()), ["net_peers:2|g"]);
+    }
+
+    #[test]
+    fn lines_are_packed_into_packets() {
+        let line = "x".repeat(MAX_PACKET_LEN / 2);
+        let lines = vec![line.clone(), "y".to_string(), line.clone()];
+        assert_eq!(packets(&lines), [format!("{line}\ny"), line]);
+    }
+
+    #[test]
+    fn metrics_are_grouped_by_instance() {
+        let pushgateway =
+            Pushgateway::new("pushgateway:9092", SocketAddr::from(([10, 0, 0, 1], 5001))).unwrap();
+        assert_eq!(
+            pushgateway.endpoint.to_string(),
+            "http://pushgateway:9092/metrics/job/schultz/instance/10.0.0.1:5001"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! gRPC service answering the same queries as the
+//! [control socket](super::control), for orchestration systems that speak
+//! gRPC rather than JSON lines.
+//!
+//! The service is described in `proto/schultz.proto`. Besides the peers and
+//! status of the node, clients may have it connect to or disconnect from a
+//! peer, and stream the [`NetworkEvent`]s and gossip messages it sees.
+//!
+//! Requests come over plain HTTP/2, so the service is answered on a loopback
+//! address unless a token is configured. Connecting and disconnecting peers
+//! always takes the token, sent as `authorization: Bearer <token>`.
+
+// `Status` is what tonic answers with, however large.
+#![allow(clippy::result_large_err)]
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tracing::info;
+
+use super::status;
+use super::Node;
+use crate::network::history::EventKind;
+use crate::network::history::NetworkEvent;
+use crate::network::manager::PeerInfo;
+use crate::network::observe::Observed;
+
+pub mod proto {
+    tonic::include_proto!("schultz.v1");
+}
+
+use proto::control_server::Control;
+use proto::control_server::ControlServer;
+
+/// The name `value` serializes to, e.g. `outbound` for
+/// [`Direction::Outbound`](crate::network::connection::Direction::Outbound).
+fn name(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+fn parse_addr(addr: &str) -> Result<SocketAddr, Status> {
+    addr.parse()
+        .map_err(|e| Status::invalid_argument(format!("invalid address {addr:?}: {e}")))
+}
+
+impl From<PeerInfo> for proto::Peer {
+    fn from(peer: PeerInfo) -> Self {
+        proto::Peer {
+            addr: peer.addr.to_string(),
+            node_id: peer.node_id.map(|node_id| node_id.to_string()),
+            protocol_version: peer.protocol_version.map(|version| version.to_string()),
+            direction: name(&peer.direction),
+            connected_since: peer.connected_since.millis(),
+            last_seen: peer.last_seen.map(|last_seen| last_seen.millis()),
+            bytes_read: peer.bytes_read,
+            bytes_written: peer.bytes_written,
+        }
+    }
+}
+
+impl From<status::Status> for proto::StatusResponse {
+    fn from(status: status::Status) -> Self {
+        proto::StatusResponse {
+            fingerprint: status.fingerprint.to_string(),
+            addr: status.addr.to_string(),
+            uptime_secs: status.uptime_secs,
+            network_name: status.network_name,
+            chainspec_hash: status.chainspec_hash,
+            connected_peers: status.connected_peers.len().try_into().unwrap_or(u32::MAX),
+            current_era: status.current_era.map(|era| era.era_id.value()),
+            observed_tip: status.observed_tip.map(|tip| proto::Tip {
+                block_hash: tip.block_hash.to_string(),
+                height: tip.height,
+                era_id: tip.era_id.value(),
+                timestamp: tip.timestamp.millis(),
+                finalized: tip.finalized,
+            }),
+            header_forks: status.header_forks,
+            invalid_headers: status.invalid_headers,
+        }
+    }
+}
+
+impl From<&NetworkEvent> for proto::NetworkEvent {
+    fn from(event: &NetworkEvent) -> Self {
+        let mut converted = proto::NetworkEvent {
+            at: event.at.millis(),
+            peer: event.peer.to_string(),
+            ..Default::default()
+        };
+        match &event.kind {
+            EventKind::Connected { direction } => {
+                converted.event = "connected".to_string();
+                converted.direction = Some(name(direction));
+            }
+            EventKind::Closed => converted.event = "closed".to_string(),
+            EventKind::Dropped => converted.event = "dropped".to_string(),
+            EventKind::Scored { behavior, score } => {
+                converted.event = "scored".to_string();
+                converted.behavior = Some(name(behavior));
+                converted.score = Some(*score);
+            }
+        }
+        converted
+    }
+}
+
+impl From<&Observed> for proto::Gossip {
+    fn from(observed: &Observed) -> Self {
+        proto::Gossip {
+            peer: observed.peer.to_string(),
+            gossiper: name(&observed.gossiper),
+            kind: name(&observed.kind),
+            item: observed.item.clone(),
+            bytes: observed.bytes.try_into().unwrap_or(u64::MAX),
+        }
+    }
+}
+
+fn event(event: proto::event::Event) -> Result<proto::Event, Status> {
+    Ok(proto::Event { event: Some(event) })
+}
+
+/// Reads the token clients have to send to connect or disconnect peers from
+/// the file at `path`, without the whitespace around it.
+pub fn read_token(path: &Path) -> io::Result<String> {
+    let token = fs::read_to_string(path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the token is empty",
+        ));
+    }
+    Ok(token)
+}
+
+/// Checks that `metadata` carries `token` as a bearer token. Without a
+/// token, nothing is authorized.
+fn authorize(token: Option<&str>, metadata: &MetadataMap) -> Result<(), Status> {
+    let Some(token) = token else {
+        return Err(Status::permission_denied(
+            "connecting and disconnecting peers over gRPC needs node.grpc_token_file",
+        ));
+    };
+    let sent = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared in constant time, so the token cannot be guessed byte by byte.
+    let matches = sent.is_some_and(|sent| {
+        sent.len() == token.len()
+            && sent.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    });
+    if !matches {
+        return Err(Status::unauthenticated("missing or wrong bearer token"));
+    }
+    Ok(())
+}
+
+/// The [`Control`] service of a node.
+pub struct ControlService {
+    node: Node,
+    /// What clients send to connect or disconnect peers.
+    token: Option<String>,
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    type StreamEventsStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+    async fn peers(
+        &self,
+        _: Request<proto::PeersRequest>,
+    ) -> Result<Response<proto::PeersResponse>, Status> {
+        let peers = self.node.manager.read().await.peers().await;
+        Ok(Response::new(proto::PeersResponse {
+            peers: peers.into_iter().map(proto::Peer::from).collect(),
+        }))
+    }
+
+    async fn status(
+        &self,
+        _: Request<proto::StatusRequest>,
+    ) -> Result<Response<proto::StatusResponse>, Status> {
+        Ok(Response::new(status::Status::of(&self.node).await.into()))
+    }
+
+    async fn connect_peer(
+        &self,
+        request: Request<proto::ConnectRequest>,
+    ) -> Result<Response<proto::ConnectResponse>, Status> {
+        authorize(self.token.as_deref(), request.metadata())?;
+        let addr = parse_addr(&request.get_ref().addr)?;
+        self.node
+            .connect_peer(addr)
+            .await
+            .map_err(|e| Status::unavailable(format!("could not connect to {addr}: {e}")))?;
+        Ok(Response::new(proto::ConnectResponse {}))
+    }
+
+    async fn disconnect_peer(
+        &self,
+        request: Request<proto::DisconnectRequest>,
+    ) -> Result<Response<proto::DisconnectResponse>, Status> {
+        authorize(self.token.as_deref(), request.metadata())?;
+        let addr = parse_addr(&request.get_ref().addr)?;
+        if !self.node.disconnect_peer(addr).await {
+            return Err(Status::not_found(format!("not connected to {addr}")));
+        }
+        Ok(Response::new(proto::DisconnectResponse {}))
+    }
+
+    async fn stream_events(
+        &self,
+        _: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let receivers = {
+            let manager = self.node.manager.read().await;
+            (manager.network_events(), manager.observe())
+        };
+        let events = futures::stream::unfold(receivers, |(mut network, mut gossip)| async move {
+            use proto::event::Event;
+            let next = tokio::select! {
+                received = network.recv() => match received {
+                    Ok(received) => event(Event::Network((&received).into())),
+                    Err(RecvError::Lagged(missed)) => event(Event::Lagged(missed)),
+                    Err(RecvError::Closed) => return None,
+                },
+                observed = gossip.recv() => match observed {
+                    Ok(observed) => event(Event::Gossip((&observed).into())),
+                    Err(RecvError::Lagged(missed)) => event(Event::Lagged(missed)),
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            Some((next, (network, gossip)))
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Listens for gRPC clients on `addr`.
+pub fn bind(addr: SocketAddr) -> Result<TcpIncoming, Box<dyn Error + Send + Sync>> {
+    TcpIncoming::new(addr, true, None)
+}
+
+/// Answers gRPC requests about `node` coming in on `incoming` until serving
+/// fails. Clients sending `token` may connect and disconnect peers.
+pub async fn serve(
+    incoming: TcpIncoming,
+    node: Node,
+    token: Option<String>,
+) -> Result<(), tonic::transport::Error> {
+    info!("Answering gRPC requests");
+    Server::builder()
+        .add_service(ControlServer::new(ControlService { node, token }))
+        .serve_with_incoming(incoming)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::Timestamp;
+    use tokio::net::TcpListener;
+    use tonic::Code;
+
+    use super::*;
+    use crate::network::reputation::Behavior;
+    use crate::testing::TestPeer;
+
+    fn bearer(token: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", format!("Bearer {token}").parse().unwrap());
+        metadata
+    }
+
+    #[test]
+    fn events_keep_their_details() {
+        let event = NetworkEvent {
+            at: Timestamp::from(5),
+            peer: SocketAddr::from(([127, 0, 0, 1], 1)),
+            kind: EventKind::Scored {
+                behavior: Behavior::HandshakeFailed,
+                score: -20,
+            },
+        };
+        let converted = proto::NetworkEvent::from(&event);
+        assert_eq!(converted.at, 5);
+        assert_eq!(converted.peer, "127.0.0.1:1");
+        assert_eq!(converted.event, "scored");
+        assert_eq!(converted.behavior.as_deref(), Some("handshake_failed"));
+        assert_eq!(converted.score, Some(-20));
+        assert_eq!(converted.direction, None);
+    }
+
+    #[test]
+    fn only_the_token_is_authorized() {
+        assert!(authorize(Some("secret"), &bearer("secret")).is_ok());
+        let code = |token, metadata| authorize(token, &metadata).unwrap_err().code();
+        assert_eq!(
+            code(Some("secret"), bearer("secrets")),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(Some("secret"), bearer("public")),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(Some("secret"), MetadataMap::new()),
+            Code::Unauthenticated
+        );
+        let mut basic = MetadataMap::new();
+        basic.insert("authorization", "Basic secret".parse().unwrap());
+        assert_eq!(code(Some("secret"), basic), Code::Unauthenticated);
+        // Without a token, nobody may change the node's connections.
+        assert_eq!(code(None, bearer("secret")), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn tokens_are_read_without_surrounding_whitespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("grpc-token");
+        fs::write(&path, "  secret\n").unwrap();
+        assert_eq!(read_token(&path).unwrap(), "secret");
+        fs::write(&path, "\n").unwrap();
+        assert_eq!(
+            read_token(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[tokio::test]
+    async fn peers_are_connected_and_disconnected_with_the_token_only() {
+        let peer = TestPeer::spawn(1, vec![]).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let token = Some("secret".to_string());
+        tokio::spawn(serve(incoming, peer.node.clone(), token));
+
+        let mut client = proto::control_client::ControlClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+        // Queries need no token.
+        let status = client.status(proto::StatusRequest {}).await.unwrap();
+        assert_eq!(status.get_ref().connected_peers, 0);
+
+        let disconnect = |token: Option<&str>| {
+            let mut request = Request::new(proto::DisconnectRequest {
+                addr: "127.0.0.1:1".to_string(),
+            });
+            if let Some(token) = token {
+                *request.metadata_mut() = bearer(token);
+            }
+            request
+        };
+        let refused = client.disconnect_peer(disconnect(None)).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        let refused = client.disconnect_peer(disconnect(Some("public"))).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        // Past the token, there is no such peer to disconnect.
+        let answered = client.disconnect_peer(disconnect(Some("secret"))).await.unwrap_err();
+        assert_eq!(answered.code(), Code::NotFound);
+
+        let connect = proto::ConnectRequest {
+            addr: "127.0.0.1:1".to_string(),
+        };
+        let refused = client.connect_peer(connect).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        peer.shutdown().await;
+    }
+}
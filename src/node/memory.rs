@@ -0,0 +1,207 @@
+//! Estimated heap usage of a running node, by subsystem.
+//!
+//! The peer table and chainspec are estimated by their `DataSize`, the
+//! message queues by the bytes charged to peers, see
+//! [`MemoryBudget`](crate::network::memory::MemoryBudget), and the capture
+//! buffers by what the event history and wire log hold. `/status` reports
+//! the estimates, and every [`MEMORY_CHECK_INTERVAL`] they are set on the
+//! `node_memory_bytes` gauge. A node whose message queues hold more than
+//! `node.max_memory` sheds the peers holding the most in them until they are
+//! back under, the queues being the only subsystem it can give memory back
+//! from while running.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use datasize::data_size;
+use prometheus::IntCounter;
+use prometheus::IntGaugeVec;
+use prometheus::Opts;
+use prometheus::Registry;
+use serde::Serialize;
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+use super::Node;
+use crate::network::memory::ByteSize;
+
+/// Interval between two estimates of the memory used.
+pub const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Estimated bytes each subsystem holds on the heap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    /// Known peers and the addresses learned through gossip.
+    pub peer_table: usize,
+    /// Messages read from peers and frames queued for them, not yet handled.
+    pub message_queues: usize,
+    pub chainspec: usize,
+    /// The network event history and the wire log's buffer.
+    pub capture_buffers: usize,
+}
+
+impl MemoryUsage {
+    pub async fn of(node: &Node) -> Self {
+        let peer_table =
+            data_size(&*node.peers.lock().await) + data_size(&*node.address_book.lock().await);
+        let manager = node.manager.read().await;
+        MemoryUsage {
+            peer_table,
+            message_queues: manager.buffered_memory(),
+            chainspec: data_size(&manager.chainspec),
+            capture_buffers: manager.capture_memory(),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.peer_table + self.message_queues + self.chainspec + self.capture_buffers
+    }
+
+    fn by_subsystem(&self) -> [(&'static str, usize); 4] {
+        [
+            ("peer_table", self.peer_table),
+            ("message_queues", self.message_queues),
+            ("chainspec", self.chainspec),
+            ("capture_buffers", self.capture_buffers),
+        ]
+    }
+}
+
+/// Metrics of the memory a node uses.
+#[derive(Clone, Debug)]
+pub struct MemoryMetrics {
+    /// Estimated bytes on the heap, by subsystem.
+    usage: IntGaugeVec,
+    /// Number of peers disconnected for the message queues going over the
+    /// memory cap.
+    shed_peers: IntCounter,
+}
+
+impl MemoryMetrics {
+    /// Creates the memory metrics and registers them with `registry`.
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let usage = IntGaugeVec::new(
+            Opts::new(
+                "node_memory_bytes",
+                "estimated bytes on the heap, by subsystem",
+            ),
+            &["subsystem"],
+        )?;
+        let shed_peers = IntCounter::new(
+            "node_memory_shed_peers",
+            "number of peers disconnected for the node going over its memory cap",
+        )?;
+        registry.register(Box::new(usage.clone()))?;
+        registry.register(Box::new(shed_peers.clone()))?;
+        Ok(MemoryMetrics { usage, shed_peers })
+    }
+}
+
+/// Estimates the memory `node` uses every `every`, setting the estimates on
+/// `metrics` and shedding peers whenever its message queues hold more than
+/// `max`.
+pub async fn watch(node: Node, metrics: MemoryMetrics, every: Duration, max: Option<ByteSize>) {
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let usage = MemoryUsage::of(&node).await;
+        for (subsystem, bytes) in usage.by_subsystem() {
+            metrics
+                .usage
+                .with_label_values(&[subsystem])
+                .set(bytes.try_into().unwrap_or(i64::MAX));
+        }
+        if let Some(max) = max {
+            metrics.shed_peers.inc_by(shed(&node, &usage, max).await);
+        }
+    }
+}
+
+/// Disconnects the peers holding the most in the message queues, until
+/// those in `usage` would be back under `max`. Returns how many were
+/// disconnected.
+async fn shed(node: &Node, usage: &MemoryUsage, max: ByteSize) -> u64 {
+    let manager = node.manager.read().await;
+    let (peers, excess) = to_shed(manager.peers_by_memory(), usage.message_queues, max);
+    for (peer, bytes) in &peers {
+        warn!("Over the memory cap of {max}, disconnecting {peer:?} holding {bytes} bytes");
+        manager.disconnect(*peer).await;
+    }
+    if excess > 0 {
+        warn!("Still {excess} bytes over the memory cap of {max} with no peer left to shed");
+    }
+    peers.len() as u64
+}
+
+/// The first of `peers`, the most holding first, to disconnect for the
+/// `message_queues` bytes to be back under `max`, and the bytes still over
+/// once they are.
+fn to_shed(
+    peers: Vec<(SocketAddr, usize)>,
+    message_queues: usize,
+    max: ByteSize,
+) -> (Vec<(SocketAddr, usize)>, usize) {
+    let max_bytes = usize::try_from(max.bytes()).unwrap_or(usize::MAX);
+    let mut excess = message_queues.saturating_sub(max_bytes);
+    let mut shed = Vec::new();
+    for (peer, bytes) in peers {
+        if excess == 0 {
+            break;
+        }
+        excess = excess.saturating_sub(bytes);
+        shed.push((peer, bytes));
+    }
+    (shed, excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestPeer;
+
+    fn peer(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn sheds_the_peers_holding_the_most_until_under_the_cap() {
+        let peers = vec![(peer(1), 700), (peer(2), 500), (peer(3), 300)];
+        assert_eq!(
+            to_shed(peers.clone(), 1500, ByteSize::from_bytes(2000)),
+            (vec![], 0)
+        );
+        assert_eq!(
+            to_shed(peers.clone(), 1500, ByteSize::from_bytes(1000)),
+            (vec![(peer(1), 700)], 0)
+        );
+        assert_eq!(
+            to_shed(peers.clone(), 1500, ByteSize::from_bytes(500)),
+            (vec![(peer(1), 700), (peer(2), 500)], 0)
+        );
+        // Queued bytes no peer is charged for are left over.
+        assert_eq!(
+            to_shed(peers, 2000, ByteSize::from_bytes(100)),
+            (vec![(peer(1), 700), (peer(2), 500), (peer(3), 300)], 400)
+        );
+    }
+
+    #[tokio::test]
+    async fn usage_is_estimated_by_subsystem() {
+        let peer = TestPeer::spawn(1, vec![]).await.unwrap();
+        let usage = MemoryUsage::of(&peer.node).await;
+        assert_eq!(usage.message_queues, 0);
+        assert!(usage.chainspec > 0);
+        assert_eq!(
+            usage.by_subsystem().iter().map(|(_, bytes)| bytes).sum::<usize>(),
+            usage.total()
+        );
+
+        // Memory the node cannot give back is never shed for.
+        let over = MemoryUsage {
+            chainspec: 1 << 30,
+            ..usage
+        };
+        assert_eq!(shed(&peer.node, &over, ByteSize::from_bytes(1)).await, 0);
+        peer.shutdown().await;
+    }
+}
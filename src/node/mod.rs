@@ -1,32 +1,193 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 
+use casper_hashing::Digest;
+use casper_types::EraId;
+use casper_types::TimeDiff;
+use prometheus::Registry;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::Mutex;
 use tokio::sync::RwLock;
-use tracing::error;
+use tokio::task::JoinSet;
+use tokio::time::interval;
 use tracing::info;
+use tracing::info_span;
+use tracing::instrument;
+use tracing::trace;
+use tracing::warn;
+use tracing::Instrument;
 
 use crate::error::Result;
+use crate::network::deploy::Deploy;
+use crate::network::deploy::DeployMessage;
+use crate::network::deploy::Gossiped;
+use crate::network::deploy::HeldDeploys;
+use crate::network::dispatch::Dispatcher;
+use crate::network::era_tracker::EraTracker;
+use crate::network::error::DeployError;
+use crate::network::error::FetchError;
+use crate::network::error::HeaderError;
+use crate::network::error::ManagerError;
+use crate::network::fetch::FetchResponse;
+use crate::network::fetch::Fetched;
+use crate::network::fetch::PendingFetches;
+use crate::network::fetch::Request;
+use crate::network::fetch::Tag;
+use crate::network::finality::Collected;
+use crate::network::finality::FinalityCollector;
+use crate::network::finality::FinalitySignature;
+use crate::network::finality::FinalitySignatureId;
+use crate::network::gossip::AddressBook;
+use crate::network::gossip::GossipMessage;
+use crate::network::gossip::GossipedAddress;
+use crate::network::gossip::NodePayload;
+use crate::network::gossip::NodePayloadDiscriminants;
+use crate::network::gossip::GOSSIP_FANOUT;
+use crate::network::headers::BlockHeader;
+use crate::network::headers::Followed;
+use crate::network::headers::HeaderChain;
+use crate::network::headers::MAX_HEADERS;
+use crate::network::manager::Event;
 use crate::network::manager::Manager;
+use crate::network::message::BincodeFormat;
 use crate::network::message::Message;
+use crate::network::message::Route;
+use crate::network::mux::Channel;
+use crate::network::observe::GossipKind;
+use crate::network::observe::Gossiper;
+use crate::network::progress::BootstrapError;
+use crate::network::progress::NoProgress;
+use crate::network::progress::Phase;
+use crate::network::progress::Progress;
+use crate::network::progress::Step;
+use crate::network::resolve::default_resolver;
+use crate::network::resolve::Bootnode;
+use crate::network::resolve::CachingResolver;
+use crate::network::resolve::DEFAULT_NEGATIVE_TTL;
+use crate::network::tls::Identity;
+use crate::network::transport::TlsTransport;
+use crate::network::transport::Transport;
+use crate::network::Config;
+use crate::node::observations::ObservationStore;
+use crate::node::peers::PeerStore;
 use crate::primitives::Chainspec;
-use crate::primitives::Payload;
+
+#[cfg(any(unix, windows))]
+pub mod control;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod memory;
+pub mod observations;
+pub mod peers;
+pub mod signals;
+pub mod snapshots;
+pub mod status;
+#[cfg(unix)]
+pub mod systemd;
+pub mod ws;
 
 /// Channel bounds
 pub const CHANNEL_SIZE: usize = 10_000;
 
-// Placeholder for the casper_types::message::Message's generic.
-// Required by the event loop channels
-impl Payload for Vec<u8> {}
+/// Gossiped headers waiting to be synced to the header chain, see
+/// [`Node::sync_headers`].
+const MAX_UNANCHORED: usize = 64;
+
+/// Passes the progress of one bootstrap attempt on for `bootnode`, recording
+/// the phase it got to.
+///
+/// Addresses the bootnode resolved to are raced, so steps come in from
+/// several connection attempts at once. Only those going further than any
+/// before are passed on.
+struct PhaseTracker<'a> {
+    bootnode: &'a Bootnode,
+    progress: &'a dyn Progress,
+    /// The furthest step so far, as the phase and whether it completed.
+    reached: std::sync::Mutex<Option<(Phase, bool)>>,
+}
 
-type EventReceiver = Receiver<(SocketAddr, Message<Vec<u8>>)>;
+impl<'a> PhaseTracker<'a> {
+    fn new(bootnode: &'a Bootnode, progress: &'a dyn Progress) -> Self {
+        Self {
+            bootnode,
+            progress,
+            reached: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// The phase started last.
+    fn phase(&self) -> Phase {
+        let reached = *self.reached.lock().expect("phase lock poisoned");
+        reached.map_or(Phase::Resolve, |(phase, _)| phase)
+    }
+
+    fn step(&self, step: Step) {
+        let next = match step {
+            Step::Started(phase) => (phase, false),
+            Step::Completed(phase) => (phase, true),
+        };
+        {
+            let mut reached = self.reached.lock().expect("phase lock poisoned");
+            if reached.is_some_and(|reached| next <= reached) {
+                return;
+            }
+            *reached = Some(next);
+        }
+        match step {
+            Step::Started(phase) => self.progress.started(self.bootnode, phase),
+            Step::Completed(phase) => self.progress.completed(self.bootnode, phase),
+        }
+    }
+}
+
+/// Interval between two checks of our certificate's expiry.
+pub const CERT_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Interval at which the known-peers table is written out if it changed,
+/// so a burst of connections costs one write rather than one each.
+pub const PEERS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+type EventReceiver = Receiver<Event<NodePayload>>;
 
 #[derive(Clone)]
 pub struct Node {
     pub manager: Arc<RwLock<Manager>>,
     pub event_rx: Arc<RwLock<EventReceiver>>,
-    pub bootnode_addr: Option<SocketAddr>,
+    pub registry: Registry,
+    pub address_book: Arc<Mutex<AddressBook>>,
+    pub peers: Arc<Mutex<PeerStore>>,
+    fetches: Arc<PendingFetches>,
+    /// Deploys we gossiped, handed out to the peers that ask for them.
+    deploys: Arc<HeldDeploys>,
+    /// Headers of the blocks peers gossiped, if following them.
+    headers: Arc<HeaderChain>,
+    /// Validators of every era known.
+    eras: Arc<EraTracker>,
+    /// Where the headers, deploys and signatures observed are recorded, if
+    /// anywhere, see [`Node::record_observations`].
+    observations: Arc<OnceLock<ObservationStore>>,
+    config: Arc<std::sync::RwLock<Config>>,
+    resolver: Arc<CachingResolver>,
+    /// Bootnodes joined through, by name, with the address connected to.
+    bootnodes: Arc<Mutex<BTreeMap<Bootnode, SocketAddr>>>,
+    gossip_index: Arc<AtomicU32>,
+    /// Number of events the event loop got through.
+    events_handled: Arc<AtomicU64>,
+    started_at: Instant,
 }
 
 impl Node {
@@ -34,70 +195,1299 @@ impl Node {
         schultz_addr: SocketAddr,
         bootnodes_addrs: Vec<SocketAddr>,
         chainspec_path: PathBuf,
+        config: Config,
+    ) -> Result<Self> {
+        let identity = match &config.identity_dir {
+            Some(dir) => Identity::load_or_generate(dir, &config.cert_subject)?,
+            None => Identity::generate(&config.cert_subject)?,
+        };
+        Self::with_identity(
+            identity,
+            schultz_addr,
+            bootnodes_addrs,
+            chainspec_path,
+            config,
+        )
+        .await
+    }
+
+    /// Starts a node presenting the given TLS `identity` to its peers.
+    pub async fn with_identity(
+        identity: Identity,
+        schultz_addr: SocketAddr,
+        bootnodes_addrs: Vec<SocketAddr>,
+        chainspec_path: PathBuf,
+        config: Config,
+    ) -> Result<Self> {
+        let transport = Arc::new(TlsTransport::new(identity.clone(), config.tls_options()));
+        Self::with_transport(
+            transport,
+            identity,
+            schultz_addr,
+            bootnodes_addrs,
+            chainspec_path,
+            config,
+        )
+        .await
+    }
+
+    /// Starts a node connecting to its peers through `transport`.
+    #[instrument(name = "bootstrap", skip_all, fields(addr = %schultz_addr))]
+    pub async fn with_transport(
+        transport: Arc<dyn Transport>,
+        identity: Identity,
+        schultz_addr: SocketAddr,
+        bootnodes_addrs: Vec<SocketAddr>,
+        chainspec_path: PathBuf,
+        config: Config,
     ) -> Result<Self> {
         info!("Starting node at {:?}", schultz_addr);
         let (event_tx, event_rx) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
-        let chainspec = Chainspec::from_path(&chainspec_path).expect("Failed to load chainspec");
+        let chainspec = Chainspec::from_path(&chainspec_path)?;
 
-        let manager = Manager::new(schultz_addr, event_tx, chainspec).await?;
+        // Nodes of several networks may run side by side, and their metrics
+        // be gathered together.
+        let labels =
+            HashMap::from([("network".to_string(), chainspec.network_config.name.clone())]);
+        let registry = Registry::new_custom(None, Some(labels)).map_err(ManagerError::from)?;
 
-        let bootnode_addr = bootnodes_addrs.first().cloned();
-        for addr in bootnodes_addrs {
-            manager.connect(&addr).await?;
-            manager.handshake::<Vec<u8>>(addr).await?;
-        }
+        let manager = Manager::with_transport(
+            transport,
+            identity,
+            schultz_addr,
+            event_tx,
+            chainspec,
+            config.clone(),
+            &registry,
+        )
+        .await?;
 
         info!("Started node at {:?}", manager.schultz_addr());
 
-        Ok(Self {
+        let eras = manager.eras();
+        match eras.start_from(&chainspec_path, &manager.chainspec) {
+            Ok(era_id) => info!("Tracking the validators from era {era_id}"),
+            Err(e) => warn!("Tracking the validators from the first switch block seen: {e}"),
+        }
+
+        let peers = match &config.peers_file {
+            Some(path) => PeerStore::load(path)?,
+            None => PeerStore::in_memory(),
+        };
+        let mut address_book = AddressBook::new(manager.schultz_addr());
+        for peer in peers.peers() {
+            address_book.learn(peer.addr);
+        }
+        if !peers.is_empty() {
+            info!("Loaded {} known peers", peers.peers().len());
+        }
+
+        let node = Self {
+            address_book: Arc::new(Mutex::new(address_book)),
+            peers: Arc::new(Mutex::new(peers)),
+            fetches: Arc::new(PendingFetches::default()),
+            deploys: Arc::new(HeldDeploys::default()),
+            headers: Arc::new(HeaderChain::default()),
+            eras,
+            observations: Arc::new(OnceLock::new()),
             manager: Arc::new(RwLock::new(manager)),
             event_rx: Arc::new(RwLock::new(event_rx)),
-            bootnode_addr,
-        })
+            registry,
+            resolver: Arc::new(CachingResolver::new(
+                default_resolver(),
+                config.bootnode_resolve_ttl.into(),
+                DEFAULT_NEGATIVE_TTL,
+            )),
+            config: Arc::new(std::sync::RwLock::new(config)),
+            bootnodes: Arc::new(Mutex::new(BTreeMap::new())),
+            gossip_index: Arc::new(AtomicU32::new(0)),
+            events_handled: Arc::new(AtomicU64::new(0)),
+            started_at: Instant::now(),
+        };
+        let bootnodes: Vec<_> = bootnodes_addrs.into_iter().map(Bootnode::from).collect();
+        node.bootstrap(&bootnodes, &NoProgress).await?;
+        Ok(node)
+    }
+
+    /// Closes the listener and every connection, see [`Manager::shutdown`],
+    /// and saves the known peers. The event loop is up to the caller to
+    /// stop.
+    pub async fn shutdown(&self) {
+        self.manager.read().await.shutdown().await;
+        self.save_peers().await;
+    }
+
+    /// Number of events the event loop got through so far.
+    pub fn events_handled(&self) -> u64 { self.events_handled.load(Ordering::Relaxed) }
+
+    /// Whether events are waiting for the event loop. The loop holds the
+    /// receiver while it waits for the next event, so it has none waiting
+    /// then.
+    pub fn events_pending(&self) -> bool {
+        self.event_rx.try_read().is_ok_and(|event_rx| !event_rx.is_empty())
+    }
+
+    /// The chain of block headers followed, see [`Node::follow_headers`].
+    pub fn headers(&self) -> Arc<HeaderChain> { self.headers.clone() }
+
+    /// The validators of every era known, see [`EraTracker`].
+    pub fn eras(&self) -> Arc<EraTracker> { self.eras.clone() }
+
+    /// The network configuration, as last reloaded.
+    fn config(&self) -> Config { self.config.read().expect("config lock poisoned").clone() }
+
+    /// Takes over the settings of `config` that can change while the node
+    /// runs, see [`Config::reload`]. Returns the names of those that need a
+    /// restart instead.
+    pub async fn reload(&self, config: &Config) -> Vec<&'static str> {
+        let restart = self.config.write().expect("config lock poisoned").reload(config);
+        self.resolver.set_max_ttl(config.bootnode_resolve_ttl.into());
+        self.manager.write().await.reload(config);
+        restart
+    }
+
+    /// Connects to every bootnode, reporting each phase to `progress`.
+    ///
+    /// A bootnode failing in any phase is tried again from the start, up to
+    /// the configured number of attempts, waiting twice as long before every
+    /// retry. Errors that will not go away by themselves, see
+    /// [`ManagerError::is_retryable`], end the attempts right away. If the
+    /// bootnode still fails, the peers known from before a restart are dialed
+    /// instead; only without any does the bootstrap fail.
+    pub async fn bootstrap(&self, bootnodes: &[Bootnode], progress: &dyn Progress) -> Result<()> {
+        let mut fell_back = false;
+        for bootnode in bootnodes {
+            let mut delay: Duration = self.config().bootstrap_retry_delay.into();
+            let mut attempt = 1;
+            loop {
+                let tracker = PhaseTracker::new(bootnode, progress);
+                let Err(error) = self.bootstrap_from(&tracker).await else {
+                    break;
+                };
+                // The bootnode may have moved, so the next attempt looks its
+                // name up again.
+                self.resolver.forget(bootnode);
+
+                let phase = tracker.phase();
+                if !error.is_retryable() || attempt >= self.config().bootstrap_attempts {
+                    let known = self.peers.lock().await.peers().len();
+                    if known > 0 {
+                        warn!(
+                            "Bootstrapping from {bootnode} failed in the {phase} phase: {error}, \
+                             rejoining through {known} known peers"
+                        );
+                        fell_back = true;
+                        break;
+                    }
+                    return Err(BootstrapError {
+                        bootnode: bootnode.clone(),
+                        phase,
+                        source: error,
+                    }
+                    .into());
+                }
+
+                warn!(
+                    code = error.code(),
+                    "Bootstrapping from {bootnode} failed in the {phase} phase: {error}"
+                );
+                progress.retrying(bootnode, phase, &error, attempt, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+        if fell_back {
+            self.dial_new_peers().await;
+        }
+        Ok(())
+    }
+
+    /// Resolves the tracked bootnode, connects to the first of its addresses
+    /// to answer and joins the network through it.
+    async fn bootstrap_from(
+        &self,
+        tracker: &PhaseTracker<'_>,
+    ) -> std::result::Result<(), ManagerError> {
+        let bootnode = tracker.bootnode;
+        tracker.step(Step::Started(Phase::Resolve));
+        let addrs = match bootnode.resolve_with(&*self.resolver).await {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                self.manager.read().await.count_failure(&e);
+                return Err(e);
+            }
+        };
+        tracker.progress.resolved(bootnode, &addrs);
+        tracker.step(Step::Completed(Phase::Resolve));
+
+        self.join_any(&addrs, tracker).await.map(|_| ())
+    }
+
+    /// Connects to the first of `addrs` of the tracked bootnode to answer and
+    /// joins the network through it, remembering the address it was reached
+    /// on.
+    async fn join_any(
+        &self,
+        addrs: &[SocketAddr],
+        tracker: &PhaseTracker<'_>,
+    ) -> std::result::Result<SocketAddr, ManagerError> {
+        let bootnode = tracker.bootnode;
+        let addr = {
+            let manager = self.manager.read().await;
+            manager.connect_any(addrs, &|step| tracker.step(step)).await?
+        };
+        info!("Connected to bootnode {bootnode} at {addr:?}");
+        tracker.progress.connected(bootnode, addr);
+
+        if let Err(error) = self.join(addr, tracker).await {
+            self.manager.read().await.disconnect(addr).await;
+            return Err(error);
+        }
+        self.bootnodes.lock().await.insert(bootnode.clone(), addr);
+        Ok(addr)
+    }
+
+    /// Reconnects to the bootnodes joined through whose connection was lost,
+    /// or whose name now resolves to addresses other than the one connected
+    /// to, moving over to the new address.
+    ///
+    /// Names are looked up again once their previous answer expired, see
+    /// [`CachingResolver`], rather than redialing the first address ever
+    /// resolved.
+    pub async fn reconnect_bootnodes(&self) {
+        let bootnodes = self.bootnodes.lock().await.clone();
+        if bootnodes.is_empty() {
+            return;
+        }
+        let connected = self.manager.read().await.connected_peers().await;
+        for (bootnode, addr) in bootnodes {
+            let addrs = match bootnode.resolve_with(&*self.resolver).await {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    warn!(
+                        code = e.code(),
+                        "Could not resolve bootnode {bootnode} again: {e}"
+                    );
+                    self.manager.read().await.count_failure(&e);
+                    continue;
+                }
+            };
+            let moved = !addrs.contains(&addr);
+            let is_connected = connected.contains(&addr);
+            if is_connected && !moved {
+                continue;
+            }
+            if moved {
+                info!("Bootnode {bootnode} moved from {addr:?} to {addrs:?}, reconnecting");
+            } else {
+                info!("Lost the connection to bootnode {bootnode} at {addr:?}, reconnecting");
+            }
+
+            let tracker = PhaseTracker::new(&bootnode, &NoProgress);
+            match self.join_any(&addrs, &tracker).await {
+                Ok(_) if moved && is_connected => {
+                    self.manager.read().await.disconnect(addr).await;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(
+                        code = e.code(),
+                        "Reconnecting to bootnode {bootnode} failed: {e}"
+                    );
+                    self.resolver.forget(&bootnode);
+                }
+            }
+        }
+    }
+
+    /// Handshakes with the bootnode connected to at `addr` and announces
+    /// ourselves to it.
+    async fn join(
+        &self,
+        addr: SocketAddr,
+        tracker: &PhaseTracker<'_>,
+    ) -> std::result::Result<(), ManagerError> {
+        tracker.step(Step::Started(Phase::Handshake));
+        self.manager.read().await.handshake::<NodePayload>(addr).await?;
+        tracker.step(Step::Completed(Phase::Handshake));
+        self.address_book.lock().await.outgoing_connected(addr);
+        self.record_seen(addr).await;
+
+        tracker.step(Step::Started(Phase::Sync));
+        self.announce_to(addr).await?;
+        tracker.step(Step::Completed(Phase::Sync));
+        Ok(())
     }
 
+    /// Runs the event loop, along with gossiping, checking the certificate
+    /// and saving the known peers, which stop when it is dropped.
     pub async fn keepalive(&self) {
         let event_rx = self.event_rx.clone();
-        let manager = self.manager.clone();
         info!("Starting keepalive task");
+
+        let mut background = JoinSet::new();
+        let gossiper = self.clone();
+        background.spawn(async move { gossiper.gossip_periodically().await });
+
+        let checker = self.clone();
+        background.spawn(async move { checker.check_certificate_periodically().await });
+
+        let saver = self.clone();
+        background.spawn(async move { saver.save_peers_periodically().await });
+
+        let dispatcher = Self::dispatcher();
         loop {
-            self.handle_event(event_rx.clone(), manager.clone()).await;
+            self.handle_event(&dispatcher, event_rx.clone()).await;
         }
     }
 
-    async fn handle_event(
+    /// The handlers for the messages peers send us.
+    fn dispatcher() -> Dispatcher<Node, NodePayload> {
+        Dispatcher::new()
+            .on(Route::Handshake, |node: Node, addr, message| async move {
+                let Message::Handshake { public_addr, .. } = message else {
+                    return;
+                };
+                info!("Received handshake from {}", addr);
+                if node.turn_away_blocked(addr).await {
+                    return;
+                }
+                // NOTE: We cannot reply to the incoming peer address because it is always
+                // different to the listening address of the same peer, so the public address
+                // goes into the address book and we answer on the incoming connection.
+                node.address_book.lock().await.incoming_connected(public_addr);
+                node.announce(addr).await;
+                node.share_known_peers(addr, public_addr).await;
+            })
+            .on(Route::Ping, |node: Node, _, message| async move {
+                let network_name = node.manager.read().await.chainspec.network_config.name.clone();
+                info!("Received a {message:?} from {network_name:?}, answered with a pong");
+            })
+            .on(Route::Pong, |_, addr, message| async move {
+                info!("Received a {message:?} from {addr:?}");
+            })
+            .on(
+                Route::Payload(NodePayloadDiscriminants::GetRequest),
+                |node: Node, addr, message| async move {
+                    if let Message::Payload(NodePayload::GetRequest { tag, serialized_id }) =
+                        message
+                    {
+                        node.answer_request(addr, tag, serialized_id).await;
+                    }
+                },
+            )
+            .on(
+                Route::Payload(NodePayloadDiscriminants::GetResponse),
+                |node: Node, addr, message| async move {
+                    if let Message::Payload(NodePayload::GetResponse {
+                        tag,
+                        serialized_item,
+                    }) = message
+                    {
+                        node.handle_response(addr, tag, &serialized_item);
+                    }
+                },
+            )
+            .on(
+                Route::Payload(NodePayloadDiscriminants::AddressGossiper),
+                |node: Node, addr, message| async move {
+                    if let Message::Payload(NodePayload::AddressGossiper(gossip)) = message {
+                        node.handle_address_gossip(addr, gossip).await;
+                    }
+                },
+            )
+    }
+
+    /// Announces our address to every connected peer, reconnects lost
+    /// bootnodes and tops up outgoing connections, once per gossip interval.
+    async fn gossip_periodically(&self) {
+        let mut interval = interval(self.config().gossip_interval.into());
+        loop {
+            interval.tick().await;
+            self.reconnect_bootnodes().await;
+
+            let peers = self.manager.read().await.connected_peers().await;
+            for addr in peers {
+                self.announce(addr).await;
+            }
+
+            self.dial_new_peers().await;
+        }
+    }
+
+    async fn save_peers_periodically(&self) {
+        let mut interval = interval(PEERS_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.save_peers().await;
+        }
+    }
+
+    /// Writes the known-peers table out if it changed, off the runtime's
+    /// threads and without holding the table. Failures are only logged, as
+    /// losing an update only costs us a peer to try after a restart.
+    async fn save_peers(&self) {
+        let Some(pending) = self.peers.lock().await.changes() else {
+            return;
+        };
+        match tokio::task::spawn_blocking(move || pending.write()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("{e:?}"),
+            Err(e) => warn!("Saving the peer list did not finish: {e}"),
+        }
+    }
+
+    /// Warns about our certificate expiring within the configured threshold,
+    /// renewing it if configured to, once per check interval.
+    async fn check_certificate_periodically(&self) {
+        let mut interval = interval(CERT_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check_certificate().await;
+        }
+    }
+
+    async fn check_certificate(&self) {
+        let identity = self.manager.read().await.identity().clone();
+        let valid_for = match identity.valid_for() {
+            Ok(valid_for) => valid_for,
+            Err(e) => {
+                warn!("Could not check the expiry of our TLS certificate: {e}");
+                return;
+            }
+        };
+        if valid_for > self.config().cert_expiry_warning.into() {
+            return;
+        }
+
+        let valid_for = TimeDiff::from_seconds(valid_for.as_secs().try_into().unwrap_or(u32::MAX));
+        warn!("Our TLS certificate expires in {valid_for}");
+        if self.config().rotate_certs {
+            if let Err(e) = self.rotate_certificate().await {
+                warn!("Could not renew our TLS certificate: {e}");
+            }
+        }
+    }
+
+    /// Switches to a renewed certificate, saving it to the identity directory
+    /// if there is one.
+    ///
+    /// New connections present the renewed certificate right away. Those
+    /// already established keep the previous one, and are closed one by one
+    /// over the configured drain window so their peers reconnect and see the
+    /// new one, rather than all of them dropping at once.
+    pub async fn rotate_certificate(&self) -> Result<()> {
+        let identity = self.manager.read().await.identity().clone();
+        let renewed = identity.renewed().map_err(ManagerError::from)?;
+        if let Some(dir) = &self.config().identity_dir {
+            renewed.save(dir).map_err(ManagerError::from)?;
+        }
+        info!(
+            "Renewed our TLS certificate, moving peers over to it within {}",
+            self.config().cert_drain_window
+        );
+
+        let mut manager = self.manager.write().await;
+        manager.set_identity(renewed);
+        let node = self.clone();
+        manager.set_drain(tokio::spawn(
+            async move { node.drain_stale_connections().await },
+        ));
+        Ok(())
+    }
+
+    /// Closes the connections set up presenting a previous certificate,
+    /// spread evenly over the drain window, and dials new peers in place of
+    /// the outgoing ones. Incoming peers dial us again themselves.
+    async fn drain_stale_connections(&self) {
+        let stale = self.manager.read().await.stale_connections().await;
+        if stale.is_empty() {
+            return;
+        }
+        let window: Duration = self.config().cert_drain_window.into();
+        let pause = window / u32::try_from(stale.len()).unwrap_or(u32::MAX);
+        for addr in stale {
+            tokio::time::sleep(pause).await;
+            {
+                let manager = self.manager.read().await;
+                // The peer may have reconnected by itself in the meantime.
+                if manager.is_stale(addr).await {
+                    info!("Reconnecting {addr:?} to present our renewed certificate");
+                    manager.disconnect(addr).await;
+                }
+            }
+            self.dial_new_peers().await;
+        }
+    }
+
+    /// Gossips our own address to a peer.
+    async fn announce(&self, addr: SocketAddr) {
+        if let Err(e) = self.announce_to(addr).await {
+            warn!("Error {e:?} sending address gossip to {addr:?}");
+        }
+    }
+
+    async fn announce_to(&self, addr: SocketAddr) -> std::result::Result<(), ManagerError> {
+        let manager = self.manager.read().await;
+        let index = self.gossip_index.fetch_add(1, Ordering::Relaxed);
+        let item = GossipedAddress::new(manager.schultz_addr(), index);
+        let payload = NodePayload::AddressGossiper(GossipMessage::Gossip(item));
+        manager.send_payload(addr, payload).await
+    }
+
+    /// Tells a peer that just connected to us, listening on `public_addr`,
+    /// about every other peer we know, so it need not wait for them to be
+    /// gossiped.
+    async fn share_known_peers(&self, addr: SocketAddr, public_addr: SocketAddr) {
+        let known: Vec<_> = self
+            .address_book
+            .lock()
+            .await
+            .known()
+            .filter(|known| **known != public_addr)
+            .copied()
+            .collect();
+        for known in known {
+            let index = self.gossip_index.fetch_add(1, Ordering::Relaxed);
+            let item = GossipedAddress::new(known, index);
+            self.send_gossip(addr, GossipMessage::Item(Box::new(item))).await;
+        }
+    }
+
+    async fn send_gossip(&self, addr: SocketAddr, message: GossipMessage) {
+        let payload = NodePayload::AddressGossiper(message);
+        if let Err(e) = self.manager.read().await.send_payload(addr, payload).await {
+            warn!("Error {e:?} sending address gossip to {addr:?}");
+        }
+    }
+
+    /// Dials known addresses until the target outgoing-connection count is
+    /// reached.
+    async fn dial_new_peers(&self) {
+        let manager = self.manager.read().await;
+        let connected = manager.connected_peers().await;
+        let to_dial = {
+            let mut address_book = self.address_book.lock().await;
+            address_book.retain_outgoing(&connected);
+            address_book.next_to_dial(self.config().target_outgoing_connections, |addrs| {
+                manager.reputation().rank(addrs)
+            })
+        };
+        drop(manager);
+
+        for addr in to_dial {
+            let node = self.clone();
+            tokio::spawn(async move { node.dial(addr).await });
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn dial(&self, addr: SocketAddr) {
+        info!("Dialing gossiped peer {addr:?}");
+        let result = {
+            let manager = self.manager.read().await;
+            match manager.connect(&addr).await {
+                Ok(()) => manager.handshake::<NodePayload>(addr).await.map(|_| ()),
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(()) if self.turn_away_blocked(addr).await => {
+                self.address_book.lock().await.block(addr, None);
+            }
+            Ok(()) => {
+                self.address_book.lock().await.outgoing_connected(addr);
+                self.record_seen(addr).await;
+                self.announce(addr).await;
+            }
+            Err(e) if e.is_peer_fault() => {
+                warn!(code = e.code(), "Blocking gossiped peer {addr:?}: {e}");
+                let node_id = self.manager.read().await.peer_id(&addr);
+                self.address_book.lock().await.block(addr, node_id);
+                self.peers.lock().await.forget(&addr);
+                self.manager.read().await.disconnect(addr).await;
+            }
+            Err(e) => {
+                warn!(
+                    code = e.code(),
+                    "Could not connect to gossiped peer {addr:?}: {e}"
+                );
+                self.address_book.lock().await.forget(&addr);
+                self.peers.lock().await.failed(addr);
+                self.manager.read().await.disconnect(addr).await;
+            }
+        }
+    }
+
+    /// Connects and handshakes with the peer at `addr` on request, and
+    /// announces our address to it as to any other peer.
+    pub async fn connect_peer(&self, addr: SocketAddr) -> std::result::Result<(), ManagerError> {
+        {
+            let manager = self.manager.read().await;
+            manager.connect(&addr).await?;
+            if let Err(e) = manager.handshake::<NodePayload>(addr).await {
+                manager.disconnect(addr).await;
+                return Err(e);
+            }
+        }
+        self.record_seen(addr).await;
+        self.announce(addr).await;
+        Ok(())
+    }
+
+    /// Closes the connection to the peer at `addr` on request. Returns
+    /// whether there was one.
+    pub async fn disconnect_peer(&self, addr: SocketAddr) -> bool {
+        let manager = self.manager.read().await;
+        let connected = manager.connected_peers().await.contains(&addr);
+        manager.disconnect(addr).await;
+        connected
+    }
+
+    /// Disconnects the peer at `addr` if it is a node blocked at another
+    /// address. Returns whether it was turned away.
+    async fn turn_away_blocked(&self, addr: SocketAddr) -> bool {
+        let manager = self.manager.read().await;
+        let Some(node_id) = manager.peer_id(&addr) else {
+            return false;
+        };
+        if !self.address_book.lock().await.is_blocked(&node_id) {
+            return false;
+        }
+        warn!("Turning away blocked node {node_id} at {addr:?}");
+        manager.disconnect(addr).await;
+        true
+    }
+
+    /// Records the peer at `addr` in the known-peers table after connecting
+    /// to it.
+    async fn record_seen(&self, addr: SocketAddr) {
+        let node_id = self.manager.read().await.peer_id(&addr);
+        self.peers.lock().await.seen(addr, node_id);
+    }
+
+    /// Records a gossiped address, relaying it to a few peers and dialing it
+    /// if it was not known yet. Returns `true` if the address was new.
+    async fn learn(&self, from: SocketAddr, item: GossipedAddress) -> bool {
+        if !self.address_book.lock().await.learn(item.address()) {
+            return false;
+        }
+        self.peers.lock().await.learn(item.address());
+
+        info!("Learned about peer {} from {from:?}", item.address());
+        let peers = {
+            let manager = self.manager.read().await;
+            manager.reputation().rank(manager.connected_peers().await)
+        };
+        // Relay to the peers we trust most.
+        for peer in peers.into_iter().filter(|peer| *peer != from).take(GOSSIP_FANOUT) {
+            self.send_gossip(peer, GossipMessage::Gossip(item)).await;
+        }
+
+        self.dial_new_peers().await;
+        true
+    }
+
+    async fn handle_address_gossip(&self, addr: SocketAddr, message: GossipMessage) {
+        match message {
+            GossipMessage::Gossip(item) => {
+                let is_new = self.learn(addr, item).await;
+                let response = GossipMessage::GossipResponse {
+                    item_id: item,
+                    is_already_held: !is_new,
+                };
+                self.send_gossip(addr, response).await;
+            }
+            GossipMessage::Item(item) => {
+                self.learn(addr, *item).await;
+            }
+            GossipMessage::GetItem(item) => {
+                self.send_gossip(addr, GossipMessage::Item(Box::new(item))).await;
+            }
+            GossipMessage::GossipResponse { .. } => {
+                trace!("Received a gossip response from {addr:?}");
+            }
+        }
+    }
+
+    /// Fetches the item of `request` from the connected peers, asking the
+    /// most reputable first and giving each `timeout` to hand it out.
+    #[instrument(skip(self), fields(hash = %request.hash()))]
+    pub async fn fetch(
         &self,
-        event_rx: Arc<RwLock<EventReceiver>>,
-        manager: Arc<RwLock<Manager>>,
+        request: Request,
+        timeout: Duration,
+    ) -> std::result::Result<Fetched, FetchError> {
+        let fetched = self.fetch_untracked(request, timeout).await?;
+        self.track_fetched(request, &fetched.item);
+        Ok(fetched)
+    }
+
+    /// Like [`Node::fetch`], without following the header fetched.
+    async fn fetch_untracked(
+        &self,
+        request: Request,
+        timeout: Duration,
+    ) -> std::result::Result<Fetched, FetchError> {
+        let (tag, hash) = (request.tag(), request.hash());
+        let serialized_id =
+            request.serialized_id().map_err(|e| FetchError::CouldNotEncodeId(hash, e))?;
+        let peers = {
+            let manager = self.manager.read().await;
+            manager.reputation().rank(manager.connected_peers().await)
+        };
+        if peers.is_empty() {
+            return Err(FetchError::NoPeers(tag, hash));
+        }
+
+        for &peer in &peers {
+            if let Some(item) = self.request_from(peer, tag, &serialized_id, timeout).await {
+                info!("Fetched {tag} {hash} from {peer:?}");
+                return Ok(Fetched { peer, item });
+            }
+        }
+        Err(FetchError::NotFound {
+            tag,
+            hash,
+            tried: peers.len(),
+        })
+    }
+
+    /// Follows the header of a fetched block, so the validators it announces
+    /// are taken once it is settled.
+    fn track_fetched(&self, request: Request, item: &[u8]) {
+        let header = match request {
+            Request::BlockHeader(_) => BlockHeader::decode(item),
+            Request::Block(_) => BlockHeader::decode_from_block(item),
+            Request::Deploy(_) => None,
+        };
+        let Some(header) = header else {
+            return;
+        };
+        let hash = request.hash();
+        match self.headers.follow(hash, &header) {
+            Ok(_) => self.track_eras(),
+            Err(e) => warn!("Rejected the fetched header of block {hash}: {e}"),
+        }
+    }
+
+    /// Marks block `hash` final, a quorum of the validators of `era_id`
+    /// having signed it, and takes the validators it settles.
+    pub fn finalize(&self, hash: Digest, era_id: EraId) -> std::result::Result<(), HeaderError> {
+        let finalized = self.headers.finalize(hash, era_id);
+        self.track_eras();
+        finalized
+    }
+
+    /// Takes the validators settled switch blocks announced.
+    fn track_eras(&self) {
+        for announcement in self.headers.take_announcements() {
+            self.eras.announce(&announcement);
+            info!(
+                "Block {} announced the validators of era {}",
+                announcement.block_hash, announcement.era_id
+            );
+        }
+    }
+
+    /// Asks `peer` for the `tag` item with `serialized_id` and gives it
+    /// `timeout` to hand it out.
+    async fn request_from(
+        &self,
+        peer: SocketAddr,
+        tag: Tag,
+        serialized_id: &[u8],
+        timeout: Duration,
+    ) -> Option<Vec<u8>> {
+        let reply_rx = self.fetches.expect(peer, tag, serialized_id.to_vec());
+        let payload = NodePayload::GetRequest {
+            tag,
+            serialized_id: serialized_id.to_vec(),
+        };
+        if let Err(e) = self.manager.read().await.send_payload(peer, payload).await {
+            warn!("Could not ask {peer:?} for a {tag}: {e}");
+            self.fetches.cancel(peer, tag, serialized_id);
+            return None;
+        }
+
+        match tokio::time::timeout(timeout, reply_rx).await {
+            Ok(Ok(FetchResponse::Fetched(item))) => Some(item),
+            Ok(Ok(_)) => {
+                info!("{peer:?} does not provide the {tag}");
+                None
+            }
+            Ok(Err(_)) => {
+                warn!("Gave up on the answer of {peer:?} for a {tag}");
+                None
+            }
+            Err(_) => {
+                warn!("Timed out waiting for {peer:?} to provide a {tag}");
+                self.fetches.cancel(peer, tag, serialized_id);
+                None
+            }
+        }
+    }
+
+    /// Counts the finality signatures peers gossip into `collector`, for as
+    /// long as the node runs.
+    ///
+    /// Signatures gossiped by their id are fetched from the peer that
+    /// gossiped them, which gets `timeout` to hand them out, unless they were
+    /// counted already.
+    pub async fn watch_finality(&self, collector: Arc<FinalityCollector>, timeout: Duration) {
+        let mut observed = self.manager.read().await.observe();
+        let requested = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        loop {
+            let observed = match observed.recv().await {
+                Ok(observed) => observed,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Missed {missed} gossip messages watching finality");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            if observed.gossiper != Gossiper::FinalitySignature {
+                continue;
+            }
+            match observed.kind {
+                GossipKind::Item => match FinalitySignature::decode(&observed.body) {
+                    Some(signature) => self.collect(&collector, &signature),
+                    None => info!("Undecodable finality signature from {}", observed.peer),
+                },
+                GossipKind::Gossip => {
+                    let Some(id) = FinalitySignatureId::decode(&observed.body) else {
+                        info!(
+                            "Could not decode a finality signature id from {}",
+                            observed.peer
+                        );
+                        continue;
+                    };
+                    if collector.knows(&id)
+                        || !requested.lock().expect("requested lock poisoned").insert(id.clone())
+                    {
+                        continue;
+                    }
+                    let (node, collector, requested) =
+                        (self.clone(), collector.clone(), requested.clone());
+                    tokio::spawn(async move {
+                        let signature = match id.serialized() {
+                            Ok(serialized_id) => {
+                                let tag = Tag::FinalitySignature;
+                                node.request_from(observed.peer, tag, &serialized_id, timeout).await
+                            }
+                            Err(e) => {
+                                warn!("Could not serialize a finality signature id: {e}");
+                                None
+                            }
+                        };
+                        match signature.as_deref().and_then(FinalitySignature::decode) {
+                            Some(signature) => node.collect(&collector, &signature),
+                            None => info!("No finality signature from {}", observed.peer),
+                        }
+                        requested.lock().expect("requested lock poisoned").remove(&id);
+                    });
+                }
+                GossipKind::GossipResponse | GossipKind::GetItem => {}
+            }
+        }
+    }
+
+    /// Follows the chain of the blocks peers gossip into
+    /// [`Node::headers`], for as long as the node runs, and counts the
+    /// finality signatures of the blocks into `collector`.
+    ///
+    /// The header of every block gossiped is fetched from the peer that
+    /// gossiped it, which gets `timeout` to hand it out, unless it was
+    /// followed already. With the chain anchored to a trusted block, that
+    /// block's header is fetched first, and the headers that do not descend
+    /// from a followed one yet are synced to the chain by one walk shared by
+    /// all of them, see [`Node::sync_headers`].
+    pub async fn follow_headers(&self, collector: Arc<FinalityCollector>, timeout: Duration) {
+        if let Some(trusted) = self.headers.trusted_hash() {
+            match self.fetch_untracked(Request::BlockHeader(trusted), timeout).await {
+                Ok(Fetched { item, .. }) => match BlockHeader::decode(&item) {
+                    Some(header) => {
+                        self.follow_header(trusted, &header);
+                    }
+                    None => warn!("Could not decode the header of trusted block {trusted}"),
+                },
+                // The sync will come by it.
+                Err(e) => warn!("Could not fetch the header of the trusted block: {e}"),
+            }
+        }
+        let (unanchored_tx, unanchored) = mpsc::channel(MAX_UNANCHORED);
+        let mut observed = self.manager.read().await.observe();
+        let requested = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let follow = async {
+            loop {
+                let observed = match observed.recv().await {
+                    Ok(observed) => observed,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {missed} gossip messages following headers");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if observed.gossiper != Gossiper::Block || observed.kind != GossipKind::Gossip {
+                    continue;
+                }
+                let hash = BincodeFormat::default().deserialize_arbitrary(&observed.body);
+                let Ok(hash) = hash else {
+                    info!("Could not decode a block hash from {}", observed.peer);
+                    continue;
+                };
+                if self.headers.knows(&hash)
+                    || !requested.lock().expect("requested lock poisoned").insert(hash)
+                {
+                    continue;
+                }
+                let (node, requested) = (self.clone(), requested.clone());
+                let unanchored_tx = unanchored_tx.clone();
+                tokio::spawn(async move {
+                    let (peer, tag) = (observed.peer, Tag::BlockHeader);
+                    let header = node.request_from(peer, tag, &observed.body, timeout).await;
+                    match header.as_deref().and_then(BlockHeader::decode) {
+                        Some(header) => {
+                            if node.follow_header(hash, &header) == Some(Followed::Unanchored) {
+                                // A full queue holds higher headers already.
+                                let _ = unanchored_tx.try_send((hash, header));
+                            }
+                        }
+                        None => info!("No block header {hash} from {peer}"),
+                    }
+                    requested.lock().expect("requested lock poisoned").remove(&hash);
+                });
+            }
+        };
+        tokio::join!(
+            follow,
+            self.sync_headers(unanchored, timeout),
+            self.watch_finality(collector, timeout)
+        );
+    }
+
+    /// Syncs the chain to the `unanchored` headers, whose parents were not
+    /// followed, one walk at a time.
+    ///
+    /// A walk goes from the highest header waiting down to the chain, or to
+    /// the trusted block, fetching each parent from whichever peer hands it
+    /// out. Past the first [`MAX_HEADERS`] headers, only their hashes are
+    /// kept.
+    /// It then follows the blocks forward from the chain, height after
+    /// height, fetching the headers it did not keep again. The headers
+    /// gossiped meanwhile mostly descend from the block walked to, so one
+    /// walk brings them all to the chain.
+    async fn sync_headers(
+        &self,
+        mut unanchored: mpsc::Receiver<(Digest, BlockHeader)>,
+        timeout: Duration,
     ) {
-        let network_name = manager.read().await.chainspec.network_config.name.clone();
-        let (addr, message) = match event_rx.write().await.recv().await {
-            Some(event) => event,
-            None => return,
+        while let Some(mut target) = unanchored.recv().await {
+            while let Ok(next) = unanchored.try_recv() {
+                if next.1.height > target.1.height {
+                    target = next;
+                }
+            }
+            let (hash, header) = target;
+            if !self.headers.knows(&hash) {
+                self.sync_to(hash, header, timeout).await;
+            }
+        }
+    }
+
+    /// Walks from block `hash` down to the chain and follows the blocks
+    /// walked forward from there, see [`Node::sync_headers`].
+    async fn sync_to(&self, hash: Digest, header: BlockHeader, timeout: Duration) {
+        let trusted = self.headers.trusted_hash();
+        // Highest first.
+        let mut walked = vec![hash];
+        let mut kept = HashMap::from([(hash, header)]);
+        let (mut lowest, mut height) = (hash, kept[&hash].height);
+        let mut parent_hash = kept[&hash].parent_hash;
+        while Some(lowest) != trusted && !self.headers.knows(&parent_hash) {
+            if height == 0 || self.headers.trusted_height().is_some_and(|trusted| height <= trusted)
+            {
+                warn!("Block {hash} does not descend from the trusted block, not syncing to it");
+                return;
+            }
+            let Some(parent) = self.fetch_header(parent_hash, timeout).await else {
+                return;
+            };
+            // A peer handing out another header would lead the walk astray.
+            if parent.hash().ok() != Some(parent_hash) {
+                warn!("The header fetched for block {parent_hash} is another block's");
+                return;
+            }
+            if parent.height + 1 != height {
+                warn!(
+                    "Block {lowest} at height {height} names {parent_hash} at height {} as its \
+                     parent",
+                    parent.height
+                );
+                return;
+            }
+            walked.push(parent_hash);
+            (lowest, height) = (parent_hash, parent.height);
+            parent_hash = parent.parent_hash;
+            if kept.len() < MAX_HEADERS as usize {
+                kept.insert(lowest, parent);
+            }
+        }
+        if walked.len() > 1 {
+            info!("Syncing {} headers up to block {hash}", walked.len());
+        }
+        for hash in walked.into_iter().rev() {
+            let header = match kept.remove(&hash) {
+                Some(header) => header,
+                None => match self.fetch_header(hash, timeout).await {
+                    Some(header) => header,
+                    None => return,
+                },
+            };
+            match self.follow_header(hash, &header) {
+                Some(Followed::Unanchored) | None => return,
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Fetches and decodes the header of block `hash`.
+    async fn fetch_header(&self, hash: Digest, timeout: Duration) -> Option<BlockHeader> {
+        match self.fetch_untracked(Request::BlockHeader(hash), timeout).await {
+            Ok(Fetched { peer, item }) => {
+                let header = BlockHeader::decode(&item);
+                if header.is_none() {
+                    info!("Could not decode the header of block {hash} from {peer}");
+                }
+                header
+            }
+            Err(e) => {
+                info!("Could not fetch the header of block {hash}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Follows the header of block `hash` and records it. Returns what
+    /// following it came to, `None` if it was rejected.
+    fn follow_header(&self, hash: Digest, header: &BlockHeader) -> Option<Followed> {
+        let height = header.height;
+        let followed = match self.headers.follow(hash, header) {
+            Ok(followed) => followed,
+            Err(e) => {
+                warn!("Rejected the header of block {hash}: {e}");
+                return None;
+            }
         };
+        match &followed {
+            Followed::Unanchored => return Some(followed),
+            Followed::Fork { other } => {
+                warn!("Fork at height {height}: block {hash} next to {other}")
+            }
+            Followed::Trusted => info!("Followed trusted block {hash} at height {height}"),
+            _ => trace!("Followed block {hash} at height {height}"),
+        }
+        self.track_eras();
+        let header = header.clone();
+        self.record(move |store| {
+            if let Err(e) = store.record_header(hash, &header) {
+                warn!("Could not record the header of block {hash}: {e}");
+            }
+        });
+        Some(followed)
+    }
 
-        match message {
-            Message::Handshake { .. } => {
-                info!("Received handshake from {}", addr);
-                // Test Handshake
-                // NOTE: We cannot reply to the incoming peer address because it is always
-                // different to the listening address of the same peer
-                // Therefore we'll test a random peer for Ping/Pong
-                if let Some(test_addr) = self.bootnode_addr {
-                    if let Err(e) = manager.write().await.send_ping::<Vec<u8>>(test_addr).await {
-                        error!("Error {e:?} sending ping to {addr:?}");
+    fn collect(&self, collector: &FinalityCollector, signature: &FinalitySignature) {
+        match collector.add(signature) {
+            Ok(Collected::Signed(_) | Collected::Quorum(_)) => {
+                let signature = signature.clone();
+                self.record(move |store| {
+                    if let Err(e) = store.record_signature(&signature) {
+                        warn!("Could not record a finality signature: {e}");
                     }
+                });
+            }
+            Ok(Collected::Duplicate) => {}
+            Err(e) => {
+                warn!(
+                    "Rejected a finality signature for block {}: {e}",
+                    signature.block_hash
+                )
+            }
+        }
+    }
+
+    /// Records the deploys peers gossip in `store`, for as long as the node
+    /// runs, along with the headers followed and the finality signatures
+    /// counted from now on.
+    pub async fn record_observations(&self, store: ObservationStore) {
+        if self.observations.set(store.clone()).is_err() {
+            warn!("Already recording observations");
+            return;
+        }
+        let mut observed = self.manager.read().await.observe();
+        loop {
+            let observed = match observed.recv().await {
+                Ok(observed) => observed,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Missed {missed} gossip messages recording observations");
+                    continue;
                 }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            if observed.gossiper != Gossiper::Deploy || observed.kind != GossipKind::Gossip {
+                continue;
             }
-            Message::Ping { .. } => {
-                info!("Received a {message:?} from {network_name:?}. Not going to send a Pong!");
+            let (store, item) = (store.clone(), observed.item.clone());
+            let record = move || store.record_deploy(&observed.item, observed.peer);
+            match tokio::task::spawn_blocking(record).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Could not record deploy {item}: {e}"),
+                Err(e) => warn!("Recording deploy {item} did not finish: {e}"),
             }
-            Message::Pong { .. } => {
-                info!("Received a {message:?} from {addr:?}");
+        }
+    }
+
+    /// Runs `record` on the observations store, if recording, off the async
+    /// threads as sled blocks on its I/O.
+    fn record(&self, record: impl FnOnce(&ObservationStore) + Send + 'static) {
+        if let Some(store) = self.observations.get().cloned() {
+            tokio::task::spawn_blocking(move || record(&store));
+        }
+    }
+
+    /// Answers a request for an item. Only deploys we gossiped are handed
+    /// out, other items are refused so the peer can ask someone else right
+    /// away.
+    async fn answer_request(&self, addr: SocketAddr, tag: Tag, serialized_id: Vec<u8>) {
+        let response = match self.deploys.get(addr, tag, &serialized_id) {
+            Some(item) => {
+                info!("Handing out a {tag} to {addr:?}");
+                FetchResponse::Fetched(item)
+            }
+            None => {
+                trace!("Refusing a request for a {tag} from {addr:?}");
+                FetchResponse::NotProvided(serialized_id)
+            }
+        };
+        let payload = NodePayload::GetResponse {
+            tag,
+            serialized_item: response.encode(),
+        };
+        if let Err(e) = self.manager.read().await.send_payload(addr, payload).await {
+            warn!("Error {e:?} answering a request from {addr:?}");
+        }
+    }
+
+    /// Gossips `deploy` to every connected peer and, for `wait`, hands it
+    /// out to those that answer the gossip asking for it or fetch it.
+    #[instrument(skip_all, fields(hash = %deploy.hash()))]
+    pub async fn gossip_deploy(
+        &self,
+        deploy: Deploy,
+        wait: Duration,
+    ) -> std::result::Result<Gossiped, DeployError> {
+        let hash = deploy.hash();
+        let gossip = DeployMessage::Gossip(deploy.id()?);
+        // Deploy gossip is not decoded, its answers are only observed.
+        let mut observed = self.manager.read().await.observe();
+        self.deploys.hold(deploy);
+
+        let frame = gossip.frame().map_err(|e| DeployError::CouldNotSerialize(hash, e))?;
+        let peers = self.manager.read().await.peers().await;
+        let mut gossiped_to = BTreeSet::new();
+        for peer in peers {
+            // Peers whose handshake is not done yet do not take gossip.
+            if peer.protocol_version.is_none() {
+                continue;
+            }
+            let manager = self.manager.read().await;
+            match manager.send_encoded(peer.addr, Channel::Gossip, frame.clone()).await {
+                Ok(()) => {
+                    gossiped_to.insert(peer.addr);
+                }
+                Err(e) => warn!("Could not gossip deploy {hash} to {:?}: {e}", peer.addr),
+            }
+        }
+        if gossiped_to.is_empty() {
+            return Err(DeployError::NoPeers(hash));
+        }
+        info!("Gossiped deploy {hash} to {} peers", gossiped_to.len());
+
+        let item = hash.to_string();
+        let deadline = tokio::time::sleep(wait);
+        tokio::pin!(deadline);
+        loop {
+            let observed = tokio::select! {
+                _ = &mut deadline => break,
+                observed = observed.recv() => observed,
+            };
+            let observed = match observed {
+                Ok(observed) => observed,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let asks = matches!(
+                observed.kind,
+                GossipKind::GossipResponse | GossipKind::GetItem
+            );
+            if observed.gossiper != Gossiper::Deploy || !asks || observed.item != item {
+                continue;
+            }
+            // Peers that had the deploy already are not handed it.
+            if observed.kind == GossipKind::GossipResponse
+                && DeployMessage::already_held(&observed.body)
+            {
+                continue;
+            }
+            if !gossiped_to.contains(&observed.peer) {
+                continue;
             }
-            _ => {
-                info!("Received unknown message from {}", addr);
+            let Some(deploy) = self.deploys.hand_out(hash, observed.peer) else {
+                continue;
+            };
+            let frame = DeployMessage::Item(Box::new(deploy))
+                .frame()
+                .map_err(|e| DeployError::CouldNotSerialize(hash, e))?;
+            let manager = self.manager.read().await;
+            if let Err(e) = manager.send_encoded(observed.peer, Channel::Gossip, frame).await {
+                warn!("Could not hand deploy {hash} to {:?}: {e}", observed.peer);
             }
         }
+        Ok(Gossiped {
+            hash,
+            gossiped_to: gossiped_to.into_iter().collect(),
+            handed_to: self.deploys.handed_to(hash),
+        })
+    }
+
+    /// Passes an answer to one of our requests on to whoever is waiting for it.
+    fn handle_response(&self, addr: SocketAddr, tag: Tag, serialized_item: &[u8]) {
+        let Some(response) = FetchResponse::decode(serialized_item) else {
+            warn!("Received a malformed {tag} response from {addr:?}");
+            return;
+        };
+        if !self.fetches.resolve(addr, tag, response) {
+            info!("Received an unrequested {tag} response from {addr:?}");
+        }
+    }
+
+    async fn handle_event(
+        &self,
+        dispatcher: &Dispatcher<Node, NodePayload>,
+        event_rx: Arc<RwLock<EventReceiver>>,
+    ) {
+        // The message counts against the peer's memory budget until we are
+        // done with it.
+        let (addr, connection, message, _reservation) = match event_rx.write().await.recv().await {
+            Some(event) => event,
+            None => return,
+        };
+
+        let span = info_span!("event", connection = %connection, peer = %addr);
+        dispatcher.dispatch(self.clone(), addr, message).instrument(span).await;
+        self.events_handled.fetch_add(1, Ordering::Relaxed);
     }
 }
@@ -0,0 +1,463 @@
+//! The headers, deploys and finality signatures observed, kept on disk for
+//! analysis after the node stopped.
+//!
+//! With `node.store_observations` set, `tap`, `finality watch` and nodes
+//! following the header chain record every header they follow, every deploy
+//! hash peers gossip and every finality signature they count in a sled
+//! database in the data directory. Each kind is a tree keyed by when it was
+//! observed, so the oldest are pruned first: those older than
+//! `node.store_retention`, and any beyond `node.store_max_entries` of a kind.
+//! `schultz db query` prints them.
+//!
+//! sled locks its database, so it is queried once the node recording to it
+//! stopped.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use casper_hashing::Digest;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use clap::ValueEnum;
+use miette::Diagnostic;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use sled::transaction::ConflictableTransactionResult;
+use sled::transaction::TransactionError;
+use sled::Transactional;
+use thiserror::Error;
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+use tracing::warn;
+
+use crate::network::finality::FinalitySignature;
+use crate::network::headers::BlockHeader;
+
+/// How often the retention policy is applied while recording.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tree telling when each observation was recorded, by kind and id, so
+/// every one is recorded once.
+const SEEN_TREE: &str = "seen";
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("Could not open the observations at {}, is a node recording to it?", .0.display())]
+    Open(PathBuf, #[source] sled::Error),
+    #[error("Could not access the observations")]
+    Db(#[from] sled::Error),
+    #[error("Invalid {0} record in the observations")]
+    Corrupt(Kind, #[source] serde_json::Error),
+}
+
+impl Diagnostic for StoreError {}
+
+/// A kind of observation, each kept in a tree of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Headers,
+    Deploys,
+    FinalitySignatures,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::Headers, Kind::Deploys, Kind::FinalitySignatures];
+
+    /// Name of the kind, and of the tree it is kept in.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Headers => "headers",
+            Kind::Deploys => "deploys",
+            Kind::FinalitySignatures => "finality_signatures",
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Kind::Headers => 0,
+            Kind::Deploys => 1,
+            Kind::FinalitySignatures => 2,
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Headers => write!(f, "header"),
+            Kind::Deploys => write!(f, "deploy"),
+            Kind::FinalitySignatures => write!(f, "finality signature"),
+        }
+    }
+}
+
+/// The header of a block followed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRecord {
+    pub observed_at: Timestamp,
+    pub block_hash: Digest,
+    pub parent_hash: Digest,
+    pub height: u64,
+    pub era_id: EraId,
+    pub timestamp: Timestamp,
+    pub protocol_version: ProtocolVersion,
+    pub switch_block: bool,
+}
+
+/// A deploy a peer gossiped, with the first peer it was seen from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployRecord {
+    pub observed_at: Timestamp,
+    pub deploy_hash: String,
+    pub peer: SocketAddr,
+}
+
+/// A finality signature counted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureRecord {
+    pub observed_at: Timestamp,
+    pub block_hash: Digest,
+    pub block_height: Option<u64>,
+    pub era_id: EraId,
+    pub public_key: PublicKey,
+}
+
+/// An observation of any kind, as `schultz db query` prints it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Record {
+    Header(HeaderRecord),
+    Deploy(DeployRecord),
+    FinalitySignature(SignatureRecord),
+}
+
+impl Record {
+    pub fn observed_at(&self) -> Timestamp {
+        match self {
+            Record::Header(record) => record.observed_at,
+            Record::Deploy(record) => record.observed_at,
+            Record::FinalitySignature(record) => record.observed_at,
+        }
+    }
+
+    /// The block the observation is about, if any.
+    fn block_hash(&self) -> Option<&Digest> {
+        match self {
+            Record::Header(record) => Some(&record.block_hash),
+            Record::Deploy(_) => None,
+            Record::FinalitySignature(record) => Some(&record.block_hash),
+        }
+    }
+}
+
+/// What to keep, the rest is pruned oldest first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// How long observations are kept, forever if unset.
+    pub max_age: Option<TimeDiff>,
+    /// Observations kept of each kind, all of them if unset.
+    pub max_entries: Option<u64>,
+}
+
+/// Which observations of a kind `schultz db query` prints.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    /// Only those observed at or after this time.
+    pub since: Option<Timestamp>,
+    /// Only those about this block.
+    pub block_hash: Option<Digest>,
+    /// At most this many, the most recent.
+    pub limit: Option<usize>,
+}
+
+/// The observations database.
+#[derive(Clone, Debug)]
+pub struct ObservationStore {
+    db: sled::Db,
+}
+
+impl ObservationStore {
+    /// Opens the database at `path`, creating it if there is none yet.
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let db = sled::open(path).map_err(|e| StoreError::Open(path.to_path_buf(), e))?;
+        Ok(Self { db })
+    }
+
+    /// A database kept in memory only, gone once dropped.
+    pub fn temporary() -> Result<Self, StoreError> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Ok(Self { db })
+    }
+
+    /// Records the header of block `hash`, unless it was recorded before.
+    pub fn record_header(&self, hash: Digest, header: &BlockHeader) -> Result<bool, StoreError> {
+        let record = HeaderRecord {
+            observed_at: Timestamp::now(),
+            block_hash: hash,
+            parent_hash: header.parent_hash,
+            height: header.height,
+            era_id: header.era_id,
+            timestamp: header.timestamp,
+            protocol_version: header.protocol_version,
+            switch_block: header.is_switch_block(),
+        };
+        self.insert(Kind::Headers, hash.as_ref(), record.observed_at, &record)
+    }
+
+    /// Records the deploy `hash` gossiped by `peer`, unless it was recorded
+    /// before.
+    pub fn record_deploy(&self, hash: &str, peer: SocketAddr) -> Result<bool, StoreError> {
+        let record = DeployRecord {
+            observed_at: Timestamp::now(),
+            deploy_hash: hash.to_string(),
+            peer,
+        };
+        self.insert(Kind::Deploys, hash.as_bytes(), record.observed_at, &record)
+    }
+
+    /// Records `signature`, unless it was recorded before.
+    pub fn record_signature(&self, signature: &FinalitySignature) -> Result<bool, StoreError> {
+        let record = SignatureRecord {
+            observed_at: Timestamp::now(),
+            block_hash: signature.block_hash,
+            block_height: signature.block_height,
+            era_id: signature.era_id,
+            public_key: signature.public_key.clone(),
+        };
+        let mut id = signature.block_hash.as_ref().to_vec();
+        id.extend(signature.public_key.to_string().into_bytes());
+        self.insert(Kind::FinalitySignatures, &id, record.observed_at, &record)
+    }
+
+    /// Records `record` of `kind` under `id` if it is new, keyed by
+    /// `observed_at` so the oldest come first.
+    fn insert<T: Serialize>(
+        &self,
+        kind: Kind,
+        id: &[u8],
+        observed_at: Timestamp,
+        record: &T,
+    ) -> Result<bool, StoreError> {
+        let mut seen_key = vec![kind.tag()];
+        seen_key.extend_from_slice(id);
+        let mut key = observed_at.millis().to_be_bytes().to_vec();
+        key.extend_from_slice(id);
+
+        let value = serde_json::to_vec(record).expect("records always serialize");
+        let (seen, tree) = (
+            self.db.open_tree(SEEN_TREE)?,
+            self.db.open_tree(kind.name())?,
+        );
+        // Both or neither, an id seen but not recorded would never be.
+        (&seen, &tree)
+            .transaction(|(seen, tree)| -> ConflictableTransactionResult<bool> {
+                if seen.get(&seen_key)?.is_some() {
+                    return Ok(false);
+                }
+                seen.insert(seen_key.as_slice(), key.as_slice())?;
+                tree.insert(key.as_slice(), value.as_slice())?;
+                Ok(true)
+            })
+            .map_err(storage_error)
+    }
+
+    /// The observations of `kind` matching `query`, most recent first.
+    pub fn query(&self, kind: Kind, query: &Query) -> Result<Vec<Record>, StoreError> {
+        let tree = self.db.open_tree(kind.name())?;
+        let since = query
+            .since
+            .map(|since| since.millis().to_be_bytes().to_vec())
+            .unwrap_or_default();
+        let mut records = Vec::new();
+        for entry in tree.range(since..).rev() {
+            if query.limit.is_some_and(|limit| records.len() >= limit) {
+                break;
+            }
+            let (_, value) = entry?;
+            let record = Self::decode(kind, &value)?;
+            if query.block_hash.is_some() && record.block_hash() != query.block_hash.as_ref() {
+                continue;
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn decode(kind: Kind, value: &[u8]) -> Result<Record, StoreError> {
+        fn parse<T: DeserializeOwned>(kind: Kind, value: &[u8]) -> Result<T, StoreError> {
+            serde_json::from_slice(value).map_err(|e| StoreError::Corrupt(kind, e))
+        }
+        Ok(match kind {
+            Kind::Headers => Record::Header(parse(kind, value)?),
+            Kind::Deploys => Record::Deploy(parse(kind, value)?),
+            Kind::FinalitySignatures => Record::FinalitySignature(parse(kind, value)?),
+        })
+    }
+
+    /// Number of observations of `kind`.
+    pub fn len(&self, kind: Kind) -> Result<usize, StoreError> {
+        Ok(self.db.open_tree(kind.name())?.len())
+    }
+
+    /// Removes the observations `retention` does not keep, returning how
+    /// many were.
+    pub fn prune(&self, retention: Retention) -> Result<usize, StoreError> {
+        let cutoff = retention
+            .max_age
+            .map(|max_age| Timestamp::now().millis().saturating_sub(max_age.millis()));
+        let seen = self.db.open_tree(SEEN_TREE)?;
+        let mut pruned = 0;
+        for kind in Kind::ALL {
+            let tree = self.db.open_tree(kind.name())?;
+            let mut excess = retention.max_entries.map_or(0, |max| {
+                tree.len().saturating_sub(max.try_into().unwrap_or(usize::MAX))
+            });
+            for entry in tree.iter() {
+                let (key, _) = entry?;
+                let millis = key.get(..8).and_then(|millis| millis.try_into().ok());
+                let expired = cutoff.is_some_and(|cutoff| {
+                    millis.is_some_and(|millis| u64::from_be_bytes(millis) < cutoff)
+                });
+                if !expired && excess == 0 {
+                    break;
+                }
+                excess = excess.saturating_sub(1);
+                let mut seen_key = vec![kind.tag()];
+                seen_key.extend_from_slice(&key[8..]);
+                (&seen, &tree)
+                    .transaction(|(seen, tree)| -> ConflictableTransactionResult<()> {
+                        tree.remove(&key)?;
+                        seen.remove(seen_key.as_slice())?;
+                        Ok(())
+                    })
+                    .map_err(storage_error)?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Applies `retention` every `every`, for as long as the node runs.
+    pub async fn prune_periodically(self, retention: Retention, every: Duration) {
+        if retention == Retention::default() {
+            return;
+        }
+        let mut ticks = interval(every);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let store = self.clone();
+            // sled blocks on its I/O.
+            match tokio::task::spawn_blocking(move || store.prune(retention)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(pruned)) => info!("Pruned {pruned} observations"),
+                Ok(Err(e)) => warn!("Could not prune the observations: {e}"),
+                Err(e) => warn!("Pruning the observations did not finish: {e}"),
+            }
+        }
+    }
+}
+
+/// The error of a transaction, which is never aborted.
+fn storage_error(e: TransactionError<()>) -> StoreError {
+    match e {
+        TransactionError::Storage(e) => StoreError::Db(e),
+        TransactionError::Abort(()) => unreachable!("observations are never aborted"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    /// Records deploys `hashes`, the first observed longest ago, `seconds`
+    /// apart.
+    fn record_deploys(store: &ObservationStore, hashes: &[&str], seconds: u32) {
+        for (age, hash) in (1..=hashes.len() as u32).rev().zip(hashes) {
+            let record = DeployRecord {
+                observed_at: Timestamp::now().saturating_sub(TimeDiff::from_seconds(age * seconds)),
+                deploy_hash: hash.to_string(),
+                peer: addr(1),
+            };
+            let (id, observed_at) = (hash.as_bytes(), record.observed_at);
+            assert!(store.insert(Kind::Deploys, id, observed_at, &record).unwrap());
+        }
+    }
+
+    #[test]
+    fn records_each_observation_once() {
+        let store = ObservationStore::temporary().unwrap();
+        assert!(store.record_deploy("aa", addr(1)).unwrap());
+        assert!(!store.record_deploy("aa", addr(2)).unwrap());
+        assert!(store.record_deploy("bb", addr(2)).unwrap());
+        assert_eq!(store.len(Kind::Deploys).unwrap(), 2);
+
+        let records = store.query(Kind::Deploys, &Query::default()).unwrap();
+        let Record::Deploy(first) = &records[1] else {
+            panic!("not a deploy: {records:?}");
+        };
+        assert_eq!((first.deploy_hash.as_str(), first.peer), ("aa", addr(1)));
+    }
+
+    #[test]
+    fn queries_most_recent_first() {
+        let store = ObservationStore::temporary().unwrap();
+        record_deploys(&store, &["aa", "bb", "cc"], 1);
+        let query = Query {
+            limit: Some(2),
+            ..Query::default()
+        };
+        let hashes: Vec<_> = store
+            .query(Kind::Deploys, &query)
+            .unwrap()
+            .into_iter()
+            .map(|record| match record {
+                Record::Deploy(record) => record.deploy_hash,
+                other => panic!("not a deploy: {other:?}"),
+            })
+            .collect();
+        assert_eq!(hashes, vec!["cc", "bb"]);
+
+        let query = Query {
+            since: Some(Timestamp::now() + TimeDiff::from_seconds(60)),
+            ..Query::default()
+        };
+        assert!(store.query(Kind::Deploys, &query).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prunes_the_oldest_beyond_the_limit() {
+        let store = ObservationStore::temporary().unwrap();
+        record_deploys(&store, &["aa", "bb", "cc"], 10);
+        let retention = Retention {
+            max_age: None,
+            max_entries: Some(1),
+        };
+        assert_eq!(store.prune(retention).unwrap(), 2);
+        assert_eq!(store.len(Kind::Deploys).unwrap(), 1);
+        assert_eq!(store.prune(retention).unwrap(), 0);
+
+        // Pruned ones are recorded again when seen again.
+        assert!(store.record_deploy("aa", addr(1)).unwrap());
+        let retention = Retention {
+            max_age: Some(TimeDiff::from_seconds(5)),
+            max_entries: None,
+        };
+        assert_eq!(store.prune(retention).unwrap(), 1);
+        let records = store.query(Kind::Deploys, &Query::default()).unwrap();
+        assert!(matches!(&records[..], [Record::Deploy(record)] if record.deploy_hash == "aa"));
+    }
+}
@@ -0,0 +1,247 @@
+//! The known-peers table, kept on disk across restarts.
+//!
+//! Every peer we learn about is recorded with the node we found there,
+//! when we last connected to it and how often connecting failed since. The
+//! node writes the table to a JSON file a little after it changes and reads
+//! it back on start, so a restarted node can rejoin through the peers it
+//! knew even if its bootnode is gone.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_types::Timestamp;
+use datasize::DataSize;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::network::node_id::NodeId;
+
+/// Consecutive failed connections after which a peer is dropped from the
+/// table.
+pub const MAX_PEER_FAILURES: u32 = 5;
+
+#[derive(Debug, Error)]
+pub enum PeerStoreError {
+    #[error("Could not read the peer list {}", .0.display())]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Invalid peer list in {}", .0.display())]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Could not write the peer list {}", .0.display())]
+    Write(PathBuf, #[source] io::Error),
+}
+
+/// A peer in the table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DataSize)]
+pub struct KnownPeer {
+    pub addr: SocketAddr,
+    /// The node we found at the address the last time, if we ever connected
+    /// to it. Tables written before call it `fingerprint`, which it is.
+    #[serde(alias = "fingerprint")]
+    pub node_id: Option<NodeId>,
+    /// When we last connected to the peer.
+    pub last_seen: Option<Timestamp>,
+    /// Failed connections since the last successful one.
+    pub failures: u32,
+}
+
+impl KnownPeer {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            node_id: None,
+            last_seen: None,
+            failures: 0,
+        }
+    }
+}
+
+/// The known-peers table, saved to `path` if there is one.
+#[derive(Debug, Default, DataSize)]
+pub struct PeerStore {
+    #[data_size(skip)]
+    path: Option<PathBuf>,
+    peers: BTreeMap<SocketAddr, KnownPeer>,
+    /// Whether the table changed since it was last taken to be saved.
+    changed: bool,
+}
+
+/// The table as it was when taken to be saved, written out by
+/// [`PendingSave::write`] away from the lock guarding the table.
+#[derive(Debug)]
+pub struct PendingSave {
+    path: PathBuf,
+    contents: Vec<u8>,
+}
+
+impl PeerStore {
+    /// A table kept in memory only.
+    pub fn in_memory() -> Self { Self::default() }
+
+    /// Reads the table saved at `path`, starting out empty if there is none
+    /// yet.
+    pub fn load(path: &Path) -> Result<Self, PeerStoreError> {
+        let peers: Vec<KnownPeer> = match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| PeerStoreError::Parse(path.to_path_buf(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(PeerStoreError::Read(path.to_path_buf(), e)),
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            peers: peers.into_iter().map(|peer| (peer.addr, peer)).collect(),
+            changed: false,
+        })
+    }
+
+    /// Every peer in the table, most recently seen first.
+    pub fn peers(&self) -> Vec<&KnownPeer> {
+        let mut peers: Vec<_> = self.peers.values().collect();
+        peers.sort_by_key(|peer| (std::cmp::Reverse(peer.last_seen), peer.failures));
+        peers
+    }
+
+    pub fn is_empty(&self) -> bool { self.peers.is_empty() }
+
+    /// Adds a peer learned about, unless it is already in the table.
+    pub fn learn(&mut self, addr: SocketAddr) {
+        if self.peers.contains_key(&addr) {
+            return;
+        }
+        self.peers.insert(addr, KnownPeer::new(addr));
+        self.changed = true;
+    }
+
+    /// Records a successful connection to `node_id` at `addr`.
+    pub fn seen(&mut self, addr: SocketAddr, node_id: Option<NodeId>) {
+        let peer = self.peers.entry(addr).or_insert_with(|| KnownPeer::new(addr));
+        if let Some(node_id) = node_id {
+            if let Some(known) = peer.node_id.filter(|known| *known != node_id) {
+                warn!("Found {node_id} at {addr:?}, where {known} was before");
+            }
+            peer.node_id = Some(node_id);
+        }
+        peer.last_seen = Some(Timestamp::now());
+        peer.failures = 0;
+        self.changed = true;
+    }
+
+    /// Records a failed connection to the peer at `addr`, dropping it once it
+    /// failed [`MAX_PEER_FAILURES`] times in a row.
+    pub fn failed(&mut self, addr: SocketAddr) {
+        let peer = self.peers.entry(addr).or_insert_with(|| KnownPeer::new(addr));
+        peer.failures += 1;
+        if peer.failures >= MAX_PEER_FAILURES {
+            self.peers.remove(&addr);
+        }
+        self.changed = true;
+    }
+
+    /// Drops the peer at `addr` from the table, e.g. after it broke the
+    /// protocol.
+    pub fn forget(&mut self, addr: &SocketAddr) {
+        if self.peers.remove(addr).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// The table to write out, if it is saved to a file and changed since
+    /// it was last taken.
+    pub fn changes(&mut self) -> Option<PendingSave> {
+        let path = self.path.clone()?;
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        let peers: Vec<_> = self.peers.values().collect();
+        let contents = serde_json::to_vec_pretty(&peers).expect("peers always serialize");
+        Some(PendingSave { path, contents })
+    }
+}
+
+impl PendingSave {
+    /// Writes the table out. This blocks on the file system.
+    pub fn write(self) -> Result<(), PeerStoreError> {
+        // Written next to the table and renamed over it, so a crash never
+        // leaves a truncated file behind.
+        let write_error = |e| PeerStoreError::Write(self.path.clone(), e);
+        let partial = self.path.with_extension("json.tmp");
+        fs::write(&partial, &self.contents).map_err(write_error)?;
+        fs::rename(&partial, &self.path).map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
+
+    #[test]
+    fn table_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.json");
+
+        let mut store = PeerStore::load(&path).unwrap();
+        assert!(store.is_empty());
+        store.learn(addr(1));
+        store.seen(addr(2), None);
+        store.failed(addr(3));
+        store.changes().unwrap().write().unwrap();
+
+        let reloaded = PeerStore::load(&path).unwrap();
+        assert_eq!(reloaded.peers(), store.peers());
+        assert_eq!(reloaded.peers()[0].addr, addr(2));
+        assert!(reloaded.peers()[0].last_seen.is_some());
+    }
+
+    #[test]
+    fn only_changed_tables_are_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PeerStore::load(&dir.path().join("peers.json")).unwrap();
+        assert!(store.changes().is_none());
+
+        store.learn(addr(1));
+        assert!(store.changes().is_some());
+        assert!(store.changes().is_none());
+
+        store.learn(addr(1));
+        store.forget(&addr(2));
+        assert!(store.changes().is_none());
+
+        let mut in_memory = PeerStore::in_memory();
+        in_memory.learn(addr(1));
+        assert!(in_memory.changes().is_none());
+    }
+
+    #[test]
+    fn peers_failing_repeatedly_are_dropped() {
+        let mut store = PeerStore::in_memory();
+        store.learn(addr(1));
+        for _ in 1..MAX_PEER_FAILURES {
+            store.failed(addr(1));
+        }
+        store.seen(addr(1), None);
+        assert_eq!(store.peers()[0].failures, 0);
+
+        for _ in 0..MAX_PEER_FAILURES {
+            store.failed(addr(1));
+        }
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_corrupt_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.json");
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            PeerStore::load(&path),
+            Err(PeerStoreError::Parse(..))
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! Signals asking a running node to stop or reload, on every platform.
+//!
+//! Unix sends SIGINT or SIGTERM to stop, macOS included. Windows has console
+//! events instead: Ctrl-C and Ctrl-Break, and the console closing, the user
+//! logging off or the system shutting down. Reloads are asked for with
+//! SIGHUP on Unix; Windows has nothing like it, so they are asked for over
+//! the [control socket](super::control) there, which Unix takes too.
+
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::signal;
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
+#[cfg(windows)]
+use tokio::signal::windows;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tracing::error;
+#[cfg(unix)]
+use tracing::info;
+
+/// Asks whoever runs the node to reload its configuration. Answered with the
+/// settings that need a restart to apply, or why the running configuration
+/// was kept.
+pub type Reload = oneshot::Sender<Result<Vec<String>, String>>;
+
+/// Waits for a signal asking the node to stop, and names it.
+///
+/// Never completes if the signals cannot be listened for, so the node runs
+/// on as it would without.
+pub async fn shutdown() -> &'static str {
+    match listen().await {
+        Ok(signal) => signal,
+        Err(e) => {
+            error!("Cannot listen for shutdown signals: {e}");
+            std::future::pending().await
+        }
+    }
+}
+
+/// Asks `reloads` for a reload every time the process receives a SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_hangup(reloads: mpsc::Sender<Reload>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Cannot reload on SIGHUP: {e}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("Received SIGHUP, reloading the configuration");
+        // Nobody waits for the outcome, which is logged anyway.
+        let (reload, _) = oneshot::channel();
+        if reloads.send(reload).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn listen() -> io::Result<&'static str> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    Ok(tokio::select! {
+        _ = interrupt.recv() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    })
+}
+
+#[cfg(windows)]
+async fn listen() -> io::Result<&'static str> {
+    let mut ctrl_c = windows::ctrl_c()?;
+    let mut ctrl_break = windows::ctrl_break()?;
+    let mut ctrl_close = windows::ctrl_close()?;
+    let mut ctrl_logoff = windows::ctrl_logoff()?;
+    let mut ctrl_shutdown = windows::ctrl_shutdown()?;
+    Ok(tokio::select! {
+        _ = ctrl_c.recv() => "Ctrl-C",
+        _ = ctrl_break.recv() => "Ctrl-Break",
+        _ = ctrl_close.recv() => "console close",
+        _ = ctrl_logoff.recv() => "logoff",
+        _ = ctrl_shutdown.recv() => "system shutdown",
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn listen() -> io::Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("Ctrl-C")
+}
@@ -0,0 +1,308 @@
+//! Metrics snapshots written to disk, for environments without a metrics
+//! stack scraping the node.
+//!
+//! Every `node.metrics_interval` the value of every counter and gauge is
+//! written as a JSON [`Snapshot`] to the metrics directory, named after when
+//! it was taken, and only the latest [`SNAPSHOTS_KEPT`] are kept.
+//! `schultz stats` prints the [`Interval`]s between them: connection churn,
+//! handshake failures and traffic over time.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use casper_types::Timestamp;
+use prometheus::proto::MetricType;
+use prometheus::Registry;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// Name of the directory in the root directory snapshots are written to.
+pub const METRICS_DIR_NAME: &str = "metrics";
+
+/// Snapshots kept by default, older ones are removed.
+pub const SNAPSHOTS_KEPT: usize = 1_000;
+
+/// The value of every counter and gauge at one point in time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: Timestamp,
+    /// Values by name and labels as Prometheus writes them, e.g.
+    /// `net_handshakes{outcome="rejected"}`.
+    pub metrics: BTreeMap<String, f64>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the counters and gauges of `registry`. Histograms
+    /// and summaries are left out.
+    pub fn of(registry: &Registry) -> Self {
+        let mut metrics = BTreeMap::new();
+        for family in registry.gather() {
+            for metric in family.get_metric() {
+                let value = match family.get_field_type() {
+                    MetricType::COUNTER => metric.get_counter().get_value(),
+                    MetricType::GAUGE => metric.get_gauge().get_value(),
+                    _ => continue,
+                };
+                let labels: Vec<_> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| format!("{}={:?}", label.get_name(), label.get_value()))
+                    .collect();
+                let key = match labels.is_empty() {
+                    true => family.get_name().to_string(),
+                    false => format!("{}{{{}}}", family.get_name(), labels.join(",")),
+                };
+                metrics.insert(key, value);
+            }
+        }
+        Snapshot {
+            taken_at: Timestamp::now(),
+            metrics,
+        }
+    }
+
+    /// The sum of every series of the metric `name`, whatever its labels.
+    pub fn total(&self, name: &str) -> f64 { self.series(name).map(|(_, value)| value).sum() }
+
+    /// The sum of the series of the metric `name` with `label` among their
+    /// labels.
+    pub fn total_with(&self, name: &str, (label, value): (&str, &str)) -> f64 {
+        let label = format!("{label}={value:?}");
+        self.series(name)
+            .filter(|(labels, _)| labels.split(',').any(|each| each == label))
+            .map(|(_, value)| value)
+            .sum()
+    }
+
+    /// The labels, without braces, and value of every series of `name`.
+    fn series<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a str, f64)> + 'a {
+        self.metrics.range(name.to_string()..).map_while(move |(key, value)| {
+            let labels = key.strip_prefix(name)?;
+            let labels = match labels.strip_prefix('{') {
+                Some(labels) => labels.strip_suffix('}')?,
+                None if labels.is_empty() => "",
+                // Another metric whose name starts with this one.
+                None => return Some(("\0", 0.0)),
+            };
+            Some((labels, *value))
+        })
+    }
+
+    /// Writes the snapshot to `dir`, creating it if needed.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.json", self.taken_at.millis()));
+        // Readers never see half a snapshot.
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Every snapshot in `dir`, oldest first. Files that are not snapshots
+    /// are skipped.
+    pub fn read_all(dir: &Path) -> io::Result<Vec<Snapshot>> {
+        let mut snapshots = Vec::new();
+        for path in snapshot_files(dir)? {
+            match fs::read(&path).map(|bytes| serde_json::from_slice::<Snapshot>(&bytes)) {
+                Ok(Ok(snapshot)) => snapshots.push(snapshot),
+                Ok(Err(e)) => warn!("Skipping {}: {e}", path.display()),
+                Err(e) => warn!("Skipping {}: {e}", path.display()),
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        Ok(snapshots)
+    }
+}
+
+/// How the node fared between two snapshots.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Interval {
+    pub from: Timestamp,
+    pub to: Timestamp,
+    pub connections_opened: u64,
+    pub connections_closed: u64,
+    pub handshakes: u64,
+    /// Handshakes rejected by either side or timed out.
+    pub handshakes_failed: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl Interval {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> Self {
+        let increase = |total: &dyn Fn(&Snapshot) -> f64| {
+            let (before, after) = (total(before), total(after));
+            // Counters start over with the node.
+            let increase = if after >= before {
+                after - before
+            } else {
+                after
+            };
+            increase as u64
+        };
+        Interval {
+            from: before.taken_at,
+            to: after.taken_at,
+            connections_opened: increase(&|snapshot| snapshot.total("net_connections_opened")),
+            connections_closed: increase(&|snapshot| snapshot.total("net_connections_closed")),
+            handshakes: increase(&|snapshot| snapshot.total("net_handshakes")),
+            handshakes_failed: increase(&|snapshot| {
+                snapshot.total("net_handshakes")
+                    - snapshot.total_with("net_handshakes", ("outcome", "completed"))
+            }),
+            bytes_read: increase(&|snapshot| snapshot.total("net_bytes_read")),
+            bytes_written: increase(&|snapshot| snapshot.total("net_bytes_written")),
+        }
+    }
+
+    /// The intervals between consecutive `snapshots`.
+    pub fn all(snapshots: &[Snapshot]) -> Vec<Self> {
+        snapshots.windows(2).map(|pair| Self::between(&pair[0], &pair[1])).collect()
+    }
+
+    /// Share of the handshakes that failed, if there were any.
+    pub fn handshake_failure_rate(&self) -> Option<f64> {
+        (self.handshakes > 0).then(|| self.handshakes_failed as f64 / self.handshakes as f64)
+    }
+}
+
+/// Writes a snapshot of `registry` to `dir` every `every`, keeping the latest
+/// `keep`.
+pub async fn write_periodically(registry: Registry, dir: PathBuf, every: Duration, keep: usize) {
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        if let Err(e) = Snapshot::of(&registry).write(&dir) {
+            warn!(
+                "Could not write a metrics snapshot to {}: {e}",
+                dir.display()
+            );
+            continue;
+        }
+        if let Err(e) = prune(&dir, keep) {
+            warn!("Could not remove old metrics snapshots: {e}");
+        }
+    }
+}
+
+/// Removes all but the latest `keep` snapshots in `dir`.
+fn prune(dir: &Path, keep: usize) -> io::Result<()> {
+    let files = snapshot_files(dir)?;
+    for path in &files[..files.len().saturating_sub(keep)] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The snapshot files in `dir`, oldest first, by the time in their name.
+fn snapshot_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            if let Some(millis) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok()) {
+                files.push((millis, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path): (u64, _)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::IntCounterVec;
+    use prometheus::Opts;
+
+    use super::*;
+
+    fn snapshot(millis: u64, metrics: &[(&str, f64)]) -> Snapshot {
+        Snapshot {
+            taken_at: Timestamp::from(millis),
+            metrics: metrics.iter().map(|(key, value)| (key.to_string(), *value)).collect(),
+        }
+    }
+
+    #[test]
+    fn snapshots_name_series_by_labels() {
+        let registry = Registry::new();
+        let handshakes =
+            IntCounterVec::new(Opts::new("net_handshakes", "handshakes"), &["outcome"]).unwrap();
+        registry.register(Box::new(handshakes.clone())).unwrap();
+        handshakes.with_label_values(&["completed"]).inc_by(3);
+        handshakes.with_label_values(&["rejected"]).inc();
+
+        let snapshot = Snapshot::of(&registry);
+        assert_eq!(
+            snapshot.metrics[r#"net_handshakes{outcome="completed"}"#],
+            3.0
+        );
+        assert_eq!(snapshot.total("net_handshakes"), 4.0);
+        assert_eq!(
+            snapshot.total_with("net_handshakes", ("outcome", "rejected")),
+            1.0
+        );
+        assert_eq!(snapshot.total("net_handshake"), 0.0);
+    }
+
+    #[test]
+    fn intervals_count_what_happened_in_between() {
+        let first = snapshot(
+            1_000,
+            &[
+                (r#"net_connections_opened{direction="inbound"}"#, 2.0),
+                (r#"net_handshakes{outcome="completed"}"#, 2.0),
+                ("net_bytes_read", 100.0),
+            ],
+        );
+        let second = snapshot(
+            2_000,
+            &[
+                (r#"net_connections_opened{direction="inbound"}"#, 5.0),
+                (r#"net_connections_opened{direction="outbound"}"#, 1.0),
+                (r#"net_connections_closed{reason="closed"}"#, 2.0),
+                (r#"net_handshakes{outcome="completed"}"#, 4.0),
+                (r#"net_handshakes{outcome="timed_out"}"#, 2.0),
+                ("net_bytes_read", 400.0),
+            ],
+        );
+        // The node restarted.
+        let third = snapshot(3_000, &[("net_bytes_read", 50.0)]);
+
+        let intervals = Interval::all(&[first, second, third]);
+        assert_eq!(intervals.len(), 2);
+        let interval = &intervals[0];
+        assert_eq!(interval.connections_opened, 4);
+        assert_eq!(interval.connections_closed, 2);
+        assert_eq!(interval.handshakes, 4);
+        assert_eq!(interval.handshakes_failed, 2);
+        assert_eq!(interval.handshake_failure_rate(), Some(0.5));
+        assert_eq!(interval.bytes_read, 300);
+        assert_eq!(intervals[1].bytes_read, 50);
+        assert_eq!(intervals[1].handshake_failure_rate(), None);
+    }
+
+    #[test]
+    fn only_the_latest_snapshots_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("snapshots");
+        for millis in [3_000, 1_000, 2_000] {
+            snapshot(millis, &[("net_bytes_read", millis as f64)]).write(&dir).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a snapshot").unwrap();
+
+        prune(&dir, 2).unwrap();
+        let kept = Snapshot::read_all(&dir).unwrap();
+        let times: Vec<_> = kept.iter().map(|snapshot| snapshot.taken_at.millis()).collect();
+        assert_eq!(times, [2_000, 3_000]);
+    }
+}
@@ -0,0 +1,181 @@
+//! HTTP endpoint reporting the health and status of a running node.
+//!
+//! `/health` answers as long as the node is up, which is all a liveness probe
+//! needs. `/status` describes the node and its connections as JSON, and
+//! `/metrics` serves its metrics for Prometheus to scrape. `/log_filter`
+//! answers the directives logs are filtered by, and putting others there
+//! filters logs by them from then on, without a restart.
+
+use std::net::SocketAddr;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use prometheus::Encoder;
+use prometheus::TextEncoder;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use super::memory::MemoryUsage;
+use super::Node;
+use crate::network::connection::ConnectionId;
+use crate::network::era_tracker::EraValidators;
+use crate::network::handshake::HandshakeResult;
+use crate::network::headers::Tip;
+use crate::telemetry;
+use crate::telemetry::LogFilterError;
+use crate::utils::Fingerprint;
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub status: &'static str,
+}
+
+/// A connected peer as reported by `/status`.
+#[derive(Debug, Serialize)]
+pub struct PeerStatus {
+    pub addr: SocketAddr,
+    /// The connection to the peer, as found in logs and metric labels.
+    pub connection_id: Option<ConnectionId>,
+    /// Last measured ping round-trip time.
+    pub latency_ms: Option<f64>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Bytes of messages and frames to or from the peer not yet handled.
+    pub buffered_bytes: usize,
+    /// Reputation of the peer, higher is better.
+    pub score: i32,
+}
+
+/// Body of `/status`.
+#[derive(Debug, Serialize)]
+pub struct Status {
+    /// Fingerprint of our TLS public key, which peers know us by.
+    pub fingerprint: Fingerprint,
+    pub addr: SocketAddr,
+    pub uptime_secs: u64,
+    pub network_name: String,
+    pub chainspec_hash: String,
+    pub connected_peers: Vec<PeerStatus>,
+    pub last_handshake: Option<HandshakeResult>,
+    /// Latest era whose validators are known.
+    pub current_era: Option<EraValidators>,
+    /// Highest block peers gossiped that is trusted, final, or linked to one
+    /// that is, when following the header chain.
+    pub observed_tip: Option<Tip>,
+    /// Headers found next to another at the same height.
+    pub header_forks: u64,
+    /// Headers, or finality quorums, that failed their checks.
+    pub invalid_headers: u64,
+    /// Estimated bytes on the heap, by subsystem.
+    pub memory: MemoryUsage,
+}
+
+impl Status {
+    pub async fn of(node: &Node) -> Self {
+        let memory = MemoryUsage::of(node).await;
+        let manager = node.manager.read().await;
+        let latencies = manager.peer_latencies().await;
+        let traffic = manager.peer_traffic();
+        let connection_ids = manager.connection_ids().await;
+
+        let connected_peers = manager
+            .connected_peers()
+            .await
+            .into_iter()
+            .map(|addr| {
+                let traffic = traffic.get(&addr).copied().unwrap_or_default();
+                PeerStatus {
+                    addr,
+                    connection_id: connection_ids.get(&addr).copied(),
+                    latency_ms: latencies.get(&addr).map(|latency| latency.as_secs_f64() * 1000.0),
+                    bytes_read: traffic.bytes_read,
+                    bytes_written: traffic.bytes_written,
+                    buffered_bytes: manager.peer_memory(&addr),
+                    score: manager.reputation().score(&addr),
+                }
+            })
+            .collect();
+
+        Status {
+            fingerprint: manager.identity().fingerprint(),
+            addr: manager.schultz_addr(),
+            uptime_secs: node.started_at.elapsed().as_secs(),
+            network_name: manager.chainspec.network_config.name.clone(),
+            chainspec_hash: base16::encode_lower(&manager.chainspec.hash()),
+            connected_peers,
+            last_handshake: manager.last_handshake().await,
+            current_era: node.eras.status(),
+            observed_tip: node.headers.tip(),
+            header_forks: node.headers.forks(),
+            invalid_headers: node.headers.invalid(),
+            memory,
+        }
+    }
+}
+
+async fn health() -> Json<Health> { Json(Health { status: "ok" }) }
+
+async fn status(State(node): State<Node>) -> Json<Status> { Json(Status::of(&node).await) }
+
+async fn metrics(State(node): State<Node>) -> Response {
+    let encoder = TextEncoder::new();
+    let mut body = vec![];
+    match encoder.encode(&node.registry.gather(), &mut body) {
+        Ok(()) => ([(header::CONTENT_TYPE, encoder.format_type())], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn log_filter() -> Response { log_filter_response(telemetry::log_filter()) }
+
+async fn set_log_filter(directives: String) -> Response {
+    let filter = telemetry::set_log_filter(&directives).and_then(|()| {
+        info!("Filtering logs by {directives:?}");
+        telemetry::log_filter()
+    });
+    log_filter_response(filter)
+}
+
+fn log_filter_response(filter: Result<String, LogFilterError>) -> Response {
+    match filter {
+        Ok(filter) => filter.into_response(),
+        Err(e @ LogFilterError::Invalid(..)) => {
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+    }
+}
+
+/// Serves `/health`, `/status`, `/metrics` and `/log_filter` for `node`
+/// until the listener fails.
+pub async fn serve(listener: TcpListener, node: Node) -> std::io::Result<()> {
+    info!("Serving node status on {:?}", listener.local_addr()?);
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .route("/log_filter", get(log_filter).put(set_log_filter))
+        .with_state(node);
+    axum::serve(listener, app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn invalid_log_filters_are_refused() {
+        let response = set_log_filter("schultz=loud".to_string()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // Tests install no subscriber, so there is no filter to replace.
+        let response = set_log_filter("schultz=trace".to_string()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
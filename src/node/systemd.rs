@@ -0,0 +1,178 @@
+//! Readiness and liveness notifications to systemd.
+//!
+//! A node started by a `Type=notify` unit tells systemd it is ready once it
+//! completed its first handshake. If the unit sets `WatchdogSec=`, the node
+//! then keeps telling systemd it is alive, but only as long as its event loop
+//! gets through the messages peers send and it is connected to at least one
+//! peer, so systemd restarts a node that is stuck or cut off. Outside of
+//! systemd, `NOTIFY_SOCKET` is not set and nothing is sent.
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use tokio::time::interval;
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+use tracing::warn;
+
+use super::Node;
+
+/// How often to check whether the node completed its first handshake.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sends notifications to the socket systemd is listening on.
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+}
+
+impl Notifier {
+    /// Connects to the socket named by `NOTIFY_SOCKET`, if the variable is
+    /// set, i.e. if systemd expects notifications.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match std::env::var_os("NOTIFY_SOCKET") {
+            Some(path) => Self::connect(&path.to_string_lossy()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Connects to the socket at `path`, which names an abstract socket if
+    /// it starts with `@`.
+    pub fn connect(path: &str) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&addr)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "abstract sockets are only supported on Linux",
+                ))
+            }
+            None => socket.connect(path)?,
+        }
+        Ok(Self { socket })
+    }
+
+    /// Sends `state`, newline-separated assignments such as `READY=1`.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send(state.as_bytes()).map(|_| ())
+    }
+}
+
+/// How often systemd expects to hear from us, half its watchdog timeout as
+/// recommended, if `WATCHDOG_USEC` is set for this very process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok();
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    parse_watchdog_interval(usec.as_deref(), pid.as_deref(), std::process::id())
+}
+
+fn parse_watchdog_interval(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    // Without a pid, the watchdog is meant for the main process, which we
+    // are when started by systemd.
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// Tells systemd once `node` is ready, then keeps pinging its watchdog every
+/// `watchdog` while the node is healthy.
+pub async fn supervise(node: Node, notifier: Notifier, watchdog: Option<Duration>) {
+    let mut poll = interval(READY_POLL_INTERVAL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        poll.tick().await;
+        let peers = node.manager.read().await.connected_peers().await.len();
+        if peers > 0 {
+            info!("Completed a handshake, telling systemd we are ready");
+            notify(
+                &notifier,
+                &format!("READY=1\nSTATUS=Connected to {peers} peers"),
+            );
+            break;
+        }
+    }
+
+    let Some(watchdog) = watchdog else {
+        return;
+    };
+    let mut ticks = interval(watchdog);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut handled = node.events_handled();
+    loop {
+        ticks.tick().await;
+        let handled_before = std::mem::replace(&mut handled, node.events_handled());
+        let responsive = handled != handled_before || !node.events_pending();
+        let peers = node.manager.read().await.connected_peers().await.len();
+        match (responsive, peers) {
+            (true, 1..) => notify(
+                &notifier,
+                &format!("WATCHDOG=1\nSTATUS=Connected to {peers} peers"),
+            ),
+            (false, _) => {
+                warn!("The event loop is stuck, leaving the systemd watchdog unanswered");
+                notify(&notifier, "STATUS=Event loop stuck");
+            }
+            (true, 0) => {
+                warn!("No peers are connected, leaving the systemd watchdog unanswered");
+                notify(&notifier, "STATUS=No peers connected");
+            }
+        }
+    }
+}
+
+fn notify(notifier: &Notifier, state: &str) {
+    if let Err(e) = notifier.notify(state) {
+        warn!("Could not notify systemd: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_are_single_datagrams() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+
+        let notifier = Notifier::connect(&path.to_string_lossy()).unwrap();
+        notifier.notify("READY=1\nSTATUS=Connected to 1 peers").unwrap();
+        let mut buf = [0; 64];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=Connected to 1 peers");
+    }
+
+    #[test]
+    fn the_watchdog_fires_at_half_its_timeout_for_our_process_only() {
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), None, 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("7"), 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("8"), 7),
+            None
+        );
+        assert_eq!(parse_watchdog_interval(Some("0"), None, 7), None);
+        assert_eq!(parse_watchdog_interval(None, None, 7), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! WebSocket endpoint streaming what a running node sees of the network.
+//!
+//! Every client connecting to `/events` gets one JSON text message per
+//! [`NetworkEvent`] and per gossip message [observed](Observed), tagged by
+//! `stream`, from then on:
+//!
+//! ```json
+//! {"stream":"network","at":"2024-11-07T04:51:33.000Z","peer":"1.2.3.4:35000","kind":{"event":"closed"}}
+//! {"stream":"gossip","peer":"1.2.3.4:35000","gossiper":"deploy","kind":"gossip","item":"ab..","bytes":45}
+//! ```
+//!
+//! A client that reads too slowly misses messages, and is told how many with
+//! a `lagged` message. Clients only ever read, whatever they send is ignored.
+
+use axum::extract::ws::Message;
+use axum::extract::ws::WebSocket;
+use axum::extract::State;
+use axum::extract::WebSocketUpgrade;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::info;
+use tracing::trace;
+
+use super::Node;
+use crate::network::history::NetworkEvent;
+use crate::network::observe::Observed;
+
+/// A message sent to clients.
+#[derive(Debug, Serialize)]
+#[serde(tag = "stream", rename_all = "snake_case")]
+pub enum Update<'a> {
+    Network(&'a NetworkEvent),
+    Gossip(&'a Observed),
+    /// The client fell behind and missed this many messages.
+    Lagged {
+        missed: u64,
+    },
+}
+
+impl Update<'_> {
+    fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("updates always serialize"))
+    }
+}
+
+async fn events(State(node): State<Node>, upgrade: WebSocketUpgrade) -> Response {
+    let (network, gossip) = {
+        let manager = node.manager.read().await;
+        (manager.network_events(), manager.observe())
+    };
+    upgrade.on_upgrade(move |socket| stream(socket, network, gossip))
+}
+
+/// Sends every event and gossip message to `socket` until the client goes
+/// away or the node stops.
+async fn stream(
+    mut socket: WebSocket,
+    mut network: broadcast::Receiver<NetworkEvent>,
+    mut gossip: broadcast::Receiver<Observed>,
+) {
+    loop {
+        let message = tokio::select! {
+            event = network.recv() => match event {
+                Ok(event) => Update::Network(&event).to_message(),
+                Err(RecvError::Lagged(missed)) => Update::Lagged { missed }.to_message(),
+                Err(RecvError::Closed) => return,
+            },
+            observed = gossip.recv() => match observed {
+                Ok(observed) => Update::Gossip(&observed).to_message(),
+                Err(RecvError::Lagged(missed)) => Update::Lagged { missed }.to_message(),
+                Err(RecvError::Closed) => return,
+            },
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if let Err(e) = socket.send(message).await {
+            trace!("WebSocket client went away: {e}");
+            return;
+        }
+    }
+}
+
+/// Serves `/events` for `node` until the listener fails.
+pub async fn serve(listener: TcpListener, node: Node) -> std::io::Result<()> {
+    info!(
+        "Streaming network events on ws://{:?}/events",
+        listener.local_addr()?
+    );
+    let app = Router::new().route("/events", get(events)).with_state(node);
+    axum::serve(listener, app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use casper_types::Timestamp;
+    use serde_json::json;
+
+    use super::*;
+    use crate::network::history::EventKind;
+
+    #[test]
+    fn updates_are_tagged_by_stream() {
+        let event = NetworkEvent {
+            at: Timestamp::zero(),
+            peer: SocketAddr::from(([127, 0, 0, 1], 1)),
+            kind: EventKind::Closed,
+        };
+        let json = serde_json::to_value(Update::Network(&event)).unwrap();
+        assert_eq!(json["stream"], "network");
+        assert_eq!(json["peer"], "127.0.0.1:1");
+        assert_eq!(json["kind"], json!({"event": "closed"}));
+
+        let json = serde_json::to_value(Update::Lagged { missed: 3 }).unwrap();
+        assert_eq!(json, json!({"stream": "lagged", "missed": 3}));
+    }
+}
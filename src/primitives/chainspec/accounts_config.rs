@@ -5,6 +5,7 @@ mod account_config;
 mod delegator_config;
 mod validator_config;
 
+use std::collections::BTreeMap;
 use std::path::Path;
 
 pub use account_config::AccountConfig;
@@ -15,6 +16,7 @@ use casper_types::bytesrepr::{self};
 use casper_types::file_utils;
 use casper_types::Motes;
 use casper_types::PublicKey;
+use casper_types::U512;
 use datasize::DataSize;
 pub use delegator_config::DelegatorConfig;
 use serde::Deserialize;
@@ -24,7 +26,7 @@ pub use validator_config::ValidatorConfig;
 
 use super::error::ChainspecAccountsLoadError;
 
-const CHAINSPEC_ACCOUNTS_FILENAME: &str = "accounts.toml";
+pub(super) const CHAINSPEC_ACCOUNTS_FILENAME: &str = "accounts.toml";
 
 fn sorted_vec_deserializer<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
 where
@@ -132,6 +134,23 @@ impl AccountsConfig {
         self.accounts.iter().find(|account| &account.public_key == public_key)
     }
 
+    /// Weight of every genesis validator: its bond and what is delegated to
+    /// it.
+    pub fn validator_weights(&self) -> BTreeMap<PublicKey, U512> {
+        let mut weights: BTreeMap<PublicKey, U512> = self
+            .accounts
+            .iter()
+            .filter(|account| account.is_genesis_validator())
+            .map(|account| (account.public_key(), account.bonded_amount().value()))
+            .collect();
+        for delegator in &self.delegators {
+            if let Some(weight) = weights.get_mut(&delegator.validator_public_key) {
+                *weight = weight.saturating_add(delegator.delegated_amount.value());
+            }
+        }
+        weights
+    }
+
     pub fn is_genesis_validator(&self, public_key: &PublicKey) -> bool {
         match self.account(public_key) {
             None => false,
@@ -183,3 +202,39 @@ impl FromBytes for AccountsConfig {
         Ok((accounts_config, remainder))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::primitives::chainspec::gens::accounts_config_arb;
+    use crate::primitives::chainspec::gens::delegator_config_arb;
+    use crate::primitives::chainspec::gens::validator_config_arb;
+
+    proptest! {
+        #[test]
+        fn validator_config_bytesrepr_roundtrip(config in validator_config_arb()) {
+            bytesrepr::test_serialization_roundtrip(&config);
+        }
+
+        #[test]
+        fn delegator_config_bytesrepr_roundtrip(config in delegator_config_arb()) {
+            bytesrepr::test_serialization_roundtrip(&config);
+        }
+
+        #[test]
+        fn bytesrepr_roundtrip(config in accounts_config_arb()) {
+            bytesrepr::test_serialization_roundtrip(&config);
+        }
+
+        #[test]
+        fn toml_roundtrip(config in accounts_config_arb()) {
+            // The serializer can't write an empty list after a list of tables
+            // without going through `toml::Value` first.
+            let value = toml::Value::try_from(&config).unwrap();
+            let encoded = toml::to_string_pretty(&value).unwrap();
+            prop_assert_eq!(toml::from_str::<AccountsConfig>(&encoded).unwrap(), config);
+        }
+    }
+}
@@ -35,49 +35,6 @@ impl DelegatorConfig {
             delegated_amount,
         }
     }
-
-    #[cfg(test)]
-    /// Generates a random instance using a `TestRng`.
-    pub fn random(rng: &mut TestRng) -> Self {
-        let validator_public_key =
-            PublicKey::from(&SecretKey::ed25519_from_bytes(rng.gen::<[u8; 32]>()).unwrap());
-        let delegator_public_key =
-            PublicKey::from(&SecretKey::ed25519_from_bytes(rng.gen::<[u8; 32]>()).unwrap());
-        let balance = Motes::new(U512::from(rng.gen::<u64>()));
-        let delegated_amount = Motes::new(U512::from(rng.gen::<u64>()));
-
-        DelegatorConfig {
-            validator_public_key,
-            delegator_public_key,
-            balance,
-            delegated_amount,
-        }
-    }
-}
-
-#[cfg(test)]
-impl Distribution<DelegatorConfig> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> DelegatorConfig {
-        let validator_secret_key = SecretKey::ed25519_from_bytes(rng.gen::<[u8; 32]>()).unwrap();
-        let delegator_secret_key = SecretKey::ed25519_from_bytes(rng.gen::<[u8; 32]>()).unwrap();
-
-        let validator_public_key = PublicKey::from(&validator_secret_key);
-        let delegator_public_key = PublicKey::from(&delegator_secret_key);
-
-        let mut u512_array = [0u8; 64];
-        rng.fill_bytes(u512_array.as_mut());
-        let balance = Motes::new(U512::from(u512_array));
-
-        rng.fill_bytes(u512_array.as_mut());
-        let delegated_amount = Motes::new(U512::from(u512_array));
-
-        DelegatorConfig::new(
-            validator_public_key,
-            delegator_public_key,
-            balance,
-            delegated_amount,
-        )
-    }
 }
 
 impl ToBytes for DelegatorConfig {
@@ -32,19 +32,6 @@ impl ValidatorConfig {
     pub fn bonded_amount(&self) -> Motes { self.bonded_amount }
 }
 
-#[cfg(test)]
-impl Distribution<ValidatorConfig> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ValidatorConfig {
-        let mut u512_array = [0; 64];
-        rng.fill_bytes(u512_array.as_mut());
-        let bonded_amount = Motes::new(U512::from(u512_array));
-
-        let delegation_rate = rng.gen();
-
-        ValidatorConfig::new(bonded_amount, delegation_rate)
-    }
-}
-
 impl ToBytes for ValidatorConfig {
     fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
         let mut buffer = bytesrepr::allocate_buffer(self)?;
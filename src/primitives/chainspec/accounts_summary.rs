@@ -0,0 +1,638 @@
+//! Summary of an `accounts.toml`, the accounts, validators, delegators and
+//! administrators a network starts with, checked for the mistakes
+//! casper-node refuses at genesis.
+//!
+//! Like the lint of global state updates, the summary works on the file as
+//! written rather than on a loaded
+//! [`AccountsConfig`](super::accounts_config::AccountsConfig): public keys
+//! and amounts are read as plain strings, so every malformed one is reported
+//! rather than the first only. Entries are referred to by their table and
+//! their position in it, starting at 0. An entry with an invalid public key
+//! counts towards no total, and one listed again only counts the first time.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::Path;
+
+use casper_types::file_utils;
+use casper_types::AsymmetricType;
+use casper_types::PublicKey;
+use casper_types::U512;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+use super::accounts_config::CHAINSPEC_ACCOUNTS_FILENAME;
+use super::error::ChainspecAccountsLoadError;
+use super::global_state_update::DecWeight;
+use super::global_state_update::HexPublicKey;
+use super::lint::Severity;
+
+/// Highest delegation rate, the validator keeping all the rewards of its
+/// delegators.
+const MAX_DELEGATION_RATE: u8 = 100;
+
+/// The file as written.
+#[derive(Deserialize)]
+struct RawAccounts {
+    accounts: Vec<RawAccount>,
+    #[serde(default)]
+    delegators: Vec<RawDelegator>,
+    #[serde(default)]
+    administrators: Vec<RawAdministrator>,
+}
+
+#[derive(Deserialize)]
+struct RawAccount {
+    public_key: String,
+    balance: String,
+    validator: Option<RawValidator>,
+}
+
+#[derive(Deserialize)]
+struct RawValidator {
+    bonded_amount: String,
+    #[serde(default)]
+    delegation_rate: u8,
+}
+
+#[derive(Deserialize)]
+struct RawDelegator {
+    validator_public_key: String,
+    delegator_public_key: String,
+    balance: String,
+    delegated_amount: String,
+}
+
+#[derive(Deserialize)]
+struct RawAdministrator {
+    public_key: String,
+    balance: String,
+}
+
+/// A mistake found in the accounts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccountsFinding {
+    /// A public key is not hex-encoded with its algorithm tag.
+    InvalidPublicKey {
+        table: &'static str,
+        index: usize,
+        public_key: String,
+        error: String,
+    },
+    /// An amount of motes is not a decimal number.
+    InvalidAmount {
+        table: &'static str,
+        index: usize,
+        field: &'static str,
+        amount: String,
+        error: String,
+    },
+    /// An account is listed more than once.
+    DuplicateAccount {
+        public_key: String,
+        accounts: Vec<usize>,
+    },
+    /// An administrator is listed more than once.
+    DuplicateAdministrator {
+        public_key: String,
+        administrators: Vec<usize>,
+    },
+    /// A delegator delegates to the same validator more than once.
+    DuplicateDelegation {
+        validator: String,
+        delegator: String,
+        delegators: Vec<usize>,
+    },
+    /// No account is a validator, so the network cannot start.
+    NoValidators,
+    /// A validator bonds nothing.
+    ZeroBond { public_key: String },
+    /// A validator keeps more than all the rewards of its delegators.
+    DelegationRateTooHigh {
+        public_key: String,
+        delegation_rate: u8,
+    },
+    /// A delegator delegates nothing.
+    ZeroDelegation { delegator: usize },
+    /// A delegator delegates to an account that is not a validator.
+    DelegatedToNonValidator { delegator: usize, validator: String },
+    /// A validator holds at least a third of the total weight, enough to halt
+    /// finality on its own.
+    DominantValidator {
+        public_key: String,
+        weight: String,
+        total_weight: String,
+    },
+    /// A total does not fit in 512 bits.
+    TotalOverflow { total: &'static str },
+}
+
+impl AccountsFinding {
+    pub fn severity(&self) -> Severity {
+        match self {
+            AccountsFinding::DominantValidator { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for AccountsFinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountsFinding::InvalidPublicKey {
+                table,
+                index,
+                public_key,
+                error,
+            } => write!(
+                f,
+                "{table} {index} has invalid public key {public_key:?}: {error}"
+            ),
+            AccountsFinding::InvalidAmount {
+                table,
+                index,
+                field,
+                amount,
+                error,
+            } => write!(f, "{table} {index} has invalid {field} {amount:?}: {error}"),
+            AccountsFinding::DuplicateAccount {
+                public_key,
+                accounts,
+            } => write!(f, "{public_key} is listed as accounts {accounts:?}"),
+            AccountsFinding::DuplicateAdministrator {
+                public_key,
+                administrators,
+            } => write!(
+                f,
+                "{public_key} is listed as administrators {administrators:?}"
+            ),
+            AccountsFinding::DuplicateDelegation {
+                validator,
+                delegator,
+                delegators,
+            } => write!(
+                f,
+                "{delegator} delegates to {validator} as delegators {delegators:?}"
+            ),
+            AccountsFinding::NoValidators => write!(f, "no account is a validator"),
+            AccountsFinding::ZeroBond { public_key } => write!(f, "{public_key} bonds nothing"),
+            AccountsFinding::DelegationRateTooHigh {
+                public_key,
+                delegation_rate,
+            } => write!(
+                f,
+                "{public_key} has delegation rate {delegation_rate}, over {MAX_DELEGATION_RATE}"
+            ),
+            AccountsFinding::ZeroDelegation { delegator } => {
+                write!(f, "delegator {delegator} delegates nothing")
+            }
+            AccountsFinding::DelegatedToNonValidator {
+                delegator,
+                validator,
+            } => write!(
+                f,
+                "delegator {delegator} delegates to {validator}, which is not a validator"
+            ),
+            AccountsFinding::DominantValidator {
+                public_key,
+                weight,
+                total_weight,
+            } => write!(
+                f,
+                "{public_key} holds {weight} of the total weight {total_weight}, a third or more"
+            ),
+            AccountsFinding::TotalOverflow { total } => {
+                write!(f, "the total {total} does not fit in 512 bits")
+            }
+        }
+    }
+}
+
+/// A validator at genesis, with what is delegated to it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GenesisValidator {
+    pub public_key: HexPublicKey,
+    pub bonded_amount: DecWeight,
+    pub delegation_rate: u8,
+    pub delegated_amount: DecWeight,
+    pub delegators: usize,
+}
+
+impl GenesisValidator {
+    /// The bond and what is delegated, which the validator weighs.
+    pub fn weight(&self) -> U512 { self.bonded_amount.0.saturating_add(self.delegated_amount.0) }
+}
+
+/// What an `accounts.toml` sets up, and what is wrong with it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AccountsSummary {
+    pub accounts: usize,
+    /// The validators, in the order of their public keys.
+    pub validators: Vec<GenesisValidator>,
+    pub delegators: usize,
+    pub administrators: usize,
+    /// Motes held by the accounts, the delegators and the administrators.
+    pub total_balance: DecWeight,
+    pub total_bonded: DecWeight,
+    pub total_delegated: DecWeight,
+    /// Findings in the order they were found, each with its severity.
+    #[serde(serialize_with = "serialize_findings")]
+    pub findings: Vec<AccountsFinding>,
+}
+
+fn serialize_findings<S: Serializer>(
+    findings: &[AccountsFinding],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Reported<'a> {
+        severity: Severity,
+        #[serde(flatten)]
+        finding: &'a AccountsFinding,
+    }
+
+    serializer.collect_seq(findings.iter().map(|finding| Reported {
+        severity: finding.severity(),
+        finding,
+    }))
+}
+
+impl AccountsSummary {
+    /// Summarizes `accounts.toml` in the given directory.
+    ///
+    /// If the file doesn't exist, returns `Ok(None)`.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Option<Self>, ChainspecAccountsLoadError> {
+        let accounts_path = path.as_ref().join(CHAINSPEC_ACCOUNTS_FILENAME);
+        if !accounts_path.is_file() {
+            return Ok(None);
+        }
+        Self::read(&file_utils::read_file(accounts_path)?).map(Some)
+    }
+
+    /// Summarizes the accounts in `bytes`. Fails only if they are not valid
+    /// TOML or miss a field.
+    pub fn read(bytes: &[u8]) -> Result<Self, ChainspecAccountsLoadError> {
+        Ok(Summarizer::default().summarize(toml::from_slice(bytes)?))
+    }
+
+    /// Number of findings with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity() == severity).count()
+    }
+
+    pub fn is_clean(&self) -> bool { self.findings.is_empty() }
+}
+
+/// Totals kept while the entries are read.
+#[derive(Default)]
+struct Summarizer {
+    findings: Vec<AccountsFinding>,
+    total_balance: U512,
+    total_bonded: U512,
+    total_delegated: U512,
+    overflowed: BTreeSet<&'static str>,
+}
+
+impl Summarizer {
+    fn summarize(mut self, raw: RawAccounts) -> AccountsSummary {
+        let mut accounts: BTreeMap<PublicKey, Vec<usize>> = BTreeMap::new();
+        let mut validators: BTreeMap<PublicKey, GenesisValidator> = BTreeMap::new();
+        for (index, account) in raw.accounts.iter().enumerate() {
+            let public_key = self.public_key("account", index, &account.public_key);
+            let balance = self.amount("account", index, "balance", &account.balance);
+            let validator = account.validator.as_ref().map(|validator| {
+                let bond = self.amount("account", index, "bonded_amount", &validator.bonded_amount);
+                (bond, validator.delegation_rate)
+            });
+            let Some(public_key) = public_key else {
+                continue;
+            };
+            let listed = accounts.entry(public_key.clone()).or_default();
+            listed.push(index);
+            let duplicate = listed.len() > 1;
+            if !duplicate {
+                self.add_balance(balance);
+            }
+            let Some((bond, delegation_rate)) = validator else {
+                continue;
+            };
+            if bond.is_some_and(|bond| bond.is_zero()) {
+                self.findings.push(AccountsFinding::ZeroBond {
+                    public_key: public_key.to_hex(),
+                });
+            }
+            if delegation_rate > MAX_DELEGATION_RATE {
+                self.findings.push(AccountsFinding::DelegationRateTooHigh {
+                    public_key: public_key.to_hex(),
+                    delegation_rate,
+                });
+            }
+            if duplicate {
+                continue;
+            }
+            let bond = bond.unwrap_or_default();
+            self.total_bonded = self.add(self.total_bonded, bond, "bonded");
+            validators.entry(public_key.clone()).or_insert(GenesisValidator {
+                public_key: HexPublicKey(public_key),
+                bonded_amount: DecWeight(bond),
+                delegation_rate,
+                delegated_amount: DecWeight(U512::zero()),
+                delegators: 0,
+            });
+        }
+
+        let mut delegations: BTreeMap<(PublicKey, PublicKey), Vec<usize>> = BTreeMap::new();
+        for (index, delegator) in raw.delegators.iter().enumerate() {
+            let validator = self.public_key("delegator", index, &delegator.validator_public_key);
+            let public_key = self.public_key("delegator", index, &delegator.delegator_public_key);
+            let balance = self.amount("delegator", index, "balance", &delegator.balance);
+            let delegated = self.amount(
+                "delegator",
+                index,
+                "delegated_amount",
+                &delegator.delegated_amount,
+            );
+            let (Some(validator), Some(public_key)) = (validator, public_key) else {
+                continue;
+            };
+            let listed = delegations.entry((validator.clone(), public_key)).or_default();
+            listed.push(index);
+            if listed.len() > 1 {
+                continue;
+            }
+            self.add_balance(balance);
+            let Some(delegated) = delegated else {
+                continue;
+            };
+            if delegated.is_zero() {
+                self.findings.push(AccountsFinding::ZeroDelegation { delegator: index });
+            }
+            self.total_delegated = self.add(self.total_delegated, delegated, "delegated");
+            match validators.get_mut(&validator) {
+                Some(validator) => {
+                    validator.delegated_amount.0 =
+                        validator.delegated_amount.0.saturating_add(delegated);
+                    validator.delegators += 1;
+                }
+                None => self.findings.push(AccountsFinding::DelegatedToNonValidator {
+                    delegator: index,
+                    validator: validator.to_hex(),
+                }),
+            }
+        }
+
+        let mut administrators: BTreeMap<PublicKey, Vec<usize>> = BTreeMap::new();
+        for (index, administrator) in raw.administrators.iter().enumerate() {
+            let public_key = self.public_key("administrator", index, &administrator.public_key);
+            let balance = self.amount("administrator", index, "balance", &administrator.balance);
+            let Some(public_key) = public_key else {
+                continue;
+            };
+            let listed = administrators.entry(public_key).or_default();
+            listed.push(index);
+            if listed.len() == 1 {
+                self.add_balance(balance);
+            }
+        }
+
+        for (public_key, accounts) in accounts {
+            if accounts.len() > 1 {
+                self.findings.push(AccountsFinding::DuplicateAccount {
+                    public_key: public_key.to_hex(),
+                    accounts,
+                });
+            }
+        }
+        for ((validator, delegator), delegators) in delegations {
+            if delegators.len() > 1 {
+                self.findings.push(AccountsFinding::DuplicateDelegation {
+                    validator: validator.to_hex(),
+                    delegator: delegator.to_hex(),
+                    delegators,
+                });
+            }
+        }
+        for (public_key, administrators) in administrators {
+            if administrators.len() > 1 {
+                self.findings.push(AccountsFinding::DuplicateAdministrator {
+                    public_key: public_key.to_hex(),
+                    administrators,
+                });
+            }
+        }
+
+        let validators: Vec<_> = validators.into_values().collect();
+        if validators.is_empty() {
+            self.findings.push(AccountsFinding::NoValidators);
+        }
+        let total_weight = validators.iter().fold(U512::zero(), |total, validator| {
+            total.saturating_add(validator.weight())
+        });
+        // A single validator always holds all the weight, which is fine.
+        if validators.len() > 1 {
+            for validator in &validators {
+                let weight = validator.weight();
+                if !weight.is_zero() && weight.saturating_mul(U512::from(3)) >= total_weight {
+                    self.findings.push(AccountsFinding::DominantValidator {
+                        public_key: validator.public_key.0.to_hex(),
+                        weight: weight.to_string(),
+                        total_weight: total_weight.to_string(),
+                    });
+                }
+            }
+        }
+        for &total in &self.overflowed {
+            self.findings.push(AccountsFinding::TotalOverflow { total });
+        }
+
+        AccountsSummary {
+            accounts: raw.accounts.len(),
+            validators,
+            delegators: raw.delegators.len(),
+            administrators: raw.administrators.len(),
+            total_balance: DecWeight(self.total_balance),
+            total_bonded: DecWeight(self.total_bonded),
+            total_delegated: DecWeight(self.total_delegated),
+            findings: self.findings,
+        }
+    }
+
+    fn public_key(&mut self, table: &'static str, index: usize, hex: &str) -> Option<PublicKey> {
+        PublicKey::from_hex(hex)
+            .map_err(|error| {
+                self.findings.push(AccountsFinding::InvalidPublicKey {
+                    table,
+                    index,
+                    public_key: hex.to_string(),
+                    error: error.to_string(),
+                })
+            })
+            .ok()
+    }
+
+    fn amount(
+        &mut self,
+        table: &'static str,
+        index: usize,
+        field: &'static str,
+        amount: &str,
+    ) -> Option<U512> {
+        U512::from_dec_str(amount)
+            .map_err(|error| {
+                self.findings.push(AccountsFinding::InvalidAmount {
+                    table,
+                    index,
+                    field,
+                    amount: amount.to_string(),
+                    error: format!("{error:?}"),
+                })
+            })
+            .ok()
+    }
+
+    fn add_balance(&mut self, balance: Option<U512>) {
+        if let Some(balance) = balance {
+            self.total_balance = self.add(self.total_balance, balance, "balance");
+        }
+    }
+
+    /// `total` and `amount`, saturating and remembering that `name`
+    /// overflowed.
+    fn add(&mut self, total: U512, amount: U512, name: &'static str) -> U512 {
+        total.checked_add(amount).unwrap_or_else(|| {
+            self.overflowed.insert(name);
+            U512::MAX
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::public_key;
+
+    fn validator(seed: u8, bond: &str, delegation_rate: u8) -> String {
+        format!(
+            "[[accounts]]\npublic_key = \"{}\"\nbalance = \
+             \"1000\"\n\n[accounts.validator]\nbonded_amount = \"{bond}\"\ndelegation_rate = \
+             {delegation_rate}\n",
+            public_key(seed).to_hex()
+        )
+    }
+
+    fn delegator(validator: &str, seed: u8, delegated: &str) -> String {
+        format!(
+            "[[delegators]]\nvalidator_public_key = \"{validator}\"\ndelegator_public_key = \
+             \"{}\"\nbalance = \"10\"\ndelegated_amount = \"{delegated}\"\n",
+            public_key(seed).to_hex()
+        )
+    }
+
+    fn summarize(toml: &str) -> AccountsSummary { AccountsSummary::read(toml.as_bytes()).unwrap() }
+
+    #[test]
+    fn sound_accounts_are_summed_up() {
+        let toml = [
+            validator(1, "100", 10),
+            validator(2, "100", 10),
+            validator(3, "100", 10),
+            validator(4, "50", 10),
+            delegator(&public_key(4).to_hex(), 5, "50"),
+            format!(
+                "[[administrators]]\npublic_key = \"{}\"\nbalance = \"5\"\n",
+                public_key(6).to_hex()
+            ),
+        ]
+        .concat();
+
+        let summary = summarize(&toml);
+        assert_eq!(summary.findings, []);
+        assert_eq!(
+            (summary.accounts, summary.delegators, summary.administrators),
+            (4, 1, 1)
+        );
+        assert_eq!(summary.total_balance.0, U512::from(4015));
+        assert_eq!(summary.total_bonded.0, U512::from(350));
+        assert_eq!(summary.total_delegated.0, U512::from(50));
+        let delegated_to = summary
+            .validators
+            .iter()
+            .find(|validator| validator.public_key.0 == public_key(4))
+            .unwrap();
+        assert_eq!(delegated_to.weight(), U512::from(100));
+        assert_eq!(delegated_to.delegators, 1);
+    }
+
+    #[test]
+    fn finds_what_genesis_would_refuse() {
+        let toml = [
+            validator(1, "100", 101),
+            validator(1, "0", 0),
+            "[[accounts]]\npublic_key = \"01beef\"\nbalance = \"lots\"\n".to_string(),
+            delegator(&public_key(2).to_hex(), 3, "0"),
+            delegator(&public_key(1).to_hex(), 4, "10"),
+            delegator(&public_key(1).to_hex(), 4, "10"),
+        ]
+        .concat();
+
+        let summary = summarize(&toml);
+        let kinds: Vec<_> = summary
+            .findings
+            .iter()
+            .map(|finding| serde_json::to_value(finding).unwrap()["kind"].clone())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "delegation_rate_too_high",
+                "zero_bond",
+                "invalid_public_key",
+                "invalid_amount",
+                "zero_delegation",
+                "delegated_to_non_validator",
+                "duplicate_account",
+                "duplicate_delegation",
+            ]
+        );
+        assert_eq!(summary.count(Severity::Error), 8);
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["findings"][1]["severity"], "error");
+        assert_eq!(json["total_bonded"], "100");
+    }
+
+    #[test]
+    fn entries_count_once_and_only_with_valid_public_keys() {
+        let administrator = |public_key: &str| {
+            format!("[[administrators]]\npublic_key = \"{public_key}\"\nbalance = \"5\"\n")
+        };
+        let toml = [
+            validator(1, "100", 10),
+            validator(2, "100", 10),
+            validator(1, "300", 10),
+            delegator(&public_key(1).to_hex(), 3, "10"),
+            delegator(&public_key(1).to_hex(), 3, "20"),
+            delegator("01beef", 4, "40"),
+            administrator(&public_key(5).to_hex()),
+            administrator(&public_key(5).to_hex()),
+            administrator("01beef"),
+        ]
+        .concat();
+
+        let summary = summarize(&toml);
+        assert_eq!(summary.validators.len(), 2);
+        let delegated_to = summary
+            .validators
+            .iter()
+            .find(|validator| validator.public_key.0 == public_key(1))
+            .unwrap();
+        assert_eq!(delegated_to.weight(), U512::from(110));
+        assert_eq!(summary.total_balance.0, U512::from(2015));
+        assert_eq!(summary.total_bonded.0, U512::from(200));
+        assert_eq!(summary.total_delegated.0, U512::from(10));
+    }
+}
@@ -108,3 +108,31 @@ impl FromBytes for ActivationPoint {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::primitives::chainspec::gens::activation_point_arb;
+
+    /// The activation point as it appears under `[protocol]` in the chainspec.
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    struct Protocol {
+        activation_point: ActivationPoint,
+    }
+
+    proptest! {
+        #[test]
+        fn bytesrepr_roundtrip(activation_point in activation_point_arb()) {
+            bytesrepr::test_serialization_roundtrip(&activation_point);
+        }
+
+        #[test]
+        fn toml_roundtrip(activation_point in activation_point_arb()) {
+            let protocol = Protocol { activation_point };
+            let encoded = toml::to_string(&protocol).unwrap();
+            prop_assert_eq!(toml::from_str::<Protocol>(&encoded).unwrap(), protocol);
+        }
+    }
+}
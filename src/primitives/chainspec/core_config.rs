@@ -172,81 +172,6 @@ impl CoreConfig {
     }
 }
 
-#[cfg(test)]
-impl CoreConfig {
-    /// Generates a random instance using a `TestRng`.
-    pub fn random(rng: &mut TestRng) -> Self {
-        let era_duration = TimeDiff::from_seconds(rng.gen_range(600..604_800));
-        let minimum_era_height = rng.gen_range(5..100);
-        let minimum_block_time = TimeDiff::from_seconds(rng.gen_range(1..60));
-        let validator_slots = rng.gen_range(1..10_000);
-        let finality_threshold_fraction = Ratio::new(rng.gen_range(1..100), 100);
-        let start_protocol_version_with_strict_finality_signatures_required =
-            ProtocolVersion::from_parts(1, rng.gen_range(5..10), rng.gen_range(0..100));
-        let legacy_required_finality = rng.gen();
-        let auction_delay = rng.gen_range(1..5);
-        let locked_funds_period = TimeDiff::from_seconds(rng.gen_range(600..604_800));
-        let vesting_schedule_period = TimeDiff::from_seconds(rng.gen_range(600..604_800));
-        let unbonding_delay = rng.gen_range((auction_delay + 1)..1_000_000_000);
-        let round_seigniorage_rate = Ratio::new(
-            rng.gen_range(1..1_000_000_000),
-            rng.gen_range(1..1_000_000_000),
-        );
-        let max_associated_keys = rng.gen();
-        let max_runtime_call_stack_height = rng.gen();
-        let minimum_delegation_amount = rng.gen::<u32>() as u64;
-        let prune_batch_size = rng.gen_range(0..100);
-        let strict_argument_checking = rng.gen();
-        let simultaneous_peer_requests = rng.gen_range(3..100);
-        let consensus_protocol = rng.gen();
-        let allow_auction_bids = rng.gen();
-        let allow_unrestricted_transfers = rng.gen();
-        let compute_rewards = rng.gen();
-        let administrators =
-            (0..rng.gen_range(0..=10u32)).map(|_| PublicKey::random(rng)).collect();
-        let refund_handling = {
-            let numer = rng.gen_range(0..=100);
-            let refund_ratio = Ratio::new(numer, 100);
-            RefundHandling::Refund { refund_ratio }
-        };
-
-        let fee_handling = if rng.gen() {
-            FeeHandling::PayToProposer
-        } else {
-            FeeHandling::Accumulate
-        };
-
-        CoreConfig {
-            era_duration,
-            minimum_era_height,
-            minimum_block_time,
-            validator_slots,
-            finality_threshold_fraction,
-            start_protocol_version_with_strict_finality_signatures_required,
-            legacy_required_finality,
-            auction_delay,
-            locked_funds_period,
-            vesting_schedule_period,
-            unbonding_delay,
-            round_seigniorage_rate,
-            max_associated_keys,
-            max_runtime_call_stack_height,
-            minimum_delegation_amount,
-            prune_batch_size,
-            strict_argument_checking,
-            simultaneous_peer_requests,
-            consensus_protocol,
-            max_delegators_per_validator: 0,
-            allow_auction_bids,
-            administrators,
-            allow_unrestricted_transfers,
-            compute_rewards,
-            refund_handling,
-            fee_handling,
-        }
-    }
-}
-
 impl ToBytes for CoreConfig {
     fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
         let mut buffer = bytesrepr::allocate_buffer(self)?;
@@ -434,17 +359,6 @@ impl FromBytes for ConsensusProtocolName {
     }
 }
 
-#[cfg(test)]
-impl Distribution<ConsensusProtocolName> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ConsensusProtocolName {
-        if rng.gen() {
-            ConsensusProtocolName::Highway
-        } else {
-            ConsensusProtocolName::Zug
-        }
-    }
-}
-
 /// Which finality a legacy block needs during a fast sync.
 #[derive(Copy, Clone, DataSize, PartialEq, Eq, Debug)]
 pub enum LegacyRequiredFinality {
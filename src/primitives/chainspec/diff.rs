@@ -0,0 +1,152 @@
+//! Field-by-field comparison of two chainspecs.
+//!
+//! Both chainspecs are lowered to JSON (see [`Chainspec::to_json`]) and walked
+//! in parallel, so every leaf that differs is reported with its full dotted
+//! path. Global state update entries are decoded into `StoredValue`s first,
+//! which makes protocol-upgrade changes readable instead of opaque base64
+//! blobs.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::primitives::Chainspec;
+
+/// A single difference between two chainspecs.
+#[derive(Clone, PartialEq, Eq, Serialize, Debug)]
+pub struct FieldChange {
+    /// Dotted path of the field, e.g. `core.era_duration`.
+    pub path: String,
+    /// Value in the first chainspec, `None` if the field was added.
+    pub old: Option<Value>,
+    /// Value in the second chainspec, `None` if the field was removed.
+    pub new: Option<Value>,
+}
+
+impl Display for FieldChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "~ {}: {} -> {}", self.path, old, new),
+            (None, Some(new)) => write!(f, "+ {}: {}", self.path, new),
+            (Some(old), None) => write!(f, "- {}: {}", self.path, old),
+            (None, None) => write!(f, "  {}", self.path),
+        }
+    }
+}
+
+/// Structured diff between two chainspecs.
+#[derive(Clone, PartialEq, Eq, Serialize, Debug, Default)]
+pub struct ChainspecDiff {
+    pub changes: Vec<FieldChange>,
+}
+
+impl ChainspecDiff {
+    /// Computes the differences going from `a` to `b`.
+    pub fn between(a: &Chainspec, b: &Chainspec) -> Result<Self, serde_json::Error> {
+        let mut diff = ChainspecDiff::default();
+        diff_values("", &a.to_json()?, &b.to_json()?, &mut diff.changes);
+        Ok(diff)
+    }
+
+    /// Returns `true` if both chainspecs are identical.
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+}
+
+fn join_path(prefix: &str, segment: &str) -> String {
+    if prefix.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{prefix}.{segment}")
+    }
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            let keys: BTreeSet<&String> = old_map.keys().chain(new_map.keys()).collect();
+            for key in keys {
+                let field_path = join_path(path, key);
+                match (old_map.get(key), new_map.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&field_path, old, new, changes),
+                    (old, new) => changes.push(FieldChange {
+                        path: field_path,
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for index in 0..old_items.len().max(new_items.len()) {
+                let item_path = format!("{path}[{index}]");
+                match (old_items.get(index), new_items.get(index)) {
+                    (Some(old), Some(new)) => diff_values(&item_path, old, new, changes),
+                    (old, new) => changes.push(FieldChange {
+                        path: item_path,
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    }),
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(FieldChange {
+            path: path.to_string(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reports_changed_added_and_removed_fields() {
+        let old = json!({ "core": { "era_duration": "120min", "slots": 100 }, "list": [1, 2] });
+        let new = json!({ "core": { "era_duration": "60min", "extra": true }, "list": [1] });
+
+        let mut changes = vec![];
+        diff_values("", &old, &new, &mut changes);
+
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    path: "core.era_duration".to_string(),
+                    old: Some(json!("120min")),
+                    new: Some(json!("60min")),
+                },
+                FieldChange {
+                    path: "core.extra".to_string(),
+                    old: None,
+                    new: Some(json!(true)),
+                },
+                FieldChange {
+                    path: "core.slots".to_string(),
+                    old: Some(json!(100)),
+                    new: None,
+                },
+                FieldChange {
+                    path: "list[1]".to_string(),
+                    old: Some(json!(2)),
+                    new: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_chainspecs_have_no_changes() {
+        let chainspec = Chainspec::from_path("examples").expect("example chainspec should load");
+        let diff = ChainspecDiff::between(&chainspec, &chainspec).unwrap();
+        assert!(diff.is_empty());
+    }
+}
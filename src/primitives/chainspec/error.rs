@@ -1,4 +1,10 @@
+use std::io;
+use std::path::PathBuf;
+
 use casper_types::file_utils::ReadFileError;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use miette::Diagnostic;
 use thiserror::Error;
 use uint::FromDecStrErr;
 
@@ -26,6 +32,8 @@ pub enum Error {
     LoadGlobalStateUpgrade(#[from] GlobalStateUpdateLoadError),
 }
 
+impl Diagnostic for Error {}
+
 /// Error loading chainspec accounts file.
 #[derive(Debug, Error)]
 pub enum ChainspecAccountsLoadError {
@@ -50,6 +58,8 @@ pub enum ChainspecAccountsLoadError {
     Crypto(#[from] casper_types::crypto::ErrorExt),
 }
 
+impl Diagnostic for ChainspecAccountsLoadError {}
+
 /// Error loading global state update file.
 #[derive(Debug, Error)]
 pub enum GlobalStateUpdateLoadError {
@@ -61,11 +71,99 @@ pub enum GlobalStateUpdateLoadError {
     #[error("decoding from TOML error: {0}")]
     DecodingFromToml(#[from] toml::de::Error),
 
-    /// Error while decoding a serialized value from a base64 encoded string.
-    #[error("decoding from base64 error: {0}")]
-    DecodingFromBase64(#[from] base64::DecodeError),
+    /// Error while decoding an entry value from base64.
+    #[error("value of {key} is not base64: {error}")]
+    DecodingValue {
+        key: String,
+        error: base64::DecodeError,
+    },
+
+    /// Error opening the file to read it entry by entry.
+    #[error("could not open {}: {1}", .0.display())]
+    OpenFile(PathBuf, io::Error),
+
+    /// Error reading the next line of the file.
+    #[error("could not read the file: {0}")]
+    ReadLine(io::Error),
+
+    /// Error while decoding part of the file from TOML format.
+    #[error("decoding from TOML error at line {line}: {message}")]
+    DecodingLine { line: usize, message: String },
+
+    /// A table other than `[[entries]]` or `[[validators]]`.
+    #[error("unexpected table {0}")]
+    UnexpectedTable(String),
+
+    /// An entry value larger than allowed.
+    #[error("value of entry {entry} is {len} bytes, more than allowed")]
+    ValueTooLarge { entry: usize, len: usize },
+
+    /// A file larger than allowed, refused before reading it.
+    #[error("file is {len} bytes, more than the {max} allowed")]
+    FileTooLarge { len: u64, max: u64 },
+
+    /// More entries than allowed.
+    #[error("file has {count} entries, more than the {max} allowed")]
+    TooManyEntries { count: usize, max: usize },
+
+    /// Arrays or inline tables nested deeper than allowed, refused before
+    /// parsing the file.
+    #[error("arrays or tables at line {line} are nested more than {max} deep")]
+    NestedTooDeep { line: usize, max: usize },
+}
+
+impl Diagnostic for GlobalStateUpdateLoadError {}
+
+/// Error loading a directory of chainspecs, one per protocol version.
+#[derive(Debug, Error)]
+pub enum UpgradesError {
+    /// Error listing the directory.
+    #[error("could not read {}: {1}", .0.display())]
+    ReadDir(PathBuf, io::Error),
+
+    /// No directory named after a protocol version.
+    #[error("no protocol version directories in {}", .0.display())]
+    Empty(PathBuf),
+
+    /// Error loading the chainspec of a version.
+    #[error("could not load the chainspec in {}: {1}", .0.display())]
+    Load(PathBuf, Error),
+
+    /// A chainspec for another version than its directory is named after.
+    #[error("chainspec in {} is for version {version}", dir.display())]
+    VersionMismatch {
+        dir: PathBuf,
+        version: ProtocolVersion,
+    },
+
+    /// Two directories named after the same version, like `1_5_3` and
+    /// `1.5.3`.
+    #[error("{} and {} are both for version {version}", first.display(), second.display())]
+    DuplicateVersion {
+        first: PathBuf,
+        second: PathBuf,
+        version: ProtocolVersion,
+    },
+
+    /// A version activating no later than the version before.
+    #[error("chainspec in {} activates in era {era_id}, not after era {previous}", dir.display())]
+    ActivatesTooEarly {
+        dir: PathBuf,
+        era_id: EraId,
+        previous: EraId,
+    },
+}
+
+impl Diagnostic for UpgradesError {}
+
+/// Error writing a global state update file.
+#[derive(Debug, Error)]
+pub enum GlobalStateUpdateWriteError {
+    /// Error while encoding the global state update to TOML format.
+    #[error("encoding to TOML error: {0}")]
+    EncodingToToml(#[from] toml::ser::Error),
 
-    /// Error while decoding a key from formatted string.
-    #[error("decoding from formatted string error: {0}")]
-    DecodingKeyFromStr(String),
+    /// Error writing the file.
+    #[error("could not write the file: {0}")]
+    WriteFile(#[from] io::Error),
 }
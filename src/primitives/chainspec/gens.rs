@@ -0,0 +1,127 @@
+//! Proptest strategies for the chainspec types.
+//!
+//! Every type with a `ToBytes`/`FromBytes` or TOML representation should have
+//! a strategy here, so its round-trip tests cover the whole value space rather
+//! than a handful of hand-picked values.
+
+use std::collections::BTreeMap;
+
+use casper_types::crypto::gens::public_key_arb_no_system;
+use casper_types::gens::key_arb;
+use casper_types::gens::protocol_version_arb;
+use casper_types::gens::u512_arb;
+use casper_types::EraId;
+use casper_types::Motes;
+use casper_types::PublicKey;
+use casper_types::Timestamp;
+use casper_types::U512;
+use proptest::collection::btree_map;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+use super::accounts_config::AccountConfig;
+use super::accounts_config::AccountsConfig;
+use super::accounts_config::AdministratorAccount;
+use super::accounts_config::DelegatorConfig;
+use super::accounts_config::ValidatorConfig;
+use super::activation_point::ActivationPoint;
+use super::global_state_update::GlobalStateUpdate;
+use super::protocol_config::ProtocolConfig;
+
+/// Upper bound on the number of elements in generated collections.
+const MAX_LEN: usize = 8;
+
+pub fn motes_arb() -> impl Strategy<Value = Motes> { u512_arb().prop_map(Motes::new) }
+
+/// A post-upgrade validator set, mapping public keys to their weights.
+pub fn validators_arb() -> impl Strategy<Value = BTreeMap<PublicKey, U512>> {
+    btree_map(public_key_arb_no_system(), u512_arb(), 0..MAX_LEN)
+}
+
+pub fn global_state_update_arb() -> impl Strategy<Value = GlobalStateUpdate> {
+    (
+        option::of(validators_arb()),
+        btree_map(key_arb(), vec(any::<u8>(), 0..64), 0..MAX_LEN),
+    )
+        .prop_map(|(validators, entries)| GlobalStateUpdate {
+            validators,
+            entries: entries.into_iter().map(|(key, value)| (key, value.into())).collect(),
+        })
+}
+
+pub fn activation_point_arb() -> impl Strategy<Value = ActivationPoint> {
+    prop_oneof![
+        // TOML integers are signed 64-bit.
+        (0..=i64::MAX as u64).prop_map(|era| ActivationPoint::EraId(EraId::from(era))),
+        // Timestamps print with millisecond precision, and only years up to
+        // 9999 parse back.
+        (0..253_402_300_799_999u64)
+            .prop_map(|millis| ActivationPoint::Genesis(Timestamp::from(millis))),
+    ]
+}
+
+pub fn protocol_config_arb() -> impl Strategy<Value = ProtocolConfig> {
+    (
+        protocol_version_arb(),
+        any::<bool>(),
+        activation_point_arb(),
+        option::of(global_state_update_arb()),
+    )
+        .prop_map(
+            |(version, hard_reset, activation_point, global_state_update)| ProtocolConfig {
+                version,
+                hard_reset,
+                activation_point,
+                global_state_update,
+            },
+        )
+}
+
+pub fn validator_config_arb() -> impl Strategy<Value = ValidatorConfig> {
+    (motes_arb(), any::<u8>())
+        .prop_map(|(bonded_amount, rate)| ValidatorConfig::new(bonded_amount, rate))
+}
+
+pub fn account_config_arb() -> impl Strategy<Value = AccountConfig> {
+    (
+        public_key_arb_no_system(),
+        motes_arb(),
+        option::of(validator_config_arb()),
+    )
+        .prop_map(|(public_key, balance, validator)| {
+            AccountConfig::new(public_key, balance, validator)
+        })
+}
+
+pub fn delegator_config_arb() -> impl Strategy<Value = DelegatorConfig> {
+    (
+        public_key_arb_no_system(),
+        public_key_arb_no_system(),
+        motes_arb(),
+        motes_arb(),
+    )
+        .prop_map(|(validator, delegator, balance, delegated_amount)| {
+            DelegatorConfig::new(validator, delegator, balance, delegated_amount)
+        })
+}
+
+pub fn administrator_account_arb() -> impl Strategy<Value = AdministratorAccount> {
+    (public_key_arb_no_system(), motes_arb())
+        .prop_map(|(public_key, balance)| AdministratorAccount::new(public_key, balance))
+}
+
+/// Accounts as `accounts.toml` holds them: every list sorted.
+pub fn accounts_config_arb() -> impl Strategy<Value = AccountsConfig> {
+    (
+        vec(account_config_arb(), 0..MAX_LEN),
+        vec(delegator_config_arb(), 0..MAX_LEN),
+        vec(administrator_account_arb(), 0..MAX_LEN),
+    )
+        .prop_map(|(mut accounts, mut delegators, mut administrators)| {
+            accounts.sort_unstable();
+            delegators.sort_unstable();
+            administrators.sort_unstable();
+            AccountsConfig::new(accounts, delegators, administrators)
+        })
+}
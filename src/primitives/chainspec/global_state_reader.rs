@@ -0,0 +1,293 @@
+//! Reading a `global_state.toml` one entry at a time.
+//!
+//! Upgrade files can carry tens of thousands of entries. Rather than parsing
+//! the whole file into a [`GlobalStateUpdateConfig`], [`GlobalStateReader`]
+//! splits it ahead of its table headers and parses one `[[entries]]` table at
+//! a time, so only the entry at hand is held in memory.
+//!
+//! Validators are few and are kept as they are read. Entries given inline as
+//! `entries = [...]` ahead of the first table are read in one go.
+//!
+//! The entry and validator types default to the checked ones of
+//! [`GlobalStateUpdateConfig`](super::global_state_update::GlobalStateUpdateConfig);
+//! linting reads them as plain strings instead.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Lines;
+use std::path::Path;
+
+use casper_types::bytesrepr::Bytes;
+use casper_types::Key;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::error::GlobalStateUpdateLoadError;
+use super::global_state_update::GlobalStateUpdateEntry;
+use super::global_state_update::GlobalStateUpdateValidatorInfo;
+use super::global_state_update::GLOBAL_STATE_UPDATE_FILENAME;
+
+/// A part of a `global_state.toml`: either the keys ahead of the first
+/// table, or a single table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Part<E, V> {
+    validators: Option<Vec<V>>,
+    #[serde(default = "Vec::new")]
+    entries: Vec<E>,
+}
+
+/// Iterates over the entries of a `global_state.toml`, parsing one at a time.
+pub struct GlobalStateReader<R, E = GlobalStateUpdateEntry, V = GlobalStateUpdateValidatorInfo> {
+    lines: Lines<R>,
+    /// Lines of the current part, starting with its table header.
+    chunk: String,
+    /// Number of lines read so far.
+    line: usize,
+    /// Number of lines ahead of the current part.
+    offset: usize,
+    /// Entries read but not yet returned, only ever the inline ones.
+    pending: VecDeque<E>,
+    validators: Option<Vec<V>>,
+    max_value_len: Option<usize>,
+    done: bool,
+}
+
+impl GlobalStateReader<BufReader<File>> {
+    /// Opens `global_state.toml` in the given directory.
+    ///
+    /// If the file doesn't exist, returns `Ok(None)`.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        Self::open(path)
+    }
+}
+
+impl<E: DeserializeOwned, V: DeserializeOwned> GlobalStateReader<BufReader<File>, E, V> {
+    pub(super) fn open<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        let update_path = path.as_ref().join(GLOBAL_STATE_UPDATE_FILENAME);
+        if !update_path.is_file() {
+            return Ok(None);
+        }
+        let file = File::open(&update_path)
+            .map_err(|error| GlobalStateUpdateLoadError::OpenFile(update_path, error))?;
+        Ok(Some(Self::from_reader(BufReader::new(file))))
+    }
+}
+
+impl<R: BufRead> GlobalStateReader<R> {
+    pub fn new(reader: R) -> Self { Self::from_reader(reader) }
+
+    /// Fails on entries whose value decodes to more than `max_value_len`
+    /// bytes, before decoding them.
+    pub fn with_max_value_len(mut self, max_value_len: usize) -> Self {
+        self.max_value_len = Some(max_value_len);
+        self
+    }
+
+    /// Decodes every entry into its key and serialized value as it is read.
+    pub fn decoded(self) -> impl Iterator<Item = Result<(Key, Bytes), GlobalStateUpdateLoadError>> {
+        let max_value_len = self.max_value_len;
+        self.enumerate().map(move |(index, entry)| {
+            let entry = entry?;
+            if let Some(max_value_len) = max_value_len {
+                let len = entry.value_len();
+                if len > max_value_len {
+                    return Err(GlobalStateUpdateLoadError::ValueTooLarge { entry: index, len });
+                }
+            }
+            entry.decode()
+        })
+    }
+}
+
+impl<R: BufRead, E: DeserializeOwned, V: DeserializeOwned> GlobalStateReader<R, E, V> {
+    pub(super) fn from_reader(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            chunk: String::new(),
+            line: 0,
+            offset: 0,
+            pending: VecDeque::new(),
+            validators: None,
+            max_value_len: None,
+            done: false,
+        }
+    }
+
+    /// The validators read so far. Only complete once every entry was read,
+    /// as validator tables may follow the entries.
+    pub fn validators(&self) -> Option<&[V]> { self.validators.as_deref() }
+
+    /// Parses the part read so far.
+    fn finish_part(&mut self) -> Result<(), GlobalStateUpdateLoadError> {
+        let part: Part<E, V> = parse(&std::mem::take(&mut self.chunk), self.offset)?;
+        self.pending.extend(part.entries);
+        if let Some(validators) = part.validators {
+            self.validators.get_or_insert_with(Vec::new).extend(validators);
+        }
+        Ok(())
+    }
+
+    /// Reads lines up to the next table header and parses them.
+    fn read_part(&mut self) -> Result<(), GlobalStateUpdateLoadError> {
+        loop {
+            let line = match self.lines.next() {
+                Some(line) => line.map_err(GlobalStateUpdateLoadError::ReadLine)?,
+                None => {
+                    self.done = true;
+                    return self.finish_part();
+                }
+            };
+            self.line += 1;
+
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                if !matches!(table_name(trimmed), Some("entries" | "validators")) {
+                    return Err(GlobalStateUpdateLoadError::UnexpectedTable(
+                        trimmed.to_string(),
+                    ));
+                }
+                let finished = self.finish_part();
+                self.offset = self.line - 1;
+                self.chunk.push_str(&line);
+                self.chunk.push('\n');
+                return finished;
+            }
+            self.chunk.push_str(&line);
+            self.chunk.push('\n');
+        }
+    }
+}
+
+impl<R: BufRead, E: DeserializeOwned, V: DeserializeOwned> Iterator for GlobalStateReader<R, E, V> {
+    type Item = Result<E, GlobalStateUpdateLoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                return Some(Ok(entry));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(error) = self.read_part() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// Parses a part following `offset` lines of the file. TOML reports lines
+/// relative to the part, so errors are moved to the line of the file.
+fn parse<T: DeserializeOwned>(chunk: &str, offset: usize) -> Result<T, GlobalStateUpdateLoadError> {
+    toml::from_str(chunk).map_err(|error| {
+        let mut message = error.to_string();
+        let line = match error.line_col() {
+            Some((line, column)) => {
+                let suffix = format!(" at line {} column {}", line + 1, column + 1);
+                if message.ends_with(&suffix) {
+                    message.truncate(message.len() - suffix.len());
+                }
+                offset + line + 1
+            }
+            None => offset + 1,
+        };
+        GlobalStateUpdateLoadError::DecodingLine { line, message }
+    })
+}
+
+/// The name of an array of tables header like `[[entries]]`, ignoring any
+/// trailing comment.
+fn table_name(header: &str) -> Option<&str> {
+    let name = header.strip_prefix("[[")?;
+    let (name, rest) = name.split_once("]]")?;
+    let rest = rest.trim();
+    (rest.is_empty() || rest.starts_with('#')).then(|| name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use casper_types::bytesrepr::ToBytes;
+    use casper_types::CLValue;
+    use casper_types::PublicKey;
+    use casper_types::SecretKey;
+    use casper_types::StoredValue;
+    use casper_types::U512;
+
+    use super::*;
+    use crate::primitives::chainspec::global_state_update::GlobalStateUpdateConfig;
+    use crate::primitives::GlobalStateUpdate;
+
+    fn update(entries: u8) -> GlobalStateUpdate {
+        let value = StoredValue::CLValue(CLValue::from_t(42u64).unwrap()).to_bytes().unwrap();
+        let public_key = PublicKey::from(&SecretKey::ed25519_from_bytes([1; 32]).unwrap());
+        let mut builder = GlobalStateUpdate::builder().validator(public_key, U512::from(100));
+        for seed in 0..entries {
+            builder = builder.entry(Key::Hash([seed; 32]), value.clone());
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn reads_the_entries_one_table_at_a_time() {
+        let update = update(20);
+        let toml = update.to_toml_string().unwrap();
+
+        let mut reader = GlobalStateReader::new(Cursor::new(toml.clone()));
+        let entries: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+
+        let config: GlobalStateUpdateConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(entries, config.entries);
+        assert_eq!(reader.validators(), config.validators.as_deref());
+
+        let decoded: Vec<_> = GlobalStateReader::new(Cursor::new(toml))
+            .decoded()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, update.entries.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reads_inline_entries_and_comments() {
+        let toml = r#"# upgrade
+entries = [{ key = "hash-0101010101010101010101010101010101010101010101010101010101010101", value = "AQ==" }]
+
+[[ entries ]] # second
+key = "hash-0202020202020202020202020202020202020202020202020202020202020202"
+value = "Ag=="
+"#;
+
+        let keys: Vec<_> = GlobalStateReader::new(Cursor::new(toml))
+            .decoded()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(keys, vec![Key::Hash([1; 32]), Key::Hash([2; 32])]);
+    }
+
+    #[test]
+    fn refuses_values_over_the_limit_and_unknown_tables() {
+        let toml = update(1).to_toml_string().unwrap();
+        let mut decoded = GlobalStateReader::new(Cursor::new(toml)).with_max_value_len(4).decoded();
+        assert!(matches!(
+            decoded.next(),
+            Some(Err(GlobalStateUpdateLoadError::ValueTooLarge {
+                entry: 0,
+                ..
+            }))
+        ));
+
+        let mut reader = GlobalStateReader::new(Cursor::new("[other]\nkey = 1\n"));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(GlobalStateUpdateLoadError::UnexpectedTable(_)))
+        ));
+        assert!(reader.next().is_none());
+    }
+}
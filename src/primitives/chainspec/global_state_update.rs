@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::fmt::Display;
+use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 use casper_types::bytesrepr::Bytes;
 use casper_types::bytesrepr::FromBytes;
@@ -10,51 +13,317 @@ use casper_types::file_utils;
 use casper_types::AsymmetricType;
 use casper_types::Key;
 use casper_types::PublicKey;
+use casper_types::StoredValue;
 use casper_types::U512;
 use datasize::DataSize;
+use rayon::prelude::*;
+use serde::de;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
+use serde_json::json;
+use serde_json::Value;
 
 use super::error::GlobalStateUpdateLoadError;
+use super::error::GlobalStateUpdateWriteError;
 
-const GLOBAL_STATE_UPDATE_FILENAME: &str = "global_state.toml";
+pub(super) const GLOBAL_STATE_UPDATE_FILENAME: &str = "global_state.toml";
+
+/// Default size of a `global_state.toml` beyond which it is not read.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 512 << 20;
+
+/// Default number of entries an update may carry.
+pub const DEFAULT_MAX_ENTRIES: usize = 1_000_000;
+
+/// Default size of a single decoded entry value.
+pub const DEFAULT_MAX_VALUE_LEN: usize = 16 << 20;
+
+/// Default depth arrays and inline tables may be nested to. Inline entries,
+/// `entries = [{ .. }]`, take two.
+pub const DEFAULT_MAX_NESTING: usize = 8;
+
+/// Bounds a `global_state.toml` has to stay within to be loaded, so a
+/// corrupted or malicious upgrade file cannot exhaust memory.
+///
+/// The file size and nesting are checked before parsing the file, the rest
+/// before decoding any value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlobalStateUpdateLimits {
+    pub max_file_size: u64,
+    pub max_entries: usize,
+    pub max_value_len: usize,
+    pub max_nesting: usize,
+}
+
+impl Default for GlobalStateUpdateLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+            max_nesting: DEFAULT_MAX_NESTING,
+        }
+    }
+}
+
+/// A validator public key, written as hex.
+#[derive(PartialEq, Eq, DataSize, Debug, Clone)]
+pub struct HexPublicKey(pub PublicKey);
+
+/// A validator weight, written as a decimal string.
+#[derive(PartialEq, Eq, DataSize, Debug, Clone, Copy)]
+pub struct DecWeight(pub U512);
+
+/// A global state key, written in its formatted form like `hash-00..`.
+#[derive(PartialEq, Eq, DataSize, Debug, Clone, Copy)]
+pub struct FormattedKey(pub Key);
+
+/// Deserializes a string and parses it with `parse`, naming `what` was
+/// expected if it fails. TOML adds the key and line to the error.
+fn parse_str<'de, D, T, E>(
+    deserializer: D,
+    what: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    E: Display,
+{
+    let string = String::deserialize(deserializer)?;
+    parse(&string).map_err(|error| de::Error::custom(format!("invalid {what} {string:?}: {error}")))
+}
+
+impl Serialize for HexPublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for HexPublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_str(deserializer, "public key", |hex| PublicKey::from_hex(hex)).map(HexPublicKey)
+    }
+}
+
+impl Serialize for DecWeight {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for DecWeight {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_str(deserializer, "weight", |weight| {
+            U512::from_dec_str(weight).map_err(|error| format!("{error:?}"))
+        })
+        .map(DecWeight)
+    }
+}
+
+impl FromStr for DecWeight {
+    type Err = String;
+
+    fn from_str(weight: &str) -> Result<Self, Self::Err> {
+        U512::from_dec_str(weight)
+            .map(DecWeight)
+            .map_err(|error| format!("invalid weight {weight:?}: {error:?}"))
+    }
+}
+
+impl Serialize for FormattedKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_formatted_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for FormattedKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_str(deserializer, "key", Key::from_formatted_str).map(FormattedKey)
+    }
+}
 
 #[derive(PartialEq, Eq, Serialize, Deserialize, DataSize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct GlobalStateUpdateEntry {
-    key: String,
-    value: String,
+    pub(super) key: FormattedKey,
+    pub(super) value: String,
+}
+
+impl GlobalStateUpdateEntry {
+    /// The key the entry writes to.
+    pub fn key(&self) -> Key { self.key.0 }
+
+    /// The base64 encoded serialized value.
+    pub fn value(&self) -> &str { &self.value }
+
+    /// Length of the serialized value, without decoding it.
+    pub fn value_len(&self) -> usize {
+        // Base64 takes four characters for every three bytes, padding the
+        // last ones with `=`.
+        let padding = self.value.bytes().rev().take_while(|byte| *byte == b'=').count();
+        (self.value.len() / 4 * 3).saturating_sub(padding)
+    }
+
+    /// Decodes the serialized value.
+    pub fn decode(&self) -> Result<(Key, Bytes), GlobalStateUpdateLoadError> {
+        let value = base64::decode(&self.value).map_err(|error| {
+            GlobalStateUpdateLoadError::DecodingValue {
+                key: self.key.0.to_formatted_string(),
+                error,
+            }
+        })?;
+        Ok((self.key.0, value.into()))
+    }
 }
 
 #[derive(PartialEq, Eq, Serialize, Deserialize, DataSize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct GlobalStateUpdateValidatorInfo {
-    public_key: String,
-    weight: String,
+    pub(super) public_key: HexPublicKey,
+    pub(super) weight: DecWeight,
 }
 
 #[derive(PartialEq, Eq, Serialize, Deserialize, DataSize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct GlobalStateUpdateConfig {
-    validators: Option<Vec<GlobalStateUpdateValidatorInfo>>,
-    entries: Vec<GlobalStateUpdateEntry>,
+    pub(super) validators: Option<Vec<GlobalStateUpdateValidatorInfo>>,
+    pub(super) entries: Vec<GlobalStateUpdateEntry>,
 }
 
 impl GlobalStateUpdateConfig {
-    /// Returns `Self` and the raw bytes of the file.
+    /// Returns `Self` and the raw bytes of the file, within the default
+    /// [`GlobalStateUpdateLimits`].
     ///
     /// If the file doesn't exist, returns `Ok(None)`.
     pub(super) fn from_dir<P: AsRef<Path>>(
         path: P,
+    ) -> Result<Option<(Self, Bytes)>, GlobalStateUpdateLoadError> {
+        Self::from_dir_with_limits(path, &GlobalStateUpdateLimits::default())
+    }
+
+    /// Like [`GlobalStateUpdateConfig::from_dir`], within `limits`.
+    pub(super) fn from_dir_with_limits<P: AsRef<Path>>(
+        path: P,
+        limits: &GlobalStateUpdateLimits,
     ) -> Result<Option<(Self, Bytes)>, GlobalStateUpdateLoadError> {
         let update_path = path.as_ref().join(GLOBAL_STATE_UPDATE_FILENAME);
         if !update_path.is_file() {
             return Ok(None);
         }
+        let len = fs::metadata(&update_path)
+            .map_err(|error| GlobalStateUpdateLoadError::OpenFile(update_path.clone(), error))?
+            .len();
+        if len > limits.max_file_size {
+            return Err(GlobalStateUpdateLoadError::FileTooLarge {
+                len,
+                max: limits.max_file_size,
+            });
+        }
         let bytes = file_utils::read_file(update_path)?;
+        check_nesting(&bytes, limits.max_nesting)?;
         let config: GlobalStateUpdateConfig = toml::from_slice(&bytes)?;
+        config.check_limits(limits)?;
         Ok(Some((config, Bytes::from(bytes))))
     }
+
+    /// Checks the number of entries and the length of their values.
+    fn check_limits(
+        &self,
+        limits: &GlobalStateUpdateLimits,
+    ) -> Result<(), GlobalStateUpdateLoadError> {
+        if self.entries.len() > limits.max_entries {
+            return Err(GlobalStateUpdateLoadError::TooManyEntries {
+                count: self.entries.len(),
+                max: limits.max_entries,
+            });
+        }
+        for (index, entry) in self.entries.iter().enumerate() {
+            let len = entry.value_len();
+            if len > limits.max_value_len {
+                return Err(GlobalStateUpdateLoadError::ValueTooLarge { entry: index, len });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that no array or inline table in `toml` is nested deeper than
+/// `max`, as the TOML parser recurses into every level.
+fn check_nesting(toml: &[u8], max: usize) -> Result<(), GlobalStateUpdateLoadError> {
+    let mut depth = 0usize;
+    let mut line = 1;
+    let mut i = 0;
+    while i < toml.len() {
+        match toml[i] {
+            b'\n' => line += 1,
+            b'#' => {
+                while i < toml.len() && toml[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            quote @ (b'"' | b'\'') => {
+                // Brackets in strings do not count, whether on one line or
+                // several.
+                let delimiter = if toml[i..].starts_with(&[quote; 3]) {
+                    &toml[i..i + 3]
+                } else {
+                    &toml[i..i + 1]
+                };
+                i += delimiter.len();
+                while i < toml.len() && !toml[i..].starts_with(delimiter) {
+                    if quote == b'"' && toml[i] == b'\\' {
+                        i += 1;
+                    }
+                    if toml.get(i) == Some(&b'\n') {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += delimiter.len();
+                continue;
+            }
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max {
+                    return Err(GlobalStateUpdateLoadError::NestedTooDeep { line, max });
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+impl From<&GlobalStateUpdate> for GlobalStateUpdateConfig {
+    fn from(update: &GlobalStateUpdate) -> Self {
+        let validators = update.validators.as_ref().map(|validators| {
+            validators
+                .iter()
+                .map(|(public_key, weight)| GlobalStateUpdateValidatorInfo {
+                    public_key: HexPublicKey(public_key.clone()),
+                    weight: DecWeight(*weight),
+                })
+                .collect()
+        });
+
+        let entries = update
+            .entries
+            .iter()
+            .map(|(key, value)| GlobalStateUpdateEntry {
+                key: FormattedKey(*key),
+                value: base64::encode(value.as_slice()),
+            })
+            .collect();
+
+        GlobalStateUpdateConfig {
+            validators,
+            entries,
+        }
+    }
 }
 
 /// Type storing the information about modifications to be applied to the global
@@ -69,6 +338,185 @@ pub struct GlobalStateUpdate {
     pub(crate) entries: BTreeMap<Key, Bytes>,
 }
 
+impl GlobalStateUpdate {
+    /// Returns a builder for assembling an update programmatically.
+    pub fn builder() -> GlobalStateUpdateBuilder { GlobalStateUpdateBuilder::default() }
+
+    /// Encodes `self` in the `global_state.toml` format.
+    pub fn to_toml_string(&self) -> Result<String, GlobalStateUpdateWriteError> {
+        // Going through `toml::Value` writes `entries = []` ahead of the
+        // `[[validators]]` tables; serializing the config directly fails with
+        // `ValueAfterTable` when there are validators but no entries.
+        let value = toml::Value::try_from(GlobalStateUpdateConfig::from(self))?;
+        Ok(toml::to_string_pretty(&value)?)
+    }
+
+    /// Writes `self` as `global_state.toml` into the given directory.
+    pub fn write_to_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), GlobalStateUpdateWriteError> {
+        let toml = self.to_toml_string()?;
+        fs::write(path.as_ref().join(GLOBAL_STATE_UPDATE_FILENAME), toml)?;
+        Ok(())
+    }
+
+    /// Loads `global_state.toml` from the given directory, within the
+    /// default [`GlobalStateUpdateLimits`].
+    ///
+    /// If the file doesn't exist, returns `Ok(None)`.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        Self::from_dir_with_limits(path, &GlobalStateUpdateLimits::default())
+    }
+
+    /// Like [`GlobalStateUpdate::from_dir`], within `limits`.
+    pub fn from_dir_with_limits<P: AsRef<Path>>(
+        path: P,
+        limits: &GlobalStateUpdateLimits,
+    ) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        GlobalStateUpdateConfig::from_dir_with_limits(path, limits)?
+            .map(|(config, _bytes)| GlobalStateUpdate::try_from(config))
+            .transpose()
+    }
+
+    /// Returns every entry with its value decoded where possible.
+    pub fn decoded_entries(&self) -> Vec<DecodedEntry> {
+        self.entries
+            .iter()
+            .map(|(key, bytes)| DecodedEntry {
+                key: key.to_formatted_string(),
+                value: DecodedValue::from_bytes(bytes.as_slice()),
+            })
+            .collect()
+    }
+
+    /// Returns a JSON representation with every entry decoded into its
+    /// `StoredValue` where possible.
+    ///
+    /// Entries which fail to decode are kept as base64 alongside the decoding
+    /// error, so the output always covers the full update.
+    pub fn to_decoded_json(&self) -> Value {
+        let validators = self.validators.as_ref().map(|validators| {
+            validators
+                .iter()
+                .map(|(public_key, weight)| (public_key.to_hex(), json!(weight.to_string())))
+                .collect::<serde_json::Map<_, _>>()
+        });
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|(key, bytes)| (key.to_formatted_string(), decoded_entry_json(bytes)))
+            .collect::<serde_json::Map<_, _>>();
+
+        json!({
+            "validators": validators,
+            "entries": entries,
+        })
+    }
+}
+
+/// Builder for [`GlobalStateUpdate`].
+///
+/// Adding a validator switches the update into "replace the validator set"
+/// mode; an update built without any validators leaves the set untouched.
+#[derive(Clone, Debug, Default)]
+pub struct GlobalStateUpdateBuilder {
+    validators: Option<BTreeMap<PublicKey, U512>>,
+    entries: BTreeMap<Key, Bytes>,
+}
+
+impl GlobalStateUpdateBuilder {
+    /// Adds a post-upgrade validator with the given weight.
+    pub fn validator(mut self, public_key: PublicKey, weight: U512) -> Self {
+        let _ = self.validators.get_or_insert_with(BTreeMap::new).insert(public_key, weight);
+        self
+    }
+
+    /// Adds a serialized `StoredValue` to be written under `key`.
+    pub fn entry<B: Into<Bytes>>(mut self, key: Key, value: B) -> Self {
+        let _ = self.entries.insert(key, value.into());
+        self
+    }
+
+    pub fn build(self) -> GlobalStateUpdate {
+        GlobalStateUpdate {
+            validators: self.validators,
+            entries: self.entries,
+        }
+    }
+}
+
+/// Attempts to decode the serialized value of a global state entry.
+pub fn decode_stored_value(bytes: &[u8]) -> Result<StoredValue, bytesrepr::Error> {
+    bytesrepr::deserialize_from_slice(bytes)
+}
+
+/// Converts a `StoredValue` into human-readable JSON.
+///
+/// `StoredValue`'s own `Serialize` impl emits raw bytesrepr, so we serialize
+/// the wrapped value instead.
+pub fn stored_value_to_json(value: &StoredValue) -> Result<Value, serde_json::Error> {
+    match value {
+        StoredValue::CLValue(inner) => serde_json::to_value(inner),
+        StoredValue::Account(inner) => serde_json::to_value(inner),
+        StoredValue::ContractWasm(inner) => serde_json::to_value(inner),
+        StoredValue::Contract(inner) => serde_json::to_value(inner),
+        StoredValue::ContractPackage(inner) => serde_json::to_value(inner),
+        StoredValue::Transfer(inner) => serde_json::to_value(inner),
+        StoredValue::DeployInfo(inner) => serde_json::to_value(inner),
+        StoredValue::EraInfo(inner) => serde_json::to_value(inner),
+        StoredValue::Bid(inner) => serde_json::to_value(inner),
+        StoredValue::Withdraw(inner) => serde_json::to_value(inner),
+        StoredValue::Unbonding(inner) => serde_json::to_value(inner),
+    }
+}
+
+/// A global state entry value, decoded into a `StoredValue` where possible.
+#[derive(Clone, PartialEq, Eq, Serialize, Debug)]
+#[serde(untagged)]
+pub enum DecodedValue {
+    /// The bytes decoded into a `StoredValue` of the given type.
+    Decoded {
+        #[serde(rename = "type")]
+        type_name: String,
+        value: Value,
+    },
+    /// The bytes could not be decoded; `raw` holds them base64 encoded.
+    Undecodable { raw: String, error: String },
+}
+
+impl DecodedValue {
+    /// Decodes the serialized value of a global state entry.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let decoded =
+            decode_stored_value(bytes).map_err(|error| error.to_string()).and_then(|value| {
+                stored_value_to_json(&value)
+                    .map(|json| (value.type_name(), json))
+                    .map_err(|error| error.to_string())
+            });
+
+        match decoded {
+            Ok((type_name, value)) => DecodedValue::Decoded { type_name, value },
+            Err(error) => DecodedValue::Undecodable {
+                raw: base64::encode(bytes),
+                error,
+            },
+        }
+    }
+
+    pub fn is_decoded(&self) -> bool { matches!(self, DecodedValue::Decoded { .. }) }
+}
+
+/// A global state entry keyed by its formatted `Key`.
+#[derive(Clone, PartialEq, Eq, Serialize, Debug)]
+pub struct DecodedEntry {
+    pub key: String,
+    #[serde(flatten)]
+    pub value: DecodedValue,
+}
+
+fn decoded_entry_json(bytes: &Bytes) -> Value {
+    serde_json::to_value(DecodedValue::from_bytes(bytes.as_slice())).unwrap_or(Value::Null)
+}
+
 impl ToBytes for GlobalStateUpdate {
     fn write_bytes(&self, writer: &mut Vec<u8>) -> Result<(), bytesrepr::Error> {
         self.validators.write_bytes(writer)?;
@@ -102,38 +550,17 @@ impl TryFrom<GlobalStateUpdateConfig> for GlobalStateUpdate {
     type Error = GlobalStateUpdateLoadError;
 
     fn try_from(config: GlobalStateUpdateConfig) -> Result<Self, Self::Error> {
-        let mut validators: Option<BTreeMap<PublicKey, U512>> = None;
-        if let Some(config_validators) = config.validators {
-            let mut new_validators = BTreeMap::new();
-            for (index, validator) in config_validators.into_iter().enumerate() {
-                let public_key = PublicKey::from_hex(&validator.public_key).map_err(|error| {
-                    GlobalStateUpdateLoadError::DecodingKeyFromStr(format!(
-                        "failed to decode validator public key {}: {}",
-                        index, error
-                    ))
-                })?;
-                let weight = U512::from_dec_str(&validator.weight).map_err(|error| {
-                    GlobalStateUpdateLoadError::DecodingKeyFromStr(format!(
-                        "failed to decode validator weight {}: {}",
-                        index, error
-                    ))
-                })?;
-                let _ = new_validators.insert(public_key, weight);
-            }
-            validators = Some(new_validators);
-        }
-
-        let mut entries = BTreeMap::new();
-        for (index, entry) in config.entries.into_iter().enumerate() {
-            let key = Key::from_formatted_str(&entry.key).map_err(|error| {
-                GlobalStateUpdateLoadError::DecodingKeyFromStr(format!(
-                    "failed to decode entry key {}: {}",
-                    index, error
-                ))
-            })?;
-            let value = base64::decode(&entry.value)?.into();
-            let _ = entries.insert(key, value);
-        }
+        let validators = config.validators.map(|validators| {
+            validators
+                .into_iter()
+                .map(|validator| (validator.public_key.0, validator.weight.0))
+                .collect()
+        });
+        // Entries are decoded on all cores, but collected in order, so the
+        // error reported is always the one of the first bad entry.
+        let decoded: Vec<_> =
+            config.entries.par_iter().map(GlobalStateUpdateEntry::decode).collect();
+        let entries = decoded.into_iter().collect::<Result<_, _>>()?;
 
         Ok(GlobalStateUpdate {
             validators,
@@ -141,3 +568,185 @@ impl TryFrom<GlobalStateUpdateConfig> for GlobalStateUpdate {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use casper_types::CLValue;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::primitives::chainspec::gens::global_state_update_arb;
+    use crate::primitives::GlobalStateReader;
+    use crate::testing::public_key;
+
+    fn read_back(dir: &Path) -> GlobalStateUpdate {
+        let (config, _bytes) = GlobalStateUpdateConfig::from_dir(dir).unwrap().unwrap();
+        GlobalStateUpdate::try_from(config).unwrap()
+    }
+
+    #[test]
+    fn builder_roundtrips_through_from_dir() {
+        let value = StoredValue::CLValue(CLValue::from_t(42u64).unwrap());
+        let update = GlobalStateUpdate::builder()
+            .validator(public_key(1), U512::from(100))
+            .validator(public_key(2), U512::from(250))
+            .entry(Key::Hash([7; 32]), value.to_bytes().unwrap())
+            .build();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        update.write_to_dir(dir).unwrap();
+
+        assert_eq!(read_back(dir), update);
+    }
+
+    #[test]
+    fn decodes_valid_entries_and_flags_garbage() {
+        let value = StoredValue::CLValue(CLValue::from_t(String::from("hello")).unwrap());
+        let update = GlobalStateUpdate::builder()
+            .entry(Key::Hash([1; 32]), value.to_bytes().unwrap())
+            .entry(Key::Hash([2; 32]), vec![0xff, 0x00])
+            .build();
+
+        let entries = update.decoded_entries();
+        assert_eq!(entries.len(), 2);
+        match &entries[0].value {
+            DecodedValue::Decoded { type_name, value } => {
+                assert_eq!(type_name, "String");
+                assert_eq!(value["parsed"], "hello");
+            }
+            other => panic!("expected a decoded value, got {other:?}"),
+        }
+        assert!(!entries[1].value.is_decoded());
+    }
+
+    #[test]
+    fn update_without_validators_roundtrips() {
+        let update = GlobalStateUpdate::builder().entry(Key::Hash([3; 32]), vec![1, 2, 3]).build();
+        assert!(update.validators.is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        update.write_to_dir(dir).unwrap();
+
+        assert_eq!(read_back(dir), update);
+    }
+
+    #[test]
+    fn invalid_fields_name_their_key_and_line() {
+        let toml = format!(
+            "[[validators]]\npublic_key = \"{}\"\nweight = \"100\"\n\n[[validators]]\npublic_key \
+             = \"{}\"\nweight = \"lots\"\n",
+            public_key(1).to_hex(),
+            public_key(2).to_hex()
+        );
+        let error = toml::from_str::<GlobalStateUpdateConfig>(&toml).unwrap_err().to_string();
+        assert!(error.starts_with("invalid weight \"lots\""), "{error}");
+        assert!(
+            error.ends_with("for key `validators.weight` at line 5 column 1"),
+            "{error}"
+        );
+
+        // Reading table by table reports the same line.
+        let error = GlobalStateReader::new(Cursor::new(toml)).last().unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            GlobalStateUpdateLoadError::DecodingLine { line: 5, .. }
+        ));
+
+        let toml = "[[entries]]\nkey = \"hash-zz\"\nvalue = \"\"\n";
+        let error = toml::from_str::<GlobalStateUpdateConfig>(toml).unwrap_err().to_string();
+        assert!(error.starts_with("invalid key \"hash-zz\""), "{error}");
+    }
+
+    #[test]
+    fn the_first_undecodable_entry_is_reported() {
+        let entry = |seed: u8, value: &str| GlobalStateUpdateEntry {
+            key: FormattedKey(Key::Hash([seed; 32])),
+            value: value.to_string(),
+        };
+        let mut entries: Vec<_> = (0..1000).map(|seed| entry(seed as u8, "AQ==")).collect();
+        entries[10] = entry(10, "not base64");
+        entries[900] = entry(200, "nor this");
+        let config = GlobalStateUpdateConfig {
+            validators: None,
+            entries,
+        };
+
+        for _ in 0..10 {
+            match GlobalStateUpdate::try_from(config.clone()) {
+                Err(GlobalStateUpdateLoadError::DecodingValue { key, .. }) => {
+                    assert_eq!(key, Key::Hash([10; 32]).to_formatted_string())
+                }
+                other => panic!("expected a decoding error, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn limits_are_checked_before_decoding() {
+        let update = GlobalStateUpdate::builder()
+            .entry(Key::Hash([1; 32]), vec![1; 10])
+            .entry(Key::Hash([2; 32]), vec![2; 100])
+            .build();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        update.write_to_dir(dir).unwrap();
+        let limits = GlobalStateUpdateLimits::default();
+        let load = |limits| GlobalStateUpdate::from_dir_with_limits(dir, &limits);
+
+        assert_eq!(load(limits).unwrap(), Some(update));
+        assert!(matches!(
+            load(GlobalStateUpdateLimits {
+                max_file_size: 64,
+                ..limits
+            }),
+            Err(GlobalStateUpdateLoadError::FileTooLarge { max: 64, .. })
+        ));
+        assert!(matches!(
+            load(GlobalStateUpdateLimits {
+                max_entries: 1,
+                ..limits
+            }),
+            Err(GlobalStateUpdateLoadError::TooManyEntries { count: 2, max: 1 })
+        ));
+        assert!(matches!(
+            load(GlobalStateUpdateLimits {
+                max_value_len: 50,
+                ..limits
+            }),
+            Err(GlobalStateUpdateLoadError::ValueTooLarge { entry: 1, len: 100 })
+        ));
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected_before_parsing() {
+        let toml = "# [[[[[[[[[\nkey = \"[[[[[[[[[\"\nentries = [[[[[[[[[[]]]]]]]]]]\n";
+        assert!(matches!(
+            check_nesting(toml.as_bytes(), DEFAULT_MAX_NESTING),
+            Err(GlobalStateUpdateLoadError::NestedTooDeep {
+                line: 3,
+                max: DEFAULT_MAX_NESTING
+            })
+        ));
+
+        let toml = "s = \"\"\"\n[[[[[[[[[\\\"\"\"\"\nentries = [{ key = \"a\", value = '{{{' }]\n";
+        check_nesting(toml.as_bytes(), 2).unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn bytesrepr_roundtrip(update in global_state_update_arb()) {
+            bytesrepr::test_serialization_roundtrip(&update);
+        }
+
+        #[test]
+        fn toml_roundtrip(update in global_state_update_arb()) {
+            let encoded = update.to_toml_string().unwrap();
+            let config: GlobalStateUpdateConfig = toml::from_str(&encoded).unwrap();
+            prop_assert_eq!(GlobalStateUpdate::try_from(config).unwrap(), update);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Canonical JSON form of a chainspec, for tooling that speaks neither TOML
+//! nor bytesrepr.
+//!
+//! Object keys are sorted, so the same chainspec always yields the same
+//! document. Global state update entries are decoded into their
+//! `StoredValue`s, keeping the ones that fail to decode as base64.
+
+use serde_json::Value;
+
+use crate::primitives::Chainspec;
+
+impl Chainspec {
+    /// Lowers the chainspec into canonical JSON, decoding its global state
+    /// update entries.
+    pub fn to_json(&self) -> Result<Value, serde_json::Error> {
+        // `GlobalStateUpdate` is keyed by `Key`, which has no string form under
+        // serde, so it is taken out and rendered separately.
+        let mut chainspec = self.clone();
+        let global_state_update = chainspec.protocol_config.global_state_update.take();
+
+        let mut value = serde_json::to_value(&chainspec)?;
+        value["protocol"]["global_state_update"] = global_state_update
+            .map(|update| update.to_decoded_json())
+            .unwrap_or(Value::Null);
+
+        sort_keys(&mut value);
+        Ok(value)
+    }
+}
+
+/// Sorts the keys of every object in `value`, which otherwise keep the order
+/// they were serialized in.
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.sort_keys();
+            map.values_mut().for_each(sort_keys);
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::bytesrepr::ToBytes;
+    use casper_types::CLValue;
+    use casper_types::Key;
+    use casper_types::StoredValue;
+
+    use crate::primitives::Chainspec;
+    use crate::primitives::GlobalStateUpdate;
+
+    #[test]
+    fn decodes_the_global_state_update_and_sorts_keys() {
+        let mut chainspec =
+            Chainspec::from_path("examples").expect("example chainspec should load");
+        let value = StoredValue::CLValue(CLValue::from_t(42u64).unwrap());
+        chainspec.protocol_config.global_state_update = Some(
+            GlobalStateUpdate::builder()
+                .entry(Key::Hash([7; 32]), value.to_bytes().unwrap())
+                .build(),
+        );
+
+        let json = chainspec.to_json().unwrap();
+        let entry = &json["protocol"]["global_state_update"]["entries"]
+            [Key::Hash([7; 32]).to_formatted_string()];
+        assert_eq!(entry["type"], "U64");
+        assert_eq!(json["network"]["name"], chainspec.network_config.name);
+
+        let keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        assert_eq!(json, chainspec.to_json().unwrap());
+    }
+}
@@ -0,0 +1,462 @@
+//! Checks of a `global_state.toml` for common protocol-upgrade mistakes.
+//!
+//! Linting works on the file as written rather than on a loaded
+//! [`GlobalStateUpdate`](super::global_state_update::GlobalStateUpdate):
+//! loading keeps the last of two entries for the same key and stops at the
+//! first key or value it cannot decode, which are exactly the mistakes worth
+//! reporting. Keys, public keys and weights are read as plain strings for the
+//! same reason. The file is read an entry at a time through a
+//! [`GlobalStateReader`], so even large updates are linted in little memory.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::BufRead;
+use std::path::Path;
+
+use casper_types::AsymmetricType;
+use casper_types::Key;
+use casper_types::PublicKey;
+use casper_types::StoredValue;
+use casper_types::U512;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+use super::error::GlobalStateUpdateLoadError;
+use super::global_state_reader::GlobalStateReader;
+use super::global_state_update::decode_stored_value;
+
+/// An entry as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    key: String,
+    value: String,
+}
+
+/// A validator as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawValidator {
+    public_key: String,
+    weight: String,
+}
+
+/// How bad a finding is. Errors make the update wrong, warnings merely
+/// suspicious.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A mistake found in a global state update. Entries and validators are
+/// referred to by their position in the file, starting at 0.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Finding {
+    /// Several entries write the same key; only the last one takes effect.
+    DuplicateKey { key: String, entries: Vec<usize> },
+    /// An entry key is not a formatted `Key`.
+    InvalidKey {
+        entry: usize,
+        key: String,
+        error: String,
+    },
+    /// An entry value is not base64 or does not decode to a `StoredValue`.
+    InvalidValue {
+        entry: usize,
+        key: String,
+        error: String,
+    },
+    /// A validator's public key or weight cannot be parsed.
+    InvalidValidator { validator: usize, error: String },
+    /// A validator is listed more than once.
+    DuplicateValidator {
+        public_key: String,
+        validators: Vec<usize>,
+    },
+    /// The validator list is given but empty, leaving the network without
+    /// validators.
+    NoValidators,
+    /// A validator is given no weight.
+    ZeroWeight { public_key: String },
+    /// A validator holds at least a third of the total weight, enough to halt
+    /// finality on its own.
+    DominantValidator {
+        public_key: String,
+        weight: String,
+        total_weight: String,
+    },
+    /// An active bid is written for a validator missing from the validator
+    /// list.
+    UnlistedValidator { public_key: String, key: String },
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Finding::DominantValidator { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::DuplicateKey { key, entries } => {
+                write!(
+                    f,
+                    "{key} is written by entries {entries:?}, only the last one applies"
+                )
+            }
+            Finding::InvalidKey { entry, key, error } => {
+                write!(f, "entry {entry} has invalid key {key:?}: {error}")
+            }
+            Finding::InvalidValue { entry, key, error } => {
+                write!(f, "entry {entry} ({key}) has an invalid value: {error}")
+            }
+            Finding::InvalidValidator { validator, error } => {
+                write!(f, "validator {validator} is invalid: {error}")
+            }
+            Finding::DuplicateValidator {
+                public_key,
+                validators,
+            } => write!(f, "{public_key} is listed as validators {validators:?}"),
+            Finding::NoValidators => write!(f, "the validator list is empty"),
+            Finding::ZeroWeight { public_key } => write!(f, "{public_key} has zero weight"),
+            Finding::DominantValidator {
+                public_key,
+                weight,
+                total_weight,
+            } => write!(
+                f,
+                "{public_key} holds {weight} of the total weight {total_weight}, a third or more"
+            ),
+            Finding::UnlistedValidator { public_key, key } => {
+                write!(
+                    f,
+                    "{key} holds an active bid of {public_key}, which is not a validator"
+                )
+            }
+        }
+    }
+}
+
+/// Findings of a global state update, in the order they were found.
+///
+/// Serializes as a list of findings, each with its severity.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GlobalStateLint {
+    pub findings: Vec<Finding>,
+}
+
+impl Serialize for GlobalStateLint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Reported<'a> {
+            severity: Severity,
+            #[serde(flatten)]
+            finding: &'a Finding,
+        }
+
+        serializer.collect_seq(self.findings.iter().map(|finding| Reported {
+            severity: finding.severity(),
+            finding,
+        }))
+    }
+}
+
+impl GlobalStateLint {
+    /// Lints `global_state.toml` in the given directory.
+    ///
+    /// If the file doesn't exist, returns `Ok(None)`.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        GlobalStateReader::open(path)?.map(Self::lint).transpose()
+    }
+
+    /// Lints the update read from `reader`. Fails only if it is not valid
+    /// TOML or cannot be read.
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, GlobalStateUpdateLoadError> {
+        Self::lint(GlobalStateReader::from_reader(reader))
+    }
+
+    fn lint<R: BufRead>(
+        mut reader: GlobalStateReader<R, RawEntry, RawValidator>,
+    ) -> Result<Self, GlobalStateUpdateLoadError> {
+        let mut findings = vec![];
+        let mut entries = EntryLint::default();
+        for (index, entry) in reader.by_ref().enumerate() {
+            entries.lint(index, &entry?, &mut findings);
+        }
+        // Validator tables may follow the entries, so the bids can only be
+        // checked against them once the whole file was read.
+        let validators =
+            reader.validators().map(|validators| lint_validators(validators, &mut findings));
+        entries.finish(validators.as_ref(), &mut findings);
+        Ok(Self { findings })
+    }
+
+    /// Number of findings with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity() == severity).count()
+    }
+
+    pub fn is_clean(&self) -> bool { self.findings.is_empty() }
+}
+
+/// Checks the validator list and returns the validators that could be parsed.
+fn lint_validators(
+    validators: &[RawValidator],
+    findings: &mut Vec<Finding>,
+) -> BTreeSet<PublicKey> {
+    if validators.is_empty() {
+        findings.push(Finding::NoValidators);
+    }
+
+    let mut weights: BTreeMap<PublicKey, (Vec<usize>, U512)> = BTreeMap::new();
+    for (index, validator) in validators.iter().enumerate() {
+        let public_key = match PublicKey::from_hex(&validator.public_key) {
+            Ok(public_key) => public_key,
+            Err(error) => {
+                findings.push(Finding::InvalidValidator {
+                    validator: index,
+                    error: format!("invalid public key: {error}"),
+                });
+                continue;
+            }
+        };
+        let weight = match U512::from_dec_str(&validator.weight) {
+            Ok(weight) => weight,
+            Err(error) => {
+                findings.push(Finding::InvalidValidator {
+                    validator: index,
+                    error: format!("invalid weight {:?}: {error:?}", validator.weight),
+                });
+                continue;
+            }
+        };
+        let listed = weights.entry(public_key).or_default();
+        listed.0.push(index);
+        listed.1 = weight;
+    }
+
+    let mut total_weight = U512::zero();
+    for (public_key, (indices, weight)) in &weights {
+        if indices.len() > 1 {
+            findings.push(Finding::DuplicateValidator {
+                public_key: public_key.to_hex(),
+                validators: indices.clone(),
+            });
+        }
+        if weight.is_zero() {
+            findings.push(Finding::ZeroWeight {
+                public_key: public_key.to_hex(),
+            });
+        }
+        total_weight = total_weight.saturating_add(*weight);
+    }
+
+    // A single validator always holds all the weight, which is fine.
+    if weights.len() > 1 {
+        for (public_key, (_, weight)) in &weights {
+            if !weight.is_zero() && weight.saturating_mul(U512::from(3)) >= total_weight {
+                findings.push(Finding::DominantValidator {
+                    public_key: public_key.to_hex(),
+                    weight: weight.to_string(),
+                    total_weight: total_weight.to_string(),
+                });
+            }
+        }
+    }
+
+    weights.into_keys().collect()
+}
+
+/// What is kept of the entries while they are checked one at a time.
+#[derive(Default)]
+struct EntryLint {
+    /// The entries writing each key.
+    writes: BTreeMap<Key, Vec<usize>>,
+    /// The validators of active bids and the keys holding them.
+    active_bids: Vec<(PublicKey, String)>,
+}
+
+impl EntryLint {
+    fn lint(&mut self, index: usize, entry: &RawEntry, findings: &mut Vec<Finding>) {
+        let key = match Key::from_formatted_str(&entry.key) {
+            Ok(key) => key,
+            Err(error) => {
+                findings.push(Finding::InvalidKey {
+                    entry: index,
+                    key: entry.key.clone(),
+                    error: error.to_string(),
+                });
+                return;
+            }
+        };
+        self.writes.entry(key).or_default().push(index);
+
+        let value = base64::decode(&entry.value)
+            .map_err(|error| format!("not base64: {error}"))
+            .and_then(|bytes| {
+                decode_stored_value(&bytes).map_err(|error| format!("not a StoredValue: {error}"))
+            });
+        match value {
+            Ok(StoredValue::Bid(bid)) if !bid.inactive() => {
+                let public_key = bid.validator_public_key().clone();
+                self.active_bids.push((public_key, entry.key.clone()));
+            }
+            Ok(_) => {}
+            Err(error) => findings.push(Finding::InvalidValue {
+                entry: index,
+                key: entry.key.clone(),
+                error,
+            }),
+        }
+    }
+
+    /// Reports keys written more than once, and active bids of validators
+    /// missing from `validators` if the update replaces the validator set.
+    fn finish(self, validators: Option<&BTreeSet<PublicKey>>, findings: &mut Vec<Finding>) {
+        for (key, entries) in self.writes {
+            if entries.len() > 1 {
+                findings.push(Finding::DuplicateKey {
+                    key: key.to_formatted_string(),
+                    entries,
+                });
+            }
+        }
+
+        let Some(validators) = validators else {
+            return;
+        };
+        for (public_key, key) in self.active_bids {
+            if !validators.contains(&public_key) {
+                findings.push(Finding::UnlistedValidator {
+                    public_key: public_key.to_hex(),
+                    key,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use casper_types::bytesrepr::ToBytes;
+    use casper_types::system::auction::Bid;
+    use casper_types::AccessRights;
+    use casper_types::CLValue;
+    use casper_types::URef;
+
+    use super::*;
+    use crate::testing::public_key;
+    use crate::testing::validator_entry;
+
+    fn entry(key: Key, value: &[u8]) -> String {
+        format!(
+            "[[entries]]\nkey = \"{}\"\nvalue = \"{}\"\n",
+            key.to_formatted_string(),
+            base64::encode(value)
+        )
+    }
+
+    fn lint(toml: &str) -> Vec<Finding> {
+        GlobalStateLint::read(Cursor::new(toml)).unwrap().findings
+    }
+
+    #[test]
+    fn sound_update_has_no_findings() {
+        let value = StoredValue::CLValue(CLValue::from_t(42u64).unwrap()).to_bytes().unwrap();
+        let toml = [
+            entry(Key::Hash([1; 32]), &value),
+            validator_entry(1, "100"),
+            validator_entry(2, "100"),
+            validator_entry(3, "100"),
+            validator_entry(4, "100"),
+        ]
+        .concat();
+
+        assert_eq!(lint(&toml), vec![]);
+    }
+
+    #[test]
+    fn finds_broken_entries_and_validators() {
+        let value = StoredValue::CLValue(CLValue::from_t(42u64).unwrap()).to_bytes().unwrap();
+        let purse = URef::new([9; 32], AccessRights::READ_ADD_WRITE);
+        let bid = StoredValue::Bid(Box::new(Bid::unlocked(
+            public_key(9),
+            purse,
+            U512::from(10),
+            0,
+        )))
+        .to_bytes()
+        .unwrap();
+        let toml = [
+            entry(Key::Hash([1; 32]), &value),
+            entry(Key::Hash([1; 32]), &value),
+            entry(Key::Hash([2; 32]), &[0xff, 0x00]),
+            entry(Key::Hash([3; 32]), &bid),
+            validator_entry(1, "0"),
+            validator_entry(2, "100"),
+            validator_entry(2, "100"),
+        ]
+        .concat();
+
+        let findings = lint(&toml);
+        let public_key_2 = public_key(2).to_hex();
+        assert!(findings.contains(&Finding::DuplicateKey {
+            key: Key::Hash([1; 32]).to_formatted_string(),
+            entries: vec![0, 1],
+        }));
+        assert!(findings
+            .iter()
+            .any(|finding| matches!(finding, Finding::InvalidValue { entry: 2, .. })));
+        assert!(findings.contains(&Finding::UnlistedValidator {
+            public_key: public_key(9).to_hex(),
+            key: Key::Hash([3; 32]).to_formatted_string(),
+        }));
+        assert!(findings.contains(&Finding::ZeroWeight {
+            public_key: public_key(1).to_hex(),
+        }));
+        assert!(findings.contains(&Finding::DuplicateValidator {
+            public_key: public_key_2.clone(),
+            validators: vec![1, 2],
+        }));
+        assert!(findings.contains(&Finding::DominantValidator {
+            public_key: public_key_2,
+            weight: "100".to_string(),
+            total_weight: "100".to_string(),
+        }));
+    }
+
+    #[test]
+    fn findings_serialize_with_their_kind_and_severity() {
+        let lint = GlobalStateLint {
+            findings: vec![Finding::ZeroWeight {
+                public_key: "01ab".to_string(),
+            }],
+        };
+        assert_eq!(
+            serde_json::to_value(lint).unwrap(),
+            serde_json::json!([{ "severity": "error", "kind": "zero_weight", "public_key": "01ab" }])
+        );
+    }
+}
@@ -1,11 +1,21 @@
 pub mod accounts_config;
+pub mod accounts_summary;
 pub mod activation_point;
 pub mod chainspec_raw_bytes;
 pub mod core_config;
 pub mod deploy_config;
+pub mod diff;
 pub mod error;
+#[cfg(test)]
+pub mod gens;
+pub mod global_state_reader;
 pub mod global_state_update;
 pub mod highway_config;
+pub mod json;
+pub mod lint;
 pub mod network_config;
 pub mod parse_toml;
 pub mod protocol_config;
+pub mod state_digest;
+pub mod upgrades;
+pub mod validators;
@@ -69,95 +69,24 @@ impl FromBytes for ProtocolConfig {
 
 #[cfg(test)]
 mod tests {
-    use casper_types::EraId;
+    use proptest::prelude::*;
 
     use super::*;
-    use crate::types::Block;
-
-    #[test]
-    fn activation_point_bytesrepr_roundtrip() {
-        let mut rng = crate::new_rng();
-        let activation_point = ActivationPoint::random(&mut rng);
-        bytesrepr::test_serialization_roundtrip(&activation_point);
-    }
-
-    #[test]
-    fn protocol_config_bytesrepr_roundtrip() {
-        let mut rng = crate::new_rng();
-        let config = ProtocolConfig::random(&mut rng);
-        bytesrepr::test_serialization_roundtrip(&config);
-    }
-
-    #[test]
-    fn toml_roundtrip() {
-        let mut rng = crate::new_rng();
-        let config = ProtocolConfig::random(&mut rng);
-        let encoded = toml::to_string_pretty(&config).unwrap();
-        let decoded = toml::from_str(&encoded).unwrap();
-        assert_eq!(config, decoded);
-    }
-
-    #[test]
-    fn should_perform_checks_without_global_state_update() {
-        let mut rng = crate::new_rng();
-        let mut protocol_config = ProtocolConfig::random(&mut rng);
-
-        // We force `global_state_update` to be `None`.
-        protocol_config.global_state_update = None;
-
-        assert!(protocol_config.is_valid());
-    }
-
-    #[test]
-    fn should_perform_checks_with_global_state_update() {
-        let mut rng = crate::new_rng();
-        let mut protocol_config = ProtocolConfig::random(&mut rng);
-
-        // We force `global_state_update` to be `Some`.
-        protocol_config.global_state_update = Some(GlobalStateUpdate::random(&mut rng));
-
-        assert!(protocol_config.is_valid());
-    }
-
-    #[test]
-    fn should_recognize_blocks_before_activation_point() {
-        let past_version = ProtocolVersion::from_parts(1, 0, 0);
-        let current_version = ProtocolVersion::from_parts(2, 0, 0);
-        let future_version = ProtocolVersion::from_parts(3, 0, 0);
-
-        let upgrade_era = EraId::from(5);
-        let previous_era = upgrade_era.saturating_sub(1);
-
-        let mut rng = crate::new_rng();
-        let protocol_config = ProtocolConfig {
-            version: current_version,
-            hard_reset: false,
-            activation_point: ActivationPoint::EraId(upgrade_era),
-            global_state_update: None,
-        };
-
-        // The block before this protocol version: a switch block with previous era and
-        // version.
-        let block =
-            Block::random_with_specifics(&mut rng, previous_era, 100, past_version, true, None);
-        assert!(protocol_config.is_last_block_before_activation(block.header()));
-
-        // Not the activation point: wrong era.
-        let block =
-            Block::random_with_specifics(&mut rng, upgrade_era, 100, past_version, true, None);
-        assert!(!protocol_config.is_last_block_before_activation(block.header()));
-
-        // Not the activation point: wrong version.
-        let block =
-            Block::random_with_specifics(&mut rng, previous_era, 100, current_version, true, None);
-        assert!(!protocol_config.is_last_block_before_activation(block.header()));
-        let block =
-            Block::random_with_specifics(&mut rng, previous_era, 100, future_version, true, None);
-        assert!(!protocol_config.is_last_block_before_activation(block.header()));
-
-        // Not the activation point: not a switch block.
-        let block =
-            Block::random_with_specifics(&mut rng, previous_era, 100, past_version, false, None);
-        assert!(!protocol_config.is_last_block_before_activation(block.header()));
+    use crate::primitives::chainspec::gens::protocol_config_arb;
+
+    proptest! {
+        #[test]
+        fn bytesrepr_roundtrip(config in protocol_config_arb()) {
+            bytesrepr::test_serialization_roundtrip(&config);
+        }
+
+        #[test]
+        fn toml_roundtrip(mut config in protocol_config_arb()) {
+            // The chainspec never holds the update, it is read from its own
+            // `global_state.toml`.
+            config.global_state_update = None;
+            let encoded = toml::to_string_pretty(&config).unwrap();
+            prop_assert_eq!(toml::from_str::<ProtocolConfig>(&encoded).unwrap(), config);
+        }
     }
 }
@@ -0,0 +1,124 @@
+//! Digests of what a global state update writes, to compare an upgrade file
+//! against what a node reports once it applied it.
+//!
+//! Casper's global state is a Merkle trie whose leaves hold each key with its
+//! `StoredValue`, and every node of the trie is stored under the hash of its
+//! bytesrepr encoding. An entry of an update thus becomes exactly one leaf
+//! whose hash can be computed without the trie, while the new state root also
+//! depends on every other leaf and can only be computed by a node holding the
+//! pre-upgrade state.
+
+use casper_hashing::Digest;
+use casper_types::bytesrepr;
+use casper_types::bytesrepr::ToBytes;
+use casper_types::Key;
+use serde::Serialize;
+
+use super::global_state_update::FormattedKey;
+use super::global_state_update::GlobalStateUpdate;
+
+/// Tag of a leaf in the bytesrepr encoding of a trie node.
+const TRIE_LEAF_TAG: u8 = 0;
+
+/// Hash of the trie leaf holding `value`, a serialized `StoredValue`, under
+/// `key`, as Casper nodes store it in their trie store.
+pub fn leaf_hash(key: &Key, value: &[u8]) -> Result<Digest, bytesrepr::Error> {
+    let mut leaf = Vec::with_capacity(1 + key.serialized_length() + value.len());
+    leaf.push(TRIE_LEAF_TAG);
+    key.write_bytes(&mut leaf)?;
+    leaf.extend_from_slice(value);
+    Ok(Digest::hash_into_chunks_if_necessary(&leaf))
+}
+
+/// The trie leaf an entry of an update becomes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EntryDigest {
+    pub key: FormattedKey,
+    pub leaf_hash: Digest,
+}
+
+/// Digests of every write of a global state update.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StateDigest {
+    /// The leaves the update writes, ordered by key.
+    pub entries: Vec<EntryDigest>,
+    /// Merkle root over the leaf hashes in key order, a single digest to
+    /// compare two updates by. It is not a state root.
+    pub digest: Digest,
+}
+
+impl StateDigest {
+    pub fn of(update: &GlobalStateUpdate) -> Result<Self, bytesrepr::Error> {
+        let entries = update
+            .entries
+            .iter()
+            .map(|(key, value)| {
+                Ok(EntryDigest {
+                    key: FormattedKey(*key),
+                    leaf_hash: leaf_hash(key, value.as_slice())?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let digest = Digest::hash_merkle_tree(entries.iter().map(|entry| entry.leaf_hash));
+        Ok(Self { entries, digest })
+    }
+
+    /// The hash of the leaf written under `key`, if the update writes it.
+    pub fn leaf_hash(&self, key: &Key) -> Option<Digest> {
+        self.entries
+            .binary_search_by(|entry| entry.key.0.cmp(key))
+            .ok()
+            .map(|index| self.entries[index].leaf_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::CLValue;
+    use casper_types::StoredValue;
+
+    use super::*;
+
+    fn value(n: u64) -> Vec<u8> {
+        StoredValue::CLValue(CLValue::from_t(n).unwrap()).to_bytes().unwrap()
+    }
+
+    #[test]
+    fn leaves_hash_the_tagged_key_and_value() {
+        let key = Key::Hash([1; 32]);
+        let mut leaf = vec![0];
+        leaf.extend(key.to_bytes().unwrap());
+        leaf.extend(value(42));
+        assert_eq!(leaf_hash(&key, &value(42)).unwrap(), Digest::hash(&leaf));
+    }
+
+    #[test]
+    fn digests_depend_on_every_write_but_not_the_order() {
+        let a = GlobalStateUpdate::builder()
+            .entry(Key::Hash([1; 32]), value(1))
+            .entry(Key::Hash([2; 32]), value(2))
+            .build();
+        let b = GlobalStateUpdate::builder()
+            .entry(Key::Hash([2; 32]), value(2))
+            .entry(Key::Hash([1; 32]), value(1))
+            .build();
+        let c = GlobalStateUpdate::builder()
+            .entry(Key::Hash([1; 32]), value(1))
+            .entry(Key::Hash([2; 32]), value(3))
+            .build();
+
+        let digest = StateDigest::of(&a).unwrap();
+        assert_eq!(digest, StateDigest::of(&b).unwrap());
+        let other = StateDigest::of(&c).unwrap();
+        assert_ne!(digest.digest, other.digest);
+        assert_eq!(
+            digest.leaf_hash(&Key::Hash([1; 32])),
+            other.leaf_hash(&Key::Hash([1; 32]))
+        );
+        assert_ne!(
+            digest.leaf_hash(&Key::Hash([2; 32])),
+            other.leaf_hash(&Key::Hash([2; 32]))
+        );
+        assert_eq!(digest.leaf_hash(&Key::Hash([3; 32])), None);
+    }
+}
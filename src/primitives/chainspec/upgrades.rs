@@ -0,0 +1,225 @@
+//! Chainspecs of every protocol version a network went through, laid out as
+//! casper-node installs them: one directory per version, named after it as
+//! `1_5_6` or `1.5.6`, each holding its `chainspec.toml` and the files that
+//! go with it.
+//!
+//! The chainspec active in an era is that of the latest version whose
+//! activation point the era reached. Other entries of the directory, like
+//! casper-node's `config.toml`, are ignored.
+//!
+//! A node runs the version active in the era the network is in, see
+//! [`Upgrades::resolve`], as configured or learned from the switch blocks
+//! it followed. With the era unknown it runs the latest version installed,
+//! as casper-node does once upgraded.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+
+use super::error::UpgradesError;
+use crate::primitives::Chainspec;
+use crate::primitives::CHAINSPEC_FILENAME;
+
+/// The chainspec of one protocol version.
+#[derive(Clone, Debug)]
+pub struct Upgrade {
+    pub version: ProtocolVersion,
+    /// Directory the chainspec was loaded from.
+    pub dir: PathBuf,
+    pub chainspec: Chainspec,
+}
+
+impl Upgrade {
+    /// First era the version is active in, 0 for the genesis one.
+    pub fn activation_era(&self) -> EraId {
+        self.chainspec.protocol_config.activation_point.era_id()
+    }
+}
+
+/// The chainspecs of the protocol versions in a directory, lowest version
+/// first.
+#[derive(Clone, Debug)]
+pub struct Upgrades(Vec<Upgrade>);
+
+impl Upgrades {
+    /// Loads the chainspec of every version directory in `root`, checking
+    /// that each is for the version its directory is named after, that no
+    /// two are for the same version and that each activates after the one
+    /// before.
+    pub fn load(root: &Path) -> Result<Self, UpgradesError> {
+        let dirs = version_dirs(root).map_err(|e| UpgradesError::ReadDir(root.to_path_buf(), e))?;
+        if dirs.is_empty() {
+            return Err(UpgradesError::Empty(root.to_path_buf()));
+        }
+        let mut upgrades: Vec<Upgrade> = Vec::with_capacity(dirs.len());
+        for (version, dir) in dirs {
+            let chainspec =
+                Chainspec::from_path(&dir).map_err(|e| UpgradesError::Load(dir.clone(), e))?;
+            if chainspec.protocol_version() != version {
+                return Err(UpgradesError::VersionMismatch {
+                    dir,
+                    version: chainspec.protocol_version(),
+                });
+            }
+            let upgrade = Upgrade {
+                version,
+                dir,
+                chainspec,
+            };
+            if let Some(previous) = upgrades.last() {
+                if previous.version == upgrade.version {
+                    return Err(UpgradesError::DuplicateVersion {
+                        first: previous.dir.clone(),
+                        second: upgrade.dir,
+                        version,
+                    });
+                }
+                let era_id = upgrade.activation_era();
+                if era_id <= previous.activation_era() {
+                    return Err(UpgradesError::ActivatesTooEarly {
+                        dir: upgrade.dir,
+                        era_id,
+                        previous: previous.activation_era(),
+                    });
+                }
+            }
+            upgrades.push(upgrade);
+        }
+        Ok(Upgrades(upgrades))
+    }
+
+    /// Every version, lowest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Upgrade> { self.0.iter() }
+
+    /// The version active in `era_id`, `None` if it is before the first.
+    pub fn active_at(&self, era_id: EraId) -> Option<&Upgrade> {
+        self.0.iter().rev().find(|upgrade| upgrade.activation_era() <= era_id)
+    }
+
+    /// The highest version.
+    pub fn latest(&self) -> &Upgrade { self.0.last().expect("upgrades are never empty") }
+
+    /// The directory to load the chainspec of `dir` from: `dir` itself if it
+    /// holds a chainspec, else the version directory active in `era_id`.
+    ///
+    /// A version stays in use until the network reaches the activation point
+    /// of the next, and the first is used before its own. With the era
+    /// unknown, the latest version is, whether or not the network reached
+    /// its activation point.
+    pub fn resolve(dir: &Path, era_id: Option<EraId>) -> PathBuf {
+        if dir.join(CHAINSPEC_FILENAME).is_file() {
+            return dir.to_path_buf();
+        }
+        let Some(era_id) = era_id else {
+            return match version_dirs(dir).ok().and_then(|dirs| dirs.into_iter().last()) {
+                Some((_, latest)) => latest,
+                None => dir.to_path_buf(),
+            };
+        };
+        // Loading checks every version, so a broken one is reported when the
+        // node loads the chainspec resolved.
+        match Self::load(dir) {
+            Ok(upgrades) => {
+                let active = upgrades.active_at(era_id).unwrap_or(&upgrades.0[0]);
+                active.dir.clone()
+            }
+            Err(_) => Self::resolve(dir, None),
+        }
+    }
+}
+
+/// The directories in `root` named after a protocol version, lowest version
+/// first.
+fn version_dirs(root: &Path) -> io::Result<Vec<(ProtocolVersion, PathBuf)>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let version = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.replace('_', ".").parse::<ProtocolVersion>().ok());
+        if let Some(version) = version {
+            dirs.push((version, path));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::chainspec_dir;
+
+    /// Writes the example chainspec to `root/name`, as `version` activating
+    /// in `era`.
+    fn install(root: &Path, name: &str, version: &str, era: u64) {
+        let chainspec = fs::read_to_string(chainspec_dir().join(CHAINSPEC_FILENAME)).unwrap();
+        let chainspec = chainspec
+            .replace("version = '1.5.2'", &format!("version = '{version}'"))
+            .replace(
+                "activation_point = 9100",
+                &format!("activation_point = {era}"),
+            );
+        fs::create_dir_all(root.join(name)).unwrap();
+        fs::write(root.join(name).join(CHAINSPEC_FILENAME), chainspec).unwrap();
+    }
+
+    #[test]
+    fn the_active_chainspec_is_that_of_the_last_activation_point_reached() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        install(root, "1_5_3", "1.5.3", 9200);
+        install(root, "1.5.2", "1.5.2", 9100);
+        // Not a version, so not an upgrade.
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("config.toml"), "").unwrap();
+
+        let upgrades = Upgrades::load(root).unwrap();
+        let versions: Vec<_> = upgrades.iter().map(|upgrade| upgrade.version).collect();
+        assert_eq!(
+            versions,
+            [
+                ProtocolVersion::from_parts(1, 5, 2),
+                ProtocolVersion::from_parts(1, 5, 3)
+            ]
+        );
+        assert!(upgrades.active_at(EraId::new(9099)).is_none());
+        let active = |era| upgrades.active_at(EraId::new(era)).unwrap().version;
+        assert_eq!(active(9100), ProtocolVersion::from_parts(1, 5, 2));
+        assert_eq!(active(9199), ProtocolVersion::from_parts(1, 5, 2));
+        assert_eq!(active(9200), ProtocolVersion::from_parts(1, 5, 3));
+        assert_eq!(upgrades.latest().dir, root.join("1_5_3"));
+        assert_eq!(Upgrades::resolve(root, None), root.join("1_5_3"));
+        let resolve = |era| Upgrades::resolve(root, Some(EraId::new(era)));
+        assert_eq!(resolve(9000), root.join("1.5.2"));
+        assert_eq!(resolve(9199), root.join("1.5.2"));
+        assert_eq!(resolve(9200), root.join("1_5_3"));
+        let era = Some(EraId::new(9199));
+        assert_eq!(Upgrades::resolve(&chainspec_dir(), era), chainspec_dir());
+
+        install(root, "1_6_0", "1.6.1", 9300);
+        assert!(matches!(
+            Upgrades::load(root),
+            Err(UpgradesError::VersionMismatch { .. })
+        ));
+        install(root, "1_6_0", "1.6.0", 9150);
+        assert!(matches!(
+            Upgrades::load(root),
+            Err(UpgradesError::ActivatesTooEarly { .. })
+        ));
+        install(root, "1_6_0", "1.6.0", 9300);
+        install(root, "1.6.0", "1.6.0", 9300);
+        assert!(matches!(
+            Upgrades::load(root),
+            Err(UpgradesError::DuplicateVersion { .. })
+        ));
+    }
+}
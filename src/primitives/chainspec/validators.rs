@@ -0,0 +1,176 @@
+//! The validator set a global state update installs, for reviewing upgrades.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::BufRead;
+
+use casper_types::AsymmetricType;
+use casper_types::PublicKey;
+use casper_types::U512;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::error::GlobalStateUpdateLoadError;
+use super::global_state_reader::GlobalStateReader;
+use super::global_state_update::DecWeight;
+use super::global_state_update::GlobalStateUpdateValidatorInfo;
+use super::global_state_update::HexPublicKey;
+
+/// A validator of the set, with its share of the total weight.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ValidatorWeight {
+    pub public_key: HexPublicKey,
+    pub weight: DecWeight,
+    /// Percentage of the total weight.
+    pub share: f64,
+    /// Whether the weight is below the minimum asked for.
+    pub below_minimum: bool,
+}
+
+impl Display for ValidatorWeight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<68}  {:>30}  {:>7.2}%",
+            self.public_key.0.to_hex(),
+            self.weight.0,
+            self.share
+        )?;
+        if self.below_minimum {
+            write!(f, "  below minimum")?;
+        }
+        Ok(())
+    }
+}
+
+/// The validators after the upgrade, heaviest first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ValidatorSet {
+    pub validators: Vec<ValidatorWeight>,
+    pub total_weight: DecWeight,
+    pub minimum_weight: Option<DecWeight>,
+}
+
+impl ValidatorSet {
+    /// Reads the validators of the update, flagging those weighing less than
+    /// `minimum_weight`. A validator listed twice weighs what it is given
+    /// last, as it does once the update is applied.
+    ///
+    /// If the update leaves the validator set as it is, returns `Ok(None)`.
+    pub fn read<R: BufRead, E: DeserializeOwned>(
+        mut reader: GlobalStateReader<R, E, GlobalStateUpdateValidatorInfo>,
+        minimum_weight: Option<U512>,
+    ) -> Result<Option<Self>, GlobalStateUpdateLoadError> {
+        // Validator tables may follow the entries, so every entry has to be
+        // read before the list is complete.
+        for entry in reader.by_ref() {
+            entry?;
+        }
+        let Some(validators) = reader.validators() else {
+            return Ok(None);
+        };
+        let weights: BTreeMap<&PublicKey, U512> = validators
+            .iter()
+            .map(|validator| (&validator.public_key.0, validator.weight.0))
+            .collect();
+        Ok(Some(Self::new(weights, minimum_weight)))
+    }
+
+    fn new(weights: BTreeMap<&PublicKey, U512>, minimum_weight: Option<U512>) -> Self {
+        let total_weight = weights
+            .values()
+            .fold(U512::zero(), |total, weight| total.saturating_add(*weight));
+        let mut validators: Vec<_> = weights
+            .into_iter()
+            .map(|(public_key, weight)| ValidatorWeight {
+                public_key: HexPublicKey(public_key.clone()),
+                weight: DecWeight(weight),
+                share: share(weight, total_weight),
+                below_minimum: minimum_weight.is_some_and(|minimum| weight < minimum),
+            })
+            .collect();
+        validators.sort_by_key(|validator| Reverse(validator.weight.0));
+        Self {
+            validators,
+            total_weight: DecWeight(total_weight),
+            minimum_weight: minimum_weight.map(DecWeight),
+        }
+    }
+
+    /// Number of validators weighing less than the minimum.
+    pub fn below_minimum(&self) -> usize {
+        self.validators.iter().filter(|validator| validator.below_minimum).count()
+    }
+}
+
+/// Percentage of `total` that `weight` is, to a hundredth of a basis point.
+fn share(weight: U512, total: U512) -> f64 {
+    if total.is_zero() {
+        return 0.0;
+    }
+    let scaled = weight.saturating_mul(U512::from(1_000_000)) / total;
+    scaled.as_u64() as f64 / 10_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testing::public_key;
+    use crate::testing::validator_entry;
+
+    fn read(toml: &str, minimum_weight: Option<u64>) -> Option<ValidatorSet> {
+        let reader = GlobalStateReader::new(Cursor::new(toml.to_string()));
+        ValidatorSet::read(reader, minimum_weight.map(U512::from)).unwrap()
+    }
+
+    #[test]
+    fn validators_are_weighed_against_the_total() {
+        let entry = format!(
+            "[[entries]]\nkey = \"hash-{}\"\nvalue = \"AA==\"\n",
+            "01".repeat(32)
+        );
+        let toml = [
+            validator_entry(1, "100"),
+            validator_entry(2, "600"),
+            entry,
+            validator_entry(3, "300"),
+            // Listed again, so only the later weight counts.
+            validator_entry(1, "50"),
+        ]
+        .concat();
+
+        let set = read(&toml, Some(100)).unwrap();
+        assert_eq!(set.total_weight, DecWeight(U512::from(950)));
+        let weighed: Vec<_> = set
+            .validators
+            .iter()
+            .map(|validator| (validator.public_key.0.clone(), validator.weight.0.as_u64()))
+            .collect();
+        assert_eq!(
+            weighed,
+            vec![
+                (public_key(2), 600),
+                (public_key(3), 300),
+                (public_key(1), 50)
+            ]
+        );
+        assert_eq!(set.validators[0].share, 63.1578);
+        assert_eq!(set.below_minimum(), 1);
+        assert!(set.validators[2].below_minimum);
+        assert!(set.validators[2].to_string().ends_with("5.26%  below minimum"));
+    }
+
+    #[test]
+    fn updates_without_validators_leave_the_set_alone() {
+        assert_eq!(read("", None), None);
+
+        let set = read(&validator_entry(1, "0"), None).unwrap();
+        assert_eq!(set.validators[0].share, 0.0);
+        assert_eq!(set.below_minimum(), 0);
+    }
+}
@@ -13,13 +13,41 @@ use casper_types::bytesrepr::ToBytes;
 use casper_types::bytesrepr::U32_SERIALIZED_LENGTH;
 use casper_types::Gas;
 use casper_types::ProtocolVersion;
+pub use chainspec::accounts_summary::AccountsFinding;
+pub use chainspec::accounts_summary::AccountsSummary;
+pub use chainspec::accounts_summary::GenesisValidator;
 use chainspec::core_config::CoreConfig;
 use chainspec::deploy_config::DeployConfig;
+pub use chainspec::diff::ChainspecDiff;
+pub use chainspec::diff::FieldChange;
+pub use chainspec::error::ChainspecAccountsLoadError;
 use chainspec::error::Error;
+pub use chainspec::error::Error as ChainspecError;
+pub use chainspec::error::GlobalStateUpdateLoadError;
+pub use chainspec::error::UpgradesError;
+pub use chainspec::global_state_reader::GlobalStateReader;
+pub use chainspec::global_state_update::DecWeight;
+pub use chainspec::global_state_update::DecodedEntry;
+pub use chainspec::global_state_update::DecodedValue;
+pub use chainspec::global_state_update::FormattedKey;
+pub use chainspec::global_state_update::GlobalStateUpdate;
+pub use chainspec::global_state_update::GlobalStateUpdateBuilder;
+pub use chainspec::global_state_update::GlobalStateUpdateConfig;
+pub use chainspec::global_state_update::GlobalStateUpdateLimits;
+pub use chainspec::global_state_update::HexPublicKey;
 use chainspec::highway_config::HighwayConfig;
+pub use chainspec::lint::Finding;
+pub use chainspec::lint::GlobalStateLint;
+pub use chainspec::lint::Severity;
 use chainspec::network_config::NetworkConfig;
 use chainspec::parse_toml;
 use chainspec::protocol_config::ProtocolConfig;
+pub use chainspec::state_digest::EntryDigest;
+pub use chainspec::state_digest::StateDigest;
+pub use chainspec::upgrades::Upgrade;
+pub use chainspec::upgrades::Upgrades;
+pub use chainspec::validators::ValidatorSet;
+pub use chainspec::validators::ValidatorWeight;
 use datasize::DataSize;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
@@ -27,6 +55,7 @@ use serde::Serialize;
 use tracing::error;
 
 use crate::network::message::Message;
+use crate::network::mux::Channel;
 
 /// The name of the chainspec file on disk.
 pub const CHAINSPEC_FILENAME: &str = "chainspec.toml";
@@ -239,11 +268,13 @@ pub type Cost = u32;
 //
 //       While we do check for consecutive ping nonces being generated, we still like the lower
 //       collision chance for repeated pings being sent.
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, DataSize)]
 pub struct Nonce(u64);
 
 impl Nonce {
     pub fn new(num: u64) -> Self { Self(num) }
+
+    pub fn value(&self) -> u64 { self.0 }
 }
 
 impl Display for Nonce {
@@ -1904,6 +1935,11 @@ impl FromBytes for BrTableCost {
     }
 }
 
-pub trait Payload: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static {}
+pub trait Payload:
+    Serialize + DeserializeOwned + Clone + Debug + DataSize + Send + Sync + 'static
+{
+    /// The channel the payload is sent on once a connection is multiplexed.
+    fn channel(&self) -> Channel { Channel::Gossip }
+}
 
 impl<P: Payload> Message<P> {}
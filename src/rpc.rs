@@ -0,0 +1,269 @@
+//! Client for the JSON-RPC API casper-node serves, usually on port 7777.
+//!
+//! Schultz only sees what peers tell it on the wire; asking a node through
+//! its RPC API tells what the node itself believes, e.g. which peers it has
+//! and which block it is at, to cross-check the two.
+
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+use casper_hashing::Digest;
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+use crate::http::Endpoint;
+use crate::http::ParseEndpointError;
+
+/// Where nodes serve the API unless configured otherwise.
+pub const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:7777/rpc";
+
+/// Largest response read, a block with all its deploy hashes fits easily.
+const MAX_RESPONSE_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("could not reach {0}")]
+    Connect(Endpoint, #[source] io::Error),
+    #[error("{0} did not answer in time")]
+    Timeout(Endpoint),
+    #[error("could not talk to {0}")]
+    Io(Endpoint, #[source] io::Error),
+    #[error("HTTP status {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("invalid JSON-RPC response")]
+    Json(#[from] serde_json::Error),
+    #[error("the node answered with error {code}: {message}")]
+    Rpc { code: i64, message: String },
+}
+
+/// Parses where a node serves the API, which defaults to port 7777 and the
+/// path `/rpc`.
+pub fn parse_endpoint(value: &str) -> Result<Endpoint, ParseEndpointError> {
+    Endpoint::parse(value, 7777, "/rpc")
+}
+
+/// The methods `schultz rpc` passes through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    GetStatus,
+    GetPeers,
+    GetBlock,
+    GetStateRootHash,
+}
+
+impl Method {
+    /// Name of the method in the API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::GetStatus => "info_get_status",
+            Method::GetPeers => "info_get_peers",
+            Method::GetBlock => "chain_get_block",
+            Method::GetStateRootHash => "chain_get_state_root_hash",
+        }
+    }
+}
+
+/// A block to ask about, by hash or height. Asking about no block in
+/// particular means the node's highest block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum BlockIdentifier {
+    Hash(Digest),
+    Height(u64),
+}
+
+impl FromStr for BlockIdentifier {
+    type Err = String;
+
+    /// Parses a height if `value` is all digits and a hex hash otherwise.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(height) = value.parse() {
+            return Ok(BlockIdentifier::Height(height));
+        }
+        Digest::from_hex(value)
+            .map(BlockIdentifier::Hash)
+            .map_err(|e| format!("neither a height nor a block hash: {e:?}"))
+    }
+}
+
+/// A peer a node reports through `info_get_peers`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcPeer {
+    pub node_id: String,
+    pub address: String,
+}
+
+/// Asks a node through its JSON-RPC API.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    endpoint: Endpoint,
+    timeout: Duration,
+}
+
+impl RpcClient {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Time a request may take from connecting until the whole response was
+    /// read.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn endpoint(&self) -> &Endpoint { &self.endpoint }
+
+    pub async fn get_status(&self) -> Result<Value, RpcError> {
+        self.call(Method::GetStatus.name(), None).await
+    }
+
+    pub async fn get_peers(&self) -> Result<Vec<RpcPeer>, RpcError> {
+        #[derive(Deserialize)]
+        struct Peers {
+            peers: Vec<RpcPeer>,
+        }
+
+        let result = self.call(Method::GetPeers.name(), None).await?;
+        Ok(serde_json::from_value::<Peers>(result)?.peers)
+    }
+
+    /// The block, or `None` if the node does not have it.
+    pub async fn get_block(
+        &self,
+        block: Option<BlockIdentifier>,
+    ) -> Result<Option<Value>, RpcError> {
+        let mut result = self.call(Method::GetBlock.name(), block_params(block)).await?;
+        Ok(Some(result["block"].take()).filter(|block| !block.is_null()))
+    }
+
+    /// The state root hash after the block, or `None` if the node does not
+    /// have it.
+    pub async fn get_state_root_hash(
+        &self,
+        block: Option<BlockIdentifier>,
+    ) -> Result<Option<Digest>, RpcError> {
+        #[derive(Deserialize)]
+        struct StateRootHash {
+            state_root_hash: Option<Digest>,
+        }
+
+        let result = self.call(Method::GetStateRootHash.name(), block_params(block)).await?;
+        Ok(serde_json::from_value::<StateRootHash>(result)?.state_root_hash)
+    }
+
+    /// Calls `method` with `params` and returns the result, whatever it is.
+    pub async fn call(&self, method: &str, params: Option<Value>) -> Result<Value, RpcError> {
+        let mut request = json!({ "jsonrpc": "2.0", "id": 1, "method": method });
+        if let Some(params) = params {
+            request["params"] = params;
+        }
+        let body = serde_json::to_vec(&request)?;
+        let response = tokio::time::timeout(self.timeout, self.post(&body))
+            .await
+            .map_err(|_| RpcError::Timeout(self.endpoint.clone()))??;
+
+        let mut response: Value = serde_json::from_slice(&response)?;
+        if let Some(error) = response.get("error") {
+            return Err(RpcError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(response["result"].take())
+    }
+
+    /// Posts `body` and returns the body of the response.
+    async fn post(&self, body: &[u8]) -> Result<Vec<u8>, RpcError> {
+        let io_error = |e| RpcError::Io(self.endpoint.clone(), e);
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("Accept", "application/json"),
+        ];
+        let response = http::request(&self.endpoint, "POST", &headers, body)
+            .await
+            .map_err(|e| RpcError::Connect(self.endpoint.clone(), e))?;
+        let status = response.status;
+        let body = response.read_to_end(MAX_RESPONSE_LEN).await.map_err(io_error)?;
+        if status != 200 {
+            return Err(RpcError::Http {
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        Ok(body)
+    }
+}
+
+/// Parameters of the methods asking about `block`.
+pub fn block_params(block: Option<BlockIdentifier>) -> Option<Value> {
+    block.map(|block| json!({ "block_identifier": block }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::post;
+    use axum::Json;
+    use axum::Router;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Answers like a node at height 10 with a single peer.
+    async fn node(Json(request): Json<Value>) -> Json<Value> {
+        let result = match request["method"].as_str() {
+            Some("info_get_peers") => json!({
+                "api_version": "1.5.6",
+                "peers": [{ "node_id": "tls:0102..0304", "address": "10.0.0.1:35000" }],
+            }),
+            Some("chain_get_state_root_hash") => match request["params"]["block_identifier"] {
+                Value::Null => json!({ "state_root_hash": "ab".repeat(32) }),
+                _ => json!({ "state_root_hash": null }),
+            },
+            _ => {
+                return Json(json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32601, "message": "Method not found" },
+                }))
+            }
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    #[tokio::test]
+    async fn calls_a_node() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/rpc", post(node));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = RpcClient::new(parse_endpoint(&addr.to_string()).unwrap());
+
+        let peers = client.get_peers().await.unwrap();
+        assert_eq!(peers[0].address, "10.0.0.1:35000");
+        assert_eq!(
+            client.get_state_root_hash(None).await.unwrap(),
+            Some(Digest::from([0xab; 32]))
+        );
+        let unknown = Some(BlockIdentifier::Height(11));
+        assert_eq!(client.get_state_root_hash(unknown).await.unwrap(), None);
+        assert!(matches!(
+            client.get_status().await.unwrap_err(),
+            RpcError::Rpc { code: -32601, .. }
+        ));
+
+        let client = RpcClient::new(parse_endpoint(&format!("{addr}/elsewhere")).unwrap());
+        assert!(matches!(
+            client.get_status().await.unwrap_err(),
+            RpcError::Http { status: 404, .. }
+        ));
+    }
+}
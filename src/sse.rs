@@ -0,0 +1,324 @@
+//! Client for the event stream casper-node serves over SSE, usually on port
+//! 9999.
+//!
+//! Nodes announce what happens to them as server-sent events, one JSON object
+//! per event named after its kind, e.g. `{"BlockAdded":{..}}`. Casper 1.x
+//! splits them over `/events/main`, `/events/deploys` for accepted deploys
+//! and `/events/sigs` for finality signatures.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+
+use casper_hashing::Digest;
+use casper_types::AsymmetricType;
+use casper_types::EraId;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::Signature;
+use casper_types::TimeDiff;
+use casper_types::Timestamp;
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+use crate::http::Endpoint;
+use crate::http::ParseEndpointError;
+
+/// Where nodes serve the main stream unless configured otherwise.
+pub const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:9999/events/main";
+
+/// Longest line of the stream read, a block with all its deploy hashes fits
+/// easily.
+const MAX_LINE_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum SseError {
+    #[error("could not reach {0}")]
+    Connect(Endpoint, #[source] io::Error),
+    #[error("HTTP status {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("the event stream failed")]
+    Io(#[from] io::Error),
+    #[error("line of the event stream longer than {MAX_LINE_LEN} bytes")]
+    LineTooLong,
+}
+
+/// Parses where a node serves its events, which defaults to port 9999 and the
+/// path `/events/main`.
+pub fn parse_endpoint(value: &str) -> Result<Endpoint, ParseEndpointError> {
+    Endpoint::parse(value, 9999, "/events/main")
+}
+
+/// Something that happened to the node.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    /// Sent first, telling the version of the API.
+    ApiVersion(ProtocolVersion),
+    BlockAdded {
+        block_hash: Digest,
+        block: Value,
+    },
+    DeployAccepted(Value),
+    DeployProcessed {
+        deploy_hash: Digest,
+        account: PublicKey,
+        timestamp: Timestamp,
+        ttl: TimeDiff,
+        block_hash: Digest,
+        execution_result: Value,
+    },
+    DeployExpired {
+        deploy_hash: Digest,
+    },
+    Fault {
+        era_id: EraId,
+        public_key: PublicKey,
+        timestamp: Timestamp,
+    },
+    FinalitySignature {
+        block_hash: Digest,
+        era_id: EraId,
+        signature: Signature,
+        public_key: PublicKey,
+    },
+    Step {
+        era_id: EraId,
+        execution_effect: Value,
+    },
+    /// Sent last when the node shuts down.
+    Shutdown,
+    /// An event schultz does not know, kept as it was sent.
+    #[serde(untagged)]
+    Other(Value),
+}
+
+/// The kinds of events to filter the stream by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    Blocks,
+    Deploys,
+    FinalitySignatures,
+    Steps,
+    Faults,
+}
+
+impl Event {
+    /// The kind of the event, `None` for the ones about the stream itself.
+    pub fn kind(&self) -> Option<EventKind> {
+        match self {
+            Event::BlockAdded { .. } => Some(EventKind::Blocks),
+            Event::DeployAccepted(_)
+            | Event::DeployProcessed { .. }
+            | Event::DeployExpired { .. } => Some(EventKind::Deploys),
+            Event::FinalitySignature { .. } => Some(EventKind::FinalitySignatures),
+            Event::Step { .. } => Some(EventKind::Steps),
+            Event::Fault { .. } => Some(EventKind::Faults),
+            Event::ApiVersion(_) | Event::Shutdown | Event::Other(_) => None,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::ApiVersion(version) => write!(f, "api version {version}"),
+            Event::BlockAdded { block_hash, block } => {
+                write!(f, "block added {block_hash:x}")?;
+                match block["header"]["height"].as_u64() {
+                    Some(height) => write!(f, " at height {height}"),
+                    None => Ok(()),
+                }
+            }
+            Event::DeployAccepted(deploy) => {
+                let hash = deploy["hash"].as_str().or_else(|| deploy["deploy"]["hash"].as_str());
+                write!(f, "deploy accepted {}", hash.unwrap_or("?"))
+            }
+            Event::DeployProcessed {
+                deploy_hash,
+                block_hash,
+                ..
+            } => write!(
+                f,
+                "deploy processed {deploy_hash:x} in block {block_hash:x}"
+            ),
+            Event::DeployExpired { deploy_hash } => write!(f, "deploy expired {deploy_hash:x}"),
+            Event::Fault {
+                era_id, public_key, ..
+            } => write!(f, "fault by {} in {era_id}", public_key.to_hex()),
+            Event::FinalitySignature {
+                block_hash,
+                public_key,
+                ..
+            } => write!(
+                f,
+                "finality signature by {} for block {block_hash:x}",
+                public_key.to_hex()
+            ),
+            Event::Step { era_id, .. } => write!(f, "step at the end of {era_id}"),
+            Event::Shutdown => write!(f, "shutdown"),
+            Event::Other(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// An event with the id the node numbered it with.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StreamEvent {
+    pub id: Option<u64>,
+    pub event: Event,
+}
+
+/// The events of a node's stream, as they happen.
+#[derive(Debug)]
+pub struct EventStream {
+    response: http::Response,
+    decoder: Decoder,
+}
+
+impl EventStream {
+    pub async fn connect(endpoint: &Endpoint) -> Result<Self, SseError> {
+        let connect_error = |e| SseError::Connect(endpoint.clone(), e);
+        let response = http::request(endpoint, "GET", &[("Accept", "text/event-stream")], &[])
+            .await
+            .map_err(connect_error)?;
+        if response.status != 200 {
+            let status = response.status;
+            let body = response.read_to_end(64 * 1024).await.map_err(connect_error)?;
+            return Err(SseError::Http {
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        Ok(Self {
+            response,
+            decoder: Decoder::default(),
+        })
+    }
+
+    /// The next event, `None` once the node closed the stream.
+    pub async fn next(&mut self) -> Result<Option<StreamEvent>, SseError> {
+        loop {
+            if let Some(event) = self.decoder.next_event()? {
+                return Ok(Some(event));
+            }
+            match self.response.next_piece().await? {
+                Some(piece) => self.decoder.buffer.extend_from_slice(&piece),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Turns the lines of a stream into events.
+#[derive(Debug, Default)]
+struct Decoder {
+    /// What was read but does not make a full line yet.
+    buffer: Vec<u8>,
+    data: Vec<String>,
+    id: Option<u64>,
+}
+
+impl Decoder {
+    /// The next event complete in the buffer.
+    fn next_event(&mut self) -> Result<Option<StreamEvent>, SseError> {
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                if self.data.is_empty() {
+                    continue;
+                }
+                let data = std::mem::take(&mut self.data).join("\n");
+                let event =
+                    serde_json::from_str(&data).unwrap_or(Event::Other(Value::String(data)));
+                return Ok(Some(StreamEvent {
+                    id: self.id.take(),
+                    event,
+                }));
+            }
+            // Lines starting with a colon are comments, sent to keep the
+            // connection alive.
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "data" => self.data.push(value.to_string()),
+                "id" => self.id = value.parse().ok(),
+                _ => {}
+            }
+        }
+        if self.buffer.len() > MAX_LINE_LEN {
+            return Err(SseError::LineTooLong);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use casper_types::SecretKey;
+    use serde_json::json;
+
+    use super::*;
+
+    fn decode(stream: &str) -> Vec<StreamEvent> {
+        let mut decoder = Decoder::default();
+        let mut events = vec![];
+        // Pieces of the stream arrive cut anywhere.
+        for piece in stream.as_bytes().chunks(7) {
+            decoder.buffer.extend_from_slice(piece);
+            while let Some(event) = decoder.next_event().unwrap() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn events_are_decoded_with_their_ids() {
+        let public_key = PublicKey::from(&SecretKey::ed25519_from_bytes([1; 32]).unwrap());
+        let fault = json!({
+            "Fault": {
+                "era_id": 12,
+                "public_key": public_key.to_hex(),
+                "timestamp": "2024-01-01T00:00:00.000Z",
+            }
+        });
+        let block_hash = "ab".repeat(32);
+        let block_added = json!({
+            "BlockAdded": { "block_hash": block_hash, "block": { "header": { "height": 42 } } }
+        });
+        let stream = [
+            "data:{\"ApiVersion\":\"1.5.6\"}\n\n:keepalive\n\n".to_string(),
+            format!("data:{block_added}\nid:7\n\r\n"),
+            format!("data:{fault}\nid:8\n\n"),
+            "data:{\"Mystery\":1}\n\ndata:\"Shutdown\"\n\n".to_string(),
+        ]
+        .concat();
+
+        let events = decode(&stream);
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[0].event,
+            Event::ApiVersion(ProtocolVersion::from_parts(1, 5, 6))
+        );
+        assert_eq!(events[0].id, None);
+        assert_eq!(events[1].id, Some(7));
+        assert_eq!(
+            events[1].event.to_string(),
+            format!("block added {block_hash} at height 42")
+        );
+        assert_eq!(events[1].event.kind(), Some(EventKind::Blocks));
+        assert_eq!(events[2].event.kind(), Some(EventKind::Faults));
+        assert_eq!(events[3].event, Event::Other(json!({ "Mystery": 1 })));
+        assert_eq!(events[4].event, Event::Shutdown);
+
+        // Known events are written back as the node sent them.
+        assert_eq!(serde_json::to_value(&events[2].event).unwrap(), fault);
+    }
+}
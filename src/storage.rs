@@ -0,0 +1,354 @@
+//! The data directory schultz keeps its state in, and its layout.
+//!
+//! Everything kept between runs lives in one directory, `--data-dir`, or
+//! else the `schultz` directory of the root dir:
+//!
+//! - `schultz.toml`, the config file, unless `--config` names another;
+//! - `identity/`, the TLS identity nodes present, kept across restarts;
+//! - `peers.db`, the peers nodes know of, to rejoin through;
+//! - `captures/`, wire logs kept for later, see `schultz wire-log`;
+//! - `metrics/`, the metrics snapshots `schultz stats` reads;
+//! - `observations.db/`, the observations `schultz db` queries;
+//! - `crawl.json`, the checkpoint `schultz crawl --resume` continues from;
+//! - `crawls/`, the finished crawls `schultz crawl --diff` compares with.
+//!
+//! A network selected with `--network` gets an identity, peers, metrics,
+//! observations and crawls of its own: `identity/<network>/`,
+//! `peers-<network>.db`, `metrics/<network>/`, `observations-<network>.db/`,
+//! `crawl-<network>.json` and `crawls/<network>/`. The identity and
+//! peers go elsewhere if the configuration says so.
+//!
+//! The version of the layout is kept in the `layout` file. A directory of an
+//! older layout, or of one from before layouts were versioned, is migrated
+//! to [`LAYOUT_VERSION`] one version at a time when opened. One of a newer
+//! layout is refused rather than risk a migration it does not know of. Only
+//! the commands running a node open it; the others read it as they find it.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use miette::Diagnostic;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::network;
+use crate::network::churn::CRAWLS_DIR_NAME;
+use crate::node::snapshots::METRICS_DIR_NAME;
+
+/// Version of the layout this schultz creates.
+pub const LAYOUT_VERSION: u32 = 1;
+
+pub const LAYOUT_FILE_NAME: &str = "layout";
+pub const IDENTITY_DIR_NAME: &str = "identity";
+pub const PEERS_FILE_NAME: &str = "peers.db";
+pub const CAPTURES_DIR_NAME: &str = "captures";
+pub const OBSERVATIONS_DB_NAME: &str = "observations.db";
+pub const CRAWL_CHECKPOINT_NAME: &str = "crawl.json";
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Could not set up the data directory at {}", .0.display())]
+    Io(PathBuf, #[source] io::Error),
+    #[error("Invalid layout version {1:?} in {}", .0.display())]
+    InvalidLayout(PathBuf, String),
+    #[error(
+        "The data directory at {} has layout {found}, newer than schultz knows of ({supported})",
+        .path.display()
+    )]
+    NewerLayout {
+        path: PathBuf,
+        found: u32,
+        supported: u32,
+    },
+}
+
+impl Diagnostic for StorageError {}
+
+/// Brings a data directory from the layout before `to` to `to`.
+struct Migration {
+    to: u32,
+    migrate: fn(&Path) -> io::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    to: 1,
+    migrate: managed_layout,
+}];
+
+/// From the unversioned directory, which only held the config file and the
+/// metrics, to the first managed layout.
+fn managed_layout(root: &Path) -> io::Result<()> {
+    for dir in [IDENTITY_DIR_NAME, CAPTURES_DIR_NAME, METRICS_DIR_NAME] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+    Ok(())
+}
+
+/// Part of the layout, as reported by `schultz data-dir info`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub exists: bool,
+    /// Number of files, those of subdirectories included.
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// What `schultz data-dir info` reports.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Info {
+    pub path: PathBuf,
+    pub layout_version: u32,
+    pub entries: Vec<Entry>,
+}
+
+/// A data directory of the current layout.
+#[derive(Clone, Debug)]
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    /// The data directory at `root` as it is, neither created nor migrated,
+    /// for commands that only read from it.
+    pub fn at(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Opens the data directory at `root`, creating it, or migrating it if
+    /// it is of an older layout.
+    pub fn open(root: &Path) -> Result<Self, StorageError> {
+        fs::create_dir_all(root).map_err(|e| StorageError::Io(root.to_path_buf(), e))?;
+        let data_dir = Self::at(root);
+        let found = data_dir.layout_version()?;
+        if found > LAYOUT_VERSION {
+            return Err(StorageError::NewerLayout {
+                path: data_dir.root,
+                found,
+                supported: LAYOUT_VERSION,
+            });
+        }
+        for migration in MIGRATIONS.iter().filter(|migration| migration.to > found) {
+            (migration.migrate)(root).map_err(|e| StorageError::Io(root.to_path_buf(), e))?;
+            data_dir.set_layout_version(migration.to)?;
+        }
+        Ok(data_dir)
+    }
+
+    /// The version of the layout, 0 for a directory from before layouts were
+    /// versioned.
+    pub fn layout_version(&self) -> Result<u32, StorageError> {
+        let path = self.root.join(LAYOUT_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let contents = contents.trim();
+                contents
+                    .parse()
+                    .map_err(|_| StorageError::InvalidLayout(path.clone(), contents.to_string()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(StorageError::Io(path, e)),
+        }
+    }
+
+    fn set_layout_version(&self, version: u32) -> Result<(), StorageError> {
+        let path = self.root.join(LAYOUT_FILE_NAME);
+        fs::write(&path, format!("{version}\n")).map_err(|e| StorageError::Io(path, e))
+    }
+
+    pub fn root(&self) -> &Path { &self.root }
+
+    pub fn config_file(&self) -> PathBuf { self.root.join(CONFIG_FILE_NAME) }
+
+    pub fn identity_dir(&self, network: Option<&str>) -> PathBuf {
+        let dir = self.root.join(IDENTITY_DIR_NAME);
+        match network {
+            Some(name) => dir.join(name),
+            None => dir,
+        }
+    }
+
+    pub fn peers_file(&self, network: Option<&str>) -> PathBuf {
+        match network {
+            Some(name) => self.root.join(format!("peers-{name}.db")),
+            None => self.root.join(PEERS_FILE_NAME),
+        }
+    }
+
+    pub fn captures_dir(&self) -> PathBuf { self.root.join(CAPTURES_DIR_NAME) }
+
+    pub fn metrics_dir(&self, network: Option<&str>) -> PathBuf {
+        let dir = self.root.join(METRICS_DIR_NAME);
+        match network {
+            Some(name) => dir.join(name),
+            None => dir,
+        }
+    }
+
+    pub fn observations_db(&self, network: Option<&str>) -> PathBuf {
+        match network {
+            Some(name) => self.root.join(format!("observations-{name}.db")),
+            None => self.root.join(OBSERVATIONS_DB_NAME),
+        }
+    }
+
+    pub fn crawl_checkpoint(&self, network: Option<&str>) -> PathBuf {
+        match network {
+            Some(name) => self.root.join(format!("crawl-{name}.json")),
+            None => self.root.join(CRAWL_CHECKPOINT_NAME),
+        }
+    }
+
+    pub fn crawls_dir(&self, network: Option<&str>) -> PathBuf {
+        let dir = self.root.join(CRAWLS_DIR_NAME);
+        match network {
+            Some(name) => dir.join(name),
+            None => dir,
+        }
+    }
+
+    /// Keeps the identity and the peers of `network` in the data directory,
+    /// unless `config` puts them elsewhere.
+    pub fn apply_defaults(&self, config: &mut network::Config, network: Option<&str>) {
+        config.identity_dir.get_or_insert_with(|| self.identity_dir(network));
+        config.peers_file.get_or_insert_with(|| self.peers_file(network));
+    }
+
+    /// The layout as used for `network`, with what every part holds.
+    pub fn info(&self, network: Option<&str>) -> Result<Info, StorageError> {
+        let entries = [
+            ("config", self.config_file()),
+            ("identity", self.identity_dir(network)),
+            ("peers", self.peers_file(network)),
+            ("captures", self.captures_dir()),
+            ("metrics", self.metrics_dir(network)),
+            ("observations", self.observations_db(network)),
+            ("crawl", self.crawl_checkpoint(network)),
+            ("crawls", self.crawls_dir(network)),
+        ]
+        .into_iter()
+        .map(|(name, path)| {
+            let (files, bytes) = usage(&path).map_err(|e| StorageError::Io(path.clone(), e))?;
+            Ok(Entry {
+                name,
+                exists: path.exists(),
+                path,
+                files,
+                bytes,
+            })
+        })
+        .collect::<Result<_, StorageError>>()?;
+        Ok(Info {
+            path: self.root.clone(),
+            layout_version: self.layout_version()?,
+            entries,
+        })
+    }
+}
+
+/// Number of files at `path` and their size, those of subdirectories
+/// included, nothing if there is nothing at `path`.
+fn usage(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e),
+    };
+    if !metadata.is_dir() {
+        return Ok((1, metadata.len()));
+    }
+    let mut total = (0, 0);
+    for entry in fs::read_dir(path)? {
+        let (files, bytes) = usage(&entry?.path())?;
+        total = (total.0 + files, total.1 + bytes);
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn older_layouts_are_migrated_and_newer_ones_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("migrate");
+        // A directory from before layouts were versioned.
+        fs::create_dir_all(root.join(METRICS_DIR_NAME)).unwrap();
+        fs::write(root.join(CONFIG_FILE_NAME), "[node]\n").unwrap();
+
+        let data_dir = DataDir::open(&root).unwrap();
+        assert_eq!(data_dir.layout_version().unwrap(), LAYOUT_VERSION);
+        assert!(data_dir.identity_dir(None).is_dir());
+        assert!(data_dir.captures_dir().is_dir());
+        assert_eq!(
+            fs::read_to_string(data_dir.config_file()).unwrap(),
+            "[node]\n"
+        );
+        // Opening it again changes nothing.
+        DataDir::open(&root).unwrap();
+
+        fs::write(
+            root.join(LAYOUT_FILE_NAME),
+            format!("{}\n", LAYOUT_VERSION + 1),
+        )
+        .unwrap();
+        assert!(matches!(
+            DataDir::open(&root),
+            Err(StorageError::NewerLayout { found, .. }) if found == LAYOUT_VERSION + 1
+        ));
+        fs::write(root.join(LAYOUT_FILE_NAME), "one\n").unwrap();
+        assert!(matches!(
+            DataDir::open(&root),
+            Err(StorageError::InvalidLayout(..))
+        ));
+    }
+
+    #[test]
+    fn networks_keep_their_state_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("networks");
+        let data_dir = DataDir::open(&root).unwrap();
+        let mut config = network::Config::default();
+        data_dir.apply_defaults(&mut config, Some("testnet"));
+        assert_eq!(config.identity_dir, Some(root.join("identity/testnet")));
+        assert_eq!(config.peers_file, Some(root.join("peers-testnet.db")));
+
+        config.peers_file = Some(PathBuf::from("/elsewhere/peers.db"));
+        data_dir.apply_defaults(&mut config, None);
+        assert_eq!(config.identity_dir, Some(root.join("identity/testnet")));
+        assert_eq!(
+            config.peers_file,
+            Some(PathBuf::from("/elsewhere/peers.db"))
+        );
+
+        fs::write(data_dir.peers_file(None), "[]").unwrap();
+        fs::create_dir_all(data_dir.metrics_dir(None).join("testnet")).unwrap();
+        fs::write(data_dir.metrics_dir(None).join("testnet/1.json"), "{}").unwrap();
+        let info = data_dir.info(None).unwrap();
+        let usage: Vec<_> = info
+            .entries
+            .iter()
+            .map(|entry| (entry.name, entry.exists, entry.files, entry.bytes))
+            .collect();
+        assert_eq!(
+            usage,
+            [
+                ("config", false, 0, 0),
+                ("identity", true, 0, 0),
+                ("peers", true, 1, 2),
+                ("captures", true, 0, 0),
+                ("metrics", true, 1, 2),
+                ("observations", false, 0, 0),
+                ("crawl", false, 0, 0),
+                ("crawls", false, 0, 0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Log output and optional OpenTelemetry trace export.
+//!
+//! Logs always go to stdout, filtered by `RUST_LOG`. With an OTLP endpoint
+//! configured, spans are additionally exported over gRPC, so a bootstrap can
+//! be followed as a trace next to the casper-node it talks to.
+//!
+//! The filter can be replaced while running with [`set_log_filter`], which
+//! `schultz log-filter` does through the control socket, e.g. to log
+//! `schultz::network::tls` at trace level without restarting the node.
+
+use std::sync::OnceLock;
+
+use miette::Diagnostic;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::runtime;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Registry;
+
+/// Service name spans are reported under.
+const SERVICE_NAME: &str = "schultz";
+
+/// Handle to the filter of the installed subscriber.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    #[error("Invalid log filter {0:?}")]
+    Invalid(String, #[source] ParseError),
+    #[error("No log filter installed")]
+    NotInstalled,
+    #[error("Could not replace the log filter")]
+    Reload(#[source] reload::Error),
+}
+
+impl Diagnostic for LogFilterError {}
+
+/// Flushes pending spans when dropped.
+pub struct TelemetryGuard {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {e}");
+            }
+        }
+    }
+}
+
+/// Installs the global tracing subscriber, exporting spans to
+/// `otlp_endpoint` if one is given.
+///
+/// Must be called from within a Tokio runtime when exporting.
+pub fn init(otlp_endpoint: Option<&str>) -> miette::Result<TelemetryGuard> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let provider = otlp_endpoint
+        .map(|endpoint| {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to create OTLP exporter for {endpoint}"))?;
+
+            Ok::<_, miette::Report>(
+                TracerProvider::builder()
+                    .with_batch_exporter(exporter, runtime::Tokio)
+                    .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .try_init()
+        .into_diagnostic()
+        .wrap_err("Failed to install the tracing subscriber")?;
+    let _ = LOG_FILTER.set(handle);
+
+    Ok(TelemetryGuard { provider })
+}
+
+/// The directives logs are filtered by, e.g.
+/// `schultz::network::tls=trace,info`.
+pub fn log_filter() -> Result<String, LogFilterError> {
+    let handle = LOG_FILTER.get().ok_or(LogFilterError::NotInstalled)?;
+    handle.with_current(ToString::to_string).map_err(LogFilterError::Reload)
+}
+
+/// Filters logs by `directives` from now on, written like `RUST_LOG`.
+/// Targets no directive matches are logged at info level.
+pub fn set_log_filter(directives: &str) -> Result<(), LogFilterError> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse(directives)
+        .map_err(|e| LogFilterError::Invalid(directives.to_string(), e))?;
+    let handle = LOG_FILTER.get().ok_or(LogFilterError::NotInstalled)?;
+    handle.reload(filter).map_err(LogFilterError::Reload)
+}
@@ -0,0 +1,1048 @@
+//! Helpers for tests running several schultz nodes in one process.
+//!
+//! Peers started here listen on a free loopback port, or on a
+//! [`MemoryNetwork`] without touching the OS, and present an identity derived
+//! from a seed, so a test sees the same fingerprints on every run.
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use casper_types::AsymmetricType;
+use casper_types::ProtocolVersion;
+use casper_types::PublicKey;
+use casper_types::SecretKey;
+use tokio::task::JoinHandle;
+
+use crate::error::Result;
+use crate::network::crawl::CrawledNode;
+use crate::network::node_id::NodeId;
+use crate::network::testing::MemoryNetwork;
+use crate::network::tls::Identity;
+use crate::network::transport::Transport;
+use crate::network::Config;
+use crate::node::Node;
+use crate::utils::Fingerprint;
+
+/// Directory of the example chainspec every test peer runs.
+pub fn chainspec_dir() -> PathBuf { PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples") }
+
+/// The identity a peer spawned with `seed` presents.
+pub fn identity(seed: u64) -> Identity {
+    Identity::from_seed(seed).expect("seeded identities are always valid")
+}
+
+/// The validator key derived from `seed`.
+pub fn secret_key(seed: u8) -> SecretKey {
+    SecretKey::ed25519_from_bytes([seed; 32]).expect("any 32 bytes are an ed25519 key")
+}
+
+pub fn public_key(seed: u8) -> PublicKey { PublicKey::from(&secret_key(seed)) }
+
+/// A `[[validators]]` table of a `global_state.toml`, for the validator
+/// derived from `seed`.
+pub fn validator_entry(seed: u8, weight: &str) -> String {
+    format!(
+        "[[validators]]\npublic_key = \"{}\"\nweight = \"{weight}\"\n",
+        public_key(seed).to_hex()
+    )
+}
+
+/// Builds the nodes crawls reach, for tests of what is made of them.
+pub struct CrawledNodeBuilder(CrawledNode);
+
+impl CrawledNodeBuilder {
+    /// The node with the id derived from `seed`, reached on and announcing
+    /// `addr`, on protocol 1.0.0 of the example network, which gossiped no
+    /// peers and was not timed.
+    pub fn new(seed: u8, addr: SocketAddr) -> Self {
+        Self(CrawledNode {
+            node_id: NodeId::from(Fingerprint::from_bytes([seed; Fingerprint::SIZE])),
+            addrs: BTreeSet::from([addr]),
+            public_addr: addr,
+            network_name: "casper-test".to_string(),
+            protocol_version: ProtocolVersion::V1_0_0,
+            latency_ms: None,
+            peers: BTreeSet::new(),
+        })
+    }
+
+    pub fn protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.0.protocol_version = protocol_version;
+        self
+    }
+
+    pub fn latency_ms(mut self, latency_ms: u64) -> Self {
+        self.0.latency_ms = Some(latency_ms);
+        self
+    }
+
+    /// The addresses the node gossiped.
+    pub fn peers(mut self, peers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.0.peers = peers.into_iter().collect();
+        self
+    }
+
+    pub fn build(self) -> CrawledNode { self.0 }
+}
+
+/// An in-process node. Dropping it stops its event loop and shuts it down in
+/// the background, [`TestPeer::shutdown`] waits for that.
+pub struct TestPeer {
+    pub node: Node,
+    event_loop: JoinHandle<()>,
+}
+
+impl TestPeer {
+    /// Starts a node with the identity derived from `seed`, connected to
+    /// `bootnodes`, and runs its event loop in the background.
+    pub async fn spawn(seed: u64, bootnodes: Vec<SocketAddr>) -> Result<Self> {
+        Self::spawn_with_config(seed, bootnodes, Config::default()).await
+    }
+
+    pub async fn spawn_with_config(
+        seed: u64,
+        bootnodes: Vec<SocketAddr>,
+        config: Config,
+    ) -> Result<Self> {
+        let node = Node::with_identity(
+            identity(seed),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            bootnodes,
+            chainspec_dir(),
+            config,
+        )
+        .await?;
+        Ok(Self::run(node))
+    }
+
+    /// Like [`TestPeer::spawn_with_config`], but connected through `network`
+    /// instead of TCP and TLS.
+    pub async fn spawn_in_memory(
+        network: &MemoryNetwork,
+        seed: u64,
+        bootnodes: Vec<SocketAddr>,
+        config: Config,
+    ) -> Result<Self> {
+        Self::spawn_with_transport(Arc::new(network.clone()), seed, bootnodes, config).await
+    }
+
+    /// Like [`TestPeer::spawn_with_config`], but connected through
+    /// `transport`, e.g. one injecting [`faults`](crate::network::faults).
+    pub async fn spawn_with_transport(
+        transport: Arc<dyn Transport>,
+        seed: u64,
+        bootnodes: Vec<SocketAddr>,
+        config: Config,
+    ) -> Result<Self> {
+        let node = Node::with_transport(
+            transport,
+            identity(seed),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            bootnodes,
+            chainspec_dir(),
+            config,
+        )
+        .await?;
+        Ok(Self::run(node))
+    }
+
+    fn run(node: Node) -> Self {
+        let event_loop = tokio::spawn({
+            let node = node.clone();
+            async move { node.keepalive().await }
+        });
+        Self { node, event_loop }
+    }
+
+    /// The address the peer listens on.
+    pub async fn addr(&self) -> SocketAddr { self.node.manager.read().await.schultz_addr() }
+
+    /// Peers whose handshake with this one completed.
+    pub async fn connected_peers(&self) -> Vec<SocketAddr> {
+        self.node.manager.read().await.connected_peers().await
+    }
+
+    /// Stops the node, returning once its port and connections are closed.
+    pub async fn shutdown(self) {
+        self.event_loop.abort();
+        self.node.shutdown().await;
+    }
+}
+
+impl Drop for TestPeer {
+    fn drop(&mut self) {
+        self.event_loop.abort();
+        // Outside a runtime there is nothing left running to shut down.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let node = self.node.clone();
+            runtime.spawn(async move { node.shutdown().await });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use casper_hashing::Digest;
+    use casper_types::TimeDiff;
+    use tokio::io::AsyncReadExt;
+    #[cfg(unix)]
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::crypto::ConsensusKeys;
+    use crate::error::Error;
+    use crate::network;
+    use crate::network::connection::Direction;
+    use crate::network::error::FetchError;
+    use crate::network::error::ManagerError;
+    use crate::network::faults::Faults;
+    use crate::network::faults::FaultyTransport;
+    use crate::network::fetch::Request;
+    use crate::network::fetch::Tag;
+    use crate::network::gossip::NodePayload;
+    use crate::network::handshake::Handshake;
+    use crate::network::history::EventKind;
+    use crate::network::manager::PeerInfo;
+    use crate::network::message::Message;
+    use crate::network::observe::Gossiper;
+    use crate::network::progress::BootstrapError;
+    use crate::network::progress::NoProgress;
+    use crate::network::progress::Phase;
+    use crate::network::progress::Progress;
+    use crate::network::reputation::Behavior;
+    use crate::network::resolve::Bootnode;
+    use crate::network::tls::TlsOptions;
+    use crate::network::transport::TlsTransport;
+    use crate::network::transport::Transport;
+    use crate::network::wire_log::Capture;
+    use crate::network::wire_log::Decoded;
+    use crate::network::PeerSession;
+    #[cfg(unix)]
+    use crate::node::control;
+    use crate::node::status::Status;
+    use crate::primitives::Chainspec;
+    use crate::primitives::Nonce;
+
+    #[test]
+    fn seeded_identities_are_reproducible() {
+        assert_eq!(identity(7).fingerprint(), identity(7).fingerprint());
+        assert_ne!(identity(7).fingerprint(), identity(8).fingerprint());
+    }
+
+    #[tokio::test]
+    async fn peers_handshake_in_process() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+
+        assert_eq!(second.connected_peers().await, vec![first.addr().await]);
+
+        // The accepting side registers the peer under its ephemeral address.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while first.connected_peers().await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("first peer saw the handshake");
+    }
+
+    #[tokio::test]
+    async fn wire_log_records_both_directions_of_a_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wire.log");
+        let config = Config {
+            wire_log: Some(path.clone()),
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], config).await.unwrap();
+        let _second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+
+        let records: Vec<_> = Capture::open(&path).unwrap().map(|record| record.unwrap()).collect();
+        for direction in [Direction::Inbound, Direction::Outbound] {
+            assert!(
+                records
+                    .iter()
+                    .any(|record| record.direction == direction && record.tag == "handshake"),
+                "no {direction} handshake in {records:?}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn peers_report_how_and_when_they_connected() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+        let version = second.node.manager.read().await.chainspec.protocol_version();
+
+        let peers = second.node.manager.read().await.peers().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].addr, first.addr().await);
+        assert_eq!(peers[0].direction, Direction::Outbound);
+        assert_eq!(peers[0].protocol_version, Some(version));
+        assert_eq!(peers[0].node_id, Some(identity(1).node_id()));
+        assert!(peers[0].last_seen.is_some());
+        assert!(peers[0].bytes_read > 0 && peers[0].bytes_written > 0);
+
+        // The accepting side knows the peer by the certificate it presented
+        // too, and answers on its control socket.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while first.connected_peers().await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("first peer saw the handshake");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = control::bind(&path).unwrap();
+        tokio::spawn(control::serve(
+            listener,
+            first.node.clone(),
+            mpsc::channel(1).0,
+        ));
+        let control::Response::Peers(peers) =
+            control::request(&path, &control::Request::Peers).await.unwrap()
+        else {
+            panic!("the node could not answer");
+        };
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].direction, Direction::Inbound);
+        assert_eq!(peers[0].protocol_version, Some(version));
+        assert_eq!(peers[0].node_id, Some(identity(2).node_id()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reloads_are_asked_for_over_the_control_socket() {
+        let peer = TestPeer::spawn(1, vec![]).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = control::bind(&path).unwrap();
+        let (reloads, mut requests) = mpsc::channel(1);
+        tokio::spawn(control::serve(listener, peer.node.clone(), reloads));
+        tokio::spawn(async move {
+            let reload = requests.recv().await.unwrap();
+            reload.send(Ok(vec!["network.max_connections".to_string()])).unwrap();
+            let reload = requests.recv().await.unwrap();
+            reload.send(Err("invalid configuration".to_string())).unwrap();
+        });
+
+        let reloaded = control::request(&path, &control::Request::Reload).await.unwrap();
+        assert_eq!(
+            reloaded,
+            control::Response::Reloaded(vec!["network.max_connections".to_string()])
+        );
+        let kept = control::request(&path, &control::Request::Reload).await.unwrap();
+        assert_eq!(
+            kept,
+            control::Response::Error("invalid configuration".to_string())
+        );
+        // Nobody is left to reload.
+        let unanswered = control::request(&path, &control::Request::Reload).await.unwrap();
+        assert!(matches!(unanswered, control::Response::Error(_)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn network_state_covers_peers_scores_and_events() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+        let first_addr = first.addr().await;
+        second.node.manager.read().await.disconnect(first_addr).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = control::bind(&path).unwrap();
+        tokio::spawn(control::serve(
+            listener,
+            second.node.clone(),
+            mpsc::channel(1).0,
+        ));
+        let control::Response::NetworkState(state) =
+            control::request(&path, &control::Request::NetworkState).await.unwrap()
+        else {
+            panic!("the node could not answer");
+        };
+
+        assert_eq!(state.addr, second.addr().await);
+        assert_eq!(state.fingerprint, identity(2).fingerprint());
+        assert!(state.peers.is_empty());
+        assert!(state.scores[&first_addr] > 0);
+        let kinds: Vec<_> = state
+            .events
+            .into_iter()
+            .filter(|event| event.peer == first_addr)
+            .map(|event| event.kind)
+            .collect();
+        // Messages exchanged alongside the handshake may count towards the
+        // score already.
+        assert!(
+            matches!(
+                kinds.as_slice(),
+                [
+                    EventKind::Connected {
+                        direction: Direction::Outbound
+                    },
+                    EventKind::Scored {
+                        behavior: Behavior::HandshakeCompleted,
+                        ..
+                    },
+                    EventKind::Dropped,
+                ]
+            ),
+            "{kinds:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn validators_certify_the_session_in_their_handshakes() {
+        let key = chainspec_dir().join("secret_key.pem");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wire.log");
+        let first = TestPeer::spawn_with_config(
+            1,
+            vec![],
+            Config {
+                consensus_key: Some(key.clone()),
+                ..Config::default()
+            },
+        )
+        .await
+        .unwrap();
+        let first_addr = first.addr().await;
+        let transport = Arc::new(TlsTransport::new(identity(2), TlsOptions::default()));
+        let config = Config {
+            consensus_key: Some(key.clone()),
+            wire_log: Some(path.clone()),
+            ..Config::default()
+        };
+        let _second =
+            TestPeer::spawn_with_transport(transport.clone(), 2, vec![first_addr], config)
+                .await
+                .unwrap();
+
+        let session = transport.session_id(first_addr).unwrap();
+        let public_key = ConsensusKeys::load(&key).unwrap().public_key().clone();
+        let handshakes: Vec<_> = Capture::open(&path)
+            .unwrap()
+            .map(|record| record.unwrap())
+            .filter(|record| record.tag == "handshake")
+            .collect();
+        assert_eq!(handshakes.len(), 2, "{handshakes:?}");
+        for record in handshakes {
+            let Decoded::Message(message) = record.decode() else {
+                panic!("undecoded handshake");
+            };
+            let certificate = Handshake::from_message(&message)
+                .and_then(|handshake| handshake.consensus_certificate)
+                .expect("the handshake carries a consensus certificate");
+            assert_eq!(certificate.public_key(), &public_key);
+            certificate.validate(&session).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnecting_opens_a_new_connection() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+        let second = TestPeer::spawn(2, vec![first_addr]).await.unwrap();
+
+        let status = Status::of(&second.node).await;
+        let before = status.connected_peers[0].connection_id.unwrap();
+
+        let manager = second.node.manager.read().await;
+        manager.disconnect(first_addr).await;
+        assert!(manager.connection_ids().await.is_empty());
+        manager.connect(&first_addr).await.unwrap();
+
+        let after = manager.connection_ids().await[&first_addr];
+        assert!(after > before);
+    }
+
+    #[tokio::test]
+    async fn inbound_connections_from_one_ip_are_capped() {
+        let config = Config {
+            max_inbound_per_ip: 1,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], config).await.unwrap();
+        let first_addr = first.addr().await;
+        let _second = TestPeer::spawn(2, vec![first_addr]).await.unwrap();
+
+        // A third connection from the same IP is closed as soon as it is
+        // accepted, before the TLS handshake.
+        let transport = TlsTransport::new(identity(3), Config::default().tls_options());
+        assert!(transport.connect(first_addr).await.is_err());
+        assert_eq!(first.connected_peers().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn peers_stalling_the_tls_handshake_hold_up_no_one_else() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+
+        // Connects but never says hello.
+        let _silent = tokio::net::TcpStream::connect(first_addr).await.unwrap();
+        let second =
+            tokio::time::timeout(Duration::from_secs(5), TestPeer::spawn(2, vec![first_addr]))
+                .await
+                .expect("second peer connected while the first was stalled")
+                .unwrap();
+        assert_eq!(second.connected_peers().await, vec![first_addr]);
+    }
+
+    #[tokio::test]
+    async fn reconnecting_nodes_replace_their_stale_connection() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+        let node_id = identity(3).node_id();
+        let transport = TlsTransport::new(identity(3), Config::default().tls_options());
+        let connections_of = |peers: Vec<PeerInfo>| {
+            peers.into_iter().filter(|peer| peer.node_id == Some(node_id)).count()
+        };
+
+        let mut stale = transport.connect(first_addr).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while connections_of(first.node.manager.read().await.peers().await) == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("first peer saw the connection");
+        let stale_addr = first.node.manager.read().await.peer_addr(&node_id).await.unwrap();
+        let _fresh = transport.connect(first_addr).await.unwrap();
+
+        let read = tokio::time::timeout(Duration::from_secs(5), stale.read(&mut [0; 1])).await;
+        assert!(
+            matches!(read, Ok(Ok(0)) | Ok(Err(_))),
+            "stale connection still open"
+        );
+        let manager = first.node.manager.read().await;
+        assert_eq!(connections_of(manager.peers().await), 1);
+        let addr = manager.peer_addr(&node_id).await.unwrap();
+        assert_eq!(manager.peer_id(&addr), Some(node_id));
+        // Nothing is kept of where the node was before.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while manager.peer_id(&stale_addr).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the stale address was forgotten");
+    }
+
+    #[tokio::test]
+    async fn full_nodes_evict_their_worst_peer() {
+        let config = Config {
+            max_connections: 1,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], config).await.unwrap();
+        let first_addr = first.addr().await;
+        let _second = TestPeer::spawn(2, vec![first_addr]).await.unwrap();
+        let misbehaving = first.connected_peers().await[0];
+        first
+            .node
+            .manager
+            .read()
+            .await
+            .reputation()
+            .record(misbehaving, Behavior::ProtocolViolation);
+
+        let _third = TestPeer::spawn(3, vec![first_addr]).await.unwrap();
+        let connected = first.connected_peers().await;
+        assert_eq!(connected.len(), 1);
+        assert_ne!(connected[0], misbehaving);
+
+        let status = Status::of(&first.node).await;
+        assert_eq!(status.connected_peers[0].score, 10);
+    }
+
+    #[tokio::test]
+    async fn reloaded_connection_limits_apply_right_away() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+        let reloaded = Config {
+            max_connections: 1,
+            ping_interval: TimeDiff::from_seconds(1),
+            ..Config::default()
+        };
+        assert_eq!(first.node.reload(&reloaded).await, vec!["ping_interval"]);
+
+        let _second = TestPeer::spawn(2, vec![first_addr]).await.unwrap();
+        let transport = TlsTransport::new(identity(3), Config::default().tls_options());
+        assert!(transport.connect(first_addr).await.is_err());
+        assert_eq!(first.connected_peers().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fetches_ask_every_peer_and_get_refused() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let request = Request::BlockHeader(Digest::hash(b"block"));
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(
+            first.node.fetch(request, timeout).await,
+            Err(FetchError::NoPeers(Tag::BlockHeader, _))
+        ));
+
+        let second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+        // Schultz holds no items, so it refuses right away instead of letting
+        // the request time out.
+        let fetched = tokio::time::timeout(timeout, second.node.fetch(request, timeout)).await;
+        assert!(matches!(
+            fetched,
+            Ok(Err(FetchError::NotFound { tried: 1, .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gossip_read_from_peers_can_be_observed() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let mut observed = first.node.manager.read().await.observe();
+        let second = TestPeer::spawn(2, vec![first.addr().await]).await.unwrap();
+
+        // The newcomer announces its own address to the peer it joined.
+        let second_addr = second.addr().await.to_string();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let observed = observed.recv().await.unwrap();
+                if observed.gossiper == Gossiper::Address && observed.item == second_addr {
+                    break;
+                }
+            }
+        })
+        .await
+        .expect("the announced address was observed");
+    }
+
+    /// Records every reported phase.
+    #[derive(Default)]
+    struct Recorder(std::sync::Mutex<Vec<(&'static str, Phase)>>);
+
+    impl Progress for Recorder {
+        fn started(&self, _bootnode: &Bootnode, phase: Phase) {
+            self.0.lock().unwrap().push(("started", phase));
+        }
+
+        fn completed(&self, _bootnode: &Bootnode, phase: Phase) {
+            self.0.lock().unwrap().push(("completed", phase));
+        }
+
+        fn retrying(
+            &self,
+            _bootnode: &Bootnode,
+            phase: Phase,
+            _error: &ManagerError,
+            _attempt: u32,
+            _delay: Duration,
+        ) {
+            self.0.lock().unwrap().push(("retrying", phase));
+        }
+    }
+
+    #[tokio::test]
+    async fn bootstrap_reports_every_phase() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn(2, vec![]).await.unwrap();
+
+        let recorder = Recorder::default();
+        second.node.bootstrap(&[first.addr().await.into()], &recorder).await.unwrap();
+
+        let expected: Vec<_> = Phase::ALL
+            .into_iter()
+            .flat_map(|phase| [("started", phase), ("completed", phase)])
+            .collect();
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+    }
+
+    /// Records the address a bootnode was reached on.
+    #[derive(Default)]
+    struct Connected(std::sync::Mutex<Option<SocketAddr>>);
+
+    impl Progress for Connected {
+        fn connected(&self, _bootnode: &Bootnode, addr: SocketAddr) {
+            *self.0.lock().unwrap() = Some(addr);
+        }
+    }
+
+    #[tokio::test]
+    async fn bootstraps_from_a_bootnode_given_by_name() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn(2, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+
+        // Whatever else localhost resolves to, only 127.0.0.1 answers.
+        let bootnode = format!("localhost:{}", first_addr.port()).parse().unwrap();
+        let connected = Connected::default();
+        second.node.bootstrap(&[bootnode], &connected).await.unwrap();
+
+        assert_eq!(*connected.0.lock().unwrap(), Some(first_addr));
+        assert_eq!(second.connected_peers().await, vec![first_addr]);
+    }
+
+    #[tokio::test]
+    async fn lost_bootnodes_are_reconnected_by_name() {
+        // Gossiped peers are not dialed, so only the bootnode is reconnected.
+        let config = Config {
+            target_outgoing_connections: 0,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let second = TestPeer::spawn_with_config(2, vec![], config).await.unwrap();
+        let first_addr = first.addr().await;
+        let bootnode: Bootnode = format!("localhost:{}", first_addr.port()).parse().unwrap();
+        second.node.bootstrap(&[bootnode], &NoProgress).await.unwrap();
+
+        second.node.manager.read().await.disconnect(first_addr).await;
+        assert!(!second.connected_peers().await.contains(&first_addr));
+
+        second.node.reconnect_bootnodes().await;
+        assert_eq!(second.connected_peers().await, vec![first_addr]);
+    }
+
+    #[tokio::test]
+    async fn bootstrap_retries_and_reports_the_failed_phase() {
+        let config = Config {
+            bootstrap_attempts: 2,
+            bootstrap_retry_delay: TimeDiff::from_millis(10),
+            ..Config::default()
+        };
+        let peer = TestPeer::spawn_with_config(1, vec![], config).await.unwrap();
+        // Nothing listens on a port we just bound and released.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let recorder = Recorder::default();
+        let error = peer.node.bootstrap(&[closed.into()], &recorder).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Bootstrap(BootstrapError {
+                phase: Phase::Connect,
+                ..
+            })
+        ));
+        let retries = recorder
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(event, _)| *event == "retrying")
+            .count();
+        assert_eq!(retries, 1);
+    }
+
+    #[tokio::test]
+    async fn restarted_peer_rejoins_through_known_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            peers_file: Some(dir.path().join("peers.json")),
+            bootstrap_attempts: 1,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+
+        let second =
+            TestPeer::spawn_with_config(2, vec![first_addr], config.clone()).await.unwrap();
+        let known = second.node.peers.lock().await.peers()[0].clone();
+        assert_eq!(known.addr, first_addr);
+        assert_eq!(known.node_id, Some(identity(1).node_id()));
+        let mut events = first.node.manager.read().await.network_events();
+        let second_addr = second.addr().await;
+        second.shutdown().await;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while events.recv().await.unwrap().kind != EventKind::Closed {}
+        })
+        .await
+        .expect("first peer saw the second one leave");
+        assert!(tokio::net::TcpStream::connect(second_addr).await.is_err());
+
+        // The bootnode is gone after the restart, the peer from before is not.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let restarted = TestPeer::spawn_with_config(2, vec![closed], config).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !restarted.connected_peers().await.contains(&first_addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("restarted peer dialed the known peer");
+    }
+
+    #[tokio::test]
+    async fn renewed_certificate_is_presented_after_reconnecting() {
+        let first = TestPeer::spawn(1, vec![]).await.unwrap();
+        let first_addr = first.addr().await;
+        let config = Config {
+            cert_drain_window: TimeDiff::from_millis(500),
+            ..Config::default()
+        };
+        let second = TestPeer::spawn_with_config(2, vec![first_addr], config).await.unwrap();
+        let original = second.node.manager.read().await.identity().clone();
+
+        let wait_for_incoming = |except: Vec<SocketAddr>| {
+            let first = &first;
+            async move {
+                tokio::time::timeout(Duration::from_secs(10), async {
+                    loop {
+                        let peers = first.connected_peers().await;
+                        if let Some(addr) = peers.into_iter().find(|addr| !except.contains(addr)) {
+                            return addr;
+                        }
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .expect("first peer saw the second connect")
+            }
+        };
+        let before = wait_for_incoming(vec![]).await;
+
+        second.node.rotate_certificate().await.unwrap();
+        // The connection stays up on the previous certificate for now.
+        let manager = second.node.manager.read().await;
+        assert_eq!(manager.stale_connections().await, [first_addr]);
+        drop(manager);
+        assert!(first.connected_peers().await.contains(&before));
+
+        // The reconnected peer comes in from a new ephemeral port.
+        wait_for_incoming(vec![before]).await;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !second.connected_peers().await.contains(&first_addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("second peer reconnected to the first");
+        let manager = second.node.manager.read().await;
+        assert!(manager.stale_connections().await.is_empty());
+        let renewed = manager.identity().clone();
+        assert_eq!(renewed.fingerprint(), original.fingerprint());
+        assert_ne!(renewed.to_der().unwrap(), original.to_der().unwrap());
+    }
+
+    #[tokio::test]
+    async fn in_memory_peers_discover_each_other() {
+        let network = MemoryNetwork::new();
+        let config = Config::default();
+        let hub = TestPeer::spawn_in_memory(&network, 1, vec![], config.clone()).await.unwrap();
+        let hub_addr = hub.addr().await;
+        let first = TestPeer::spawn_in_memory(&network, 2, vec![hub_addr], config.clone())
+            .await
+            .unwrap();
+        let second = TestPeer::spawn_in_memory(&network, 3, vec![hub_addr], config).await.unwrap();
+
+        // Both peers announce themselves to the hub, which relays each
+        // address to the other peer. Whichever dials first wins, the other
+        // then sees it as an incoming peer and does not dial back.
+        let first_addr = first.addr().await;
+        let second_addr = second.addr().await;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !first.connected_peers().await.contains(&second_addr)
+                && !second.connected_peers().await.contains(&first_addr)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("one peer connected to the other through gossip");
+    }
+
+    #[tokio::test]
+    async fn peers_cut_off_by_injected_faults_time_out() {
+        let network = MemoryNetwork::new();
+        let config = Config {
+            ping_interval: TimeDiff::from_millis(200),
+            max_missed_pongs: 2,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_in_memory(&network, 1, vec![], config.clone()).await.unwrap();
+        let faults = Faults::new(0);
+        faults.update(|plan| plan.latency = Duration::from_millis(20));
+        let transport = FaultyTransport::new(Arc::new(network.clone()), faults.clone());
+        let _second = TestPeer::spawn_with_transport(
+            Arc::new(transport),
+            2,
+            vec![first.addr().await],
+            config,
+        )
+        .await
+        .unwrap();
+
+        // Pongs come back no faster than the injected latency.
+        let latency = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let latencies = first.node.manager.read().await.peer_latencies().await;
+                if let Some(latency) = latencies.values().next() {
+                    break *latency;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the second peer answered a ping");
+        assert!(latency >= Duration::from_millis(20));
+
+        // Once the second peer's writes reset its connection, its pongs stop
+        // and the first peer gives up on it.
+        faults.update(|plan| plan.disconnect_probability = 1.0);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !first.connected_peers().await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the first peer dropped the second");
+    }
+
+    #[tokio::test]
+    async fn newcomers_are_handed_the_known_peers() {
+        let network = MemoryNetwork::new();
+        // Long enough that nothing is gossiped periodically during the test.
+        let config = Config {
+            gossip_interval: TimeDiff::from_seconds(3600),
+            ..Config::default()
+        };
+        let relay = TestPeer::spawn_in_memory(&network, 1, vec![], config.clone()).await.unwrap();
+        let relay_addr = relay.addr().await;
+        // The first peer never dials the second, which has to learn about it
+        // from the relay.
+        let first_config = Config {
+            target_outgoing_connections: 0,
+            ..config.clone()
+        };
+        let first = TestPeer::spawn_in_memory(&network, 2, vec![relay_addr], first_config)
+            .await
+            .unwrap();
+        let first_addr = first.addr().await;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !relay.node.address_book.lock().await.known().any(|addr| *addr == first_addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("relay learned the first peer's address");
+
+        let second =
+            TestPeer::spawn_in_memory(&network, 3, vec![relay_addr], config).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !second.node.address_book.lock().await.known().any(|addr| *addr == first_addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("relay handed the first peer's address to the second");
+    }
+
+    #[tokio::test]
+    async fn peers_on_another_protocol_version_are_refused_unless_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let newer = dir.path().to_path_buf();
+        let chainspec = std::fs::read_to_string(chainspec_dir().join("chainspec.toml")).unwrap();
+        let chainspec = chainspec.replace("version = '1.5.2'", "version = '1.5.3'");
+        std::fs::write(newer.join("chainspec.toml"), chainspec).unwrap();
+        let spawn_newer = |config: Config, bootnode: SocketAddr| {
+            let newer = newer.clone();
+            async move {
+                let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+                Node::with_identity(identity(2), addr, vec![bootnode], newer, config).await
+            }
+        };
+
+        let strict = Config {
+            bootstrap_attempts: 1,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], strict.clone()).await.unwrap();
+        let error = spawn_newer(strict, first.addr().await).await.err().unwrap();
+        assert!(matches!(
+            error,
+            Error::Bootstrap(BootstrapError {
+                phase: Phase::Handshake,
+                source: ManagerError::HandshakeRejected(..),
+                ..
+            })
+        ));
+        assert!(first.connected_peers().await.is_empty());
+
+        let lenient = Config {
+            allow_version_mismatch: true,
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], lenient.clone()).await.unwrap();
+        let first_addr = first.addr().await;
+        let newer_node = spawn_newer(lenient, first_addr).await.unwrap();
+        assert_eq!(
+            newer_node.manager.read().await.connected_peers().await,
+            vec![first_addr]
+        );
+    }
+
+    #[tokio::test]
+    async fn bootnodes_on_another_network_are_not_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = dir.path().to_path_buf();
+        let chainspec = std::fs::read_to_string(chainspec_dir().join("chainspec.toml")).unwrap();
+        let chainspec = chainspec.replace("name = 'casper'", "name = 'casper-other'");
+        std::fs::write(other.join("chainspec.toml"), chainspec).unwrap();
+
+        let config = Config {
+            bootstrap_attempts: 3,
+            bootstrap_retry_delay: TimeDiff::from_millis(10),
+            ..Config::default()
+        };
+        let first = TestPeer::spawn_with_config(1, vec![], config.clone()).await.unwrap();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let node = Node::with_identity(identity(2), addr, vec![], other.clone(), config)
+            .await
+            .unwrap();
+
+        let recorder = Recorder::default();
+        let error = node.bootstrap(&[first.addr().await.into()], &recorder).await.unwrap_err();
+        let Error::Bootstrap(error) = error else {
+            panic!("expected a bootstrap error, got {error}");
+        };
+        assert_eq!(error.source.code(), "handshake.wrong_network");
+        assert!(error.source.is_peer_fault());
+        assert!(recorder.0.lock().unwrap().iter().all(|(event, _)| *event != "retrying"));
+    }
+
+    #[tokio::test]
+    async fn sessions_check_the_peer_and_exchange_messages() {
+        let peer = TestPeer::spawn(1, vec![]).await.unwrap();
+        let addr = peer.addr().await;
+        let chainspec = Chainspec::from_path(chainspec_dir()).unwrap();
+
+        let mut session: PeerSession<NodePayload> =
+            network::connect(addr, &identity(2), &chainspec).await.unwrap();
+        assert_eq!(session.fingerprint(), identity(1).fingerprint());
+        assert_eq!(
+            session.handshake().network_name,
+            chainspec.network_config.name
+        );
+
+        let nonce = Nonce::new(7);
+        session.send(Message::Ping { nonce }).await.unwrap();
+        let pong = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match session.recv().await.unwrap() {
+                    Some(Message::Pong { nonce: answered }) if answered == nonce => break,
+                    Some(_) => continue,
+                    None => panic!("the peer closed the connection"),
+                }
+            }
+        });
+        pong.await.unwrap();
+        session.close().await.unwrap();
+
+        let mut other = chainspec.clone();
+        other.network_config.name = "casper-other".to_string();
+        let error =
+            network::connect::<NodePayload>(addr, &identity(3), &other).await.err().unwrap();
+        assert_eq!(error.code(), "handshake.wrong_network");
+    }
+}
@@ -7,13 +7,19 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
+use std::str::FromStr;
 
 use datasize::DataSize;
+#[cfg(feature = "openssl")]
 use openssl::hash::MessageDigest;
+#[cfg(feature = "openssl")]
 use openssl::nid::Nid;
-use openssl::sha;
+use serde::de;
+use serde::de::Visitor;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
 
 mod big_array {
     use serde_big_array::big_array;
@@ -30,13 +36,14 @@ impl Sha512 {
     const SIZE: usize = 64;
 
     /// OpenSSL NID.
+    #[cfg(feature = "openssl")]
     pub const NID: Nid = Nid::SHA512;
 
     /// Create a new Sha512 by hashing a slice.
     pub fn new<B: AsRef<[u8]>>(data: B) -> Self {
-        let mut openssl_sha = sha::Sha512::new();
-        openssl_sha.update(data.as_ref());
-        Sha512(openssl_sha.finish())
+        use sha2::Digest;
+
+        Sha512(sha2::Sha512::digest(data.as_ref()).into())
     }
 
     /// Returns bytestring of the hash, with length `Self::SIZE`.
@@ -48,6 +55,7 @@ impl Sha512 {
     }
 
     /// Returns a new OpenSSL `MessageDigest` set to SHA-512.
+    #[cfg(feature = "openssl")]
     pub fn create_message_digest() -> MessageDigest {
         // This can only fail if we specify a `Nid` that does not exist, which cannot
         // happen unless there is something wrong with `Self::NID`.
@@ -81,6 +89,102 @@ impl Debug for Sha512 {
     }
 }
 
+impl Display for Sha512 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", base16::encode_lower(&self.0[..]))
+    }
+}
+
+/// Fingerprint of a public key, the SHA512 hash of its DER encoding, which
+/// peers are known by.
+///
+/// Equality takes the same time however many leading bytes match, so
+/// comparing against a fingerprint reveals nothing about it. Displayed and
+/// serialized as lowercase hex.
+#[derive(Copy, Clone, DataSize)]
+pub struct Fingerprint([u8; Fingerprint::SIZE]);
+
+impl Fingerprint {
+    /// Size of a fingerprint in bytes.
+    pub const SIZE: usize = Sha512::SIZE;
+
+    /// Fingerprints a DER encoded public key.
+    pub fn of<B: AsRef<[u8]>>(public_key_der: B) -> Self { Sha512::new(public_key_der).into() }
+
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self { Fingerprint(bytes) }
+
+    pub fn as_bytes(&self) -> &[u8; Self::SIZE] { &self.0 }
+}
+
+impl From<Sha512> for Fingerprint {
+    fn from(hash: Sha512) -> Self { Fingerprint(hash.0) }
+}
+
+impl PartialEq for Fingerprint {
+    fn eq(&self, other: &Self) -> bool {
+        let difference = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        // Keeps the compiler from turning the loop into an early return.
+        std::hint::black_box(difference) == 0
+    }
+}
+
+impl Eq for Fingerprint {}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut hex = [0; 2 * Fingerprint::SIZE];
+        base16::encode_config_slice(&self.0, base16::EncodeLower, &mut hex);
+        f.write_str(std::str::from_utf8(&hex).expect("hex is always ASCII"))
+    }
+}
+
+impl Debug for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result { Display::fmt(self, f) }
+}
+
+impl FromStr for Fingerprint {
+    type Err = base16::DecodeError;
+
+    fn from_str(hex: &str) -> core::result::Result<Self, Self::Err> {
+        if hex.len() != 2 * Fingerprint::SIZE {
+            return Err(base16::DecodeError::InvalidLength { length: hex.len() });
+        }
+        let mut bytes = [0; Fingerprint::SIZE];
+        base16::decode_slice(hex, &mut bytes)?;
+        Ok(Fingerprint(bytes))
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        struct HexVisitor;
+
+        impl Visitor<'_> for HexVisitor {
+            type Value = Fingerprint;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> Result {
+                write!(f, "{} hex digits", 2 * Fingerprint::SIZE)
+            }
+
+            fn visit_str<E: de::Error>(self, hex: &str) -> core::result::Result<Fingerprint, E> {
+                hex.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(HexVisitor)
+    }
+}
+
 /// Wrapper around `Option` that implements `Display`.
 ///
 /// For convenience, it also includes a `Serialize` implementation that works
@@ -127,8 +231,42 @@ impl<'a, T: Display> Display for OptDisplay<'a, T> {
 
 #[cfg(test)]
 mod tests {
+    use super::Fingerprint;
     use super::OptDisplay;
 
+    #[test]
+    fn fingerprint_roundtrips_through_hex_and_json() {
+        let fingerprint = Fingerprint::of(b"public key");
+        let hex = fingerprint.to_string();
+        assert_eq!(hex.len(), 2 * Fingerprint::SIZE);
+        assert_eq!(hex, base16::encode_lower(fingerprint.as_bytes()));
+        assert_eq!(hex.parse::<Fingerprint>().unwrap(), fingerprint);
+
+        let json = serde_json::to_string(&fingerprint).unwrap();
+        assert_eq!(json, format!("\"{hex}\""));
+        assert_eq!(
+            serde_json::from_str::<Fingerprint>(&json).unwrap(),
+            fingerprint
+        );
+
+        assert!(hex[2..].parse::<Fingerprint>().is_err());
+        assert!(hex.replacen(char::is_alphanumeric, "x", 1).parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn fingerprints_differing_in_any_byte_are_unequal() {
+        let fingerprint = Fingerprint::of(b"public key");
+        for i in 0..Fingerprint::SIZE {
+            let mut bytes = *fingerprint.as_bytes();
+            bytes[i] ^= 1;
+            assert_ne!(Fingerprint::from_bytes(bytes), fingerprint);
+        }
+        assert_eq!(
+            Fingerprint::from_bytes(*fingerprint.as_bytes()),
+            fingerprint
+        );
+    }
+
     #[test]
     fn opt_display_works() {
         let some_value: Option<u32> = Some(12345);